@@ -0,0 +1,155 @@
+//!
+//! An example for plugging in a custom backend.
+//!
+
+use ruc::*;
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+use vsdb::{vsdb_register_engine, Engine, EngineIter, Mapx};
+
+const PREFIX_SIZ: usize = 8;
+
+// Everything lives in one map, nothing is persisted.
+struct BTreeEngine {
+    meta: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+    data: RwLock<BTreeMap<Vec<u8>, Box<[u8]>>>,
+    prefix_allocator: AtomicU64,
+    branch_id_allocator: AtomicU64,
+    version_id_allocator: AtomicU64,
+}
+
+impl Engine for BTreeEngine {
+    fn new(_: &str) -> Result<Self> {
+        Ok(BTreeEngine {
+            meta: RwLock::new(BTreeMap::new()),
+            data: RwLock::new(BTreeMap::new()),
+            prefix_allocator: AtomicU64::new(4096_0000),
+            branch_id_allocator: AtomicU64::new(1),
+            version_id_allocator: AtomicU64::new(0),
+        })
+    }
+
+    fn alloc_prefix(&self) -> u64 {
+        self.prefix_allocator.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn alloc_branch_id(&self) -> u64 {
+        self.branch_id_allocator.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn alloc_version_id(&self) -> u64 {
+        self.version_id_allocator.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn area_count(&self) -> usize {
+        1
+    }
+
+    fn flush(&self) {}
+
+    fn iter(&self, area_idx: usize, meta_prefix: [u8; 8]) -> EngineIter {
+        self.range(area_idx, meta_prefix, (Bound::Unbounded, Bound::Unbounded))
+    }
+
+    // collected eagerly, just for simplicity
+    fn range<'a>(
+        &'a self,
+        _: usize,
+        meta_prefix: [u8; 8],
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        let with_prefix = |b: Bound<&[u8]>| match b {
+            Bound::Included(k) => Bound::Included([&meta_prefix[..], k].concat()),
+            Bound::Excluded(k) => Bound::Excluded([&meta_prefix[..], k].concat()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let bounds = (with_prefix(bounds.0), with_prefix(bounds.1));
+
+        let entries = self
+            .data
+            .read()
+            .unwrap()
+            .range(bounds)
+            .take_while(|(k, _)| k.starts_with(&meta_prefix))
+            .map(|(k, v)| (k[PREFIX_SIZ..].to_vec().into_boxed_slice(), v.clone()))
+            .collect::<Vec<_>>();
+
+        EngineIter::new(entries.into_iter())
+    }
+
+    fn get(&self, _: usize, meta_prefix: [u8; 8], key: &[u8]) -> Option<Box<[u8]>> {
+        let k = [&meta_prefix[..], key].concat();
+        self.data.read().unwrap().get(&k).cloned()
+    }
+
+    fn insert(
+        &self,
+        _: usize,
+        meta_prefix: [u8; 8],
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<Box<[u8]>> {
+        let k = [&meta_prefix[..], key].concat();
+        self.data.write().unwrap().insert(k, value.into())
+    }
+
+    fn remove(&self, _: usize, meta_prefix: [u8; 8], key: &[u8]) -> Option<Box<[u8]>> {
+        let k = [&meta_prefix[..], key].concat();
+        self.data.write().unwrap().remove(&k)
+    }
+
+    fn get_instance_len(&self, instance_prefix: [u8; 8]) -> u64 {
+        let meta = self.meta.read().unwrap();
+        let len = meta.get(&instance_prefix[..]).unwrap();
+        u64::from_be_bytes(len[..].try_into().unwrap())
+    }
+
+    fn instance_exists(&self, instance_prefix: [u8; 8]) -> bool {
+        self.meta.read().unwrap().contains_key(&instance_prefix[..])
+    }
+
+    fn set_instance_len(&self, instance_prefix: [u8; 8], new_len: u64) {
+        self.meta
+            .write()
+            .unwrap()
+            .insert(instance_prefix.to_vec(), new_len.to_be_bytes().to_vec());
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: [u8; 8]) -> bool {
+        self.meta
+            .read()
+            .unwrap()
+            .contains_key(&[&instance_prefix[..], &[0_u8][..]].concat())
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: [u8; 8]) {
+        self.meta
+            .write()
+            .unwrap()
+            .insert([&instance_prefix[..], &[0_u8][..]].concat(), vec![1]);
+    }
+}
+
+fn main() {
+    // must be done before any instance is created
+    pnk!(vsdb_register_engine::<BTreeEngine>());
+
+    let m: Mapx<u32, String> = Mapx::new();
+    (0..10).for_each(|i| {
+        m.insert(i, i.to_string());
+    });
+
+    assert_eq!(10, m.len());
+    assert_eq!(Some("3".to_owned()), m.get(&3));
+    assert_eq!(10, m.iter().count());
+
+    m.remove(&3);
+    assert_eq!(9, m.len());
+    assert!(m.get(&3).is_none());
+}
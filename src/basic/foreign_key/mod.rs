@@ -0,0 +1,121 @@
+//!
+//! Referential integrity between two `Mapx` instances.
+//!
+//! A [ForeignKey](self::ForeignKey) declares that the values of a `Mapx<A, B>`
+//! are keys of another `Mapx<B, C>`, writes through it will be checked,
+//! and removing a referenced key will be restricted or cascaded.
+//!
+//! NOTE:
+//! - Nothing is checked if the maps are modified directly
+//! - There is no reverse index, finding the referencing entries
+//!   of a key requires a full scan of the referencing map
+//!
+//! # Examples
+//!
+//! ```
+//! use vsdb::{basic::foreign_key::{ForeignKey, OnRemove}, Mapx};
+//!
+//! let owners: Mapx<u32, String> = Mapx::new();
+//! let pets: Mapx<String, u32> = Mapx::new();
+//! let fk = ForeignKey::new(&pets, &owners, OnRemove::Cascade);
+//!
+//! owners.insert(1, "alice".to_owned());
+//! assert!(fk.insert("cat".to_owned(), 1).is_ok());
+//! assert!(fk.insert("dog".to_owned(), 2).is_err());
+//!
+//! fk.remove_target(&1).unwrap();
+//! assert!(pets.is_empty());
+//! ```
+//!
+
+#[cfg(test)]
+mod test;
+
+use crate::{
+    basic::mapx::Mapx,
+    common::ende::{KeyEnDe, ValueEnDe},
+};
+use ruc::*;
+
+/// What to do with the referencing entries when a referenced key is removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnRemove {
+    /// Refuse to remove keys that are still referenced.
+    Restrict,
+    /// Remove all entries that reference the key together.
+    Cascade,
+}
+
+/// A reference from the values of `from` to the keys of `to`.
+pub struct ForeignKey<'a, A, B, C> {
+    from: &'a Mapx<A, B>,
+    to: &'a Mapx<B, C>,
+    on_remove: OnRemove,
+}
+
+impl<'a, A, B, C> ForeignKey<'a, A, B, C>
+where
+    A: KeyEnDe,
+    B: KeyEnDe + ValueEnDe,
+    C: ValueEnDe,
+{
+    #[inline(always)]
+    pub fn new(from: &'a Mapx<A, B>, to: &'a Mapx<B, C>, on_remove: OnRemove) -> Self {
+        Self {
+            from,
+            to,
+            on_remove,
+        }
+    }
+
+    /// Insert an entry into the referencing map,
+    /// the value must be an existing key of the referenced map.
+    #[inline(always)]
+    pub fn insert(&self, key: A, value: B) -> Result<Option<B>> {
+        if !self.to.contains_key(&value) {
+            return Err(eg!("the referenced key does not exist"));
+        }
+        Ok(self.from.insert(key, value))
+    }
+
+    /// Remove a key from the referenced map,
+    /// the referencing entries will be handled by the `OnRemove` policy.
+    pub fn remove_target(&self, key: &B) -> Result<Option<C>> {
+        let referencing = self.referencing(key);
+        if !referencing.is_empty() {
+            match self.on_remove {
+                OnRemove::Restrict => {
+                    return Err(eg!(format!(
+                        "the key is still referenced by {} entries",
+                        referencing.len()
+                    )));
+                }
+                OnRemove::Cascade => {
+                    referencing.iter().for_each(|k| {
+                        self.from.remove(k);
+                    });
+                }
+            }
+        }
+        Ok(self.to.remove(key))
+    }
+
+    /// All keys of the referencing map whose values are `key`.
+    pub fn referencing(&self, key: &B) -> Vec<A> {
+        let target = key.encode_key();
+        self.from
+            .iter()
+            .filter(|(_, v)| v.encode_key() == target)
+            .map(|(k, _)| k)
+            .collect()
+    }
+
+    /// Find all dangling references,
+    /// that is, entries whose values are not keys of the referenced map.
+    pub fn check_references(&self) -> Vec<(A, B)> {
+        self.from
+            .iter()
+            .filter(|(_, v)| !self.to.contains_key(v))
+            .collect()
+    }
+}
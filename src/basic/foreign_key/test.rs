@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn foreign_key() {
+    let owners: Mapx<u32, String> = Mapx::new();
+    let pets: Mapx<String, u32> = Mapx::new();
+    (0..3).for_each(|i| {
+        owners.insert(i, format!("owner-{}", i));
+    });
+
+    let fk = ForeignKey::new(&pets, &owners, OnRemove::Restrict);
+    assert!(fk.insert("cat".to_owned(), 0).is_ok());
+    assert!(fk.insert("dog".to_owned(), 0).is_ok());
+    assert!(fk.insert("pig".to_owned(), 1).is_ok());
+    assert!(fk.insert("fox".to_owned(), 9).is_err());
+    assert!(!pets.contains_key(&"fox".to_owned()));
+
+    let mut referencing = fk.referencing(&0);
+    referencing.sort();
+    assert_eq!(referencing, vec!["cat".to_owned(), "dog".to_owned()]);
+    assert!(fk.check_references().is_empty());
+
+    // referenced keys can not be removed
+    assert!(fk.remove_target(&0).is_err());
+    assert!(owners.contains_key(&0));
+    assert_eq!(fk.remove_target(&2).unwrap(), Some("owner-2".to_owned()));
+
+    // modifications out of the helper are found by the check
+    owners.remove(&1);
+    assert_eq!(fk.check_references(), vec![("pig".to_owned(), 1)]);
+
+    let fk = ForeignKey::new(&pets, &owners, OnRemove::Cascade);
+    assert_eq!(fk.remove_target(&0).unwrap(), Some("owner-0".to_owned()));
+    assert!(!pets.contains_key(&"cat".to_owned()));
+    assert!(!pets.contains_key(&"dog".to_owned()));
+    assert_eq!(1, pets.len());
+}
@@ -34,29 +34,46 @@
 mod test;
 
 use crate::{
-    basic::mapx_ord_rawkey::{Entry, MapxOrdRawKey, MapxOrdRawKeyIter, ValueMut},
-    common::ende::{KeyEnDe, ValueEnDe},
+    basic::{
+        mapx_ord_rawkey::{Entry, MapxOrdRawKey, MapxOrdRawKeyIter, ValueMut},
+        mapx_raw::MapxRaw,
+    },
+    common::{
+        append_only::AppendOnlyViolation,
+        compression::Compression,
+        ende::{
+            decode_key_by_policy_with, encode_key_by, Codec, DefaultCodec, KeyEnDe,
+            ValueEnDe,
+        },
+        named,
+        stats::SizeHistogram,
+        RawValue,
+    },
 };
+use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::Duration};
 
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
 #[serde(bound = "")]
-pub struct Mapx<K, V> {
-    inner: MapxOrdRawKey<V>,
+pub struct Mapx<K, V, C = DefaultCodec> {
+    inner: MapxOrdRawKey<V, C>,
     pk: PhantomData<K>,
 }
 
-impl<K, V> Default for Mapx<K, V>
+impl<K, V, C> Default for Mapx<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn default() -> Self {
-        Self::new()
+        Self::with_codec()
     }
 }
 
+// Constructors of the default codec,
+// use `with_codec` to create an instance of another codec.
 impl<K, V> Mapx<K, V>
 where
     K: KeyEnDe,
@@ -70,20 +87,100 @@ where
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    /// Create an append-only instance,
+    /// existing keys can not be overwritten or removed.
+    #[inline(always)]
+    pub fn new_append_only() -> Self {
+        Mapx {
+            inner: MapxOrdRawKey::new_append_only(),
+            pk: PhantomData,
+        }
+    }
+
+    /// Create an instance in the area `area_idx`, usually a dedicated one,
+    /// see [vsdb_set_area_opts](crate::vsdb_set_area_opts).
+    #[inline(always)]
+    pub fn new_pinned(area_idx: usize) -> Result<Self> {
+        MapxOrdRawKey::new_pinned(area_idx)
+            .c(d!())
+            .map(|inner| Mapx {
+                inner,
+                pk: PhantomData,
+            })
+    }
+
+    /// Create an instance whose values are compressed by `c`.
+    #[cfg(feature = "compression")]
+    #[inline(always)]
+    pub fn new_compressed(c: Compression) -> Self {
+        Mapx {
+            inner: MapxOrdRawKey::new_compressed(c),
+            pk: PhantomData,
+        }
+    }
+
+    /// Create an instance whose entries can expire.
+    #[inline(always)]
+    pub fn new_with_ttl() -> Self {
+        Mapx {
+            inner: MapxOrdRawKey::new_with_ttl(),
+            pk: PhantomData,
+        }
+    }
+}
+
+impl<K, V, C> Mapx<K, V, C>
+where
+    K: KeyEnDe,
+    V: ValueEnDe,
+    C: Codec,
+{
+    /// Create an instance whose keys and values are encoded by the codec `C`,
+    /// e.g. `Mapx::<K, V, BcsCodec>::with_codec()`.
+    #[inline(always)]
+    pub fn with_codec() -> Self {
+        Mapx {
+            inner: MapxOrdRawKey::with_codec(),
+            pk: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_append_only(&self) -> bool {
+        self.inner.is_append_only()
+    }
+
+    #[inline(always)]
+    pub fn compression(&self) -> Option<Compression> {
+        self.inner.compression()
+    }
+
+    #[inline(always)]
+    pub fn has_ttl(&self) -> bool {
+        self.inner.has_ttl()
+    }
+
     #[inline(always)]
     pub fn get(&self, key: &K) -> Option<V> {
-        self.inner.get(&key.encode())
+        self.inner.get(&encode_key_by::<C, K>(key))
     }
 
     #[inline(always)]
-    pub fn get_mut(&self, key: &K) -> Option<ValueMut<'_, V>> {
-        let k = key.encode();
+    pub fn get_mut(&self, key: &K) -> Option<ValueMut<'_, V, C>> {
+        let k = encode_key_by::<C, K>(key);
         self.inner.get(&k).map(|v| ValueMut::new(&self.inner, k, v))
     }
 
     #[inline(always)]
     pub fn contains_key(&self, key: &K) -> bool {
-        self.inner.contains_key(&key.encode())
+        self.inner.contains_key(&encode_key_by::<C, K>(key))
     }
 
     #[inline(always)]
@@ -101,9 +198,36 @@ where
         self.insert_ref(&key, &value)
     }
 
+    /// Panic if the instance is not created by `new_with_ttl`.
+    #[inline(always)]
+    pub fn insert_with_ttl(&self, key: &K, value: &V, ttl: Duration) -> Option<V> {
+        self.inner
+            .insert_with_ttl(&encode_key_by::<C, K>(key), value, ttl)
+    }
+
     #[inline(always)]
     pub fn insert_ref(&self, key: &K, value: &V) -> Option<V> {
-        self.inner.insert_ref(&key.encode(), value)
+        self.inner.insert_ref(&encode_key_by::<C, K>(key), value)
+    }
+
+    /// Get the encoded bytes of a value, e.g. for replicating or hashing,
+    /// the codec is bypassed.
+    #[inline(always)]
+    pub fn get_raw(&self, key: &K) -> Option<RawValue> {
+        self.inner.get_raw(&encode_key_by::<C, K>(key))
+    }
+
+    /// Insert the encoded bytes of a value, e.g. got by `get_raw`,
+    /// the codec is bypassed, it's your duty to ensure that they
+    /// can be decoded as a `V` by the codec `C`.
+    #[inline(always)]
+    pub fn insert_raw(&self, key: &K, value: &[u8]) -> Option<RawValue> {
+        self.inner.insert_raw(&encode_key_by::<C, K>(key), value)
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        self.inner.as_raw()
     }
 
     #[inline(always)]
@@ -113,16 +237,16 @@ where
 
     #[inline(always)]
     pub fn set_value_ref(&self, key: &K, value: &V) {
-        self.inner.set_value_ref(&key.encode(), value);
+        self.inner.set_value_ref(&encode_key_by::<C, K>(key), value);
     }
 
     #[inline(always)]
-    pub fn entry(&self, key: K) -> Entry<'_, V> {
-        self.inner.entry(key.encode())
+    pub fn entry(&self, key: K) -> Entry<'_, V, C> {
+        self.inner.entry(encode_key_by::<C, K>(&key))
     }
 
     #[inline(always)]
-    pub fn iter(&self) -> MapxIter<K, V> {
+    pub fn iter(&self) -> MapxIter<K, V, C> {
         MapxIter {
             iter: self.inner.iter(),
             pk: PhantomData,
@@ -130,72 +254,102 @@ where
     }
 
     #[inline(always)]
-    pub fn values(&self) -> MapxValues<K, V> {
+    pub fn values(&self) -> MapxValues<K, V, C> {
         MapxValues { iter: self.iter() }
     }
 
     #[inline(always)]
     pub fn remove(&self, key: &K) -> Option<V> {
-        self.inner.remove(&key.encode())
+        self.inner.remove(&encode_key_by::<C, K>(key))
+    }
+
+    #[inline(always)]
+    pub fn try_insert(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, AppendOnlyViolation> {
+        self.inner
+            .try_insert_ref(&encode_key_by::<C, K>(key), &value)
+    }
+
+    #[inline(always)]
+    pub fn try_remove(&self, key: &K) -> Result<Option<V>, AppendOnlyViolation> {
+        self.inner.try_remove(&encode_key_by::<C, K>(key))
     }
 
     #[inline(always)]
     pub fn unset_value(&self, key: &K) {
-        self.inner.unset_value(&key.encode());
+        self.inner.unset_value(&encode_key_by::<C, K>(key));
     }
 
     #[inline(always)]
     pub fn clear(&self) {
         self.inner.clear();
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
 }
 
-pub struct MapxIter<K, V>
+pub struct MapxIter<K, V, C = DefaultCodec>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
-    iter: MapxOrdRawKeyIter<V>,
+    iter: MapxOrdRawKeyIter<V, C>,
     pk: PhantomData<K>,
 }
 
-impl<K, V> Iterator for MapxIter<K, V>
+impl<K, V, C> Iterator for MapxIter<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     type Item = (K, V);
     fn next(&mut self) -> Option<Self::Item> {
         self.iter
-            .next()
-            .map(|(k, v)| (<K as KeyEnDe>::decode(&k).unwrap(), v))
+            .find_map(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 }
 
-impl<K, V> DoubleEndedIterator for MapxIter<K, V>
+impl<K, V, C> DoubleEndedIterator for MapxIter<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter
-            .next_back()
-            .map(|(k, v)| (<K as KeyEnDe>::decode(&k).unwrap(), v))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 }
 
-pub struct MapxValues<K, V>
+pub struct MapxValues<K, V, C = DefaultCodec>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
-    iter: MapxIter<K, V>,
+    iter: MapxIter<K, V, C>,
 }
 
-impl<K, V> Iterator for MapxValues<K, V>
+impl<K, V, C> Iterator for MapxValues<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     type Item = V;
     fn next(&mut self) -> Option<Self::Item> {
@@ -203,19 +357,21 @@ where
     }
 }
 
-impl<K, V> DoubleEndedIterator for MapxValues<K, V>
+impl<K, V, C> DoubleEndedIterator for MapxValues<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter.next_back().map(|(_, v)| v)
     }
 }
 
-impl<K, V> ExactSizeIterator for MapxValues<K, V>
+impl<K, V, C> ExactSizeIterator for MapxValues<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
 }
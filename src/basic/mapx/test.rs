@@ -1,7 +1,13 @@
 use super::*;
-use crate::ValueEnDe;
+use crate::{
+    vsdb_set_codec_hook, CodecEvent, CodecHook, CodecOp, CodecTarget, ValueEnDe,
+};
 use ruc::*;
 use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 #[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone)]
 struct SampleBlock {
@@ -64,3 +70,260 @@ fn basic_cases() {
     reloaded.clear();
     assert!(reloaded.is_empty());
 }
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+struct HookedValue(u64);
+
+#[derive(Default)]
+struct Counter {
+    encoded: AtomicUsize,
+    decoded: AtomicUsize,
+    decoded_bytes: AtomicUsize,
+}
+
+impl CodecHook for Counter {
+    fn after(&self, event: &CodecEvent) {
+        // other tests may run concurrently
+        if !event.type_name.ends_with("HookedValue") {
+            return;
+        }
+        assert_eq!(CodecTarget::Value, event.target);
+        assert!(event.ok);
+        match event.op {
+            CodecOp::Encode => self.encoded.fetch_add(1, Ordering::Relaxed),
+            CodecOp::Decode => {
+                self.decoded_bytes.fetch_add(event.bytes, Ordering::Relaxed);
+                self.decoded.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+    }
+}
+
+#[test]
+fn codec_hook() {
+    let counter = Arc::new(Counter::default());
+    vsdb_set_codec_hook(Some(counter.clone()));
+
+    let hdr = Mapx::new();
+    hdr.insert(1u64, HookedValue(1));
+    hdr.insert(2u64, HookedValue(2));
+    assert_eq!(Some(HookedValue(1)), hdr.get(&1));
+
+    vsdb_set_codec_hook(None);
+    assert_eq!(Some(HookedValue(2)), hdr.get(&2));
+
+    assert_eq!(2, counter.encoded.load(Ordering::Relaxed));
+    assert_eq!(1, counter.decoded.load(Ordering::Relaxed));
+    assert_eq!(
+        HookedValue(1).encode().len(),
+        counter.decoded_bytes.load(Ordering::Relaxed)
+    );
+}
+
+#[cfg(feature = "prost_codec")]
+#[test]
+fn prost_messages() {
+    use crate::{MapxVs, Proto, VersionName, VsMgmt};
+    use prost::Message;
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Account {
+        #[prost(uint64, tag = "1")]
+        id: u64,
+        #[prost(string, tag = "2")]
+        name: String,
+        #[prost(bytes = "vec", tag = "3")]
+        data: Vec<u8>,
+    }
+
+    let account = |id: u64| Account {
+        id,
+        name: format!("account-{}", id),
+        data: vec![id as u8; 8],
+    };
+
+    let hdr = Mapx::new();
+    (0..10).for_each(|i| {
+        assert!(hdr.insert(i, Proto(account(i))).is_none());
+    });
+    assert_eq!(Some(account(3)), hdr.get(&3).map(Proto::into_inner));
+    assert_eq!("account-5", hdr.get(&5).unwrap().name);
+    assert_eq!(10, hdr.iter().filter(|(k, v)| *k == v.id).count());
+
+    // the protobuf bytes are kept as they are
+    let v = Proto(account(1));
+    let decoded = pnk!(<Proto<Account> as ValueEnDe>::decode(&v.encode()));
+    assert_eq!(account(1).encode_to_vec(), decoded.encode_to_vec());
+
+    // versioned collections are supported too
+    let hdr = MapxVs::new();
+    pnk!(hdr.version_create(VersionName(b"v1")));
+    pnk!(hdr.insert(1u64, Proto(account(1))));
+    assert_eq!(Some(account(1)), hdr.get(&1).map(Proto::into_inner));
+}
+
+#[cfg(feature = "rkyv_codec")]
+#[test]
+fn rkyv_values() {
+    use crate::Rkyv;
+    use rkyv::{Archive, Deserialize as RkyvDe, Serialize as RkyvSer};
+
+    #[derive(Archive, RkyvSer, RkyvDe, Debug, Eq, PartialEq, Clone)]
+    #[archive(check_bytes)]
+    struct Block {
+        height: u64,
+        txs: Vec<Vec<u8>>,
+    }
+
+    let block = |h: u64| Block {
+        height: h,
+        txs: (0..h).map(|i| vec![i as u8; 64]).collect(),
+    };
+
+    let hdr = Mapx::new();
+    (0..10).for_each(|h| {
+        assert!(hdr.insert(h, Rkyv::new(&block(h))).is_none());
+    });
+
+    // fields are read from the archived view directly
+    let v = hdr.get(&5).unwrap();
+    assert_eq!(5, v.height);
+    assert_eq!(5, v.txs.len());
+    assert_eq!(&[4; 64][..], &v.txs[4][..]);
+    assert_eq!(block(5), v.unarchive());
+
+    assert_eq!(45, hdr.iter().map(|(_, v)| v.txs.len()).sum::<usize>());
+
+    // invalid bytes are rejected
+    let bad = <Vec<u8> as ValueEnDe>::encode(&vec![0xff; 3]);
+    assert!(<Rkyv<Block> as ValueEnDe>::decode(&bad).is_err());
+}
+
+#[cfg(all(feature = "cbor_codec", feature = "bcs_codec"))]
+#[test]
+fn mixed_codecs() {
+    use crate::{BcsCodec, CborCodec};
+
+    let consensus = Mapx::<u64, String, BcsCodec>::with_codec();
+    let aux: Mapx<u64, String> = Mapx::new();
+
+    (0..10u64).for_each(|i| {
+        assert!(consensus.insert(i, i.to_string()).is_none());
+        assert!(aux.insert(i, i.to_string()).is_none());
+    });
+
+    assert_eq!(Some("5".to_owned()), consensus.get(&5));
+    assert_eq!(Some("5".to_owned()), aux.get(&5));
+    assert_eq!(
+        consensus.iter().collect::<Vec<_>>(),
+        aux.iter().collect::<Vec<_>>()
+    );
+
+    // keys are encoded by the codec of each instance
+    let k = pnk!(bcs::to_bytes(&5u64));
+    assert!(consensus.inner.contains_key(&k));
+    assert_eq!(Some("5".to_owned()), consensus.inner.get(&k));
+    assert!(aux.inner.contains_key(&CborCodec::to_bytes(&5u64)));
+
+    *consensus.get_mut(&5).unwrap() = "five".to_owned();
+    assert_eq!(Some("five".to_owned()), consensus.get(&5));
+    assert_eq!(Some("5".to_owned()), aux.get(&5));
+}
+
+#[test]
+fn schema_upgrades() {
+    use crate::{vsdb_register_schema_upgrade, Enveloped, Schema};
+
+    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    struct AccountV1 {
+        balance: u64,
+    }
+
+    impl Schema for AccountV1 {
+        const SCHEMA_VERSION: u8 = 1;
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    struct Account {
+        balance: u64,
+        nonce: u64,
+    }
+
+    impl Schema for Account {
+        const SCHEMA_VERSION: u8 = 2;
+    }
+
+    let old: Mapx<u64, Enveloped<AccountV1>> = Mapx::new();
+    (0..10).for_each(|i| {
+        old.insert(i, Enveloped(AccountV1 { balance: i }));
+    });
+
+    // no upgrade yet
+    let v1 = Enveloped(AccountV1 { balance: 1 }).encode();
+    assert!(<Enveloped<Account> as ValueEnDe>::decode(&v1).is_err());
+
+    assert!(vsdb_register_schema_upgrade::<Account>(2, |_| unreachable!()).is_err());
+    pnk!(vsdb_register_schema_upgrade(1, |bytes| {
+        let old = <AccountV1 as ValueEnDe>::decode(bytes).c(d!())?;
+        Ok(Account {
+            balance: old.balance,
+            nonce: 0,
+        })
+    }));
+
+    // the same instance, read by the new release
+    let new = pnk!(<Mapx<u64, Enveloped<Account>> as ValueEnDe>::decode(
+        &old.encode()
+    ));
+    assert_eq!(10, new.len());
+    assert_eq!(
+        Some(Account {
+            balance: 5,
+            nonce: 0
+        }),
+        new.get(&5).map(Enveloped::into_inner)
+    );
+
+    // rewritten values are stored with the current version
+    new.get_mut(&5).unwrap().nonce = 1;
+    assert_eq!(
+        Some(Account {
+            balance: 5,
+            nonce: 1
+        }),
+        new.get(&5).map(Enveloped::into_inner)
+    );
+    assert!(
+        <Enveloped<AccountV1> as ValueEnDe>::decode(&new.get(&5).unwrap().encode())
+            .is_err()
+    );
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_codec() {
+    use crate::{Compressed, DefaultCodec};
+
+    let hdr = Mapx::<u64, Vec<u8>, Compressed>::with_codec();
+    let plain: Mapx<u64, Vec<u8>> = Mapx::new();
+
+    let blob = vec![7u8; 4096];
+    let small = vec![7u8; 16];
+    hdr.insert(1, blob.clone());
+    hdr.insert(2, small.clone());
+    plain.insert(1, blob.clone());
+    plain.insert(2, small.clone());
+
+    assert_eq!(Some(blob), hdr.get(&1));
+    assert_eq!(Some(small), hdr.get(&2));
+
+    // large values are compressed, small ones are kept raw
+    let raw = |m: &Mapx<u64, Vec<u8>, Compressed>, k| m.get_raw(&k).unwrap().len();
+    assert!(raw(&hdr, 1) < plain.get_raw(&1).unwrap().len() / 10);
+    assert_eq!(raw(&hdr, 2), 1 + plain.get_raw(&2).unwrap().len());
+
+    // values of a plain codec are not accepted
+    assert!(
+        <Compressed<DefaultCodec, 0> as Codec>::from_bytes::<Vec<u8>>(&[0xff]).is_err()
+    );
+}
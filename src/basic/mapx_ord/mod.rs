@@ -7,6 +7,10 @@
 //!     - Keys will be encoded by `KeyEnDeOrdered`
 //!     - Values will be encoded by some `serde`-like methods
 //! - It's your duty to ensure that the encoded key keeps a same order with the original key
+//!     - Unsigned integers, `OrdI8`..`OrdI128` and `OrdF32`/`OrdF64` keep their orders
+//!     - Plain signed integers are stored in big-endian, negative ones are
+//!       ordered after positive ones, use `OrdI64` and friends to keep the order
+//!     - Tuples of up to 5 ordered keys are ordered by their components in turn
 //!
 //! # Examples
 //!
@@ -36,14 +40,25 @@
 mod test;
 
 use crate::{
-    basic::mapx_ord_rawkey::{Entry, MapxOrdRawKey, MapxOrdRawKeyIter, ValueMut},
-    common::ende::{KeyEnDeOrdered, ValueEnDe},
+    basic::{
+        mapx_ord_rawkey::{Entry, MapxOrdRawKey, MapxOrdRawKeyIter, ValueMut},
+        mapx_raw::MapxRaw,
+    },
+    common::{
+        append_only::AppendOnlyViolation,
+        compression::Compression,
+        cursor::Cursor,
+        ende::{key_from_bytes_by_policy, KeyEnDeOrdered, ValueEnDe},
+        named,
+        stats::SizeHistogram,
+    },
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
 use std::{
     marker::PhantomData,
     ops::{Bound, RangeBounds},
+    time::Duration,
 };
 
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -76,6 +91,66 @@ where
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn new_append_only() -> Self {
+        MapxOrd {
+            inner: MapxOrdRawKey::new_append_only(),
+            pk: PhantomData,
+        }
+    }
+
+    /// Create an instance in the area `area_idx`, usually a dedicated one,
+    /// see [vsdb_set_area_opts](crate::vsdb_set_area_opts).
+    #[inline(always)]
+    pub fn new_pinned(area_idx: usize) -> Result<Self> {
+        MapxOrdRawKey::new_pinned(area_idx)
+            .c(d!())
+            .map(|inner| MapxOrd {
+                inner,
+                pk: PhantomData,
+            })
+    }
+
+    #[inline(always)]
+    pub fn is_append_only(&self) -> bool {
+        self.inner.is_append_only()
+    }
+
+    #[cfg(feature = "compression")]
+    #[inline(always)]
+    pub fn new_compressed(c: Compression) -> Self {
+        MapxOrd {
+            inner: MapxOrdRawKey::new_compressed(c),
+            pk: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn compression(&self) -> Option<Compression> {
+        self.inner.compression()
+    }
+
+    /// Create an instance whose entries can expire.
+    #[inline(always)]
+    pub fn new_with_ttl() -> Self {
+        MapxOrd {
+            inner: MapxOrdRawKey::new_with_ttl(),
+            pk: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn has_ttl(&self) -> bool {
+        self.inner.has_ttl()
+    }
+
     #[inline(always)]
     pub fn get(&self, key: &K) -> Option<V> {
         self.inner.get(&key.to_bytes())
@@ -96,14 +171,14 @@ where
     pub fn get_le(&self, key: &K) -> Option<(K, V)> {
         self.inner
             .get_le(&key.to_bytes())
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge(&self, key: &K) -> Option<(K, V)> {
         self.inner
             .get_ge(&key.to_bytes())
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -121,6 +196,12 @@ where
         self.insert_ref(&key, &value)
     }
 
+    /// Panic if the instance is not created by `new_with_ttl`.
+    #[inline(always)]
+    pub fn insert_with_ttl(&self, key: &K, value: &V, ttl: Duration) -> Option<V> {
+        self.inner.insert_with_ttl(&key.to_bytes(), value, ttl)
+    }
+
     #[inline(always)]
     pub fn insert_ref(&self, key: &K, value: &V) -> Option<V> {
         self.inner.insert_ref(&key.to_bytes(), value)
@@ -163,6 +244,20 @@ where
         MapxOrdValues { iter: self.iter() }
     }
 
+    #[inline(always)]
+    pub fn iter_page(
+        &self,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<(K, V)>, Option<Cursor>) {
+        let (kvs, next) = self.inner.iter_page(cursor, limit);
+        let kvs = kvs
+            .into_iter()
+            .filter_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
+            .collect();
+        (kvs, next)
+    }
+
     #[inline(always)]
     pub fn range<R: RangeBounds<K>>(&self, bounds: R) -> MapxOrdIter<K, V> {
         self.range_ref((bounds.start_bound(), bounds.end_bound()))
@@ -220,6 +315,20 @@ where
         self.inner.remove(&key.to_bytes())
     }
 
+    #[inline(always)]
+    pub fn try_insert(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, AppendOnlyViolation> {
+        self.inner.try_insert_ref(&key.to_bytes(), &value)
+    }
+
+    #[inline(always)]
+    pub fn try_remove(&self, key: &K) -> Result<Option<V>, AppendOnlyViolation> {
+        self.inner.try_remove(&key.to_bytes())
+    }
+
     #[inline(always)]
     pub fn unset_value(&self, key: &K) {
         self.inner.remove(&key.to_bytes());
@@ -229,6 +338,21 @@ where
     pub fn clear(&self) {
         self.inner.clear();
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        self.inner.as_raw()
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
 }
 
 pub struct MapxOrdIter<K, V>
@@ -247,7 +371,8 @@ where
 {
     type Item = (K, V);
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+        self.iter
+            .find_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 }
 
@@ -258,8 +383,9 @@ where
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter
-            .next_back()
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 }
 
@@ -75,15 +75,162 @@ fn basic_cases() {
     assert_eq!(100, reloaded.range(12..=999).next().unwrap().1.idx);
 
     assert_eq!(100, reloaded.range(100..=999).next().unwrap().1.idx);
-    assert!(
-        reloaded
-            .range((Bound::Excluded(100), Bound::Included(999)))
-            .next()
-            .is_none()
-    );
+    assert!(reloaded
+        .range((Bound::Excluded(100), Bound::Included(999)))
+        .next()
+        .is_none());
 
     assert_eq!(100, reloaded.get_ge(&99).unwrap().1.idx);
     assert_eq!(100, reloaded.get_ge(&100).unwrap().1.idx);
     assert_eq!(100, reloaded.get_le(&100).unwrap().1.idx);
     assert_eq!(100, reloaded.get_le(&101).unwrap().1.idx);
 }
+
+#[test]
+fn iter_page() {
+    let hdr: MapxOrd<u32, u32> = MapxOrd::new();
+    (0..10).for_each(|i| {
+        hdr.insert(i, i * 10);
+    });
+
+    let mut keys = vec![];
+    let mut cursor = None;
+    loop {
+        let (page, next) = hdr.iter_page(cursor.as_ref(), 3);
+        assert!(!page.is_empty() && page.len() <= 3);
+        keys.extend(page.into_iter().map(|(k, _)| k));
+
+        // cursors can be passed through strings or any codec
+        cursor = match next {
+            Some(c) => {
+                let c = c.to_string().parse::<Cursor>().unwrap();
+                Some(<Cursor as ValueEnDe>::decode(&c.encode()).unwrap())
+            }
+            None => break,
+        };
+    }
+    assert_eq!((0..10).collect::<Vec<_>>(), keys);
+
+    // the last page is never empty
+    let (page, next) = hdr.iter_page(None, 10);
+    assert_eq!(10, page.len());
+    assert!(next.is_none());
+
+    // new keys after the cursor are visible to the following pages
+    let (_, next) = hdr.iter_page(None, 5);
+    hdr.insert(100, 0);
+    let (page, _) = hdr.iter_page(next.as_ref(), 10);
+    assert_eq!(Some(&(100, 0)), page.last());
+
+    assert!("xyz".parse::<Cursor>().is_err());
+}
+
+#[test]
+fn signed_and_float_keys() {
+    use crate::{KeyEnDeOrdered, OrdF64, OrdI64};
+
+    // the format of plain signed integers is kept
+    assert_eq!(&(-1i64).to_be_bytes()[..], &(-1i64).to_bytes()[..]);
+    assert_eq!(-1, pnk!(i64::from_slice(&(-1i64).to_be_bytes())));
+
+    let i = OrdI64;
+    let hdr: MapxOrd<OrdI64, i64> = MapxOrd::new();
+    let keys = [i64::MIN, -1000, -1, 0, 1, 1000, i64::MAX];
+    keys.iter().rev().for_each(|k| {
+        hdr.insert(i(*k), *k);
+    });
+
+    assert_eq!(
+        keys.to_vec(),
+        hdr.iter().map(|(k, _)| k.0).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![-1000, -1, 0],
+        hdr.range(i(-1000)..i(1))
+            .map(|(_, v)| v)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some((i(-1000), -1000)), hdr.get_le(&i(-2)));
+    assert_eq!(Some((i(-1), -1)), hdr.get_ge(&i(-999)));
+    assert_eq!(Some((i(i64::MIN), i64::MIN)), hdr.first());
+    assert_eq!(Some((i(i64::MAX), i64::MAX)), hdr.last());
+
+    let f = |v: f64| pnk!(OrdF64::new(v));
+    let hdr: MapxOrd<OrdF64, u32> = MapxOrd::new();
+    let keys = [
+        f64::MIN,
+        -1.5,
+        -f64::MIN_POSITIVE,
+        0.0,
+        1e-300,
+        2.5,
+        f64::MAX,
+    ];
+    keys.iter().enumerate().rev().for_each(|(i, v)| {
+        hdr.insert(f(*v), i as u32);
+    });
+
+    assert_eq!(
+        keys.to_vec(),
+        hdr.iter().map(|(k, _)| k.get()).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![1, 2, 3],
+        hdr.range(f(-2.0)..f(1e-301))
+            .map(|(_, v)| v)
+            .collect::<Vec<_>>()
+    );
+
+    // `-0.0` is the same key as `0.0`
+    assert_eq!(Some(3), hdr.get(&f(-0.0)));
+
+    assert!(OrdF64::new(f64::NAN).is_err());
+    assert!(OrdF64::new(f64::INFINITY).is_err());
+    assert!(OrdF64::from_slice(&(f64::NAN.to_bits() | 1 << 63).to_be_bytes()).is_err());
+}
+
+#[test]
+fn tuple_keys() {
+    use crate::{KeyEnDeOrdered, OrdF64};
+
+    let hdr: MapxOrd<(u64, String), u32> = MapxOrd::new();
+    let mut keys = vec![];
+    (0..5u64).for_each(|i| {
+        ["", "\0", "a", "a\0", "a\0b", "ab", "b"]
+            .iter()
+            .for_each(|s| keys.push((i, s.to_string())));
+    });
+    keys.iter().enumerate().rev().for_each(|(i, k)| {
+        hdr.insert(k.clone(), i as u32);
+    });
+
+    assert_eq!(keys, hdr.iter().map(|(k, _)| k).collect::<Vec<_>>());
+
+    // range-scans by the first component
+    let l = (2, String::new());
+    let h = (3, String::new());
+    assert_eq!(
+        keys.iter()
+            .filter(|(i, _)| 2 == *i)
+            .cloned()
+            .collect::<Vec<_>>(),
+        hdr.range(l..h).map(|(k, _)| k).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(((1, "b".to_owned()), 13)),
+        hdr.get_le(&(1, "c".to_owned()))
+    );
+
+    fn roundtrip<K: KeyEnDeOrdered>(k: K) {
+        assert_eq!(k, pnk!(K::from_bytes(k.to_bytes())));
+    }
+    roundtrip((
+        -1i32,
+        vec![0u8, 1],
+        "x".to_owned(),
+        0u8,
+        pnk!(OrdF64::new(0.5)),
+    ));
+    roundtrip((String::new(), String::new()));
+    assert!(<(String, u8)>::from_slice(b"a\0").is_err());
+}
@@ -34,31 +34,47 @@ mod test;
 
 use crate::{
     basic::mapx_raw::{MapxRaw, MapxRawIter},
-    common::{ende::ValueEnDe, RawKey},
+    common::{
+        append_only::AppendOnlyViolation,
+        compression::Compression,
+        cursor::{self, Cursor},
+        database,
+        ende::{
+            decode_value_by_policy_with, encode_value_by, Codec, DefaultCodec, ValueEnDe,
+        },
+        named,
+        stats::SizeHistogram,
+        RawKey, RawValue,
+    },
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
 use std::{
     marker::PhantomData,
     ops::{Bound, Deref, DerefMut, RangeBounds},
+    result::Result as StdResult,
+    time::Duration,
 };
 
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
 #[serde(bound = "")]
-pub struct MapxOrdRawKey<V> {
+pub struct MapxOrdRawKey<V, C = DefaultCodec> {
     inner: MapxRaw,
-    p: PhantomData<V>,
+    p: PhantomData<(V, C)>,
 }
 
-impl<V> Default for MapxOrdRawKey<V>
+impl<V, C> Default for MapxOrdRawKey<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn default() -> Self {
-        Self::new()
+        Self::with_codec()
     }
 }
 
+// Constructors of the default codec,
+// use `with_codec` to create an instance of another codec.
 impl<V> MapxOrdRawKey<V>
 where
     V: ValueEnDe,
@@ -71,22 +87,92 @@ where
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn new_append_only() -> Self {
+        MapxOrdRawKey {
+            inner: MapxRaw::new_append_only(),
+            p: PhantomData,
+        }
+    }
+
+    /// Create an instance in the area `area_idx`, usually a dedicated one,
+    /// see [vsdb_set_area_opts](crate::vsdb_set_area_opts).
+    #[inline(always)]
+    pub fn new_pinned(area_idx: usize) -> Result<Self> {
+        MapxRaw::new_pinned(area_idx)
+            .c(d!())
+            .map(|inner| MapxOrdRawKey {
+                inner,
+                p: PhantomData,
+            })
+    }
+
+    #[cfg(feature = "compression")]
+    #[inline(always)]
+    pub fn new_compressed(c: Compression) -> Self {
+        MapxOrdRawKey {
+            inner: MapxRaw::new_compressed(c),
+            p: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn new_with_ttl() -> Self {
+        MapxOrdRawKey {
+            inner: MapxRaw::new_with_ttl(),
+            p: PhantomData,
+        }
+    }
+}
+
+impl<V, C> MapxOrdRawKey<V, C>
+where
+    V: ValueEnDe,
+    C: Codec,
+{
+    /// Create an instance whose keys and values are encoded by the codec `C`,
+    /// e.g. `MapxOrdRawKey::<V, BcsCodec>::with_codec()`.
+    #[inline(always)]
+    pub fn with_codec() -> Self {
+        MapxOrdRawKey {
+            inner: MapxRaw::new(),
+            p: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_append_only(&self) -> bool {
+        self.inner.is_append_only()
+    }
+
+    #[inline(always)]
+    pub fn compression(&self) -> Option<Compression> {
+        self.inner.compression()
+    }
+
+    #[inline(always)]
+    pub fn has_ttl(&self) -> bool {
+        self.inner.has_ttl()
+    }
+
     #[inline(always)]
     pub fn get(&self, key: &[u8]) -> Option<V> {
         self.inner
             .get(key)
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .and_then(|v| decode_in::<C, V>(self.inner.db(), &v))
     }
 
     #[inline(always)]
-    pub fn get_mut(&self, key: &[u8]) -> Option<ValueMut<'_, V>> {
-        self.inner.get(key).map(|v| {
-            ValueMut::new(
-                self,
-                key.to_vec().into_boxed_slice(),
-                <V as ValueEnDe>::decode(&v).unwrap(),
-            )
-        })
+    pub fn get_mut(&self, key: &[u8]) -> Option<ValueMut<'_, V, C>> {
+        self.get(key)
+            .map(|v| ValueMut::new(self, key.to_vec().into_boxed_slice(), v))
     }
 
     #[inline(always)]
@@ -98,14 +184,14 @@ where
     pub fn get_le(&self, key: &[u8]) -> Option<(RawKey, V)> {
         self.inner
             .get_le(key)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_in::<C, V>(self.inner.db(), &v).map(|v| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge(&self, key: &[u8]) -> Option<(RawKey, V)> {
         self.inner
             .get_ge(key)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_in::<C, V>(self.inner.db(), &v).map(|v| (k, v)))
     }
 
     #[inline(always)]
@@ -123,11 +209,37 @@ where
         self.insert_ref(&key, &value)
     }
 
+    /// Panic if the instance is not created by `new_with_ttl`.
+    #[inline(always)]
+    pub fn insert_with_ttl(&self, key: &[u8], value: &V, ttl: Duration) -> Option<V> {
+        self.inner
+            .insert_with_ttl(key, &encode_value_by::<C, V>(value), ttl)
+            .and_then(|v| decode_in::<C, V>(self.inner.db(), &v))
+    }
+
     #[inline(always)]
     pub fn insert_ref(&self, key: &[u8], value: &V) -> Option<V> {
         self.inner
-            .insert(key, &value.encode())
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .insert(key, &encode_value_by::<C, V>(value))
+            .and_then(|v| decode_in::<C, V>(self.inner.db(), &v))
+    }
+
+    /// Get the encoded bytes of a value, the codec is bypassed.
+    #[inline(always)]
+    pub fn get_raw(&self, key: &[u8]) -> Option<RawValue> {
+        self.inner.get(key)
+    }
+
+    /// Insert the encoded bytes of a value, the codec is bypassed,
+    /// it's your duty to ensure that they can be decoded by the codec `C`.
+    #[inline(always)]
+    pub fn insert_raw(&self, key: &[u8], value: &[u8]) -> Option<RawValue> {
+        self.inner.insert(key, value)
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        &self.inner
     }
 
     // used to support efficient versioned-implementations
@@ -139,7 +251,7 @@ where
     ) -> Option<V> {
         self.inner
             .insert(key, value)
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .and_then(|v| decode_in::<C, V>(self.inner.db(), &v))
     }
 
     #[inline(always)]
@@ -149,34 +261,35 @@ where
 
     #[inline(always)]
     pub fn set_value_ref(&self, key: &[u8], value: &V) {
-        self.inner.insert(key, &value.encode());
+        self.inner.insert(key, &encode_value_by::<C, V>(value));
     }
 
     #[inline(always)]
-    pub fn entry(&self, key: RawKey) -> Entry<'_, V> {
+    pub fn entry(&self, key: RawKey) -> Entry<'_, V, C> {
         Entry { key, hdr: self }
     }
 
     #[inline(always)]
-    pub fn entry_ref<'a>(&'a self, key: &'a [u8]) -> EntryRef<'a, V> {
+    pub fn entry_ref<'a>(&'a self, key: &'a [u8]) -> EntryRef<'a, V, C> {
         EntryRef { key, hdr: self }
     }
 
     #[inline(always)]
-    pub fn iter(&self) -> MapxOrdRawKeyIter<V> {
+    pub fn iter(&self) -> MapxOrdRawKeyIter<V, C> {
         MapxOrdRawKeyIter {
             iter: self.inner.iter(),
+            db: self.inner.db(),
             p: PhantomData,
         }
     }
 
     #[inline(always)]
-    pub fn values(&self) -> MapxOrdRawKeyValues<V> {
+    pub fn values(&self) -> MapxOrdRawKeyValues<V, C> {
         MapxOrdRawKeyValues { iter: self.iter() }
     }
 
     #[inline(always)]
-    pub fn range<R: RangeBounds<RawKey>>(&self, bounds: R) -> MapxOrdRawKeyIter<V> {
+    pub fn range<R: RangeBounds<RawKey>>(&self, bounds: R) -> MapxOrdRawKeyIter<V, C> {
         let start = match bounds.start_bound() {
             Bound::Included(s) => Bound::Included(&s[..]),
             Bound::Excluded(s) => Bound::Excluded(&s[..]),
@@ -196,13 +309,31 @@ where
     pub fn range_ref<'a, R: RangeBounds<&'a [u8]>>(
         &'a self,
         bounds: R,
-    ) -> MapxOrdRawKeyIter<V> {
+    ) -> MapxOrdRawKeyIter<V, C> {
         MapxOrdRawKeyIter {
             iter: self.inner.range(bounds),
+            db: self.inner.db(),
             p: PhantomData,
         }
     }
 
+    /// Get at most `limit` entries after the cursor,
+    /// and the cursor of the next page, `None` if there are no more entries.
+    #[inline(always)]
+    pub fn iter_page(
+        &self,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<(RawKey, V)>, Option<Cursor>) {
+        match cursor {
+            Some(c) => cursor::page(
+                self.range_ref((Bound::Excluded(c.key()), Bound::Unbounded)),
+                limit,
+            ),
+            None => cursor::page(self.iter(), limit),
+        }
+    }
+
     #[inline(always)]
     pub fn first(&self) -> Option<(RawKey, V)> {
         self.iter().next()
@@ -217,7 +348,25 @@ where
     pub fn remove(&self, key: &[u8]) -> Option<V> {
         self.inner
             .remove(key)
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .and_then(|v| decode_in::<C, V>(self.inner.db(), &v))
+    }
+
+    #[inline(always)]
+    pub fn try_insert_ref(
+        &self,
+        key: &[u8],
+        value: &V,
+    ) -> StdResult<Option<V>, AppendOnlyViolation> {
+        self.inner
+            .try_insert(key, &encode_value_by::<C, V>(value))
+            .map(|v| v.and_then(|v| decode_in::<C, V>(self.inner.db(), &v)))
+    }
+
+    #[inline(always)]
+    pub fn try_remove(&self, key: &[u8]) -> StdResult<Option<V>, AppendOnlyViolation> {
+        self.inner
+            .try_remove(key)
+            .map(|v| v.and_then(|v| decode_in::<C, V>(self.inner.db(), &v)))
     }
 
     #[inline(always)]
@@ -229,39 +378,53 @@ where
     pub fn clear(&self) {
         self.inner.clear();
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
 }
 
 #[derive(Debug)]
-pub struct ValueMut<'a, V>
+pub struct ValueMut<'a, V, C = DefaultCodec>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    hdr: &'a MapxOrdRawKey<V>,
+    hdr: &'a MapxOrdRawKey<V, C>,
     key: RawKey,
     value: V,
 }
 
-impl<'a, V> ValueMut<'a, V>
+impl<'a, V, C> ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    pub fn new(hdr: &'a MapxOrdRawKey<V>, key: RawKey, value: V) -> Self {
+    pub fn new(hdr: &'a MapxOrdRawKey<V, C>, key: RawKey, value: V) -> Self {
         ValueMut { hdr, key, value }
     }
 }
 
-impl<'a, V> Drop for ValueMut<'a, V>
+impl<'a, V, C> Drop for ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn drop(&mut self) {
         self.hdr.set_value_ref(&self.key, &self.value);
     }
 }
 
-impl<'a, V> Deref for ValueMut<'a, V>
+impl<'a, V, C> Deref for ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     type Target = V;
     fn deref(&self) -> &Self::Target {
@@ -269,28 +432,31 @@ where
     }
 }
 
-impl<'a, V> DerefMut for ValueMut<'a, V>
+impl<'a, V, C> DerefMut for ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.value
     }
 }
 
-pub struct Entry<'a, V>
+pub struct Entry<'a, V, C = DefaultCodec>
 where
     V: 'a + ValueEnDe,
+    C: Codec,
 {
     pub key: RawKey,
-    pub hdr: &'a MapxOrdRawKey<V>,
+    pub hdr: &'a MapxOrdRawKey<V, C>,
 }
 
-impl<'a, V> Entry<'a, V>
+impl<'a, V, C> Entry<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    pub fn or_insert(self, default: V) -> ValueMut<'a, V> {
+    pub fn or_insert(self, default: V) -> ValueMut<'a, V, C> {
         if !self.hdr.contains_key(&self.key) {
             self.hdr.set_value_ref(&self.key, &default);
         }
@@ -298,19 +464,21 @@ where
     }
 }
 
-pub struct EntryRef<'a, V>
+pub struct EntryRef<'a, V, C = DefaultCodec>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     key: &'a [u8],
-    hdr: &'a MapxOrdRawKey<V>,
+    hdr: &'a MapxOrdRawKey<V, C>,
 }
 
-impl<'a, V> EntryRef<'a, V>
+impl<'a, V, C> EntryRef<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, V> {
+    pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, V, C> {
         if !self.hdr.contains_key(self.key) {
             self.hdr.set_value_ref(self.key, default);
         }
@@ -318,49 +486,62 @@ where
     }
 }
 
-pub struct MapxOrdRawKeyIter<V>
+pub struct MapxOrdRawKeyIter<V, C = DefaultCodec>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     iter: MapxRawIter,
-    p: PhantomData<V>,
+    db: database::Db,
+    p: PhantomData<(V, C)>,
 }
 
-impl<V> Iterator for MapxOrdRawKeyIter<V>
+impl<V, C> Iterator for MapxOrdRawKeyIter<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     type Item = (RawKey, V);
     fn next(&mut self) -> Option<Self::Item> {
+        let db = self.db;
         self.iter
-            .next()
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .find_map(|(k, v)| decode_in::<C, V>(db, &v).map(|v| (k, v)))
     }
 }
 
-impl<V> DoubleEndedIterator for MapxOrdRawKeyIter<V>
+impl<V, C> DoubleEndedIterator for MapxOrdRawKeyIter<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
+        let db = self.db;
         self.iter
-            .next_back()
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| decode_in::<C, V>(db, &v).map(|v| (k, v)))
     }
 }
 
-impl<V> ExactSizeIterator for MapxOrdRawKeyIter<V> where V: ValueEnDe {}
+impl<V, C> ExactSizeIterator for MapxOrdRawKeyIter<V, C>
+where
+    V: ValueEnDe,
+    C: Codec,
+{
+}
 
-pub struct MapxOrdRawKeyValues<V>
+pub struct MapxOrdRawKeyValues<V, C = DefaultCodec>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    iter: MapxOrdRawKeyIter<V>,
+    iter: MapxOrdRawKeyIter<V, C>,
 }
 
-impl<V> Iterator for MapxOrdRawKeyValues<V>
+impl<V, C> Iterator for MapxOrdRawKeyValues<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     type Item = V;
     fn next(&mut self) -> Option<Self::Item> {
@@ -368,13 +549,26 @@ where
     }
 }
 
-impl<V> DoubleEndedIterator for MapxOrdRawKeyValues<V>
+impl<V, C> DoubleEndedIterator for MapxOrdRawKeyValues<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter.next_back().map(|(_, v)| v)
     }
 }
 
-impl<V> ExactSizeIterator for MapxOrdRawKeyValues<V> where V: ValueEnDe {}
+impl<V, C> ExactSizeIterator for MapxOrdRawKeyValues<V, C>
+where
+    V: ValueEnDe,
+    C: Codec,
+{
+}
+
+// Decode values in `db`, the database of the map, instead of the current
+// one of the thread, so nested instances stay in the database of their parent.
+#[inline(always)]
+fn decode_in<C: Codec, V: ValueEnDe>(db: database::Db, v: &[u8]) -> Option<V> {
+    database::scoped(db, || decode_value_by_policy_with::<C, V>(v))
+}
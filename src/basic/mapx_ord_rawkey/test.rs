@@ -1,5 +1,8 @@
 use super::*;
-use crate::ValueEnDe;
+use crate::{
+    vsdb_get_codec_failure_policy, vsdb_set_codec_failure_policy, CodecFailurePolicy,
+    ValueEnDe,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone)]
@@ -66,3 +69,154 @@ fn basic_cases() {
     reloaded.clear();
     assert!(reloaded.is_empty());
 }
+
+#[test]
+fn codec_failure_policy() {
+    let hdr = MapxOrdRawKey::new();
+    hdr.insert_ref(&[1], &1u64);
+    hdr.insert_ref(&[2], &2u64);
+
+    // the same underlying data, but decode values as another type
+    let hdr = <MapxOrdRawKey<u64> as ValueEnDe>::encode(&hdr);
+    let hdr = pnk!(<MapxOrdRawKey<String> as ValueEnDe>::decode(&hdr));
+    hdr.insert_ref(&[3], &"3".to_owned());
+
+    vsdb_set_codec_failure_policy(CodecFailurePolicy::Skip);
+    assert_eq!(CodecFailurePolicy::Skip, vsdb_get_codec_failure_policy());
+
+    assert!(hdr.get(&[1]).is_none());
+    assert_eq!(Some("3".to_owned()), hdr.get(&[3]));
+    assert_eq!(1, hdr.iter().count());
+    assert_eq!(1, hdr.iter().rev().count());
+    assert_eq!("3", hdr.last().unwrap().1);
+
+    vsdb_set_codec_failure_policy(CodecFailurePolicy::Panic);
+    assert_eq!(CodecFailurePolicy::Panic, vsdb_get_codec_failure_policy());
+}
+
+#[cfg(feature = "msgpack_codec")]
+#[test]
+fn msgpack_interop() {
+    let hdr = MapxOrdRawKey::new();
+    hdr.insert_ref(&[1], &gen_sample(&[1, 2, 3]));
+
+    // stored values can be read by other MessagePack implementations
+    let raw = hdr.inner;
+    let v = raw.get(&[1]).unwrap();
+    let v = pnk!(rmp_serde::from_slice::<SampleBlock>(&v));
+    assert_eq!(gen_sample(&[1, 2, 3]), v);
+
+    let v = pnk!(rmp_serde::to_vec_named(&gen_sample(&[4])));
+    raw.insert(&[2], &v);
+    assert_eq!(Some(gen_sample(&[4])), hdr.get(&[2]));
+}
+
+#[cfg(feature = "json_codec")]
+#[test]
+fn json_inspection() {
+    let hdr = MapxOrdRawKey::new();
+    hdr.insert_ref(&[1], &gen_sample(&[1, 2, 3]));
+
+    // stored values are plain JSON
+    let raw = hdr.inner;
+    assert_eq!(&br#"{"data":[1,2,3]}"#[..], &raw.get(&[1]).unwrap()[..]);
+
+    // and can be patched by hand
+    raw.insert(&[1], br#"{ "data": [4] }"#);
+    assert_eq!(Some(gen_sample(&[4])), hdr.get(&[1]));
+
+    // instances themselves can be encoded too
+    let reloaded = pnk!(<MapxOrdRawKey<SampleBlock> as ValueEnDe>::decode(
+        &<MapxOrdRawKey<SampleBlock> as ValueEnDe>::encode(&hdr)
+    ));
+    assert_eq!(Some(gen_sample(&[4])), reloaded.get(&[1]));
+}
+
+#[cfg(feature = "borsh_codec")]
+#[test]
+fn borsh_compatibility() {
+    use crate::{Borsh, ValueEn};
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        io,
+    };
+
+    #[derive(BorshSerialize, BorshDeserialize, Debug, Eq, PartialEq, Clone)]
+    enum Kind {
+        A,
+        B(u16),
+        C { x: i32, y: String },
+    }
+
+    // no serde derives at all
+    #[derive(BorshSerialize, BorshDeserialize, Debug, Eq, PartialEq, Clone)]
+    struct Account {
+        id: u64,
+        name: String,
+        balance: u128,
+        parent: Option<u32>,
+        kinds: Vec<Kind>,
+        attrs: BTreeMap<u8, bool>,
+        labels: HashMap<u32, String>,
+    }
+
+    // writes the given bytes as they are
+    struct Raw(Vec<u8>);
+
+    impl BorshSerialize for Raw {
+        fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+            writer.write_all(&self.0)
+        }
+    }
+
+    let labels = |ids: &[u32]| {
+        let mut m = HashMap::new();
+        ids.iter().for_each(|i| {
+            m.insert(*i, i.to_string());
+        });
+        m
+    };
+
+    let v = Account {
+        id: 9,
+        name: "alice".to_owned(),
+        balance: u128::MAX,
+        parent: Some(7),
+        kinds: vec![
+            Kind::A,
+            Kind::B(3),
+            Kind::C {
+                x: -1,
+                y: "z".to_owned(),
+            },
+        ],
+        attrs: [(1, true), (2, false)].into_iter().collect(),
+        labels: labels(&(0..64).collect::<Vec<_>>()),
+    };
+
+    // the payload is exactly the bytes of borsh itself
+    let bytes = pnk!(v.try_to_vec());
+    let encoded = Borsh(v.clone()).encode_value();
+    assert_eq!(encoded, Borsh(Raw(bytes.clone())).encode_value());
+    assert_eq!(
+        v,
+        pnk!(<Borsh<Account> as ValueEnDe>::decode(&encoded)).into_inner()
+    );
+
+    // canonical, entries of a `HashMap` are sorted by borsh
+    let mut w = v.clone();
+    w.labels = labels(&(0..64).rev().collect::<Vec<_>>());
+    assert_eq!(encoded, Borsh(w).encode_value());
+
+    let hdr = MapxOrdRawKey::new();
+    hdr.insert_ref(&[1], &Borsh(v.clone()));
+    assert_eq!(&encoded[..], &hdr.inner.get(&[1]).unwrap()[..]);
+    assert_eq!(Some(v), hdr.get(&[1]).map(Borsh::into_inner));
+
+    // trailing bytes are rejected
+    let mut bytes = bytes;
+    bytes.push(0);
+    let encoded = Borsh(Raw(bytes)).encode_value();
+    assert!(<Borsh<Account> as ValueEnDe>::decode(&encoded).is_err());
+}
@@ -35,7 +35,12 @@ mod test;
 
 use crate::{
     basic::mapx_raw::{MapxRaw, MapxRawIter},
-    common::{ende::KeyEnDeOrdered, RawValue},
+    common::{
+        ende::{key_from_bytes_by_policy, KeyEnDeOrdered},
+        named,
+        stats::SizeHistogram,
+        RawValue,
+    },
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
@@ -72,6 +77,13 @@ where
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
     #[inline(always)]
     pub fn get(&self, key: &K) -> Option<RawValue> {
         self.inner.get(&key.to_bytes())
@@ -93,14 +105,14 @@ where
     pub fn get_le(&self, key: &K) -> Option<(K, RawValue)> {
         self.inner
             .get_le(&key.to_bytes())
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge(&self, key: &K) -> Option<(K, RawValue)> {
         self.inner
             .get_ge(&key.to_bytes())
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -217,6 +229,21 @@ where
     pub fn clear(&self) {
         self.inner.clear();
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        &self.inner
+    }
 }
 
 #[derive(Debug)]
@@ -321,7 +348,8 @@ where
 {
     type Item = (K, RawValue);
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+        self.iter
+            .find_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 }
 
@@ -331,8 +359,9 @@ where
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter
-            .next_back()
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 }
 
@@ -30,10 +30,18 @@
 #[cfg(test)]
 mod test;
 
-use crate::common::{engines, RawKey, RawValue};
+use crate::common::{
+    append_only::AppendOnlyViolation, compression::Compression, database, engines,
+    named, stats::SizeHistogram, RawKey, RawValue,
+};
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::ops::{Deref, DerefMut, RangeBounds};
+use std::{
+    collections::BTreeSet,
+    ops::{Deref, DerefMut, RangeBounds},
+    result::Result as StdResult,
+    time::Duration,
+};
 
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
 #[serde(bound = "")]
@@ -55,6 +63,107 @@ impl MapxRaw {
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    /// Create an append-only instance, existing keys can not be
+    /// overwritten or removed, `insert`/`remove`/`clear` will panic
+    /// on a violation, use `try_insert`/`try_remove` to get an error instead.
+    #[inline(always)]
+    pub fn new_append_only() -> Self {
+        MapxRaw {
+            inner: engines::Mapx::new_append_only(),
+        }
+    }
+
+    /// Create an instance in the area `area_idx`, usually a dedicated one,
+    /// see [vsdb_set_area_opts](crate::vsdb_set_area_opts).
+    #[inline(always)]
+    pub fn new_pinned(area_idx: usize) -> Result<Self> {
+        engines::Mapx::new_pinned(area_idx)
+            .c(d!())
+            .map(|inner| MapxRaw { inner })
+    }
+
+    #[inline(always)]
+    pub fn is_append_only(&self) -> bool {
+        self.inner.is_append_only()
+    }
+
+    /// Create an instance whose values are compressed by `c`,
+    /// it is transparent to all operations.
+    #[cfg(feature = "compression")]
+    #[inline(always)]
+    pub fn new_compressed(c: Compression) -> Self {
+        MapxRaw {
+            inner: engines::Mapx::new_compressed(c),
+        }
+    }
+
+    #[inline(always)]
+    pub fn compression(&self) -> Option<Compression> {
+        self.inner.compression()
+    }
+
+    /// Create an instance whose entries can expire,
+    /// see [insert_with_ttl](Self::insert_with_ttl).
+    #[inline(always)]
+    pub fn new_with_ttl() -> Self {
+        MapxRaw {
+            inner: engines::Mapx::new_with_ttl(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn has_ttl(&self) -> bool {
+        self.inner.has_ttl()
+    }
+
+    #[inline(always)]
+    pub(crate) fn open(id: u64) -> Option<Self> {
+        engines::Mapx::open(id).map(|inner| MapxRaw { inner })
+    }
+
+    #[inline(always)]
+    pub(crate) fn open_reserved(id: u64) -> Self {
+        MapxRaw {
+            inner: engines::Mapx::open_reserved(id),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn open_reserved_in(db: database::Db, id: u64) -> Self {
+        MapxRaw {
+            inner: engines::Mapx::open_reserved_in(db, id),
+        }
+    }
+
+    // The database holding this instance.
+    #[inline(always)]
+    pub(crate) fn db(&self) -> database::Db {
+        self.inner.db()
+    }
+
+    #[inline(always)]
+    pub(crate) fn id(&self) -> u64 {
+        self.inner.prefix()
+    }
+
+    #[inline(always)]
+    pub(crate) fn area_idx(&self) -> usize {
+        self.inner.area_idx()
+    }
+
+    // Flush the given areas of the database holding this instance.
+    #[inline(always)]
+    pub(crate) fn flush_areas(&self, area_indexes: &BTreeSet<usize>) {
+        self.inner.flush_areas(area_indexes);
+    }
+
     #[inline(always)]
     pub fn get(&self, key: &[u8]) -> Option<RawValue> {
         self.inner.get(key)
@@ -116,15 +225,60 @@ impl MapxRaw {
         self.inner.insert(key, value)
     }
 
+    /// The entry will be invisible after `ttl`,
+    /// and removed physically on the next flush of the database.
+    ///
+    /// Panic if the instance is not created by `new_with_ttl`.
+    #[inline(always)]
+    pub fn insert_with_ttl(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Option<RawValue> {
+        self.inner.insert_with_ttl(key, value, ttl)
+    }
+
     #[inline(always)]
     pub fn remove(&self, key: &[u8]) -> Option<RawValue> {
         self.inner.remove(key)
     }
 
+    #[inline(always)]
+    pub fn try_insert(
+        &self,
+        key: &[u8],
+        value: &[u8],
+    ) -> StdResult<Option<RawValue>, AppendOnlyViolation> {
+        self.inner.try_insert(key, value)
+    }
+
+    #[inline(always)]
+    pub fn try_remove(
+        &self,
+        key: &[u8],
+    ) -> StdResult<Option<RawValue>, AppendOnlyViolation> {
+        self.inner.try_remove(key)
+    }
+
     #[inline(always)]
     pub fn clear(&self) {
         self.inner.clear();
     }
+
+    /// Flush the data of this instance to disk,
+    /// other instances in different areas will not be touched.
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    /// Distribution of the key and value sizes of this instance,
+    /// only one of every `sample_step` entries will be recorded.
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        SizeHistogram::scan(self.iter(), sample_step)
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -175,7 +329,7 @@ impl<'a> Entry<'a> {
 }
 
 pub struct MapxRawIter {
-    iter: engines::MapxIter,
+    iter: engines::EngineIter,
 }
 
 impl Iterator for MapxRawIter {
@@ -1,5 +1,20 @@
 use super::*;
-use crate::ValueEnDe;
+use crate::{
+    common::{namespace::next_derived_prefix, write_batch::persist_only},
+    raw_area, vsdb_begin_tx, vsdb_flush, vsdb_get_base_dir, vsdb_get_config,
+    vsdb_get_custom_dir, vsdb_get_durability, vsdb_get_engine_opts,
+    vsdb_get_iter_memory_usage, vsdb_is_replica, vsdb_open_as_replica, vsdb_reconfigure,
+    vsdb_recover_write_batches, vsdb_refresh, vsdb_set_durability, vsdb_set_engine_opts,
+    vsdb_set_flush_hook, vsdb_set_iter_memory_budget, vsdb_set_slow_op_hook,
+    vsdb_storage_stats, vsdb_with_prefix_namespace, AppendOnlyViolation,
+    AreaStorageStats, BranchName, BudgetedIter, CompactionStyle, Durability, EngineOpts,
+    FileChange, FlushHook, IterBudgetExceeded, Mapx, MapxVs, PartialConfig, RawArea,
+    SlowOp, StorageStats, ValueEnDe, Vecx, VersionName, VsMgmt, Vsdb, WriteBatch,
+};
+use parking_lot::Mutex;
+use std::{
+    fs, ops::Bound, panic::catch_unwind, path::PathBuf, sync::Arc, time::Duration,
+};
 
 #[test]
 fn basic_cases() {
@@ -26,6 +41,9 @@ fn basic_cases() {
 
         assert_eq!(cnt, hdr_i.len());
 
+        hdr_i.flush();
+        assert_eq!(cnt, hdr_i.len());
+
         <MapxRaw as ValueEnDe>::encode(&hdr_i)
     };
 
@@ -77,3 +95,972 @@ fn basic_cases() {
         reloaded.get_le(&[100]).unwrap().1
     );
 }
+
+#[test]
+fn size_histogram() {
+    let hdr = MapxRaw::new();
+
+    hdr.insert(&[], &[0; 3]);
+    hdr.insert(&[1], &[0; 100]);
+    hdr.insert(&[2, 2], &[0; 1000]);
+    hdr.insert(&[3, 3, 3, 3], &[]);
+
+    let h = hdr.size_histogram(1);
+    assert_eq!(4, h.entries);
+    assert_eq!(7, h.total_key_size);
+    assert_eq!(1103, h.total_value_size);
+
+    // keys: 0, 1, 2, 4
+    assert_eq!(1, h.keys[0]);
+    assert_eq!(1, h.keys[1]);
+    assert_eq!(1, h.keys[2]);
+    assert_eq!(1, h.keys[3]);
+
+    // values: 0, 3, 100, 1000
+    assert_eq!(1, h.values[0]);
+    assert_eq!(1, h.values[2]);
+    assert_eq!(1, h.values[7]);
+    assert_eq!(1, h.values[10]);
+    assert_eq!(4, h.values.iter().sum::<u64>());
+
+    assert_eq!(2, hdr.size_histogram(2).entries);
+    assert_eq!(4, hdr.size_histogram(0).entries);
+}
+
+#[test]
+fn budgeted_iter() {
+    let hdr = MapxRaw::new();
+    (0..10u64).for_each(|i| {
+        hdr.insert(&i.to_be_bytes(), &[0; 8]);
+    });
+
+    // every item has 16 bytes
+    vsdb_set_iter_memory_budget(64);
+
+    let mut it = BudgetedIter::new_raw(hdr.iter(), 48);
+    let c0 = it.next_chunk().unwrap().unwrap();
+    assert_eq!(3, c0.len());
+    assert_eq!(48, it.in_flight());
+    assert_eq!(48, vsdb_get_iter_memory_usage());
+
+    // limited by the remaining budget
+    let c1 = it.next_chunk().unwrap().unwrap();
+    assert_eq!(1, c1.len());
+    assert_eq!(
+        IterBudgetExceeded {
+            requested: 16,
+            available: 0
+        },
+        it.next_chunk().unwrap().unwrap_err()
+    );
+
+    drop(c0);
+    drop(c1);
+    assert_eq!(0, it.in_flight());
+    assert_eq!(0, vsdb_get_iter_memory_usage());
+
+    let c2 = it.next_chunk().unwrap().unwrap();
+    assert_eq!(&c2[0].0[..], &4u64.to_be_bytes()[..]);
+    assert_eq!(3, c2.len());
+    let c3 = it.next_chunk().unwrap().unwrap();
+    assert_eq!(1, c3.len());
+    drop(c2);
+    drop(c3);
+
+    let c4 = it.next_chunk().unwrap().unwrap();
+    assert_eq!(2, c4.len());
+    drop(c4);
+    assert!(it.next_chunk().is_none());
+
+    vsdb_set_iter_memory_budget(0);
+}
+
+#[derive(Default)]
+struct Mirror(Mutex<Vec<FileChange>>);
+
+impl FlushHook for Mirror {
+    fn after_flush(&self, changes: &[FileChange]) {
+        self.0.lock().extend_from_slice(changes);
+    }
+}
+
+#[test]
+fn flush_hook() {
+    let mirror = Arc::new(Mirror::default());
+    let path = PathBuf::from(vsdb_get_custom_dir()).join("flush_hook");
+    fs::write(&path, [0; 8]).unwrap();
+
+    vsdb_set_flush_hook(Some(Arc::clone(&mirror) as Arc<dyn FlushHook>));
+
+    let hdr = MapxRaw::new();
+    hdr.insert(b"k", b"v");
+
+    // changes made before installing are not reported
+    fs::write(&path, [0; 16]).unwrap();
+    hdr.flush();
+
+    let found = |path: &PathBuf| {
+        mirror
+            .0
+            .lock()
+            .iter()
+            .rev()
+            .find(|c| &c.path == path)
+            .cloned()
+    };
+    let c = found(&path).unwrap();
+    assert_eq!(Some((8, 16)), c.appended_range());
+
+    fs::remove_file(&path).unwrap();
+    crate::vsdb_flush();
+    assert!(found(&path).unwrap().is_removed());
+
+    vsdb_set_flush_hook(None);
+}
+
+#[test]
+fn prefix_namespace() {
+    assert!(next_derived_prefix().is_none());
+
+    let two = || (next_derived_prefix(), next_derived_prefix());
+    let (a0, a1) = vsdb_with_prefix_namespace("prefix_namespace", two);
+    let (b0, b1) = vsdb_with_prefix_namespace("prefix_namespace", two);
+    assert_eq!((a0, a1), (b0, b1));
+    assert_ne!(a0, a1);
+    assert_ne!(a0, vsdb_with_prefix_namespace("other", next_derived_prefix));
+
+    // nested namespaces are restored after returning
+    vsdb_with_prefix_namespace("outer", || {
+        let first = next_derived_prefix();
+        vsdb_with_prefix_namespace("inner", next_derived_prefix);
+        let second = next_derived_prefix();
+        assert_ne!(first, second);
+        assert_eq!((first, second), vsdb_with_prefix_namespace("outer", two));
+    });
+    assert!(next_derived_prefix().is_none());
+
+    // taken prefixes are skipped
+    let (x, y) = vsdb_with_prefix_namespace("prefix_namespace/mapx", || {
+        (MapxRaw::new(), MapxRaw::new())
+    });
+    let z = vsdb_with_prefix_namespace("prefix_namespace/mapx", MapxRaw::new);
+    x.insert(b"k", b"x");
+    y.insert(b"k", b"y");
+    z.insert(b"k", b"z");
+    assert_eq!(&x.get(b"k").unwrap()[..], b"x");
+    assert_eq!(&y.get(b"k").unwrap()[..], b"y");
+    assert_eq!(&z.get(b"k").unwrap()[..], b"z");
+    assert_ne!(x.encode(), z.encode());
+}
+
+#[test]
+fn raw_area_reopen() {
+    let area = raw_area();
+    area.insert(b"col/0", b"a");
+    area.insert(b"col/1", b"b");
+    area.insert(b"idx/0", b"c");
+
+    let reopened = RawArea::open(area.id()).unwrap();
+    assert_eq!(reopened, area);
+    assert_eq!(reopened.range(&b"col/"[..]..&b"col0"[..]).count(), 2);
+    reopened.remove(b"col/0");
+    assert!(area.get(b"col/0").is_none());
+    assert_eq!(2, area.len());
+
+    assert_ne!(raw_area().id(), area.id());
+
+    // reserved prefixes are never allocated
+    assert!(RawArea::open(0).is_err());
+}
+
+#[test]
+fn append_only() {
+    let hdr = MapxRaw::new_append_only();
+    assert!(hdr.is_append_only());
+    assert!(!MapxRaw::new().is_append_only());
+
+    assert!(hdr.try_insert(b"k", b"v").unwrap().is_none());
+    assert_eq!(
+        hdr.try_insert(b"k", b"x").unwrap_err(),
+        AppendOnlyViolation { key: b"k".to_vec() }
+    );
+    assert!(hdr.try_remove(b"k").is_err());
+    assert_eq!(&hdr.get(b"k").unwrap()[..], b"v");
+
+    // removing an absent key changes nothing
+    assert!(hdr.try_remove(b"absent").unwrap().is_none());
+    assert!(std::panic::catch_unwind(|| hdr.insert(b"k", b"x")).is_err());
+    assert!(std::panic::catch_unwind(|| hdr.clear()).is_err());
+
+    // the mode is persisted along with the instance
+    let decoded = <MapxRaw as ValueEnDe>::decode(&hdr.encode()).unwrap();
+    assert!(decoded.is_append_only());
+    assert!(decoded.try_insert(b"k", b"x").is_err());
+
+    // only one of the concurrent writers of an absent key succeeds
+    let writers = (0..8u8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                (0..100u8)
+                    .filter(|k| hdr.try_insert(&[*k], &[i]).is_ok())
+                    .count()
+            })
+        })
+        .collect::<Vec<_>>();
+    let succeeded = writers
+        .into_iter()
+        .map(|w| w.join().unwrap())
+        .sum::<usize>();
+    assert_eq!(100, succeeded);
+    assert_eq!(101, hdr.len());
+
+    let log = crate::Vecx::new_append_only();
+    log.push(1);
+    log.push(2);
+    assert_eq!(2, log.len());
+    assert!(std::panic::catch_unwind(|| log.pop()).is_err());
+    assert_eq!(Some(2), log.last());
+}
+
+#[test]
+fn reconfigure() {
+    let hdr = MapxRaw::new();
+
+    let ops = Arc::new(Mutex::new(Vec::new()));
+    let ops_ = Arc::clone(&ops);
+    vsdb_set_slow_op_hook(Some(Arc::new(move |op: SlowOp| ops_.lock().push(op.op))));
+
+    // every operation is slower than 1ns
+    vsdb_reconfigure(PartialConfig {
+        slow_op_threshold: Some(Duration::from_nanos(1)),
+        flush_interval: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(Duration::from_secs(3600), vsdb_get_config().flush_interval);
+
+    // the interval is kept when syncing per version
+    pnk!(vsdb_set_durability(Durability::PerVersion));
+    assert_eq!(Durability::PerVersion, vsdb_get_durability());
+    assert_eq!(Duration::from_secs(3600), vsdb_get_config().flush_interval);
+    let interval = Durability::Interval(Duration::from_secs(3600));
+    pnk!(vsdb_set_durability(interval));
+    assert_eq!(interval, vsdb_get_durability());
+
+    hdr.insert(b"k", b"v");
+    hdr.get(b"k");
+    assert!(ops.lock().contains(&"insert"));
+    assert!(ops.lock().contains(&"get"));
+
+    vsdb_reconfigure(PartialConfig {
+        slow_op_threshold: Some(Duration::ZERO),
+        flush_interval: Some(Duration::ZERO),
+        ..Default::default()
+    })
+    .unwrap();
+    vsdb_set_slow_op_hook(None);
+    assert!(vsdb_get_config().slow_op_threshold.is_zero());
+    assert_eq!(Durability::Async, vsdb_get_durability());
+
+    // the cache size is kept as it was if it can not be applied
+    let cache_size = 64 * 1024 * 1024;
+    let applied = vsdb_reconfigure(PartialConfig {
+        cache_size: Some(cache_size),
+        ..Default::default()
+    })
+    .is_ok();
+    assert_eq!(applied, cache_size == vsdb_get_config().cache_size);
+}
+
+#[test]
+fn replica_mode() {
+    // open the database as a primary first
+    let hdr = MapxRaw::new();
+    hdr.insert(b"k", b"v");
+
+    assert!(vsdb_open_as_replica(Duration::ZERO).is_err());
+    assert!(!vsdb_is_replica());
+    assert!(vsdb_refresh().is_err());
+}
+
+#[test]
+fn iter_from_both_ends() {
+    let hdr = MapxRaw::new();
+    (0..10u8).for_each(|i| {
+        hdr.insert(&[i], &[i]);
+    });
+
+    // the two ends meet in the middle, no entry is yielded twice
+    let mut it = hdr.iter();
+    let mut seen = vec![];
+    while let Some((k, _)) = it.next() {
+        seen.push(k[0]);
+        if let Some((k, _)) = it.next_back() {
+            seen.push(k[0]);
+        }
+    }
+    seen.sort_unstable();
+    assert_eq!((0..10).collect::<Vec<_>>(), seen);
+
+    let lo: &[u8] = &[6];
+    let hi: &[u8] = &[3];
+    assert_eq!(0, hdr.range(lo..hi).count());
+    let empty = (Bound::Excluded(hi), Bound::Excluded(hi));
+    assert_eq!(0, hdr.range(empty).count());
+    assert_eq!(Some(3), hdr.range(hi..=hi).next().map(|(k, _)| k[0]));
+    assert_eq!(Some(5), hdr.range(..lo).next_back().map(|(k, _)| k[0]));
+
+    // other instances are never reached
+    let other = MapxRaw::new();
+    other.insert(&[0], &[0]);
+    assert_eq!(10, hdr.iter().count());
+    assert_eq!(10, hdr.iter().rev().count());
+}
+
+#[test]
+fn engine_opts_after_opening() {
+    // open the database first
+    MapxRaw::new();
+
+    let opts = EngineOpts {
+        compaction_style: Some(CompactionStyle::Universal),
+        ..Default::default()
+    };
+    assert!(vsdb_set_engine_opts(opts).is_err());
+    assert_eq!(EngineOpts::default(), vsdb_get_engine_opts());
+}
+
+#[test]
+fn storage_stats() {
+    let area = |live, disk, tombstones| AreaStorageStats {
+        live_bytes: Some(live),
+        disk_bytes: Some(disk),
+        tombstones,
+    };
+    let stats =
+        StorageStats::from_areas(vec![area(100, 150, Some(2)), area(100, 250, None)]);
+    assert_eq!(Some(200), stats.live_bytes);
+    assert_eq!(Some(400), stats.disk_bytes);
+    assert_eq!(Some(2.0), stats.space_amplification());
+    assert!(stats.tombstones().is_none());
+    assert!(StorageStats::default().space_amplification().is_none());
+    assert!(StorageStats::default().tombstones().is_none());
+
+    // whatever the engine tracks should be consistent
+    let hdr = MapxRaw::new();
+    (0_u8..100).for_each(|i| {
+        hdr.insert(&[i], &[i; 128]);
+    });
+    (0_u8..50).for_each(|i| {
+        hdr.remove(&[i]);
+    });
+    vsdb_flush();
+
+    let stats = vsdb_storage_stats();
+    if !stats.areas.is_empty() {
+        let live = stats
+            .areas
+            .iter()
+            .map(|a| a.live_bytes)
+            .sum::<Option<u64>>();
+        assert_eq!(live, stats.live_bytes);
+    }
+    if let Some(n) = stats.live_bytes {
+        assert!(0 < n);
+    }
+}
+
+#[test]
+fn independent_databases() {
+    let dir = |name: &str| format!("{}/databases/{}", vsdb_get_custom_dir(), name);
+    let a = pnk!(Vsdb::open(dir("a")));
+    let b = pnk!(Vsdb::open(dir("b")));
+    assert_ne!(a, b);
+    assert_eq!(a, pnk!(Vsdb::open(dir("a"))));
+    assert_eq!(Vsdb::global(), pnk!(Vsdb::open(vsdb_get_base_dir())));
+
+    // both get the first prefix of their own databases
+    let ha = a.scope(MapxRaw::new);
+    let hb = b.scope(MapxRaw::new);
+    a.scope(|| ha.insert(&[0], &[1]));
+    b.scope(|| hb.insert(&[0], &[2]));
+    a.scope(|| assert_eq!(&[1], &*ha.get(&[0]).unwrap()));
+    b.scope(|| assert_eq!(&[2], &*hb.get(&[0]).unwrap()));
+
+    // decoded in the current database
+    let bytes = ha.encode();
+    let ha2 = a.scope(|| pnk!(<MapxRaw as ValueEnDe>::decode(&bytes)));
+    a.scope(|| assert_eq!(1, ha2.len()));
+
+    // the outer database is restored after a nested scope
+    a.scope(|| {
+        b.scope(|| assert_eq!(1, hb.len()));
+        assert_eq!(1, ha.len());
+    });
+    assert_eq!(0, MapxRaw::new().len());
+
+    // instances keep using their own databases out of the scopes
+    assert_eq!(1, ha.len());
+    assert_eq!(1, b.scope(|| ha.len()));
+    let remote = std::thread::spawn(move || hb.get(&[0]).unwrap()[0]);
+    assert_eq!(2, remote.join().unwrap());
+}
+
+#[cfg(feature = "remote_engine")]
+#[test]
+fn remote_engine() {
+    use crate::common::{
+        engines::{Engine, RemoteEngine},
+        remote,
+    };
+    use std::net::TcpListener;
+
+    let listener = pnk!(TcpListener::bind("127.0.0.1:0"));
+    remote::set_remote_addr(&pnk!(listener.local_addr()).to_string());
+    std::thread::spawn(move || remote::serve_on(listener));
+
+    // served by the engine of this process
+    let engine = pnk!(RemoteEngine::new(&vsdb_get_base_dir()));
+    let prefix = engine.alloc_prefix().to_be_bytes();
+    engine.set_instance_len(prefix, 0);
+    assert!(engine.instance_exists(prefix));
+    assert!(!engine.instance_is_append_only(prefix));
+
+    let key = |i: u16| i.to_be_bytes();
+    (0..300).for_each(|i| {
+        assert!(engine.insert(0, prefix, &key(i), &[1]).is_none());
+    });
+    assert_eq!(Some(1), engine.get(0, prefix, &key(7)).map(|v| v[0]));
+
+    // more than one chunk from both ends, no entry is yielded twice
+    let mut it = engine.iter(0, prefix);
+    let mut seen = vec![];
+    while let Some((k, _)) = it.next() {
+        seen.push(k);
+        if let Some((k, _)) = it.next_back() {
+            seen.push(k);
+        }
+    }
+    seen.sort();
+    seen.dedup();
+    assert_eq!(300, seen.len());
+
+    let (lo, hi) = (key(100), key(200));
+    let bounds = (Bound::Included(&lo[..]), Bound::Excluded(&hi[..]));
+    assert_eq!(100, engine.range(0, prefix, bounds).rev().count());
+
+    assert_eq!(Some(1), engine.remove(0, prefix, &key(7)).map(|v| v[0]));
+    assert!(engine.get(0, prefix, &key(7)).is_none());
+
+    engine.set_instance_len(prefix, 299);
+    assert_eq!(299, engine.get_instance_len(prefix));
+    engine.flush();
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encryption() {
+    use crate::common::engines::{
+        Backend, Encrypted, EncryptionKey, EncryptionOpts, Engine,
+    };
+    use std::ops::Bound;
+
+    let secret = |id: u32| EncryptionKey {
+        id,
+        key: [id as u8; 32],
+    };
+    let opts = |id, old_ids: &[u32], encrypt_keys| EncryptionOpts {
+        key: secret(id),
+        old_keys: old_ids.iter().copied().map(secret).collect(),
+        encrypt_keys,
+    };
+    let encrypted_by = |inner: &Backend, prefix, id: u32| {
+        inner
+            .iter(0, prefix)
+            .filter(|(_, v)| v[..4] == id.to_be_bytes())
+            .count()
+    };
+
+    for encrypt_keys in [false, true] {
+        let dir = format!("{}/encryption_{}", vsdb_get_base_dir(), encrypt_keys);
+        let e = Encrypted::wrap(
+            pnk!(Backend::open_plain(&dir)),
+            &opts(1, &[], encrypt_keys),
+        );
+        let prefix = e.alloc_prefix().to_be_bytes();
+        (0_u8..10).for_each(|i| {
+            assert!(e.insert(0, prefix, &[i], &[i]).is_none());
+        });
+        assert_eq!(Some(vec![0].into()), e.insert(0, prefix, &[0], &[0]));
+        assert_eq!(Some(vec![3].into()), e.get(0, prefix, &[3]));
+
+        // in the order of plaintext keys, even if keys are encrypted
+        let bounds = (Bound::Included(&[3_u8][..]), Bound::Excluded(&[6_u8][..]));
+        let keys = e
+            .range(0, prefix, bounds)
+            .map(|(k, _)| k[0])
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3, 4, 5], keys);
+        assert_eq!(Some(9), e.iter(0, prefix).next_back().map(|(k, _)| k[0]));
+
+        // nothing is stored as plaintext
+        let inner = e.into_inner();
+        assert_eq!(10, encrypted_by(&inner, prefix, 1));
+        inner.iter(0, prefix).for_each(|(k, v)| {
+            assert_eq!(encrypt_keys, 1 != k.len());
+            assert!(1 < v.len());
+        });
+
+        // re-encrypted when read out, old copies are replaced
+        let e = Encrypted::wrap(inner, &opts(2, &[1], encrypt_keys));
+        assert_eq!(Some(vec![3].into()), e.get(0, prefix, &[3]));
+        assert_eq!(Some(vec![5].into()), e.remove(0, prefix, &[5]));
+        assert!(e.get(0, prefix, &[5]).is_none());
+        assert!(e.insert(0, prefix, &[7], &[70]).is_some());
+        assert_eq!(9, e.iter(0, prefix).count());
+
+        let inner = e.into_inner();
+        assert_eq!(2, encrypted_by(&inner, prefix, 2));
+        assert_eq!(7, encrypted_by(&inner, prefix, 1));
+
+        // the old secret is no longer needed by rotated entries
+        let e = Encrypted::wrap(inner, &opts(2, &[], encrypt_keys));
+        assert_eq!(Some(vec![3].into()), e.get(0, prefix, &[3]));
+        assert_eq!(Some(vec![70].into()), e.get(0, prefix, &[7]));
+    }
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed() {
+    use crate::Compression;
+
+    let c = Compression::Zstd { level: 3 };
+    let hdr = MapxRaw::new_compressed(c);
+    assert_eq!(Some(c), hdr.compression());
+    assert!(MapxRaw::new().compression().is_none());
+
+    let value = |i: u8| vec![i; 1024];
+    (0_u8..10).for_each(|i| {
+        assert!(hdr.insert(&[i], &value(i)).is_none());
+    });
+    assert_eq!(Some(value(1).into()), hdr.insert(&[1], &value(11)));
+    assert_eq!(Some(value(11).into()), hdr.get(&[1]));
+    assert_eq!(Some(value(2).into()), hdr.remove(&[2]));
+    assert_eq!(9, hdr.len());
+
+    // values are decompressed along with the iteration
+    hdr.iter().for_each(|(k, v)| {
+        assert_eq!(alt!(1 == k[0], value(11), value(k[0])), v.to_vec());
+    });
+    assert_eq!(
+        Some(value(9).into()),
+        hdr.iter().next_back().map(|(_, v)| v)
+    );
+
+    // the algorithm is recorded in the database
+    let reopened = MapxRaw::open(hdr.id()).unwrap();
+    assert_eq!(Some(c), reopened.compression());
+    assert_eq!(Some(value(3).into()), reopened.get(&[3]));
+
+    // the algorithm is kept after serialization
+    let reloaded = pnk!(bcs::from_bytes::<MapxRaw>(&pnk!(bcs::to_bytes(&hdr))));
+    assert_eq!(Some(c), reloaded.compression());
+    assert_eq!(Some(value(4).into()), reloaded.get(&[4]));
+}
+
+#[test]
+fn flush_async() {
+    use crate::vsdb_flush_async;
+    use std::time::Duration;
+
+    let hdr = MapxRaw::new();
+    hdr.insert(&[0], &[0]);
+
+    let h = vsdb_flush_async();
+    h.wait();
+    assert!(h.is_done());
+    assert!(h.wait_timeout(Duration::from_secs(1)));
+
+    // handles of concurrent flushes are independent
+    let hs = (0..4).map(|_| vsdb_flush_async()).collect::<Vec<_>>();
+    hdr.insert(&[1], &[1]);
+    hs.iter().for_each(|h| h.wait());
+    assert_eq!(2, hdr.len());
+}
+
+#[cfg(feature = "migration")]
+#[test]
+fn migration() {
+    use crate::{
+        common::engines::{open_engine, Engine},
+        vsdb_migrate, EngineKind,
+    };
+
+    let root = format!("{}/migration", vsdb_get_custom_dir());
+    let _ = fs::remove_dir_all(&root);
+    let dir = |name: &str| {
+        let dir = format!("{}/{}", root, name);
+        pnk!(fs::create_dir_all(&dir));
+        pnk!(fs::canonicalize(dir)).to_string_lossy().into_owned()
+    };
+
+    let src = pnk!(open_engine(&dir("sled"), EngineKind::Sled));
+    let prefixes = (0..3).map(|_| src.alloc_prefix()).collect::<Vec<_>>();
+    prefixes.iter().for_each(|p| {
+        let area_idx = (p % src.area_count() as u64) as usize;
+        (0_u8..10).for_each(|i| {
+            src.insert(area_idx, p.to_be_bytes(), &[i], &[i]);
+        });
+        src.set_instance_len(p.to_be_bytes(), 10);
+    });
+    src.set_instance_append_only(prefixes[0].to_be_bytes());
+    let branch_id = src.alloc_branch_id();
+    let version_id = src.alloc_version_id();
+
+    pnk!(vsdb_migrate(
+        dir("sled"),
+        EngineKind::Sled,
+        dir("rocksdb"),
+        EngineKind::Rocksdb
+    ));
+    pnk!(vsdb_migrate(
+        dir("rocksdb"),
+        EngineKind::Rocksdb,
+        dir("sled_2"),
+        EngineKind::Sled
+    ));
+
+    // the target must be empty
+    assert!(vsdb_migrate(
+        dir("sled"),
+        EngineKind::Sled,
+        dir("sled_2"),
+        EngineKind::Sled
+    )
+    .is_err());
+
+    for (name, kind) in [
+        ("rocksdb", EngineKind::Rocksdb),
+        ("sled_2", EngineKind::Sled),
+    ] {
+        let dst = pnk!(open_engine(&dir(name), kind));
+        prefixes.iter().for_each(|p| {
+            let area_idx = (p % dst.area_count() as u64) as usize;
+            assert_eq!(10, dst.get_instance_len(p.to_be_bytes()));
+            assert_eq!(10, dst.iter(area_idx, p.to_be_bytes()).count());
+            assert_eq!(
+                Some(vec![3].into()),
+                dst.get(area_idx, p.to_be_bytes(), &[3])
+            );
+        });
+        assert!(dst.instance_is_append_only(prefixes[0].to_be_bytes()));
+        assert!(!dst.instance_is_append_only(prefixes[1].to_be_bytes()));
+
+        // allocators go on from where the source stopped
+        assert_eq!(1 + prefixes[2], dst.alloc_prefix());
+        assert_eq!(1 + branch_id, dst.alloc_branch_id());
+        assert_eq!(1 + version_id, dst.alloc_version_id());
+    }
+}
+
+#[test]
+fn sharding() {
+    use crate::{
+        common::engines::{Engine, Sharded},
+        vsdb_set_shard_dirs,
+    };
+
+    // VSDB has been initialized by other cases
+    assert!(vsdb_set_shard_dirs(&["/tmp"]).is_err());
+
+    let root = format!("{}/sharding", vsdb_get_custom_dir());
+    let _ = fs::remove_dir_all(&root);
+    let roots = (0..3)
+        .map(|i| {
+            let r = PathBuf::from(format!("{}/disk_{}", root, i));
+            pnk!(fs::create_dir_all(&r));
+            r
+        })
+        .collect::<Vec<_>>();
+    let dir = format!("{}/db", root);
+
+    let db = pnk!(Sharded::open(&dir, &roots));
+    assert_eq!(0, db.area_count() % roots.len());
+
+    let prefixes = (0..6).map(|_| db.alloc_prefix()).collect::<Vec<_>>();
+    prefixes.iter().for_each(|p| {
+        let area_idx = (p % db.area_count() as u64) as usize;
+        (0_u8..10).for_each(|i| {
+            db.insert(area_idx, p.to_be_bytes(), &[i], &[i]);
+        });
+        db.set_instance_len(p.to_be_bytes(), 10);
+    });
+    db.set_instance_append_only(prefixes[0].to_be_bytes());
+    db.flush();
+
+    prefixes.iter().for_each(|p| {
+        let area_idx = (p % db.area_count() as u64) as usize;
+        assert_eq!(10, db.get_instance_len(p.to_be_bytes()));
+        assert_eq!(10, db.iter(area_idx, p.to_be_bytes()).count());
+        assert_eq!(
+            Some(vec![3].into()),
+            db.get(area_idx, p.to_be_bytes(), &[3])
+        );
+        db.flush_area(area_idx);
+    });
+    assert!(db.instance_is_append_only(prefixes[0].to_be_bytes()));
+    assert!(!db.instance_is_append_only(prefixes[1].to_be_bytes()));
+
+    // shards are created under all roots
+    roots.iter().for_each(|r| {
+        assert!(pnk!(fs::read_dir(r)).next().is_some());
+    });
+
+    // the layout can not be changed
+    assert!(Sharded::open(&dir, &roots[..2]).is_err());
+}
+
+#[test]
+fn data_dirs() {
+    let base = PathBuf::from(vsdb_get_base_dir());
+    let custom = PathBuf::from(vsdb_get_custom_dir());
+    assert!(base.is_dir());
+    assert!(custom.is_dir());
+    assert_eq!(Some(base.as_path()), custom.parent());
+}
+
+#[test]
+fn open_at() {
+    let hdr = pnk!(MapxRaw::open_at("open_at//raw/"));
+    hdr.insert(&[1], &[1]);
+
+    let reopened = pnk!(MapxRaw::open_at("open_at/raw"));
+    assert_eq!(hdr.id(), reopened.id());
+    assert_eq!(Some(vec![1].into()), reopened.get(&[1]));
+
+    assert_ne!(hdr.id(), pnk!(MapxRaw::open_at("open_at/raw_2")).id());
+
+    assert!(MapxRaw::open_at("").is_err());
+    assert!(MapxRaw::open_at("//").is_err());
+    assert!(MapxRaw::open_at("open_at/../raw").is_err());
+}
+
+#[test]
+fn area_partitioning() {
+    use crate::{
+        common::areas::{area_of, check_layout},
+        vsdb_get_area_opts, vsdb_set_area_opts, AreaOpts, AreaStrategy,
+    };
+
+    // VSDB has been initialized by other cases
+    assert!(vsdb_set_area_opts(AreaOpts::default()).is_err());
+    assert_eq!(AreaStrategy::RoundRobin, vsdb_get_area_opts().strategy);
+    assert_eq!(3, area_of(7, 4));
+
+    let area_cnt = Vsdb::global().area_count();
+    let last = area_cnt - 1;
+    (0..3).for_each(|_| {
+        let hdr = pnk!(MapxRaw::new_pinned(last));
+        assert_eq!(last, area_of(hdr.id(), area_cnt));
+        hdr.insert(&[1], &[1]);
+        assert_eq!(Some(vec![1].into()), hdr.get(&[1]));
+    });
+    assert!(MapxRaw::new_pinned(area_cnt).is_err());
+
+    let dir = format!("{}/area_partitioning", vsdb_get_custom_dir());
+    let _ = fs::remove_dir_all(&dir);
+    pnk!(fs::create_dir_all(&dir));
+    pnk!(check_layout(&dir, area_cnt));
+    pnk!(check_layout(&dir, area_cnt));
+    assert!(check_layout(&dir, 1 + area_cnt).is_err());
+}
+
+#[test]
+fn ttl() {
+    let hdr = MapxRaw::new_with_ttl();
+    assert!(hdr.has_ttl());
+    assert!(!MapxRaw::new().has_ttl());
+
+    hdr.insert(&[0], &[0]);
+    hdr.insert_with_ttl(&[1], &[1], Duration::from_millis(100));
+    hdr.insert_with_ttl(&[2], &[2], Duration::from_secs(3600));
+    // the expiration time is replaced
+    hdr.insert_with_ttl(&[3], &[3], Duration::from_millis(100));
+    hdr.insert(&[3], &[3]);
+    assert_eq!(4, hdr.len());
+
+    std::thread::sleep(Duration::from_millis(200));
+
+    // expired entries are invisible at once
+    assert!(hdr.get(&[1]).is_none());
+    assert_eq!(Some(vec![2].into()), hdr.get(&[2]));
+    assert_eq!(Some(vec![3].into()), hdr.get(&[3]));
+    assert_eq!(
+        vec![0, 2, 3],
+        hdr.iter().map(|(k, _)| k[0]).collect::<Vec<_>>()
+    );
+
+    // the TTL setting is recorded in the database
+    let reopened = MapxRaw::open(hdr.id()).unwrap();
+    assert!(reopened.has_ttl());
+    assert!(reopened.get(&[1]).is_none());
+
+    // and swept physically by flushing
+    vsdb_flush();
+    assert_eq!(3, hdr.len());
+
+    assert!(
+        catch_unwind(|| MapxRaw::new().insert_with_ttl(&[0], &[0], Duration::ZERO))
+            .is_err()
+    );
+}
+
+#[test]
+fn write_batch() {
+    let balances: Mapx<u8, u64> = Mapx::new();
+    let logs: Vecx<u64> = Vecx::new();
+    let states: MapxVs<u8, u64> = MapxVs::new();
+    pnk!(states.version_create(VersionName(b"v0")));
+    balances.insert(1, 100);
+    balances.insert(3, 100);
+
+    let mut batch = WriteBatch::new();
+    assert!(batch.is_empty());
+    batch.insert(&balances, &1, &90);
+    batch.insert(&balances, &2, &10);
+    batch.remove(&balances, &3);
+    batch.push(&logs, &10);
+    batch.push(&logs, &20);
+    pnk!(batch.update(&logs, 1, &21));
+    assert!(batch.update(&logs, 2, &0).is_err());
+    pnk!(batch.insert_vs(&states, &1, &90));
+    assert!(batch
+        .insert_vs_by_branch(&states, &1, &0, BranchName(b"none"))
+        .is_err());
+    assert_eq!(7, batch.len());
+    pnk!(batch.commit());
+
+    assert_eq!(Some(90), balances.get(&1));
+    assert_eq!(Some(10), balances.get(&2));
+    assert!(balances.get(&3).is_none());
+    assert_eq!(vec![10, 21], logs.iter().collect::<Vec<_>>());
+    assert_eq!(Some(90), states.get(&1));
+
+    // nothing is changed if any of the writes can not be done
+    let ao: Mapx<u8, u8> = Mapx::new_append_only();
+    ao.insert(0, 0);
+    let mut batch = WriteBatch::new();
+    batch.insert(&balances, &1, &0);
+    batch.insert(&ao, &0, &1);
+    assert!(batch.commit().is_err());
+    assert_eq!(Some(90), balances.get(&1));
+
+    pnk!(states.branch_create(BranchName(b"frozen")));
+    pnk!(states.version_create_by_branch(VersionName(b"v1"), BranchName(b"frozen")));
+    pnk!(states.branch_freeze(BranchName(b"frozen")));
+    let mut batch = WriteBatch::new();
+    batch.push(&logs, &30);
+    pnk!(batch.insert_vs_by_branch(&states, &1, &0, BranchName(b"frozen")));
+    assert!(batch.commit().is_err());
+    assert_eq!(2, logs.len());
+    assert_eq!(Some(90), states.get_by_branch(&1, BranchName(b"frozen")));
+
+    // a batch interrupted by a crash is finished by the recovery,
+    // whether or not a part of it has been applied
+    let mut batch = WriteBatch::new();
+    batch.insert(&balances, &1, &80);
+    batch.push(&logs, &30);
+    pnk!(batch.insert_vs(&states, &1, &80));
+    persist_only(&batch);
+    balances.insert(1, 80);
+
+    assert_eq!(1, pnk!(vsdb_recover_write_batches()));
+    assert_eq!(Some(80), balances.get(&1));
+    assert_eq!(vec![10, 21, 30], logs.iter().collect::<Vec<_>>());
+    assert_eq!(Some(80), states.get(&1));
+    assert_eq!(0, pnk!(vsdb_recover_write_batches()));
+}
+
+#[test]
+fn write_batch_in_databases() {
+    let a = pnk!(Vsdb::open(format!(
+        "{}/databases/write_batch_a",
+        vsdb_get_custom_dir()
+    )));
+    let b = pnk!(Vsdb::open(format!(
+        "{}/databases/write_batch_b",
+        vsdb_get_custom_dir()
+    )));
+    let ha: Mapx<u8, u8> = a.scope(Mapx::new);
+    let hb: Mapx<u8, u8> = b.scope(Mapx::new);
+
+    // each part of a batch is logged in the database of its targets
+    let mut batch = WriteBatch::new();
+    batch.insert(&ha, &1, &1);
+    batch.insert(&hb, &1, &1);
+    persist_only(&batch);
+
+    assert_eq!(1, pnk!(a.scope(vsdb_recover_write_batches)));
+    assert_eq!(Some(1), ha.get(&1));
+    assert!(hb.get(&1).is_none());
+    assert_eq!(1, pnk!(b.scope(vsdb_recover_write_batches)));
+    assert_eq!(Some(1), hb.get(&1));
+
+    // committed outside the scopes of both databases
+    let mut batch = WriteBatch::new();
+    batch.insert(&ha, &2, &2);
+    batch.insert(&hb, &2, &2);
+    pnk!(batch.commit());
+    assert_eq!(Some(2), ha.get(&2));
+    assert_eq!(Some(2), hb.get(&2));
+    assert_eq!(0, pnk!(a.scope(vsdb_recover_write_batches)));
+    assert_eq!(0, pnk!(b.scope(vsdb_recover_write_batches)));
+}
+
+#[test]
+fn tx() {
+    let m: Mapx<u8, u8> = Mapx::new();
+    let mvs: MapxVs<u8, u8> = MapxVs::new();
+    pnk!(mvs.version_create(VersionName(b"v0")));
+    pnk!(mvs.insert(0, 0));
+    m.insert(0, 0);
+
+    // nothing is written before `commit`
+    let mut tx = vsdb_begin_tx();
+    tx.insert(&m, &1, &1);
+    tx.remove(&m, &0);
+    pnk!(tx.insert_vs(&mvs, &1, &1));
+    pnk!(tx.remove_vs(&mvs, &0));
+    assert_eq!(Some(1), tx.get(&m, &1));
+    assert!(tx.get(&m, &0).is_none());
+    assert_eq!(Some(1), tx.get_vs(&mvs, &1));
+    assert!(tx.get_vs(&mvs, &0).is_none());
+    assert!(m.get(&1).is_none());
+    assert_eq!(Some(0), mvs.get(&0));
+    tx.rollback();
+    assert!(m.get(&1).is_none());
+    assert_eq!(Some(0), m.get(&0));
+    assert!(mvs.get(&1).is_none());
+
+    // changes on versioned collections go into a new version
+    let mut tx = vsdb_begin_tx();
+    tx.insert(&m, &1, &1);
+    pnk!(tx.insert_vs(&mvs, &1, &1));
+    pnk!(tx.remove_vs(&mvs, &0));
+    pnk!(tx.commit_as_version(VersionName(b"v1")));
+    assert_eq!(Some(1), m.get(&1));
+    assert!(mvs.version_exists(VersionName(b"v1")));
+    assert_eq!(Some(1), mvs.get(&1));
+    assert!(mvs.get(&0).is_none());
+    let v0 = mvs.get_by_branch_version(&0, BranchName(b"main"), VersionName(b"v0"));
+    assert_eq!(Some(0), v0);
+
+    // the new version is checked before anything is written
+    let mut tx = vsdb_begin_tx();
+    tx.insert(&m, &2, &2);
+    pnk!(tx.insert_vs(&mvs, &2, &2));
+    assert!(tx.commit_as_version(VersionName(b"v1")).is_err());
+    assert!(m.get(&2).is_none());
+    assert!(mvs.get(&2).is_none());
+}
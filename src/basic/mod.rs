@@ -104,6 +104,7 @@
 //!     .unwrap();
 //! ```
 
+pub mod foreign_key;
 pub mod mapx;
 pub mod mapx_ord;
 pub mod mapx_ord_rawkey;
@@ -79,7 +79,11 @@
 #[cfg(test)]
 mod test;
 
-use crate::{basic::mapx_ord_rawkey::MapxOrdRawKey, ValueEnDe};
+use crate::{
+    basic::{mapx_ord_rawkey::MapxOrdRawKey, mapx_raw::MapxRaw},
+    common::stats::SizeHistogram,
+    ValueEnDe,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
@@ -142,6 +146,21 @@ where
         let value = self.get_value();
         ValueMut { hdr: self, value }
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        self.inner.as_raw()
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
 }
 
 ////////////////////////////////////////////////////////////////////
@@ -30,8 +30,14 @@
 mod test;
 
 use crate::{
-    basic::mapx_ord_rawkey::{MapxOrdRawKey, MapxOrdRawKeyIter, ValueMut},
-    common::ende::ValueEnDe,
+    basic::{
+        mapx_ord_rawkey::{MapxOrdRawKey, MapxOrdRawKeyIter, ValueMut},
+        mapx_raw::MapxRaw,
+    },
+    common::{
+        compression::Compression, ende::ValueEnDe, named, stats::SizeHistogram,
+        VECX_HEAD_REGISTRY,
+    },
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
@@ -57,6 +63,90 @@ impl<T: ValueEnDe> Vecx<T> {
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    /// Create an append-only instance, that is, an audit log,
+    /// only `push` is allowed, all operations that change or remove
+    /// existing elements will panic.
+    #[inline(always)]
+    pub fn new_append_only() -> Self {
+        Vecx {
+            inner: MapxOrdRawKey::new_append_only(),
+        }
+    }
+
+    /// Create an instance in the area `area_idx`, usually a dedicated one,
+    /// see [vsdb_set_area_opts](crate::vsdb_set_area_opts).
+    #[inline(always)]
+    pub fn new_pinned(area_idx: usize) -> Result<Self> {
+        MapxOrdRawKey::new_pinned(area_idx)
+            .c(d!())
+            .map(|inner| Vecx { inner })
+    }
+
+    #[inline(always)]
+    pub fn is_append_only(&self) -> bool {
+        self.inner.is_append_only()
+    }
+
+    /// Create an instance whose elements are compressed by `c`.
+    #[cfg(feature = "compression")]
+    #[inline(always)]
+    pub fn new_compressed(c: Compression) -> Self {
+        Vecx {
+            inner: MapxOrdRawKey::new_compressed(c),
+        }
+    }
+
+    #[inline(always)]
+    pub fn compression(&self) -> Option<Compression> {
+        self.inner.compression()
+    }
+
+    /// The index of the first alive element,
+    /// it is always `0` unless `pop_front`/`drain_front` have been called.
+    #[inline(always)]
+    pub fn head(&self) -> usize {
+        self.head_get() as usize
+    }
+
+    // The head is kept in a global registry keyed by the ID of `inner`,
+    // instead of a field, so instances encoded by older releases can still
+    // be decoded, instances without a record have never been popped from the front.
+    #[inline(always)]
+    fn head_get(&self) -> u64 {
+        heads()
+            .get(&self.as_raw().id().to_be_bytes())
+            .map(|h| crate::parse_int!(h, u64))
+            .unwrap_or(0)
+    }
+
+    #[inline(always)]
+    fn head_set(&self, head: u64) {
+        let id = self.as_raw().id().to_be_bytes();
+        if 0 == head {
+            heads().remove(&id);
+        } else {
+            heads().insert(&id, &head.to_be_bytes());
+        }
+    }
+
+    // the index that the next pushed element will get
+    #[inline(always)]
+    pub(crate) fn end(&self) -> u64 {
+        self.head_get() + self.len() as u64
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        self.inner.as_raw()
+    }
+
     #[inline(always)]
     pub fn get(&self, idx: usize) -> Option<T> {
         self.inner.get(&(idx as u64).to_be_bytes())
@@ -73,11 +163,7 @@ impl<T: ValueEnDe> Vecx<T> {
     #[inline(always)]
     pub fn last(&self) -> Option<T> {
         alt!(self.is_empty(), return None);
-        Some(
-            self.inner
-                .get(&(self.len() as u64 - 1).to_be_bytes())
-                .unwrap(),
-        )
+        Some(self.inner.get(&(self.end() - 1).to_be_bytes()).unwrap())
     }
 
     #[inline(always)]
@@ -97,7 +183,7 @@ impl<T: ValueEnDe> Vecx<T> {
 
     #[inline(always)]
     pub fn push_ref(&self, v: &T) {
-        self.inner.insert_ref(&(self.len() as u64).to_be_bytes(), v);
+        self.inner.insert_ref(&self.end().to_be_bytes(), v);
     }
 
     #[inline(always)]
@@ -108,12 +194,14 @@ impl<T: ValueEnDe> Vecx<T> {
     #[inline(always)]
     pub fn insert_ref(&self, idx: usize, v: &T) {
         let idx = idx as u64;
-        match (self.len() as u64).cmp(&idx) {
+        let end = self.end();
+        if idx < self.head_get() {
+            panic!("out of index");
+        }
+        match end.cmp(&idx) {
             Ordering::Greater => {
                 self.inner
-                    .range_ref(
-                        &idx.to_be_bytes()[..]..&(self.len() as u64).to_be_bytes()[..],
-                    )
+                    .range_ref(&idx.to_be_bytes()[..]..&end.to_be_bytes()[..])
                     .for_each(|(i, iv)| {
                         self.inner.insert_ref(
                             &(crate::parse_int!(i, u64) + 1).to_be_bytes(),
@@ -134,14 +222,39 @@ impl<T: ValueEnDe> Vecx<T> {
     #[inline(always)]
     pub fn pop(&self) -> Option<T> {
         alt!(self.is_empty(), return None);
-        self.inner.remove(&(self.len() as u64 - 1).to_be_bytes())
+        self.inner.remove(&(self.end() - 1).to_be_bytes())
+    }
+
+    /// Remove the oldest element,
+    /// the indexes of the remaining elements will not be changed.
+    #[inline(always)]
+    pub fn pop_front(&self) -> Option<T> {
+        alt!(self.is_empty(), return None);
+        let head = self.head_get();
+        let ret = self.inner.remove(&head.to_be_bytes());
+        self.head_set(head + 1);
+        ret
+    }
+
+    /// Remove at most `n` oldest elements,
+    /// the indexes of the remaining elements will not be changed.
+    ///
+    /// Useful when using a `Vecx` as a compactable append-log.
+    pub fn drain_front(&self, n: usize) -> Vec<T> {
+        let n = n.min(self.len());
+        let head = self.head_get();
+        let ret = (head..head + n as u64)
+            .filter_map(|i| self.inner.remove(&i.to_be_bytes()))
+            .collect();
+        self.head_set(head + n as u64);
+        ret
     }
 
     #[inline(always)]
     pub fn remove(&self, idx: usize) -> T {
         let idx = idx as u64;
-        if !self.is_empty() && idx < self.len() as u64 {
-            let last_idx = self.len() as u64 - 1;
+        if !self.is_empty() && self.head_get() <= idx && idx < self.end() {
+            let last_idx = self.end() - 1;
             let ret = self.inner.remove(&idx.to_be_bytes()).unwrap();
             self.inner
                 .range_ref(&(1 + idx).to_be_bytes()[..]..)
@@ -158,8 +271,8 @@ impl<T: ValueEnDe> Vecx<T> {
     #[inline(always)]
     pub fn swap_remove(&self, idx: usize) -> T {
         let idx = idx as u64;
-        if !self.is_empty() && idx < self.len() as u64 {
-            let last_idx = self.len() as u64 - 1;
+        if !self.is_empty() && self.head_get() <= idx && idx < self.end() {
+            let last_idx = self.end() - 1;
             let ret = self.inner.remove(&idx.to_be_bytes()).unwrap();
             if let Some(v) = self.inner.remove(&last_idx.to_be_bytes()) {
                 self.inner.insert_ref(&idx.to_be_bytes(), &v);
@@ -175,7 +288,7 @@ impl<T: ValueEnDe> Vecx<T> {
 
     #[inline(always)]
     pub fn update_ref(&self, idx: usize, v: &T) -> Option<T> {
-        if idx < self.len() {
+        if self.head() <= idx && idx < self.end() as usize {
             return self.inner.insert_ref(&(idx as u64).to_be_bytes(), v);
         }
         panic!("out of index");
@@ -191,7 +304,24 @@ impl<T: ValueEnDe> Vecx<T> {
     #[inline(always)]
     pub fn clear(&self) {
         self.inner.clear();
+        self.head_set(0);
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+        heads().flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+}
+
+#[inline(always)]
+fn heads() -> MapxRaw {
+    MapxRaw::open_reserved(VECX_HEAD_REGISTRY)
 }
 
 pub struct VecxIter<T: ValueEnDe> {
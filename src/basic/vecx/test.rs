@@ -85,6 +85,95 @@ fn write() {
     assert_eq!(3, hdr.get(1).unwrap());
 }
 
+#[test]
+fn pop_front() {
+    let hdr = Vecx::new();
+
+    (0..10).for_each(|i| hdr.push(i));
+    assert_eq!(0, hdr.head());
+
+    assert_eq!(Some(0), hdr.pop_front());
+    assert_eq!(1, hdr.head());
+    assert_eq!(9, hdr.len());
+    assert!(hdr.get(0).is_none());
+    assert_eq!(1, hdr.get(1).unwrap());
+
+    assert_eq!(vec![1, 2, 3], hdr.drain_front(3));
+    assert_eq!(4, hdr.head());
+    assert_eq!(6, hdr.len());
+    assert_eq!(4, hdr.get(4).unwrap());
+    assert_eq!(9, hdr.last().unwrap());
+
+    // indexes of the new elements follow the old ones
+    hdr.push(10);
+    assert_eq!(10, hdr.get(10).unwrap());
+    assert_eq!(7, hdr.len());
+
+    hdr.update(5, 50);
+    assert_eq!(50, hdr.get(5).unwrap());
+    assert_eq!(50, hdr.remove(5));
+    assert_eq!(6, hdr.get(5).unwrap());
+    assert_eq!(10, hdr.pop().unwrap());
+
+    assert_eq!(vec![4, 6, 7, 8, 9], hdr.drain_front(100));
+    assert!(hdr.is_empty());
+    assert_eq!(9, hdr.head());
+    assert!(hdr.pop_front().is_none());
+
+    hdr.push(9);
+    assert_eq!(9, hdr.get(9).unwrap());
+
+    hdr.clear();
+    assert_eq!(0, hdr.head());
+}
+
+// instances encoded by older releases, without the head
+#[test]
+fn old_layout() {
+    use crate::basic::mapx_ord_rawkey::MapxOrdRawKey;
+
+    #[derive(Serialize, Deserialize)]
+    struct Old {
+        inner: MapxOrdRawKey<u32>,
+    }
+
+    let old = Old {
+        inner: MapxOrdRawKey::new(),
+    };
+    (0..3u32).for_each(|i| {
+        old.inner.insert_ref(&(i as u64).to_be_bytes(), &i);
+    });
+    let bytes = <Old as ValueEnDe>::encode(&old);
+
+    let hdr = pnk!(<Vecx<u32> as ValueEnDe>::decode(&bytes));
+    assert_eq!(0, hdr.head());
+    assert_eq!(3, hdr.len());
+    assert_eq!(0, hdr.get(0).unwrap());
+    assert_eq!(vec![0, 1, 2], hdr.iter().collect::<Vec<_>>());
+    hdr.push(3);
+    assert_eq!(3, hdr.get(3).unwrap());
+
+    // the head is seen by all handles of the instance
+    assert_eq!(Some(0), hdr.pop_front());
+    let hdr = pnk!(<Vecx<u32> as ValueEnDe>::decode(&bytes));
+    assert_eq!(1, hdr.head());
+    assert_eq!(3, hdr.last().unwrap());
+
+    // the layout is unchanged
+    let old = pnk!(<Old as ValueEnDe>::decode(&hdr.encode()));
+    assert_eq!(3, old.inner.len());
+}
+
+#[test]
+#[should_panic]
+fn pop_front_out_of_index() {
+    let hdr = Vecx::new();
+    hdr.push(0);
+    hdr.push(1);
+    hdr.pop_front();
+    hdr.update_ref(0, &0);
+}
+
 #[test]
 #[should_panic]
 fn write_out_of_index_0() {
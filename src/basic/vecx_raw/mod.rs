@@ -31,7 +31,7 @@ mod test;
 
 use crate::{
     basic::mapx_ord_rawvalue::{MapxOrdRawValue, MapxOrdRawValueIter, ValueMut},
-    common::RawValue,
+    common::{named, stats::SizeHistogram, RawValue},
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
@@ -57,6 +57,13 @@ impl VecxRaw {
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
     #[inline(always)]
     pub fn get(&self, idx: usize) -> Option<RawValue> {
         self.inner.get(&(idx as u64))
@@ -180,6 +187,16 @@ impl VecxRaw {
     pub fn clear(&self) {
         self.inner.clear();
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
 }
 
 pub struct VecxRawIter {
@@ -0,0 +1,57 @@
+//!
+//! Write-once collections.
+//!
+//! Existing keys of an append-only collection can not be overwritten or
+//! removed, the check is done at the storage layer, so it can not be
+//! bypassed by any API of the collection.
+//!
+//! The `try_*` methods return an [AppendOnlyViolation](self::AppendOnlyViolation)
+//! on a violation, the plain ones panic.
+//!
+//! The check of a key and the write of it are done under the same lock,
+//! so only one of the concurrent writers of an absent key can succeed.
+//!
+
+use crate::common::PrefixBytes;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    collections::hash_map::DefaultHasher,
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+// keys are spread over a fixed number of locks
+const LOCK_STRIPES: usize = 64;
+
+static LOCKS: Lazy<Vec<Mutex<()>>> =
+    Lazy::new(|| (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect());
+
+// Held from checking a key of an append-only instance to writing it.
+#[inline(always)]
+pub(crate) fn lock(instance_prefix: PrefixBytes, key: &[u8]) -> MutexGuard<'static, ()> {
+    let mut h = DefaultHasher::new();
+    instance_prefix.hash(&mut h);
+    key.hash(&mut h);
+    LOCKS[h.finish() as usize % LOCK_STRIPES].lock()
+}
+
+/// Returned when trying to change an existing key of an append-only collection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppendOnlyViolation {
+    /// The raw bytes of the key.
+    pub key: Vec<u8>,
+}
+
+impl fmt::Display for AppendOnlyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "append-only collection: can not overwrite or remove the existing key {:?}",
+            self.key
+        )
+    }
+}
+
+impl Error for AppendOnlyViolation {}
@@ -0,0 +1,139 @@
+//!
+//! Zero-copy values by rkyv.
+//!
+//! `Rkyv<T>` holds the archived bytes of `T` instead of `T` itself,
+//! so reading it from a collection, e.g. `Mapx<u64, Rkyv<Block>>`,
+//! only validates the bytes got from the engine, nothing is deserialized,
+//! fields are accessed through the archived view(`&T::Archived`) directly.
+//! Call `unarchive` when an owned `T` is needed.
+//!
+//! It works along with any codec, the archived bytes are stored as a byte
+//! string of the selected codec.
+//!
+
+use crate::common::ende::SimpleVisitor;
+use rkyv::{
+    bytecheck::CheckBytes, ser::serializers::AllocSerializer,
+    validation::validators::DefaultValidator, AlignedVec, Archive, Infallible,
+};
+use ruc::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, marker::PhantomData, ops::Deref};
+
+/// The archived bytes of a `T`, dereferenced to `T::Archived`.
+pub struct Rkyv<T> {
+    // always validated as an archived `T`
+    bytes: AlignedVec,
+    p: PhantomData<T>,
+}
+
+impl<T> Rkyv<T>
+where
+    T: Archive + rkyv::Serialize<AllocSerializer<256>>,
+{
+    #[inline(always)]
+    pub fn new(v: &T) -> Self {
+        Rkyv {
+            bytes: pnk!(rkyv::to_bytes::<_, 256>(v).c(d!())),
+            p: PhantomData,
+        }
+    }
+}
+
+impl<T: Archive> Rkyv<T> {
+    /// The zero-copy view of the value.
+    #[inline(always)]
+    pub fn archived(&self) -> &T::Archived {
+        // the bytes are produced by `new` or validated in deserializing
+        unsafe { rkyv::archived_root::<T>(&self.bytes) }
+    }
+
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<T> Rkyv<T>
+where
+    T: Archive,
+    T::Archived: rkyv::Deserialize<T, Infallible>,
+{
+    /// Get an owned `T` by a full deserialization.
+    #[inline(always)]
+    pub fn unarchive(&self) -> T {
+        // the error type of `Infallible` has no values
+        rkyv::Deserialize::deserialize(self.archived(), &mut Infallible).unwrap()
+    }
+}
+
+impl<T: Archive> Deref for Rkyv<T> {
+    type Target = T::Archived;
+
+    #[inline(always)]
+    fn deref(&self) -> &T::Archived {
+        self.archived()
+    }
+}
+
+impl<T> Clone for Rkyv<T> {
+    fn clone(&self) -> Self {
+        let mut bytes = AlignedVec::with_capacity(self.bytes.len());
+        bytes.extend_from_slice(&self.bytes);
+        Rkyv {
+            bytes,
+            p: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Rkyv<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes[..] == other.bytes[..]
+    }
+}
+
+impl<T> Eq for Rkyv<T> {}
+
+impl<T: Archive> fmt::Debug for Rkyv<T>
+where
+    T::Archived: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.archived().fmt(f)
+    }
+}
+
+impl<T> Serialize for Rkyv<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.bytes)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Rkyv<T>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = deserializer.deserialize_bytes(SimpleVisitor)?;
+
+        // archived values must be aligned
+        let mut bytes = AlignedVec::with_capacity(raw.len());
+        bytes.extend_from_slice(&raw);
+
+        rkyv::check_archived_root::<T>(&bytes)
+            .map_err(|_| de::Error::custom("invalid archived value"))?;
+
+        Ok(Rkyv {
+            bytes,
+            p: PhantomData,
+        })
+    }
+}
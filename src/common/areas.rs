@@ -0,0 +1,124 @@
+//!
+//! Partitioning of instances into areas.
+//!
+//! Each instance lives in one area of the engine(a tree of sled, a column
+//! family of rocksdb, etc.), which is decided by its prefix. The number of
+//! areas and how prefixes are mapped to them can be configured by
+//! [vsdb_set_area_opts](self::vsdb_set_area_opts):
+//!
+//! - `RoundRobin`, the default, `prefix % count`, new instances
+//!   are spread over areas in turn
+//! - `Hash`, by a stable hash of the prefix, useful when prefixes are
+//!   not allocated in sequence(e.g. derived from namespaces)
+//!
+//! The last `dedicated` areas are never assigned to new instances
+//! automatically, they are only used by instances created by `new_pinned`,
+//! so hot instances can be isolated into their own trees.
+//!
+//! NOTE: the layout is recorded in the data directory when the database is
+//! created, opening it with a different layout fails.
+//!
+
+use crate::common::{config, namespace, replica, Prefix};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::{fs, path::Path};
+
+// the layout of areas, written in the data directory
+const LAYOUT_FILE: &str = "AREAS";
+
+static AREA_OPTS: Lazy<Mutex<AreaOpts>> = Lazy::new(|| Mutex::new(AreaOpts::default()));
+
+/// How prefixes of instances are mapped to areas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AreaStrategy {
+    #[default]
+    RoundRobin,
+    Hash,
+}
+
+/// Options of the area partitioning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AreaOpts {
+    /// The number of areas, `None` means the default one of the engine.
+    pub count: Option<usize>,
+    pub strategy: AreaStrategy,
+    /// How many areas(at the end) are reserved for pinned instances.
+    pub dedicated: usize,
+}
+
+/// Set the area partitioning, it can only be called before VSDB is initialized,
+/// and should be the same as the one used when the database was created.
+pub fn vsdb_set_area_opts(opts: AreaOpts) -> Result<()> {
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+
+    match opts.count {
+        Some(0) => return Err(eg!("the number of areas should NOT be zero")),
+        Some(n) if opts.dedicated >= n => {
+            return Err(eg!("no area is left for unpinned instances"));
+        }
+        _ => {}
+    }
+
+    *AREA_OPTS.lock() = opts;
+
+    Ok(())
+}
+
+/// Get the current area partitioning.
+#[inline(always)]
+pub fn vsdb_get_area_opts() -> AreaOpts {
+    *AREA_OPTS.lock()
+}
+
+// Used by engines when creating their areas.
+#[inline(always)]
+pub(crate) fn area_count(default: usize) -> usize {
+    AREA_OPTS.lock().count.unwrap_or(default)
+}
+
+// The area holding the instance of `prefix`,
+// it must never change for an existing instance.
+#[inline(always)]
+pub(crate) fn area_of(prefix: Prefix, area_count: usize) -> usize {
+    let n = area_count as Prefix;
+    // NOTE: this is NOT equal to `prefix as usize % area_count`,
+    // the MAX value of `usize` can be less than Prefix::MAX(u64::MAX)
+    match AREA_OPTS.lock().strategy {
+        AreaStrategy::RoundRobin => (prefix % n) as usize,
+        AreaStrategy::Hash => (namespace::fnv1a(&prefix.to_be_bytes()) % n) as usize,
+    }
+}
+
+// Whether new instances can be placed in the area automatically.
+#[inline(always)]
+pub(crate) fn is_shared(area_idx: usize, area_count: usize) -> bool {
+    area_idx + AREA_OPTS.lock().dedicated < area_count
+}
+
+// Record the layout in a new database, or check it against the recorded one.
+pub(crate) fn check_layout(dir: &str, area_count: usize) -> Result<()> {
+    let opts = vsdb_get_area_opts();
+    if opts.dedicated >= area_count {
+        return Err(eg!(format!(
+            "{} dedicated areas are set, but there are only {} areas",
+            opts.dedicated, area_count
+        )));
+    }
+
+    let layout = format!("{} {:?} {}", area_count, opts.strategy, opts.dedicated);
+
+    let path = Path::new(dir).join(LAYOUT_FILE);
+    match fs::read_to_string(&path) {
+        Ok(l) if l != layout => Err(eg!(format!(
+            "the layout of areas has been changed, recorded: `{}`, current: `{}`",
+            l, layout
+        ))),
+        Ok(_) => Ok(()),
+        Err(_) if replica::vsdb_is_replica() => Ok(()),
+        Err(_) => fs::write(&path, &layout).c(d!()),
+    }
+}
@@ -0,0 +1,88 @@
+//!
+//! Borsh values.
+//!
+//! `Borsh<T>` wraps any type implementing `BorshSerialize` and
+//! `BorshDeserialize`, it implements the serde traits by the borsh encoding
+//! of `T`, so it gets `ValueEnDe`(and `KeyEnDe`) and can be stored in all
+//! collections, e.g. `Mapx<u64, Borsh<Account>>`, no serde derives are needed
+//! on `T`, and the stored payload is exactly what `BorshSerialize` produces.
+//!
+//! It works along with any codec, the borsh bytes are stored as a byte
+//! string of the selected codec.
+//!
+
+use crate::common::ende::SimpleVisitor;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// A value stored by its borsh encoding.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Borsh<T>(pub T);
+
+impl<T> Borsh<T> {
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Borsh<T> {
+    #[inline(always)]
+    fn from(v: T) -> Self {
+        Borsh(v)
+    }
+}
+
+impl<T> Deref for Borsh<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Borsh<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Borsh<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: BorshSerialize> Serialize for Borsh<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0
+            .try_to_vec()
+            .map_err(serde::ser::Error::custom)
+            .and_then(|bytes| serializer.serialize_bytes(&bytes))
+    }
+}
+
+impl<'de, T: BorshDeserialize> Deserialize<'de> for Borsh<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // trailing bytes are rejected by `try_from_slice`
+        deserializer
+            .deserialize_bytes(SimpleVisitor)
+            .and_then(|bytes| {
+                T::try_from_slice(&bytes)
+                    .map(Borsh)
+                    .map_err(de::Error::custom)
+            })
+    }
+}
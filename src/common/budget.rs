@@ -0,0 +1,199 @@
+//!
+//! A global memory budget for iteration buffers.
+//!
+//! Iterators wrapped by [BudgetedIter](self::BudgetedIter) read items in chunks,
+//! the size of every alive chunk is charged to the global budget,
+//! and will be released when the chunk is dropped.
+//!
+
+use std::{
+    error::Error,
+    fmt,
+    iter::Peekable,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+// `0` means unlimited
+static BUDGET: AtomicUsize = AtomicUsize::new(0);
+static USED: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the global memory budget(in bytes) of all iteration chunks,
+/// `0` means unlimited, this is the default.
+#[inline(always)]
+pub fn vsdb_set_iter_memory_budget(bytes: usize) {
+    BUDGET.store(bytes, Ordering::Relaxed);
+}
+
+/// Get the global memory budget of all iteration chunks.
+#[inline(always)]
+pub fn vsdb_get_iter_memory_budget() -> usize {
+    BUDGET.load(Ordering::Relaxed)
+}
+
+/// How many bytes are held by all alive iteration chunks.
+#[inline(always)]
+pub fn vsdb_get_iter_memory_usage() -> usize {
+    USED.load(Ordering::Relaxed)
+}
+
+fn try_charge(n: usize) -> Result<(), IterBudgetExceeded> {
+    USED.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+        let budget = BUDGET.load(Ordering::Relaxed);
+        if 0 == budget || used + n <= budget {
+            Some(used + n)
+        } else {
+            None
+        }
+    })
+    .map(|_| ())
+    .map_err(|used| IterBudgetExceeded {
+        requested: n,
+        available: BUDGET.load(Ordering::Relaxed).saturating_sub(used),
+    })
+}
+
+/// Returned when even a single item can not fit in the remaining budget,
+/// the caller should retry later, after some chunks have been dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IterBudgetExceeded {
+    /// Size of the next item.
+    pub requested: usize,
+    /// Remaining bytes of the global budget.
+    pub available: usize,
+}
+
+impl fmt::Display for IterBudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "iteration memory budget exceeded: requested {} bytes, {} available",
+            self.requested, self.available
+        )
+    }
+}
+
+impl Error for IterBudgetExceeded {}
+
+/// Items read by one `next_chunk`, they are charged to
+/// both the global budget and the iterator until dropped.
+pub struct IterChunk<T> {
+    items: Vec<T>,
+    charged: usize,
+    owner: Arc<AtomicUsize>,
+}
+
+impl<T> IterChunk<T> {
+    /// How many bytes are charged for this chunk.
+    #[inline(always)]
+    pub fn charged(&self) -> usize {
+        self.charged
+    }
+}
+
+impl<T> Deref for IterChunk<T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+impl<T> Drop for IterChunk<T> {
+    fn drop(&mut self) {
+        USED.fetch_sub(self.charged, Ordering::AcqRel);
+        self.owner.fetch_sub(self.charged, Ordering::AcqRel);
+    }
+}
+
+/// An iterator wrapper that reads items in chunks under the global budget.
+pub struct BudgetedIter<I: Iterator, F> {
+    inner: Peekable<I>,
+    size_of: F,
+    chunk_size: usize,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<I, F> BudgetedIter<I, F>
+where
+    I: Iterator,
+    F: Fn(&I::Item) -> usize,
+{
+    /// Every chunk will hold items of `chunk_size` bytes at most,
+    /// sizes of items are measured by `size_of`.
+    #[inline(always)]
+    pub fn new(iter: I, chunk_size: usize, size_of: F) -> Self {
+        Self {
+            inner: iter.peekable(),
+            size_of,
+            chunk_size,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Read the next chunk, `None` means the end of the iteration.
+    ///
+    /// The chunk will be smaller than `chunk_size`
+    /// if the remaining budget is not enough,
+    /// and an error will be returned if even the next item can not fit in it.
+    ///
+    /// NOTE:
+    /// - A chunk contains at least one item,
+    ///   even if the item itself is bigger than `chunk_size`
+    pub fn next_chunk(
+        &mut self,
+    ) -> Option<Result<IterChunk<I::Item>, IterBudgetExceeded>> {
+        let mut items = vec![];
+        let mut charged = 0;
+
+        while let Some(item) = self.inner.peek() {
+            let n = (self.size_of)(item);
+            if !items.is_empty() && charged + n > self.chunk_size {
+                break;
+            }
+            if let Err(e) = try_charge(n) {
+                if items.is_empty() {
+                    return Some(Err(e));
+                }
+                break;
+            }
+            charged += n;
+            items.push(self.inner.next().unwrap());
+        }
+
+        if items.is_empty() {
+            return None;
+        }
+
+        self.in_flight.fetch_add(charged, Ordering::AcqRel);
+
+        Some(Ok(IterChunk {
+            items,
+            charged,
+            owner: Arc::clone(&self.in_flight),
+        }))
+    }
+
+    /// How many bytes are held by the alive chunks of this iterator.
+    #[inline(always)]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+impl<I, K, V> BudgetedIter<I, fn(&(K, V)) -> usize>
+where
+    I: Iterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    /// For iterators of raw KVs, sizes of items are the length of their bytes.
+    #[inline(always)]
+    pub fn new_raw(iter: I, chunk_size: usize) -> Self {
+        Self::new(iter, chunk_size, |(k, v)| {
+            k.as_ref().len() + v.as_ref().len()
+        })
+    }
+}
@@ -0,0 +1,74 @@
+//!
+//! Per-instance value compression.
+//!
+//! An instance created by `new_compressed` compresses all its values in the
+//! insert path and decompresses them in the get/iterate path, keys are
+//! stored as they are, so the order of keys is not affected.
+//!
+//! The algorithm of each instance is recorded in the database, instances
+//! reopened by their IDs or deserialized from metadata keep using it.
+//!
+//! NOTE: compressed instances can only be accessed
+//! with the `compression` feature enabled.
+//!
+
+use crate::common::RawValue;
+use ruc::*;
+use serde::{Deserialize, Serialize};
+
+const ALGO_ZSTD: u8 = 1;
+
+/// Compression algorithms of values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    /// `level` ranges from 1 to 22, `0` means the default level(3).
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    // [ algorithm ] + [ parameters ]
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Compression::Zstd { level } => {
+                let mut ret = vec![ALGO_ZSTD];
+                ret.extend_from_slice(&level.to_be_bytes());
+                ret
+            }
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&ALGO_ZSTD, level)) if 4 == level.len() => Ok(Compression::Zstd {
+                level: crate::parse_int!(level, i32),
+            }),
+            _ => Err(eg!("unknown compression")),
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    pub(crate) fn compress(self, value: &[u8]) -> RawValue {
+        match self {
+            Compression::Zstd { level } => {
+                pnk!(zstd::encode_all(value, level)).into_boxed_slice()
+            }
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    pub(crate) fn decompress(self, value: &[u8]) -> RawValue {
+        match self {
+            Compression::Zstd { .. } => pnk!(zstd::decode_all(value)).into_boxed_slice(),
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    pub(crate) fn compress(self, _: &[u8]) -> RawValue {
+        panic!("the `compression` feature is not enabled")
+    }
+
+    #[cfg(not(feature = "compression"))]
+    pub(crate) fn decompress(self, _: &[u8]) -> RawValue {
+        panic!("the `compression` feature is not enabled")
+    }
+}
@@ -0,0 +1,187 @@
+//!
+//! Runtime configuration.
+//!
+//! All parameters can be adjusted by [vsdb_reconfigure](self::vsdb_reconfigure)
+//! at any time, the changes take effect without restarting the process.
+//!
+//! - `cache_size`, in bytes, `0` means the default size of the engine
+//!     - rocksdb: the capacity of the block cache is changed in place
+//!     - sled: it can only be changed before the database is opened
+//! - `flush_interval`, a background thread flushes all data periodically,
+//!   `Duration::ZERO` means never, this is the default
+//! - `slow_op_threshold`, reads, writes and flushes that take longer than it
+//!   are reported to the installed [SlowOpHook](self::SlowOpHook),
+//!   `Duration::ZERO` means never, this is the default
+//!
+
+use crate::common::{vsdb_flush, VSDB};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use ruc::*;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+static CACHE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+// in nanoseconds, `0` means disabled
+static FLUSH_INTERVAL: AtomicU64 = AtomicU64::new(0);
+static SLOW_OP_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+// set when the engine has been opened
+static DB_OPENED: AtomicBool = AtomicBool::new(false);
+
+static SLOW_OP_HOOK: Lazy<RwLock<Option<Arc<dyn SlowOpHook>>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// A snapshot of all runtime parameters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VsdbConfig {
+    pub cache_size: usize,
+    pub flush_interval: Duration,
+    pub slow_op_threshold: Duration,
+}
+
+/// Parameters to be changed, `None` fields are kept as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PartialConfig {
+    pub cache_size: Option<usize>,
+    pub flush_interval: Option<Duration>,
+    pub slow_op_threshold: Option<Duration>,
+}
+
+/// Get the current runtime parameters.
+#[inline(always)]
+pub fn vsdb_get_config() -> VsdbConfig {
+    VsdbConfig {
+        cache_size: CACHE_SIZE.load(Ordering::Relaxed),
+        flush_interval: Duration::from_nanos(FLUSH_INTERVAL.load(Ordering::Relaxed)),
+        slow_op_threshold: Duration::from_nanos(
+            SLOW_OP_THRESHOLD.load(Ordering::Relaxed),
+        ),
+    }
+}
+
+/// Adjust runtime parameters,
+/// nothing will be changed if the new cache size can not be applied.
+pub fn vsdb_reconfigure(cfg: PartialConfig) -> Result<()> {
+    if let Some(n) = cfg.cache_size {
+        if db_opened() {
+            VSDB.db.set_cache_size(n).c(d!())?;
+        }
+        CACHE_SIZE.store(n, Ordering::Relaxed);
+    }
+
+    if let Some(d) = cfg.slow_op_threshold {
+        SLOW_OP_THRESHOLD.store(d.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    if let Some(d) = cfg.flush_interval {
+        FLUSH_INTERVAL.store(d.as_nanos() as u64, Ordering::Relaxed);
+        FLUSHER.unpark();
+    }
+
+    Ok(())
+}
+
+// The configured cache size, read by engines when opening.
+#[inline(always)]
+pub(crate) fn cache_size() -> usize {
+    CACHE_SIZE.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub(crate) fn db_opened() -> bool {
+    DB_OPENED.load(Ordering::Acquire)
+}
+
+#[inline(always)]
+pub(crate) fn mark_db_opened() {
+    DB_OPENED.store(true, Ordering::Release);
+}
+
+// Sleep for the configured interval and flush,
+// it is parked when auto-flush is disabled,
+// and waked up by `vsdb_reconfigure` on any change of the interval.
+static FLUSHER: Lazy<Thread> = Lazy::new(|| {
+    thread::spawn(|| {
+        let mut last_flush = Instant::now();
+        loop {
+            let interval = vsdb_get_config().flush_interval;
+            if interval.is_zero() {
+                thread::park();
+                last_flush = Instant::now();
+                continue;
+            }
+            let elapsed = last_flush.elapsed();
+            if elapsed < interval {
+                thread::park_timeout(interval - elapsed);
+                continue;
+            }
+            vsdb_flush();
+            last_flush = Instant::now();
+        }
+    })
+    .thread()
+    .clone()
+});
+
+/// A read, write or flush that takes longer than the threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlowOp {
+    /// `get`, `insert`, `remove` or `flush`.
+    pub op: &'static str,
+    pub elapsed: Duration,
+}
+
+/// Receive slow operations.
+pub trait SlowOpHook: Send + Sync {
+    /// Called in the thread of the operation, after it finished,
+    /// time-consuming work should be sent to other threads.
+    fn on_slow_op(&self, op: SlowOp);
+}
+
+impl<F> SlowOpHook for F
+where
+    F: Fn(SlowOp) + Send + Sync,
+{
+    fn on_slow_op(&self, op: SlowOp) {
+        self(op)
+    }
+}
+
+/// Install a global slow-op hook, `None` means removing the current one.
+pub fn vsdb_set_slow_op_hook(hook: Option<Arc<dyn SlowOpHook>>) {
+    *SLOW_OP_HOOK.write() = hook;
+}
+
+// `None` if slow ops are not tracked,
+// so no time is read in the default configuration.
+#[inline(always)]
+pub(crate) fn op_start() -> Option<Instant> {
+    alt!(
+        0 == SLOW_OP_THRESHOLD.load(Ordering::Relaxed),
+        None,
+        Some(Instant::now())
+    )
+}
+
+#[inline(always)]
+pub(crate) fn op_finish(op: &'static str, start: Option<Instant>) {
+    if let Some(start) = start {
+        let elapsed = start.elapsed();
+        let threshold = SLOW_OP_THRESHOLD.load(Ordering::Relaxed);
+        if 0 < threshold && elapsed.as_nanos() as u64 >= threshold {
+            // clone the hook out, so the lock will not be held when it is running
+            let hook = SLOW_OP_HOOK.read().clone();
+            if let Some(hook) = hook {
+                hook.on_slow_op(SlowOp { op, elapsed });
+            }
+        }
+    }
+}
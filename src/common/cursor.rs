@@ -0,0 +1,69 @@
+//!
+//! Resume points of paginated iterations.
+//!
+//! A [Cursor](self::Cursor) is returned along with every page of `iter_page`,
+//! pass it back to get the next page, iterations are never restarted from
+//! the beginning. It can be serialized or converted to a string, so it can
+//! be handed to the clients of web APIs, its content should be treated
+//! as opaque by them.
+//!
+
+use crate::common::RawKey;
+use ruc::*;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Where the next page starts, that is, right after the last key of a page.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cursor {
+    // the encoded last key of the previous page
+    key: Vec<u8>,
+}
+
+impl Cursor {
+    #[inline(always)]
+    pub(crate) fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.key.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = Box<dyn RucError>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if 0 != s.len() % 2 {
+            return Err(eg!("invalid cursor"));
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| {
+                s.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    .c(d!("invalid cursor"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|key| Cursor { key })
+    }
+}
+
+// Take at most `limit`(at least 1) items, one more item is read to know
+// whether there are remaining ones, so the last page is never empty.
+pub(crate) fn page<T>(
+    iter: impl Iterator<Item = (RawKey, T)>,
+    limit: usize,
+) -> (Vec<(RawKey, T)>, Option<Cursor>) {
+    let limit = limit.max(1);
+    let mut items = iter.take(limit.saturating_add(1)).collect::<Vec<_>>();
+    if items.len() <= limit {
+        return (items, None);
+    }
+    items.pop();
+    let cursor = items.last().map(|(k, _)| Cursor { key: k.to_vec() });
+    (items, cursor)
+}
@@ -0,0 +1,181 @@
+//!
+//! Independent databases in one process.
+//!
+//! The global database lives in ${VSDB_BASE_DIR}, more databases can be
+//! opened by [Vsdb::open](self::Vsdb::open), each of them has its own data
+//! directory and its own allocators of prefixes, branch IDs and version IDs.
+//!
+//! An instance belongs to the database that is current when it is created
+//! or decoded, that is the global one unless within a
+//! [Vsdb::scope](self::Vsdb::scope), and it keeps using that database
+//! wherever it is used later, on any thread. Instances nested in another
+//! one, e.g. the values of a `Mapx`, always belong to the database of it.
+//!
+
+use crate::common::{
+    replica, stats::StorageStats, ttl, vsdb_flush, vsdb_get_base_dir, Engine,
+    EngineImpl, VsDB, VSDB,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::{cell::Cell, collections::HashMap, fmt, fs, path::Path, ptr};
+
+pub(crate) type Db = &'static VsDB<EngineImpl>;
+
+// every directory is opened only once,
+// databases are never closed before the process exits
+static OPENED: Lazy<Mutex<HashMap<String, Db>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+thread_local! {
+    static CURRENT: Cell<Option<Db>> = Cell::new(None);
+}
+
+/// The handle of an opened database.
+///
+/// ```
+/// use vsdb::{vsdb_get_custom_dir, Mapx, Vsdb};
+///
+/// let tenant = Vsdb::open(format!("{}/tenant_a", vsdb_get_custom_dir())).unwrap();
+///
+/// let m = tenant.scope(|| {
+///     let m: Mapx<u8, u8> = Mapx::new();
+///     m.insert(1, 1);
+///     m
+/// });
+/// tenant.scope(|| assert_eq!(Some(1), m.get(&1)));
+/// ```
+#[derive(Clone)]
+pub struct Vsdb {
+    db: Db,
+    dir: String,
+}
+
+impl Vsdb {
+    /// Open a database in `dir`, create it if not exists,
+    /// the same database is returned if `dir` has been opened.
+    ///
+    /// NOTE:
+    /// - Not available in the replica mode
+    /// - `dir` should not be used as ${VSDB_BASE_DIR} later
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        if replica::vsdb_is_replica() {
+            return Err(eg!("only the global database is available to a replica"));
+        }
+
+        fs::create_dir_all(dir.as_ref()).c(d!())?;
+        let dir = canonicalize(dir).c(d!())?;
+
+        let global = Self::global();
+        if canonicalize(&global.dir).c(d!())? == dir {
+            return Ok(global);
+        }
+
+        let mut opened = OPENED.lock();
+        let db = if let Some(db) = opened.get(&dir) {
+            *db
+        } else {
+            let db: Db = Box::leak(Box::new(VsDB::new_at(&dir).c(d!())?));
+            opened.insert(dir.clone(), db);
+            db
+        };
+
+        Ok(Self { db, dir })
+    }
+
+    /// The global database in ${VSDB_BASE_DIR}, it will be initialized
+    /// if not yet, so ${VSDB_BASE_DIR} can not be changed after this.
+    pub fn global() -> Self {
+        Self {
+            db: Lazy::force(&VSDB),
+            dir: vsdb_get_base_dir(),
+        }
+    }
+
+    /// Run `f` with this database as the current one of the thread,
+    /// all instances created or decoded by `f` will belong to it.
+    ///
+    /// Nested calls shadow the outer database until they return.
+    #[inline(always)]
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        scoped(self.db, f)
+    }
+
+    /// Flush data of this database to disk, may take a long time.
+    pub fn flush(&self) {
+        if self.is_global() {
+            vsdb_flush();
+        } else {
+            ttl::sweep(&self.db.db);
+            self.db.db.flush();
+        }
+    }
+
+    /// Storage statistics of this database reported by the engine.
+    #[inline(always)]
+    pub fn storage_stats(&self) -> StorageStats {
+        self.db.db.storage_stats()
+    }
+
+    /// How many areas the data is divided into,
+    /// see [vsdb_set_area_opts](crate::vsdb_set_area_opts).
+    #[inline(always)]
+    pub fn area_count(&self) -> usize {
+        self.db.db.area_count()
+    }
+
+    /// The data directory of this database.
+    #[inline(always)]
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    #[inline(always)]
+    fn is_global(&self) -> bool {
+        ptr::eq(self.db, &*VSDB)
+    }
+}
+
+impl fmt::Debug for Vsdb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Vsdb").field("dir", &self.dir).finish()
+    }
+}
+
+impl PartialEq for Vsdb {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.db, other.db)
+    }
+}
+
+impl Eq for Vsdb {}
+
+// The database of the current thread.
+#[inline(always)]
+pub(crate) fn current() -> Db {
+    CURRENT.with(|c| c.get()).unwrap_or(&*VSDB)
+}
+
+// Run `f` with `db` as the current database of the thread,
+// used to work on an instance in the database holding it.
+pub(crate) fn scoped<T>(db: Db, f: impl FnOnce() -> T) -> T {
+    // restore the outer database even if `f` panics
+    struct Restore(Option<Db>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|c| c.set(self.0));
+        }
+    }
+
+    let _outer = Restore(CURRENT.with(|c| c.replace(Some(db))));
+    f()
+}
+
+fn canonicalize(dir: impl AsRef<Path>) -> Result<String> {
+    fs::canonicalize(dir)
+        .c(d!())?
+        .into_os_string()
+        .into_string()
+        .map_err(|_| eg!("non-UTF8 path"))
+}
@@ -0,0 +1,87 @@
+//!
+//! Durability policies.
+//!
+//! - `Async`, the default, data is synced by `vsdb_flush`, `vsdb_flush_async`
+//!   and the engine itself, a crash may lose the latest changes
+//! - `Interval(d)`, synced by a background thread every `d`, the same as
+//!   setting `flush_interval` by [vsdb_reconfigure](crate::vsdb_reconfigure)
+//! - `PerVersion`, the database holding an instance is synced every time
+//!   a version of it is created(or committed by `vs_commit`), so all changes
+//!   of a version are on the disk before the next version starts, the
+//!   `flush_interval` is kept as it was, so the two can work together
+//!
+//! Versions created by `vs_commit` are recorded in a journal until the whole
+//! commit finishes, those left by a crash are rolled back by
+//! [vsdb_recover](crate::vsdb_recover).
+//!
+
+use crate::common::{
+    config::{vsdb_get_config, vsdb_reconfigure, PartialConfig},
+    database,
+};
+use ruc::*;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+static PER_VERSION: AtomicBool = AtomicBool::new(false);
+
+/// When data is synced to the disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    #[default]
+    Async,
+    Interval(Duration),
+    PerVersion,
+}
+
+/// Set the durability policy, it can be changed at any time.
+///
+/// `PerVersion` leaves the `flush_interval` untouched,
+/// the other two replace it.
+pub fn vsdb_set_durability(d: Durability) -> Result<()> {
+    let interval = match d {
+        Durability::Interval(i) if i.is_zero() => {
+            return Err(eg!("the interval can not be zero"));
+        }
+        Durability::Interval(i) => Some(i),
+        Durability::Async => Some(Duration::ZERO),
+        Durability::PerVersion => None,
+    };
+
+    if interval.is_some() {
+        vsdb_reconfigure(PartialConfig {
+            flush_interval: interval,
+            ..Default::default()
+        })
+        .c(d!())?;
+    }
+
+    PER_VERSION.store(Durability::PerVersion == d, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Get the current durability policy,
+/// `PerVersion` takes precedence over a `flush_interval`.
+pub fn vsdb_get_durability() -> Durability {
+    if PER_VERSION.load(Ordering::Relaxed) {
+        return Durability::PerVersion;
+    }
+    let interval = vsdb_get_config().flush_interval;
+    alt!(
+        interval.is_zero(),
+        Durability::Async,
+        Durability::Interval(interval)
+    )
+}
+
+// Called after a version is created outside of `vs_commit`,
+// or after a `vs_commit` finishes, `db` holds the versioned instance.
+#[inline(always)]
+pub(crate) fn version_created(db: database::Db) {
+    if PER_VERSION.load(Ordering::Relaxed) {
+        db.flush();
+    }
+}
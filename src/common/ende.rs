@@ -5,15 +5,25 @@
 //!
 
 use super::RawBytes;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use ruc::*;
 use serde::{
     de::{self, DeserializeOwned},
-    Serialize,
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 use std::{
+    any::{self, Any, TypeId},
+    collections::HashMap,
     fmt,
     mem::{size_of, transmute},
+    ops::{Deref, DerefMut},
     result::Result as StdResult,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 /////////////////////////////////////////////////////////////////////////////
@@ -34,6 +44,18 @@ impl<'de> de::Visitor<'de> for SimpleVisitor {
     {
         Ok(v.to_vec())
     }
+
+    // bytes are encoded as arrays of numbers by some codecs, such as json
+    fn visit_seq<A>(self, mut seq: A) -> StdResult<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut ret = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            ret.push(b);
+        }
+        Ok(ret)
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////////
@@ -42,30 +64,16 @@ impl<'de> de::Visitor<'de> for SimpleVisitor {
 /// Methods used to encode the KEY.
 pub trait KeyEn: Serialize + Sized {
     /// Encode original key type to bytes.
-    #[cfg(all(feature = "cbor_codec", not(feature = "bcs_codec")))]
-    fn encode_key(&self) -> RawBytes {
-        serde_cbor::to_vec(self).unwrap().into_boxed_slice()
-    }
-
-    /// Encode original key type to bytes.
-    #[cfg(all(feature = "bcs_codec", not(feature = "cbor_codec")))]
     fn encode_key(&self) -> RawBytes {
-        bcs::to_bytes(self).unwrap().into_boxed_slice()
+        encode_key_by::<DefaultCodec, Self>(self)
     }
 }
 
 /// Methods used to decode the KEY.
 pub trait KeyDe: DeserializeOwned {
     /// Decode from bytes to the original key type.
-    #[cfg(all(feature = "cbor_codec", not(feature = "bcs_codec")))]
-    fn decode_key(bytes: &[u8]) -> Result<Self> {
-        serde_cbor::from_slice(bytes).c(d!())
-    }
-
-    /// Decode from bytes to the original key type.
-    #[cfg(all(feature = "bcs_codec", not(feature = "cbor_codec")))]
     fn decode_key(bytes: &[u8]) -> Result<Self> {
-        bcs::from_bytes(bytes).c(d!())
+        decode_key_by::<DefaultCodec, Self>(bytes)
     }
 }
 
@@ -85,30 +93,16 @@ pub trait KeyEnDe: KeyEn + KeyDe {
 /// Methods used to encode the VALUE.
 pub trait ValueEn: Serialize + Sized {
     /// Encode original key type to bytes.
-    #[cfg(all(feature = "cbor_codec", not(feature = "bcs_codec")))]
-    fn encode_value(&self) -> RawBytes {
-        serde_cbor::to_vec(self).unwrap().into_boxed_slice()
-    }
-
-    /// Encode original key type to bytes.
-    #[cfg(all(feature = "bcs_codec", not(feature = "cbor_codec")))]
     fn encode_value(&self) -> RawBytes {
-        bcs::to_bytes(self).unwrap().into_boxed_slice()
+        encode_value_by::<DefaultCodec, Self>(self)
     }
 }
 
 /// Methods used to decode the VALUE.
 pub trait ValueDe: DeserializeOwned {
     /// Decode from bytes to the original key type.
-    #[cfg(all(feature = "cbor_codec", not(feature = "bcs_codec")))]
-    fn decode_value(bytes: &[u8]) -> Result<Self> {
-        serde_cbor::from_slice(bytes).c(d!())
-    }
-
-    /// Decode from bytes to the original key type.
-    #[cfg(all(feature = "bcs_codec", not(feature = "cbor_codec")))]
     fn decode_value(bytes: &[u8]) -> Result<Self> {
-        bcs::from_bytes(bytes).c(d!())
+        decode_value_by::<DefaultCodec, Self>(bytes)
     }
 }
 
@@ -134,15 +128,526 @@ impl<T: DeserializeOwned> ValueDe for T {}
 impl<T: KeyEn + KeyDe> KeyEnDe for T {}
 impl<T: ValueEn + ValueDe> ValueEnDe for T {}
 
+/////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////////////////////////////////////////////////
+
+/// How to deal with the failures of decoding stored data,
+/// such as corrupted entries, or entries written by an incompatible type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodecFailurePolicy {
+    /// Panic at once, this is the default policy.
+    Panic,
+    /// Print the error and treat the entry as a non-existing one,
+    /// iterators will skip it and go on.
+    Skip,
+}
+
+static SKIP_CODEC_FAILURES: AtomicBool = AtomicBool::new(false);
+
+/// Set the global policy of decoding failures.
+#[inline(always)]
+pub fn vsdb_set_codec_failure_policy(policy: CodecFailurePolicy) {
+    SKIP_CODEC_FAILURES.store(CodecFailurePolicy::Skip == policy, Ordering::Relaxed);
+}
+
+/// Get the global policy of decoding failures.
+#[inline(always)]
+pub fn vsdb_get_codec_failure_policy() -> CodecFailurePolicy {
+    if SKIP_CODEC_FAILURES.load(Ordering::Relaxed) {
+        CodecFailurePolicy::Skip
+    } else {
+        CodecFailurePolicy::Panic
+    }
+}
+
+#[inline(always)]
+fn handle_by_policy<T>(r: Result<T>) -> Option<T> {
+    if SKIP_CODEC_FAILURES.load(Ordering::Relaxed) {
+        info!(r).ok()
+    } else {
+        Some(pnk!(r))
+    }
+}
+
+// Decode a stored value, failures are handled by the global policy.
+#[inline(always)]
+pub(crate) fn decode_value_by_policy<V: ValueEnDe>(bytes: &[u8]) -> Option<V> {
+    handle_by_policy(<V as ValueEnDe>::decode(bytes))
+}
+
+// Decode a stored value by the codec `C`,
+// failures are handled by the global policy.
+#[inline(always)]
+pub(crate) fn decode_value_by_policy_with<C: Codec, V: DeserializeOwned>(
+    bytes: &[u8],
+) -> Option<V> {
+    handle_by_policy(decode_value_by::<C, V>(bytes))
+}
+
+// Decode a stored key, failures are handled by the global policy.
+#[inline(always)]
+pub(crate) fn decode_key_by_policy<K: KeyEnDe>(bytes: &[u8]) -> Option<K> {
+    handle_by_policy(<K as KeyEnDe>::decode(bytes))
+}
+
+// Decode a stored key by the codec `C`,
+// failures are handled by the global policy.
+#[inline(always)]
+pub(crate) fn decode_key_by_policy_with<C: Codec, K: DeserializeOwned>(
+    bytes: &[u8],
+) -> Option<K> {
+    handle_by_policy(decode_key_by::<C, K>(bytes))
+}
+
+// Decode a stored ordered key, failures are handled by the global policy.
+#[inline(always)]
+pub(crate) fn key_from_bytes_by_policy<K: KeyEnDeOrdered>(bytes: RawBytes) -> Option<K> {
+    handle_by_policy(K::from_bytes(bytes))
+}
+
+/////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////////////////////////////////////////////////
+
+/// Which kind of data is being encoded or decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodecTarget {
+    /// Keys, via `KeyEn`/`KeyDe`.
+    Key,
+    /// Values, via `ValueEn`/`ValueDe`.
+    Value,
+}
+
+/// The direction of a codec operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodecOp {
+    /// Original type => bytes.
+    Encode,
+    /// Bytes => original type.
+    Decode,
+}
+
+/// Details of a finished codec operation.
+#[derive(Clone, Debug)]
+pub struct CodecEvent {
+    /// Encoding or decoding.
+    pub op: CodecOp,
+    /// A key or a value.
+    pub target: CodecTarget,
+    /// The original type, as returned by `std::any::type_name`.
+    pub type_name: &'static str,
+    /// Size of the encoded bytes.
+    pub bytes: usize,
+    /// Time spent on this operation.
+    pub elapsed: Duration,
+    /// Whether this operation succeeded.
+    pub ok: bool,
+}
+
+/// Callbacks around every `serde`-based encoding and decoding,
+/// can be used to measure the serialization overhead with real traffic.
+///
+/// NOTE:
+/// - Hooks run inline with the codec, so keep them cheap
+/// - Ordered keys(`KeyEnDeOrdered`) are not serialized by `serde`,
+///   so they are not reported
+pub trait CodecHook: Send + Sync {
+    /// Called before an operation starts.
+    fn before(&self, _op: CodecOp, _target: CodecTarget, _type_name: &'static str) {}
+
+    /// Called after an operation finishes.
+    fn after(&self, _event: &CodecEvent) {}
+}
+
+static CODEC_HOOK_ENABLED: AtomicBool = AtomicBool::new(false);
+static CODEC_HOOK: Lazy<RwLock<Option<Arc<dyn CodecHook>>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// Install a global codec hook, `None` means removing the current one.
+pub fn vsdb_set_codec_hook(hook: Option<Arc<dyn CodecHook>>) {
+    let mut hdr = CODEC_HOOK.write();
+    CODEC_HOOK_ENABLED.store(hook.is_some(), Ordering::Relaxed);
+    *hdr = hook;
+}
+
+// Clone the hook out, so the lock will not be held when it is running.
+#[inline(always)]
+fn codec_hook() -> Option<Arc<dyn CodecHook>> {
+    if CODEC_HOOK_ENABLED.load(Ordering::Relaxed) {
+        CODEC_HOOK.read().clone()
+    } else {
+        None
+    }
+}
+
+#[inline(always)]
+fn hooked_encode<T: ?Sized>(
+    target: CodecTarget,
+    f: impl FnOnce() -> RawBytes,
+) -> RawBytes {
+    let hook = if let Some(h) = codec_hook() {
+        h
+    } else {
+        return f();
+    };
+
+    let type_name = any::type_name::<T>();
+    hook.before(CodecOp::Encode, target, type_name);
+    let ts = Instant::now();
+    let ret = f();
+    hook.after(&CodecEvent {
+        op: CodecOp::Encode,
+        target,
+        type_name,
+        bytes: ret.len(),
+        elapsed: ts.elapsed(),
+        ok: true,
+    });
+
+    ret
+}
+
+#[inline(always)]
+fn hooked_decode<T>(
+    target: CodecTarget,
+    bytes: &[u8],
+    f: impl FnOnce(&[u8]) -> Result<T>,
+) -> Result<T> {
+    let hook = if let Some(h) = codec_hook() {
+        h
+    } else {
+        return f(bytes);
+    };
+
+    let type_name = any::type_name::<T>();
+    hook.before(CodecOp::Decode, target, type_name);
+    let ts = Instant::now();
+    let ret = f(bytes);
+    hook.after(&CodecEvent {
+        op: CodecOp::Decode,
+        target,
+        type_name,
+        bytes: bytes.len(),
+        elapsed: ts.elapsed(),
+        ok: ret.is_ok(),
+    });
+
+    ret
+}
+
 // used to encode the deref value of `Option<Box<[u8]>>`
-#[cfg(all(feature = "cbor_codec", not(feature = "bcs_codec")))]
 pub(crate) fn encode_optioned_bytes(v: &Option<&[u8]>) -> RawBytes {
-    serde_cbor::to_vec(v).unwrap().into_boxed_slice()
+    DefaultCodec::to_bytes(v)
+}
+
+/////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////////////////////////////////////////////////
+
+/// A serialization format of keys and values.
+///
+/// Collections are generic over it, e.g. `Mapx<K, V, BcsCodec>`,
+/// so one application can mix several formats. Each codec is available
+/// with its feature, the first enabled one of `cbor_codec`, `bcs_codec`,
+/// `msgpack_codec` and `json_codec` is the `DefaultCodec`.
+///
+/// NOTE: a collection must always be opened with the same codec.
+pub trait Codec:
+    Clone + Copy + fmt::Debug + Default + PartialEq + Eq + Send + Sync + 'static
+{
+    /// Encode a value to bytes.
+    fn to_bytes<T: Serialize>(v: &T) -> RawBytes;
+
+    /// Decode a value from bytes.
+    fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+}
+
+/// Cbor, the default codec.
+#[cfg(feature = "cbor_codec")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor_codec")]
+impl Codec for CborCodec {
+    fn to_bytes<T: Serialize>(v: &T) -> RawBytes {
+        serde_cbor::to_vec(v).unwrap().into_boxed_slice()
+    }
+
+    fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_cbor::from_slice(bytes).c(d!())
+    }
 }
 
+/// Bcs, for blockchain scenarios.
+#[cfg(feature = "bcs_codec")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BcsCodec;
+
+#[cfg(feature = "bcs_codec")]
+impl Codec for BcsCodec {
+    fn to_bytes<T: Serialize>(v: &T) -> RawBytes {
+        bcs::to_bytes(v).unwrap().into_boxed_slice()
+    }
+
+    fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        bcs::from_bytes(bytes).c(d!())
+    }
+}
+
+/// MessagePack, structs are encoded as maps, just like the `CborCodec`,
+/// so they can be read by other MessagePack implementations directly.
+#[cfg(feature = "msgpack_codec")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MsgpackCodec;
+
+#[cfg(feature = "msgpack_codec")]
+impl Codec for MsgpackCodec {
+    fn to_bytes<T: Serialize>(v: &T) -> RawBytes {
+        rmp_serde::to_vec_named(v).unwrap().into_boxed_slice()
+    }
+
+    fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        rmp_serde::from_slice(bytes).c(d!())
+    }
+}
+
+/// JSON, slow, but stored values can be inspected and patched by generic tools.
+#[cfg(feature = "json_codec")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json_codec")]
+impl Codec for JsonCodec {
+    fn to_bytes<T: Serialize>(v: &T) -> RawBytes {
+        serde_json::to_vec(v).unwrap().into_boxed_slice()
+    }
+
+    fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).c(d!())
+    }
+}
+
+// the frame header of `Compressed`
+#[cfg(feature = "compression")]
+const FRAME_RAW: u8 = 0;
+#[cfg(feature = "compression")]
+const FRAME_ZSTD: u8 = 1;
+
+/// Zstd-compress the outputs of the codec `C` whose sizes reach `MIN`,
+/// e.g. `MapxVs<u64, Blob, Compressed<BcsCodec>>`.
+///
+/// Every output has a one-byte frame header, smaller outputs(and the ones
+/// that can not be shrunk) are kept raw, so small values pay nothing but
+/// the header. Keys are also encoded by it, so it suits values better.
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Compressed<C = DefaultCodec, const MIN: usize = 256>(
+    std::marker::PhantomData<C>,
+);
+
+#[cfg(feature = "compression")]
+impl<C: Codec, const MIN: usize> Codec for Compressed<C, MIN> {
+    fn to_bytes<T: Serialize>(v: &T) -> RawBytes {
+        let v = C::to_bytes(v);
+        let mut ret = Vec::with_capacity(1 + v.len());
+
+        if MIN <= v.len() {
+            // `0` means the default level
+            let z = pnk!(zstd::encode_all(&v[..], 0));
+            if z.len() < v.len() {
+                ret.push(FRAME_ZSTD);
+                ret.extend_from_slice(&z);
+                return ret.into_boxed_slice();
+            }
+        }
+
+        ret.push(FRAME_RAW);
+        ret.extend_from_slice(&v);
+        ret.into_boxed_slice()
+    }
+
+    fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        match bytes.split_first() {
+            Some((&FRAME_RAW, v)) => C::from_bytes(v).c(d!()),
+            Some((&FRAME_ZSTD, v)) => zstd::decode_all(v)
+                .c(d!())
+                .and_then(|v| C::from_bytes(&v).c(d!())),
+            _ => Err(eg!("invalid frame")),
+        }
+    }
+}
+
+/// The codec used by `KeyEnDe`/`ValueEnDe` and collections by default.
+#[cfg(feature = "cbor_codec")]
+pub type DefaultCodec = CborCodec;
+
+/// The codec used by `KeyEnDe`/`ValueEnDe` and collections by default.
 #[cfg(all(feature = "bcs_codec", not(feature = "cbor_codec")))]
-pub(crate) fn encode_optioned_bytes(v: &Option<&[u8]>) -> RawBytes {
-    bcs::to_bytes(v).unwrap().into_boxed_slice()
+pub type DefaultCodec = BcsCodec;
+
+/// The codec used by `KeyEnDe`/`ValueEnDe` and collections by default.
+#[cfg(all(
+    feature = "msgpack_codec",
+    not(any(feature = "cbor_codec", feature = "bcs_codec"))
+))]
+pub type DefaultCodec = MsgpackCodec;
+
+/// The codec used by `KeyEnDe`/`ValueEnDe` and collections by default.
+#[cfg(all(
+    feature = "json_codec",
+    not(any(
+        feature = "cbor_codec",
+        feature = "bcs_codec",
+        feature = "msgpack_codec"
+    ))
+))]
+pub type DefaultCodec = JsonCodec;
+
+// Encode a key by the codec `C`, the codec hook is called as usual.
+#[inline(always)]
+pub(crate) fn encode_key_by<C: Codec, K: Serialize>(key: &K) -> RawBytes {
+    hooked_encode::<K>(CodecTarget::Key, || C::to_bytes(key))
+}
+
+#[inline(always)]
+pub(crate) fn decode_key_by<C: Codec, K: DeserializeOwned>(bytes: &[u8]) -> Result<K> {
+    hooked_decode(CodecTarget::Key, bytes, |b| C::from_bytes(b).c(d!()))
+}
+
+// Encode a value by the codec `C`, the codec hook is called as usual.
+#[inline(always)]
+pub(crate) fn encode_value_by<C: Codec, V: Serialize>(value: &V) -> RawBytes {
+    hooked_encode::<V>(CodecTarget::Value, || C::to_bytes(value))
+}
+
+#[inline(always)]
+pub(crate) fn decode_value_by<C: Codec, V: DeserializeOwned>(bytes: &[u8]) -> Result<V> {
+    hooked_decode(CodecTarget::Value, bytes, |b| C::from_bytes(b).c(d!()))
+}
+
+/////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////////////////////////////////////////////////
+
+/// A type whose encoded layout may change between releases.
+///
+/// Bump `SCHEMA_VERSION` whenever the layout changes, e.g. a field is added,
+/// and register upgrades of the old versions by `vsdb_register_schema_upgrade`.
+pub trait Schema: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The version of the current layout.
+    const SCHEMA_VERSION: u8;
+}
+
+type Upgrade<T> = Arc<dyn Fn(&[u8]) -> Result<T> + Send + Sync>;
+
+// [ (type, old version) ] => `Upgrade<T>`
+static SCHEMA_UPGRADES: Lazy<RwLock<HashMap<(TypeId, u8), Box<dyn Any + Send + Sync>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Register how to get a `T` from the values written with the `from` version
+/// of its schema, the callback receives the bytes encoded by the `DefaultCodec`,
+/// usually they are decoded as the old type and then converted.
+///
+/// Registering the same version again replaces the previous upgrade.
+pub fn vsdb_register_schema_upgrade<T: Schema>(
+    from: u8,
+    upgrade: impl Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+) -> Result<()> {
+    if T::SCHEMA_VERSION == from {
+        return Err(eg!("can not upgrade from the current version"));
+    }
+    let upgrade: Upgrade<T> = Arc::new(upgrade);
+    SCHEMA_UPGRADES
+        .write()
+        .insert((TypeId::of::<T>(), from), Box::new(upgrade));
+    Ok(())
+}
+
+// Clone the upgrade out, so the lock will not be held when it is running.
+fn schema_upgrade<T: Schema>(from: u8) -> Option<Upgrade<T>> {
+    SCHEMA_UPGRADES
+        .read()
+        .get(&(TypeId::of::<T>(), from))
+        .and_then(|u| u.downcast_ref::<Upgrade<T>>())
+        .cloned()
+}
+
+/// A value stored along with the version of its schema,
+/// e.g. `Mapx<u64, Enveloped<Account>>`.
+///
+/// Values of older versions are upgraded by the registered callbacks
+/// when they are read, instead of failing to decode, they are stored
+/// with the current version when they are written again.
+///
+/// The envelope is `[ schema version ] + [ value encoded by the DefaultCodec ]`,
+/// stored as a byte string of the selected codec.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Enveloped<T>(pub T);
+
+impl<T> Enveloped<T> {
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Enveloped<T> {
+    #[inline(always)]
+    fn from(v: T) -> Self {
+        Enveloped(v)
+    }
+}
+
+impl<T> Deref for Enveloped<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Enveloped<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Schema> Serialize for Enveloped<T> {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let v = DefaultCodec::to_bytes(&self.0);
+        let mut bytes = Vec::with_capacity(1 + v.len());
+        bytes.push(T::SCHEMA_VERSION);
+        bytes.extend_from_slice(&v);
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de, T: Schema> Deserialize<'de> for Enveloped<T> {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = deserializer.deserialize_bytes(SimpleVisitor)?;
+        let (ver, v) = bytes
+            .split_first()
+            .ok_or_else(|| de::Error::custom("empty envelope"))?;
+
+        let ret = if T::SCHEMA_VERSION == *ver {
+            DefaultCodec::from_bytes(v)
+        } else if let Some(upgrade) = schema_upgrade::<T>(*ver) {
+            upgrade(v)
+        } else {
+            Err(eg!(format!(
+                "no upgrade of `{}` from the schema version {}",
+                any::type_name::<T>(),
+                ver
+            )))
+        };
+
+        ret.map(Enveloped).map_err(de::Error::custom)
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////////
@@ -358,6 +863,210 @@ impl_type!(@u64);
 impl_type!(@u128);
 impl_type!(@usize);
 
+macro_rules! impl_float {
+    ($name: ident, $float: ty, $bits: ty) => {
+        #[doc = concat!(
+            "A finite `", stringify!($float), "` that can be used as an ordered key, ",
+            "e.g. `MapxOrd<", stringify!($name), ", V>`."
+        )]
+        ///
+        /// NaN and infinities are rejected, `-0.0` is stored as `0.0`.
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct $name($float);
+
+        impl $name {
+            #[inline(always)]
+            pub fn new(v: $float) -> Result<Self> {
+                if v.is_finite() {
+                    // `-0.0 == 0.0`, they must have the same bytes
+                    Ok(Self(if 0.0 == v { 0.0 } else { v }))
+                } else {
+                    Err(eg!(format!("{} is not a finite number", v)))
+                }
+            }
+
+            #[inline(always)]
+            pub fn get(self) -> $float {
+                self.0
+            }
+        }
+
+        impl TryFrom<$float> for $name {
+            type Error = Box<dyn RucError>;
+
+            #[inline(always)]
+            fn try_from(v: $float) -> Result<Self> {
+                Self::new(v).c(d!())
+            }
+        }
+
+        impl From<$name> for $float {
+            #[inline(always)]
+            fn from(v: $name) -> $float {
+                v.0
+            }
+        }
+
+        // all values are finite
+        impl Eq for $name {}
+
+        impl Ord for $name {
+            #[inline(always)]
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl PartialOrd for $name {
+            #[inline(always)]
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        // Positive numbers get the sign bit set, negative ones get
+        // all bits flipped, then the bytes are ordered as the numbers.
+        impl KeyEnDeOrdered for $name {
+            #[inline(always)]
+            fn to_bytes(&self) -> RawBytes {
+                let bits = self.0.to_bits();
+                let sign = 1 << (<$bits>::BITS - 1);
+                let bits = if 0 == bits & sign { bits | sign } else { !bits };
+                Box::new(bits.to_be_bytes())
+            }
+            #[inline(always)]
+            fn from_slice(b: &[u8]) -> Result<Self> {
+                let bits = <[u8; size_of::<$bits>()]>::try_from(b)
+                    .c(d!())
+                    .map(<$bits>::from_be_bytes)?;
+                let sign = 1 << (<$bits>::BITS - 1);
+                let bits = if 0 == bits & sign { !bits } else { bits ^ sign };
+                Self::new(<$float>::from_bits(bits)).c(d!())
+            }
+        }
+    };
+}
+
+impl_float!(OrdF32, f32, u32);
+impl_float!(OrdF64, f64, u64);
+
+macro_rules! impl_ord_int {
+    ($name: ident, $int: ty) => {
+        #[doc = concat!(
+            "A `", stringify!($int), "` that can be used as an ordered key, ",
+            "e.g. `MapxOrd<", stringify!($name), ", V>`."
+        )]
+        ///
+        #[doc = concat!(
+            "Keys of the plain `", stringify!($int), "` are stored in big-endian ",
+            "as they always were, so negative ones are ordered after positive ones, ",
+            "this one flips the sign bit to keep the order of numbers."
+        )]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub $int);
+
+        impl From<$int> for $name {
+            #[inline(always)]
+            fn from(v: $int) -> Self {
+                Self(v)
+            }
+        }
+
+        impl From<$name> for $int {
+            #[inline(always)]
+            fn from(v: $name) -> $int {
+                v.0
+            }
+        }
+
+        impl KeyEnDeOrdered for $name {
+            #[inline(always)]
+            fn to_bytes(&self) -> RawBytes {
+                Box::new((self.0 ^ <$int>::MIN).to_be_bytes())
+            }
+            #[inline(always)]
+            fn from_slice(b: &[u8]) -> Result<Self> {
+                <[u8; size_of::<$int>()]>::try_from(b)
+                    .c(d!())
+                    .map(|i| Self(<$int>::from_be_bytes(i) ^ <$int>::MIN))
+            }
+        }
+    };
+}
+
+impl_ord_int!(OrdI8, i8);
+impl_ord_int!(OrdI16, i16);
+impl_ord_int!(OrdI32, i32);
+impl_ord_int!(OrdI64, i64);
+impl_ord_int!(OrdI128, i128);
+
+// Components of a tuple are escaped and terminated, except the last one:
+// `0x00` => `0x00 0xff`, the end => `0x00 0x00`,
+// so a component is ordered before the ones that it is a prefix of.
+fn push_escaped(buf: &mut Vec<u8>, b: &[u8]) {
+    b.iter().for_each(|i| {
+        buf.push(*i);
+        if 0 == *i {
+            buf.push(0xff);
+        }
+    });
+    buf.extend_from_slice(&[0, 0]);
+}
+
+// Split an escaped component from the head of `b`.
+fn split_escaped(b: &[u8]) -> Result<(Vec<u8>, &[u8])> {
+    let mut ret = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        if 0 == b[i] {
+            match b.get(i + 1) {
+                Some(0) => return Ok((ret, &b[i + 2..])),
+                Some(0xff) => i += 1,
+                _ => return Err(eg!("invalid bytes")),
+            }
+            ret.push(0);
+        } else {
+            ret.push(b[i]);
+        }
+        i += 1;
+    }
+    Err(eg!("invalid bytes"))
+}
+
+macro_rules! impl_tuple {
+    ($(($t: ident, $v: ident)),+; ($last_t: ident, $last_v: ident)) => {
+        impl<$($t,)+ $last_t> KeyEnDeOrdered for ($($t,)+ $last_t)
+        where
+            $($t: KeyEnDeOrdered,)+
+            $last_t: KeyEnDeOrdered,
+        {
+            #[inline(always)]
+            fn to_bytes(&self) -> RawBytes {
+                let ($($v,)+ $last_v) = self;
+                let mut ret = vec![];
+                $(push_escaped(&mut ret, &$v.to_bytes());)+
+                ret.extend_from_slice(&$last_v.to_bytes());
+                ret.into_boxed_slice()
+            }
+            #[inline(always)]
+            fn from_slice(b: &[u8]) -> Result<Self> {
+                let rest = b;
+                $(
+                    let ($v, rest) = split_escaped(rest).c(d!())?;
+                    let $v = $t::from_bytes($v.into_boxed_slice()).c(d!())?;
+                )+
+                let $last_v = $last_t::from_slice(rest).c(d!())?;
+                Ok(($($v,)+ $last_v))
+            }
+        }
+    };
+}
+
+impl_tuple!((A, a); (B, b));
+impl_tuple!((A, a), (B, b); (C, c));
+impl_tuple!((A, a), (B, b), (C, c); (D, d));
+impl_tuple!((A, a), (B, b), (C, c), (D, d); (E, e));
+
 // macro_rules! impl_repeat {
 //     ($i: expr) => {
 //         impl_type!(i8, $i);
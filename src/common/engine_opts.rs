@@ -0,0 +1,73 @@
+//!
+//! Tuning options of the backend engine.
+//!
+//! Unlike the runtime parameters in [config](crate::common::config), these
+//! options can only be set before VSDB is initialized. They are honored by
+//! the rocksdb engine and ignored by other engines, the defaults of VSDB are
+//! kept for all `None` fields.
+//!
+
+use crate::common::config::{self, vsdb_reconfigure, PartialConfig};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+
+static ENGINE_OPTS: Lazy<Mutex<EngineOpts>> =
+    Lazy::new(|| Mutex::new(EngineOpts::default()));
+
+/// Compression algorithms of data blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockCompression {
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+/// How data files are compacted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactionStyle {
+    Level,
+    Universal,
+    Fifo,
+}
+
+/// Options applied when the engine is opened.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineOpts {
+    /// In bytes, the same parameter as `PartialConfig::cache_size`,
+    /// it can still be adjusted by `vsdb_reconfigure` after opening.
+    pub block_cache_size: Option<usize>,
+    /// Starts from level 0, deeper levels use the last one,
+    /// `Lz4` is used for all levels by default.
+    pub compression_per_level: Option<Vec<BlockCompression>>,
+    pub compaction_style: Option<CompactionStyle>,
+    /// In bytes, the size of a memtable.
+    pub write_buffer_size: Option<usize>,
+}
+
+/// Set the tuning options of the engine,
+/// it can only be called before VSDB is initialized.
+pub fn vsdb_set_engine_opts(opts: EngineOpts) -> Result<()> {
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+
+    if let Some(n) = opts.block_cache_size {
+        vsdb_reconfigure(PartialConfig {
+            cache_size: Some(n),
+            ..Default::default()
+        })
+        .c(d!())?;
+    }
+
+    *ENGINE_OPTS.lock() = opts;
+
+    Ok(())
+}
+
+/// Get the tuning options of the engine.
+#[inline(always)]
+pub fn vsdb_get_engine_opts() -> EngineOpts {
+    ENGINE_OPTS.lock().clone()
+}
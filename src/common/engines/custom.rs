@@ -0,0 +1,193 @@
+//!
+//! Custom engines provided by users.
+//!
+//! The engine registered by [vsdb_register_engine](self::vsdb_register_engine)
+//! takes the place of the builtin one selected by features,
+//! for all databases opened after the registration.
+//!
+
+#[cfg(feature = "encryption")]
+use crate::common::engines::encrypted::{self, Encrypted};
+use crate::common::{
+    config,
+    engines::{
+        sharded::{self, Sharded},
+        BuiltinEngine, Engine, EngineIter,
+    },
+    stats::StorageStats,
+    BranchID, Prefix, PrefixBytes, RawValue, VersionID,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::ops::Bound;
+
+type EngineCtor = fn(&str) -> Result<Box<dyn Engine>>;
+
+static CUSTOM_ENGINE: Lazy<Mutex<Option<EngineCtor>>> = Lazy::new(|| Mutex::new(None));
+
+/// Use `E` as the backend database instead of the builtin one,
+/// it can only be called before VSDB is initialized.
+///
+/// NOTE:
+/// - The same engine should be registered between restarts
+/// - Hooks of flushing only watch the files in ${VSDB_BASE_DIR}
+pub fn vsdb_register_engine<E: Engine + 'static>() -> Result<()> {
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+    *CUSTOM_ENGINE.lock() = Some(open::<E>);
+    Ok(())
+}
+
+fn open<E: Engine + 'static>(dir: &str) -> Result<Box<dyn Engine>> {
+    E::new(dir).c(d!()).map(|e| Box::new(e) as Box<dyn Engine>)
+}
+
+// The builtin engine, or the one registered by users.
+pub(crate) enum Backend {
+    Builtin(BuiltinEngine),
+    Custom(Box<dyn Engine>),
+}
+
+macro_rules! dispatch {
+    ($hdr: ident, $e: ident => $op: expr) => {
+        match $hdr {
+            Backend::Builtin($e) => $op,
+            Backend::Custom($e) => $op,
+        }
+    };
+}
+
+impl Backend {
+    // Without any wrapper, such as the encryption layer.
+    pub(crate) fn open_plain(dir: &str) -> Result<Self> {
+        if sharded::sharding_enabled() {
+            return Sharded::new(dir)
+                .c(d!())
+                .map(|e| Backend::Custom(Box::new(e)));
+        }
+
+        Backend::open_single(dir).c(d!())
+    }
+
+    // One instance of the engine, without sharding.
+    pub(crate) fn open_single(dir: &str) -> Result<Self> {
+        let ctor = *CUSTOM_ENGINE.lock();
+        if let Some(ctor) = ctor {
+            ctor(dir).c(d!()).map(Backend::Custom)
+        } else {
+            BuiltinEngine::new(dir).c(d!()).map(Backend::Builtin)
+        }
+    }
+}
+
+impl Engine for Backend {
+    fn new(dir: &str) -> Result<Self> {
+        #[cfg(feature = "encryption")]
+        if encrypted::encryption_enabled() {
+            return Encrypted::new(dir)
+                .c(d!())
+                .map(|e| Backend::Custom(Box::new(e)));
+        }
+
+        Backend::open_plain(dir).c(d!())
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        dispatch!(self, e => e.alloc_prefix())
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        dispatch!(self, e => e.alloc_branch_id())
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        dispatch!(self, e => e.alloc_version_id())
+    }
+
+    fn area_count(&self) -> usize {
+        dispatch!(self, e => e.area_count())
+    }
+
+    fn flush(&self) {
+        dispatch!(self, e => e.flush())
+    }
+
+    fn flush_area(&self, area_idx: usize) {
+        dispatch!(self, e => e.flush_area(area_idx))
+    }
+
+    fn set_cache_size(&self, bytes: usize) -> Result<()> {
+        dispatch!(self, e => e.set_cache_size(bytes))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        dispatch!(self, e => e.refresh())
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        dispatch!(self, e => e.storage_stats())
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        dispatch!(self, e => e.iter(area_idx, meta_prefix))
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        dispatch!(self, e => e.range(area_idx, meta_prefix, bounds))
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        dispatch!(self, e => e.get(area_idx, meta_prefix, key))
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        dispatch!(self, e => e.insert(area_idx, meta_prefix, key, value))
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        dispatch!(self, e => e.remove(area_idx, meta_prefix, key))
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        dispatch!(self, e => e.get_instance_len(instance_prefix))
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        dispatch!(self, e => e.instance_exists(instance_prefix))
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        dispatch!(self, e => e.set_instance_len(instance_prefix, new_len))
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        dispatch!(self, e => e.instance_is_append_only(instance_prefix))
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        dispatch!(self, e => e.set_instance_append_only(instance_prefix))
+    }
+}
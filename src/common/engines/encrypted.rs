@@ -0,0 +1,452 @@
+//!
+//! Transparent at-rest encryption.
+//!
+//! Values, and optionally keys, are encrypted by XChaCha20-Poly1305 before
+//! they reach the backend engine, the secret is supplied by
+//! [vsdb_set_encryption](self::vsdb_set_encryption) before VSDB is initialized.
+//!
+//! Every ciphertext carries the ID of its secret, so secrets can be rotated:
+//! set a new one and move the current one into `old_keys`, data encrypted by
+//! old secrets is still readable, and re-encrypted by the new one when it is read.
+//!
+//! NOTE:
+//! - The meta store(lengths of instances, etc.) is not encrypted
+//! - Encrypted keys are not ordered, so iterating over such instances
+//!   reads and sorts all their entries in memory
+//! - `encrypt_keys` should be the same between restarts
+//!
+
+use crate::common::{
+    config,
+    engines::{Backend, Engine, EngineIter},
+    replica,
+    stats::StorageStats,
+    BranchID, Prefix, PrefixBytes, RawBytes, RawKey, RawValue, VersionID,
+};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, MutexGuard};
+use ruc::*;
+use sha3::{Digest, Sha3_256};
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    mem::size_of,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+const ID_SIZ: usize = size_of::<u32>();
+const NONCE_SIZ: usize = 24;
+const HDR_SIZ: usize = ID_SIZ + NONCE_SIZ;
+
+// writes of the same key are serialized by one of them
+const LOCK_NUM: usize = 64;
+
+static ENCRYPTION: Lazy<Mutex<Option<EncryptionOpts>>> = Lazy::new(|| Mutex::new(None));
+
+/// A 256-bit secret and its ID, the ID is stored along with every ciphertext.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey {
+    pub id: u32,
+    pub key: [u8; 32],
+}
+
+// the secret should never appear in logs
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Options of the encryption layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptionOpts {
+    /// All new data is encrypted by it.
+    pub key: EncryptionKey,
+    /// Only used for decrypting,
+    /// data read out is re-encrypted by `key`.
+    pub old_keys: Vec<EncryptionKey>,
+    /// Keys are encrypted deterministically,
+    /// so they can still be found by lookups.
+    pub encrypt_keys: bool,
+}
+
+/// Encrypt all data written to the backend engine,
+/// it can only be called before VSDB is initialized.
+pub fn vsdb_set_encryption(opts: EncryptionOpts) -> Result<()> {
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+
+    let mut ids = HashSet::new();
+    if !ids.insert(opts.key.id) || !opts.old_keys.iter().all(|k| ids.insert(k.id)) {
+        return Err(eg!("duplicate key IDs"));
+    }
+
+    *ENCRYPTION.lock() = Some(opts);
+
+    Ok(())
+}
+
+#[inline(always)]
+pub(crate) fn encryption_enabled() -> bool {
+    ENCRYPTION.lock().is_some()
+}
+
+struct Cipher {
+    id: u32,
+    secret: [u8; 32],
+    aead: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn new(k: &EncryptionKey) -> Self {
+        Self {
+            id: k.id,
+            secret: k.key,
+            aead: XChaCha20Poly1305::new(Key::from_slice(&k.key)),
+        }
+    }
+
+    // [ key ID ] + [ nonce ] + [ ciphertext ]
+    fn seal(&self, nonce: &XNonce, msg: &[u8], aad: &[u8]) -> RawBytes {
+        let ct = pnk!(self
+            .aead
+            .encrypt(nonce, Payload { msg, aad })
+            .ok()
+            .c(d!("encryption failed")));
+        let mut ret = Vec::with_capacity(HDR_SIZ + ct.len());
+        ret.extend_from_slice(&self.id.to_be_bytes());
+        ret.extend_from_slice(nonce);
+        ret.extend_from_slice(&ct);
+        ret.into_boxed_slice()
+    }
+
+    fn encrypt(&self, msg: &[u8], aad: &[u8]) -> RawBytes {
+        self.seal(&XChaCha20Poly1305::generate_nonce(&mut OsRng), msg, aad)
+    }
+
+    // The nonce is derived from the secret and the input,
+    // so the same key is always encrypted to the same bytes.
+    fn encrypt_key(&self, key: &[u8], meta_prefix: PrefixBytes) -> RawBytes {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"vsdb key nonce");
+        hasher.update(self.secret);
+        hasher.update(meta_prefix);
+        hasher.update(key);
+        let digest = hasher.finalize();
+        self.seal(XNonce::from_slice(&digest[..NONCE_SIZ]), key, &meta_prefix)
+    }
+}
+
+// The current cipher is the first one.
+struct Keyring(Vec<Cipher>);
+
+impl Keyring {
+    #[inline(always)]
+    fn current(&self) -> &Cipher {
+        &self.0[0]
+    }
+
+    // Also returns whether it was encrypted by an old secret.
+    fn decrypt(&self, data: &[u8], aad: &[u8]) -> (RawBytes, bool) {
+        assert!(HDR_SIZ <= data.len(), "not encrypted data");
+        let id = crate::parse_int!(data[..ID_SIZ], u32);
+        let c = pnk!(self
+            .0
+            .iter()
+            .find(|c| c.id == id)
+            .c(d!(format!("unknown key ID: {}", id))));
+        let msg = pnk!(c
+            .aead
+            .decrypt(
+                XNonce::from_slice(&data[ID_SIZ..HDR_SIZ]),
+                Payload {
+                    msg: &data[HDR_SIZ..],
+                    aad,
+                },
+            )
+            .ok()
+            .c(d!("decryption failed, wrong key or corrupted data")));
+        (msg.into_boxed_slice(), id != self.current().id)
+    }
+}
+
+// Values are bound to their keys,
+// so they can not be moved to other places.
+fn value_aad(meta_prefix: PrefixBytes, key: &[u8]) -> Vec<u8> {
+    let mut aad = meta_prefix.to_vec();
+    aad.extend_from_slice(key);
+    aad
+}
+
+pub(crate) struct Encrypted {
+    inner: Backend,
+    keys: Arc<Keyring>,
+    encrypt_keys: bool,
+    // a lazy re-encryption must not overwrite a newer value
+    locks: Vec<Mutex<()>>,
+}
+
+impl Encrypted {
+    pub(crate) fn wrap(inner: Backend, opts: &EncryptionOpts) -> Self {
+        let ciphers = [&opts.key]
+            .into_iter()
+            .chain(opts.old_keys.iter())
+            .map(Cipher::new)
+            .collect();
+        Encrypted {
+            inner,
+            keys: Arc::new(Keyring(ciphers)),
+            encrypt_keys: opts.encrypt_keys,
+            locks: (0..LOCK_NUM).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn into_inner(self) -> Backend {
+        self.inner
+    }
+
+    fn lock(&self, meta_prefix: PrefixBytes, key: &[u8]) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        meta_prefix.hash(&mut hasher);
+        key.hash(&mut hasher);
+        self.locks[hasher.finish() as usize % LOCK_NUM].lock()
+    }
+
+    // Re-encrypt an entry found by `found_key` with the current secret,
+    // nothing is done if it has been changed by others.
+    fn reencrypt(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        found_key: &[u8],
+        found_value: &[u8],
+        value: &[u8],
+    ) {
+        if replica::vsdb_is_replica() {
+            return;
+        }
+
+        let _guard = self.lock(meta_prefix, key);
+        if self.inner.get(area_idx, meta_prefix, found_key).as_deref()
+            != Some(found_value)
+        {
+            return;
+        }
+
+        let cur = self.keys.current();
+        let ev = cur.encrypt(value, &value_aad(meta_prefix, key));
+        if self.encrypt_keys {
+            let ek = cur.encrypt_key(key, meta_prefix);
+            if *ek != *found_key {
+                self.inner.remove(area_idx, meta_prefix, found_key);
+            }
+            self.inner.insert(area_idx, meta_prefix, &ek, &ev);
+        } else {
+            self.inner.insert(area_idx, meta_prefix, key, &ev);
+        }
+    }
+}
+
+impl Engine for Encrypted {
+    fn new(dir: &str) -> Result<Self> {
+        let opts = ENCRYPTION.lock().clone().c(d!("no encryption key"))?;
+        Backend::open_plain(dir)
+            .c(d!())
+            .map(|inner| Encrypted::wrap(inner, &opts))
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.inner.alloc_prefix()
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.inner.alloc_branch_id()
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.inner.alloc_version_id()
+    }
+
+    fn area_count(&self) -> usize {
+        self.inner.area_count()
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+
+    fn flush_area(&self, area_idx: usize) {
+        self.inner.flush_area(area_idx)
+    }
+
+    fn set_cache_size(&self, bytes: usize) -> Result<()> {
+        self.inner.set_cache_size(bytes)
+    }
+
+    fn refresh(&self) -> Result<()> {
+        self.inner.refresh()
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        self.inner.storage_stats()
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        self.range(area_idx, meta_prefix, (Bound::Unbounded, Bound::Unbounded))
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        if !self.encrypt_keys {
+            let keys = Arc::clone(&self.keys);
+            let iter =
+                self.inner
+                    .range(area_idx, meta_prefix, bounds)
+                    .map(move |(k, ev)| {
+                        let v = keys.decrypt(&ev, &value_aad(meta_prefix, &k)).0;
+                        (k, v)
+                    });
+            return EngineIter::new(iter);
+        }
+
+        let mut entries = self
+            .inner
+            .iter(area_idx, meta_prefix)
+            .filter_map(|(ek, ev)| {
+                let k = self.keys.decrypt(&ek, &meta_prefix).0;
+                alt!(!bounds.contains(&&k[..]), return None);
+                let v = self.keys.decrypt(&ev, &value_aad(meta_prefix, &k)).0;
+                Some((k, v))
+            })
+            .collect::<Vec<(RawKey, RawValue)>>();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        EngineIter::new(entries.into_iter())
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let aad = value_aad(meta_prefix, key);
+
+        if !self.encrypt_keys {
+            let ev = self.inner.get(area_idx, meta_prefix, key)?;
+            let (v, stale) = self.keys.decrypt(&ev, &aad);
+            if stale {
+                self.reencrypt(area_idx, meta_prefix, key, key, &ev, &v);
+            }
+            return Some(v);
+        }
+
+        // the current secret first, most keys have been rotated
+        self.keys.0.iter().enumerate().find_map(|(i, c)| {
+            let ek = c.encrypt_key(key, meta_prefix);
+            let ev = self.inner.get(area_idx, meta_prefix, &ek)?;
+            let (v, stale) = self.keys.decrypt(&ev, &aad);
+            if 0 < i || stale {
+                self.reencrypt(area_idx, meta_prefix, key, &ek, &ev, &v);
+            }
+            Some(v)
+        })
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let aad = value_aad(meta_prefix, key);
+        let cur = self.keys.current();
+        let ev = cur.encrypt(value, &aad);
+
+        let _guard = self.lock(meta_prefix, key);
+
+        if !self.encrypt_keys {
+            return self
+                .inner
+                .insert(area_idx, meta_prefix, key, &ev)
+                .map(|old| self.keys.decrypt(&old, &aad).0);
+        }
+
+        let ek = cur.encrypt_key(key, meta_prefix);
+        let mut old = self.inner.insert(area_idx, meta_prefix, &ek, &ev);
+
+        // copies under old secrets are replaced
+        for c in self.keys.0[1..].iter() {
+            let ek = c.encrypt_key(key, meta_prefix);
+            if let Some(v) = self.inner.remove(area_idx, meta_prefix, &ek) {
+                old = old.or(Some(v));
+            }
+        }
+
+        old.map(|old| self.keys.decrypt(&old, &aad).0)
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let aad = value_aad(meta_prefix, key);
+
+        let _guard = self.lock(meta_prefix, key);
+
+        if !self.encrypt_keys {
+            return self
+                .inner
+                .remove(area_idx, meta_prefix, key)
+                .map(|old| self.keys.decrypt(&old, &aad).0);
+        }
+
+        let mut old = None;
+        for c in self.keys.0.iter() {
+            let ek = c.encrypt_key(key, meta_prefix);
+            if let Some(v) = self.inner.remove(area_idx, meta_prefix, &ek) {
+                old = old.or(Some(v));
+            }
+        }
+
+        old.map(|old| self.keys.decrypt(&old, &aad).0)
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        self.inner.get_instance_len(instance_prefix)
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.inner.instance_exists(instance_prefix)
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        self.inner.set_instance_len(instance_prefix, new_len)
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.inner.instance_is_append_only(instance_prefix)
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.inner.set_instance_append_only(instance_prefix)
+    }
+}
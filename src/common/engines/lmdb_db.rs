@@ -0,0 +1,291 @@
+use crate::common::{
+    areas,
+    engines::{append_only_meta_key, seek::SeekBounds, EngineIter},
+    replica, BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
+};
+use heed::{flags::Flags, types::ByteSlice, Database, Env, EnvOpenOptions};
+use ruc::*;
+use std::{fs, ops::Bound};
+
+// all writes are serialized by lmdb,
+// areas only keep the b-trees smaller
+const DATA_SET_NUM: usize = 4;
+
+// only the virtual address space is reserved,
+// the data file grows on demand
+const MAP_SIZE: usize = 1 << 40;
+
+const META_KEY_BRANCH_ID: [u8; 1] = [u8::MAX - 1];
+const META_KEY_VERSION_ID: [u8; 1] = [u8::MAX - 2];
+const META_KEY_PREFIX_ALLOCATOR: [u8; 1] = [u8::MIN];
+
+type Db = Database<ByteSlice, ByteSlice>;
+
+pub(crate) struct LmdbEngine {
+    env: Env,
+    meta: Db,
+    areas: Vec<Db>,
+}
+
+impl LmdbEngine {
+    #[inline(always)]
+    fn get_from(&self, db: Db, key: &[u8]) -> Option<RawValue> {
+        let txn = self.env.read_txn().unwrap();
+        db.get(&txn, key)
+            .unwrap()
+            .map(|v| v.to_vec().into_boxed_slice())
+    }
+
+    #[inline(always)]
+    fn put_to(&self, db: Db, key: &[u8], value: &[u8]) -> Option<RawValue> {
+        let mut txn = self.env.write_txn().unwrap();
+        let old_v = db
+            .get(&txn, key)
+            .unwrap()
+            .map(|v| v.to_vec().into_boxed_slice());
+        db.put(&mut txn, key, value).unwrap();
+        txn.commit().unwrap();
+        old_v
+    }
+
+    // Write transactions are serialized by lmdb,
+    // so no extra lock is needed.
+    fn alloc(&self, key: &[u8]) -> u64 {
+        let mut txn = self.env.write_txn().unwrap();
+        let ret = crate::parse_int!(self.meta.get(&txn, key).unwrap().unwrap(), u64);
+        self.meta
+            .put(&mut txn, key, &(1 + ret).to_be_bytes())
+            .unwrap();
+        txn.commit().unwrap();
+        ret
+    }
+}
+
+impl Engine for LmdbEngine {
+    fn new(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir).c(d!())?;
+
+        let mut opts = EnvOpenOptions::new();
+        let area_cnt = areas::area_count(DATA_SET_NUM);
+        opts.map_size(MAP_SIZE).max_dbs(1 + area_cnt as u32);
+
+        // data is synced by `flush`, like other engines
+        unsafe {
+            opts.flag(Flags::MdbNoSync);
+        }
+
+        // lmdb can be shared by processes natively,
+        // a replica just opens it in the read-only mode
+        if replica::vsdb_is_replica() {
+            unsafe {
+                opts.flag(Flags::MdbRdOnly);
+            }
+            let env = opts.open(dir).c(d!())?;
+            let open = |name: &str| {
+                env.open_database::<ByteSlice, ByteSlice>(Some(name))
+                    .c(d!())?
+                    .c(d!("the primary has not been initialized"))
+            };
+            let meta = open("meta").c(d!())?;
+            let areas = (0..area_cnt)
+                .map(|i| open(&i.to_string()).c(d!()))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(LmdbEngine { env, meta, areas });
+        }
+
+        let env = opts.open(dir).c(d!())?;
+        let meta = env.create_database(Some("meta")).c(d!())?;
+        let areas = (0..area_cnt)
+            .map(|i| env.create_database(Some(&i.to_string())).c(d!()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut txn = env.write_txn().c(d!())?;
+        let initial_values = [
+            (
+                META_KEY_BRANCH_ID,
+                (1 + INITIAL_BRANCH_ID as usize).to_be_bytes(),
+            ),
+            (META_KEY_VERSION_ID, 0_usize.to_be_bytes()),
+            (
+                META_KEY_PREFIX_ALLOCATOR,
+                (RESERVED_ID_CNT + Prefix::MIN).to_be_bytes(),
+            ),
+        ];
+        for (k, v) in initial_values.iter() {
+            if meta.get(&txn, k).c(d!())?.is_none() {
+                meta.put(&mut txn, k, v).c(d!())?;
+            }
+        }
+        txn.commit().c(d!())?;
+
+        Ok(LmdbEngine { env, meta, areas })
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.alloc(&META_KEY_PREFIX_ALLOCATOR)
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.alloc(&META_KEY_BRANCH_ID)
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.alloc(&META_KEY_VERSION_ID)
+    }
+
+    fn area_count(&self) -> usize {
+        self.areas.len()
+    }
+
+    fn flush(&self) {
+        self.env.force_sync().unwrap();
+    }
+
+    // all areas are in the same data file
+    fn flush_area(&self, _: usize) {
+        self.flush();
+    }
+
+    fn set_cache_size(&self, _: usize) -> Result<()> {
+        Err(eg!("lmdb relies on the page cache of the OS"))
+    }
+
+    // every new read transaction sees the latest committed data
+    fn refresh(&self) -> Result<()> {
+        if !replica::vsdb_is_replica() {
+            return Err(eg!("not a replica"));
+        }
+        Ok(())
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        EngineIter::builtin(LmdbIter {
+            env: self.env.clone(),
+            db: self.areas[area_idx],
+            bounds: SeekBounds::all(meta_prefix),
+        })
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        EngineIter::builtin(LmdbIter {
+            env: self.env.clone(),
+            db: self.areas[area_idx],
+            bounds: SeekBounds::new(meta_prefix, bounds),
+        })
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.get_from(self.areas[area_idx], &k)
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.put_to(self.areas[area_idx], &k, value)
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+
+        let db = self.areas[area_idx];
+        let mut txn = self.env.write_txn().unwrap();
+        let old_v = db
+            .get(&txn, &k)
+            .unwrap()
+            .map(|v| v.to_vec().into_boxed_slice());
+        db.delete(&mut txn, &k).unwrap();
+        txn.commit().unwrap();
+        old_v
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        crate::parse_int!(self.get_from(self.meta, &instance_prefix).unwrap(), u64)
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.get_from(self.meta, &instance_prefix).is_some()
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        self.put_to(self.meta, &instance_prefix, &new_len.to_be_bytes());
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.get_from(self.meta, &append_only_meta_key(instance_prefix))
+            .is_some()
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.put_to(self.meta, &append_only_meta_key(instance_prefix), &[1_u8]);
+    }
+}
+
+// Read transactions can not be held across steps, they block the
+// reuse of pages by writers, so a new one is opened on every step.
+pub struct LmdbIter {
+    env: Env,
+    db: Db,
+    bounds: SeekBounds,
+}
+
+impl LmdbIter {
+    fn step(&mut self, forward: bool) -> Option<(RawKey, RawValue)> {
+        alt!(self.bounds.exhausted(), return None);
+
+        let txn = self.env.read_txn().unwrap();
+        let bounds = self.bounds.as_slices();
+        let (k, v) = if forward {
+            self.db.range(&txn, &bounds).unwrap().next()
+        } else {
+            self.db.rev_range(&txn, &bounds).unwrap().next()
+        }?
+        .unwrap();
+        let k = k.to_vec().into_boxed_slice();
+        let ret = (
+            k[PREFIX_SIZ..].to_vec().into_boxed_slice(),
+            v.to_vec().into_boxed_slice(),
+        );
+        drop(txn);
+
+        alt!(forward, self.bounds.move_lo(k), self.bounds.move_hi(k));
+        Some(ret)
+    }
+}
+
+impl Iterator for LmdbIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(true)
+    }
+}
+
+impl DoubleEndedIterator for LmdbIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.step(false)
+    }
+}
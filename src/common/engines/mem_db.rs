@@ -0,0 +1,227 @@
+use crate::common::{
+    areas,
+    engines::{append_only_meta_key, seek::SeekBounds, EngineIter},
+    replica,
+    stats::{AreaStorageStats, StorageStats},
+    BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
+};
+use parking_lot::RwLock;
+use ruc::*;
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+// only used to reduce the contention of locks
+const DATA_SET_NUM: usize = 16;
+
+type Area = Arc<RwLock<BTreeMap<RawKey, RawValue>>>;
+
+// Nothing is persisted, all data will be lost after the process exits,
+// mainly used in tests and CI.
+pub(crate) struct MemEngine {
+    // instance lens and append-only flags
+    meta: RwLock<BTreeMap<RawKey, RawValue>>,
+    areas: Vec<Area>,
+    prefix_allocator: AtomicU64,
+    branch_id_allocator: AtomicU64,
+    version_id_allocator: AtomicU64,
+}
+
+impl Engine for MemEngine {
+    fn new(_: &str) -> Result<Self> {
+        // there is nothing to be shared with other processes
+        if replica::vsdb_is_replica() {
+            return Err(eg!("the memory engine does not support the replica mode"));
+        }
+
+        Ok(MemEngine {
+            meta: RwLock::new(BTreeMap::new()),
+            areas: (0..areas::area_count(DATA_SET_NUM))
+                .map(|_| Area::default())
+                .collect(),
+            prefix_allocator: AtomicU64::new(RESERVED_ID_CNT + Prefix::MIN),
+            branch_id_allocator: AtomicU64::new(1 + INITIAL_BRANCH_ID),
+            version_id_allocator: AtomicU64::new(0),
+        })
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.prefix_allocator.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.branch_id_allocator.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.version_id_allocator.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn area_count(&self) -> usize {
+        self.areas.len()
+    }
+
+    fn flush(&self) {}
+
+    fn flush_area(&self, _: usize) {}
+
+    // no cache at all
+    fn set_cache_size(&self, _: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Err(eg!("the memory engine does not support the replica mode"))
+    }
+
+    // removed entries are dropped at once, nothing is on the disk
+    fn storage_stats(&self) -> StorageStats {
+        let areas = self
+            .areas
+            .iter()
+            .map(|a| AreaStorageStats {
+                live_bytes: Some(
+                    a.read()
+                        .iter()
+                        .map(|(k, v)| (k.len() + v.len()) as u64)
+                        .sum(),
+                ),
+                disk_bytes: Some(0),
+                tombstones: Some(0),
+            })
+            .collect();
+        StorageStats {
+            pending_compaction_bytes: Some(0),
+            ..StorageStats::from_areas(areas)
+        }
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        EngineIter::builtin(MemIter {
+            area: Arc::clone(&self.areas[area_idx]),
+            bounds: SeekBounds::all(meta_prefix),
+        })
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        EngineIter::builtin(MemIter {
+            area: Arc::clone(&self.areas[area_idx]),
+            bounds: SeekBounds::new(meta_prefix, bounds),
+        })
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.areas[area_idx].read().get(k.as_slice()).cloned()
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.areas[area_idx]
+            .write()
+            .insert(k.into_boxed_slice(), value.to_vec().into_boxed_slice())
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.areas[area_idx].write().remove(k.as_slice())
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        crate::parse_int!(
+            self.meta.read().get(instance_prefix.as_slice()).unwrap(),
+            u64
+        )
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta.read().contains_key(instance_prefix.as_slice())
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        self.meta
+            .write()
+            .insert(Box::new(instance_prefix), Box::new(new_len.to_be_bytes()));
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta
+            .read()
+            .contains_key(append_only_meta_key(instance_prefix).as_slice())
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.meta.write().insert(
+            Box::new(append_only_meta_key(instance_prefix)),
+            Box::new([1_u8]),
+        );
+    }
+}
+
+// The area is read again on every step, so no lock is held between steps,
+// and changes made during the iteration may be seen, just like sled.
+pub struct MemIter {
+    area: Area,
+    bounds: SeekBounds,
+}
+
+impl Iterator for MemIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        alt!(self.bounds.exhausted(), return None);
+        let (k, v) = self
+            .area
+            .read()
+            .range::<[u8], _>(self.bounds.as_slices())
+            .next()
+            .map(|(k, v)| (k.clone(), v.clone()))?;
+        let ret = (k[PREFIX_SIZ..].to_vec().into_boxed_slice(), v);
+        self.bounds.move_lo(k);
+        Some(ret)
+    }
+}
+
+impl DoubleEndedIterator for MemIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        alt!(self.bounds.exhausted(), return None);
+        let (k, v) = self
+            .area
+            .read()
+            .range::<[u8], _>(self.bounds.as_slices())
+            .next_back()
+            .map(|(k, v)| (k.clone(), v.clone()))?;
+        let ret = (k[PREFIX_SIZ..].to_vec().into_boxed_slice(), v);
+        self.bounds.move_hi(k);
+        Some(ret)
+    }
+}
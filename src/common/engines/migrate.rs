@@ -0,0 +1,125 @@
+//!
+//! Migration between builtin engines.
+//!
+//! All entries of the meta store and the areas are streamed from one engine
+//! into another, entries of each instance are moved to the area that the
+//! target engine expects, so instances, branches and versions are all kept,
+//! and the data can be used by the target engine directly.
+//!
+
+use crate::common::{
+    areas,
+    engines::{rocks_db::RocksEngine, sled_db::SledEngine, Engine},
+    RawKey, RawValue, PREFIX_SIZ,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::{collections::HashMap, fs, path::Path};
+
+// engines are never closed, rocksdb can not be closed after being opened,
+// and a directory can be used by several migrations
+static OPENED: Lazy<Mutex<HashMap<String, (EngineKind, &'static dyn Migratable)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Builtin engines that data can be migrated between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineKind {
+    Sled,
+    Rocksdb,
+}
+
+// Raw access to all data of a builtin engine.
+pub(crate) trait Migratable: Engine {
+    // entries of the meta store, private ones of the engine are excluded
+    fn meta_entries(&self) -> Box<dyn Iterator<Item = (RawKey, RawValue)> + '_>;
+
+    // entries of an area, keys are returned with their prefixes
+    fn area_entries(
+        &self,
+        area_idx: usize,
+    ) -> Box<dyn Iterator<Item = (RawKey, RawValue)> + '_>;
+
+    fn put_meta(&self, key: &[u8], value: &[u8]);
+}
+
+/// Copy all data in `src_dir`, which is written by `src_engine`,
+/// into `dst_dir` in the format of `dst_engine`.
+///
+/// NOTE:
+/// - Neither of them should be opened by VSDB, or by other processes
+/// - `dst_dir` must be empty or not exist
+/// - Both databases are kept opened until the process exits,
+///   so it should be done in a standalone process before starting the service
+/// - Encrypted data is copied as it is, the same secrets are needed to read it
+pub fn vsdb_migrate(
+    src_dir: impl AsRef<Path>,
+    src_engine: EngineKind,
+    dst_dir: impl AsRef<Path>,
+    dst_engine: EngineKind,
+) -> Result<()> {
+    let (src_dir, dst_dir) = (src_dir.as_ref(), dst_dir.as_ref());
+
+    if !src_dir.is_dir() {
+        return Err(eg!(format!("{} is not a directory", src_dir.display())));
+    }
+    if dst_dir.exists() && fs::read_dir(dst_dir).c(d!())?.next().is_some() {
+        return Err(eg!(format!("{} is not empty", dst_dir.display())));
+    }
+    fs::create_dir_all(dst_dir).c(d!())?;
+
+    let src_dir = src_dir.canonicalize().c(d!())?;
+    let dst_dir = dst_dir.canonicalize().c(d!())?;
+    if src_dir == dst_dir {
+        return Err(eg!("the source and the target are the same directory"));
+    }
+
+    let src = open_engine(&src_dir.to_string_lossy(), src_engine).c(d!())?;
+    let dst = open_engine(&dst_dir.to_string_lossy(), dst_engine).c(d!())?;
+
+    // allocators of prefixes, branch IDs and version IDs,
+    // lengths and append-only flags of instances
+    for (k, v) in src.meta_entries() {
+        dst.put_meta(&k, &v);
+    }
+
+    // the number of areas may be different between engines
+    let dst_area_cnt = dst.area_count();
+    for area_idx in 0..src.area_count() {
+        for (k, v) in src.area_entries(area_idx) {
+            let prefix = crate::parse_prefix!(k[..PREFIX_SIZ]);
+            dst.insert(
+                areas::area_of(prefix, dst_area_cnt),
+                prefix.to_be_bytes(),
+                &k[PREFIX_SIZ..],
+                &v,
+            );
+        }
+    }
+
+    dst.flush();
+
+    Ok(())
+}
+
+// Open an engine in `dir`, the same one is returned if `dir` has been opened.
+pub(crate) fn open_engine(
+    dir: &str,
+    kind: EngineKind,
+) -> Result<&'static dyn Migratable> {
+    let mut opened = OPENED.lock();
+    if let Some((k, engine)) = opened.get(dir) {
+        if *k != kind {
+            return Err(eg!(format!("{} has been opened as {:?}", dir, k)));
+        }
+        return Ok(*engine);
+    }
+
+    let engine: &'static dyn Migratable = match kind {
+        EngineKind::Sled => Box::leak(Box::new(SledEngine::new(dir).c(d!())?)),
+        EngineKind::Rocksdb => Box::leak(Box::new(RocksEngine::new(dir).c(d!())?)),
+    };
+    opened.insert(dir.to_owned(), (kind, engine));
+
+    Ok(engine)
+}
@@ -0,0 +1,509 @@
+use crate::common::{
+    areas,
+    engines::{append_only_meta_key, seek::SeekBounds, EngineIter},
+    replica,
+    stats::{AreaStorageStats, StorageStats},
+    BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
+};
+use memmap2::MmapMut;
+use parking_lot::RwLock;
+use ruc::*;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// every area is an independent log, named as `area_${idx}.log`
+const DATA_SET_NUM: usize = 4;
+
+const META_FILE: &str = "meta.log";
+
+// log files grow by this size at least, and are mapped as a whole
+const GROW_SIZ: u64 = 32 * 1024 * 1024;
+
+// checksum(8) + key length(4) + value length(4)
+const HDR_SIZ: usize = 16;
+
+// the value length of a removal record
+const TOMBSTONE: u32 = u32::MAX;
+
+const META_KEY_BRANCH_ID: [u8; 1] = [u8::MAX - 1];
+const META_KEY_VERSION_ID: [u8; 1] = [u8::MAX - 2];
+const META_KEY_PREFIX_ALLOCATOR: [u8; 1] = [u8::MIN];
+
+type Area = Arc<RwLock<Log>>;
+
+// Every write is appended to the end of a memory-mapped log,
+// no LSM-tree or B-tree is maintained, so sequential workloads,
+// such as pushing to a `Vecx`, are cheap.
+//
+// Keys are indexed in memory, the index is rebuilt by scanning the logs
+// when the engine is opened, and a log is rewritten when most of it is dead.
+pub(crate) struct MmapEngine {
+    // instance lens, append-only flags and allocators
+    meta: RwLock<Log>,
+    areas: Vec<Area>,
+}
+
+impl MmapEngine {
+    fn alloc(&self, key: &[u8]) -> u64 {
+        let mut meta = self.meta.write();
+        let ret = crate::parse_int!(meta.get(key).unwrap(), u64);
+        pnk!(meta.put(key, &(1 + ret).to_be_bytes()));
+        ret
+    }
+}
+
+impl Engine for MmapEngine {
+    fn new(dir: &str) -> Result<Self> {
+        // the logs are mapped and modified by this process alone
+        if replica::vsdb_is_replica() {
+            return Err(eg!("the mmap engine does not support the replica mode"));
+        }
+
+        fs::create_dir_all(dir).c(d!())?;
+        let dir = Path::new(dir);
+
+        let mut meta = Log::open(dir.join(META_FILE)).c(d!())?;
+        let initial_values = [
+            (META_KEY_BRANCH_ID, (1 + INITIAL_BRANCH_ID).to_be_bytes()),
+            (META_KEY_VERSION_ID, 0_u64.to_be_bytes()),
+            (
+                META_KEY_PREFIX_ALLOCATOR,
+                (RESERVED_ID_CNT + Prefix::MIN).to_be_bytes(),
+            ),
+        ];
+        for (k, v) in initial_values.iter() {
+            if meta.get(k).is_none() {
+                meta.put(k, v).c(d!())?;
+            }
+        }
+
+        let areas = (0..areas::area_count(DATA_SET_NUM))
+            .map(|i| {
+                Log::open(dir.join(format!("area_{}.log", i)))
+                    .c(d!())
+                    .map(|l| Arc::new(RwLock::new(l)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MmapEngine {
+            meta: RwLock::new(meta),
+            areas,
+        })
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.alloc(&META_KEY_PREFIX_ALLOCATOR)
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.alloc(&META_KEY_BRANCH_ID)
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.alloc(&META_KEY_VERSION_ID)
+    }
+
+    fn area_count(&self) -> usize {
+        self.areas.len()
+    }
+
+    fn flush(&self) {
+        self.areas.iter().for_each(|a| {
+            pnk!(a.read().sync());
+        });
+        pnk!(self.meta.read().sync());
+    }
+
+    // the meta holds the length of instances,
+    // so it should be flushed together with the target area.
+    fn flush_area(&self, area_idx: usize) {
+        pnk!(self.areas[area_idx].read().sync());
+        pnk!(self.meta.read().sync());
+    }
+
+    // data is cached by the page cache of the OS
+    fn set_cache_size(&self, _: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Err(eg!("the mmap engine does not support the replica mode"))
+    }
+
+    // dead bytes are reclaimed when logs are rewritten
+    fn storage_stats(&self) -> StorageStats {
+        let areas = self
+            .areas
+            .iter()
+            .map(|a| {
+                let log = a.read();
+                AreaStorageStats {
+                    live_bytes: Some(log.tail - log.dead_bytes),
+                    disk_bytes: Some(log.mmap.len() as u64),
+                    tombstones: Some(log.tombstones),
+                }
+            })
+            .collect();
+        StorageStats {
+            pending_compaction_bytes: Some(
+                self.areas.iter().map(|a| a.read().dead_bytes).sum(),
+            ),
+            ..StorageStats::from_areas(areas)
+        }
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        EngineIter::builtin(MmapIter {
+            area: Arc::clone(&self.areas[area_idx]),
+            bounds: SeekBounds::all(meta_prefix),
+        })
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        EngineIter::builtin(MmapIter {
+            area: Arc::clone(&self.areas[area_idx]),
+            bounds: SeekBounds::new(meta_prefix, bounds),
+        })
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.areas[area_idx].read().get(&k)
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        pnk!(self.areas[area_idx].write().put(&k, value))
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        pnk!(self.areas[area_idx].write().delete(&k))
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        crate::parse_int!(self.meta.read().get(&instance_prefix).unwrap(), u64)
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta
+            .read()
+            .index
+            .contains_key(instance_prefix.as_slice())
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        pnk!(self
+            .meta
+            .write()
+            .put(&instance_prefix, &new_len.to_be_bytes()));
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta
+            .read()
+            .index
+            .contains_key(append_only_meta_key(instance_prefix).as_slice())
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        pnk!(self
+            .meta
+            .write()
+            .put(&append_only_meta_key(instance_prefix), &[1]));
+    }
+}
+
+// The index is read again on every step, so no lock is held between steps,
+// and changes made during the iteration may be seen, just like sled.
+pub struct MmapIter {
+    area: Area,
+    bounds: SeekBounds,
+}
+
+impl Iterator for MmapIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        alt!(self.bounds.exhausted(), return None);
+        let (k, v) = {
+            let log = self.area.read();
+            log.index
+                .range::<[u8], _>(self.bounds.as_slices())
+                .next()
+                .map(|(k, &(off, len))| {
+                    (k.clone(), RawValue::from(log.value(off, len)))
+                })?
+        };
+        let ret = (k[PREFIX_SIZ..].to_vec().into_boxed_slice(), v);
+        self.bounds.move_lo(k);
+        Some(ret)
+    }
+}
+
+impl DoubleEndedIterator for MmapIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        alt!(self.bounds.exhausted(), return None);
+        let (k, v) = {
+            let log = self.area.read();
+            log.index
+                .range::<[u8], _>(self.bounds.as_slices())
+                .next_back()
+                .map(|(k, &(off, len))| {
+                    (k.clone(), RawValue::from(log.value(off, len)))
+                })?
+        };
+        let ret = (k[PREFIX_SIZ..].to_vec().into_boxed_slice(), v);
+        self.bounds.move_hi(k);
+        Some(ret)
+    }
+}
+
+// An append-only log in a memory-mapped file.
+//
+// Records: [ checksum(8) ][ key length(4) ][ value length(4) ][ key ][ value ],
+// a removal is recorded with `TOMBSTONE` as the value length and no value.
+struct Log {
+    path: PathBuf,
+    file: File,
+    mmap: MmapMut,
+    // where the next record will be written
+    tail: u64,
+    // key => (offset of the value, length of the value)
+    index: BTreeMap<RawKey, (u64, u32)>,
+    // taken by overwritten values, removed values and tombstones
+    dead_bytes: u64,
+    tombstones: u64,
+}
+
+impl Log {
+    fn open(path: PathBuf) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)
+            .c(d!())?;
+        if 0 == file.metadata().c(d!())?.len() {
+            file.set_len(GROW_SIZ).c(d!())?;
+        }
+
+        // the file is only modified through this mapping
+        let mmap = unsafe { MmapMut::map_mut(&file) }.c(d!())?;
+
+        let mut log = Log {
+            path,
+            file,
+            mmap,
+            tail: 0,
+            index: BTreeMap::new(),
+            dead_bytes: 0,
+            tombstones: 0,
+        };
+        log.load();
+        log.maybe_compact().c(d!())?;
+
+        Ok(log)
+    }
+
+    // Rebuild the index, stop at the first broken record,
+    // which may be left by a crash.
+    fn load(&mut self) {
+        let mut off = 0;
+        while let Some((key, value, siz)) = read_record(&self.mmap, off) {
+            let key = key.to_vec().into_boxed_slice();
+            let klen = key.len();
+            if let Some((voff, vlen)) = value {
+                if let Some((_, old_vlen)) = self.index.insert(key, (voff, vlen)) {
+                    self.dead_bytes += record_siz(klen, old_vlen as usize);
+                }
+            } else {
+                if let Some((_, old_vlen)) = self.index.remove(&key) {
+                    self.dead_bytes += record_siz(klen, old_vlen as usize);
+                }
+                self.dead_bytes += siz as u64;
+                self.tombstones += 1;
+            }
+            off += siz;
+        }
+        self.tail = off as u64;
+
+        // the remains of a broken record may be parsed
+        // as valid ones after new records are written
+        if self.mmap[off..].iter().take(HDR_SIZ).any(|b| 0 != *b) {
+            self.mmap[off..].fill(0);
+        }
+    }
+
+    #[inline(always)]
+    fn value(&self, off: u64, len: u32) -> &[u8] {
+        &self.mmap[off as usize..off as usize + len as usize]
+    }
+
+    fn get(&self, key: &[u8]) -> Option<RawValue> {
+        self.index
+            .get(key)
+            .map(|&(off, len)| self.value(off, len).into())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Option<RawValue>> {
+        if TOMBSTONE as usize <= value.len() {
+            return Err(eg!("the value is too large"));
+        }
+
+        let old = self.get(key);
+        let off = self.append(key, Some(value)).c(d!())?;
+        if let Some(v) = old.as_ref() {
+            self.dead_bytes += record_siz(key.len(), v.len());
+        }
+        self.index.insert(key.into(), (off, value.len() as u32));
+
+        self.maybe_compact().c(d!()).map(|_| old)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Option<RawValue>> {
+        let old = self.get(key);
+        if let Some(v) = old.as_ref() {
+            self.append(key, None).c(d!())?;
+            self.index.remove(key);
+            self.dead_bytes += record_siz(key.len(), v.len()) + record_siz(key.len(), 0);
+            self.tombstones += 1;
+        }
+
+        self.maybe_compact().c(d!()).map(|_| old)
+    }
+
+    // Return the offset of the value.
+    fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<u64> {
+        let vlen = value.map(|v| v.len()).unwrap_or(0);
+        let siz = record_siz(key.len(), vlen) as usize;
+        self.reserve(siz).c(d!())?;
+
+        let off = self.tail as usize;
+        let rec = &mut self.mmap[off..off + siz];
+        rec[8..12].copy_from_slice(&(key.len() as u32).to_be_bytes());
+        rec[12..16].copy_from_slice(
+            &value
+                .map(|v| v.len() as u32)
+                .unwrap_or(TOMBSTONE)
+                .to_be_bytes(),
+        );
+        rec[HDR_SIZ..HDR_SIZ + key.len()].copy_from_slice(key);
+        if let Some(v) = value {
+            rec[HDR_SIZ + key.len()..].copy_from_slice(v);
+        }
+        let sum = checksum(&rec[8..]);
+        rec[..8].copy_from_slice(&sum.to_be_bytes());
+
+        self.tail += siz as u64;
+        Ok((off + HDR_SIZ + key.len()) as u64)
+    }
+
+    fn reserve(&mut self, siz: usize) -> Result<()> {
+        let needed = self.tail + siz as u64;
+        let cur = self.mmap.len() as u64;
+        if needed <= cur {
+            return Ok(());
+        }
+        self.file.set_len(needed.max(cur + GROW_SIZ)).c(d!())?;
+        self.mmap = unsafe { MmapMut::map_mut(&self.file) }.c(d!())?;
+        Ok(())
+    }
+
+    // Rewrite the live entries into a new file when most of the log is dead.
+    fn maybe_compact(&mut self) -> Result<()> {
+        if self.dead_bytes < GROW_SIZ || self.dead_bytes < self.tail - self.dead_bytes {
+            return Ok(());
+        }
+
+        // left by an interrupted compaction
+        let tmp = self.path.with_extension("compacting");
+        if tmp.exists() {
+            fs::remove_file(&tmp).c(d!())?;
+        }
+
+        let mut new = Log::open(tmp.clone()).c(d!())?;
+        for (k, &(off, len)) in self.index.iter() {
+            new.put(k, self.value(off, len)).c(d!())?;
+        }
+        new.sync().c(d!())?;
+
+        fs::rename(&tmp, &self.path).c(d!())?;
+        new.path = self.path.clone();
+        *self = new;
+
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.mmap.flush().c(d!())?;
+        self.file.sync_all().c(d!())
+    }
+}
+
+#[inline(always)]
+fn record_siz(key_len: usize, value_len: usize) -> u64 {
+    (HDR_SIZ + key_len + value_len) as u64
+}
+
+// Return the key, the (offset, length) of the value(`None` for a removal),
+// and the size of the record.
+#[allow(clippy::type_complexity)]
+fn read_record(buf: &[u8], off: usize) -> Option<(&[u8], Option<(u64, u32)>, usize)> {
+    let hdr = buf.get(off..off + HDR_SIZ)?;
+    let sum = crate::parse_int!(hdr[..8], u64);
+    let klen = crate::parse_int!(hdr[8..12], u32) as usize;
+    let vlen = crate::parse_int!(hdr[12..16], u32);
+
+    // the unwritten space is filled with zero
+    alt!(0 == klen, return None);
+
+    let body_len = klen + alt!(TOMBSTONE == vlen, 0, vlen as usize);
+    let rec = buf.get(off..off + HDR_SIZ + body_len)?;
+    alt!(sum != checksum(&rec[8..]), return None);
+
+    let key = &rec[HDR_SIZ..HDR_SIZ + klen];
+    let value = alt!(
+        TOMBSTONE == vlen,
+        None,
+        Some(((off + HDR_SIZ + klen) as u64, vlen))
+    );
+    Some((key, value, rec.len()))
+}
+
+// FNV-1a, only used to find broken records,
+// the data itself is protected by the OS like other engines.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -1,58 +1,302 @@
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
 
-#[cfg(all(feature = "rocks_engine", not(feature = "sled_engine")))]
+mod custom;
+mod sharded;
+
+#[cfg(feature = "encryption")]
+mod encrypted;
+
+#[cfg(feature = "mem_engine")]
+mod mem_db;
+
+#[cfg(all(feature = "lmdb_engine", not(feature = "mem_engine")))]
+mod lmdb_db;
+
+#[cfg(all(
+    feature = "paritydb_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine")
+))]
+mod paritydb_db;
+
+#[cfg(all(
+    feature = "sqlite_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine")
+))]
+mod sqlite_db;
+
+#[cfg(all(
+    feature = "mmap_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine")
+))]
+mod mmap_db;
+
+#[cfg(any(
+    feature = "mem_engine",
+    feature = "lmdb_engine",
+    feature = "paritydb_engine",
+    feature = "sqlite_engine",
+    feature = "mmap_engine"
+))]
+mod seek;
+
+#[cfg(feature = "remote_engine")]
+mod remote_db;
+
+#[cfg(feature = "migration")]
+mod migrate;
+
+// both of sled and rocksdb are compiled for the migration,
+// sled is the builtin one in that case
+#[cfg(any(
+    all(
+        feature = "rocks_engine",
+        not(feature = "sled_engine"),
+        not(feature = "mem_engine"),
+        not(feature = "lmdb_engine"),
+        not(feature = "paritydb_engine"),
+        not(feature = "sqlite_engine"),
+        not(feature = "mmap_engine")
+    ),
+    feature = "migration"
+))]
 mod rocks_db;
 
-#[cfg(all(feature = "sled_engine", not(feature = "rocks_engine")))]
+#[cfg(any(
+    all(
+        feature = "sled_engine",
+        not(feature = "mem_engine"),
+        not(feature = "lmdb_engine"),
+        not(feature = "paritydb_engine"),
+        not(feature = "sqlite_engine"),
+        not(feature = "mmap_engine")
+    ),
+    feature = "migration"
+))]
 mod sled_db;
 
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
 
-#[cfg(all(feature = "rocks_engine", not(feature = "sled_engine")))]
-pub(crate) use rocks_db::RocksEngine as RocksDB;
-
-#[cfg(all(feature = "sled_engine", not(feature = "rocks_engine")))]
-pub(crate) use sled_db::SledEngine as Sled;
-
-#[cfg(all(feature = "sled_engine", not(feature = "rocks_engine")))]
-pub type MapxIter = sled_db::SledIter;
-
-#[cfg(all(feature = "rocks_engine", not(feature = "sled_engine")))]
-pub type MapxIter = rocks_db::RocksIter;
+#[cfg(all(
+    feature = "rocks_engine",
+    not(feature = "sled_engine"),
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine"),
+    not(feature = "mmap_engine")
+))]
+pub(crate) use rocks_db::RocksEngine as BuiltinEngine;
+
+#[cfg(all(
+    feature = "sled_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine"),
+    not(feature = "mmap_engine")
+))]
+pub(crate) use sled_db::SledEngine as BuiltinEngine;
+
+#[cfg(feature = "mem_engine")]
+pub(crate) use mem_db::MemEngine as BuiltinEngine;
+
+#[cfg(all(feature = "lmdb_engine", not(feature = "mem_engine")))]
+pub(crate) use lmdb_db::LmdbEngine as BuiltinEngine;
+
+#[cfg(all(
+    feature = "paritydb_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine")
+))]
+pub(crate) use paritydb_db::ParityDbEngine as BuiltinEngine;
+
+#[cfg(all(
+    feature = "sqlite_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine")
+))]
+pub(crate) use sqlite_db::SqliteEngine as BuiltinEngine;
+
+#[cfg(all(
+    feature = "mmap_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine")
+))]
+pub(crate) use mmap_db::MmapEngine as BuiltinEngine;
+
+#[cfg(all(
+    feature = "sled_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine"),
+    not(feature = "mmap_engine")
+))]
+type BuiltinIter = sled_db::SledIter;
+
+#[cfg(all(
+    feature = "rocks_engine",
+    not(feature = "sled_engine"),
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine"),
+    not(feature = "mmap_engine")
+))]
+type BuiltinIter = rocks_db::RocksIter;
+
+#[cfg(feature = "mem_engine")]
+type BuiltinIter = mem_db::MemIter;
+
+#[cfg(all(feature = "lmdb_engine", not(feature = "mem_engine")))]
+type BuiltinIter = lmdb_db::LmdbIter;
+
+#[cfg(all(
+    feature = "paritydb_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine")
+))]
+type BuiltinIter = paritydb_db::ParityDbIter;
+
+#[cfg(all(
+    feature = "sqlite_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine")
+))]
+type BuiltinIter = sqlite_db::SqliteIter;
+
+#[cfg(all(
+    feature = "mmap_engine",
+    not(feature = "mem_engine"),
+    not(feature = "lmdb_engine"),
+    not(feature = "paritydb_engine"),
+    not(feature = "sqlite_engine")
+))]
+type BuiltinIter = mmap_db::MmapIter;
+
+pub use custom::vsdb_register_engine;
+pub(crate) use custom::Backend;
+
+pub use sharded::vsdb_set_shard_dirs;
+#[cfg(test)]
+pub(crate) use sharded::Sharded;
+
+#[cfg(all(test, feature = "encryption"))]
+pub(crate) use encrypted::Encrypted;
+#[cfg(feature = "encryption")]
+pub use encrypted::{vsdb_set_encryption, EncryptionKey, EncryptionOpts};
+
+#[cfg(all(test, feature = "migration"))]
+pub(crate) use migrate::open_engine;
+#[cfg(feature = "migration")]
+pub use migrate::{vsdb_migrate, EngineKind};
+
+#[cfg(feature = "remote_engine")]
+pub(crate) use remote_db::RemoteEngine;
 
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
 
 use crate::common::{
+    append_only::{self, AppendOnlyViolation},
+    areas,
+    compression::Compression,
+    config, database,
     ende::{SimpleVisitor, ValueEnDe},
-    BranchID, Prefix, PrefixBytes, RawValue, VersionID, VSDB,
+    mirror, namespace,
+    stats::StorageStats,
+    ttl, BranchID, EngineImpl, Prefix, PrefixBytes, RawKey, RawValue, VersionID, VsDB,
+    BIGGEST_RESERVED_ID, COMPRESSION_REGISTRY, PREFIX_SIZ,
 };
+use parking_lot::MutexGuard;
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::{ops::RangeBounds, result::Result as StdResult};
+use std::{
+    collections::BTreeSet,
+    fmt,
+    ops::{Bound, RangeBounds},
+    result::Result as StdResult,
+    time::Duration,
+};
 
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
 
 /// Low-level database interface.
-pub trait Engine: Sized {
-    fn new() -> Result<Self>;
+///
+/// The builtin engines are selected by features, a custom one can be
+/// plugged in by [vsdb_register_engine](crate::vsdb_register_engine).
+///
+/// Data is divided into areas, each instance lives in one of them,
+/// and its keys are stored with its prefix(8 bytes) in front.
+/// The meta store keeps the lengths and the append-only flags of instances,
+/// they are accessed by the prefix of an instance, keys and values are
+/// just passed to the engine as raw bytes.
+pub trait Engine: Send + Sync {
+    /// Open(or create) a database in `dir`.
+    fn new(dir: &str) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Allocate a new prefix for an instance, never returns the same value twice,
+    /// starts from `4096_0000`, the smaller ones are reserved.
     fn alloc_prefix(&self) -> Prefix;
+
+    /// Allocate a new branch ID, starts from `1`.
     fn alloc_branch_id(&self) -> BranchID;
+
+    /// Allocate a new version ID, starts from `0`.
     fn alloc_version_id(&self) -> VersionID;
+
+    /// How many areas the data is divided into, it must not change between restarts.
     fn area_count(&self) -> usize;
+
+    /// Persist all data.
     fn flush(&self);
 
-    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> MapxIter;
+    /// Persist data of an area along with the meta store.
+    fn flush_area(&self, _area_idx: usize) {
+        self.flush();
+    }
+
+    /// Resize the cache of the opened database.
+    fn set_cache_size(&self, _bytes: usize) -> Result<()> {
+        Err(eg!("the cache is not resizable"))
+    }
+
+    /// Catch up with the primary, only available in the replica mode.
+    fn refresh(&self) -> Result<()> {
+        Err(eg!("the replica mode is not supported"))
+    }
 
-    fn range<'a, R: RangeBounds<&'a [u8]>>(
+    /// Statistics of the storage, nothing is tracked by default.
+    fn storage_stats(&self) -> StorageStats {
+        StorageStats::default()
+    }
+
+    /// All entries of an instance, in the order of keys,
+    /// the prefix must be stripped from the returned keys.
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter;
+
+    /// Like `iter`, bounds do not contain the prefix.
+    fn range<'a>(
         &'a self,
         area_idx: usize,
         meta_prefix: PrefixBytes,
-        bounds: R,
-    ) -> MapxIter;
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter;
 
     fn get(
         &self,
@@ -61,6 +305,7 @@ pub trait Engine: Sized {
         key: &[u8],
     ) -> Option<RawValue>;
 
+    /// Return the old value.
     fn insert(
         &self,
         area_idx: usize,
@@ -69,6 +314,7 @@ pub trait Engine: Sized {
         value: &[u8],
     ) -> Option<RawValue>;
 
+    /// Return the old value.
     fn remove(
         &self,
         area_idx: usize,
@@ -76,10 +322,18 @@ pub trait Engine: Sized {
         key: &[u8],
     ) -> Option<RawValue>;
 
+    /// Only called on existing instances.
     fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64;
 
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool;
+
+    /// An instance exists after its length is set.
     fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64);
 
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool;
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes);
+
     fn increase_instance_len(&self, instance_prefix: PrefixBytes) {
         self.set_instance_len(
             instance_prefix,
@@ -95,38 +349,301 @@ pub trait Engine: Sized {
     }
 }
 
+type DynIter = Box<dyn DoubleEndedIterator<Item = (RawKey, RawValue)> + Send>;
+
+/// Iterator over the entries of an instance, returned by engines.
+pub struct EngineIter(IterInner);
+
+enum IterInner {
+    Builtin(BuiltinIter),
+    Custom(DynIter),
+}
+
+impl EngineIter {
+    /// Wrap the iterator of a custom engine.
+    pub fn new<I>(iter: I) -> Self
+    where
+        I: DoubleEndedIterator<Item = (Box<[u8]>, Box<[u8]>)> + Send + 'static,
+    {
+        EngineIter(IterInner::Custom(Box::new(iter)))
+    }
+
+    #[inline(always)]
+    fn builtin(iter: BuiltinIter) -> Self {
+        EngineIter(IterInner::Builtin(iter))
+    }
+}
+
+impl Iterator for EngineIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            IterInner::Builtin(i) => i.next(),
+            IterInner::Custom(i) => i.next(),
+        }
+    }
+}
+
+impl DoubleEndedIterator for EngineIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            IterInner::Builtin(i) => i.next_back(),
+            IterInner::Custom(i) => i.next_back(),
+        }
+    }
+}
+
+// The meta key of the append-only flag of an instance,
+// it can not collide with the len keys(`PREFIX_SIZ` bytes)
+// or the global counters(1 byte).
+pub(crate) fn append_only_meta_key(
+    instance_prefix: PrefixBytes,
+) -> [u8; PREFIX_SIZ + 1] {
+    let mut key = [0; PREFIX_SIZ + 1];
+    key[..PREFIX_SIZ].copy_from_slice(&instance_prefix);
+    key
+}
+
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
 
-#[derive(Clone, Copy, Eq, Debug)]
+#[derive(Clone, Copy)]
 pub(crate) struct Mapx {
+    // the database holding this instance
+    db: &'static VsDB<EngineImpl>,
     area_idx: usize,
     // the unique ID of each instance
     prefix: PrefixBytes,
+    // cached from the meta store, it never changes after creation
+    append_only: bool,
+    // cached from the compression registry, never changes either
+    compression: Option<Compression>,
+    // cached from the TTL registry, never changes either
+    ttl: bool,
 }
 
 impl Mapx {
     #[inline(always)]
     pub(crate) fn new() -> Self {
-        let prefix = VSDB.db.alloc_prefix();
+        Self::new_in_area(None)
+    }
 
-        // NOTE: this is NOT equal to
-        // `prefix as usize % VSDB.area_count()`, the MAX value of
-        // the type used by `len()` of almost all known OS-platforms
-        // can be considered to be always less than Prefix::MAX(u64::MAX),
-        // but the reverse logic can NOT be guaranteed.
-        let area_idx = (prefix % VSDB.db.area_count() as Prefix) as usize;
+    // Placed in the area `area_idx`, usually a dedicated one,
+    // see `common::areas` for details.
+    #[inline(always)]
+    pub(crate) fn new_pinned(area_idx: usize) -> Result<Self> {
+        let area_cnt = database::current().db.area_count();
+        if area_idx >= area_cnt {
+            return Err(eg!(format!(
+                "area {} is out of range, there are {} areas",
+                area_idx, area_cnt
+            )));
+        }
+        Ok(Self::new_in_area(Some(area_idx)))
+    }
+
+    fn new_in_area(area_idx: Option<usize>) -> Self {
+        let db = database::current();
+        let prefix = Self::alloc_prefix(db, area_idx);
+        let area_idx = areas::area_of(prefix, db.db.area_count());
 
         let prefix_bytes = prefix.to_be_bytes();
 
-        assert!(VSDB.db.iter(area_idx, prefix_bytes).next().is_none());
+        assert!(db.db.iter(area_idx, prefix_bytes).next().is_none());
 
-        VSDB.db.set_instance_len(prefix_bytes, 0);
+        db.db.set_instance_len(prefix_bytes, 0);
 
         Mapx {
+            db,
             area_idx,
             prefix: prefix_bytes,
+            append_only: false,
+            compression: None,
+            ttl: false,
+        }
+    }
+
+    // Existing keys can not be overwritten or removed in this mode.
+    #[inline(always)]
+    pub(crate) fn new_append_only() -> Self {
+        let mut hdr = Self::new();
+        hdr.engine().set_instance_append_only(hdr.prefix);
+        hdr.append_only = true;
+        hdr
+    }
+
+    // Values are compressed by `c` in the engine insert/get path.
+    #[inline(always)]
+    pub(crate) fn new_compressed(c: Compression) -> Self {
+        let mut hdr = Self::new();
+        let (area_idx, registry) = compression_registry(hdr.db);
+        hdr.engine()
+            .insert(area_idx, registry, &hdr.prefix, &c.to_bytes());
+        hdr.compression = Some(c);
+        hdr
+    }
+
+    // Entries can expire, see `common::ttl` for details.
+    #[inline(always)]
+    pub(crate) fn new_with_ttl() -> Self {
+        let mut hdr = Self::new();
+        ttl::register(hdr.engine(), hdr.prefix);
+        hdr.ttl = true;
+        hdr
+    }
+
+    // Derive from the current namespace if there is one,
+    // prefixes that have been taken are skipped,
+    // so are those mapped to other areas than the wanted one.
+    fn alloc_prefix(db: &VsDB<EngineImpl>, area_idx: Option<usize>) -> Prefix {
+        let area_cnt = db.db.area_count();
+        let wanted = |prefix| {
+            let idx = areas::area_of(prefix, area_cnt);
+            match area_idx {
+                Some(i) => i == idx,
+                None => areas::is_shared(idx, area_cnt),
+            }
+        };
+
+        while let Some(prefix) = namespace::next_derived_prefix() {
+            if wanted(prefix) && !db.db.instance_exists(prefix.to_be_bytes()) {
+                return prefix;
+            }
+        }
+
+        loop {
+            let prefix = db.db.alloc_prefix();
+            if wanted(prefix) {
+                return prefix;
+            }
+        }
+    }
+
+    // Reconnect to an existing instance by its prefix.
+    #[inline(always)]
+    pub(crate) fn open(prefix: Prefix) -> Option<Self> {
+        Self::open_in(database::current(), prefix)
+    }
+
+    // Reconnect to an existing instance of the database `db`.
+    pub(crate) fn open_in(
+        db: &'static VsDB<EngineImpl>,
+        prefix: Prefix,
+    ) -> Option<Self> {
+        let prefix_bytes = prefix.to_be_bytes();
+        alt!(!db.db.instance_exists(prefix_bytes), return None);
+        Some(Mapx {
+            db,
+            area_idx: areas::area_of(prefix, db.db.area_count()),
+            prefix: prefix_bytes,
+            append_only: db.db.instance_is_append_only(prefix_bytes),
+            compression: registered_compression(db, prefix_bytes),
+            ttl: ttl::registered(&db.db, prefix_bytes),
+        })
+    }
+
+    // Open an internal instance at a reserved prefix,
+    // it is created if not found.
+    #[inline(always)]
+    pub(crate) fn open_reserved(prefix: Prefix) -> Self {
+        Self::open_reserved_in(database::current(), prefix)
+    }
+
+    // Open an internal instance of the database `db` at a reserved prefix.
+    pub(crate) fn open_reserved_in(
+        db: &'static VsDB<EngineImpl>,
+        prefix: Prefix,
+    ) -> Self {
+        assert!(prefix <= BIGGEST_RESERVED_ID);
+
+        // avoid resetting the length of one created by others
+        static LK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+        let _guard = LK.lock();
+
+        let prefix_bytes = prefix.to_be_bytes();
+        if !db.db.instance_exists(prefix_bytes) {
+            db.db.set_instance_len(prefix_bytes, 0);
+        }
+        pnk!(Self::open_in(db, prefix).c(d!()))
+    }
+
+    // The engine of the database holding this instance,
+    // it does not depend on the current database of the thread.
+    #[inline(always)]
+    fn engine(&self) -> &'static EngineImpl {
+        &self.db.db
+    }
+
+    // The database holding this instance.
+    #[inline(always)]
+    pub(crate) fn db(&self) -> &'static VsDB<EngineImpl> {
+        self.db
+    }
+
+    #[inline(always)]
+    pub(crate) fn prefix(&self) -> Prefix {
+        crate::parse_prefix!(self.prefix)
+    }
+
+    #[inline(always)]
+    pub(crate) fn area_idx(&self) -> usize {
+        self.area_idx
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_append_only(&self) -> bool {
+        self.append_only
+    }
+
+    #[inline(always)]
+    pub(crate) fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    #[inline(always)]
+    pub(crate) fn has_ttl(&self) -> bool {
+        self.ttl
+    }
+
+    // `None` if the entry has expired.
+    #[inline(always)]
+    fn decoded(&self, v: RawValue) -> Option<RawValue> {
+        let v = match self.compression {
+            Some(c) => c.decompress(&v),
+            None => v,
+        };
+        alt!(self.ttl, ttl::open(v, ttl::now()), Some(v))
+    }
+
+    // Values are decoded lazily along with the iteration,
+    // expired entries are skipped.
+    #[inline(always)]
+    fn decoded_iter(&self, iter: EngineIter) -> EngineIter {
+        if self.ttl {
+            let now = ttl::now();
+            return EngineIter::new(
+                iter.filter_map(move |(k, v)| ttl::open(v, now).map(|v| (k, v))),
+            );
+        }
+        match self.compression {
+            Some(c) => EngineIter::new(iter.map(move |(k, v)| (k, c.decompress(&v)))),
+            None => iter,
+        }
+    }
+
+    // The returned guard must be held until the key is written,
+    // so the key can not be taken by others after the check.
+    #[inline(always)]
+    fn check_writable(
+        &self,
+        key: &[u8],
+    ) -> StdResult<Option<MutexGuard<'static, ()>>, AppendOnlyViolation> {
+        alt!(!self.append_only, return Ok(None));
+        let guard = append_only::lock(self.prefix, key);
+        if self.get(key).is_some() {
+            return Err(AppendOnlyViolation { key: key.to_vec() });
         }
+        Ok(Some(guard))
     }
 
     fn get_instance_cfg(&self) -> InstanceCfg {
@@ -135,12 +652,15 @@ impl Mapx {
 
     #[inline(always)]
     pub(crate) fn get(&self, key: &[u8]) -> Option<RawValue> {
-        VSDB.db.get(self.area_idx, self.prefix, key)
+        let t = config::op_start();
+        let ret = self.engine().get(self.area_idx, self.prefix, key);
+        config::op_finish("get", t);
+        ret.and_then(|v| self.decoded(v))
     }
 
     #[inline(always)]
     pub(crate) fn len(&self) -> usize {
-        VSDB.db.get_instance_len(self.prefix) as usize
+        self.engine().get_instance_len(self.prefix) as usize
     }
 
     #[inline(always)]
@@ -149,38 +669,131 @@ impl Mapx {
     }
 
     #[inline(always)]
-    pub(crate) fn iter(&self) -> MapxIter {
-        VSDB.db.iter(self.area_idx, self.prefix)
+    pub(crate) fn iter(&self) -> EngineIter {
+        self.decoded_iter(self.engine().iter(self.area_idx, self.prefix))
     }
 
     #[inline(always)]
-    pub(crate) fn range<'a, R: RangeBounds<&'a [u8]>>(&'a self, bounds: R) -> MapxIter {
-        VSDB.db.range(self.area_idx, self.prefix, bounds)
+    pub(crate) fn range<'a, R: RangeBounds<&'a [u8]>>(
+        &'a self,
+        bounds: R,
+    ) -> EngineIter {
+        let bounds = (bounds.start_bound().cloned(), bounds.end_bound().cloned());
+        self.decoded_iter(self.engine().range(self.area_idx, self.prefix, bounds))
     }
 
+    // Panic if the instance is append-only and the key exists.
     #[inline(always)]
     pub(crate) fn insert(&self, key: &[u8], value: &[u8]) -> Option<RawValue> {
-        let ret = VSDB.db.insert(self.area_idx, self.prefix, key, value);
+        pnk!(self.try_insert(key, value).c(d!()))
+    }
+
+    #[inline(always)]
+    pub(crate) fn try_insert(
+        &self,
+        key: &[u8],
+        value: &[u8],
+    ) -> StdResult<Option<RawValue>, AppendOnlyViolation> {
+        let _guard = self.check_writable(key)?;
+        Ok(self.write(key, value, 0))
+    }
+
+    // Panic if TTLs are not enabled on the instance.
+    #[inline(always)]
+    pub(crate) fn insert_with_ttl(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Option<RawValue> {
+        assert!(self.ttl, "TTLs are not enabled on this instance");
+        let expire_at = ttl::expire_at(ttl);
+        let ret = self.write(key, value, expire_at);
+        ttl::track(self.engine(), self.prefix, key, expire_at);
+        ret
+    }
+
+    // `expire_at` is ignored if TTLs are not enabled.
+    fn write(&self, key: &[u8], value: &[u8], expire_at: u64) -> Option<RawValue> {
+        let t = config::op_start();
+        let engine = self.engine();
+        let ret = if self.ttl {
+            let _guard = ttl::lock();
+            engine.insert(
+                self.area_idx,
+                self.prefix,
+                key,
+                &ttl::seal(value, expire_at),
+            )
+        } else if let Some(c) = self.compression {
+            engine.insert(self.area_idx, self.prefix, key, &c.compress(value))
+        } else {
+            engine.insert(self.area_idx, self.prefix, key, value)
+        };
         if ret.is_none() {
-            VSDB.db.increase_instance_len(self.prefix);
+            engine.increase_instance_len(self.prefix);
         }
-        ret
+        config::op_finish("insert", t);
+        ret.and_then(|v| self.decoded(v))
     }
 
+    // Panic if the instance is append-only and the key exists.
     #[inline(always)]
     pub(crate) fn remove(&self, key: &[u8]) -> Option<RawValue> {
-        let ret = VSDB.db.remove(self.area_idx, self.prefix, key);
+        pnk!(self.try_remove(key).c(d!()))
+    }
+
+    #[inline(always)]
+    pub(crate) fn try_remove(
+        &self,
+        key: &[u8],
+    ) -> StdResult<Option<RawValue>, AppendOnlyViolation> {
+        let _guard = self.check_writable(key)?;
+        let t = config::op_start();
+        let engine = self.engine();
+        let _ttl_guard = self.ttl.then(ttl::lock);
+        let ret = engine.remove(self.area_idx, self.prefix, key);
         if ret.is_some() {
-            VSDB.db.decrease_instance_len(self.prefix);
+            engine.decrease_instance_len(self.prefix);
         }
-        ret
+        config::op_finish("remove", t);
+        Ok(ret.and_then(|v| self.decoded(v)))
     }
 
+    // Only the area holding this instance will be flushed.
+    #[inline(always)]
+    pub(crate) fn flush(&self) {
+        let t = config::op_start();
+        self.engine().flush_area(self.area_idx);
+        config::op_finish("flush", t);
+        mirror::notify_flushed();
+    }
+
+    // Flush the given areas of the database holding this instance,
+    // used when the data of a group of instances should be flushed together.
+    pub(crate) fn flush_areas(&self, area_indexes: &BTreeSet<usize>) {
+        let t = config::op_start();
+        area_indexes
+            .iter()
+            .for_each(|&idx| self.engine().flush_area(idx));
+        config::op_finish("flush", t);
+        mirror::notify_flushed();
+    }
+
+    // Panic if the instance is append-only and not empty.
     #[inline(always)]
     pub(crate) fn clear(&self) {
-        VSDB.db.iter(self.area_idx, self.prefix).for_each(|(k, _)| {
-            VSDB.db.remove(self.area_idx, self.prefix, &k);
-            VSDB.db.decrease_instance_len(self.prefix);
+        if self.append_only {
+            if let Some((k, _)) = self.iter().next() {
+                panic!("{}", AppendOnlyViolation { key: k.to_vec() });
+            }
+            // nothing to clear, and keys inserted after the check must be kept
+            return;
+        }
+        let engine = self.engine();
+        engine.iter(self.area_idx, self.prefix).for_each(|(k, _)| {
+            engine.remove(self.area_idx, self.prefix, &k);
+            engine.decrease_instance_len(self.prefix);
         });
     }
 }
@@ -195,6 +808,20 @@ impl PartialEq for Mapx {
     }
 }
 
+impl Eq for Mapx {}
+
+impl fmt::Debug for Mapx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mapx")
+            .field("area_idx", &self.area_idx)
+            .field("prefix", &self.prefix)
+            .field("append_only", &self.append_only)
+            .field("compression", &self.compression)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 struct InstanceCfg {
     prefix: PrefixBytes,
@@ -203,9 +830,14 @@ struct InstanceCfg {
 
 impl From<InstanceCfg> for Mapx {
     fn from(cfg: InstanceCfg) -> Self {
+        let db = database::current();
         Self {
+            db,
             prefix: cfg.prefix,
             area_idx: cfg.area_idx,
+            append_only: db.db.instance_is_append_only(cfg.prefix),
+            compression: registered_compression(db, cfg.prefix),
+            ttl: ttl::registered(&db.db, cfg.prefix),
         }
     }
 }
@@ -219,6 +851,25 @@ impl From<&Mapx> for InstanceCfg {
     }
 }
 
+// The compression algorithms of instances are recorded in this
+// reserved instance, keyed by their prefixes, so nothing is needed
+// from engines, and uncompressed instances cost nothing but a lookup.
+#[inline(always)]
+fn compression_registry(db: &VsDB<EngineImpl>) -> (usize, PrefixBytes) {
+    let area_idx = areas::area_of(COMPRESSION_REGISTRY, db.db.area_count());
+    (area_idx, COMPRESSION_REGISTRY.to_be_bytes())
+}
+
+fn registered_compression(
+    db: &VsDB<EngineImpl>,
+    instance_prefix: PrefixBytes,
+) -> Option<Compression> {
+    let (area_idx, registry) = compression_registry(db);
+    db.db
+        .get(area_idx, registry, &instance_prefix)
+        .map(|c| pnk!(Compression::from_bytes(&c)))
+}
+
 ////////////////////////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////
 
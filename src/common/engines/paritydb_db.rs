@@ -0,0 +1,325 @@
+use crate::common::{
+    areas,
+    engines::{append_only_meta_key, seek::SeekBounds, EngineIter},
+    replica, BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
+};
+use parity_db::{ColId, Db, Options};
+use parking_lot::Mutex;
+use ruc::*;
+use std::{
+    fs,
+    ops::{Bound, RangeBounds},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+// column 0 is the meta store, areas start from column 1
+const DATA_SET_NUM: usize = 4;
+const META_COL: ColId = 0;
+
+const META_KEY_BRANCH_ID: [u8; 1] = [u8::MAX - 1];
+const META_KEY_VERSION_ID: [u8; 1] = [u8::MAX - 2];
+const META_KEY_PREFIX_ALLOCATOR: [u8; 1] = [u8::MIN];
+
+pub(crate) struct ParityDbEngine {
+    db: Arc<Db>,
+    area_cnt: usize,
+    prefix_allocator: Counter,
+    branch_id_allocator: Counter,
+    version_id_allocator: Counter,
+}
+
+impl ParityDbEngine {
+    #[inline(always)]
+    fn get_from(&self, col: ColId, key: &[u8]) -> Option<RawValue> {
+        self.db.get(col, key).unwrap().map(|v| v.into_boxed_slice())
+    }
+
+    #[inline(always)]
+    fn put_to(&self, col: ColId, key: &[u8], value: Option<&[u8]>) {
+        self.db
+            .commit([(col, key, value.map(|v| v.to_vec()))])
+            .unwrap();
+    }
+}
+
+impl Engine for ParityDbEngine {
+    fn new(dir: &str) -> Result<Self> {
+        // the database is locked by the primary process
+        if replica::vsdb_is_replica() {
+            return Err(eg!("parity-db does not support the replica mode"));
+        }
+
+        fs::create_dir_all(dir).c(d!())?;
+
+        // columns are numbered by `u8`
+        let area_cnt = areas::area_count(DATA_SET_NUM);
+        if area_cnt >= u8::MAX as usize {
+            return Err(eg!("parity-db supports at most 254 areas"));
+        }
+
+        // ordered indexes are needed by the iteration of areas
+        let mut opts = Options::with_columns(Path::new(dir), 1 + area_cnt as u8);
+        opts.columns
+            .iter_mut()
+            .skip(1)
+            .for_each(|c| c.btree_index = true);
+        let db = Db::open_or_create(&opts).c(d!())?;
+
+        let prefix_allocator = Counter::load(
+            &db,
+            META_KEY_PREFIX_ALLOCATOR,
+            RESERVED_ID_CNT + Prefix::MIN,
+        )
+        .c(d!())?;
+        let branch_id_allocator =
+            Counter::load(&db, META_KEY_BRANCH_ID, 1 + INITIAL_BRANCH_ID).c(d!())?;
+        let version_id_allocator = Counter::load(&db, META_KEY_VERSION_ID, 0).c(d!())?;
+
+        Ok(ParityDbEngine {
+            db: Arc::new(db),
+            area_cnt,
+            prefix_allocator,
+            branch_id_allocator,
+            version_id_allocator,
+        })
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.prefix_allocator.alloc(&self.db)
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.branch_id_allocator.alloc(&self.db)
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.version_id_allocator.alloc(&self.db)
+    }
+
+    fn area_count(&self) -> usize {
+        self.area_cnt
+    }
+
+    // commits are written to the disk by the background threads of parity-db
+    fn flush(&self) {}
+
+    fn flush_area(&self, _: usize) {}
+
+    fn set_cache_size(&self, _: usize) -> Result<()> {
+        Err(eg!("parity-db relies on the page cache of the OS"))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Err(eg!("parity-db does not support the replica mode"))
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        EngineIter::builtin(ParityDbIter {
+            db: Arc::clone(&self.db),
+            col: area_col(area_idx),
+            bounds: SeekBounds::all(meta_prefix),
+        })
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        EngineIter::builtin(ParityDbIter {
+            db: Arc::clone(&self.db),
+            col: area_col(area_idx),
+            bounds: SeekBounds::new(meta_prefix, bounds),
+        })
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        self.get_from(area_col(area_idx), &k)
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        let old_v = self.get_from(area_col(area_idx), &k);
+        self.put_to(area_col(area_idx), &k, Some(value));
+        old_v
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut k = meta_prefix.to_vec();
+        k.extend_from_slice(key);
+        let old_v = self.get_from(area_col(area_idx), &k);
+        if old_v.is_some() {
+            self.put_to(area_col(area_idx), &k, None);
+        }
+        old_v
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        crate::parse_int!(self.get_from(META_COL, &instance_prefix).unwrap(), u64)
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.get_from(META_COL, &instance_prefix).is_some()
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        self.put_to(META_COL, &instance_prefix, Some(&new_len.to_be_bytes()));
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.get_from(META_COL, &append_only_meta_key(instance_prefix))
+            .is_some()
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.put_to(
+            META_COL,
+            &append_only_meta_key(instance_prefix),
+            Some(&[1_u8]),
+        );
+    }
+}
+
+#[inline(always)]
+fn area_col(area_idx: usize) -> ColId {
+    1 + area_idx as ColId
+}
+
+// IDs are allocated from memory without any lock,
+// the next value is committed before an ID is returned,
+// so no ID will be returned twice after restarting.
+struct Counter {
+    key: [u8; 1],
+    next: AtomicU64,
+    // the largest value that has been committed
+    committed: Mutex<u64>,
+}
+
+impl Counter {
+    fn load(db: &Db, key: [u8; 1], initial_value: u64) -> Result<Self> {
+        let next = if let Some(v) = db.get(META_COL, &key).c(d!())? {
+            crate::parse_int!(v, u64)
+        } else {
+            db.commit([(META_COL, key, Some(initial_value.to_be_bytes().to_vec()))])
+                .c(d!())?;
+            initial_value
+        };
+        Ok(Counter {
+            key,
+            next: AtomicU64::new(next),
+            committed: Mutex::new(next),
+        })
+    }
+
+    fn alloc(&self, db: &Db) -> u64 {
+        let ret = self.next.fetch_add(1, Ordering::Relaxed);
+
+        // concurrent allocations may reach here out of order,
+        // a smaller value should never overwrite a larger one
+        let mut committed = self.committed.lock();
+        if *committed <= ret {
+            *committed = 1 + ret;
+            db.commit([(META_COL, self.key, Some(committed.to_be_bytes().to_vec()))])
+                .unwrap();
+        }
+
+        ret
+    }
+}
+
+// Native iterators borrow the database, so a new one is created
+// and positioned on every step, like the iterators of lmdb.
+pub struct ParityDbIter {
+    db: Arc<Db>,
+    col: ColId,
+    bounds: SeekBounds,
+}
+
+impl ParityDbIter {
+    fn step(&mut self, forward: bool) -> Option<(RawKey, RawValue)> {
+        alt!(self.bounds.exhausted(), return None);
+
+        let bounds = self.bounds.as_slices();
+        let mut it = self.db.iter(self.col).unwrap();
+        if forward {
+            match bounds.0 {
+                Bound::Included(k) | Bound::Excluded(k) => it.seek(k).unwrap(),
+                Bound::Unbounded => it.seek_to_first().unwrap(),
+            }
+        } else {
+            match bounds.1 {
+                // the successor of `k`, so `k` itself can be reached
+                Bound::Included(k) => it.seek(&[k, &[0][..]].concat()).unwrap(),
+                Bound::Excluded(k) => it.seek(k).unwrap(),
+                Bound::Unbounded => it.seek_to_last().unwrap(),
+            }
+        }
+
+        let (k, v) = loop {
+            let (k, v) = alt!(forward, it.next(), it.prev()).unwrap()?;
+            if bounds.contains(&k.as_slice()) {
+                break (k, v);
+            }
+            let end = alt!(forward, bounds.1, bounds.0);
+            alt!(beyond(end, &k, forward), return None);
+        };
+        drop(it);
+
+        let k = k.into_boxed_slice();
+        let ret = (
+            k[PREFIX_SIZ..].to_vec().into_boxed_slice(),
+            v.into_boxed_slice(),
+        );
+
+        alt!(forward, self.bounds.move_lo(k), self.bounds.move_hi(k));
+        Some(ret)
+    }
+}
+
+// Keys are visited in order, the iteration ends at the first key
+// beyond the end bound, others out of the bounds are just skipped.
+fn beyond(end: Bound<&[u8]>, k: &[u8], forward: bool) -> bool {
+    match end {
+        Bound::Included(e) => alt!(forward, k > e, k < e),
+        Bound::Excluded(e) => alt!(forward, k >= e, k <= e),
+        Bound::Unbounded => false,
+    }
+}
+
+impl Iterator for ParityDbIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(true)
+    }
+}
+
+impl DoubleEndedIterator for ParityDbIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.step(false)
+    }
+}
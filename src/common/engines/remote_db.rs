@@ -0,0 +1,289 @@
+use crate::common::{
+    engines::EngineIter,
+    remote::{self, *},
+    vsdb_get_base_dir, BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue,
+    VersionID,
+};
+use parking_lot::Mutex;
+use ruc::*;
+use std::{collections::VecDeque, net::TcpStream, ops::Bound, sync::Arc};
+
+type Conn = Arc<Mutex<TcpStream>>;
+
+// All operations are sent to a remote node, requests are serialized
+// on one connection, network errors are treated like IO errors
+// of other engines.
+pub(crate) struct RemoteEngine {
+    conn: Conn,
+    // fixed on the node side, no need to ask every time
+    area_count: usize,
+}
+
+impl RemoteEngine {
+    #[inline(always)]
+    fn call(&self, req: &[u8]) -> Vec<u8> {
+        pnk!(call(&self.conn, req))
+    }
+
+    #[inline(always)]
+    fn call_u64(&self, req: &[u8]) -> u64 {
+        pnk!(Reader(&self.call(req)).u64())
+    }
+
+    #[inline(always)]
+    fn call_flag(&self, req: &[u8]) -> bool {
+        pnk!(Reader(&self.call(req)).flag())
+    }
+
+    #[inline(always)]
+    fn call_optioned_bytes(&self, req: &[u8]) -> Option<RawValue> {
+        pnk!(Reader(&self.call(req)).optioned_bytes())
+    }
+}
+
+impl Engine for RemoteEngine {
+    fn new(dir: &str) -> Result<Self> {
+        // the node serves its global database only
+        if dir != vsdb_get_base_dir() {
+            return Err(eg!("only the global database can be remote"));
+        }
+
+        let addr = remote::remote_addr().c(d!("no remote address"))?;
+        let stream = TcpStream::connect(addr).c(d!())?;
+        stream.set_nodelay(true).c(d!())?;
+        let conn = Arc::new(Mutex::new(stream));
+
+        let area_count = call(&conn, &[OP_AREA_COUNT])
+            .and_then(|resp| Reader(&resp).u64())
+            .c(d!())? as usize;
+
+        Ok(RemoteEngine { conn, area_count })
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.call_u64(&[OP_ALLOC_PREFIX])
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.call_u64(&[OP_ALLOC_BRANCH_ID])
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.call_u64(&[OP_ALLOC_VERSION_ID])
+    }
+
+    fn area_count(&self) -> usize {
+        self.area_count
+    }
+
+    fn flush(&self) {
+        self.call(&[OP_FLUSH]);
+    }
+
+    fn flush_area(&self, area_idx: usize) {
+        let mut req = vec![OP_FLUSH_AREA];
+        put_u64(&mut req, area_idx as u64);
+        self.call(&req);
+    }
+
+    fn set_cache_size(&self, bytes: usize) -> Result<()> {
+        let mut req = vec![OP_SET_CACHE_SIZE];
+        put_u64(&mut req, bytes as u64);
+        call(&self.conn, &req).c(d!()).map(|_| ())
+    }
+
+    fn refresh(&self) -> Result<()> {
+        call(&self.conn, &[OP_REFRESH]).c(d!()).map(|_| ())
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        self.range(area_idx, meta_prefix, (Bound::Unbounded, Bound::Unbounded))
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        let to_owned = |b: Bound<&[u8]>| match b {
+            Bound::Included(k) => Bound::Included(RawKey::from(k)),
+            Bound::Excluded(k) => Bound::Excluded(RawKey::from(k)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        EngineIter::new(RemoteIter {
+            conn: Arc::clone(&self.conn),
+            area_idx,
+            meta_prefix,
+            lo: to_owned(bounds.0),
+            hi: to_owned(bounds.1),
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut req = vec![OP_GET];
+        put_u64(&mut req, area_idx as u64);
+        req.extend_from_slice(&meta_prefix);
+        put_bytes(&mut req, key);
+        self.call_optioned_bytes(&req)
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let mut req = vec![OP_INSERT];
+        put_u64(&mut req, area_idx as u64);
+        req.extend_from_slice(&meta_prefix);
+        put_bytes(&mut req, key);
+        put_bytes(&mut req, value);
+        self.call_optioned_bytes(&req)
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let mut req = vec![OP_REMOVE];
+        put_u64(&mut req, area_idx as u64);
+        req.extend_from_slice(&meta_prefix);
+        put_bytes(&mut req, key);
+        self.call_optioned_bytes(&req)
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        self.call_u64(&prefixed(OP_GET_INSTANCE_LEN, instance_prefix))
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.call_flag(&prefixed(OP_INSTANCE_EXISTS, instance_prefix))
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        let mut req = prefixed(OP_SET_INSTANCE_LEN, instance_prefix);
+        put_u64(&mut req, new_len);
+        self.call(&req);
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.call_flag(&prefixed(OP_INSTANCE_IS_APPEND_ONLY, instance_prefix))
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.call(&prefixed(OP_SET_INSTANCE_APPEND_ONLY, instance_prefix));
+    }
+}
+
+// Entries are fetched by chunks from both ends, the remaining range
+// on the node side is narrowed after each fetch, like the seeking
+// iterators of other engines, changes made during the iteration may be seen.
+pub struct RemoteIter {
+    conn: Conn,
+    area_idx: usize,
+    meta_prefix: PrefixBytes,
+    lo: Bound<RawKey>,
+    hi: Bound<RawKey>,
+    // fetched from the front, in the ascending order
+    front: VecDeque<(RawKey, RawValue)>,
+    // fetched from the back, in the descending order
+    back: VecDeque<(RawKey, RawValue)>,
+    // nothing is left on the node side
+    done: bool,
+}
+
+impl RemoteIter {
+    fn fetch(&mut self, forward: bool) {
+        alt!(self.done || self.exhausted(), return);
+
+        let mut req = vec![OP_SEEK];
+        put_u64(&mut req, self.area_idx as u64);
+        req.extend_from_slice(&self.meta_prefix);
+        put_bound(&mut req, as_slice(&self.lo));
+        put_bound(&mut req, as_slice(&self.hi));
+        req.push(forward as u8);
+
+        let resp = pnk!(call(&self.conn, &req));
+        let r = &mut Reader(&resp);
+        let n = pnk!(r.u64());
+        if 0 == n {
+            self.done = true;
+            return;
+        }
+
+        for _ in 0..n {
+            let k = RawKey::from(pnk!(r.bytes()));
+            let v = RawValue::from(pnk!(r.bytes()));
+            if forward {
+                self.lo = Bound::Excluded(k.clone());
+                self.front.push_back((k, v));
+            } else {
+                self.hi = Bound::Excluded(k.clone());
+                self.back.push_back((k, v));
+            }
+        }
+    }
+
+    // `BTreeMap::range` of some engines panics on such bounds.
+    fn exhausted(&self) -> bool {
+        match (&self.lo, &self.hi) {
+            (Bound::Included(l), Bound::Included(h)) => l > h,
+            (
+                Bound::Included(l) | Bound::Excluded(l),
+                Bound::Included(h) | Bound::Excluded(h),
+            ) => l >= h,
+            _ => false,
+        }
+    }
+}
+
+impl Iterator for RemoteIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front.is_empty() {
+            self.fetch(true);
+        }
+        // the rest are all in the back buffer
+        self.front.pop_front().or_else(|| self.back.pop_back())
+    }
+}
+
+impl DoubleEndedIterator for RemoteIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back.is_empty() {
+            self.fetch(false);
+        }
+        // the rest are all in the front buffer
+        self.back.pop_front().or_else(|| self.front.pop_back())
+    }
+}
+
+fn prefixed(op: u8, instance_prefix: PrefixBytes) -> Vec<u8> {
+    let mut req = vec![op];
+    req.extend_from_slice(&instance_prefix);
+    req
+}
+
+fn call(conn: &Conn, req: &[u8]) -> Result<Vec<u8>> {
+    remote::call(&mut conn.lock(), req).c(d!())
+}
+
+fn as_slice(b: &Bound<RawKey>) -> Bound<&[u8]> {
+    match b {
+        Bound::Included(k) => Bound::Included(&**k),
+        Bound::Excluded(k) => Bound::Excluded(&**k),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
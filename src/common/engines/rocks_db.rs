@@ -1,16 +1,24 @@
+#[cfg(feature = "migration")]
+use crate::common::engines::migrate::Migratable;
 use crate::common::{
-    vsdb_get_base_dir, vsdb_set_base_dir, BranchID, Engine, Prefix, PrefixBytes,
-    RawBytes, RawKey, RawValue, VersionID, INITIAL_BRANCH_ID, PREFIX_SIZ,
-    RESERVED_ID_CNT,
+    areas, config,
+    engine_opts::{vsdb_get_engine_opts, BlockCompression, CompactionStyle},
+    engines::{append_only_meta_key, EngineIter},
+    replica,
+    stats::{AreaStorageStats, StorageStats},
+    BranchID, Engine, Prefix, PrefixBytes, RawBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
 };
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use rocksdb::{
-    ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, DBIterator, Direction,
-    IteratorMode, Options, ReadOptions, SliceTransform, DB,
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompactionStyle,
+    DBCompressionType, DBIterator, Direction, IteratorMode, Options, ReadOptions,
+    SliceTransform, DB,
 };
 use ruc::*;
 use std::{
+    fs,
     mem::size_of,
     ops::{Bound, RangeBounds},
     sync::atomic::{AtomicUsize, Ordering},
@@ -23,11 +31,16 @@ const META_KEY_BRANCH_ID: [u8; 1] = [u8::MAX - 1];
 const META_KEY_VERSION_ID: [u8; 1] = [u8::MAX - 2];
 const META_KEY_PREFIX_ALLOCATOR: [u8; 1] = [u8::MIN];
 
-static HDR: Lazy<(DB, Vec<String>)> = Lazy::new(|| rocksdb_open().unwrap());
+// the default block cache size of rocksdb
+const DEFAULT_CACHE_SIZE: usize = 8 * 1024 * 1024;
 
 pub(crate) struct RocksEngine {
+    // leaked, iterators borrow it for the whole life of the process
     meta: &'static DB,
-    areas: Vec<&'static str>,
+    areas: Vec<String>,
+    // the block cache is shared by all column families,
+    // and can be resized by `vsdb_reconfigure` at runtime
+    cache: Mutex<Cache>,
     prefix_allocator: PrefixAllocator,
     max_keylen: AtomicUsize,
 }
@@ -35,7 +48,7 @@ pub(crate) struct RocksEngine {
 impl RocksEngine {
     #[inline(always)]
     fn cf_hdr(&self, area_idx: usize) -> &ColumnFamily {
-        self.meta.cf_handle(self.areas[area_idx]).unwrap()
+        self.meta.cf_handle(&self.areas[area_idx]).unwrap()
     }
 
     #[inline(always)]
@@ -69,12 +82,28 @@ impl RocksEngine {
 }
 
 impl Engine for RocksEngine {
-    fn new() -> Result<Self> {
-        let (meta, areas) =
-            (&HDR.0, HDR.1.iter().map(|i| i.as_str()).collect::<Vec<_>>());
+    fn new(dir: &str) -> Result<Self> {
+        let (meta, areas, cache) = rocksdb_open(dir).c(d!())?;
+        let meta: &'static DB = Box::leak(Box::new(meta));
 
         let (prefix_allocator, initial_value) = PrefixAllocator::init();
 
+        // a replica can not write anything,
+        // the database must have been initialized by the primary
+        if replica::vsdb_is_replica() {
+            let max_keylen = meta
+                .get(&META_KEY_MAX_KEYLEN)
+                .c(d!())?
+                .c(d!("the primary has not been initialized"))?;
+            return Ok(RocksEngine {
+                meta,
+                areas,
+                cache,
+                prefix_allocator,
+                max_keylen: AtomicUsize::new(crate::parse_int!(max_keylen, usize)),
+            });
+        }
+
         if meta.get(&META_KEY_MAX_KEYLEN).c(d!())?.is_none() {
             meta.put(META_KEY_MAX_KEYLEN, 0_usize.to_be_bytes())
                 .c(d!())?;
@@ -105,6 +134,7 @@ impl Engine for RocksEngine {
         Ok(RocksEngine {
             meta,
             areas,
+            cache,
             prefix_allocator,
             // length of the raw key, exclude the meta prefix
             max_keylen,
@@ -119,9 +149,11 @@ impl Engine for RocksEngine {
         let mut z = LK.lock();
 
         // step 1
-        let ret = crate::parse_prefix!(
-            self.meta.get(self.prefix_allocator.key).unwrap().unwrap()
-        );
+        let ret = crate::parse_prefix!(self
+            .meta
+            .get(self.prefix_allocator.key)
+            .unwrap()
+            .unwrap());
 
         // step 2
         self.meta
@@ -183,17 +215,84 @@ impl Engine for RocksEngine {
     }
 
     fn area_count(&self) -> usize {
-        DATA_SET_NUM
+        self.areas.len()
     }
 
     fn flush(&self) {
         self.meta.flush().unwrap();
-        (0..DATA_SET_NUM).for_each(|i| {
+        (0..self.areas.len()).for_each(|i| {
             self.meta.flush_cf(self.cf_hdr(i)).unwrap();
         });
     }
 
-    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> RocksIter {
+    // the 'meta' holds the length of instances,
+    // so it should be flushed together with the target area.
+    fn flush_area(&self, area_idx: usize) {
+        self.meta.flush().unwrap();
+        self.meta.flush_cf(self.cf_hdr(area_idx)).unwrap();
+    }
+
+    fn set_cache_size(&self, bytes: usize) -> Result<()> {
+        self.cache
+            .lock()
+            .set_capacity(alt!(0 == bytes, DEFAULT_CACHE_SIZE, bytes));
+        Ok(())
+    }
+
+    // the max key length may also be increased by the primary,
+    // reverse iterations depend on it
+    fn refresh(&self) -> Result<()> {
+        if !replica::vsdb_is_replica() {
+            return Err(eg!("not a replica"));
+        }
+        self.meta.try_catch_up_with_primary().c(d!())?;
+        let len = self
+            .meta
+            .get(META_KEY_MAX_KEYLEN)
+            .c(d!())?
+            .c(d!("the primary has not been initialized"))?;
+        self.max_keylen
+            .store(crate::parse_int!(len, usize), Ordering::Relaxed);
+        Ok(())
+    }
+
+    // tombstones in memtables are counted along with the ones in SST files
+    fn storage_stats(&self) -> StorageStats {
+        let int_prop = |cf: &ColumnFamily, name: &str| {
+            self.meta.property_int_value_cf(cf, name).ok().flatten()
+        };
+
+        let areas = (0..self.areas.len())
+            .map(|i| {
+                let cf = self.cf_hdr(i);
+                let in_memtables = int_prop(cf, "rocksdb.num-deletes-active-mem-table")
+                    .zip(int_prop(cf, "rocksdb.num-deletes-imm-mem-tables"))
+                    .map(|(a, b)| a + b);
+                let in_files = self
+                    .meta
+                    .property_value_cf(cf, "rocksdb.aggregated-table-properties")
+                    .ok()
+                    .flatten()
+                    .and_then(|props| table_prop(&props, "# deletions"));
+                AreaStorageStats {
+                    live_bytes: int_prop(cf, "rocksdb.estimate-live-data-size"),
+                    disk_bytes: int_prop(cf, "rocksdb.total-sst-files-size"),
+                    tombstones: in_memtables.zip(in_files).map(|(a, b)| a + b),
+                }
+            })
+            .collect();
+
+        StorageStats {
+            pending_compaction_bytes: (0..self.areas.len())
+                .map(|i| {
+                    int_prop(self.cf_hdr(i), "rocksdb.estimate-pending-compaction-bytes")
+                })
+                .sum(),
+            ..StorageStats::from_areas(areas)
+        }
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
         let inner = self
             .meta
             .prefix_iterator_cf(self.cf_hdr(area_idx), meta_prefix);
@@ -210,15 +309,15 @@ impl Engine for RocksEngine {
             ),
         );
 
-        RocksIter { inner, inner_rev }
+        engine_iter(RocksIter { inner, inner_rev })
     }
 
-    fn range<'a, R: RangeBounds<&'a [u8]>>(
+    fn range<'a>(
         &'a self,
         area_idx: usize,
         meta_prefix: PrefixBytes,
-        bounds: R,
-    ) -> RocksIter {
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
         let mut opt = ReadOptions::default();
         let mut opt_rev = ReadOptions::default();
 
@@ -273,7 +372,7 @@ impl Engine for RocksEngine {
             IteratorMode::From(&h, Direction::Reverse),
         );
 
-        RocksIter { inner, inner_rev }
+        engine_iter(RocksIter { inner, inner_rev })
     }
 
     fn get(
@@ -326,11 +425,68 @@ impl Engine for RocksEngine {
         crate::parse_int!(self.meta.get(instance_prefix).unwrap().unwrap(), u64)
     }
 
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta.get(instance_prefix).unwrap().is_some()
+    }
+
     fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
         self.meta
             .put(instance_prefix, new_len.to_be_bytes())
             .unwrap();
     }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta
+            .get(append_only_meta_key(instance_prefix))
+            .unwrap()
+            .is_some()
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.meta
+            .put(append_only_meta_key(instance_prefix), [1_u8])
+            .unwrap();
+    }
+}
+
+#[cfg(feature = "migration")]
+impl Migratable for RocksEngine {
+    // the max key length is maintained by `insert`
+    fn meta_entries(&self) -> Box<dyn Iterator<Item = (RawKey, RawValue)> + '_> {
+        Box::new(
+            self.meta
+                .iterator(IteratorMode::Start)
+                .filter(|(k, _)| k[..] != META_KEY_MAX_KEYLEN),
+        )
+    }
+
+    fn area_entries(
+        &self,
+        area_idx: usize,
+    ) -> Box<dyn Iterator<Item = (RawKey, RawValue)> + '_> {
+        Box::new(
+            self.meta
+                .iterator_cf(self.cf_hdr(area_idx), IteratorMode::Start),
+        )
+    }
+
+    fn put_meta(&self, key: &[u8], value: &[u8]) {
+        self.meta.put(key, value).unwrap();
+    }
+}
+
+#[cfg(not(feature = "migration"))]
+#[inline(always)]
+fn engine_iter(iter: RocksIter) -> EngineIter {
+    EngineIter::builtin(iter)
+}
+
+// rocksdb is never the builtin engine if it is compiled for the migration,
+// its iterators are boxed like the custom ones
+#[cfg(feature = "migration")]
+#[inline(always)]
+fn engine_iter(iter: RocksIter) -> EngineIter {
+    EngineIter::new(iter)
 }
 
 pub struct RocksIter {
@@ -381,9 +537,7 @@ impl PrefixAllocator {
     }
 }
 
-fn rocksdb_open() -> Result<(DB, Vec<String>)> {
-    let dir = vsdb_get_base_dir();
-
+fn rocksdb_open(dir: &str) -> Result<(DB, Vec<String>, Mutex<Cache>)> {
     let mut cfg = Options::default();
     cfg.create_if_missing(true);
     cfg.increase_parallelism(num_cpus::get() as i32);
@@ -395,17 +549,72 @@ fn rocksdb_open() -> Result<(DB, Vec<String>)> {
     cfg.set_atomic_flush(true);
     cfg.set_prefix_extractor(SliceTransform::create_fixed_prefix(size_of::<Prefix>()));
 
-    let cfhdrs = (0..DATA_SET_NUM).map(|i| i.to_string()).collect::<Vec<_>>();
+    let opts = vsdb_get_engine_opts();
+    if let Some(levels) = opts.compression_per_level.as_ref() {
+        let levels = levels.iter().copied().map(compression).collect::<Vec<_>>();
+        cfg.set_compression_per_level(&levels);
+    }
+    if let Some(style) = opts.compaction_style {
+        cfg.set_compaction_style(match style {
+            CompactionStyle::Level => DBCompactionStyle::Level,
+            CompactionStyle::Universal => DBCompactionStyle::Universal,
+            CompactionStyle::Fifo => DBCompactionStyle::Fifo,
+        });
+    }
+    if let Some(n) = opts.write_buffer_size {
+        cfg.set_write_buffer_size(n);
+    }
+
+    let cache = Cache::new_lru_cache(alt!(
+        0 == config::cache_size(),
+        DEFAULT_CACHE_SIZE,
+        config::cache_size()
+    ))
+    .c(d!())?;
+    let mut table_cfg = BlockBasedOptions::default();
+    table_cfg.set_block_cache(&cache);
+    cfg.set_block_based_table_factory(&table_cfg);
+
+    let cfhdrs = (0..areas::area_count(DATA_SET_NUM))
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>();
 
     let cfs = cfhdrs
         .iter()
         .map(|i| ColumnFamilyDescriptor::new(i, cfg.clone()))
         .collect::<Vec<_>>();
 
-    let db = DB::open_cf_descriptors(&cfg, &dir, cfs).c(d!())?;
+    let db = if replica::vsdb_is_replica() {
+        // all files must be kept open to catch up with the primary
+        cfg.set_max_open_files(-1);
+        let replica_dir = replica::replica_dir();
+        fs::create_dir_all(&replica_dir).c(d!())?;
+        DB::open_cf_as_secondary(&cfg, dir, replica_dir.as_str(), &cfhdrs).c(d!())?
+    } else {
+        DB::open_cf_descriptors(&cfg, dir, cfs).c(d!())?
+    };
+
+    Ok((db, cfhdrs, Mutex::new(cache)))
+}
 
-    // avoid setting again on an opened DB
-    info_omit!(vsdb_set_base_dir(dir));
+// Read an item from the text of table properties,
+// such as "# entries=10; # deletions=2; ...".
+fn table_prop(props: &str, name: &str) -> Option<u64> {
+    props.split(';').find_map(|item| {
+        let (k, v) = item.split_once('=')?;
+        if k.trim() == name {
+            v.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
 
-    Ok((db, cfhdrs))
+fn compression(c: BlockCompression) -> DBCompressionType {
+    match c {
+        BlockCompression::None => DBCompressionType::None,
+        BlockCompression::Snappy => DBCompressionType::Snappy,
+        BlockCompression::Lz4 => DBCompressionType::Lz4,
+        BlockCompression::Zstd => DBCompressionType::Zstd,
+    }
 }
@@ -0,0 +1,92 @@
+//!
+//! Bounds of iterators that seek again on every step.
+//!
+//! Used by engines whose native iterators can not outlive a lock or a
+//! transaction, the remaining range is narrowed after each yielded key.
+//!
+
+use crate::common::{Prefix, PrefixBytes, RawKey};
+use std::ops::{Bound, RangeBounds};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SeekBounds {
+    lo: Bound<RawKey>,
+    hi: Bound<RawKey>,
+}
+
+impl SeekBounds {
+    // All keys of an instance.
+    pub(crate) fn all(meta_prefix: PrefixBytes) -> Self {
+        Self {
+            lo: Bound::Included(Box::new(meta_prefix)),
+            hi: upper_bound(meta_prefix),
+        }
+    }
+
+    pub(crate) fn new<'a, R: RangeBounds<&'a [u8]>>(
+        meta_prefix: PrefixBytes,
+        bounds: R,
+    ) -> Self {
+        let with_prefix = |k: &[u8]| {
+            let mut b = meta_prefix.to_vec();
+            b.extend_from_slice(k);
+            b.into_boxed_slice()
+        };
+
+        let lo = match bounds.start_bound() {
+            Bound::Included(lo) => Bound::Included(with_prefix(lo)),
+            Bound::Excluded(lo) => Bound::Excluded(with_prefix(lo)),
+            Bound::Unbounded => Bound::Included(with_prefix(&[])),
+        };
+
+        let hi = match bounds.end_bound() {
+            Bound::Included(hi) => Bound::Included(with_prefix(hi)),
+            Bound::Excluded(hi) => Bound::Excluded(with_prefix(hi)),
+            Bound::Unbounded => upper_bound(meta_prefix),
+        };
+
+        Self { lo, hi }
+    }
+
+    // Nothing is left, `BTreeMap::range` also panics on such bounds.
+    pub(crate) fn exhausted(&self) -> bool {
+        match (&self.lo, &self.hi) {
+            (Bound::Included(l), Bound::Included(h)) => l > h,
+            (
+                Bound::Included(l) | Bound::Excluded(l),
+                Bound::Included(h) | Bound::Excluded(h),
+            ) => l >= h,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn as_slices(&self) -> (Bound<&[u8]>, Bound<&[u8]>) {
+        (as_slice(&self.lo), as_slice(&self.hi))
+    }
+
+    // Called after a key is yielded from the front.
+    pub(crate) fn move_lo(&mut self, yielded_key: RawKey) {
+        self.lo = Bound::Excluded(yielded_key);
+    }
+
+    // Called after a key is yielded from the back.
+    pub(crate) fn move_hi(&mut self, yielded_key: RawKey) {
+        self.hi = Bound::Excluded(yielded_key);
+    }
+}
+
+// All keys of an instance are less than the next prefix.
+fn upper_bound(meta_prefix: PrefixBytes) -> Bound<RawKey> {
+    Prefix::from_be_bytes(meta_prefix)
+        .checked_add(1)
+        .map(|p| Bound::Excluded(Box::new(p.to_be_bytes()) as RawKey))
+        .unwrap_or(Bound::Unbounded)
+}
+
+fn as_slice(b: &Bound<RawKey>) -> Bound<&[u8]> {
+    match b {
+        Bound::Included(k) => Bound::Included(&**k),
+        Bound::Excluded(k) => Bound::Excluded(&**k),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
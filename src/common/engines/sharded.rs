@@ -0,0 +1,292 @@
+//!
+//! Spreading a database over several directories.
+//!
+//! With [vsdb_set_shard_dirs](self::vsdb_set_shard_dirs), every database is
+//! split into N underlying instances of the selected engine, one under each
+//! of the given directories, usually on different disks. Areas are assigned to
+//! them in turn, and so is the meta store of instances(by their prefixes), so
+//! the data of an instance and its meta always live in the same shard.
+//! Flushing and compactions of the shards run independently of each other.
+//!
+//! NOTE:
+//! - The same directories, in the same order, should be set between restarts,
+//!   it is checked by the layout recorded in the data directory
+//! - Hooks of flushing only watch the files in ${VSDB_BASE_DIR}
+//!
+
+use crate::common::{
+    areas, config,
+    engines::{Backend, Engine, EngineIter},
+    replica,
+    stats::{AreaStorageStats, StorageStats},
+    BranchID, Prefix, PrefixBytes, RawValue, VersionID,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use sha3::{Digest, Sha3_256};
+use std::{
+    fs,
+    ops::Bound,
+    path::{Path, PathBuf},
+    thread,
+};
+
+// the layout of shards, written in the data directory
+const LAYOUT_FILE: &str = "SHARDS";
+
+static SHARD_DIRS: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Split every database into shards under `dirs`,
+/// it can only be called before VSDB is initialized.
+pub fn vsdb_set_shard_dirs<P: AsRef<Path>>(dirs: &[P]) -> Result<()> {
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+
+    if dirs.len() < 2 {
+        return Err(eg!("at least 2 directories are needed"));
+    }
+
+    let dirs = dirs
+        .iter()
+        .map(|d| {
+            fs::create_dir_all(d).c(d!())?;
+            d.as_ref().canonicalize().c(d!())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut uniq = dirs.clone();
+    uniq.sort();
+    uniq.dedup();
+    if uniq.len() != dirs.len() {
+        return Err(eg!("duplicate directories"));
+    }
+
+    *SHARD_DIRS.lock() = dirs;
+
+    Ok(())
+}
+
+#[inline(always)]
+pub(crate) fn sharding_enabled() -> bool {
+    !SHARD_DIRS.lock().is_empty()
+}
+
+pub(crate) struct Sharded {
+    shards: Vec<Backend>,
+    // areas of each shard
+    area_cnt: usize,
+}
+
+impl Sharded {
+    // Shards of `dir` are placed in `roots`, in sub-directories named by
+    // the hash of `dir`, so several databases can share the same roots.
+    pub(crate) fn open(dir: &str, roots: &[PathBuf]) -> Result<Self> {
+        fs::create_dir_all(dir).c(d!())?;
+        let dir = Path::new(dir).canonicalize().c(d!())?;
+
+        let mut hasher = Sha3_256::new();
+        hasher.update(dir.to_string_lossy().as_bytes());
+        let name = hasher.finalize()[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        let dirs = roots.iter().map(|r| r.join(&name)).collect::<Vec<_>>();
+        let layout = dirs
+            .iter()
+            .map(|d| d.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let layout_path = dir.join(LAYOUT_FILE);
+        match fs::read_to_string(&layout_path) {
+            Ok(l) if l != layout => {
+                return Err(eg!(format!(
+                    "the layout of shards has been changed, the recorded one:\n{}",
+                    l
+                )));
+            }
+            Ok(_) => {}
+            Err(_) if replica::vsdb_is_replica() => {
+                return Err(eg!("no shards found"));
+            }
+            Err(_) => fs::write(&layout_path, &layout).c(d!())?,
+        }
+
+        let shards = dirs
+            .iter()
+            .map(|d| Backend::open_single(&d.to_string_lossy()).c(d!()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let area_cnt = shards[0].area_count();
+
+        Ok(Sharded { shards, area_cnt })
+    }
+
+    // [ shard ] + [ area index in that shard ]
+    #[inline(always)]
+    fn area(&self, area_idx: usize) -> (&Backend, usize) {
+        let n = self.shards.len();
+        (&self.shards[area_idx % n], area_idx / n)
+    }
+
+    // Meta of an instance lives along with its data.
+    #[inline(always)]
+    fn meta(&self, instance_prefix: PrefixBytes) -> &Backend {
+        let prefix = crate::parse_prefix!(instance_prefix);
+        self.area(areas::area_of(prefix, self.area_count())).0
+    }
+}
+
+impl Engine for Sharded {
+    fn new(dir: &str) -> Result<Self> {
+        let roots = SHARD_DIRS.lock().clone();
+        Self::open(dir, &roots).c(d!())
+    }
+
+    // all allocators live in the first shard
+    fn alloc_prefix(&self) -> Prefix {
+        self.shards[0].alloc_prefix()
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.shards[0].alloc_branch_id()
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.shards[0].alloc_version_id()
+    }
+
+    fn area_count(&self) -> usize {
+        self.shards.len() * self.area_cnt
+    }
+
+    fn flush(&self) {
+        thread::scope(|s| {
+            self.shards.iter().for_each(|shard| {
+                s.spawn(|| shard.flush());
+            });
+        });
+    }
+
+    fn flush_area(&self, area_idx: usize) {
+        let (shard, idx) = self.area(area_idx);
+        shard.flush_area(idx);
+        if 0 != area_idx % self.shards.len() {
+            // IDs allocated by this instance
+            self.shards[0].flush_area(0);
+        }
+    }
+
+    fn set_cache_size(&self, bytes: usize) -> Result<()> {
+        let per_shard = bytes / self.shards.len();
+        self.shards
+            .iter()
+            .try_for_each(|s| s.set_cache_size(per_shard).c(d!()))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        self.shards.iter().try_for_each(|s| s.refresh().c(d!()))
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        let stats = self
+            .shards
+            .iter()
+            .map(|s| s.storage_stats())
+            .collect::<Vec<_>>();
+
+        // areas are listed in the global order if all shards track them
+        let areas = if stats.iter().all(|s| s.areas.len() == self.area_cnt) {
+            (0..self.area_count())
+                .map(|i| {
+                    let n = self.shards.len();
+                    stats[i % n].areas[i / n].clone()
+                })
+                .collect::<Vec<AreaStorageStats>>()
+        } else {
+            vec![]
+        };
+
+        StorageStats {
+            live_bytes: stats.iter().map(|s| s.live_bytes).sum(),
+            disk_bytes: stats.iter().map(|s| s.disk_bytes).sum(),
+            pending_compaction_bytes: stats
+                .iter()
+                .map(|s| s.pending_compaction_bytes)
+                .sum(),
+            areas,
+        }
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        let (shard, idx) = self.area(area_idx);
+        shard.iter(idx, meta_prefix)
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        let (shard, idx) = self.area(area_idx);
+        shard.range(idx, meta_prefix, bounds)
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let (shard, idx) = self.area(area_idx);
+        shard.get(idx, meta_prefix, key)
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let (shard, idx) = self.area(area_idx);
+        shard.insert(idx, meta_prefix, key, value)
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let (shard, idx) = self.area(area_idx);
+        shard.remove(idx, meta_prefix, key)
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        self.meta(instance_prefix).get_instance_len(instance_prefix)
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta(instance_prefix).instance_exists(instance_prefix)
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        self.meta(instance_prefix)
+            .set_instance_len(instance_prefix, new_len)
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta(instance_prefix)
+            .instance_is_append_only(instance_prefix)
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.meta(instance_prefix)
+            .set_instance_append_only(instance_prefix)
+    }
+}
@@ -1,6 +1,12 @@
+#[cfg(feature = "migration")]
+use crate::common::engines::migrate::Migratable;
 use crate::common::{
-    vsdb_get_base_dir, vsdb_set_base_dir, BranchID, Engine, Prefix, PrefixBytes, RawKey,
-    RawValue, VersionID, INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
+    areas, config,
+    engines::{append_only_meta_key, EngineIter},
+    replica,
+    stats::StorageStats,
+    BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
 };
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
@@ -23,10 +29,10 @@ pub(crate) struct SledEngine {
 }
 
 impl Engine for SledEngine {
-    fn new() -> Result<Self> {
-        let meta = sled_open().c(d!())?;
+    fn new(dir: &str) -> Result<Self> {
+        let meta = sled_open(dir).c(d!())?;
 
-        let areas = (0..DATA_SET_NUM)
+        let areas = (0..areas::area_count(DATA_SET_NUM))
             .map(|idx| meta.open_tree(idx.to_be_bytes()).c(d!()))
             .collect::<Result<Vec<_>>>()?;
 
@@ -64,13 +70,12 @@ impl Engine for SledEngine {
         let mut z = LK.lock();
 
         // step 1
-        let ret = crate::parse_prefix!(
-            self.meta
-                .get(self.prefix_allocator.key)
-                .unwrap()
-                .unwrap()
-                .as_ref()
-        );
+        let ret = crate::parse_prefix!(self
+            .meta
+            .get(self.prefix_allocator.key)
+            .unwrap()
+            .unwrap()
+            .as_ref());
 
         // step 2
         self.meta
@@ -145,19 +150,44 @@ impl Engine for SledEngine {
         });
     }
 
-    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> SledIter {
-        SledIter {
+    // the 'meta' holds the allocators of IDs and prefixes,
+    // so it should be flushed together with the target area.
+    fn flush_area(&self, area_idx: usize) {
+        self.meta.flush().unwrap();
+        self.areas[area_idx].flush().unwrap();
+    }
+
+    fn set_cache_size(&self, _: usize) -> Result<()> {
+        Err(eg!(
+            "the cache size of sled can not be changed after opening"
+        ))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Err(eg!("sled does not support the replica mode"))
+    }
+
+    // all trees share the same files
+    fn storage_stats(&self) -> StorageStats {
+        StorageStats {
+            disk_bytes: self.meta.size_on_disk().ok(),
+            ..Default::default()
+        }
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        engine_iter(SledIter {
             inner: self.areas[area_idx].scan_prefix(meta_prefix.as_slice()),
             bounds: (Bound::Unbounded, Bound::Unbounded),
-        }
+        })
     }
 
-    fn range<'a, R: RangeBounds<&'a [u8]>>(
+    fn range<'a>(
         &'a self,
         area_idx: usize,
         meta_prefix: PrefixBytes,
-        bounds: R,
-    ) -> SledIter {
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
         let mut b_lo = meta_prefix.to_vec();
         let l = match bounds.start_bound() {
             Bound::Included(lo) => {
@@ -184,10 +214,10 @@ impl Engine for SledEngine {
             Bound::Unbounded => Bound::Unbounded,
         };
 
-        SledIter {
+        engine_iter(SledIter {
             inner: self.areas[area_idx].scan_prefix(meta_prefix.as_slice()),
             bounds: (l, h),
-        }
+        })
     }
 
     fn get(
@@ -237,11 +267,77 @@ impl Engine for SledEngine {
         crate::parse_int!(self.meta.get(instance_prefix).unwrap().unwrap(), u64)
     }
 
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta.get(instance_prefix).unwrap().is_some()
+    }
+
     fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
         self.meta
             .insert(instance_prefix, new_len.to_be_bytes())
             .unwrap();
     }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.meta
+            .get(append_only_meta_key(instance_prefix))
+            .unwrap()
+            .is_some()
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.meta
+            .insert(append_only_meta_key(instance_prefix), [1_u8])
+            .unwrap();
+    }
+}
+
+#[cfg(feature = "migration")]
+impl Migratable for SledEngine {
+    fn meta_entries(&self) -> Box<dyn Iterator<Item = (RawKey, RawValue)> + '_> {
+        Box::new(self.meta.iter().map(|i| {
+            let (k, v) = i.unwrap();
+            (k.to_vec().into_boxed_slice(), v.to_vec().into_boxed_slice())
+        }))
+    }
+
+    fn area_entries(
+        &self,
+        area_idx: usize,
+    ) -> Box<dyn Iterator<Item = (RawKey, RawValue)> + '_> {
+        Box::new(self.areas[area_idx].iter().map(|i| {
+            let (k, v) = i.unwrap();
+            (k.to_vec().into_boxed_slice(), v.to_vec().into_boxed_slice())
+        }))
+    }
+
+    fn put_meta(&self, key: &[u8], value: &[u8]) {
+        self.meta.insert(key, value).unwrap();
+    }
+}
+
+#[cfg(not(any(
+    feature = "mem_engine",
+    feature = "lmdb_engine",
+    feature = "paritydb_engine",
+    feature = "sqlite_engine",
+    feature = "mmap_engine"
+)))]
+#[inline(always)]
+fn engine_iter(iter: SledIter) -> EngineIter {
+    EngineIter::builtin(iter)
+}
+
+// boxed like the custom ones if sled is only compiled for the migration
+#[cfg(any(
+    feature = "mem_engine",
+    feature = "lmdb_engine",
+    feature = "paritydb_engine",
+    feature = "sqlite_engine",
+    feature = "mmap_engine"
+))]
+#[inline(always)]
+fn engine_iter(iter: SledIter) -> EngineIter {
+    EngineIter::new(iter)
 }
 
 pub struct SledIter {
@@ -294,18 +390,18 @@ impl PrefixAllocator {
     }
 }
 
-fn sled_open() -> Result<Db> {
-    let dir = vsdb_get_base_dir();
+fn sled_open(dir: &str) -> Result<Db> {
+    // the directory is locked by the primary process
+    if replica::vsdb_is_replica() {
+        return Err(eg!("sled does not support the replica mode"));
+    }
 
-    let db = Config::new()
-        .path(&dir)
+    let mut cfg = Config::new()
+        .path(dir)
         .mode(Mode::HighThroughput)
-        .use_compression(true)
-        .open()
-        .c(d!())?;
-
-    // avoid setting again on an opened DB
-    info_omit!(vsdb_set_base_dir(dir));
-
-    Ok(db)
+        .use_compression(true);
+    if 0 < config::cache_size() {
+        cfg = cfg.cache_capacity(config::cache_size() as u64);
+    }
+    cfg.open().c(d!())
 }
@@ -0,0 +1,423 @@
+use crate::common::{
+    areas,
+    engines::{append_only_meta_key, seek::SeekBounds, EngineIter},
+    replica,
+    stats::StorageStats,
+    BranchID, Engine, Prefix, PrefixBytes, RawKey, RawValue, VersionID,
+    INITIAL_BRANCH_ID, PREFIX_SIZ, RESERVED_ID_CNT,
+};
+use parking_lot::Mutex;
+use ruc::*;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension};
+use std::{fs, ops::Bound, path::Path, sync::Arc};
+
+// every area is a table, named as `area_${idx}`
+const DATA_SET_NUM: usize = 4;
+
+// all data lives in this file, it can be copied and inspected alone
+const DB_FILE: &str = "vsdb.sqlite";
+
+// statements of iterators vary with the kinds of bounds
+const STMT_CACHE_SIZE: usize = 128;
+
+const META_KEY_BRANCH_ID: [u8; 1] = [u8::MAX - 1];
+const META_KEY_VERSION_ID: [u8; 1] = [u8::MAX - 2];
+const META_KEY_PREFIX_ALLOCATOR: [u8; 1] = [u8::MIN];
+
+type Conn = Arc<Mutex<Connection>>;
+
+pub(crate) struct SqliteEngine {
+    conn: Conn,
+    area_cnt: usize,
+}
+
+impl SqliteEngine {
+    #[inline(always)]
+    fn get_meta(&self, key: &[u8]) -> Option<RawValue> {
+        self.conn
+            .lock()
+            .prepare_cached("SELECT v FROM meta WHERE k = ?1")
+            .unwrap()
+            .query_row(params![key], |r| r.get::<_, Vec<u8>>(0))
+            .optional()
+            .unwrap()
+            .map(|v| v.into_boxed_slice())
+    }
+
+    #[inline(always)]
+    fn put_meta(&self, key: &[u8], value: &[u8]) {
+        self.conn
+            .lock()
+            .prepare_cached("INSERT OR REPLACE INTO meta (k, v) VALUES (?1, ?2)")
+            .unwrap()
+            .execute(params![key, value])
+            .unwrap();
+    }
+
+    // Writers are serialized by the lock of the connection,
+    // the transaction keeps the counter consistent on the disk.
+    fn alloc(&self, key: &[u8]) -> u64 {
+        let mut conn = self.conn.lock();
+        let txn = conn.transaction().unwrap();
+        let ret = txn
+            .query_row("SELECT v FROM meta WHERE k = ?1", params![key], |r| {
+                r.get::<_, Vec<u8>>(0)
+            })
+            .unwrap();
+        let ret = crate::parse_int!(ret, u64);
+        txn.execute(
+            "UPDATE meta SET v = ?2 WHERE k = ?1",
+            params![key, &(1 + ret).to_be_bytes()[..]],
+        )
+        .unwrap();
+        txn.commit().unwrap();
+        ret
+    }
+}
+
+impl Engine for SqliteEngine {
+    fn new(dir: &str) -> Result<Self> {
+        let path = Path::new(dir).join(DB_FILE);
+        let area_cnt = areas::area_count(DATA_SET_NUM);
+
+        // sqlite can be shared by processes natively,
+        // a replica just opens it in the read-only mode
+        if replica::vsdb_is_replica() {
+            let conn = Connection::open_with_flags(
+                &path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+            .c(d!("the primary has not been initialized"))?;
+            conn.set_prepared_statement_cache_capacity(STMT_CACHE_SIZE);
+            return Ok(SqliteEngine {
+                conn: Arc::new(Mutex::new(conn)),
+                area_cnt,
+            });
+        }
+
+        fs::create_dir_all(dir).c(d!())?;
+        let mut conn = Connection::open(&path).c(d!())?;
+        conn.set_prepared_statement_cache_capacity(STMT_CACHE_SIZE);
+
+        // data is synced by `flush`, like other engines
+        conn.pragma_update(None, "journal_mode", "WAL").c(d!())?;
+        conn.pragma_update(None, "synchronous", "NORMAL").c(d!())?;
+
+        let txn = conn.transaction().c(d!())?;
+        txn.execute(
+            "CREATE TABLE IF NOT EXISTS meta (
+                k BLOB NOT NULL PRIMARY KEY,
+                v BLOB NOT NULL
+            ) WITHOUT ROWID",
+            [],
+        )
+        .c(d!())?;
+        for i in 0..area_cnt {
+            let sql = format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    prefix BLOB NOT NULL,
+                    key BLOB NOT NULL,
+                    value BLOB NOT NULL,
+                    PRIMARY KEY (prefix, key)
+                ) WITHOUT ROWID",
+                table(i)
+            );
+            txn.execute(&sql, []).c(d!())?;
+        }
+
+        let initial_values = [
+            (
+                META_KEY_BRANCH_ID,
+                (1 + INITIAL_BRANCH_ID as usize).to_be_bytes(),
+            ),
+            (META_KEY_VERSION_ID, 0_usize.to_be_bytes()),
+            (
+                META_KEY_PREFIX_ALLOCATOR,
+                (RESERVED_ID_CNT + Prefix::MIN).to_be_bytes(),
+            ),
+        ];
+        for (k, v) in initial_values.iter() {
+            txn.execute(
+                "INSERT OR IGNORE INTO meta (k, v) VALUES (?1, ?2)",
+                params![&k[..], &v[..]],
+            )
+            .c(d!())?;
+        }
+        txn.commit().c(d!())?;
+
+        Ok(SqliteEngine {
+            conn: Arc::new(Mutex::new(conn)),
+            area_cnt,
+        })
+    }
+
+    fn alloc_prefix(&self) -> Prefix {
+        self.alloc(&META_KEY_PREFIX_ALLOCATOR)
+    }
+
+    fn alloc_branch_id(&self) -> BranchID {
+        self.alloc(&META_KEY_BRANCH_ID)
+    }
+
+    fn alloc_version_id(&self) -> VersionID {
+        self.alloc(&META_KEY_VERSION_ID)
+    }
+
+    fn area_count(&self) -> usize {
+        self.area_cnt
+    }
+
+    // the WAL is written back into the main file and synced
+    fn flush(&self) {
+        self.conn
+            .lock()
+            .query_row("PRAGMA wal_checkpoint(FULL)", [], |_| Ok(()))
+            .unwrap();
+    }
+
+    // all areas are in the same data file
+    fn flush_area(&self, _: usize) {
+        self.flush();
+    }
+
+    // a negative value means KiB in sqlite
+    fn set_cache_size(&self, bytes: usize) -> Result<()> {
+        let kib = (bytes / 1024) as i64;
+        self.conn
+            .lock()
+            .pragma_update(None, "cache_size", -kib)
+            .c(d!())
+    }
+
+    // every new statement sees the latest committed data
+    fn refresh(&self) -> Result<()> {
+        if !replica::vsdb_is_replica() {
+            return Err(eg!("not a replica"));
+        }
+        Ok(())
+    }
+
+    // free pages are reused by later writes, or reclaimed by `VACUUM`,
+    // the sizes are counted in pages, the WAL file is not included
+    fn storage_stats(&self) -> StorageStats {
+        let conn = self.conn.lock();
+        let pragma = |name| {
+            conn.pragma_query_value(None, name, |r| r.get::<_, i64>(0))
+                .ok()
+                .map(|n| n as u64)
+        };
+        let page_size = pragma("page_size");
+        let bytes = |pages: Option<u64>| pages.zip(page_size).map(|(n, siz)| n * siz);
+
+        let (pages, free_pages) = (pragma("page_count"), pragma("freelist_count"));
+        StorageStats {
+            live_bytes: bytes(pages.zip(free_pages).map(|(p, f)| p - f)),
+            disk_bytes: bytes(pages),
+            pending_compaction_bytes: bytes(free_pages),
+            areas: vec![],
+        }
+    }
+
+    fn iter(&self, area_idx: usize, meta_prefix: PrefixBytes) -> EngineIter {
+        EngineIter::builtin(SqliteIter {
+            conn: Arc::clone(&self.conn),
+            area_idx,
+            bounds: SeekBounds::all(meta_prefix),
+        })
+    }
+
+    fn range<'a>(
+        &'a self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        bounds: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> EngineIter {
+        EngineIter::builtin(SqliteIter {
+            conn: Arc::clone(&self.conn),
+            area_idx,
+            bounds: SeekBounds::new(meta_prefix, bounds),
+        })
+    }
+
+    fn get(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        get_from(&self.conn.lock(), area_idx, meta_prefix, key)
+    }
+
+    fn insert(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<RawValue> {
+        let conn = self.conn.lock();
+        let old_v = get_from(&conn, area_idx, meta_prefix, key);
+        let sql = format!(
+            "INSERT OR REPLACE INTO {} (prefix, key, value) VALUES (?1, ?2, ?3)",
+            table(area_idx)
+        );
+        conn.prepare_cached(&sql)
+            .unwrap()
+            .execute(params![&meta_prefix[..], key, value])
+            .unwrap();
+        old_v
+    }
+
+    fn remove(
+        &self,
+        area_idx: usize,
+        meta_prefix: PrefixBytes,
+        key: &[u8],
+    ) -> Option<RawValue> {
+        let conn = self.conn.lock();
+        let old_v = get_from(&conn, area_idx, meta_prefix, key);
+        if old_v.is_some() {
+            let sql = format!(
+                "DELETE FROM {} WHERE prefix = ?1 AND key = ?2",
+                table(area_idx)
+            );
+            conn.prepare_cached(&sql)
+                .unwrap()
+                .execute(params![&meta_prefix[..], key])
+                .unwrap();
+        }
+        old_v
+    }
+
+    fn get_instance_len(&self, instance_prefix: PrefixBytes) -> u64 {
+        crate::parse_int!(self.get_meta(&instance_prefix).unwrap(), u64)
+    }
+
+    fn instance_exists(&self, instance_prefix: PrefixBytes) -> bool {
+        self.get_meta(&instance_prefix).is_some()
+    }
+
+    fn set_instance_len(&self, instance_prefix: PrefixBytes, new_len: u64) {
+        self.put_meta(&instance_prefix, &new_len.to_be_bytes());
+    }
+
+    fn instance_is_append_only(&self, instance_prefix: PrefixBytes) -> bool {
+        self.get_meta(&append_only_meta_key(instance_prefix))
+            .is_some()
+    }
+
+    fn set_instance_append_only(&self, instance_prefix: PrefixBytes) {
+        self.put_meta(&append_only_meta_key(instance_prefix), &[1_u8]);
+    }
+}
+
+#[inline(always)]
+fn table(area_idx: usize) -> String {
+    format!("area_{}", area_idx)
+}
+
+fn get_from(
+    conn: &Connection,
+    area_idx: usize,
+    meta_prefix: PrefixBytes,
+    key: &[u8],
+) -> Option<RawValue> {
+    let sql = format!(
+        "SELECT value FROM {} WHERE prefix = ?1 AND key = ?2",
+        table(area_idx)
+    );
+    conn.prepare_cached(&sql)
+        .unwrap()
+        .query_row(params![&meta_prefix[..], key], |r| r.get::<_, Vec<u8>>(0))
+        .optional()
+        .unwrap()
+        .map(|v| v.into_boxed_slice())
+}
+
+// Statements can not be held across steps, they borrow the connection,
+// so the next row is queried on every step, like the iterators of lmdb.
+pub struct SqliteIter {
+    conn: Conn,
+    area_idx: usize,
+    bounds: SeekBounds,
+}
+
+impl SqliteIter {
+    fn step(&mut self, forward: bool) -> Option<(RawKey, RawValue)> {
+        alt!(self.bounds.exhausted(), return None);
+
+        let (lo, hi) = self.bounds.as_slices();
+        let mut conds = vec![];
+        let mut args = vec![];
+        cond(lo, true, &mut conds, &mut args);
+        cond(hi, false, &mut conds, &mut args);
+
+        let order = alt!(forward, "ASC", "DESC");
+        let sql = format!(
+            "SELECT prefix, key, value FROM {} WHERE {} ORDER BY prefix {}, key {} LIMIT 1",
+            table(self.area_idx),
+            conds.join(" AND "),
+            order,
+            order
+        );
+
+        let (k, v) = self
+            .conn
+            .lock()
+            .prepare_cached(&sql)
+            .unwrap()
+            .query_row(params_from_iter(args), |r| {
+                let mut k = r.get::<_, Vec<u8>>(0)?;
+                k.extend_from_slice(&r.get::<_, Vec<u8>>(1)?);
+                Ok((k.into_boxed_slice(), r.get::<_, Vec<u8>>(2)?))
+            })
+            .optional()
+            .unwrap()?;
+
+        let ret = (
+            k[PREFIX_SIZ..].to_vec().into_boxed_slice(),
+            v.into_boxed_slice(),
+        );
+
+        alt!(forward, self.bounds.move_lo(k), self.bounds.move_hi(k));
+        Some(ret)
+    }
+}
+
+// `(prefix, key)` is compared as a row value, which is in the same order
+// as the joined bytes, so the primary key can be used by the query.
+//
+// The lower bound always contains a prefix, so there is at least one condition.
+fn cond<'a>(
+    b: Bound<&'a [u8]>,
+    lower: bool,
+    conds: &mut Vec<String>,
+    args: &mut Vec<&'a [u8]>,
+) {
+    let (op, k) = match b {
+        Bound::Included(k) => (alt!(lower, ">=", "<="), k),
+        Bound::Excluded(k) => (alt!(lower, ">", "<"), k),
+        Bound::Unbounded => return,
+    };
+    args.push(&k[..PREFIX_SIZ]);
+    args.push(&k[PREFIX_SIZ..]);
+    conds.push(format!(
+        "(prefix, key) {} (?{}, ?{})",
+        op,
+        args.len() - 1,
+        args.len()
+    ));
+}
+
+impl Iterator for SqliteIter {
+    type Item = (RawKey, RawValue);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(true)
+    }
+}
+
+impl DoubleEndedIterator for SqliteIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.step(false)
+    }
+}
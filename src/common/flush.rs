@@ -0,0 +1,107 @@
+//!
+//! Flushing in the background.
+//!
+//! [vsdb_flush_async](self::vsdb_flush_async) starts a flush in another
+//! thread and returns at once, so writers are not stalled at commit points,
+//! wait on the returned handle(or `.await` it) when the data must be durable.
+//!
+//! Periodic flushing is configured by `flush_interval` of
+//! [vsdb_reconfigure](crate::common::config::vsdb_reconfigure).
+//!
+
+use crate::common::VSDB;
+use parking_lot::{Condvar, Mutex};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+#[derive(Default)]
+struct State {
+    done: bool,
+    // the task waiting on the future, if any
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+/// The handle of a flush started by `vsdb_flush_async`.
+///
+/// Dropping it does not cancel the flush.
+#[derive(Clone)]
+pub struct FlushHandle(Arc<Shared>);
+
+impl FlushHandle {
+    /// Whether the flush has finished.
+    #[inline(always)]
+    pub fn is_done(&self) -> bool {
+        self.0.state.lock().done
+    }
+
+    /// Block until the flush finishes.
+    pub fn wait(&self) {
+        let mut state = self.0.state.lock();
+        while !state.done {
+            self.0.cond.wait(&mut state);
+        }
+    }
+
+    /// Block until the flush finishes or `timeout` elapses,
+    /// return whether it has finished.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let mut state = self.0.state.lock();
+        if !state.done {
+            self.0.cond.wait_for(&mut state, timeout);
+        }
+        state.done
+    }
+}
+
+impl Future for FlushHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.0.state.lock();
+        if state.done {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Flush data to disk in a background thread, return without waiting.
+pub fn vsdb_flush_async() -> FlushHandle {
+    let shared = Arc::new(Shared::default());
+
+    let s = Arc::clone(&shared);
+    thread::spawn(move || {
+        // mark it as done even if the flush panics,
+        // or the waiters will be blocked forever
+        struct Finish(Arc<Shared>);
+        impl Drop for Finish {
+            fn drop(&mut self) {
+                let mut state = self.0.state.lock();
+                state.done = true;
+                if let Some(w) = state.waker.take() {
+                    w.wake();
+                }
+                self.0.cond.notify_all();
+            }
+        }
+
+        let _finish = Finish(s);
+        VSDB.flush();
+    });
+
+    FlushHandle(shared)
+}
@@ -0,0 +1,171 @@
+//!
+//! Hooks for mirroring the data directory.
+//!
+//! After every flush, files under `${VSDB_BASE_DIR}` are compared with
+//! their states at the previous flush, and the changed ones are reported
+//! to the installed [FlushHook](self::FlushHook), so external tools(rsync,
+//! object-store uploaders, etc.) can maintain a warm standby without
+//! scanning the contents of the whole data directory each time.
+//!
+//! Only the metadata(length, modification time) of files is compared,
+//! the contents are never read.
+//!
+
+use crate::common::vsdb_get_base_dir;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+/// A file changed since the previous flush.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileChange {
+    /// Path of the file, it is under `${VSDB_BASE_DIR}`.
+    pub path: PathBuf,
+    /// Length of the file at the previous flush,
+    /// `None` if it is a newly created file.
+    pub prev_len: Option<u64>,
+    /// Current length of the file, `None` if it has been removed.
+    pub len: Option<u64>,
+}
+
+impl FileChange {
+    /// The file is newly created.
+    #[inline(always)]
+    pub fn is_created(&self) -> bool {
+        self.prev_len.is_none()
+    }
+
+    /// The file has been removed.
+    #[inline(always)]
+    pub fn is_removed(&self) -> bool {
+        self.len.is_none()
+    }
+
+    /// The newly appended range `[prev_len, len)`,
+    /// for append-only files(e.g. logs),
+    /// only this range needs to be copied to the mirror.
+    ///
+    /// NOTE: the file may also have been rewritten in place,
+    /// it is the caller's duty to know which files are append-only.
+    #[inline(always)]
+    pub fn appended_range(&self) -> Option<(u64, u64)> {
+        match (self.prev_len, self.len) {
+            (Some(prev), Some(len)) if prev < len => Some((prev, len)),
+            _ => None,
+        }
+    }
+}
+
+/// Receive the changed files after every flush.
+pub trait FlushHook: Send + Sync {
+    /// Called after a flush finished,
+    /// `changes` may be empty if nothing has been changed on disk.
+    ///
+    /// NOTE: do not flush VSDB within this function, or it will deadlock.
+    fn after_flush(&self, changes: &[FileChange]);
+}
+
+type FileState = (u64, Option<SystemTime>);
+
+#[derive(Default)]
+struct Mirror {
+    hook: Option<Arc<dyn FlushHook>>,
+    files: HashMap<PathBuf, FileState>,
+}
+
+static MIRROR_HOOK_ENABLED: AtomicBool = AtomicBool::new(false);
+static MIRROR: Lazy<Mutex<Mirror>> = Lazy::new(|| Mutex::new(Mirror::default()));
+
+/// Install a global flush hook, `None` means removing the current one.
+///
+/// The current states of all files are recorded at installing,
+/// so the first call of the hook only reports changes after that,
+/// a full copy of the data directory should be made by the caller
+/// right after installing, if the mirror is empty.
+pub fn vsdb_set_flush_hook(hook: Option<Arc<dyn FlushHook>>) {
+    let mut hdr = MIRROR.lock();
+    hdr.files = alt!(hook.is_some(), scan(&vsdb_get_base_dir()), HashMap::new());
+    MIRROR_HOOK_ENABLED.store(hook.is_some(), Ordering::Relaxed);
+    hdr.hook = hook;
+}
+
+// Called after every flush, the lock is held when the hook is running,
+// so changes will be reported in order.
+#[inline(always)]
+pub(crate) fn notify_flushed() {
+    if !MIRROR_HOOK_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut hdr = MIRROR.lock();
+    let hook = if let Some(h) = hdr.hook.clone() {
+        h
+    } else {
+        return;
+    };
+
+    let files = scan(&vsdb_get_base_dir());
+
+    let mut changes = files
+        .iter()
+        .filter(|(path, state)| hdr.files.get(*path) != Some(*state))
+        .map(|(path, (len, _))| FileChange {
+            path: path.clone(),
+            prev_len: hdr.files.get(path).map(|(l, _)| *l),
+            len: Some(*len),
+        })
+        .collect::<Vec<_>>();
+    changes.extend(
+        hdr.files
+            .iter()
+            .filter(|(path, _)| !files.contains_key(*path))
+            .map(|(path, (len, _))| FileChange {
+                path: path.clone(),
+                prev_len: Some(*len),
+                len: None,
+            }),
+    );
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    hdr.files = files;
+    hook.after_flush(&changes);
+}
+
+// Files may be removed by the engine during scanning,
+// errors are ignored, they will be reported by the next flush.
+fn scan(dir: &str) -> HashMap<PathBuf, FileState> {
+    let mut ret = HashMap::new();
+    scan_dir(Path::new(dir), &mut ret);
+    ret
+}
+
+fn scan_dir(dir: &Path, ret: &mut HashMap<PathBuf, FileState>) {
+    let entries = if let Ok(i) = fs::read_dir(dir) {
+        i
+    } else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let meta = if let Ok(m) = entry.metadata() {
+            m
+        } else {
+            continue;
+        };
+        if meta.is_dir() {
+            scan_dir(&entry.path(), ret);
+        } else {
+            ret.insert(entry.path(), (meta.len(), meta.modified().ok()));
+        }
+    }
+}
@@ -4,18 +4,47 @@
 
 #![allow(dead_code)]
 
+pub(crate) mod append_only;
+#[cfg(feature = "rkyv_codec")]
+pub(crate) mod archived;
+pub(crate) mod areas;
+#[cfg(feature = "borsh_codec")]
+pub(crate) mod borshed;
+pub(crate) mod budget;
+pub(crate) mod compression;
+pub(crate) mod config;
+pub(crate) mod cursor;
+pub(crate) mod database;
+pub(crate) mod durability;
 pub(crate) mod ende;
+pub(crate) mod engine_opts;
 pub(crate) mod engines;
+pub(crate) mod flush;
+pub(crate) mod mirror;
+pub(crate) mod named;
+pub(crate) mod namespace;
+#[cfg(feature = "prost_codec")]
+pub(crate) mod proto;
+pub(crate) mod raw_area;
+#[cfg(feature = "remote_engine")]
+pub(crate) mod remote;
+pub(crate) mod replica;
+pub(crate) mod stats;
+pub(crate) mod ttl;
+pub(crate) mod tx;
+pub(crate) mod write_batch;
 
 use {
     engines::Engine,
     once_cell::sync::Lazy,
     parking_lot::Mutex,
     ruc::*,
+    stats::StorageStats,
     std::{
         env, fs,
         mem::size_of,
-        sync::atomic::{AtomicBool, Ordering},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     },
 };
 
@@ -42,20 +71,37 @@ pub struct ParentBranchName<'a>(pub &'a [u8]);
 /// Avoid making mistakes between branch name and version name.
 #[derive(Clone, Copy, Debug)]
 pub struct VersionName<'a>(pub &'a [u8]);
+/// Avoid making mistakes between savepoint name and version name.
+#[derive(Clone, Copy, Debug)]
+pub struct SavepointName<'a>(pub &'a [u8]);
 
 const RESERVED_ID_CNT: Prefix = 4096_0000;
 pub(crate) const BIGGEST_RESERVED_ID: Prefix = RESERVED_ID_CNT - 1;
 pub(crate) const NULL: BranchID = BIGGEST_RESERVED_ID as BranchID;
 
+// internal instances living at reserved prefixes
+pub(crate) const COMPRESSION_REGISTRY: Prefix = BIGGEST_RESERVED_ID - 1;
+pub(crate) const COMMIT_JOURNAL_ID: Prefix = BIGGEST_RESERVED_ID - 2;
+pub(crate) const NAME_REGISTRY: Prefix = BIGGEST_RESERVED_ID - 3;
+pub(crate) const TTL_REGISTRY: Prefix = BIGGEST_RESERVED_ID - 4;
+pub(crate) const TTL_INDEX: Prefix = BIGGEST_RESERVED_ID - 5;
+pub(crate) const WRITE_BATCH_LOG: Prefix = BIGGEST_RESERVED_ID - 6;
+pub(crate) const VS_EXTENSION_REGISTRY: Prefix = BIGGEST_RESERVED_ID - 7;
+pub(crate) const VECX_HEAD_REGISTRY: Prefix = BIGGEST_RESERVED_ID - 8;
+
 pub(crate) const INITIAL_BRANCH_ID: BranchID = 0;
 pub(crate) const INITIAL_BRANCH_NAME: &[u8] = b"main";
 
 /// The initial verison along with each new instance.
 pub const INITIAL_VERSION: VersionName<'static> = VersionName([0u8; 0].as_slice());
 
-/// How many ancestral branches at most one new branch can have.
+/// How many ancestral branches at most one new branch can have,
+/// this is the default value, check `vsdb_set_branch_ancestors_limit`.
 pub const BRANCH_ANCESTORS_LIMIT: usize = 128;
 
+static BRANCH_ANCESTORS_LIMIT_CUR: AtomicUsize =
+    AtomicUsize::new(BRANCH_ANCESTORS_LIMIT);
+
 // default value for reserved number when pruning old data
 pub(crate) const RESERVED_VERSION_NUM_DEFAULT: usize = 10;
 
@@ -67,17 +113,18 @@ const BASE_DIR_VAR: &str = "VSDB_BASE_DIR";
 static VSDB_BASE_DIR: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new(gen_data_dir()));
 
 static VSDB_CUSTOM_DIR: Lazy<String> = Lazy::new(|| {
-    let d = VSDB_BASE_DIR.lock().clone() + "/__CUSTOM__";
+    let d = Path::new(&*VSDB_BASE_DIR.lock())
+        .join("__CUSTOM__")
+        .to_string_lossy()
+        .into_owned();
     fs::create_dir_all(&d).unwrap();
     env::set_var("VSDB_CUSTOM_DIR", &d);
     d
 });
 
-#[cfg(all(feature = "sled_engine", not(feature = "rocks_engine")))]
-pub(crate) static VSDB: Lazy<VsDB<engines::Sled>> = Lazy::new(|| pnk!(VsDB::new()));
+pub(crate) type EngineImpl = engines::Backend;
 
-#[cfg(all(feature = "rocks_engine", not(feature = "sled_engine")))]
-pub(crate) static VSDB: Lazy<VsDB<engines::RocksDB>> = Lazy::new(|| pnk!(VsDB::new()));
+pub(crate) static VSDB: Lazy<VsDB<EngineImpl>> = Lazy::new(|| pnk!(VsDB::new()));
 
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
@@ -107,11 +154,26 @@ pub(crate) struct VsDB<T: Engine> {
 }
 
 impl<T: Engine> VsDB<T> {
+    // The global one, opened in ${VSDB_BASE_DIR}.
     #[inline(always)]
     fn new() -> Result<Self> {
-        Ok(Self {
-            db: T::new().c(d!())?,
-        })
+        let dir = vsdb_get_base_dir();
+        let db = T::new(&dir).c(d!())?;
+        areas::check_layout(&dir, db.area_count()).c(d!())?;
+
+        // avoid setting again on an opened DB
+        info_omit!(vsdb_set_base_dir(dir));
+
+        config::mark_db_opened();
+        Ok(Self { db })
+    }
+
+    // Opened by `Vsdb::open`, the global state is left untouched.
+    #[inline(always)]
+    fn new_at(dir: &str) -> Result<Self> {
+        let db = T::new(dir).c(d!())?;
+        areas::check_layout(dir, db.area_count()).c(d!())?;
+        Ok(Self { db })
     }
 
     #[inline(always)]
@@ -125,22 +187,51 @@ impl<T: Engine> VsDB<T> {
     }
 
     #[inline(always)]
-    fn flush(&self) {
-        self.db.flush()
+    pub(crate) fn flush(&self) {
+        // nothing can be written by a replica
+        if replica::vsdb_is_replica() {
+            return;
+        }
+        let t = config::op_start();
+        ttl::sweep(&self.db);
+        self.db.flush();
+        config::op_finish("flush", t);
+        mirror::notify_flushed();
     }
 }
 
 /////////////////////////////////////////////////////////////////////////////
 /////////////////////////////////////////////////////////////////////////////
 
+// ${VSDB_BASE_DIR} in the environment takes precedence,
+// the default one is decided by the platform.
 #[inline(always)]
 fn gen_data_dir() -> String {
-    // Compatible with Windows OS?
-    let d = env::var(BASE_DIR_VAR)
-        .or_else(|_| env::var("HOME").map(|h| format!("{}/.vsdb", h)))
-        .unwrap_or_else(|_| "/tmp/.vsdb".to_owned());
+    let d = env::var_os(BASE_DIR_VAR)
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(default_data_dir);
     fs::create_dir_all(&d).unwrap();
-    d
+    d.to_string_lossy().into_owned()
+}
+
+// %LOCALAPPDATA%\vsdb
+#[cfg(windows)]
+fn default_data_dir() -> PathBuf {
+    env::var_os("LOCALAPPDATA")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|d| !d.is_empty())
+        .map(|d| PathBuf::from(d).join("vsdb"))
+        .unwrap_or_else(|| env::temp_dir().join("vsdb"))
+}
+
+// $HOME/.vsdb
+#[cfg(not(windows))]
+fn default_data_dir() -> PathBuf {
+    env::var_os("HOME")
+        .filter(|d| !d.is_empty())
+        .map(|d| PathBuf::from(d).join(".vsdb"))
+        .unwrap_or_else(|| env::temp_dir().join(".vsdb"))
 }
 
 /// ${VSDB_CUSTOM_DIR}
@@ -149,7 +240,8 @@ pub fn vsdb_get_custom_dir() -> String {
     VSDB_CUSTOM_DIR.clone()
 }
 
-/// ${VSDB_BASE_DIR}
+/// ${VSDB_BASE_DIR}, defaults to `$HOME/.vsdb`,
+/// or `%LOCALAPPDATA%\vsdb` on Windows.
 #[inline(always)]
 pub fn vsdb_get_base_dir() -> String {
     VSDB_BASE_DIR.lock().clone()
@@ -169,12 +261,44 @@ pub fn vsdb_set_base_dir(dir: String) -> Result<()> {
     }
 }
 
-/// Flush data to disk, may take a long time.
+/// Set how many ancestral branches at most one new branch can have.
+///
+/// It can only be set before VSDB is initialized, and should not be
+/// lowered between restarts, or the remote ancestors of existing branches
+/// will be invisible to them.
+#[inline(always)]
+pub fn vsdb_set_branch_ancestors_limit(limit: usize) -> Result<()> {
+    if 0 == limit {
+        return Err(eg!("the limit should NOT be zero"));
+    }
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+    BRANCH_ANCESTORS_LIMIT_CUR.store(limit, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Get the current limit of ancestral branches.
+#[inline(always)]
+pub fn vsdb_get_branch_ancestors_limit() -> usize {
+    BRANCH_ANCESTORS_LIMIT_CUR.load(Ordering::Relaxed)
+}
+
+/// Flush data to disk, may take a long time,
+/// check `vsdb_flush_async` for a non-blocking one.
 #[inline(always)]
 pub fn vsdb_flush() {
     VSDB.flush();
 }
 
+/// Storage statistics of the global database reported by the engine,
+/// such as the space amplification and the tombstones of each area,
+/// items not tracked by the engine are left as `None`.
+#[inline(always)]
+pub fn vsdb_storage_stats() -> StorageStats {
+    VSDB.db.storage_stats()
+}
+
 macro_rules! impl_from_for_name {
     ($target: tt) => {
         impl<'a> From<&'a [u8]> for $target<'a> {
@@ -204,7 +328,7 @@ macro_rules! impl_from_for_name {
     };
 }
 
-impl_from_for_name!(BranchName, ParentBranchName, VersionName);
+impl_from_for_name!(BranchName, ParentBranchName, SavepointName, VersionName);
 
 impl Default for BranchName<'static> {
     fn default() -> Self {
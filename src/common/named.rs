@@ -0,0 +1,61 @@
+//!
+//! Collections bound to names.
+//!
+//! `open_at(path)` of a collection returns the one registered at `path` in the
+//! current database, or creates a new one and registers it there, so the same
+//! logical collection can be reconnected to by its name after restarting,
+//! no ID needs to be recorded anywhere.
+//!
+//! Paths look like `accounts/balances`, empty segments are ignored,
+//! `.` and `..` are not allowed.
+//!
+//! NOTE: a path should always be opened as the same type.
+//!
+
+use crate::{
+    basic::mapx_raw::MapxRaw,
+    common::{
+        ende::{ValueDe, ValueEn},
+        NAME_REGISTRY,
+    },
+};
+use parking_lot::Mutex;
+use ruc::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+// a path is registered only once
+static LK: Mutex<()> = parking_lot::const_mutex(());
+
+pub(crate) fn open_at<T>(path: &str, new: impl FnOnce() -> T) -> Result<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let path = normalize(path).c(d!())?;
+
+    let _guard = LK.lock();
+
+    let registry = MapxRaw::open_reserved(NAME_REGISTRY);
+    if let Some(meta) = registry.get(path.as_bytes()) {
+        return T::decode_value(&meta)
+            .c(d!(format!("`{}` has been opened as another type", path)));
+    }
+
+    let hdr = new();
+    registry.insert(path.as_bytes(), &hdr.encode_value());
+
+    Ok(hdr)
+}
+
+fn normalize(path: &str) -> Result<String> {
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    if segments.is_empty() {
+        return Err(eg!("empty path"));
+    }
+    if segments.iter().any(|s| "." == *s || ".." == *s) {
+        return Err(eg!(format!("invalid path: {}", path)));
+    }
+    Ok(segments.join("/"))
+}
@@ -0,0 +1,81 @@
+//!
+//! Deterministic prefix allocation.
+//!
+//! By default, the prefix of every new instance is allocated from
+//! a global counter, so the layout of the database depends on the
+//! order in which instances are created by all threads.
+//!
+//! Within [vsdb_with_prefix_namespace](self::vsdb_with_prefix_namespace),
+//! prefixes are derived from the hash of the namespace and the creation order
+//! inside the closure, two independently initialized nodes will produce
+//! the same prefixes for the same logical operations.
+//!
+
+use crate::common::{Prefix, PREFIX_SIZ};
+use std::cell::RefCell;
+
+// Derived prefixes always have the highest bit set,
+// the global counter will never reach this range.
+const DERIVED_PREFIX_FLAG: Prefix = 1 << (8 * PREFIX_SIZ - 1);
+
+struct Namespace {
+    hash: u64,
+    // how many instances have been created within this namespace
+    cnt: u64,
+}
+
+thread_local! {
+    static NAMESPACE: RefCell<Option<Namespace>> = RefCell::new(None);
+}
+
+/// Run `f` in a namespace, all instances created by it(in the current thread)
+/// will get prefixes derived from `namespace`, instead of the global counter.
+///
+/// Nested calls shadow the outer namespace until they return.
+///
+/// NOTE:
+/// - Branch IDs and version IDs are still allocated from global counters
+/// - Namespaces should be unique within a database, prefixes that
+///   have been taken will be skipped(deterministically)
+pub fn vsdb_with_prefix_namespace<T>(namespace: &str, f: impl FnOnce() -> T) -> T {
+    let ns = Namespace {
+        hash: fnv1a(namespace.as_bytes()),
+        cnt: 0,
+    };
+
+    // restore the outer namespace even if `f` panics
+    struct Restore(Option<Namespace>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let outer = self.0.take();
+            NAMESPACE.with(|n| *n.borrow_mut() = outer);
+        }
+    }
+
+    let _outer = Restore(NAMESPACE.with(|n| n.borrow_mut().replace(ns)));
+    f()
+}
+
+// Get the next candidate prefix of the current namespace,
+// `None` if there is no namespace in use.
+#[inline(always)]
+pub(crate) fn next_derived_prefix() -> Option<Prefix> {
+    NAMESPACE.with(|n| {
+        n.borrow_mut().as_mut().map(|ns| {
+            let mut buf = ns.hash.to_be_bytes().to_vec();
+            buf.extend_from_slice(&ns.cnt.to_be_bytes());
+            ns.cnt += 1;
+            fnv1a(&buf) | DERIVED_PREFIX_FLAG
+        })
+    })
+}
+
+// A stable hash, the result must not change between builds or platforms.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |h, b| (h ^ *b as u64).wrapping_mul(PRIME))
+}
@@ -0,0 +1,81 @@
+//!
+//! Protobuf messages as values.
+//!
+//! `Proto<M>` wraps any `prost::Message`, it implements the serde traits by
+//! the protobuf encoding of `M`, so it gets `ValueEnDe`(and `KeyEnDe`) and can
+//! be stored in all collections, e.g. `Mapx<u64, Proto<Account>>` or
+//! `MapxVs<u64, Proto<Account>>`, no serde derives are needed on `M`.
+//!
+//! It works along with any codec, the protobuf bytes are stored as a byte
+//! string of the selected codec.
+//!
+
+use crate::common::ende::SimpleVisitor;
+use prost::Message;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// A protobuf message stored by its protobuf encoding.
+#[derive(Clone, Default, PartialEq)]
+pub struct Proto<M>(pub M);
+
+impl<M> Proto<M> {
+    #[inline(always)]
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M> From<M> for Proto<M> {
+    #[inline(always)]
+    fn from(m: M) -> Self {
+        Proto(m)
+    }
+}
+
+impl<M> Deref for Proto<M> {
+    type Target = M;
+
+    #[inline(always)]
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> DerefMut for Proto<M> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.0
+    }
+}
+
+impl<M: fmt::Debug> fmt::Debug for Proto<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<M: Message> Serialize for Proto<M> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0.encode_to_vec())
+    }
+}
+
+impl<'de, M: Message + Default> Deserialize<'de> for Proto<M> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_bytes(SimpleVisitor)
+            .and_then(|bytes| {
+                M::decode(&bytes[..]).map(Proto).map_err(de::Error::custom)
+            })
+    }
+}
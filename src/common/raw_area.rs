@@ -0,0 +1,60 @@
+//!
+//! Raw byte-level storage areas for custom formats.
+//!
+//! A [RawArea](self::RawArea) is an isolated key space in the same database
+//! as all other VSDB instances, data in it is flushed, mirrored and backed up
+//! along with them, but no encoding or versioning is applied.
+//!
+//! The ID of an area is stable, record it somewhere(e.g. in a
+//! `MapxRaw`/`OrphanVs`), and reconnect to the area by `RawArea::open`
+//! after restarting.
+//!
+
+use crate::basic::mapx_raw::MapxRaw;
+use ruc::*;
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+
+/// Reserve a new raw area.
+#[inline(always)]
+pub fn raw_area() -> RawArea {
+    RawArea {
+        inner: MapxRaw::new(),
+    }
+}
+
+/// An isolated key space, keys and values are stored as they are.
+///
+/// All byte-level operations(`get`/`insert`/`remove`/`range`/`flush`, etc.)
+/// are provided by the underlying [MapxRaw](crate::basic::mapx_raw::MapxRaw).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawArea {
+    inner: MapxRaw,
+}
+
+impl RawArea {
+    /// Reconnect to an existing area.
+    ///
+    /// NOTE: only IDs returned by `RawArea::id` should be used here,
+    /// opening the internal space of other instances will corrupt them.
+    #[inline(always)]
+    pub fn open(id: u64) -> Result<Self> {
+        MapxRaw::open(id)
+            .c(d!("raw area not found"))
+            .map(|inner| Self { inner })
+    }
+
+    /// The stable ID of this area.
+    #[inline(always)]
+    pub fn id(&self) -> u64 {
+        self.inner.id()
+    }
+}
+
+impl Deref for RawArea {
+    type Target = MapxRaw;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
@@ -0,0 +1,322 @@
+//!
+//! Sharing one storage node between processes.
+//!
+//! A node exposes its engine by [vsdb_serve](self::vsdb_serve), clients call
+//! [vsdb_connect_remote](self::vsdb_connect_remote) before VSDB is initialized,
+//! then all low-level operations of the global database are sent to the node.
+//!
+//! The protocol is simple and stable, it does not depend on the codec of VSDB,
+//! every message is a frame of `[payload len: u32 BE][payload]`:
+//!
+//! - a request starts with an op code(1 byte), followed by its arguments
+//! - a response starts with `0u8` followed by the result,
+//!   or `1u8` followed by an error message
+//! - integers are u64 BE, byte strings are `[len: u64 BE][bytes]`
+//!
+//! NOTE:
+//! - There is no authentication or encryption, only use it in trusted networks
+//! - Only the global database of the node is served
+//!
+
+use crate::common::{
+    engines::{vsdb_register_engine, Engine, RemoteEngine},
+    PrefixBytes, RawValue, PREFIX_SIZ, VSDB,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Bound,
+    thread,
+};
+
+// larger frames are refused, a peer can not exhaust the memory
+const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+// how many entries at most are returned by one seek
+pub(crate) const SEEK_CHUNK: usize = 128;
+
+pub(crate) const OP_ALLOC_PREFIX: u8 = 0;
+pub(crate) const OP_ALLOC_BRANCH_ID: u8 = 1;
+pub(crate) const OP_ALLOC_VERSION_ID: u8 = 2;
+pub(crate) const OP_AREA_COUNT: u8 = 3;
+pub(crate) const OP_FLUSH: u8 = 4;
+pub(crate) const OP_FLUSH_AREA: u8 = 5;
+pub(crate) const OP_SET_CACHE_SIZE: u8 = 6;
+pub(crate) const OP_REFRESH: u8 = 7;
+pub(crate) const OP_SEEK: u8 = 8;
+pub(crate) const OP_GET: u8 = 9;
+pub(crate) const OP_INSERT: u8 = 10;
+pub(crate) const OP_REMOVE: u8 = 11;
+pub(crate) const OP_GET_INSTANCE_LEN: u8 = 12;
+pub(crate) const OP_INSTANCE_EXISTS: u8 = 13;
+pub(crate) const OP_SET_INSTANCE_LEN: u8 = 14;
+pub(crate) const OP_INSTANCE_IS_APPEND_ONLY: u8 = 15;
+pub(crate) const OP_SET_INSTANCE_APPEND_ONLY: u8 = 16;
+
+static REMOTE_ADDR: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Use the engine of the node at `addr`(eg. "10.0.0.1:30000"),
+/// it can only be called before VSDB is initialized.
+pub fn vsdb_connect_remote(addr: &str) -> Result<()> {
+    vsdb_register_engine::<RemoteEngine>().c(d!())?;
+    set_remote_addr(addr);
+    Ok(())
+}
+
+#[inline(always)]
+pub(crate) fn set_remote_addr(addr: &str) {
+    *REMOTE_ADDR.lock() = Some(addr.to_owned());
+}
+
+#[inline(always)]
+pub(crate) fn remote_addr() -> Option<String> {
+    REMOTE_ADDR.lock().clone()
+}
+
+/// Serve the engine of this process on `addr`,
+/// block the current thread until the listener fails.
+pub fn vsdb_serve(addr: &str) -> Result<()> {
+    serve_on(TcpListener::bind(addr).c(d!())?).c(d!())
+}
+
+// every connection is served by its own thread
+pub(crate) fn serve_on(listener: TcpListener) -> Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream.c(d!())?;
+        thread::spawn(move || info_omit!(serve(stream)));
+    }
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream) -> Result<()> {
+    stream.set_nodelay(true).c(d!())?;
+    while let Some(req) = read_frame(&mut stream).c(d!())? {
+        let resp = match handle(&req) {
+            Ok(mut ret) => {
+                ret.insert(0, 0);
+                ret
+            }
+            Err(e) => {
+                let mut ret = vec![1];
+                ret.extend_from_slice(e.to_string().as_bytes());
+                ret
+            }
+        };
+        write_frame(&mut stream, &resp).c(d!())?;
+    }
+    Ok(())
+}
+
+fn handle(req: &[u8]) -> Result<Vec<u8>> {
+    let db = &VSDB.db;
+    let r = &mut Reader(req);
+
+    let mut ret = vec![];
+    match r.u8().c(d!())? {
+        OP_ALLOC_PREFIX => put_u64(&mut ret, db.alloc_prefix()),
+        OP_ALLOC_BRANCH_ID => put_u64(&mut ret, db.alloc_branch_id()),
+        OP_ALLOC_VERSION_ID => put_u64(&mut ret, db.alloc_version_id()),
+        OP_AREA_COUNT => put_u64(&mut ret, db.area_count() as u64),
+        OP_FLUSH => db.flush(),
+        OP_FLUSH_AREA => db.flush_area(r.area_idx().c(d!())?),
+        OP_SET_CACHE_SIZE => db.set_cache_size(r.u64().c(d!())? as usize).c(d!())?,
+        OP_REFRESH => db.refresh().c(d!())?,
+        OP_SEEK => {
+            let area_idx = r.area_idx().c(d!())?;
+            let prefix = r.prefix().c(d!())?;
+            let lo = r.bound().c(d!())?;
+            let hi = r.bound().c(d!())?;
+            let forward = r.flag().c(d!())?;
+            let iter = db.range(area_idx, prefix, (lo, hi));
+            let entries = if forward {
+                iter.take(SEEK_CHUNK).collect::<Vec<_>>()
+            } else {
+                iter.rev().take(SEEK_CHUNK).collect::<Vec<_>>()
+            };
+            put_u64(&mut ret, entries.len() as u64);
+            for (k, v) in entries.iter() {
+                put_bytes(&mut ret, k);
+                put_bytes(&mut ret, v);
+            }
+        }
+        OP_GET => {
+            let (area_idx, prefix) = (r.area_idx().c(d!())?, r.prefix().c(d!())?);
+            let v = db.get(area_idx, prefix, r.bytes().c(d!())?);
+            put_optioned_bytes(&mut ret, v);
+        }
+        OP_INSERT => {
+            let (area_idx, prefix) = (r.area_idx().c(d!())?, r.prefix().c(d!())?);
+            let (k, v) = (r.bytes().c(d!())?, r.bytes().c(d!())?);
+            put_optioned_bytes(&mut ret, db.insert(area_idx, prefix, k, v));
+        }
+        OP_REMOVE => {
+            let (area_idx, prefix) = (r.area_idx().c(d!())?, r.prefix().c(d!())?);
+            let v = db.remove(area_idx, prefix, r.bytes().c(d!())?);
+            put_optioned_bytes(&mut ret, v);
+        }
+        OP_GET_INSTANCE_LEN => {
+            let prefix = r.prefix().c(d!())?;
+            if !db.instance_exists(prefix) {
+                return Err(eg!("instance not found"));
+            }
+            put_u64(&mut ret, db.get_instance_len(prefix));
+        }
+        OP_INSTANCE_EXISTS => {
+            ret.push(db.instance_exists(r.prefix().c(d!())?) as u8);
+        }
+        OP_SET_INSTANCE_LEN => {
+            let prefix = r.prefix().c(d!())?;
+            db.set_instance_len(prefix, r.u64().c(d!())?);
+        }
+        OP_INSTANCE_IS_APPEND_ONLY => {
+            ret.push(db.instance_is_append_only(r.prefix().c(d!())?) as u8);
+        }
+        OP_SET_INSTANCE_APPEND_ONLY => {
+            db.set_instance_append_only(r.prefix().c(d!())?);
+        }
+        _ => return Err(eg!("unknown op")),
+    }
+
+    if !r.0.is_empty() {
+        return Err(eg!("trailing bytes in the request"));
+    }
+
+    Ok(ret)
+}
+
+// Send a request and wait for its response.
+pub(crate) fn call(stream: &mut TcpStream, req: &[u8]) -> Result<Vec<u8>> {
+    write_frame(stream, req).c(d!())?;
+    let resp = read_frame(stream)
+        .c(d!())?
+        .c(d!("the connection is closed"))?;
+    match resp.split_first() {
+        Some((0, ret)) => Ok(ret.to_vec()),
+        Some((1, msg)) => Err(eg!(String::from_utf8_lossy(msg))),
+        _ => Err(eg!("invalid response")),
+    }
+}
+
+fn write_frame(stream: &mut TcpStream, payload: &[u8]) -> Result<()> {
+    let len = u32::try_from(payload.len()).c(d!("frame too large"))?;
+    stream.write_all(&len.to_be_bytes()).c(d!())?;
+    stream.write_all(payload).c(d!())
+}
+
+// `None` if the peer has closed the connection.
+fn read_frame(stream: &mut TcpStream) -> Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if ErrorKind::UnexpectedEof == e.kind() => return Ok(None),
+        Err(e) => return Err(eg!(e)),
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(eg!("frame too large"));
+    }
+
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).c(d!())?;
+    Ok(Some(payload))
+}
+
+pub(crate) fn put_u64(buf: &mut Vec<u8>, n: u64) {
+    buf.extend_from_slice(&n.to_be_bytes());
+}
+
+pub(crate) fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+pub(crate) fn put_bound(buf: &mut Vec<u8>, b: Bound<&[u8]>) {
+    match b {
+        Bound::Unbounded => buf.push(0),
+        Bound::Included(k) => {
+            buf.push(1);
+            put_bytes(buf, k);
+        }
+        Bound::Excluded(k) => {
+            buf.push(2);
+            put_bytes(buf, k);
+        }
+    }
+}
+
+fn put_optioned_bytes(buf: &mut Vec<u8>, bytes: Option<RawValue>) {
+    if let Some(b) = bytes {
+        buf.push(1);
+        put_bytes(buf, &b);
+    } else {
+        buf.push(0);
+    }
+}
+
+// Lengths are never trusted, they are checked against the remaining bytes.
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(eg!("truncated message"));
+        }
+        let (ret, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(ret)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        self.take(1).c(d!()).map(|b| b[0])
+    }
+
+    pub(crate) fn flag(&mut self) -> Result<bool> {
+        self.u8().c(d!()).map(|b| 0 != b)
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        self.take(8).c(d!()).map(|b| crate::parse_int!(b, u64))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u64().c(d!())?;
+        let len = usize::try_from(len).c(d!("truncated message"))?;
+        self.take(len).c(d!())
+    }
+
+    pub(crate) fn optioned_bytes(&mut self) -> Result<Option<RawValue>> {
+        if self.flag().c(d!())? {
+            self.bytes().c(d!()).map(|b| Some(b.into()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn prefix(&mut self) -> Result<PrefixBytes> {
+        self.take(PREFIX_SIZ)
+            .c(d!())
+            .map(|b| <PrefixBytes>::try_from(b).unwrap())
+    }
+
+    // an out-of-range index would panic the engine
+    fn area_idx(&mut self) -> Result<usize> {
+        let idx = self.u64().c(d!())? as usize;
+        if idx >= VSDB.db.area_count() {
+            return Err(eg!("invalid area index"));
+        }
+        Ok(idx)
+    }
+
+    fn bound(&mut self) -> Result<Bound<&'a [u8]>> {
+        match self.u8().c(d!())? {
+            0 => Ok(Bound::Unbounded),
+            1 => self.bytes().c(d!()).map(Bound::Included),
+            2 => self.bytes().c(d!()).map(Bound::Excluded),
+            _ => Err(eg!("invalid bound")),
+        }
+    }
+}
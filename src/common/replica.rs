@@ -0,0 +1,81 @@
+//!
+//! Read-only replicas of a data directory.
+//!
+//! A replica is another process that opens the data directory of a running
+//! writer(the primary) in read-only mode, so heavy reads(reporting, analysis,
+//! etc.) can be offloaded from the primary.
+//!
+//! The view of a replica is a snapshot of the data flushed by the primary,
+//! call [vsdb_refresh](self::vsdb_refresh) to catch up with the newly
+//! flushed data, or let a background thread do it periodically.
+//!
+//! NOTE:
+//! - Only the rocksdb, lmdb and sqlite engines support replicas,
+//!   sled locks its directory
+//! - Any write(including creating new instances) panics in a replica
+//! - Instances must be opened by their IDs or deserialized
+//!   from metadata written by the primary
+//!
+
+use crate::common::{config, vsdb_get_base_dir, Engine, VSDB};
+use ruc::*;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+static REPLICA: AtomicBool = AtomicBool::new(false);
+
+/// Open `${VSDB_BASE_DIR}` as a read-only replica.
+///
+/// It can only be called before VSDB is initialized,
+/// if `refresh_interval` is not zero, a background thread will
+/// refresh the view of the replica at this interval.
+pub fn vsdb_open_as_replica(refresh_interval: Duration) -> Result<()> {
+    if config::db_opened() {
+        return Err(eg!("VSDB has been initialized !!"));
+    }
+    if REPLICA.swap(true, Ordering::Relaxed) {
+        return Err(eg!("already in the replica mode"));
+    }
+
+    if !refresh_interval.is_zero() {
+        thread::spawn(move || loop {
+            thread::sleep(refresh_interval);
+            // do not open the database in the background
+            if config::db_opened() {
+                info_omit!(vsdb_refresh());
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether this process works as a read-only replica.
+#[inline(always)]
+pub fn vsdb_is_replica() -> bool {
+    REPLICA.load(Ordering::Relaxed)
+}
+
+/// Catch up with the data flushed by the primary,
+/// instances opened before will see the new data after this call.
+#[inline(always)]
+pub fn vsdb_refresh() -> Result<()> {
+    if !vsdb_is_replica() {
+        return Err(eg!("not a replica"));
+    }
+    VSDB.db.refresh().c(d!())
+}
+
+// Where a replica keeps its own files(logs, etc.),
+// every process uses a separate one.
+pub(crate) fn replica_dir() -> String {
+    Path::new(&vsdb_get_base_dir())
+        .join("__REPLICA__")
+        .join(std::process::id().to_string())
+        .to_string_lossy()
+        .into_owned()
+}
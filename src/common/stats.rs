@@ -0,0 +1,142 @@
+//!
+//! Statistics about the stored data.
+//!
+
+/// Number of buckets in a size histogram,
+/// sizes bigger than `2^(SIZE_BUCKET_NUM - 2)` share the last bucket.
+pub const SIZE_BUCKET_NUM: usize = 33;
+
+/// Distribution of the (encoded) key and value sizes of a collection.
+///
+/// Sizes are grouped into power-of-two buckets:
+/// - `[0]` counts the empty ones
+/// - `[i]`(i > 0) counts the ones whose size is in `[2^(i-1), 2^i)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeHistogram {
+    /// Distribution of key sizes.
+    pub keys: [u64; SIZE_BUCKET_NUM],
+    /// Distribution of value sizes.
+    pub values: [u64; SIZE_BUCKET_NUM],
+    /// How many entries have been recorded.
+    pub entries: u64,
+    /// Total size of all recorded keys.
+    pub total_key_size: u64,
+    /// Total size of all recorded values.
+    pub total_value_size: u64,
+}
+
+impl Default for SizeHistogram {
+    fn default() -> Self {
+        Self {
+            keys: [0; SIZE_BUCKET_NUM],
+            values: [0; SIZE_BUCKET_NUM],
+            entries: 0,
+            total_key_size: 0,
+            total_value_size: 0,
+        }
+    }
+}
+
+impl SizeHistogram {
+    /// Build a histogram from a sequence of KVs,
+    /// only one of every `sample_step` entries will be recorded,
+    /// `0` and `1` mean recording all entries.
+    pub fn scan<K, V>(iter: impl Iterator<Item = (K, V)>, sample_step: usize) -> Self
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut ret = Self::default();
+        iter.step_by(sample_step.max(1)).for_each(|(k, v)| {
+            ret.record(k.as_ref().len(), v.as_ref().len());
+        });
+        ret
+    }
+
+    /// Record the size of a KV.
+    #[inline(always)]
+    pub fn record(&mut self, key_size: usize, value_size: usize) {
+        self.keys[Self::bucket_idx(key_size)] += 1;
+        self.values[Self::bucket_idx(value_size)] += 1;
+        self.entries += 1;
+        self.total_key_size += key_size as u64;
+        self.total_value_size += value_size as u64;
+    }
+
+    /// Merge another histogram into this one.
+    pub fn merge(&mut self, other: &Self) {
+        (0..SIZE_BUCKET_NUM).for_each(|i| {
+            self.keys[i] += other.keys[i];
+            self.values[i] += other.values[i];
+        });
+        self.entries += other.entries;
+        self.total_key_size += other.total_key_size;
+        self.total_value_size += other.total_value_size;
+    }
+
+    /// The upper bound(exclusive) of sizes in a bucket.
+    #[inline(always)]
+    pub fn bucket_upper_bound(idx: usize) -> u64 {
+        1u64 << idx.min(SIZE_BUCKET_NUM - 1)
+    }
+
+    #[inline(always)]
+    fn bucket_idx(size: usize) -> usize {
+        let idx = (usize::BITS - size.leading_zeros()) as usize;
+        idx.min(SIZE_BUCKET_NUM - 1)
+    }
+}
+
+/// Statistics of the storage, reported by the engine,
+/// `None` means the item is not tracked by the engine.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Estimated size of the live data.
+    pub live_bytes: Option<u64>,
+    /// Size of the data files on the disk.
+    pub disk_bytes: Option<u64>,
+    /// Estimated size of the data to be rewritten by pending compactions.
+    pub pending_compaction_bytes: Option<u64>,
+    /// Statistics of each area, may be empty if nothing is tracked per area.
+    pub areas: Vec<AreaStorageStats>,
+}
+
+/// Storage statistics of an area.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AreaStorageStats {
+    /// Estimated size of the live data.
+    pub live_bytes: Option<u64>,
+    /// Size of the data files on the disk.
+    pub disk_bytes: Option<u64>,
+    /// Deletion markers that have not been dropped by compactions.
+    pub tombstones: Option<u64>,
+}
+
+impl StorageStats {
+    /// Build the totals from the statistics of all areas.
+    pub(crate) fn from_areas(areas: Vec<AreaStorageStats>) -> Self {
+        Self {
+            live_bytes: areas.iter().map(|a| a.live_bytes).sum(),
+            disk_bytes: areas.iter().map(|a| a.disk_bytes).sum(),
+            pending_compaction_bytes: None,
+            areas,
+        }
+    }
+
+    /// `disk_bytes / live_bytes`, `None` if any of them is unknown,
+    /// or there is no live data.
+    pub fn space_amplification(&self) -> Option<f64> {
+        match (self.disk_bytes, self.live_bytes) {
+            (Some(disk), Some(live)) if 0 < live => Some(disk as f64 / live as f64),
+            _ => None,
+        }
+    }
+
+    /// Tombstones of all areas, `None` if any area is unknown.
+    pub fn tombstones(&self) -> Option<u64> {
+        if self.areas.is_empty() {
+            return None;
+        }
+        self.areas.iter().map(|a| a.tombstones).sum()
+    }
+}
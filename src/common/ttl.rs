@@ -0,0 +1,153 @@
+//!
+//! Expiration of entries.
+//!
+//! An instance created by `new_with_ttl` accepts `insert_with_ttl`, every
+//! value of it is stored along with its expiration time(`0` means never),
+//! expired entries are invisible to reads at once, and removed physically
+//! by a sweep when the database is flushed.
+//!
+//! Expiration times of entries are also recorded in an index ordered by
+//! time, so a sweep only touches the entries that have expired.
+//!
+//! NOTE:
+//! - `len()` counts expired entries until they are swept
+//! - Entries written by `insert`(or `get_mut`, etc.) never expire
+//!
+
+use crate::common::{
+    areas, engines::Engine, replica, PrefixBytes, RawValue, PREFIX_SIZ, TTL_INDEX,
+    TTL_REGISTRY,
+};
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    mem::size_of,
+    ops::Bound,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const TIME_SIZ: usize = size_of::<u64>();
+
+// a sweep must not remove an entry that is being rewritten
+static LK: Mutex<()> = parking_lot::const_mutex(());
+
+#[inline(always)]
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    LK.lock()
+}
+
+// Milliseconds since the UNIX epoch.
+#[inline(always)]
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[inline(always)]
+pub(crate) fn expire_at(ttl: Duration) -> u64 {
+    now().saturating_add(ttl.as_millis() as u64).max(1)
+}
+
+// [ expiration time ] + [ value ]
+#[inline(always)]
+pub(crate) fn seal(value: &[u8], expire_at: u64) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(TIME_SIZ + value.len());
+    ret.extend_from_slice(&expire_at.to_be_bytes());
+    ret.extend_from_slice(value);
+    ret
+}
+
+// Strip the expiration time, `None` if the entry has expired at `now`.
+#[inline(always)]
+pub(crate) fn open(sealed: RawValue, now: u64) -> Option<RawValue> {
+    let expire_at = crate::parse_int!(sealed[..TIME_SIZ], u64);
+    if 0 != expire_at && expire_at <= now {
+        return None;
+    }
+    Some(sealed[TIME_SIZ..].to_vec().into_boxed_slice())
+}
+
+#[inline(always)]
+fn registry<E: Engine>(engine: &E) -> (usize, PrefixBytes) {
+    (
+        areas::area_of(TTL_REGISTRY, engine.area_count()),
+        TTL_REGISTRY.to_be_bytes(),
+    )
+}
+
+#[inline(always)]
+pub(crate) fn register<E: Engine>(engine: &E, instance_prefix: PrefixBytes) {
+    let (area_idx, registry) = registry(engine);
+    engine.insert(area_idx, registry, &instance_prefix, &[]);
+}
+
+#[inline(always)]
+pub(crate) fn registered<E: Engine>(engine: &E, instance_prefix: PrefixBytes) -> bool {
+    let (area_idx, registry) = registry(engine);
+    engine.get(area_idx, registry, &instance_prefix).is_some()
+}
+
+// [ expiration time ] + [ instance prefix ] + [ key ]
+pub(crate) fn track<E: Engine>(
+    engine: &E,
+    instance_prefix: PrefixBytes,
+    key: &[u8],
+    expire_at: u64,
+) {
+    let mut k = Vec::with_capacity(TIME_SIZ + PREFIX_SIZ + key.len());
+    k.extend_from_slice(&expire_at.to_be_bytes());
+    k.extend_from_slice(&instance_prefix);
+    k.extend_from_slice(key);
+    let area_idx = areas::area_of(TTL_INDEX, engine.area_count());
+    engine.insert(area_idx, TTL_INDEX.to_be_bytes(), &k, &[]);
+}
+
+// Remove all expired entries, return how many entries are removed.
+//
+// Records in the index may be stale, the entry may have been removed
+// or rewritten, it is removed only if its expiration time is not changed.
+pub(crate) fn sweep<E: Engine>(engine: &E) -> usize {
+    // nothing can be written by a replica
+    if replica::vsdb_is_replica() {
+        return 0;
+    }
+
+    let _guard = lock();
+
+    let area_cnt = engine.area_count();
+    let idx_area = areas::area_of(TTL_INDEX, area_cnt);
+    let idx_prefix = TTL_INDEX.to_be_bytes();
+    let now = now().to_be_bytes();
+
+    let due = engine
+        .range(
+            idx_area,
+            idx_prefix,
+            (Bound::Unbounded, Bound::Excluded(&now[..])),
+        )
+        .map(|(k, _)| k)
+        .collect::<Vec<_>>();
+
+    let mut cnt = 0;
+    for k in due {
+        let (expire_at, rest) = k.split_at(TIME_SIZ);
+        let (instance_prefix, key) = rest.split_at(PREFIX_SIZ);
+        let prefix = crate::parse_prefix!(instance_prefix);
+        let instance_prefix = prefix.to_be_bytes();
+        let area_idx = areas::area_of(prefix, area_cnt);
+
+        // values of TTL instances are never compressed
+        if let Some(v) = engine.get(area_idx, instance_prefix, key) {
+            if v[..TIME_SIZ] == *expire_at {
+                engine.remove(area_idx, instance_prefix, key);
+                engine.decrease_instance_len(instance_prefix);
+                cnt += 1;
+            }
+        }
+
+        engine.remove(idx_area, idx_prefix, &k);
+    }
+
+    cnt
+}
@@ -0,0 +1,122 @@
+//!
+//! Transactions with explicit commit and rollback.
+//!
+//! Changes made through a `Tx` are staged in memory, nothing is written
+//! before `commit`, which applies all of them as a
+//! [WriteBatch](crate::WriteBatch), so an error path leaves no partial state
+//! behind, the transaction is just rolled back(or dropped).
+//!
+//! ```
+//! use vsdb::{vsdb_begin_tx, Mapx};
+//!
+//! let m: Mapx<u8, u8> = Mapx::new();
+//!
+//! let mut tx = vsdb_begin_tx();
+//! tx.insert(&m, &1, &1);
+//! assert_eq!(Some(1), tx.get(&m, &1));
+//! assert!(m.get(&1).is_none());
+//! tx.rollback();
+//! assert!(m.get(&1).is_none());
+//!
+//! let mut tx = vsdb_begin_tx();
+//! tx.insert(&m, &1, &1);
+//! tx.commit().unwrap();
+//! assert_eq!(Some(1), m.get(&1));
+//! ```
+//!
+
+use crate::{
+    basic::mapx::Mapx,
+    common::{
+        ende::{decode_value_by_policy_with, encode_key_by, Codec, KeyEnDe, ValueEnDe},
+        write_batch::WriteBatch,
+        VersionName,
+    },
+    versioned::mapx::MapxVs,
+};
+use ruc::*;
+use std::ops::{Deref, DerefMut};
+
+/// Start a transaction.
+#[inline(always)]
+pub fn vsdb_begin_tx() -> Tx {
+    Tx::default()
+}
+
+/// A transaction, changes are staged by the methods of `WriteBatch`.
+#[derive(Debug, Default)]
+pub struct Tx {
+    batch: WriteBatch,
+}
+
+impl Tx {
+    /// Get a value from a `Mapx`, changes staged by this transaction are visible.
+    #[inline(always)]
+    pub fn get<K, V, C>(&self, hdr: &Mapx<K, V, C>, key: &K) -> Option<V>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        let k = encode_key_by::<C, K>(key);
+        match self.batch.staged(hdr.as_raw(), &k) {
+            Some(v) => v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)),
+            None => hdr.get(key),
+        }
+    }
+
+    /// Get a value from the default branch of a `MapxVs`,
+    /// changes staged by this transaction are visible.
+    #[inline(always)]
+    pub fn get_vs<K, V, C>(&self, hdr: &MapxVs<K, V, C>, key: &K) -> Option<V>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        let raw = hdr.as_raw();
+        let k = encode_key_by::<C, K>(key);
+        let staged = raw
+            .branch_id(None)
+            .ok()
+            .and_then(|br| self.batch.staged_vs(raw, br, &k));
+        match staged {
+            Some(v) => v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)),
+            None => hdr.get(key),
+        }
+    }
+
+    /// Write all staged changes as a whole, nothing is changed on errors.
+    #[inline(always)]
+    pub fn commit(self) -> Result<()> {
+        self.batch.commit().c(d!())
+    }
+
+    /// Write all staged changes as a whole, the changes on versioned
+    /// collections go into a new version created by `version_name`,
+    /// check `WriteBatch::commit_as_version`.
+    #[inline(always)]
+    pub fn commit_as_version(self, version_name: VersionName) -> Result<()> {
+        self.batch.commit_as_version(version_name).c(d!())
+    }
+
+    /// Discard all staged changes, the same as dropping the transaction.
+    #[inline(always)]
+    pub fn rollback(self) {}
+}
+
+impl Deref for Tx {
+    type Target = WriteBatch;
+
+    #[inline(always)]
+    fn deref(&self) -> &WriteBatch {
+        &self.batch
+    }
+}
+
+impl DerefMut for Tx {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut WriteBatch {
+        &mut self.batch
+    }
+}
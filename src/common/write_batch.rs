@@ -0,0 +1,513 @@
+//!
+//! Atomic writes across collections.
+//!
+//! A `WriteBatch` collects inserts and removes on several `Mapx`, `Vecx`
+//! and `MapxVs` instances, `commit` applies all of them or none of them.
+//!
+//! The whole batch is checked before anything is written, then its writes on
+//! each database are written to the redo log of that database as one single
+//! entry and flushed, applied, and removed from the logs at last. A batch
+//! interrupted by a crash is still in the logs, and it is finished by
+//! [vsdb_recover_write_batches](self::vsdb_recover_write_batches).
+//!
+//! NOTE: it is not a native atomic batch of the engine, other readers may see
+//! a batch partially applied while it is being committed.
+//!
+
+use crate::{
+    basic::{mapx::Mapx, mapx_raw::MapxRaw, vecx::Vecx},
+    common::{
+        append_only::AppendOnlyViolation,
+        database,
+        ende::{
+            encode_key_by, encode_value_by, Codec, KeyEnDe, ValueDe, ValueEn, ValueEnDe,
+        },
+        BranchID, BranchName, RawBytes, RawKey, RawValue, VersionName, WRITE_BATCH_LOG,
+    },
+    versioned::{mapx::MapxVs, mapx_raw::MapxRawVs},
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ptr,
+};
+
+// a `None` value means a remove
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Op {
+    Raw(MapxRaw, RawKey, Option<RawValue>),
+    Vs(MapxRawVs, BranchID, RawKey, Option<RawValue>),
+    // create a version on a branch,
+    // the following writes on the branch go into it
+    Version(MapxRawVs, BranchID, RawBytes),
+}
+
+impl Op {
+    // Apply it if it has not been applied,
+    // return the value to restore if it is applied now.
+    fn apply(&self) -> Result<Option<Option<RawValue>>> {
+        let (old, value) = match self {
+            Op::Raw(hdr, k, v) => (hdr.get(k), v),
+            Op::Vs(hdr, br, k, v) => (hdr.get_by_branch_id(k, *br), v),
+            Op::Version(hdr, br, name) => {
+                alt!(hdr.version_is_head_by_branch_id(name, *br), return Ok(None));
+                return hdr
+                    .version_create_by_branch_id(name, *br)
+                    .c(d!())
+                    .map(|_| Some(None));
+            }
+        };
+        alt!(old == *value, return Ok(None));
+        self.write(value.as_deref()).c(d!()).map(|_| Some(old))
+    }
+
+    fn undo(&self, old: Option<RawValue>) -> Result<()> {
+        if let Op::Version(hdr, br, _) = self {
+            return hdr.version_pop_by_branch_id(*br).c(d!());
+        }
+        self.write(old.as_deref()).c(d!())
+    }
+
+    // The database holding the target of this operation.
+    #[inline(always)]
+    fn db(&self) -> database::Db {
+        match self {
+            Op::Raw(hdr, ..) => hdr.db(),
+            Op::Vs(hdr, ..) | Op::Version(hdr, ..) => hdr.db(),
+        }
+    }
+
+    // nothing is written by an `Op::Version`
+    fn write(&self, value: Option<&[u8]>) -> Result<()> {
+        match self {
+            Op::Raw(hdr, k, _) => {
+                let ret = match value {
+                    Some(v) => hdr.try_insert(k, v).map(|_| ()),
+                    None => hdr.try_remove(k).map(|_| ()),
+                };
+                ret.c(d!())
+            }
+            Op::Vs(hdr, br, k, _) => {
+                hdr.write_by_branch_id(k, value, *br).c(d!()).map(|_| ())
+            }
+            Op::Version(..) => Ok(()),
+        }
+    }
+}
+
+// Apply all operations in order, the applied ones are restored on errors.
+//
+// Operations already applied are skipped,
+// so a batch can be applied again after an interruption.
+fn apply(ops: &[Op]) -> Result<()> {
+    let mut applied = vec![];
+    for op in ops.iter() {
+        match op.apply() {
+            Ok(Some(old)) => applied.push((op, old)),
+            Ok(None) => {}
+            Err(e) => {
+                for (op, old) in applied.into_iter().rev() {
+                    let _ = op.undo(old);
+                }
+                return Err(e).c(d!());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A set of writes on multiple collections, applied as a whole.
+///
+/// ```
+/// use vsdb::{Mapx, Vecx, WriteBatch};
+///
+/// let balances: Mapx<u8, u64> = Mapx::new();
+/// let logs: Vecx<String> = Vecx::new();
+/// balances.insert(1, 100);
+///
+/// let mut batch = WriteBatch::new();
+/// batch.insert(&balances, &1, &90);
+/// batch.insert(&balances, &2, &10);
+/// batch.push(&logs, &"1 => 2: 10".to_owned());
+/// batch.commit().unwrap();
+///
+/// assert_eq!(Some(90), balances.get(&1));
+/// assert_eq!(Some(10), balances.get(&2));
+/// assert_eq!(1, logs.len());
+/// ```
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    ops: Vec<Op>,
+    // the index that the next element pushed to a `Vecx` will get,
+    // indexed by the ID of its instance
+    vec_ends: HashMap<u64, u64>,
+}
+
+impl WriteBatch {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn insert<K, V, C>(&mut self, hdr: &Mapx<K, V, C>, key: &K, value: &V)
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        self.ops.push(Op::Raw(
+            *hdr.as_raw(),
+            encode_key_by::<C, K>(key),
+            Some(encode_value_by::<C, V>(value)),
+        ));
+    }
+
+    #[inline(always)]
+    pub fn remove<K, V, C>(&mut self, hdr: &Mapx<K, V, C>, key: &K)
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        self.ops
+            .push(Op::Raw(*hdr.as_raw(), encode_key_by::<C, K>(key), None));
+    }
+
+    // Insert the encoded bytes of a value, used by the versioned internals.
+    #[inline(always)]
+    pub(crate) fn insert_raw(&mut self, hdr: &MapxRaw, key: &[u8], value: &[u8]) {
+        self.ops.push(Op::Raw(
+            *hdr,
+            key.to_vec().into(),
+            Some(value.to_vec().into()),
+        ));
+    }
+
+    /// The index of the pushed element is decided here,
+    /// so the `Vecx` should not be changed by others before `commit`.
+    #[inline(always)]
+    pub fn push<T: ValueEnDe>(&mut self, hdr: &Vecx<T>, value: &T) {
+        let raw = hdr.as_raw();
+        let end = self.vec_ends.entry(raw.id()).or_insert_with(|| hdr.end());
+        self.ops.push(Op::Raw(
+            *raw,
+            end.to_be_bytes().to_vec().into(),
+            Some(value.encode_value()),
+        ));
+        *end += 1;
+    }
+
+    /// Replace an existing element of a `Vecx`,
+    /// including those pushed by this batch.
+    #[inline(always)]
+    pub fn update<T: ValueEnDe>(
+        &mut self,
+        hdr: &Vecx<T>,
+        idx: usize,
+        value: &T,
+    ) -> Result<()> {
+        let raw = hdr.as_raw();
+        let end = self
+            .vec_ends
+            .get(&raw.id())
+            .copied()
+            .unwrap_or_else(|| hdr.end());
+        if idx < hdr.head() || idx as u64 >= end {
+            return Err(eg!("out of index"));
+        }
+        self.ops.push(Op::Raw(
+            *raw,
+            (idx as u64).to_be_bytes().to_vec().into(),
+            Some(value.encode_value()),
+        ));
+        Ok(())
+    }
+
+    /// Insert a KV to the head version of the default branch.
+    #[inline(always)]
+    pub fn insert_vs<K, V, C>(
+        &mut self,
+        hdr: &MapxVs<K, V, C>,
+        key: &K,
+        value: &V,
+    ) -> Result<()>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        self.write_vs(hdr, key, Some(value), None).c(d!())
+    }
+
+    /// Insert a KV to the head version of a specified branch.
+    #[inline(always)]
+    pub fn insert_vs_by_branch<K, V, C>(
+        &mut self,
+        hdr: &MapxVs<K, V, C>,
+        key: &K,
+        value: &V,
+        branch_name: BranchName,
+    ) -> Result<()>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        self.write_vs(hdr, key, Some(value), Some(branch_name))
+            .c(d!())
+    }
+
+    /// Remove a KV from the head version of the default branch.
+    #[inline(always)]
+    pub fn remove_vs<K, V, C>(&mut self, hdr: &MapxVs<K, V, C>, key: &K) -> Result<()>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        self.write_vs(hdr, key, None, None).c(d!())
+    }
+
+    /// Remove a KV from the head version of a specified branch.
+    #[inline(always)]
+    pub fn remove_vs_by_branch<K, V, C>(
+        &mut self,
+        hdr: &MapxVs<K, V, C>,
+        key: &K,
+        branch_name: BranchName,
+    ) -> Result<()>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        self.write_vs(hdr, key, None, Some(branch_name)).c(d!())
+    }
+
+    fn write_vs<K, V, C>(
+        &mut self,
+        hdr: &MapxVs<K, V, C>,
+        key: &K,
+        value: Option<&V>,
+        branch_name: Option<BranchName>,
+    ) -> Result<()>
+    where
+        K: KeyEnDe,
+        V: ValueEnDe,
+        C: Codec,
+    {
+        let raw = hdr.as_raw();
+        let branch_id = raw.branch_id(branch_name).c(d!())?;
+        self.ops.push(Op::Vs(
+            raw.clone(),
+            branch_id,
+            encode_key_by::<C, K>(key),
+            value.map(encode_value_by::<C, V>),
+        ));
+        Ok(())
+    }
+
+    // The value of a key staged by this batch, `Some(None)` if it is removed.
+    pub(crate) fn staged(&self, hdr: &MapxRaw, key: &[u8]) -> Option<Option<RawValue>> {
+        self.ops.iter().rev().find_map(|op| match op {
+            Op::Raw(h, k, v) if h == hdr && &k[..] == key => Some(v.clone()),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn staged_vs(
+        &self,
+        hdr: &MapxRawVs,
+        branch_id: BranchID,
+        key: &[u8],
+    ) -> Option<Option<RawValue>> {
+        self.ops.iter().rev().find_map(|op| match op {
+            Op::Vs(h, br, k, v) if h == hdr && *br == branch_id && &k[..] == key => {
+                Some(v.clone())
+            }
+            _ => None,
+        })
+    }
+
+    /// Apply all writes as a whole, nothing is changed on errors.
+    ///
+    /// The batch is checked first, writes on append-only collections must not
+    /// change existing keys, and the versioned ones must be writable, e.g. no
+    /// frozen branches.
+    ///
+    /// NOTE: this is a redo log, not a native atomic batch of the engine,
+    /// the batch is flushed to the logs before it is applied, so a crash in
+    /// the middle leaves it partially applied until it is finished by
+    /// [vsdb_recover_write_batches](self::vsdb_recover_write_batches),
+    /// which is also called by the first `commit` after restarting.
+    pub fn commit(self) -> Result<()> {
+        alt!(self.ops.is_empty(), return Ok(()));
+
+        let parts = self.parts();
+        for (db, _) in parts.iter() {
+            recover_once(*db).c(d!())?;
+        }
+        self.check().c(d!())?;
+
+        let logged = parts
+            .into_iter()
+            .map(|(db, ops)| {
+                let log = persisted_log(db);
+                let id = db.alloc_version_id().to_be_bytes();
+                log.insert(&id, &ops.encode_value());
+                // the entry must be on disk before any of its writes
+                log.flush();
+                (log, id)
+            })
+            .collect::<Vec<_>>();
+        let ret = apply(&self.ops).c(d!());
+        for (log, id) in logged.iter() {
+            log.remove(id);
+        }
+        ret
+    }
+
+    // Operations grouped by the databases holding their targets,
+    // each group is logged in its own database, so it can be finished
+    // by recovering that database alone.
+    fn parts(&self) -> Vec<(database::Db, Vec<Op>)> {
+        let mut parts: Vec<(database::Db, Vec<Op>)> = vec![];
+        for op in self.ops.iter() {
+            let db = op.db();
+            match parts.iter_mut().find(|(d, _)| ptr::eq(*d, db)) {
+                Some((_, ops)) => ops.push(op.clone()),
+                None => parts.push((db, vec![op.clone()])),
+            }
+        }
+        parts
+    }
+
+    /// Apply all writes as a whole like `commit`, along with a new version
+    /// created by `version_name` on every branch written by this batch,
+    /// the writes on a branch go into its new version.
+    pub fn commit_as_version(mut self, version_name: VersionName) -> Result<()> {
+        let mut branches = vec![];
+        for op in self.ops.iter() {
+            if let Op::Vs(hdr, br, _, _) = op {
+                if !branches.contains(&(hdr, *br)) {
+                    branches.push((hdr, *br));
+                }
+            }
+        }
+
+        let mut ops = branches
+            .into_iter()
+            .map(|(hdr, br)| Op::Version(hdr.clone(), br, version_name.0.into()))
+            .collect::<Vec<_>>();
+        ops.append(&mut self.ops);
+        self.ops = ops;
+
+        self.commit().c(d!())
+    }
+
+    fn check(&self) -> Result<()> {
+        // keys written by this batch on append-only instances
+        let mut written = HashSet::new();
+        // branches getting new versions by this batch
+        let mut versioned = vec![];
+
+        for op in self.ops.iter() {
+            match op {
+                Op::Raw(hdr, k, _) if hdr.is_append_only() => {
+                    if !written.insert((hdr.id(), k)) || hdr.contains_key(k) {
+                        let e = AppendOnlyViolation { key: k.to_vec() };
+                        return Err(eg!(e.to_string()));
+                    }
+                }
+                Op::Raw(..) => {}
+                Op::Vs(hdr, br, _, _) => {
+                    if !versioned.contains(&(hdr, *br)) {
+                        hdr.check_writable_by_branch_id(*br).c(d!())?;
+                    }
+                }
+                Op::Version(hdr, br, name) => {
+                    hdr.version_check_creatable_by_branch_id(name, *br)
+                        .c(d!())?;
+                    versioned.push((hdr, *br));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The redo log of the database `db`.
+#[inline(always)]
+fn persisted_log(db: database::Db) -> MapxRaw {
+    MapxRaw::open_reserved_in(db, WRITE_BATCH_LOG)
+}
+
+/// Finish write batches interrupted by a crash,
+/// return how many batches have been finished.
+///
+/// It works on the current database, and should be called at startup,
+/// before any write, it is also called by the first `WriteBatch::commit`
+/// writing to each database automatically.
+///
+/// The writes of a batch are logged in the databases holding the targets
+/// of them, a batch writing to several databases is finished part by part
+/// as each of those databases is recovered.
+pub fn vsdb_recover_write_batches() -> Result<usize> {
+    recover(database::current()).c(d!())
+}
+
+fn recover(db: database::Db) -> Result<usize> {
+    let log = persisted_log(db);
+    let mut cnt = 0;
+    // IDs are increasing, so batches are finished in the order of commits
+    for (k, v) in log.iter() {
+        // the targets of the logged operations belong to `db`
+        let ops = database::scoped(db, || <Vec<Op>>::decode_value(&v)).c(d!())?;
+        apply(&ops).c(d!())?;
+        log.remove(&k);
+        cnt += 1;
+    }
+    if 0 < cnt {
+        db.flush();
+    }
+    Ok(cnt)
+}
+
+// Run `recover` on the database `db` if it has never been run.
+fn recover_once(db: database::Db) -> Result<()> {
+    static RECOVERED: Lazy<Mutex<HashSet<usize>>> =
+        Lazy::new(|| Mutex::new(HashSet::new()));
+
+    let mut recovered = RECOVERED.lock();
+    let id = db as *const _ as usize;
+    if !recovered.contains(&id) {
+        recover(db).c(d!())?;
+        recovered.insert(id);
+    }
+    Ok(())
+}
+
+// Write a batch to the logs without applying it,
+// as if the process died right after that.
+#[cfg(test)]
+pub(crate) fn persist_only(batch: &WriteBatch) {
+    for (db, ops) in batch.parts() {
+        let id = db.alloc_version_id().to_be_bytes();
+        persisted_log(db).insert(&id, &ops.encode_value());
+    }
+}
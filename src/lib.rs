@@ -120,6 +120,10 @@
 //! // }
 //! ```
 //!
+//! Fields wrapped in `Option`, `Box`, `Arc`, fixed-size arrays and tuples
+//! (up to 12 elements) are supported too, the versioned calls are forwarded
+//! to what they hold, `branch_set_default` fails on a shared `Arc`.
+//!
 //! **But** this one can NOT be handled correctly by `#[derive(Vs)]`:
 //!
 //! ```no_run
@@ -129,12 +133,210 @@
 //! // It can be compiled, but the result is wrong !
 //! // The versioned methods of the inner 'MapxVs<u8, u8>' will missing,
 //! // you should implement the 'VsMgmt' trait(or a part of it) manually.
+//! //
+//! // NOTE: `#[derive(Vs)]` can not detect it,
+//! // check the struct by `assert_vs_complete!` to reject it at compile time.
 //! #[derive(Vs)]
 //! struct BadCase {
 //!     a: VecxVs<MapxVs<u8, u8>>,
 //! }
 //! ```
 //!
+//! The elements of a versioned collection are stored as values,
+//! their own versions can not be reached through it, and the derive has no
+//! attribute(e.g. `#[vs(recursive)]`) to change that. If a dynamic number
+//! of collections is needed, hold them in a plain container and forward
+//! the versioned calls to every element by `impl_for_collections!`:
+//!
+//! ```
+//! use ruc::*;
+//! use std::collections::BTreeMap;
+//! use vsdb::{
+//!     impl_for_collections,
+//!     versioned::{BranchInfo, PrunePolicy, VersionMeta},
+//!     BranchName, MapxVs, ParentBranchName, VersionName, Vs, VsMgmt,
+//! };
+//!
+//! // NOTE: the number of the inner collections is not versioned
+//! struct Shards(Vec<MapxVs<u8, u8>>);
+//!
+//! impl Shards {
+//!     fn values(&self) -> std::slice::Iter<'_, MapxVs<u8, u8>> {
+//!         self.0.iter()
+//!     }
+//!
+//!     fn values_mut(&mut self) -> std::slice::IterMut<'_, MapxVs<u8, u8>> {
+//!         self.0.iter_mut()
+//!     }
+//! }
+//!
+//! impl VsMgmt for Shards {
+//!     impl_for_collections!(values, values_mut);
+//! }
+//!
+//! #[derive(Vs)]
+//! struct GoodNesting {
+//!     a: Shards,
+//! }
+//!
+//! let s = GoodNesting {
+//!     a: Shards(vec![MapxVs::new(), MapxVs::new()]),
+//! };
+//! pnk!(s.version_create(VersionName(b"v-0")));
+//! pnk!(s.a.0[1].insert(1, 1));
+//! pnk!(s.version_create(VersionName(b"v-1")));
+//! pnk!(s.a.0[1].insert(1, 2));
+//!
+//! // the versions of every shard are reached
+//! pnk!(s.version_pop());
+//! assert_eq!(Some(1), s.a.0[1].get(&1));
+//! ```
+//!
+//! **Renaming fields**
+//!
+//! `#[derive(Vs)]` comes from the `vsdb_derive` crate and takes no field
+//! attributes, there is no `#[vs(stable_id = "...")]`. The identity of a
+//! field's underlying instance is stored in the serialized struct, it is
+//! found by the field name with the `cbor_codec`, the `msgpack_codec` and
+//! the `json_codec`, and by the field order with the `bcs_codec`. To rename
+//! a field across releases without orphaning its on-disk data, keep the
+//! stored label stable by serde:
+//!
+//! ```
+//! use ruc::*;
+//! use serde::{Deserialize, Serialize};
+//! use vsdb::{BranchName, MapxVs, ParentBranchName, ValueEnDe, VersionName, Vs, VsMgmt};
+//!
+//! #[derive(Vs, Serialize, Deserialize)]
+//! struct StateV1 {
+//!     balances: MapxVs<u64, u128>,
+//! }
+//!
+//! #[derive(Vs, Serialize, Deserialize)]
+//! struct State {
+//!     // was `balances` in the previous release
+//!     #[serde(rename = "balances")]
+//!     accounts: MapxVs<u64, u128>,
+//! }
+//!
+//! let old = StateV1 { balances: MapxVs::new() };
+//! pnk!(old.version_create(VersionName(b"v-0")));
+//! pnk!(old.balances.insert(1, 100));
+//!
+//! let new = pnk!(<State as ValueEnDe>::decode(&ValueEnDe::encode(&old)));
+//! assert_eq!(Some(100), new.accounts.get(&1));
+//! ```
+//!
+//! With the `bcs_codec`, fields can not be reordered or removed, new fields
+//! should always be appended to the end.
+//!
+//! **Skipping fields**
+//!
+//! There is no `#[vs(skip)]` either, for the same reason. A field that is
+//! not versioned(e.g. a runtime cache) is wrapped in `Unversioned<T>`, all
+//! versioned calls on it do nothing. It derefs to `T`, and it is serialized
+//! as `T` itself, so wrapping an existing field keeps the stored data:
+//!
+//! ```
+//! use ruc::*;
+//! use serde::{Deserialize, Serialize};
+//! use std::collections::HashMap;
+//! use vsdb::{
+//!     BranchName, MapxVs, ParentBranchName, Unversioned, VersionName, Vs, VsMgmt,
+//! };
+//!
+//! #[derive(Vs, Serialize, Deserialize)]
+//! struct State {
+//!     balances: MapxVs<u64, u128>,
+//!     cache: Unversioned<HashMap<u64, u128>>,
+//! }
+//!
+//! let mut s = State {
+//!     balances: MapxVs::new(),
+//!     cache: Unversioned(HashMap::new()),
+//! };
+//! pnk!(s.version_create(VersionName(b"v-0")));
+//! pnk!(s.balances.insert(1, 100));
+//! s.cache.insert(1, 100);
+//!
+//! pnk!(s.version_pop());
+//! assert!(s.balances.get(&1).is_none());
+//! assert_eq!(Some(&100), s.cache.get(&1));
+//! ```
+//!
+//! **Evolving values**
+//!
+//! Values wrapped in `Enveloped<T>` are stored along with the schema version
+//! of `T`, values written by older versions are upgraded when they are read:
+//!
+//! ```no_run
+//! use ruc::*;
+//! use serde::{Deserialize, Serialize};
+//! use vsdb::{vsdb_register_schema_upgrade, Enveloped, Mapx, Schema, ValueEnDe};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct AccountV1 {
+//!     balance: u64,
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Account {
+//!     balance: u64,
+//!     nonce: u64,
+//! }
+//!
+//! impl Schema for Account {
+//!     const SCHEMA_VERSION: u8 = 2;
+//! }
+//!
+//! pnk!(vsdb_register_schema_upgrade(1, |bytes| {
+//!     let old = <AccountV1 as ValueEnDe>::decode(bytes).c(d!())?;
+//!     Ok(Account { balance: old.balance, nonce: 0 })
+//! }));
+//!
+//! let accounts: Mapx<u64, Enveloped<Account>> = Mapx::new();
+//! ```
+//!
+//! **Generic structs**
+//!
+//! The bounds of the impls generated by `#[derive(Vs)]` can not be adjusted,
+//! there is no `#[vs(bound = "...")]`. If they do not fit, implement `VsMgmt`
+//! manually with the bounds you need. For a wrapper of one collection,
+//! `impl_vs_methods!` forwards all methods to its `inner` field, or to the
+//! field path passed to it (e.g. `impl_vs_methods!(state.balances)`):
+//!
+//! ```
+//! use ruc::*;
+//! use vsdb::{
+//!     impl_vs_methods,
+//!     versioned::{BranchInfo, PrunePolicy, VersionMeta},
+//!     BranchName, KeyEnDe, MapxVs, ParentBranchName, ValueEnDe, VersionName, VsMgmt,
+//! };
+//! use std::marker::PhantomData;
+//!
+//! struct Index<K, V, Tag> {
+//!     inner: MapxVs<K, V>,
+//!     _tag: PhantomData<Tag>,
+//! }
+//!
+//! // no bounds on the phantom parameter
+//! impl<K: KeyEnDe, V: ValueEnDe, Tag> VsMgmt for Index<K, V, Tag> {
+//!     impl_vs_methods!();
+//! }
+//!
+//! // neither serializable nor versioned
+//! struct ByOwner;
+//!
+//! let idx = Index::<u64, u64, ByOwner> {
+//!     inner: MapxVs::new(),
+//!     _tag: PhantomData,
+//! };
+//! pnk!(idx.version_create(VersionName(b"v-0")));
+//! pnk!(idx.inner.insert(1, 2));
+//! assert!(idx.version_exists(VersionName(b"v-0")));
+//! assert_eq!(Some(2), idx.inner.get(&1));
+//! ```
+//!
 //! Some complete examples:
 //! - [**Versioned examples**](versioned/index.html)
 //! - [**Unversioned examples**](basic/index.html)
@@ -147,11 +349,87 @@
 //! - `rocks_engine`, use rocksdb as the backend database
 //!     - Faster running speed
 //!     - Can not be compiled into a statically linked binary
+//!     - Tunable by `vsdb_set_engine_opts` before opening
+//! - `lmdb_engine`, use lmdb as the backend database
+//!     - Memory-mapped reads, suitable for read-heavy workloads
+//!     - Takes precedence over `sled_engine` and `rocks_engine` when it is enabled
+//! - `paritydb_engine`, use parity-db as the backend database
+//!     - Ordered columns, proven in blockchain nodes
+//!     - Takes precedence over `sled_engine` and `rocks_engine` when it is enabled
+//! - `sqlite_engine`, use sqlite as the backend database
+//!     - All data in a single file, can be inspected by standard SQL tools
+//!     - Each area is a table keyed by `(prefix, key)`
+//!     - Takes precedence over `sled_engine` and `rocks_engine` when it is enabled
+//! - `mmap_engine`, use append-only memory-mapped logs as the backend database
+//!     - No LSM-tree or B-tree overhead, suitable for log-append workloads, such as `Vecx`
+//!     - Keys are indexed in memory, logs are rewritten when most of the data is dead
+//!     - Takes precedence over `sled_engine` and `rocks_engine` when it is enabled
+//! - `mem_engine`, keep all data in memory, nothing is persisted
+//!     - Mainly used in tests and CI, no files are touched by the engine
+//!     - Takes precedence over other engines when it is enabled
+//! - `remote_engine`, share the engine of one node between processes over TCP
+//!     - The node calls `vsdb_serve`, clients call `vsdb_connect_remote` before opening
+//!     - Works along with another engine, which is used by the serving node
+//! - `encryption`, encrypt data at rest, the secret is set by `vsdb_set_encryption`
+//!     - Values, and optionally keys, are encrypted by XChaCha20-Poly1305
+//!     - Secrets can be rotated, old data is re-encrypted when it is read
+//!     - Works along with any engine, including custom ones
+//! - `compression`, compress values of the instances created by `new_compressed`
+//!     - The algorithm is chosen per instance, e.g. `Compression::Zstd { level: 3 }`
+//!     - Other instances are left as they are
+//!     - Or wrap the codec of a collection, e.g. `MapxVs<K, V, Compressed<C>>`
+//! - `migration`, move data between sled and rocksdb by `vsdb_migrate`
+//!     - All instances, branches and versions are kept
+//!     - Enables both `sled_engine` and `rocks_engine`, sled is used by VSDB itself
 //! - \[**default**] `cbor_codec`, use cbor as the codec
 //!     - Faster running speed
 //! - `bcs_codec`, use bcs as the codec
 //!     - Created by the 'Libre' project of Facebook
 //!     - Security reinforcement for blockchain scenarios
+//! - `msgpack_codec`, use MessagePack as the codec
+//!     - Interoperable with existing MessagePack-based services
+//!     - Structs are encoded as maps, fields are found by their names
+//! - `json_codec`, use JSON as the codec
+//!     - Slower, but stored values can be inspected and patched by generic tools
+//!     - Keys of maps in values should be strings or integers
+//! - `borsh_codec`, store borsh values by wrapping them in `Borsh<T>`
+//!     - The payload is exactly what `BorshSerialize` produces, for Solana/NEAR users
+//!     - Any `BorshSerialize + BorshDeserialize` type gets `ValueEnDe`
+//!     - Works along with any of the codecs above
+//! - `prost_codec`, store protobuf messages as values by wrapping them in `Proto<M>`
+//!     - Any `prost::Message` gets `ValueEnDe`, no serde derives are needed
+//!     - Works along with any of the codecs above
+//! - `rkyv_codec`, store values as rkyv archives by wrapping them in `Rkyv<T>`
+//!     - Values are read as zero-copy views, without deserialization
+//!     - Suitable for large read-mostly values, works along with any codec
+//!
+//! Several codecs can be enabled at the same time, the first enabled one of the
+//! list above is the `DefaultCodec`, others can be selected per collection,
+//! e.g. `Mapx::<u64, Account, BcsCodec>::with_codec()` along with a `Mapx<u64, Log>`.
+//! `Mapx`, `MapxOrdRawKey`, `MapxVs` and `MapxOrdRawKeyVs` accept a codec parameter,
+//! other collections always use the `DefaultCodec`.
+//!
+//! Other backends can be plugged in by implementing `Engine` and calling
+//! `vsdb_register_engine` before VSDB is initialized, check `examples/custom_engine.rs`.
+//!
+//! A database can be split into shards under several directories(usually on
+//! different disks) by `vsdb_set_shard_dirs`, flushing and compactions of the
+//! shards run in parallel.
+//!
+//! The number of areas(trees of sled, column families of rocksdb, etc.) and how
+//! instances are assigned to them can be set by `vsdb_set_area_opts`, hot
+//! instances can be pinned to dedicated areas by `new_pinned`.
+//!
+//! Entries of the instances created by `new_with_ttl` can expire, they are
+//! written by `insert_with_ttl`, invisible to reads once expired, and removed
+//! physically when the database is flushed.
+//!
+//! Writes on several collections can be applied as a whole by a `WriteBatch`,
+//! it is backed by a redo log instead of native batches of engines,
+//! a batch interrupted by a crash is finished by `vsdb_recover_write_batches`.
+//!
+//! Transactions started by `vsdb_begin_tx` stage changes in memory until
+//! `commit`, or discard them by `rollback`.
 //!
 //! ## Low-level design
 //!
@@ -181,13 +459,84 @@ pub use versioned::mapx_ord::MapxOrdVs;
 pub use versioned::orphan::OrphanVs;
 pub use versioned::vecx::VecxVs;
 
-pub use versioned::VsMgmt;
+pub use versioned::{
+    clock::{vsdb_set_version_clock, LogicalClock, SystemClock, VersionClock},
+    mapx_raw::vsdb_recover,
+    Unversioned, VsComplete, VsMgmt, VsNested,
+};
 pub use vsdb_derive::Vs;
 
-pub use merkle::MerkleTree;
+pub use merkle::{versioned::MerkleTreeVs, MerkleTree};
 
 pub use common::{
-    ende::{KeyDe, KeyEn, KeyEnDe, KeyEnDeOrdered, ValueDe, ValueEn, ValueEnDe},
-    vsdb_flush, vsdb_get_base_dir, vsdb_get_custom_dir, vsdb_set_base_dir, BranchName,
-    ParentBranchName, VersionName, INITIAL_VERSION,
+    append_only::AppendOnlyViolation,
+    areas::{vsdb_get_area_opts, vsdb_set_area_opts, AreaOpts, AreaStrategy},
+    budget::{
+        vsdb_get_iter_memory_budget, vsdb_get_iter_memory_usage,
+        vsdb_set_iter_memory_budget, BudgetedIter, IterBudgetExceeded, IterChunk,
+    },
+    compression::Compression,
+    config::{
+        vsdb_get_config, vsdb_reconfigure, vsdb_set_slow_op_hook, PartialConfig, SlowOp,
+        SlowOpHook, VsdbConfig,
+    },
+    cursor::Cursor,
+    database::Vsdb,
+    durability::{vsdb_get_durability, vsdb_set_durability, Durability},
+    ende::{
+        vsdb_get_codec_failure_policy, vsdb_register_schema_upgrade,
+        vsdb_set_codec_failure_policy, vsdb_set_codec_hook, Codec, CodecEvent,
+        CodecFailurePolicy, CodecHook, CodecOp, CodecTarget, DefaultCodec, Enveloped,
+        KeyDe, KeyEn, KeyEnDe, KeyEnDeOrdered, OrdF32, OrdF64, OrdI128, OrdI16, OrdI32,
+        OrdI64, OrdI8, Schema, ValueDe, ValueEn, ValueEnDe,
+    },
+    engine_opts::{
+        vsdb_get_engine_opts, vsdb_set_engine_opts, BlockCompression, CompactionStyle,
+        EngineOpts,
+    },
+    engines::{vsdb_register_engine, vsdb_set_shard_dirs, Engine, EngineIter},
+    flush::{vsdb_flush_async, FlushHandle},
+    mirror::{vsdb_set_flush_hook, FileChange, FlushHook},
+    namespace::vsdb_with_prefix_namespace,
+    raw_area::{raw_area, RawArea},
+    replica::{vsdb_is_replica, vsdb_open_as_replica, vsdb_refresh},
+    stats::{AreaStorageStats, SizeHistogram, StorageStats, SIZE_BUCKET_NUM},
+    tx::{vsdb_begin_tx, Tx},
+    vsdb_flush, vsdb_get_base_dir, vsdb_get_branch_ancestors_limit, vsdb_get_custom_dir,
+    vsdb_set_base_dir, vsdb_set_branch_ancestors_limit, vsdb_storage_stats,
+    write_batch::{vsdb_recover_write_batches, WriteBatch},
+    BranchName, ParentBranchName, SavepointName, VersionName, INITIAL_VERSION,
 };
+
+#[cfg(feature = "remote_engine")]
+pub use common::remote::{vsdb_connect_remote, vsdb_serve};
+
+#[cfg(feature = "encryption")]
+pub use common::engines::{vsdb_set_encryption, EncryptionKey, EncryptionOpts};
+
+#[cfg(feature = "migration")]
+pub use common::engines::{vsdb_migrate, EngineKind};
+
+#[cfg(feature = "compression")]
+pub use common::ende::Compressed;
+
+#[cfg(feature = "cbor_codec")]
+pub use common::ende::CborCodec;
+
+#[cfg(feature = "bcs_codec")]
+pub use common::ende::BcsCodec;
+
+#[cfg(feature = "msgpack_codec")]
+pub use common::ende::MsgpackCodec;
+
+#[cfg(feature = "json_codec")]
+pub use common::ende::JsonCodec;
+
+#[cfg(feature = "borsh_codec")]
+pub use common::borshed::Borsh;
+
+#[cfg(feature = "prost_codec")]
+pub use common::proto::Proto;
+
+#[cfg(feature = "rkyv_codec")]
+pub use common::archived::Rkyv;
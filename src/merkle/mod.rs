@@ -1,13 +1,24 @@
 //!
 //! A simple 'Merkle-Tree' ported from solana project.
 //!
+//! Proofs(`Proof` and `SparseMerkleProof`) are serialized in a compact
+//! binary format, which starts with a format version and the kind of
+//! the proof, it is the same under all codecs, so a proof generated by one
+//! node can be verified on another one with different features.
+//!
+
+pub mod mpt;
+pub mod sparse;
+pub mod versioned;
 
 use crate::{
     basic::{mapx_ord_rawkey::MapxOrdRawKey, vecx_raw::VecxRaw},
-    common::RawBytes,
+    common::{ende::SimpleVisitor, RawBytes},
 };
-use serde::{Deserialize, Serialize};
+use ruc::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sha3::{Digest, Sha3_256};
+use std::{collections::BTreeSet, result::Result as StdResult};
 
 type Hash = RawBytes;
 
@@ -25,6 +36,39 @@ fn hashv(data: &[&[u8]]) -> Hash {
 const LEAF_PREFIX: &[u8] = &[0];
 const INTERMEDIATE_PREFIX: &[u8] = &[1];
 
+// The version of the compact format of proofs,
+// it must be bumped on any incompatible changes.
+const PROOF_FORMAT_VERSION: u8 = 1;
+
+// The kinds of proofs.
+const PROOF_KIND_TREE: u8 = 0;
+const PROOF_KIND_SPARSE: u8 = 1;
+
+// The size of hashes in proofs.
+const HASH_LEN: usize = 32;
+
+// Check the header of an encoded proof, return the remaining bytes.
+fn proof_body(bytes: &[u8], kind: u8) -> Result<&[u8]> {
+    match bytes {
+        [PROOF_FORMAT_VERSION, k, body @ ..] if *k == kind => Ok(body),
+        [PROOF_FORMAT_VERSION, k, ..] => {
+            Err(eg!(format!("unexpected kind of proof: {}", k)))
+        }
+        [v, _, ..] => Err(eg!(format!("unsupported proof format: {}", v))),
+        _ => Err(eg!("invalid proof")),
+    }
+}
+
+// Take `n` bytes from the front of `bytes`.
+fn proof_take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if bytes.len() < n {
+        return Err(eg!("invalid proof: unexpected end of bytes"));
+    }
+    let (ret, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(ret)
+}
+
 macro_rules! hash_leaf {
     {$d:ident} => {
         hashv(&[LEAF_PREFIX, $d])
@@ -58,10 +102,11 @@ impl<'a> ProofEntry<'a> {
     }
 }
 
+/// The path from a leaf to the root, borrowed from a tree.
 #[derive(Debug, Default, PartialEq)]
-pub struct Proof<'a>(Vec<ProofEntry<'a>>);
+pub struct ProofPath<'a>(Vec<ProofEntry<'a>>);
 
-impl<'a> Proof<'a> {
+impl<'a> ProofPath<'a> {
     pub fn push(&mut self, entry: ProofEntry<'a>) {
         self.0.push(entry)
     }
@@ -77,12 +122,134 @@ impl<'a> Proof<'a> {
             let rsib = pe.2.unwrap_or(&target_hash);
             let hash = hash_intermediate!(lsib, rsib);
 
-            if &hash[..] == pe.0 { Some(hash) } else { None }
+            if &hash[..] == pe.0 {
+                Some(hash)
+            } else {
+                None
+            }
         });
         matches!(result, Some(_))
     }
 }
 
+/// A standalone inclusion proof, it can be verified without the tree,
+/// e.g. by light clients against a published root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+    leaf_index: u64,
+    leaf_count: u64,
+    // hashes of the siblings, from the leaf level to the root
+    siblings: Vec<Hash>,
+}
+
+impl Proof {
+    #[inline(always)]
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index as usize
+    }
+
+    #[inline(always)]
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count as usize
+    }
+
+    /// Check that `leaf` is at `leaf_index()` of the tree whose root is `root`.
+    #[inline(always)]
+    pub fn verify(&self, root: &[u8], leaf: &[u8]) -> bool {
+        self.verify_by_hash(root, hash_leaf!(leaf))
+    }
+
+    pub fn verify_by_hash(&self, root: &[u8], leaf_hash: Hash) -> bool {
+        if self.leaf_index >= self.leaf_count {
+            return false;
+        }
+
+        let mut level_len = self.leaf_count as usize;
+        let mut node_index = self.leaf_index as usize;
+        let mut siblings = self.siblings.iter();
+        let mut hash = leaf_hash;
+
+        while level_len > 1 {
+            let sib = if let Some(sib) = siblings.next() {
+                sib
+            } else {
+                return false;
+            };
+
+            let cur = &hash;
+            hash = if node_index % 2 == 1 {
+                hash_intermediate!(sib, cur)
+            } else {
+                // the last node of an odd level is paired with itself
+                if node_index + 1 == level_len && sib != cur {
+                    return false;
+                }
+                hash_intermediate!(cur, sib)
+            };
+
+            node_index /= 2;
+            level_len = MerkleTree::next_level_len(level_len);
+        }
+
+        siblings.next().is_none() && &hash[..] == root
+    }
+
+    /// The compact encoding of the proof:
+    /// `[ format version ] + [ kind ] + [ leaf index ] + [ leaf count ] + [ siblings ]`,
+    /// integers are `u64` in big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(18 + HASH_LEN * self.siblings.len());
+        ret.extend_from_slice(&[PROOF_FORMAT_VERSION, PROOF_KIND_TREE]);
+        ret.extend_from_slice(&self.leaf_index.to_be_bytes());
+        ret.extend_from_slice(&self.leaf_count.to_be_bytes());
+        self.siblings.iter().for_each(|h| ret.extend_from_slice(h));
+        ret
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut body = proof_body(bytes, PROOF_KIND_TREE).c(d!())?;
+        let mut int = [0; 8];
+        int.copy_from_slice(proof_take(&mut body, 8).c(d!())?);
+        let leaf_index = u64::from_be_bytes(int);
+        int.copy_from_slice(proof_take(&mut body, 8).c(d!())?);
+        let leaf_count = u64::from_be_bytes(int);
+
+        if 0 != body.len() % HASH_LEN {
+            return Err(eg!("invalid proof: broken siblings"));
+        }
+        let siblings = body
+            .chunks(HASH_LEN)
+            .map(|h| h.to_vec().into_boxed_slice())
+            .collect();
+
+        Ok(Self {
+            leaf_index,
+            leaf_count,
+            siblings,
+        })
+    }
+}
+
+impl Serialize for Proof {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for Proof {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_bytes(SimpleVisitor)
+            .and_then(|bytes| Proof::from_bytes(&bytes).map_err(de::Error::custom))
+    }
+}
+
 impl MerkleTree {
     #[inline(always)]
     fn next_level_len(level_len: usize) -> usize {
@@ -163,26 +330,76 @@ impl MerkleTree {
         self.nodes.iter().last()
     }
 
+    /// Change many leaves at once, items are `(leaf index, new leaf)`.
+    ///
+    /// Every changed internal node is recomputed only once,
+    /// no matter how many changed leaves are under it.
+    ///
+    /// NOTE: nothing is changed if any index is out of range.
+    pub fn batch_update<'a>(
+        &mut self,
+        updates: impl IntoIterator<Item = (usize, &'a [u8])>,
+    ) -> Result<()> {
+        let updates = updates.into_iter().collect::<Vec<_>>();
+        if let Some((idx, _)) = updates.iter().find(|(idx, _)| *idx >= self.leaf_count) {
+            return Err(eg!(format!("leaf index out of range: {}", idx)));
+        }
+
+        let mut dirty = BTreeSet::new();
+        for (idx, leaf) in updates {
+            let hash = hash_leaf!(leaf);
+            let old = std::mem::replace(&mut self.nodes[idx], hash);
+            if Some(idx as u64) == self.hash_to_idx.get(&old) {
+                self.hash_to_idx.remove(&old);
+            }
+            self.hash_to_idx.insert_ref(&self.nodes[idx], &(idx as u64));
+            dirty.insert(idx);
+        }
+
+        let mut level_len = self.leaf_count;
+        let mut level_start = 0;
+        while level_len > 1 {
+            let next_level_start = level_start + level_len;
+            dirty = dirty.into_iter().map(|idx| idx / 2).collect();
+            for &idx in dirty.iter() {
+                let prev_level_idx = level_start + 2 * idx;
+                let lsib = &self.nodes[prev_level_idx];
+                let rsib = if 2 * idx + 1 < level_len {
+                    &self.nodes[prev_level_idx + 1]
+                } else {
+                    // Duplicate last entry if the level length is odd
+                    lsib
+                };
+                let hash = hash_intermediate!(lsib, rsib);
+                self.nodes[next_level_start + idx] = hash;
+            }
+            level_start = next_level_start;
+            level_len = MerkleTree::next_level_len(level_len);
+        }
+
+        Ok(())
+    }
+
     #[inline(always)]
-    pub fn get_proof_path(&self, target: &[u8]) -> Option<Proof> {
+    pub fn get_proof_path(&self, target: &[u8]) -> Option<ProofPath> {
         let hash = hash_leaf!(target);
         self.get_proof_path_by_hash(hash)
     }
 
     #[inline(always)]
-    pub fn get_proof_path_by_hash(&self, target_hash: Hash) -> Option<Proof> {
+    pub fn get_proof_path_by_hash(&self, target_hash: Hash) -> Option<ProofPath> {
         let idx = self.hash_to_idx.get(&target_hash)? as usize;
         self.get_proof_path_by_index(idx)
     }
 
-    pub fn get_proof_path_by_index(&self, index: usize) -> Option<Proof> {
+    pub fn get_proof_path_by_index(&self, index: usize) -> Option<ProofPath> {
         if index >= self.leaf_count {
             return None;
         }
 
         let mut level_len = self.leaf_count;
         let mut level_start = 0;
-        let mut path = Proof::default();
+        let mut path = ProofPath::default();
         let mut node_index = index;
         let mut lsib = None;
         let mut rsib = None;
@@ -211,6 +428,38 @@ impl MerkleTree {
         }
         Some(path)
     }
+
+    /// Generate a standalone proof of the leaf at `leaf_index`.
+    pub fn gen_proof(&self, leaf_index: usize) -> Option<Proof> {
+        if leaf_index >= self.leaf_count {
+            return None;
+        }
+
+        let mut level_len = self.leaf_count;
+        let mut level_start = 0;
+        let mut node_index = leaf_index;
+        let mut siblings = vec![];
+        while level_len > 1 {
+            let sib = if node_index % 2 == 1 {
+                node_index - 1
+            } else if node_index + 1 < level_len {
+                node_index + 1
+            } else {
+                node_index
+            };
+            siblings.push(self.nodes[level_start + sib].clone());
+
+            node_index /= 2;
+            level_start += level_len;
+            level_len = MerkleTree::next_level_len(level_len);
+        }
+
+        Some(Proof {
+            leaf_index: leaf_index as u64,
+            leaf_count: self.leaf_count as u64,
+            siblings,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -341,6 +590,90 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_standalone_proof() {
+        use crate::ValueEnDe;
+
+        for n in 1..=TEST.len() {
+            let mt = MerkleTree::new(&TEST[..n]);
+            let root = mt.get_root().unwrap().clone();
+
+            for (i, s) in TEST[..n].iter().enumerate() {
+                let proof = mt.gen_proof(i).unwrap();
+                assert_eq!(i, proof.leaf_index());
+
+                // proofs can be sent to anywhere
+                let proof = <Proof as ValueEnDe>::decode(&proof.encode()).unwrap();
+                assert!(proof.verify(&root, s));
+
+                assert!(!proof.verify(&root, BAD[0]));
+                assert!(!proof.verify(&hash_leaf!(s), s));
+            }
+
+            assert!(mt.gen_proof(n).is_none());
+        }
+
+        // a valid path at another index is rejected
+        let mt = MerkleTree::new(TEST);
+        let root = mt.get_root().unwrap();
+        let mut proof = mt.gen_proof(2).unwrap();
+        proof.leaf_index = 3;
+        assert!(!proof.verify(root, TEST[2]));
+        proof.leaf_index = TEST.len() as u64;
+        assert!(!proof.verify(root, TEST[2]));
+
+        let mut proof = mt.gen_proof(2).unwrap();
+        proof.siblings.pop();
+        assert!(!proof.verify(root, TEST[2]));
+    }
+
+    #[test]
+    fn test_batch_update() {
+        let mut items = TEST.to_vec();
+        let mut mt = MerkleTree::new(&items);
+
+        for updates in [
+            vec![(0, BAD[0])],
+            vec![(TEST.len() - 1, BAD[1]), (1, BAD[2]), (2, BAD[2])],
+            // the last one wins
+            vec![(5, BAD[0]), (6, BAD[1]), (5, BAD[2])],
+            TEST.iter().copied().enumerate().collect(),
+        ] {
+            for &(idx, leaf) in updates.iter() {
+                items[idx] = leaf;
+            }
+            mt.batch_update(updates).unwrap();
+
+            let expected = MerkleTree::new(&items);
+            assert_eq!(expected.get_root(), mt.get_root());
+            for (idx, leaf) in items.iter().enumerate() {
+                assert!(mt
+                    .gen_proof(idx)
+                    .unwrap()
+                    .verify(mt.get_root().unwrap(), leaf));
+            }
+        }
+
+        // leaves are indexed by their new hashes
+        for leaf in TEST {
+            assert!(mt.get_proof_path(leaf).unwrap().verify(leaf));
+        }
+        for leaf in BAD {
+            assert!(mt.get_proof_path(leaf).is_none());
+        }
+
+        // nothing is changed by a bad batch
+        let root = mt.get_root().cloned();
+        assert!(mt
+            .batch_update([(0, BAD[0]), (TEST.len(), BAD[0])])
+            .is_err());
+        assert_eq!(root.as_ref(), mt.get_root());
+
+        let mut mt = MerkleTree::new(&[]);
+        assert!(mt.batch_update([]).is_ok());
+        assert!(mt.batch_update([(0, BAD[0])]).is_err());
+    }
+
     #[test]
     fn test_proof_entry_instantiation_lsib_set() {
         ProofEntry::new(&Hash::default(), Some(&Hash::default()), None);
@@ -385,4 +718,216 @@ mod tests {
             Some(&Hash::default()),
         );
     }
+
+    #[test]
+    fn test_mpt_geth_roots() {
+        use mpt::{MerklePatriciaTrie, EMPTY_TRIE_ROOT};
+
+        let hex =
+            |h: [u8; 32]| h.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+        // from the trie tests of the ethereum project
+        let cases: &[(&[(&str, &str)], &str)] = &[
+            (
+                &[
+                    ("doe", "reindeer"),
+                    ("dog", "puppy"),
+                    ("dogglesworth", "cat"),
+                ],
+                "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3",
+            ),
+            (
+                &[
+                    ("do", "verb"),
+                    ("horse", "stallion"),
+                    ("doge", "coin"),
+                    ("dog", "puppy"),
+                ],
+                "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84",
+            ),
+            (
+                &[("foo", "bar"), ("food", "bass")],
+                "17beaa1648bafa633cda809c90c04af50fc8aed3cb40d16efbddee6fdf63c4c3",
+            ),
+            (
+                &[("be", "e"), ("dog", "puppy"), ("bed", "d")],
+                "3f67c7a47520f79faa29255d2d3c084a7a6df0453116ed7232ff10277a8be68b",
+            ),
+        ];
+
+        for (kvs, root) in cases {
+            let mut t = MerklePatriciaTrie::new();
+            assert_eq!(EMPTY_TRIE_ROOT, t.root());
+
+            kvs.iter().rev().for_each(|(k, v)| {
+                t.insert(k.as_bytes(), v.as_bytes()).unwrap();
+            });
+            assert_eq!(*root, hex(t.root()));
+            kvs.iter().for_each(|(k, v)| {
+                assert_eq!(Some(v.as_bytes().to_vec()), t.get(k.as_bytes()).unwrap());
+            });
+
+            let old = t;
+            t.insert(b"d", b"x").unwrap();
+            t.insert(b"dogs", b"x").unwrap();
+            assert_ne!(old.root(), t.root());
+            t.insert(b"dogs", b"").unwrap();
+            t.remove(b"d").unwrap();
+            assert_eq!(old.root(), t.root());
+
+            kvs.iter().for_each(|(k, _)| {
+                t.remove(k.as_bytes()).unwrap();
+            });
+            assert_eq!(EMPTY_TRIE_ROOT, t.root());
+            assert!(t.get(kvs[0].0.as_bytes()).unwrap().is_none());
+
+            // historical roots are still readable
+            let old = t.at_root(&old.root()).unwrap();
+            assert_eq!(*root, hex(old.root()));
+            assert!(old.get(kvs[0].0.as_bytes()).unwrap().is_some());
+        }
+
+        assert!(MerklePatriciaTrie::new().at_root(&[1; 32]).is_err());
+    }
+
+    #[test]
+    fn test_sparse_proofs() {
+        use sparse::{SparseMerkleStore, EMPTY_ROOT};
+
+        let store = SparseMerkleStore::new();
+        let p = store.prove(&EMPTY_ROOT, b"my");
+        assert!(p.verify_exclusion(&EMPTY_ROOT, b"my"));
+        assert!(!p.verify_inclusion(&EMPTY_ROOT, b"my", b"my"));
+
+        let root = TEST
+            .iter()
+            .fold(EMPTY_ROOT, |root, k| store.insert(&root, k, k));
+
+        for k in TEST {
+            let p = store.prove(&root, k);
+            assert!(p.is_inclusion(k));
+            assert!(p.verify_inclusion(&root, k, k));
+            assert!(!p.verify_inclusion(&root, k, b"bad"));
+            assert!(!p.verify_exclusion(&root, k));
+            assert!(!p.verify_inclusion(&EMPTY_ROOT, k, k));
+        }
+
+        for k in BAD {
+            let p = store.prove(&root, k);
+            assert!(!p.is_inclusion(k));
+            assert!(p.verify_exclusion(&root, k));
+            assert!(!p.verify_inclusion(&root, k, k));
+
+            // a proof of one key says nothing about others
+            assert!(!p.verify_exclusion(&root, TEST[0]));
+        }
+
+        // proofs against an old root still work after updates
+        let new_root = store.remove(&root, TEST[0]);
+        assert!(store
+            .prove(&new_root, TEST[0])
+            .verify_exclusion(&new_root, TEST[0]));
+        assert!(store
+            .prove(&root, TEST[0])
+            .verify_inclusion(&root, TEST[0], TEST[0]));
+    }
+
+    #[test]
+    fn test_compact_proofs() {
+        use crate::ValueEnDe;
+        use sparse::{SparseMerkleProof, SparseMerkleStore, EMPTY_ROOT};
+
+        let mt = MerkleTree::new(TEST);
+        let proof = mt.gen_proof(3).unwrap();
+        let bytes = proof.to_bytes();
+        assert_eq!(&[PROOF_FORMAT_VERSION, PROOF_KIND_TREE], &bytes[..2]);
+        assert_eq!(18 + HASH_LEN * proof.siblings.len(), bytes.len());
+        assert_eq!(proof, Proof::from_bytes(&bytes).unwrap());
+
+        // serialized as the compact bytes under any codec
+        let decoded = <Proof as ValueEnDe>::decode(&proof.encode()).unwrap();
+        assert_eq!(proof, decoded);
+
+        let store = SparseMerkleStore::new();
+        let root = TEST
+            .iter()
+            .fold(EMPTY_ROOT, |root, k| store.insert(&root, k, k));
+        for k in TEST.iter().chain(BAD.iter()) {
+            let proof = store.prove(&root, k);
+            let bytes = proof.to_bytes();
+            assert_eq!(&[PROOF_FORMAT_VERSION, PROOF_KIND_SPARSE], &bytes[..2]);
+            let decoded = SparseMerkleProof::from_bytes(&bytes).unwrap();
+            assert_eq!(proof, decoded);
+            let decoded = <SparseMerkleProof as ValueEnDe>::decode(&proof.encode());
+            assert_eq!(proof, decoded.unwrap());
+        }
+
+        // broken bytes are rejected
+        let bytes = store.prove(&root, TEST[0]).to_bytes();
+        assert!(SparseMerkleProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(SparseMerkleProof::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+        assert!(Proof::from_bytes(&bytes).is_err());
+
+        let mut bytes = mt.gen_proof(3).unwrap().to_bytes();
+        assert!(Proof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(SparseMerkleProof::from_bytes(&bytes).is_err());
+        bytes[0] = 1 + PROOF_FORMAT_VERSION;
+        assert!(Proof::from_bytes(&bytes).is_err());
+        assert!(Proof::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_versioned_tree() {
+        use crate::{common::INITIAL_BRANCH_NAME, BranchName, VersionName, VsMgmt};
+        use versioned::MerkleTreeVs;
+
+        let main = BranchName(INITIAL_BRANCH_NAME);
+        let mt = MerkleTreeVs::new();
+        assert!(mt.get_root().is_none());
+        assert!(mt.is_empty());
+
+        for (i, item) in TEST.iter().enumerate() {
+            mt.version_create(VersionName(&[i as u8])).unwrap();
+            mt.push(item).unwrap();
+
+            let expected = MerkleTree::new(&TEST[..=i]);
+            assert_eq!(expected.get_root(), mt.get_root().as_ref());
+            for (j, item) in TEST.iter().enumerate().take(1 + i) {
+                let proof = mt.gen_proof(j).unwrap();
+                assert!(proof.verify(expected.get_root().unwrap(), item));
+            }
+            assert!(mt.gen_proof(1 + i).is_none());
+        }
+        let root = mt.get_root().unwrap();
+
+        // forked branches share the nodes, and change them separately
+        mt.branch_create(BranchName(b"fork")).unwrap();
+        mt.version_create_by_branch(VersionName(b"fork"), BranchName(b"fork"))
+            .unwrap();
+        mt.push_by_branch(BAD[0], BranchName(b"fork")).unwrap();
+        assert_eq!(1 + TEST.len(), mt.len_by_branch(BranchName(b"fork")));
+        assert_eq!(TEST.len(), mt.len());
+        assert_eq!(root, mt.get_root().unwrap());
+        let mut items = TEST.to_vec();
+        items.push(BAD[0]);
+        assert_eq!(
+            MerkleTree::new(&items).get_root(),
+            mt.get_root_by_branch(BranchName(b"fork")).as_ref()
+        );
+
+        // roots of historical versions
+        for (i, item) in TEST.iter().enumerate() {
+            let ver = [i as u8];
+            let ver = VersionName(&ver);
+            assert_eq!(
+                MerkleTree::new(&TEST[..=i]).get_root(),
+                mt.get_root_by_branch_version(main, ver).as_ref()
+            );
+            assert_eq!(1 + i, mt.len_by_branch_version(main, ver));
+            assert!(mt
+                .gen_proof_by_branch_version(i, main, ver)
+                .unwrap()
+                .verify(&mt.get_root_by_branch_version(main, ver).unwrap(), item));
+        }
+    }
 }
@@ -0,0 +1,487 @@
+//!
+//! An Ethereum-compatible Merkle Patricia Trie.
+//!
+//! Nodes are encoded by RLP and hashed by keccak256 in the way of the
+//! Ethereum yellow paper, so the same entries get the same root as geth,
+//! e.g. a trie keyed by `keccak256(address)` with RLP-encoded accounts
+//! as values gives the `stateRoot` of a block.
+//!
+//! Nodes are stored(in a `MapxRaw`) by their hashes and never changed,
+//! so a trie can be opened at any of its historical roots.
+//!
+//! NOTE:
+//! - Keys and values are used as they are, hashing keys(the "secure trie")
+//!   and encoding values are left to the callers
+//! - Inserting an empty value equals to removing the key, as geth does
+//! - Nodes are never removed, even if no roots refer to them any more
+//!
+
+use crate::{basic::mapx_raw::MapxRaw, merkle::sparse::Hash32};
+use ruc::*;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::mem;
+
+/// The root of an empty trie, that is `keccak256(rlp(""))`.
+pub const EMPTY_TRIE_ROOT: Hash32 = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0,
+    0xf8, 0x6e, 0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5,
+    0xe3, 0x63, 0xb4, 0x21,
+];
+
+/// The keccak256 hash, e.g. of the keys of a "secure trie".
+pub fn keccak256(data: &[u8]) -> Hash32 {
+    let mut ret = [0; 32];
+    ret.copy_from_slice(&Keccak256::digest(data));
+    ret
+}
+
+/// A trie at one of its roots, all roots of it share the same nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerklePatriciaTrie {
+    nodes: MapxRaw,
+    root: Hash32,
+}
+
+impl Default for MerklePatriciaTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MerklePatriciaTrie {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self {
+            nodes: MapxRaw::new(),
+            root: EMPTY_TRIE_ROOT,
+        }
+    }
+
+    #[inline(always)]
+    pub fn root(&self) -> Hash32 {
+        self.root
+    }
+
+    /// The same trie at another root, e.g. a historical one.
+    pub fn at_root(&self, root: &Hash32) -> Result<Self> {
+        if EMPTY_TRIE_ROOT != *root && !self.nodes.contains_key(root) {
+            return Err(eg!("root not found"));
+        }
+        Ok(Self {
+            nodes: self.nodes,
+            root: *root,
+        })
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get_at(self.root_node(), &nibbles(key)).c(d!())
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        if value.is_empty() {
+            return self.remove(key).c(d!());
+        }
+        let root = self
+            .insert_at(self.root_node(), &nibbles(key), value)
+            .c(d!())?;
+        self.set_root(root);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<()> {
+        let root = self.remove_at(self.root_node(), &nibbles(key)).c(d!())?;
+        self.set_root(root);
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.nodes.flush();
+    }
+
+    fn root_node(&self) -> Node {
+        if EMPTY_TRIE_ROOT == self.root {
+            Node::Empty
+        } else {
+            Node::Hash(self.root)
+        }
+    }
+
+    // The root is always referred by its hash, even if it is short.
+    fn set_root(&mut self, node: Node) {
+        self.root = match node {
+            Node::Empty => EMPTY_TRIE_ROOT,
+            Node::Hash(h) => h,
+            node => {
+                let bytes = self.commit(&node);
+                let h = keccak256(&bytes);
+                self.nodes.insert(&h, &bytes);
+                h
+            }
+        };
+    }
+
+    // A node got from it is never a `Node::Hash`.
+    fn load(&self, node: Node) -> Result<Node> {
+        if let Node::Hash(h) = node {
+            let bytes = self.nodes.get(&h).c(d!("node not found"))?;
+            decode_node(&bytes).c(d!())
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn get_at(&self, node: Node, path: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.load(node).c(d!())? {
+            Node::Leaf(k, v) if k == path => Ok(Some(v)),
+            Node::Extension(k, child) if path.starts_with(&k) => {
+                self.get_at(*child, &path[k.len()..]).c(d!())
+            }
+            Node::Branch(mut children, v) => {
+                if let Some((i, rest)) = path.split_first() {
+                    let child = mem::take(&mut children[*i as usize]);
+                    self.get_at(child, rest).c(d!())
+                } else {
+                    Ok(v)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn insert_at(&self, node: Node, path: &[u8], value: &[u8]) -> Result<Node> {
+        let ret = match self.load(node).c(d!())? {
+            Node::Empty => Node::Leaf(path.to_vec(), value.to_vec()),
+            Node::Leaf(k, _) if k == path => Node::Leaf(k, value.to_vec()),
+            Node::Leaf(k, v) => {
+                let n = common_prefix_len(&k, path);
+                let mut children = Children::default();
+                let mut bv = None;
+                for (p, v) in [(&k[n..], v), (&path[n..], value.to_vec())] {
+                    if let Some((i, rest)) = p.split_first() {
+                        children[*i as usize] = Node::Leaf(rest.to_vec(), v);
+                    } else {
+                        bv = Some(v);
+                    }
+                }
+                with_prefix(&path[..n], Node::Branch(children, bv))
+            }
+            Node::Extension(k, child) if path.starts_with(&k) => {
+                let child = self.insert_at(*child, &path[k.len()..], value).c(d!())?;
+                Node::Extension(k, Box::new(child))
+            }
+            Node::Extension(k, child) => {
+                let n = common_prefix_len(&k, path);
+                let mut children = Children::default();
+                let mut bv = None;
+                children[k[n] as usize] = with_prefix(&k[1 + n..], *child);
+                if let Some((i, rest)) = path[n..].split_first() {
+                    children[*i as usize] = Node::Leaf(rest.to_vec(), value.to_vec());
+                } else {
+                    bv = Some(value.to_vec());
+                }
+                with_prefix(&path[..n], Node::Branch(children, bv))
+            }
+            Node::Branch(mut children, v) => {
+                if let Some((i, rest)) = path.split_first() {
+                    let child = mem::take(&mut children[*i as usize]);
+                    children[*i as usize] =
+                        self.insert_at(child, rest, value).c(d!())?;
+                    Node::Branch(children, v)
+                } else {
+                    Node::Branch(children, Some(value.to_vec()))
+                }
+            }
+            Node::Hash(_) => unreachable!(),
+        };
+        Ok(ret)
+    }
+
+    fn remove_at(&self, node: Node, path: &[u8]) -> Result<Node> {
+        let ret = match self.load(node).c(d!())? {
+            Node::Leaf(k, _) if k == path => Node::Empty,
+            Node::Extension(k, child) if path.starts_with(&k) => {
+                let child = self.remove_at(*child, &path[k.len()..]).c(d!())?;
+                self.join(k, child).c(d!())?
+            }
+            Node::Branch(mut children, mut v) => {
+                if let Some((i, rest)) = path.split_first() {
+                    let child = mem::take(&mut children[*i as usize]);
+                    children[*i as usize] = self.remove_at(child, rest).c(d!())?;
+                } else {
+                    v = None;
+                }
+
+                let mut used = children
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| !matches!(c, Node::Empty))
+                    .map(|(i, _)| i);
+                match (used.next(), used.next(), v) {
+                    (None, _, None) => Node::Empty,
+                    (None, _, Some(v)) => Node::Leaf(vec![], v),
+                    (Some(i), None, None) => {
+                        let child = mem::take(&mut children[i]);
+                        self.join(vec![i as u8], child).c(d!())?
+                    }
+                    (_, _, v) => Node::Branch(children, v),
+                }
+            }
+            // not found
+            node => node,
+        };
+        Ok(ret)
+    }
+
+    // Put `prefix` before `child`, they are merged into one node if possible.
+    fn join(&self, mut prefix: Vec<u8>, child: Node) -> Result<Node> {
+        let ret = match self.load(child).c(d!())? {
+            Node::Empty => Node::Empty,
+            Node::Leaf(k, v) => {
+                prefix.extend_from_slice(&k);
+                Node::Leaf(prefix, v)
+            }
+            Node::Extension(k, c) => {
+                prefix.extend_from_slice(&k);
+                Node::Extension(prefix, c)
+            }
+            branch => Node::Extension(prefix, Box::new(branch)),
+        };
+        Ok(ret)
+    }
+
+    // The RLP encoding of a node, new nodes under it are stored on the way.
+    fn commit(&self, node: &Node) -> Vec<u8> {
+        match node {
+            Node::Leaf(k, v) => {
+                rlp_list(&[rlp_bytes(&hex_prefix(k, true)), rlp_bytes(v)])
+            }
+            Node::Extension(k, child) => {
+                rlp_list(&[rlp_bytes(&hex_prefix(k, false)), self.child_ref(child)])
+            }
+            Node::Branch(children, v) => {
+                let mut items = children
+                    .iter()
+                    .map(|c| self.child_ref(c))
+                    .collect::<Vec<_>>();
+                items.push(rlp_bytes(v.as_deref().unwrap_or_default()));
+                rlp_list(&items)
+            }
+            // handled by the callers
+            Node::Empty | Node::Hash(_) => unreachable!(),
+        }
+    }
+
+    // Children are referred by their hashes,
+    // or embedded if their encodings are shorter than 32 bytes.
+    fn child_ref(&self, node: &Node) -> Vec<u8> {
+        match node {
+            Node::Empty => rlp_bytes(&[]),
+            Node::Hash(h) => rlp_bytes(h),
+            node => {
+                let bytes = self.commit(node);
+                if bytes.len() < 32 {
+                    bytes
+                } else {
+                    let h = keccak256(&bytes);
+                    self.nodes.insert(&h, &bytes);
+                    rlp_bytes(&h)
+                }
+            }
+        }
+    }
+}
+
+type Children = Box<[Node; 16]>;
+
+#[derive(Default)]
+enum Node {
+    #[default]
+    Empty,
+    // [ the rest of the path ] + [ value ]
+    Leaf(Vec<u8>, Vec<u8>),
+    // [ shared nibbles ] + [ child ]
+    Extension(Vec<u8>, Box<Node>),
+    Branch(Children, Option<Vec<u8>>),
+    // stored in the database, not loaded yet
+    Hash(Hash32),
+}
+
+#[inline(always)]
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+#[inline(always)]
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+#[inline(always)]
+fn with_prefix(prefix: &[u8], node: Node) -> Node {
+    if prefix.is_empty() {
+        node
+    } else {
+        Node::Extension(prefix.to_vec(), Box::new(node))
+    }
+}
+
+// The compact encoding of nibbles, with a flag of leaves in the first byte.
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let odd = 1 == nibbles.len() % 2;
+    let flag = 2 * leaf as u8 + odd as u8;
+    let mut ret = Vec::with_capacity(1 + nibbles.len() / 2);
+    let rest = if odd {
+        ret.push((flag << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        ret.push(flag << 4);
+        nibbles
+    };
+    rest.chunks(2).for_each(|p| ret.push((p[0] << 4) | p[1]));
+    ret
+}
+
+// [ nibbles ] + [ is a leaf ]
+fn hex_prefix_decode(bytes: &[u8]) -> Result<(Vec<u8>, bool)> {
+    let (first, rest) = bytes.split_first().c(d!("empty path"))?;
+    let flag = first >> 4;
+    if 3 < flag {
+        return Err(eg!("invalid path flag"));
+    }
+    let mut ret = Vec::with_capacity(1 + 2 * rest.len());
+    if 1 == flag & 1 {
+        ret.push(first & 0x0f);
+    }
+    ret.extend(nibbles(rest));
+    Ok((ret, 2 == flag & 2))
+}
+
+fn decode_node(bytes: &[u8]) -> Result<Node> {
+    let item = rlp_split(bytes).c(d!())?;
+    if !item.is_list {
+        return Err(eg!("invalid node"));
+    }
+    let items = rlp_items(item.payload).c(d!())?;
+    match items.len() {
+        2 => {
+            let (path, leaf) = hex_prefix_decode(items[0].payload).c(d!())?;
+            if leaf {
+                Ok(Node::Leaf(path, items[1].payload.to_vec()))
+            } else {
+                let child = decode_ref(&items[1]).c(d!())?;
+                Ok(Node::Extension(path, Box::new(child)))
+            }
+        }
+        17 => {
+            let mut children = Children::default();
+            for (i, item) in items[..16].iter().enumerate() {
+                children[i] = decode_ref(item).c(d!())?;
+            }
+            let v = Some(items[16].payload.to_vec()).filter(|v| !v.is_empty());
+            Ok(Node::Branch(children, v))
+        }
+        _ => Err(eg!("invalid node")),
+    }
+}
+
+fn decode_ref(item: &RlpItem) -> Result<Node> {
+    if item.is_list {
+        decode_node(item.raw).c(d!())
+    } else if item.payload.is_empty() {
+        Ok(Node::Empty)
+    } else if 32 == item.payload.len() {
+        let mut h = [0; 32];
+        h.copy_from_slice(item.payload);
+        Ok(Node::Hash(h))
+    } else {
+        Err(eg!("invalid child reference"))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////
+
+fn rlp_header(base: u8, len: usize) -> Vec<u8> {
+    if len <= 55 {
+        vec![base + len as u8]
+    } else {
+        let be = len.to_be_bytes();
+        let be = &be[(len.leading_zeros() / 8) as usize..];
+        let mut ret = vec![base + 55 + be.len() as u8];
+        ret.extend_from_slice(be);
+        ret
+    }
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if 1 == bytes.len() && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut ret = rlp_header(0x80, bytes.len());
+    ret.extend_from_slice(bytes);
+    ret
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut ret = rlp_header(0xc0, items.iter().map(|i| i.len()).sum());
+    items.iter().for_each(|i| ret.extend_from_slice(i));
+    ret
+}
+
+struct RlpItem<'a> {
+    is_list: bool,
+    payload: &'a [u8],
+    // the whole encoding, header included
+    raw: &'a [u8],
+}
+
+// The first item of the input.
+fn rlp_split(bytes: &[u8]) -> Result<RlpItem> {
+    let b = *bytes.first().c(d!("empty input"))?;
+    let (is_list, offset, len) = match b {
+        0..=0x7f => {
+            return Ok(RlpItem {
+                is_list: false,
+                payload: &bytes[..1],
+                raw: &bytes[..1],
+            })
+        }
+        0x80..=0xb7 => (false, 1, (b - 0x80) as usize),
+        0xc0..=0xf7 => (true, 1, (b - 0xc0) as usize),
+        _ => {
+            let (is_list, n) = if b < 0xc0 {
+                (false, (b - 0xb7) as usize)
+            } else {
+                (true, (b - 0xf7) as usize)
+            };
+            let len = bytes
+                .get(1..1 + n)
+                .c(d!("truncated input"))?
+                .iter()
+                .try_fold(0usize, |acc, b| {
+                    acc.checked_mul(256).map(|acc| acc + *b as usize)
+                })
+                .c(d!("invalid length"))?;
+            (is_list, 1 + n, len)
+        }
+    };
+    let end = offset.checked_add(len).c(d!("invalid length"))?;
+    let payload = bytes.get(offset..end).c(d!("truncated input"))?;
+    Ok(RlpItem {
+        is_list,
+        payload,
+        raw: &bytes[..end],
+    })
+}
+
+fn rlp_items(mut payload: &[u8]) -> Result<Vec<RlpItem>> {
+    let mut ret = vec![];
+    while !payload.is_empty() {
+        let item = rlp_split(payload).c(d!())?;
+        payload = &payload[item.raw.len()..];
+        ret.push(item);
+    }
+    Ok(ret)
+}
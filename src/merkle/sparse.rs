@@ -0,0 +1,436 @@
+//!
+//! Sparse Merkle trees over the hashes of keys.
+//!
+//! Nodes are stored by their hashes and never changed after being written,
+//! every update produces a new root and shares all the untouched nodes with
+//! the old tree, so one store can hold any number of trees(e.g. one per
+//! version), and an old root is still readable after updates.
+//!
+//! A subtree holding only one leaf is replaced by the leaf itself, so the
+//! depth of a tree is about `log2(N)` instead of 256, and the root of a set
+//! of entries does not depend on the order they are written.
+//!
+//! A [proof](SparseMerkleProof) of a key ends at the leaf of it, if the key
+//! is absent, it ends at an empty subtree or a leaf of another key(whose hash
+//! shares the path with it), so non-membership can be proved too.
+//!
+//! NOTE:
+//! - Nodes are not removed by updates, even if no roots refer to them any more,
+//!   call [retain](SparseMerkleStore::retain) to remove them
+//!
+
+use super::{proof_body, proof_take, HASH_LEN, PROOF_FORMAT_VERSION, PROOF_KIND_SPARSE};
+use crate::{basic::mapx_raw::MapxRaw, common::ende::SimpleVisitor};
+use ruc::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
+use std::{collections::HashSet, result::Result as StdResult};
+
+/// Hashes of nodes, a tree is identified by the hash of its root node.
+pub type Hash32 = [u8; 32];
+
+/// The root of an empty tree.
+pub const EMPTY_ROOT: Hash32 = [0; 32];
+
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+
+fn hash(data: &[&[u8]]) -> Hash32 {
+    let mut hasher = Sha3_256::new();
+    for bytes in data {
+        hasher.update(bytes);
+    }
+    let mut ret = EMPTY_ROOT;
+    ret.copy_from_slice(&hasher.finalize());
+    ret
+}
+
+// The `depth`-th bit of a key hash, `true` means the right side.
+#[inline(always)]
+fn bit(key_hash: &Hash32, depth: usize) -> bool {
+    0 != (key_hash[depth / 8] >> (7 - depth % 8)) & 1
+}
+
+enum Node {
+    // [ key hash ] + [ value hash ]
+    Leaf(Hash32, Hash32),
+    // [ left child ] + [ right child ]
+    Internal(Hash32, Hash32),
+}
+
+impl Node {
+    #[inline(always)]
+    fn hash(&self) -> Hash32 {
+        hash(&[&self.encode()])
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (tag, a, b) = match self {
+            Node::Leaf(a, b) => (LEAF, a, b),
+            Node::Internal(a, b) => (INTERNAL, a, b),
+        };
+        let mut ret = Vec::with_capacity(1 + 2 * a.len());
+        ret.push(tag);
+        ret.extend_from_slice(a);
+        ret.extend_from_slice(b);
+        ret
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let mut a = EMPTY_ROOT;
+        let mut b = EMPTY_ROOT;
+        a.copy_from_slice(&bytes[1..33]);
+        b.copy_from_slice(&bytes[33..65]);
+        if LEAF == bytes[0] {
+            Node::Leaf(a, b)
+        } else {
+            Node::Internal(a, b)
+        }
+    }
+}
+
+/// A store of the nodes of sparse Merkle trees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SparseMerkleStore {
+    nodes: MapxRaw,
+}
+
+impl SparseMerkleStore {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self {
+            nodes: MapxRaw::new(),
+        }
+    }
+
+    /// Set `key` to `value` in the tree of `root`, return the new root.
+    pub fn insert(&self, root: &Hash32, key: &[u8], value: &[u8]) -> Hash32 {
+        let key_hash = hash(&[key]);
+        let value_hash = hash(&[value]);
+        self.insert_at(*root, 0, &key_hash, &value_hash)
+    }
+
+    /// Remove `key` from the tree of `root`, return the new root.
+    pub fn remove(&self, root: &Hash32, key: &[u8]) -> Hash32 {
+        let key_hash = hash(&[key]);
+        self.remove_at(*root, 0, &key_hash)
+    }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.nodes.flush();
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRaw {
+        &self.nodes
+    }
+
+    /// Remove the nodes of all trees.
+    #[inline(always)]
+    pub fn clear(&self) {
+        self.nodes.clear();
+    }
+
+    /// Remove the nodes that do not belong to any tree of `roots`,
+    /// return the number of removed nodes.
+    ///
+    /// Trees being built along with it may lose their nodes,
+    /// so nothing else should be written to the store until it returns.
+    pub fn retain(&self, roots: impl IntoIterator<Item = Hash32>) -> usize {
+        let mut live = HashSet::new();
+        let mut pending = roots.into_iter().collect::<Vec<_>>();
+        while let Some(h) = pending.pop() {
+            if EMPTY_ROOT == h || !live.insert(h) {
+                continue;
+            }
+            if let Some(Node::Internal(l, r)) = self.node(&h) {
+                pending.push(l);
+                pending.push(r);
+            }
+        }
+
+        let mut cnt = 0;
+        for (h, _) in self.nodes.iter() {
+            if !matches!(Hash32::try_from(&h[..]), Ok(h) if live.contains(&h)) {
+                self.nodes.remove(&h);
+                cnt += 1;
+            }
+        }
+        cnt
+    }
+
+    fn node(&self, hash: &Hash32) -> Option<Node> {
+        if EMPTY_ROOT == *hash {
+            return None;
+        }
+        let bytes = pnk!(self.nodes.get(hash).c(d!("BUG: node not found")));
+        Some(Node::decode(&bytes))
+    }
+
+    fn put(&self, node: Node) -> Hash32 {
+        let bytes = node.encode();
+        let h = hash(&[&bytes]);
+        self.nodes.insert(&h, &bytes);
+        h
+    }
+
+    /// Prove the value, or the absence, of `key` in the tree of `root`.
+    pub fn prove(&self, root: &Hash32, key: &[u8]) -> SparseMerkleProof {
+        let key_hash = hash(&[key]);
+        let mut siblings = vec![];
+        let mut cur = *root;
+        let leaf = loop {
+            match self.node(&cur) {
+                None => break None,
+                Some(Node::Leaf(k, v)) => break Some((k, v)),
+                Some(Node::Internal(l, r)) => {
+                    if bit(&key_hash, siblings.len()) {
+                        siblings.push(l);
+                        cur = r;
+                    } else {
+                        siblings.push(r);
+                        cur = l;
+                    }
+                }
+            }
+        };
+        SparseMerkleProof { siblings, leaf }
+    }
+
+    fn insert_at(
+        &self,
+        node: Hash32,
+        depth: usize,
+        key_hash: &Hash32,
+        value_hash: &Hash32,
+    ) -> Hash32 {
+        match self.node(&node) {
+            None => self.put(Node::Leaf(*key_hash, *value_hash)),
+            Some(Node::Leaf(k, _)) if k == *key_hash => {
+                self.put(Node::Leaf(*key_hash, *value_hash))
+            }
+            Some(Node::Leaf(k, _)) => {
+                let leaf = self.put(Node::Leaf(*key_hash, *value_hash));
+                self.fork(depth, (node, &k), (leaf, key_hash))
+            }
+            Some(Node::Internal(l, r)) => {
+                if bit(key_hash, depth) {
+                    let r = self.insert_at(r, 1 + depth, key_hash, value_hash);
+                    self.put(Node::Internal(l, r))
+                } else {
+                    let l = self.insert_at(l, 1 + depth, key_hash, value_hash);
+                    self.put(Node::Internal(l, r))
+                }
+            }
+        }
+    }
+
+    // Place two leaves(with different key hashes) under a new subtree,
+    // down to the first bit that tells them apart.
+    fn fork(&self, depth: usize, a: (Hash32, &Hash32), b: (Hash32, &Hash32)) -> Hash32 {
+        let (a_bit, b_bit) = (bit(a.1, depth), bit(b.1, depth));
+        let node = if a_bit == b_bit {
+            let child = self.fork(1 + depth, a, b);
+            if a_bit {
+                Node::Internal(EMPTY_ROOT, child)
+            } else {
+                Node::Internal(child, EMPTY_ROOT)
+            }
+        } else if a_bit {
+            Node::Internal(b.0, a.0)
+        } else {
+            Node::Internal(a.0, b.0)
+        };
+        self.put(node)
+    }
+
+    fn remove_at(&self, node: Hash32, depth: usize, key_hash: &Hash32) -> Hash32 {
+        match self.node(&node) {
+            None => EMPTY_ROOT,
+            Some(Node::Leaf(k, _)) if k == *key_hash => EMPTY_ROOT,
+            Some(Node::Leaf(..)) => node,
+            Some(Node::Internal(l, r)) => {
+                let (new_l, new_r) = if bit(key_hash, depth) {
+                    (l, self.remove_at(r, 1 + depth, key_hash))
+                } else {
+                    (self.remove_at(l, 1 + depth, key_hash), r)
+                };
+
+                // not found
+                if new_l == l && new_r == r {
+                    return node;
+                }
+
+                // a leaf left alone takes the place of its parent
+                match (EMPTY_ROOT == new_l, EMPTY_ROOT == new_r) {
+                    (true, true) => EMPTY_ROOT,
+                    (true, false) if self.is_leaf(&new_r) => new_r,
+                    (false, true) if self.is_leaf(&new_l) => new_l,
+                    _ => self.put(Node::Internal(new_l, new_r)),
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn is_leaf(&self, hash: &Hash32) -> bool {
+        matches!(self.node(hash), Some(Node::Leaf(..)))
+    }
+}
+
+/// A proof of the value, or the absence, of a key in a sparse Merkle tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparseMerkleProof {
+    // siblings on the path of the key, from the root down
+    siblings: Vec<Hash32>,
+    // the leaf at the end of the path, [ key hash ] + [ value hash ],
+    // `None` if the path ends at an empty subtree
+    leaf: Option<(Hash32, Hash32)>,
+}
+
+impl SparseMerkleProof {
+    /// Check that `key` is set to `value` in the tree of `root`.
+    pub fn verify_inclusion(&self, root: &Hash32, key: &[u8], value: &[u8]) -> bool {
+        let key_hash = hash(&[key]);
+        matches!(self.leaf, Some((k, v)) if k == key_hash && v == hash(&[value]))
+            && Some(*root) == self.root_of(&key_hash)
+    }
+
+    /// Check that `key` is absent from the tree of `root`.
+    pub fn verify_exclusion(&self, root: &Hash32, key: &[u8]) -> bool {
+        let key_hash = hash(&[key]);
+        if let Some((k, _)) = self.leaf {
+            // the leaf of another key, it must sit on the path of this one
+            if k == key_hash
+                || (0..self.siblings.len()).any(|d| bit(&k, d) != bit(&key_hash, d))
+            {
+                return false;
+            }
+        }
+        Some(*root) == self.root_of(&key_hash)
+    }
+
+    /// `true` if it proves the presence of a key.
+    #[inline(always)]
+    pub fn is_inclusion(&self, key: &[u8]) -> bool {
+        matches!(self.leaf, Some((k, _)) if k == hash(&[key]))
+    }
+
+    /// The compact encoding of the proof:
+    /// `[ format version ] + [ kind ] + [ depth ] + [ bitmap ] + [ siblings ] + [ leaf ]`.
+    ///
+    /// - the depth is the number of siblings, as a `u16` in big-endian
+    /// - a bit of the bitmap is set if the sibling at that depth is not empty,
+    ///   only those siblings are written
+    /// - the leaf is `[ 0 ]` for an empty subtree,
+    ///   or `[ 1 ] + [ key hash ] + [ value hash ]`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let depth = self.siblings.len();
+        let mut bitmap = vec![0u8; (depth + 7) / 8];
+        let mut siblings = vec![];
+        for (d, sib) in self.siblings.iter().enumerate() {
+            if EMPTY_ROOT != *sib {
+                bitmap[d / 8] |= 1 << (7 - d % 8);
+                siblings.extend_from_slice(sib);
+            }
+        }
+
+        let mut ret = vec![PROOF_FORMAT_VERSION, PROOF_KIND_SPARSE];
+        ret.extend_from_slice(&(depth as u16).to_be_bytes());
+        ret.extend_from_slice(&bitmap);
+        ret.extend_from_slice(&siblings);
+        if let Some((k, v)) = self.leaf {
+            ret.push(1);
+            ret.extend_from_slice(&k);
+            ret.extend_from_slice(&v);
+        } else {
+            ret.push(0);
+        }
+        ret
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut body = proof_body(bytes, PROOF_KIND_SPARSE).c(d!())?;
+        let mut depth = [0; 2];
+        depth.copy_from_slice(proof_take(&mut body, 2).c(d!())?);
+        let depth = u16::from_be_bytes(depth) as usize;
+        if depth > 8 * HASH_LEN {
+            return Err(eg!("invalid proof: too deep"));
+        }
+
+        let bitmap = proof_take(&mut body, (depth + 7) / 8).c(d!())?;
+        let mut siblings = Vec::with_capacity(depth);
+        for d in 0..depth {
+            let mut sib = EMPTY_ROOT;
+            if 0 != (bitmap[d / 8] >> (7 - d % 8)) & 1 {
+                sib.copy_from_slice(proof_take(&mut body, HASH_LEN).c(d!())?);
+            }
+            siblings.push(sib);
+        }
+
+        let leaf = match proof_take(&mut body, 1).c(d!())? {
+            [0] => None,
+            [1] => {
+                let mut k = EMPTY_ROOT;
+                let mut v = EMPTY_ROOT;
+                k.copy_from_slice(proof_take(&mut body, HASH_LEN).c(d!())?);
+                v.copy_from_slice(proof_take(&mut body, HASH_LEN).c(d!())?);
+                Some((k, v))
+            }
+            _ => return Err(eg!("invalid proof: broken leaf")),
+        };
+
+        if !body.is_empty() {
+            return Err(eg!("invalid proof: trailing bytes"));
+        }
+
+        Ok(Self { siblings, leaf })
+    }
+
+    fn root_of(&self, key_hash: &Hash32) -> Option<Hash32> {
+        if 8 * key_hash.len() < self.siblings.len() {
+            return None;
+        }
+
+        let cur = self
+            .leaf
+            .map(|(k, v)| Node::Leaf(k, v).hash())
+            .unwrap_or(EMPTY_ROOT);
+        let root = self
+            .siblings
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(cur, |cur, (d, sib)| {
+                if bit(key_hash, d) {
+                    Node::Internal(*sib, cur).hash()
+                } else {
+                    Node::Internal(cur, *sib).hash()
+                }
+            });
+        Some(root)
+    }
+}
+
+impl Serialize for SparseMerkleProof {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for SparseMerkleProof {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_bytes(SimpleVisitor)
+            .and_then(|bytes| {
+                SparseMerkleProof::from_bytes(&bytes).map_err(de::Error::custom)
+            })
+    }
+}
@@ -0,0 +1,249 @@
+//!
+//! A versioned `MerkleTree`, its nodes are stored in a `MapxRawVs`.
+//!
+//! Leaves are appended one by one, only the nodes on the path of the new leaf
+//! are rewritten, and the root is the same as that of a `MerkleTree`
+//! built from the same leaves.
+//!
+//! Nodes are versioned data, so branches share the nodes of their bases,
+//! forking a branch copies nothing, and the root(or a proof) of any version
+//! is read directly instead of being recomputed.
+//!
+
+use super::{hashv, Hash, MerkleTree, Proof, INTERMEDIATE_PREFIX, LEAF_PREFIX};
+use crate::{
+    versioned::{mapx_raw::MapxRawVs, BranchInfo, PrunePolicy, VersionMeta, VsComplete},
+    BranchName, ParentBranchName, VersionName, VsMgmt,
+};
+use ruc::*;
+use serde::{Deserialize, Serialize};
+
+// node keys are `[ level ] + [ index(u64, BE) ]`, so this one never conflicts
+const LEAF_COUNT_KEY: &[u8] = &[u8::MAX];
+
+#[inline(always)]
+fn node_key(level: u8, index: usize) -> [u8; 9] {
+    let mut ret = [level; 9];
+    ret[1..].copy_from_slice(&(index as u64).to_be_bytes());
+    ret
+}
+
+fn leaf_count(get: &impl Fn(&[u8]) -> Option<Hash>) -> usize {
+    get(LEAF_COUNT_KEY)
+        .map(|n| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&n);
+            u64::from_be_bytes(bytes) as usize
+        })
+        .unwrap_or(0)
+}
+
+fn root(get: &impl Fn(&[u8]) -> Option<Hash>) -> Option<Hash> {
+    let mut level_len = leaf_count(get);
+    alt!(0 == level_len, return None);
+
+    let mut level = 0;
+    while level_len > 1 {
+        level += 1;
+        level_len = MerkleTree::next_level_len(level_len);
+    }
+    get(&node_key(level, 0))
+}
+
+fn gen_proof(get: &impl Fn(&[u8]) -> Option<Hash>, leaf_index: usize) -> Option<Proof> {
+    let leaf_count = leaf_count(get);
+    if leaf_index >= leaf_count {
+        return None;
+    }
+
+    let mut level_len = leaf_count;
+    let mut level = 0;
+    let mut node_index = leaf_index;
+    let mut siblings = vec![];
+    while level_len > 1 {
+        let sib = if node_index % 2 == 1 {
+            node_index - 1
+        } else if node_index + 1 < level_len {
+            node_index + 1
+        } else {
+            node_index
+        };
+        siblings.push(get(&node_key(level, sib))?);
+
+        node_index /= 2;
+        level += 1;
+        level_len = MerkleTree::next_level_len(level_len);
+    }
+
+    Some(Proof {
+        leaf_index: leaf_index as u64,
+        leaf_count: leaf_count as u64,
+        siblings,
+    })
+}
+
+fn push(
+    leaf: &[u8],
+    get: impl Fn(&[u8]) -> Option<Hash>,
+    put: impl Fn(&[u8], &[u8]) -> Result<()>,
+) -> Result<()> {
+    let leaf_count = leaf_count(&get);
+
+    let mut hash = hashv(&[LEAF_PREFIX, leaf]);
+    put(&node_key(0, leaf_count), &hash).c(d!())?;
+
+    let mut level_len = 1 + leaf_count;
+    let mut level = 0;
+    let mut node_index = leaf_count;
+    while level_len > 1 {
+        // the new leaf is always under the last node of every level
+        hash = if node_index % 2 == 1 {
+            let lsib =
+                get(&node_key(level, node_index - 1)).c(d!("BUG: node not found"))?;
+            hashv(&[INTERMEDIATE_PREFIX, &lsib, &hash])
+        } else {
+            // Duplicate last entry if the level length is odd
+            hashv(&[INTERMEDIATE_PREFIX, &hash, &hash])
+        };
+
+        node_index /= 2;
+        level += 1;
+        level_len = MerkleTree::next_level_len(level_len);
+        put(&node_key(level, node_index), &hash).c(d!())?;
+    }
+
+    put(LEAF_COUNT_KEY, &(1 + leaf_count as u64).to_be_bytes()).c(d!())
+}
+
+/// A `MerkleTree` whose nodes are versioned.
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct MerkleTreeVs {
+    inner: MapxRawVs,
+}
+
+impl MerkleTreeVs {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self {
+            inner: MapxRawVs::new(),
+        }
+    }
+
+    /// Append a leaf to the head version of the default branch.
+    #[inline(always)]
+    pub fn push(&self, leaf: &[u8]) -> Result<()> {
+        push(
+            leaf,
+            |k| self.inner.get(k),
+            |k, v| self.inner.insert(k, v).c(d!()).map(|_| ()),
+        )
+        .c(d!())
+    }
+
+    /// Append a leaf to the head version of a specified branch.
+    #[inline(always)]
+    pub fn push_by_branch(&self, leaf: &[u8], branch_name: BranchName) -> Result<()> {
+        push(
+            leaf,
+            |k| self.inner.get_by_branch(k, branch_name),
+            |k, v| {
+                self.inner
+                    .insert_by_branch(k, v, branch_name)
+                    .c(d!())
+                    .map(|_| ())
+            },
+        )
+        .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        leaf_count(&|k| self.inner.get(k))
+    }
+
+    #[inline(always)]
+    pub fn len_by_branch(&self, branch_name: BranchName) -> usize {
+        leaf_count(&|k| self.inner.get_by_branch(k, branch_name))
+    }
+
+    #[inline(always)]
+    pub fn len_by_branch_version(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> usize {
+        leaf_count(&|k| {
+            self.inner
+                .get_by_branch_version(k, branch_name, version_name)
+        })
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    /// The root of the head version of the default branch,
+    /// `None` if the tree is empty.
+    #[inline(always)]
+    pub fn get_root(&self) -> Option<Hash> {
+        root(&|k| self.inner.get(k))
+    }
+
+    #[inline(always)]
+    pub fn get_root_by_branch(&self, branch_name: BranchName) -> Option<Hash> {
+        root(&|k| self.inner.get_by_branch(k, branch_name))
+    }
+
+    /// The root of a historical version, it is read from the stored nodes,
+    /// nothing is recomputed.
+    #[inline(always)]
+    pub fn get_root_by_branch_version(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Option<Hash> {
+        root(&|k| {
+            self.inner
+                .get_by_branch_version(k, branch_name, version_name)
+        })
+    }
+
+    /// Generate a standalone proof of the leaf at `leaf_index`
+    /// in the head version of the default branch.
+    #[inline(always)]
+    pub fn gen_proof(&self, leaf_index: usize) -> Option<Proof> {
+        gen_proof(&|k| self.inner.get(k), leaf_index)
+    }
+
+    #[inline(always)]
+    pub fn gen_proof_by_branch(
+        &self,
+        leaf_index: usize,
+        branch_name: BranchName,
+    ) -> Option<Proof> {
+        gen_proof(&|k| self.inner.get_by_branch(k, branch_name), leaf_index)
+    }
+
+    #[inline(always)]
+    pub fn gen_proof_by_branch_version(
+        &self,
+        leaf_index: usize,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Option<Proof> {
+        gen_proof(
+            &|k| {
+                self.inner
+                    .get_by_branch_version(k, branch_name, version_name)
+            },
+            leaf_index,
+        )
+    }
+}
+
+impl VsMgmt for MerkleTreeVs {
+    crate::impl_vs_methods!();
+}
+
+impl VsComplete for MerkleTreeVs {}
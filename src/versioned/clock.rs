@@ -0,0 +1,84 @@
+//!
+//! Time sources of version timestamps.
+//!
+//! The creation time of every version is read from a global clock,
+//! which is the system clock by default, replace it by
+//! [vsdb_set_version_clock](self::vsdb_set_version_clock) to drive
+//! timestamps by a logical clock or the time of blocks.
+//!
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A source of version timestamps.
+///
+/// The unit of timestamps is decided by the clock itself,
+/// and durations in `PrunePolicy` are compared with them as seconds.
+pub trait VersionClock: Send + Sync {
+    /// The current timestamp.
+    fn now(&self) -> u64;
+}
+
+/// Seconds since the UNIX epoch, this is the default clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl VersionClock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// A counter that ticks once on every reading,
+/// mainly for deterministic simulations.
+#[derive(Debug, Default)]
+pub struct LogicalClock(AtomicU64);
+
+impl LogicalClock {
+    /// Start from a specified value.
+    pub fn new(start: u64) -> Self {
+        Self(AtomicU64::new(start))
+    }
+}
+
+impl VersionClock for LogicalClock {
+    fn now(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// User-provided time sources, such as the time of the current block.
+impl<F> VersionClock for F
+where
+    F: Fn() -> u64 + Send + Sync,
+{
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+static CLOCK: Lazy<RwLock<Arc<dyn VersionClock>>> =
+    Lazy::new(|| RwLock::new(Arc::new(SystemClock)));
+
+/// Replace the global clock of version timestamps,
+/// versions created before will not be affected.
+pub fn vsdb_set_version_clock(clock: Arc<dyn VersionClock>) {
+    *CLOCK.write() = clock;
+}
+
+// Clone the clock out, so the lock will not be held when it is running.
+#[inline(always)]
+pub(crate) fn now() -> u64 {
+    let clock = CLOCK.read().clone();
+    clock.now()
+}
@@ -3,9 +3,23 @@
 //!
 
 use crate::{
-    common::ende::{KeyEnDe, ValueEnDe},
-    versioned::mapx_ord_rawkey::{MapxOrdRawKeyVs, MapxOrdRawKeyVsIter},
-    BranchName, ParentBranchName, VersionName, VsMgmt,
+    common::{
+        ende::{
+            decode_key_by_policy_with, encode_key_by, Codec, DefaultCodec, KeyEnDe,
+            ValueEnDe,
+        },
+        named,
+        stats::SizeHistogram,
+        RawValue,
+    },
+    merkle::sparse::{Hash32, SparseMerkleProof},
+    versioned::{
+        mapx_ord_rawkey::{MapxOrdRawKeyVs, MapxOrdRawKeyVsIter},
+        mapx_raw::MapxRawVs,
+        BranchInfo, Diff, KeyBlame, MergeConflict, MergeResolution, PrunePolicy,
+        SnapshotHandle, VersionBundle, VersionInfo, VersionMeta,
+    },
+    BranchName, ParentBranchName, VersionName, VsComplete, VsMgmt, VsNested,
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
@@ -17,21 +31,24 @@ use std::{
 /// Documents => [MapxRawVs](crate::versioned::mapx_raw::MapxRawVs)
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
 #[serde(bound = "")]
-pub struct MapxVs<K, V> {
-    inner: MapxOrdRawKeyVs<V>,
+pub struct MapxVs<K, V, C = DefaultCodec> {
+    inner: MapxOrdRawKeyVs<V, C>,
     pk: PhantomData<K>,
 }
 
-impl<K, V> Default for MapxVs<K, V>
+impl<K, V, C> Default for MapxVs<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn default() -> Self {
-        Self::new()
+        Self::with_codec()
     }
 }
 
+// Constructors of the default codec,
+// use `with_codec` to create an instance of another codec.
 impl<K, V> MapxVs<K, V>
 where
     K: KeyEnDe,
@@ -39,39 +56,76 @@ where
 {
     #[inline(always)]
     pub fn new() -> Self {
+        Self::with_codec()
+    }
+
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    /// Create an instance that keeps the Merkle root of every version,
+    /// see [MapxRawVs::new_with_roots](crate::versioned::mapx_raw::MapxRawVs::new_with_roots).
+    #[inline(always)]
+    pub fn new_with_roots() -> Self {
         MapxVs {
-            inner: MapxOrdRawKeyVs::new(),
+            inner: MapxOrdRawKeyVs::new_with_roots(),
+            pk: PhantomData,
+        }
+    }
+}
+
+impl<K, V, C> MapxVs<K, V, C>
+where
+    K: KeyEnDe,
+    V: ValueEnDe,
+    C: Codec,
+{
+    /// Create an instance whose keys and values are encoded by the codec `C`,
+    /// e.g. `MapxVs::<K, V, BcsCodec>::with_codec()`.
+    #[inline(always)]
+    pub fn with_codec() -> Self {
+        MapxVs {
+            inner: MapxOrdRawKeyVs::with_codec(),
             pk: PhantomData,
         }
     }
 
     #[inline(always)]
     pub fn get(&self, key: &K) -> Option<V> {
-        self.inner.get(&key.encode())
+        self.inner.get(&encode_key_by::<C, K>(key))
     }
 
     #[inline(always)]
-    pub fn get_mut<'a>(&'a self, key: &'a K) -> Option<ValueMut<'a, K, V>> {
-        self.get(key).map(move |v| ValueMut::new(self, key, v))
+    pub fn get_mut<'a>(&'a self, key: &'a K) -> Option<ValueMut<'a, K, V, C>> {
+        self.get_head(key).map(move |v| ValueMut::new(self, key, v))
     }
 
+    // Get in the view of the writer, for read-modify-write paths.
     #[inline(always)]
-    pub fn entry_ref<'a>(&'a self, key: &'a K) -> Entry<'a, K, V> {
+    fn get_head(&self, key: &K) -> Option<V> {
+        self.inner.get_head(&encode_key_by::<C, K>(key))
+    }
+
+    #[inline(always)]
+    pub fn entry_ref<'a>(&'a self, key: &'a K) -> Entry<'a, K, V, C> {
         Entry { key, hdr: self }
     }
 
     #[inline(always)]
     pub fn get_le(&self, key: &K) -> Option<(K, V)> {
         self.inner
-            .get_le(&key.encode())
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .get_le(&encode_key_by::<C, K>(key))
+            .and_then(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge(&self, key: &K) -> Option<(K, V)> {
         self.inner
-            .get_ge(&key.encode())
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .get_ge(&encode_key_by::<C, K>(key))
+            .and_then(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -91,11 +145,98 @@ where
 
     #[inline(always)]
     pub fn insert_ref(&self, key: &K, value: &V) -> Result<Option<V>> {
-        self.inner.insert_ref(&key.encode(), value).c(d!())
+        self.inner
+            .insert_ref(&encode_key_by::<C, K>(key), value)
+            .c(d!())
+    }
+
+    /// Get the encoded bytes of a value, e.g. for replicating or hashing,
+    /// the codec is bypassed.
+    #[inline(always)]
+    pub fn get_raw(&self, key: &K) -> Option<RawValue> {
+        self.inner.get_raw(&encode_key_by::<C, K>(key))
+    }
+
+    /// Insert the encoded bytes of a value, e.g. got by `get_raw`,
+    /// the codec is bypassed, it's your duty to ensure that they
+    /// can be decoded as a `V` by the codec `C`.
+    #[inline(always)]
+    pub fn insert_raw(&self, key: &K, value: &[u8]) -> Result<Option<RawValue>> {
+        self.inner
+            .insert_raw(&encode_key_by::<C, K>(key), value)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRawVs {
+        self.inner.as_raw()
+    }
+
+    #[inline(always)]
+    pub fn roots_enabled(&self) -> bool {
+        self.inner.roots_enabled()
+    }
+
+    /// The Merkle root of the head state of the default branch,
+    /// keys and values are committed in their encoded forms.
+    #[inline(always)]
+    pub fn root(&self) -> Result<Hash32> {
+        self.inner.root().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn root_by_branch(&self, branch_name: BranchName) -> Result<Hash32> {
+        self.inner.root_by_branch(branch_name).c(d!())
     }
 
     #[inline(always)]
-    pub fn iter(&self) -> MapxVsIter<K, V> {
+    pub fn root_by_branch_version(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<Hash32> {
+        self.inner
+            .root_by_branch_version(branch_name, version_name)
+            .c(d!())
+    }
+
+    /// A proof of the value, or the absence, of a key in the head state
+    /// of the default branch, it is verified with the encoded key and value,
+    /// e.g. got by `get_raw`.
+    #[inline(always)]
+    pub fn prove(&self, key: &K) -> Result<SparseMerkleProof> {
+        self.inner.prove(&encode_key_by::<C, K>(key)).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove_by_branch(
+        &self,
+        key: &K,
+        branch_name: BranchName,
+    ) -> Result<SparseMerkleProof> {
+        self.inner
+            .prove_by_branch(&encode_key_by::<C, K>(key), branch_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove_by_branch_version(
+        &self,
+        key: &K,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<SparseMerkleProof> {
+        self.inner
+            .prove_by_branch_version(
+                &encode_key_by::<C, K>(key),
+                branch_name,
+                version_name,
+            )
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> MapxVsIter<K, V, C> {
         MapxVsIter {
             iter: self.inner.iter(),
             pk: PhantomData,
@@ -106,15 +247,15 @@ where
     pub fn range<'a, R: 'a + RangeBounds<K>>(
         &'a self,
         bounds: R,
-    ) -> MapxVsIter<'a, K, V> {
+    ) -> MapxVsIter<'a, K, V, C> {
         let l = match bounds.start_bound() {
-            Bound::Included(i) => Bound::Included(i.encode()),
-            Bound::Excluded(i) => Bound::Excluded(i.encode()),
+            Bound::Included(i) => Bound::Included(encode_key_by::<C, K>(i)),
+            Bound::Excluded(i) => Bound::Excluded(encode_key_by::<C, K>(i)),
             _ => Bound::Unbounded,
         };
         let h = match bounds.end_bound() {
-            Bound::Included(i) => Bound::Included(i.encode()),
-            Bound::Excluded(i) => Bound::Excluded(i.encode()),
+            Bound::Included(i) => Bound::Included(encode_key_by::<C, K>(i)),
+            Bound::Excluded(i) => Bound::Excluded(encode_key_by::<C, K>(i)),
             _ => Bound::Unbounded,
         };
 
@@ -136,12 +277,12 @@ where
 
     #[inline(always)]
     pub fn contains_key(&self, key: &K) -> bool {
-        self.inner.contains_key(&key.encode())
+        self.inner.contains_key(&encode_key_by::<C, K>(key))
     }
 
     #[inline(always)]
     pub fn remove(&self, key: &K) -> Result<Option<V>> {
-        self.inner.remove(&key.encode()).c(d!())
+        self.inner.remove(&encode_key_by::<C, K>(key)).c(d!())
     }
 
     #[inline(always)]
@@ -149,23 +290,50 @@ where
         self.inner.clear();
     }
 
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+
     #[inline(always)]
     pub fn get_by_branch(&self, key: &K, branch_name: BranchName) -> Option<V> {
-        self.inner.get_by_branch(&key.encode(), branch_name)
+        self.inner
+            .get_by_branch(&encode_key_by::<C, K>(key), branch_name)
+    }
+
+    #[inline(always)]
+    pub fn key_blame(&self, key: &K, branch_name: BranchName) -> Option<KeyBlame> {
+        self.inner
+            .key_blame(&encode_key_by::<C, K>(key), branch_name)
+    }
+
+    #[inline(always)]
+    pub fn key_history(
+        &self,
+        key: &K,
+        branch_name: BranchName,
+    ) -> Vec<(Vec<u8>, Option<V>)> {
+        self.inner
+            .key_history(&encode_key_by::<C, K>(key), branch_name)
     }
 
     #[inline(always)]
     pub fn get_le_by_branch(&self, key: &K, branch_name: BranchName) -> Option<(K, V)> {
         self.inner
-            .get_le_by_branch(&key.encode(), branch_name)
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .get_le_by_branch(&encode_key_by::<C, K>(key), branch_name)
+            .and_then(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge_by_branch(&self, key: &K, branch_name: BranchName) -> Option<(K, V)> {
         self.inner
-            .get_ge_by_branch(&key.encode(), branch_name)
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .get_ge_by_branch(&encode_key_by::<C, K>(key), branch_name)
+            .and_then(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -196,12 +364,12 @@ where
         branch_name: BranchName,
     ) -> Result<Option<V>> {
         self.inner
-            .insert_ref_by_branch(&key.encode(), value, branch_name)
+            .insert_ref_by_branch(&encode_key_by::<C, K>(key), value, branch_name)
             .c(d!())
     }
 
     #[inline(always)]
-    pub fn iter_by_branch(&self, branch_name: BranchName) -> MapxVsIter<K, V> {
+    pub fn iter_by_branch(&self, branch_name: BranchName) -> MapxVsIter<K, V, C> {
         MapxVsIter {
             iter: self.inner.iter_by_branch(branch_name),
             pk: PhantomData,
@@ -213,15 +381,15 @@ where
         &'a self,
         bounds: R,
         branch_name: BranchName,
-    ) -> MapxVsIter<'a, K, V> {
+    ) -> MapxVsIter<'a, K, V, C> {
         let l = match bounds.start_bound() {
-            Bound::Included(i) => Bound::Included(i.encode()),
-            Bound::Excluded(i) => Bound::Excluded(i.encode()),
+            Bound::Included(i) => Bound::Included(encode_key_by::<C, K>(i)),
+            Bound::Excluded(i) => Bound::Excluded(encode_key_by::<C, K>(i)),
             _ => Bound::Unbounded,
         };
         let h = match bounds.end_bound() {
-            Bound::Included(i) => Bound::Included(i.encode()),
-            Bound::Excluded(i) => Bound::Excluded(i.encode()),
+            Bound::Included(i) => Bound::Included(encode_key_by::<C, K>(i)),
+            Bound::Excluded(i) => Bound::Excluded(encode_key_by::<C, K>(i)),
             _ => Bound::Unbounded,
         };
 
@@ -244,7 +412,7 @@ where
     #[inline(always)]
     pub fn contains_key_by_branch(&self, key: &K, branch_name: BranchName) -> bool {
         self.inner
-            .contains_key_by_branch(&key.encode(), branch_name)
+            .contains_key_by_branch(&encode_key_by::<C, K>(key), branch_name)
     }
 
     #[inline(always)]
@@ -254,7 +422,7 @@ where
         branch_name: BranchName,
     ) -> Result<Option<V>> {
         self.inner
-            .remove_by_branch(&key.encode(), branch_name)
+            .remove_by_branch(&encode_key_by::<C, K>(key), branch_name)
             .c(d!())
     }
 
@@ -265,8 +433,11 @@ where
         branch_name: BranchName,
         version_name: VersionName,
     ) -> Option<V> {
-        self.inner
-            .get_by_branch_version(&key.encode(), branch_name, version_name)
+        self.inner.get_by_branch_version(
+            &encode_key_by::<C, K>(key),
+            branch_name,
+            version_name,
+        )
     }
 
     #[inline(always)]
@@ -277,8 +448,12 @@ where
         version_name: VersionName,
     ) -> Option<(K, V)> {
         self.inner
-            .get_le_by_branch_version(&key.encode(), branch_name, version_name)
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .get_le_by_branch_version(
+                &encode_key_by::<C, K>(key),
+                branch_name,
+                version_name,
+            )
+            .and_then(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -289,8 +464,12 @@ where
         version_name: VersionName,
     ) -> Option<(K, V)> {
         self.inner
-            .get_ge_by_branch_version(&key.encode(), branch_name, version_name)
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .get_ge_by_branch_version(
+                &encode_key_by::<C, K>(key),
+                branch_name,
+                version_name,
+            )
+            .and_then(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -317,7 +496,7 @@ where
         &self,
         branch_name: BranchName,
         version_name: VersionName,
-    ) -> MapxVsIter<K, V> {
+    ) -> MapxVsIter<K, V, C> {
         MapxVsIter {
             iter: self.inner.iter_by_branch_version(branch_name, version_name),
             pk: PhantomData,
@@ -330,15 +509,15 @@ where
         bounds: R,
         branch_name: BranchName,
         version_name: VersionName,
-    ) -> MapxVsIter<'a, K, V> {
+    ) -> MapxVsIter<'a, K, V, C> {
         let l = match bounds.start_bound() {
-            Bound::Included(i) => Bound::Included(i.encode()),
-            Bound::Excluded(i) => Bound::Excluded(i.encode()),
+            Bound::Included(i) => Bound::Included(encode_key_by::<C, K>(i)),
+            Bound::Excluded(i) => Bound::Excluded(encode_key_by::<C, K>(i)),
             _ => Bound::Unbounded,
         };
         let h = match bounds.end_bound() {
-            Bound::Included(i) => Bound::Included(i.encode()),
-            Bound::Excluded(i) => Bound::Excluded(i.encode()),
+            Bound::Included(i) => Bound::Included(encode_key_by::<C, K>(i)),
+            Bound::Excluded(i) => Bound::Excluded(encode_key_by::<C, K>(i)),
             _ => Bound::Unbounded,
         };
 
@@ -378,97 +557,493 @@ where
         version_name: VersionName,
     ) -> bool {
         self.inner.contains_key_by_branch_version(
-            &key.encode(),
+            &encode_key_by::<C, K>(key),
             branch_name,
             version_name,
         )
     }
+
+    #[inline(always)]
+    pub fn contains_all(&self, keys: &[K]) -> bool {
+        let keys = keys
+            .iter()
+            .map(|k| encode_key_by::<C, K>(k))
+            .collect::<Vec<_>>();
+        self.inner.contains_all(&keys)
+    }
+
+    #[inline(always)]
+    pub fn contains_all_by_branch(&self, keys: &[K], branch_name: BranchName) -> bool {
+        let keys = keys
+            .iter()
+            .map(|k| encode_key_by::<C, K>(k))
+            .collect::<Vec<_>>();
+        self.inner.contains_all_by_branch(&keys, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn contains_all_by_branch_version(
+        &self,
+        keys: &[K],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> bool {
+        let keys = keys
+            .iter()
+            .map(|k| encode_key_by::<C, K>(k))
+            .collect::<Vec<_>>();
+        self.inner
+            .contains_all_by_branch_version(&keys, branch_name, version_name)
+    }
+
+    #[inline(always)]
+    pub fn get_many_by_branch_version(
+        &self,
+        keys: &[K],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Vec<Option<V>> {
+        let keys = keys
+            .iter()
+            .map(|k| encode_key_by::<C, K>(k))
+            .collect::<Vec<_>>();
+        self.inner
+            .get_many_by_branch_version(&keys, branch_name, version_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_diff(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Vec<(K, Diff<V>)>> {
+        self.inner.branch_diff(branch_a, branch_b).c(d!()).map(|d| {
+            d.into_iter()
+                .filter_map(|(k, d)| {
+                    decode_key_by_policy_with::<C, K>(&k).map(|k| (k, d))
+                })
+                .collect()
+        })
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_conflicts(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<MergeConflict<K, V>>> {
+        self.inner
+            .branch_merge_conflicts(branch_name)
+            .c(d!())
+            .map(|cs| {
+                cs.into_iter()
+                    .filter_map(|c| {
+                        decode_key_by_policy_with::<C, K>(&c.key).map(|key| {
+                            MergeConflict {
+                                key,
+                                ours: c.ours,
+                                theirs: c.theirs,
+                            }
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_to_parent_with_resolver(
+        &self,
+        branch_name: BranchName,
+        mut resolver: impl FnMut(&K, Option<&V>, Option<&V>) -> MergeResolution<V>,
+    ) -> Result<()> {
+        self.inner
+            .branch_merge_to_parent_with_resolver(branch_name, |k, ours, theirs| {
+                // keys that can not be decoded keep the values of the merged branch
+                decode_key_by_policy_with::<C, K>(k)
+                    .map_or(MergeResolution::Theirs, |k| resolver(&k, ours, theirs))
+            })
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_3way(
+        &self,
+        ours: BranchName,
+        theirs: BranchName,
+        base_version: VersionName,
+    ) -> Result<Vec<MergeConflict<K, V>>> {
+        self.inner
+            .branch_merge_3way(ours, theirs, base_version)
+            .c(d!())
+            .map(|cs| {
+                cs.into_iter()
+                    .filter_map(|c| {
+                        decode_key_by_policy_with::<C, K>(&c.key).map(|key| {
+                            MergeConflict {
+                                key,
+                                ours: c.ours,
+                                theirs: c.theirs,
+                            }
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    #[inline(always)]
+    pub fn branch_rebase(
+        &self,
+        branch_name: BranchName,
+        new_parent_branch_name: ParentBranchName,
+        new_parent_version_name: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .branch_rebase(branch_name, new_parent_branch_name, new_parent_version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner.export_versions(bounds).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions_by_branch<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_name: BranchName,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner
+            .export_versions_by_branch(branch_name, bounds)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_patch(
+        &self,
+        branch_name: BranchName,
+        from_version: VersionName,
+        to_version: VersionName,
+    ) -> Result<Vec<u8>> {
+        self.inner
+            .export_patch(branch_name, from_version, to_version)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn apply_patch(&self, branch_name: BranchName, patch: &[u8]) -> Result<()> {
+        self.inner.apply_patch(branch_name, patch).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_from_bundle(
+        &self,
+        branch_name: BranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_from_bundle(branch_name, bundle)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_by_base_branch_from_bundle(
+        &self,
+        branch_name: BranchName,
+        base_branch_name: ParentBranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_by_base_branch_from_bundle(
+                branch_name,
+                base_branch_name,
+                bundle,
+            )
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_is_ancestor(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<bool> {
+        self.inner.branch_is_ancestor(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_base(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.inner.branch_merge_base(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_checksum(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<[u8; 32]> {
+        self.inner
+            .version_checksum(branch_name, version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list(&self) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list_by_branch(branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta(&self, version_name: VersionName) -> Option<VersionMeta> {
+        self.inner.version_get_meta(version_name)
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<VersionMeta> {
+        self.inner
+            .version_get_meta_by_branch(version_name, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_is_frozen(&self, branch_name: BranchName) -> bool {
+        self.inner.branch_is_frozen(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_depth(&self, branch_name: BranchName) -> Option<usize> {
+        self.inner.branch_depth(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_export_shallow(
+        &self,
+        branch_name: BranchName,
+        target_dir: &str,
+    ) -> Result<()> {
+        self.inner
+            .branch_export_shallow(branch_name, target_dir)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn import_shallow(src_dir: &str) -> Result<Self> {
+        Ok(Self {
+            inner: MapxOrdRawKeyVs::import_shallow(src_dir).c(d!())?,
+            pk: PhantomData,
+        })
+    }
 }
 
-impl<K, V> VsMgmt for MapxVs<K, V>
+impl<K, V, C> VsMgmt for MapxVs<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     crate::impl_vs_methods!();
 }
 
-pub struct MapxVsIter<'a, K, V>
+impl<K, V, C> VsComplete for MapxVs<K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
-    iter: MapxOrdRawKeyVsIter<'a, V>,
+}
+
+impl<K, V, C> VsNested for MapxVs<K, V, C>
+where
+    K: KeyEnDe,
+    V: ValueEnDe + VsComplete,
+    C: Codec,
+{
+}
+
+impl<'a, K, V, C> SnapshotHandle<'a, MapxVs<K, V, C>>
+where
+    K: KeyEnDe,
+    V: ValueEnDe,
+    C: Codec,
+{
+    #[inline(always)]
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.inner()
+            .get_by_branch_version(key, self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn get_le(&self, key: &K) -> Option<(K, V)> {
+        self.inner().get_le_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn get_ge(&self, key: &K) -> Option<(K, V)> {
+        self.inner().get_ge_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner()
+            .len_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner()
+            .is_empty_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> MapxVsIter<'a, K, V, C> {
+        self.inner()
+            .iter_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn to_plain(&self) -> Vec<(K, V)> {
+        self.iter().collect()
+    }
+
+    #[inline(always)]
+    pub fn range<R: 'a + RangeBounds<K>>(&self, bounds: R) -> MapxVsIter<'a, K, V, C> {
+        self.inner().range_by_branch_version(
+            bounds,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn first(&self) -> Option<(K, V)> {
+        self.inner()
+            .first_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn last(&self) -> Option<(K, V)> {
+        self.inner()
+            .last_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.inner().contains_key_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+}
+
+pub struct MapxVsIter<'a, K, V, C = DefaultCodec>
+where
+    K: KeyEnDe,
+    V: ValueEnDe,
+    C: Codec,
+{
+    iter: MapxOrdRawKeyVsIter<'a, V, C>,
     pk: PhantomData<K>,
 }
 
-impl<'a, K, V> Iterator for MapxVsIter<'a, K, V>
+impl<'a, K, V, C> Iterator for MapxVsIter<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     type Item = (K, V);
     fn next(&mut self) -> Option<Self::Item> {
         self.iter
-            .next()
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .find_map(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 }
 
-impl<'a, K, V> DoubleEndedIterator for MapxVsIter<'a, K, V>
+impl<'a, K, V, C> DoubleEndedIterator for MapxVsIter<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter
-            .next_back()
-            .map(|(k, v)| (pnk!(<K as KeyEnDe>::decode(&k)), v))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| decode_key_by_policy_with::<C, K>(&k).map(|k| (k, v)))
     }
 }
 
-impl<'a, K, V> ExactSizeIterator for MapxVsIter<'a, K, V>
+impl<'a, K, V, C> ExactSizeIterator for MapxVsIter<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
 }
 
 #[derive(PartialEq, Eq, Debug)]
-pub struct ValueMut<'a, K, V>
+pub struct ValueMut<'a, K, V, C = DefaultCodec>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
-    hdr: &'a MapxVs<K, V>,
+    hdr: &'a MapxVs<K, V, C>,
     key: &'a K,
     value: V,
 }
 
-impl<'a, K, V> ValueMut<'a, K, V>
+impl<'a, K, V, C> ValueMut<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
-    fn new(hdr: &'a MapxVs<K, V>, key: &'a K, value: V) -> Self {
+    fn new(hdr: &'a MapxVs<K, V, C>, key: &'a K, value: V) -> Self {
         ValueMut { hdr, key, value }
     }
 }
 
-impl<'a, K, V> Drop for ValueMut<'a, K, V>
+impl<'a, K, V, C> Drop for ValueMut<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn drop(&mut self) {
         pnk!(self.hdr.insert_ref(self.key, &self.value));
     }
 }
 
-impl<'a, K, V> Deref for ValueMut<'a, K, V>
+impl<'a, K, V, C> Deref for ValueMut<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     type Target = V;
     fn deref(&self) -> &Self::Target {
@@ -476,32 +1051,35 @@ where
     }
 }
 
-impl<'a, K, V> DerefMut for ValueMut<'a, K, V>
+impl<'a, K, V, C> DerefMut for ValueMut<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.value
     }
 }
 
-pub struct Entry<'a, K, V>
+pub struct Entry<'a, K, V, C = DefaultCodec>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
     key: &'a K,
-    hdr: &'a MapxVs<K, V>,
+    hdr: &'a MapxVs<K, V, C>,
 }
 
-impl<'a, K, V> Entry<'a, K, V>
+impl<'a, K, V, C> Entry<'a, K, V, C>
 where
     K: KeyEnDe,
     V: ValueEnDe,
+    C: Codec,
 {
-    pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, K, V> {
-        if !self.hdr.contains_key(self.key) {
+    pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, K, V, C> {
+        if self.hdr.get_head(self.key).is_none() {
             pnk!(self.hdr.insert_ref(self.key, default));
         }
         pnk!(self.hdr.get_mut(self.key))
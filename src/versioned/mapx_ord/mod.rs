@@ -3,9 +3,19 @@
 //!
 
 use crate::{
-    common::ende::{KeyEnDeOrdered, ValueEnDe},
-    versioned::mapx_ord_rawkey::{MapxOrdRawKeyVs, MapxOrdRawKeyVsIter},
-    BranchName, ParentBranchName, VersionName, VsMgmt,
+    common::{
+        cursor::Cursor,
+        ende::{key_from_bytes_by_policy, KeyEnDeOrdered, ValueEnDe},
+        named,
+        stats::SizeHistogram,
+        RawKey,
+    },
+    versioned::{
+        mapx_ord_rawkey::{MapxOrdRawKeyVs, MapxOrdRawKeyVsIter},
+        BranchInfo, Diff, KeyBlame, MergeConflict, MergeResolution, PrunePolicy,
+        SnapshotHandle, VersionBundle, VersionInfo, VersionMeta,
+    },
+    BranchName, ParentBranchName, VersionName, VsComplete, VsMgmt, VsNested,
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
@@ -45,6 +55,13 @@ where
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
     #[inline(always)]
     pub fn get(&self, key: &K) -> Option<V> {
         self.inner.get(&key.to_bytes())
@@ -52,7 +69,13 @@ where
 
     #[inline(always)]
     pub fn get_mut<'a>(&'a self, key: &'a K) -> Option<ValueMut<'a, K, V>> {
-        self.get(key).map(move |v| ValueMut::new(self, key, v))
+        self.get_head(key).map(move |v| ValueMut::new(self, key, v))
+    }
+
+    // Get in the view of the writer, for read-modify-write paths.
+    #[inline(always)]
+    fn get_head(&self, key: &K) -> Option<V> {
+        self.inner.get_head(&key.to_bytes())
     }
 
     #[inline(always)]
@@ -64,14 +87,14 @@ where
     pub fn get_le(&self, key: &K) -> Option<(K, V)> {
         self.inner
             .get_le(&key.to_bytes())
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge(&self, key: &K) -> Option<(K, V)> {
         self.inner
             .get_ge(&key.to_bytes())
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -102,6 +125,27 @@ where
         }
     }
 
+    #[inline(always)]
+    pub fn iter_page(
+        &self,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<(K, V)>, Option<Cursor>) {
+        let (kvs, next) = self.inner.iter_page(cursor, limit);
+        (decode_page(kvs), next)
+    }
+
+    #[inline(always)]
+    pub fn iter_page_by_branch(
+        &self,
+        branch_name: BranchName,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<(K, V)>, Option<Cursor>) {
+        let (kvs, next) = self.inner.iter_page_by_branch(branch_name, cursor, limit);
+        (decode_page(kvs), next)
+    }
+
     #[inline(always)]
     pub fn range<'a, R: 'a + RangeBounds<K>>(
         &'a self,
@@ -149,23 +193,47 @@ where
         self.inner.clear();
     }
 
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+
     #[inline(always)]
     pub fn get_by_branch(&self, key: &K, branch_name: BranchName) -> Option<V> {
         self.inner.get_by_branch(&key.to_bytes(), branch_name)
     }
 
+    #[inline(always)]
+    pub fn key_blame(&self, key: &K, branch_name: BranchName) -> Option<KeyBlame> {
+        self.inner.key_blame(&key.to_bytes(), branch_name)
+    }
+
+    #[inline(always)]
+    pub fn key_history(
+        &self,
+        key: &K,
+        branch_name: BranchName,
+    ) -> Vec<(Vec<u8>, Option<V>)> {
+        self.inner.key_history(&key.to_bytes(), branch_name)
+    }
+
     #[inline(always)]
     pub fn get_le_by_branch(&self, key: &K, branch_name: BranchName) -> Option<(K, V)> {
         self.inner
             .get_le_by_branch(&key.to_bytes(), branch_name)
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge_by_branch(&self, key: &K, branch_name: BranchName) -> Option<(K, V)> {
         self.inner
             .get_ge_by_branch(&key.to_bytes(), branch_name)
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -269,6 +337,42 @@ where
             .get_by_branch_version(&key.to_bytes(), branch_name, version_name)
     }
 
+    #[inline(always)]
+    pub fn min_changed_key_since(&self, version_name: VersionName) -> Option<K> {
+        self.inner
+            .min_changed_key_since(version_name)
+            .and_then(key_from_bytes_by_policy)
+    }
+
+    #[inline(always)]
+    pub fn max_changed_key_since(&self, version_name: VersionName) -> Option<K> {
+        self.inner
+            .max_changed_key_since(version_name)
+            .and_then(key_from_bytes_by_policy)
+    }
+
+    #[inline(always)]
+    pub fn min_changed_key_since_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<K> {
+        self.inner
+            .min_changed_key_since_by_branch(version_name, branch_name)
+            .and_then(key_from_bytes_by_policy)
+    }
+
+    #[inline(always)]
+    pub fn max_changed_key_since_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<K> {
+        self.inner
+            .max_changed_key_since_by_branch(version_name, branch_name)
+            .and_then(key_from_bytes_by_policy)
+    }
+
     #[inline(always)]
     pub fn get_le_by_branch_version(
         &self,
@@ -278,7 +382,7 @@ where
     ) -> Option<(K, V)> {
         self.inner
             .get_le_by_branch_version(&key.to_bytes(), branch_name, version_name)
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -290,7 +394,7 @@ where
     ) -> Option<(K, V)> {
         self.inner
             .get_ge_by_branch_version(&key.to_bytes(), branch_name, version_name)
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .and_then(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 
     #[inline(always)]
@@ -383,6 +487,275 @@ where
             version_name,
         )
     }
+
+    #[inline(always)]
+    pub fn contains_all(&self, keys: &[K]) -> bool {
+        let keys = keys.iter().map(|k| k.to_bytes()).collect::<Vec<_>>();
+        self.inner.contains_all(&keys)
+    }
+
+    #[inline(always)]
+    pub fn contains_all_by_branch(&self, keys: &[K], branch_name: BranchName) -> bool {
+        let keys = keys.iter().map(|k| k.to_bytes()).collect::<Vec<_>>();
+        self.inner.contains_all_by_branch(&keys, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn contains_all_by_branch_version(
+        &self,
+        keys: &[K],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> bool {
+        let keys = keys.iter().map(|k| k.to_bytes()).collect::<Vec<_>>();
+        self.inner
+            .contains_all_by_branch_version(&keys, branch_name, version_name)
+    }
+
+    #[inline(always)]
+    pub fn get_many_by_branch_version(
+        &self,
+        keys: &[K],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Vec<Option<V>> {
+        let keys = keys.iter().map(|k| k.to_bytes()).collect::<Vec<_>>();
+        self.inner
+            .get_many_by_branch_version(&keys, branch_name, version_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_diff(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Vec<(K, Diff<V>)>> {
+        self.inner.branch_diff(branch_a, branch_b).c(d!()).map(|d| {
+            d.into_iter()
+                .filter_map(|(k, d)| key_from_bytes_by_policy(k).map(|k| (k, d)))
+                .collect()
+        })
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_conflicts(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<MergeConflict<K, V>>> {
+        self.inner
+            .branch_merge_conflicts(branch_name)
+            .c(d!())
+            .map(|cs| {
+                cs.into_iter()
+                    .filter_map(|c| {
+                        key_from_bytes_by_policy(c.key).map(|key| MergeConflict {
+                            key,
+                            ours: c.ours,
+                            theirs: c.theirs,
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_to_parent_with_resolver(
+        &self,
+        branch_name: BranchName,
+        mut resolver: impl FnMut(&K, Option<&V>, Option<&V>) -> MergeResolution<V>,
+    ) -> Result<()> {
+        self.inner
+            .branch_merge_to_parent_with_resolver(branch_name, |k, ours, theirs| {
+                // keys that can not be decoded keep the values of the merged branch
+                key_from_bytes_by_policy(k.into())
+                    .map_or(MergeResolution::Theirs, |k| resolver(&k, ours, theirs))
+            })
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_3way(
+        &self,
+        ours: BranchName,
+        theirs: BranchName,
+        base_version: VersionName,
+    ) -> Result<Vec<MergeConflict<K, V>>> {
+        self.inner
+            .branch_merge_3way(ours, theirs, base_version)
+            .c(d!())
+            .map(|cs| {
+                cs.into_iter()
+                    .filter_map(|c| {
+                        key_from_bytes_by_policy(c.key).map(|key| MergeConflict {
+                            key,
+                            ours: c.ours,
+                            theirs: c.theirs,
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    #[inline(always)]
+    pub fn branch_rebase(
+        &self,
+        branch_name: BranchName,
+        new_parent_branch_name: ParentBranchName,
+        new_parent_version_name: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .branch_rebase(branch_name, new_parent_branch_name, new_parent_version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner.export_versions(bounds).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions_by_branch<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_name: BranchName,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner
+            .export_versions_by_branch(branch_name, bounds)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_patch(
+        &self,
+        branch_name: BranchName,
+        from_version: VersionName,
+        to_version: VersionName,
+    ) -> Result<Vec<u8>> {
+        self.inner
+            .export_patch(branch_name, from_version, to_version)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn apply_patch(&self, branch_name: BranchName, patch: &[u8]) -> Result<()> {
+        self.inner.apply_patch(branch_name, patch).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_from_bundle(
+        &self,
+        branch_name: BranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_from_bundle(branch_name, bundle)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_by_base_branch_from_bundle(
+        &self,
+        branch_name: BranchName,
+        base_branch_name: ParentBranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_by_base_branch_from_bundle(
+                branch_name,
+                base_branch_name,
+                bundle,
+            )
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_is_ancestor(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<bool> {
+        self.inner.branch_is_ancestor(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_base(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.inner.branch_merge_base(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_checksum(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<[u8; 32]> {
+        self.inner
+            .version_checksum(branch_name, version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list(&self) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list_by_branch(branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta(&self, version_name: VersionName) -> Option<VersionMeta> {
+        self.inner.version_get_meta(version_name)
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<VersionMeta> {
+        self.inner
+            .version_get_meta_by_branch(version_name, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_is_frozen(&self, branch_name: BranchName) -> bool {
+        self.inner.branch_is_frozen(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_depth(&self, branch_name: BranchName) -> Option<usize> {
+        self.inner.branch_depth(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_export_shallow(
+        &self,
+        branch_name: BranchName,
+        target_dir: &str,
+    ) -> Result<()> {
+        self.inner
+            .branch_export_shallow(branch_name, target_dir)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn import_shallow(src_dir: &str) -> Result<Self> {
+        Ok(Self {
+            inner: MapxOrdRawKeyVs::import_shallow(src_dir).c(d!())?,
+            pk: PhantomData,
+        })
+    }
 }
 
 impl<K, V> VsMgmt for MapxOrdVs<K, V>
@@ -393,6 +766,110 @@ where
     crate::impl_vs_methods!();
 }
 
+impl<K, V> VsComplete for MapxOrdVs<K, V>
+where
+    K: KeyEnDeOrdered,
+    V: ValueEnDe,
+{
+}
+
+impl<K, V> VsNested for MapxOrdVs<K, V>
+where
+    K: KeyEnDeOrdered,
+    V: ValueEnDe + VsComplete,
+{
+}
+
+impl<'a, K, V> SnapshotHandle<'a, MapxOrdVs<K, V>>
+where
+    K: KeyEnDeOrdered,
+    V: ValueEnDe,
+{
+    #[inline(always)]
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.inner()
+            .get_by_branch_version(key, self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn get_le(&self, key: &K) -> Option<(K, V)> {
+        self.inner().get_le_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn get_ge(&self, key: &K) -> Option<(K, V)> {
+        self.inner().get_ge_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner()
+            .len_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner()
+            .is_empty_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> MapxOrdVsIter<'a, K, V> {
+        self.inner()
+            .iter_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn to_plain(&self) -> Vec<(K, V)> {
+        self.iter().collect()
+    }
+
+    #[inline(always)]
+    pub fn range<R: 'a + RangeBounds<K>>(&self, bounds: R) -> MapxOrdVsIter<'a, K, V> {
+        self.inner().range_by_branch_version(
+            self.branch_name(),
+            self.version_name(),
+            bounds,
+        )
+    }
+
+    #[inline(always)]
+    pub fn first(&self) -> Option<(K, V)> {
+        self.inner()
+            .first_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn last(&self) -> Option<(K, V)> {
+        self.inner()
+            .last_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.inner().contains_key_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+}
+
+// Keys that can not be decoded are handled by the codec failure policy.
+fn decode_page<K: KeyEnDeOrdered, V>(kvs: Vec<(RawKey, V)>) -> Vec<(K, V)> {
+    kvs.into_iter()
+        .filter_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
+        .collect()
+}
+
 pub struct MapxOrdVsIter<'a, K, V>
 where
     K: KeyEnDeOrdered,
@@ -409,7 +886,8 @@ where
 {
     type Item = (K, V);
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+        self.iter
+            .find_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 }
 
@@ -420,8 +898,9 @@ where
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter
-            .next_back()
-            .map(|(k, v)| (pnk!(K::from_bytes(k)), v))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| key_from_bytes_by_policy(k).map(|k| (k, v)))
     }
 }
 
@@ -499,7 +978,7 @@ where
     V: ValueEnDe,
 {
     pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, K, V> {
-        if !self.hdr.contains_key(self.key) {
+        if self.hdr.get_head(self.key).is_none() {
             pnk!(self.hdr.insert_ref(self.key, default));
         }
         pnk!(self.hdr.get_mut(self.key))
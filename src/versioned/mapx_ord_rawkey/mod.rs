@@ -3,40 +3,86 @@
 //!
 
 use crate::{
-    common::{ende::ValueEnDe, BranchName, ParentBranchName, RawKey, VersionName},
-    versioned::mapx_raw::{MapxRawVs, MapxRawVsIter},
-    VsMgmt,
+    common::{
+        cursor::{self, Cursor},
+        ende::{
+            decode_value_by_policy_with, encode_value_by, Codec, DefaultCodec, ValueEnDe,
+        },
+        named,
+        stats::SizeHistogram,
+        BranchName, ParentBranchName, RawKey, RawValue, VersionName,
+    },
+    merkle::sparse::{Hash32, SparseMerkleProof},
+    versioned::{
+        mapx_raw::{MapxRawVs, MapxRawVsIter},
+        BranchInfo, Diff, KeyBlame, MergeConflict, MergeResolution, PrunePolicy,
+        SnapshotHandle, VersionBundle, VersionInfo, VersionMeta,
+    },
+    VsComplete, VsMgmt, VsNested,
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
 use std::{
     marker::PhantomData,
-    ops::{Deref, DerefMut, RangeBounds},
+    ops::{Bound, Deref, DerefMut, RangeBounds},
 };
 
 /// Documents => [MapxRawVs](crate::versioned::mapx_raw::MapxRawVs)
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
 #[serde(bound = "")]
-pub struct MapxOrdRawKeyVs<V> {
+pub struct MapxOrdRawKeyVs<V, C = DefaultCodec> {
     inner: MapxRawVs,
-    p: PhantomData<V>,
+    p: PhantomData<(V, C)>,
 }
 
-impl<V> Default for MapxOrdRawKeyVs<V>
+impl<V, C> Default for MapxOrdRawKeyVs<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn default() -> Self {
-        Self::new()
+        Self::with_codec()
     }
 }
 
+// Constructors of the default codec,
+// use `with_codec` to create an instance of another codec.
 impl<V> MapxOrdRawKeyVs<V>
 where
     V: ValueEnDe,
 {
     #[inline(always)]
     pub fn new() -> Self {
+        Self::with_codec()
+    }
+
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    /// Create an instance that keeps the Merkle root of every version,
+    /// see [MapxRawVs::new_with_roots](crate::versioned::mapx_raw::MapxRawVs::new_with_roots).
+    #[inline(always)]
+    pub fn new_with_roots() -> Self {
+        MapxOrdRawKeyVs {
+            inner: MapxRawVs::new_with_roots(),
+            p: PhantomData,
+        }
+    }
+}
+
+impl<V, C> MapxOrdRawKeyVs<V, C>
+where
+    V: ValueEnDe,
+    C: Codec,
+{
+    /// Create an instance whose values are encoded by the codec `C`,
+    /// e.g. `MapxOrdRawKeyVs::<V, BcsCodec>::with_codec()`.
+    #[inline(always)]
+    pub fn with_codec() -> Self {
         MapxOrdRawKeyVs {
             inner: MapxRawVs::new(),
             p: PhantomData,
@@ -47,16 +93,35 @@ where
     pub fn get(&self, key: &[u8]) -> Option<V> {
         self.inner
             .get(key)
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .and_then(|v| decode_value_by_policy_with::<C, V>(&v))
+    }
+
+    #[inline(always)]
+    pub fn get_mut<'a>(&'a self, key: &'a [u8]) -> Option<ValueMut<'_, V, C>> {
+        self.get_head(key).map(move |v| ValueMut::new(self, key, v))
+    }
+
+    // Get in the view of the writer, for read-modify-write paths.
+    #[inline(always)]
+    pub(crate) fn get_head(&self, key: &[u8]) -> Option<V> {
+        self.inner
+            .get_head(key)
+            .and_then(|v| decode_value_by_policy_with::<C, V>(&v))
     }
 
     #[inline(always)]
-    pub fn get_mut<'a>(&'a self, key: &'a [u8]) -> Option<ValueMut<'_, V>> {
-        self.get(key).map(move |v| ValueMut::new(self, key, v))
+    pub(crate) fn get_head_by_branch(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+    ) -> Option<V> {
+        self.inner
+            .get_head_by_branch(key, branch_name)
+            .and_then(|v| decode_value_by_policy_with::<C, V>(&v))
     }
 
     #[inline(always)]
-    pub fn entry_ref<'a>(&'a self, key: &'a [u8]) -> Entry<'a, V> {
+    pub fn entry_ref<'a>(&'a self, key: &'a [u8]) -> Entry<'a, V, C> {
         Entry { key, hdr: self }
     }
 
@@ -64,7 +129,27 @@ where
     pub fn get_by_branch(&self, key: &[u8], branch_name: BranchName) -> Option<V> {
         self.inner
             .get_by_branch(key, branch_name)
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .and_then(|v| decode_value_by_policy_with::<C, V>(&v))
+    }
+
+    #[inline(always)]
+    pub fn key_blame(&self, key: &[u8], branch_name: BranchName) -> Option<KeyBlame> {
+        self.inner.key_blame(key, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn key_history(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+    ) -> Vec<(Vec<u8>, Option<V>)> {
+        self.inner
+            .key_history(key, branch_name)
+            .into_iter()
+            .map(|(ver, v)| {
+                (ver, v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)))
+            })
+            .collect()
     }
 
     #[inline(always)]
@@ -76,14 +161,14 @@ where
     ) -> Option<V> {
         self.inner
             .get_by_branch_version(key, branch_name, version_name)
-            .map(|v| <V as ValueEnDe>::decode(&v).unwrap())
+            .and_then(|v| decode_value_by_policy_with::<C, V>(&v))
     }
 
     #[inline(always)]
     pub fn get_le(&self, key: &[u8]) -> Option<(RawKey, V)> {
         self.inner
             .get_le(key)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 
     #[inline(always)]
@@ -94,7 +179,37 @@ where
     ) -> Option<(RawKey, V)> {
         self.inner
             .get_le_by_branch(key, branch_name)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
+    }
+
+    #[inline(always)]
+    pub fn min_changed_key_since(&self, version_name: VersionName) -> Option<RawKey> {
+        self.inner.min_changed_key_since(version_name)
+    }
+
+    #[inline(always)]
+    pub fn max_changed_key_since(&self, version_name: VersionName) -> Option<RawKey> {
+        self.inner.max_changed_key_since(version_name)
+    }
+
+    #[inline(always)]
+    pub fn min_changed_key_since_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<RawKey> {
+        self.inner
+            .min_changed_key_since_by_branch(version_name, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn max_changed_key_since_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<RawKey> {
+        self.inner
+            .max_changed_key_since_by_branch(version_name, branch_name)
     }
 
     #[inline(always)]
@@ -106,14 +221,14 @@ where
     ) -> Option<(RawKey, V)> {
         self.inner
             .get_le_by_branch_version(key, branch_name, version_name)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 
     #[inline(always)]
     pub fn get_ge(&self, key: &[u8]) -> Option<(RawKey, V)> {
         self.inner
             .get_ge(key)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 
     #[inline(always)]
@@ -124,7 +239,7 @@ where
     ) -> Option<(RawKey, V)> {
         self.inner
             .get_ge_by_branch(key, branch_name)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 
     #[inline(always)]
@@ -136,7 +251,7 @@ where
     ) -> Option<(RawKey, V)> {
         self.inner
             .get_ge_by_branch_version(key, branch_name, version_name)
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .and_then(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 
     #[inline(always)]
@@ -196,8 +311,80 @@ where
     #[inline(always)]
     pub fn insert_ref(&self, key: &[u8], value: &V) -> Result<Option<V>> {
         self.inner
-            .insert(key, &value.encode())
-            .map(|v| v.map(|v| <V as ValueEnDe>::decode(&v).unwrap()))
+            .insert(key, &encode_value_by::<C, V>(value))
+            .map(|v| v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)))
+    }
+
+    /// Get the encoded bytes of a value, the codec is bypassed.
+    #[inline(always)]
+    pub fn get_raw(&self, key: &[u8]) -> Option<RawValue> {
+        self.inner.get(key)
+    }
+
+    /// Insert the encoded bytes of a value, the codec is bypassed,
+    /// it's your duty to ensure that they can be decoded by the codec `C`.
+    #[inline(always)]
+    pub fn insert_raw(&self, key: &[u8], value: &[u8]) -> Result<Option<RawValue>> {
+        self.inner.insert(key, value).c(d!())
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw(&self) -> &MapxRawVs {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn roots_enabled(&self) -> bool {
+        self.inner.roots_enabled()
+    }
+
+    /// The Merkle root of the head state of the default branch,
+    /// keys and values are committed in their encoded forms.
+    #[inline(always)]
+    pub fn root(&self) -> Result<Hash32> {
+        self.inner.root().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn root_by_branch(&self, branch_name: BranchName) -> Result<Hash32> {
+        self.inner.root_by_branch(branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn root_by_branch_version(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<Hash32> {
+        self.inner
+            .root_by_branch_version(branch_name, version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove(&self, key: &[u8]) -> Result<SparseMerkleProof> {
+        self.inner.prove(key).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove_by_branch(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+    ) -> Result<SparseMerkleProof> {
+        self.inner.prove_by_branch(key, branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove_by_branch_version(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<SparseMerkleProof> {
+        self.inner
+            .prove_by_branch_version(key, branch_name, version_name)
+            .c(d!())
     }
 
     #[inline(always)]
@@ -208,12 +395,12 @@ where
         branch_name: BranchName,
     ) -> Result<Option<V>> {
         self.inner
-            .insert_by_branch(key, &value.encode(), branch_name)
-            .map(|v| v.map(|v| <V as ValueEnDe>::decode(&v).unwrap()))
+            .insert_by_branch(key, &encode_value_by::<C, V>(value), branch_name)
+            .map(|v| v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)))
     }
 
     #[inline(always)]
-    pub fn iter(&self) -> MapxOrdRawKeyVsIter<'_, V> {
+    pub fn iter(&self) -> MapxOrdRawKeyVsIter<'_, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.iter(),
             p: PhantomData,
@@ -221,7 +408,10 @@ where
     }
 
     #[inline(always)]
-    pub fn iter_by_branch(&self, branch_name: BranchName) -> MapxOrdRawKeyVsIter<'_, V> {
+    pub fn iter_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> MapxOrdRawKeyVsIter<'_, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.iter_by_branch(branch_name),
             p: PhantomData,
@@ -233,18 +423,54 @@ where
         &self,
         branch_name: BranchName,
         version_name: VersionName,
-    ) -> MapxOrdRawKeyVsIter<'_, V> {
+    ) -> MapxOrdRawKeyVsIter<'_, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.iter_by_branch_version(branch_name, version_name),
             p: PhantomData,
         }
     }
 
+    /// Get at most `limit` entries after the cursor on the default branch,
+    /// and the cursor of the next page, `None` if there are no more entries.
+    #[inline(always)]
+    pub fn iter_page(
+        &self,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<(RawKey, V)>, Option<Cursor>) {
+        match cursor {
+            Some(c) => cursor::page(
+                self.range_ref((Bound::Excluded(c.key()), Bound::Unbounded)),
+                limit,
+            ),
+            None => cursor::page(self.iter(), limit),
+        }
+    }
+
+    #[inline(always)]
+    pub fn iter_page_by_branch(
+        &self,
+        branch_name: BranchName,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> (Vec<(RawKey, V)>, Option<Cursor>) {
+        match cursor {
+            Some(c) => cursor::page(
+                self.range_ref_by_branch(
+                    branch_name,
+                    (Bound::Excluded(c.key()), Bound::Unbounded),
+                ),
+                limit,
+            ),
+            None => cursor::page(self.iter_by_branch(branch_name), limit),
+        }
+    }
+
     #[inline(always)]
     pub fn range<'a, R: 'a + RangeBounds<RawKey>>(
         &'a self,
         bounds: R,
-    ) -> MapxOrdRawKeyVsIter<'a, V> {
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.range(bounds),
             p: PhantomData,
@@ -256,7 +482,7 @@ where
         &'a self,
         branch_name: BranchName,
         bounds: R,
-    ) -> MapxOrdRawKeyVsIter<'a, V> {
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.range_by_branch(branch_name, bounds),
             p: PhantomData,
@@ -269,7 +495,7 @@ where
         branch_name: BranchName,
         version_name: VersionName,
         bounds: R,
-    ) -> MapxOrdRawKeyVsIter<'a, V> {
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self
                 .inner
@@ -282,7 +508,7 @@ where
     pub fn range_ref<'a, R: RangeBounds<&'a [u8]>>(
         &'a self,
         bounds: R,
-    ) -> MapxOrdRawKeyVsIter<'a, V> {
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.range_ref(bounds),
             p: PhantomData,
@@ -294,7 +520,7 @@ where
         &'a self,
         branch_name: BranchName,
         bounds: R,
-    ) -> MapxOrdRawKeyVsIter<'a, V> {
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.range_ref_by_branch(branch_name, bounds),
             p: PhantomData,
@@ -307,7 +533,7 @@ where
         branch_name: BranchName,
         version_name: VersionName,
         bounds: R,
-    ) -> MapxOrdRawKeyVsIter<'a, V> {
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
         MapxOrdRawKeyVsIter {
             iter: self.inner.range_ref_by_branch_version(
                 branch_name,
@@ -379,11 +605,50 @@ where
             .contains_key_by_branch_version(key, branch_name, version_name)
     }
 
+    #[inline(always)]
+    pub fn contains_all<T: AsRef<[u8]>>(&self, keys: &[T]) -> bool {
+        self.inner.contains_all(keys)
+    }
+
+    #[inline(always)]
+    pub fn contains_all_by_branch<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        branch_name: BranchName,
+    ) -> bool {
+        self.inner.contains_all_by_branch(keys, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn contains_all_by_branch_version<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> bool {
+        self.inner
+            .contains_all_by_branch_version(keys, branch_name, version_name)
+    }
+
+    #[inline(always)]
+    pub fn get_many_by_branch_version<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Vec<Option<V>> {
+        self.inner
+            .get_many_by_branch_version(keys, branch_name, version_name)
+            .into_iter()
+            .map(|v| v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)))
+            .collect()
+    }
+
     #[inline(always)]
     pub fn remove(&self, key: &[u8]) -> Result<Option<V>> {
         self.inner
             .remove(key)
-            .map(|v| v.map(|v| <V as ValueEnDe>::decode(&v).unwrap()))
+            .map(|v| v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)))
     }
 
     #[inline(always)]
@@ -394,83 +659,447 @@ where
     ) -> Result<Option<V>> {
         self.inner
             .remove_by_branch(key, branch_name)
-            .map(|v| v.map(|v| <V as ValueEnDe>::decode(&v).unwrap()))
+            .map(|v| v.and_then(|v| decode_value_by_policy_with::<C, V>(&v)))
+    }
+
+    #[inline(always)]
+    pub fn branch_diff(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Vec<(RawKey, Diff<V>)>> {
+        self.inner.branch_diff(branch_a, branch_b).c(d!()).map(|d| {
+            d.into_iter()
+                .filter_map(|(k, d)| {
+                    d.map(|v| decode_value_by_policy_with::<C, V>(&v))
+                        .transpose()
+                        .map(|d| (k, d))
+                })
+                .collect()
+        })
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_conflicts(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<MergeConflict<RawKey, V>>> {
+        self.inner
+            .branch_merge_conflicts(branch_name)
+            .c(d!())
+            .map(|cs| {
+                cs.into_iter()
+                    .map(|c| MergeConflict {
+                        key: c.key,
+                        ours: c
+                            .ours
+                            .and_then(|v| decode_value_by_policy_with::<C, V>(&v)),
+                        theirs: c
+                            .theirs
+                            .and_then(|v| decode_value_by_policy_with::<C, V>(&v)),
+                    })
+                    .collect()
+            })
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_to_parent_with_resolver(
+        &self,
+        branch_name: BranchName,
+        mut resolver: impl FnMut(&[u8], Option<&V>, Option<&V>) -> MergeResolution<V>,
+    ) -> Result<()> {
+        self.inner
+            .branch_merge_to_parent_with_resolver(branch_name, |k, ours, theirs| {
+                let ours = ours.and_then(decode_value_by_policy_with::<C, V>);
+                let theirs = theirs.and_then(decode_value_by_policy_with::<C, V>);
+                resolver(k, ours.as_ref(), theirs.as_ref())
+                    .map(|v| encode_value_by::<C, V>(&v))
+            })
+            .c(d!())
     }
 
     #[inline(always)]
     pub fn clear(&mut self) {
         self.inner.clear();
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_3way(
+        &self,
+        ours: BranchName,
+        theirs: BranchName,
+        base_version: VersionName,
+    ) -> Result<Vec<MergeConflict<RawKey, V>>> {
+        self.inner
+            .branch_merge_3way(ours, theirs, base_version)
+            .c(d!())
+            .map(|cs| {
+                cs.into_iter()
+                    .map(|c| MergeConflict {
+                        key: c.key,
+                        ours: c
+                            .ours
+                            .and_then(|v| decode_value_by_policy_with::<C, V>(&v)),
+                        theirs: c
+                            .theirs
+                            .and_then(|v| decode_value_by_policy_with::<C, V>(&v)),
+                    })
+                    .collect()
+            })
+    }
+
+    #[inline(always)]
+    pub fn branch_rebase(
+        &self,
+        branch_name: BranchName,
+        new_parent_branch_name: ParentBranchName,
+        new_parent_version_name: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .branch_rebase(branch_name, new_parent_branch_name, new_parent_version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner.export_versions(bounds).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions_by_branch<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_name: BranchName,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner
+            .export_versions_by_branch(branch_name, bounds)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_patch(
+        &self,
+        branch_name: BranchName,
+        from_version: VersionName,
+        to_version: VersionName,
+    ) -> Result<Vec<u8>> {
+        self.inner
+            .export_patch(branch_name, from_version, to_version)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn apply_patch(&self, branch_name: BranchName, patch: &[u8]) -> Result<()> {
+        self.inner.apply_patch(branch_name, patch).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_from_bundle(
+        &self,
+        branch_name: BranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_from_bundle(branch_name, bundle)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_by_base_branch_from_bundle(
+        &self,
+        branch_name: BranchName,
+        base_branch_name: ParentBranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_by_base_branch_from_bundle(
+                branch_name,
+                base_branch_name,
+                bundle,
+            )
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_is_ancestor(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<bool> {
+        self.inner.branch_is_ancestor(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_base(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.inner.branch_merge_base(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_checksum(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<[u8; 32]> {
+        self.inner
+            .version_checksum(branch_name, version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list(&self) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list_by_branch(branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta(&self, version_name: VersionName) -> Option<VersionMeta> {
+        self.inner.version_get_meta(version_name)
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<VersionMeta> {
+        self.inner
+            .version_get_meta_by_branch(version_name, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_is_frozen(&self, branch_name: BranchName) -> bool {
+        self.inner.branch_is_frozen(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_depth(&self, branch_name: BranchName) -> Option<usize> {
+        self.inner.branch_depth(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_export_shallow(
+        &self,
+        branch_name: BranchName,
+        target_dir: &str,
+    ) -> Result<()> {
+        self.inner
+            .branch_export_shallow(branch_name, target_dir)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn import_shallow(src_dir: &str) -> Result<Self> {
+        Ok(Self {
+            inner: MapxRawVs::import_shallow(src_dir).c(d!())?,
+            p: PhantomData,
+        })
+    }
 }
 
-impl<V> VsMgmt for MapxOrdRawKeyVs<V>
+impl<V, C> VsMgmt for MapxOrdRawKeyVs<V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     crate::impl_vs_methods!();
 }
 
-pub struct MapxOrdRawKeyVsIter<'a, V>
+impl<V, C> VsComplete for MapxOrdRawKeyVs<V, C>
+where
+    V: ValueEnDe,
+    C: Codec,
+{
+}
+
+impl<V, C> VsNested for MapxOrdRawKeyVs<V, C>
+where
+    V: ValueEnDe + VsComplete,
+    C: Codec,
+{
+}
+
+impl<'a, V, C> SnapshotHandle<'a, MapxOrdRawKeyVs<V, C>>
 where
     V: ValueEnDe,
+    C: Codec,
+{
+    #[inline(always)]
+    pub fn get(&self, key: &[u8]) -> Option<V> {
+        self.inner()
+            .get_by_branch_version(key, self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn get_le(&self, key: &[u8]) -> Option<(RawKey, V)> {
+        self.inner().get_le_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn get_ge(&self, key: &[u8]) -> Option<(RawKey, V)> {
+        self.inner().get_ge_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner()
+            .len_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner()
+            .is_empty_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> MapxOrdRawKeyVsIter<'a, V, C> {
+        self.inner()
+            .iter_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn to_plain(&self) -> Vec<(RawKey, V)> {
+        self.iter().collect()
+    }
+
+    #[inline(always)]
+    pub fn range<R: 'a + RangeBounds<RawKey>>(
+        &self,
+        bounds: R,
+    ) -> MapxOrdRawKeyVsIter<'a, V, C> {
+        self.inner().range_by_branch_version(
+            self.branch_name(),
+            self.version_name(),
+            bounds,
+        )
+    }
+
+    #[inline(always)]
+    pub fn first(&self) -> Option<(RawKey, V)> {
+        self.inner()
+            .first_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn last(&self) -> Option<(RawKey, V)> {
+        self.inner()
+            .last_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.inner().contains_key_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+}
+
+pub struct MapxOrdRawKeyVsIter<'a, V, C = DefaultCodec>
+where
+    V: ValueEnDe,
+    C: Codec,
 {
     iter: MapxRawVsIter<'a>,
-    p: PhantomData<V>,
+    p: PhantomData<(V, C)>,
 }
 
-impl<'a, V> Iterator for MapxOrdRawKeyVsIter<'a, V>
+impl<'a, V, C> Iterator for MapxOrdRawKeyVsIter<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     type Item = (RawKey, V);
     fn next(&mut self) -> Option<Self::Item> {
         self.iter
-            .next()
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .find_map(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 }
 
-impl<'a, V> DoubleEndedIterator for MapxOrdRawKeyVsIter<'a, V>
+impl<'a, V, C> DoubleEndedIterator for MapxOrdRawKeyVsIter<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.iter
-            .next_back()
-            .map(|(k, v)| (k, <V as ValueEnDe>::decode(&v).unwrap()))
+            .by_ref()
+            .rev()
+            .find_map(|(k, v)| decode_value_by_policy_with::<C, V>(&v).map(|v| (k, v)))
     }
 }
 
-impl<'a, V> ExactSizeIterator for MapxOrdRawKeyVsIter<'a, V> where V: ValueEnDe {}
+impl<'a, V, C> ExactSizeIterator for MapxOrdRawKeyVsIter<'a, V, C>
+where
+    V: ValueEnDe,
+    C: Codec,
+{
+}
 
 #[derive(PartialEq, Eq, Debug)]
-pub struct ValueMut<'a, V: ValueEnDe> {
-    hdr: &'a MapxOrdRawKeyVs<V>,
+pub struct ValueMut<'a, V: ValueEnDe, C: Codec = DefaultCodec> {
+    hdr: &'a MapxOrdRawKeyVs<V, C>,
     key: &'a [u8],
     value: V,
 }
 
-impl<'a, V> ValueMut<'a, V>
+impl<'a, V, C> ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    fn new(hdr: &'a MapxOrdRawKeyVs<V>, key: &'a [u8], value: V) -> Self {
+    fn new(hdr: &'a MapxOrdRawKeyVs<V, C>, key: &'a [u8], value: V) -> Self {
         ValueMut { hdr, key, value }
     }
 }
 
-impl<'a, V> Drop for ValueMut<'a, V>
+impl<'a, V, C> Drop for ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn drop(&mut self) {
         pnk!(self.hdr.insert_ref(self.key, &self.value));
     }
 }
 
-impl<'a, V> Deref for ValueMut<'a, V>
+impl<'a, V, C> Deref for ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     type Target = V;
     fn deref(&self) -> &Self::Target {
@@ -478,26 +1107,28 @@ where
     }
 }
 
-impl<'a, V> DerefMut for ValueMut<'a, V>
+impl<'a, V, C> DerefMut for ValueMut<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.value
     }
 }
 
-pub struct Entry<'a, V: ValueEnDe> {
+pub struct Entry<'a, V: ValueEnDe, C: Codec = DefaultCodec> {
     key: &'a [u8],
-    hdr: &'a MapxOrdRawKeyVs<V>,
+    hdr: &'a MapxOrdRawKeyVs<V, C>,
 }
 
-impl<'a, V> Entry<'a, V>
+impl<'a, V, C> Entry<'a, V, C>
 where
     V: ValueEnDe,
+    C: Codec,
 {
-    pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, V> {
-        if !self.hdr.contains_key(self.key) {
+    pub fn or_insert_ref(self, default: &V) -> ValueMut<'a, V, C> {
+        if self.hdr.get_head(self.key).is_none() {
             pnk!(self.hdr.insert_ref(self.key, default));
         }
         pnk!(self.hdr.get_mut(self.key))
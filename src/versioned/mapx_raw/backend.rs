@@ -6,33 +6,70 @@ use crate::{
     basic::{
         mapx_ord::MapxOrd,
         mapx_ord_rawkey::{MapxOrdRawKey, MapxOrdRawKeyIter},
+        mapx_ord_rawvalue::MapxOrdRawValue,
         mapx_raw::MapxRaw,
+        orphan::Orphan,
+        vecx::Vecx,
     },
     common::{
-        ende::encode_optioned_bytes, BranchID, BranchName, RawKey, RawValue, VersionID,
-        VersionName, BRANCH_ANCESTORS_LIMIT, INITIAL_BRANCH_ID, INITIAL_BRANCH_NAME,
-        INITIAL_VERSION, NULL, VSDB,
+        database, durability,
+        ende::{encode_optioned_bytes, ValueDe, ValueEn},
+        vsdb_get_branch_ancestors_limit,
+        write_batch::WriteBatch,
+        BranchID, BranchName, RawKey, RawValue, VersionID, VersionName,
+        COMMIT_JOURNAL_ID, INITIAL_BRANCH_ID, INITIAL_BRANCH_NAME, INITIAL_VERSION,
+        NULL, VS_EXTENSION_REGISTRY,
+    },
+    merkle::sparse::{Hash32, SparseMerkleProof, SparseMerkleStore, EMPTY_ROOT},
+    versioned::{
+        clock, snapshot, BranchInfo, BundledVersion, Diff, InstanceId, KeyBlame,
+        MergeConflict, MergeResolution, PrunePolicy, VersionBundle, VersionInfo,
+        VersionMeta,
     },
 };
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, ReentrantMutex};
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, ops::RangeBounds};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    mem,
+    ops::{Bound, RangeBounds},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
 
 type BranchPath = BTreeMap<BranchID, VersionID>;
 
 // default value for reserved number when pruning branches
 pub(super) const RESERVED_VERSION_NUM_DEFAULT: usize = 10;
 
+// Held when creating versions, so the head checked by `version_create_by_branch_cas`
+// can not be changed by others before the new version is created.
+static VERSION_CREATE_LOCK: Lazy<ReentrantMutex<()>> =
+    Lazy::new(|| ReentrantMutex::new(()));
+
 ////////////////////////////////////////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////
 
+// Only the fields of `Layout` are serialized, check `Extension`.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Layout", into = "Layout")]
 pub(super) struct MapxRawVs {
     default_branch: BranchID,
 
     branch_name_to_branch_id: MapxOrdRawKey<BranchID>,
     version_name_to_version_id: MapxOrdRawKey<VersionID>,
 
+    // reverse indexes of the above two,
+    // version names are kept without the prefix of their branches
+    branch_id_to_branch_name: MapxOrdRawValue<BranchID>,
+    version_id_to_version_name: MapxOrdRawValue<VersionID>,
+
+    // human-friendly aliases of versions, `tag` => version
+    version_tags: MapxOrdRawKey<VersionID>,
+
     // which version the branch is forked from
     branch_to_parent: MapxOrd<BranchID, Option<BasePoint>>,
 
@@ -42,8 +79,30 @@ pub(super) struct MapxRawVs {
     // globally ever changed keys within each version
     version_to_change_set: MapxOrd<VersionID, MapxRaw>,
 
+    // when the version was created, in seconds since the UNIX epoch
+    version_to_timestamp: MapxOrd<VersionID, u64>,
+
+    // user-defined metadata of versions
+    version_to_meta: MapxOrd<VersionID, VersionMeta>,
+
+    // branches that can not be written
+    frozen_branches: MapxOrd<BranchID, ()>,
+
     // key -> multi-branch -> multi-version -> multi-value
     layered_kv: MapxOrdRawKey<MapxOrd<BranchID, MapxOrd<VersionID, Option<RawValue>>>>,
+
+    // Merkle roots of versions, only for instances created with them
+    roots: Option<VersionRoots>,
+
+    // if enabled, newly created versions are invisible to reads on
+    // the heads of branches, until they are finalized
+    read_isolation: Orphan<bool>,
+
+    // versions still being built, at most one(the last one) on each branch
+    branch_to_open_version: MapxOrd<BranchID, VersionID>,
+
+    // savepoints taken in the head version of each branch
+    branch_to_savepoints: MapxOrd<BranchID, SavepointLog>,
 }
 
 ////////////////////////////////////////////////////////////////////////////////////
@@ -56,26 +115,63 @@ impl MapxRawVs {
             default_branch: BranchID::default(),
             branch_name_to_branch_id: MapxOrdRawKey::new(),
             version_name_to_version_id: MapxOrdRawKey::new(),
+            branch_id_to_branch_name: MapxOrdRawValue::new(),
+            version_id_to_version_name: MapxOrdRawValue::new(),
+            version_tags: MapxOrdRawKey::new(),
             branch_to_parent: MapxOrd::new(),
             branch_to_created_versions: MapxOrd::new(),
             version_to_change_set: MapxOrd::new(),
+            version_to_timestamp: MapxOrd::new(),
+            version_to_meta: MapxOrd::new(),
+            frozen_branches: MapxOrd::new(),
             layered_kv: MapxOrdRawKey::new(),
+            roots: None,
+            read_isolation: Orphan::new(false),
+            branch_to_open_version: MapxOrd::new(),
+            branch_to_savepoints: MapxOrd::new(),
         };
+        ret.extension_save();
         ret.init();
         ret
     }
 
+    #[inline(always)]
+    pub(super) fn new_with_roots() -> Self {
+        let mut ret = Self::new();
+        ret.roots = Some(VersionRoots::new());
+        ret.extension_save();
+        ret
+    }
+
     #[inline(always)]
     fn init(&mut self) {
         self.default_branch = INITIAL_BRANCH_ID;
-        self.branch_name_to_branch_id
-            .insert_ref(&INITIAL_BRANCH_NAME.to_vec(), &INITIAL_BRANCH_ID);
+        self.branch_name_bind(INITIAL_BRANCH_NAME, INITIAL_BRANCH_ID);
         self.branch_to_parent.insert(INITIAL_BRANCH_ID, None);
         self.branch_to_created_versions
             .insert(INITIAL_BRANCH_ID, MapxOrd::new());
         self.version_create(INITIAL_VERSION.0).unwrap();
     }
 
+    // The database holding this instance, branch IDs and version IDs
+    // are allocated by it, no matter which one is current for the thread.
+    #[inline(always)]
+    pub(super) fn db(&self) -> database::Db {
+        self.layered_kv.as_raw().db()
+    }
+
+    // The identity of this instance, used to pin its versions by snapshots.
+    #[inline(always)]
+    pub(super) fn instance_id(&self) -> InstanceId {
+        InstanceId::of(self.layered_kv.as_raw())
+    }
+
+    // Create the nested instances in the database holding this one.
+    #[inline(always)]
+    fn in_db<T>(&self, f: impl FnOnce() -> T) -> T {
+        database::scoped(self.db(), f)
+    }
+
     #[inline(always)]
     pub(super) fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<RawValue>> {
         self.insert_by_branch(key, value, self.branch_get_default())
@@ -166,6 +262,8 @@ impl MapxRawVs {
         branch_id: BranchID,
         version_id: VersionID,
     ) -> Result<Option<RawValue>> {
+        self.branch_check_writable(branch_id).c(d!())?;
+
         let ret = self.get_by_branch_version(key, branch_id, version_id);
 
         // remove a non-existing value
@@ -173,6 +271,9 @@ impl MapxRawVs {
             return Ok(None);
         }
 
+        // must be recorded before the state is changed
+        self.savepoint_log_record(key, branch_id, version_id);
+
         self.version_to_change_set
             .get_mut(&version_id)
             .c(d!("BUG: version not found"))?
@@ -180,11 +281,31 @@ impl MapxRawVs {
 
         self.layered_kv
             .entry_ref(key)
-            .or_insert_ref(&MapxOrd::new())
+            .or_insert_ref(&self.in_db(MapxOrd::new))
             .entry(branch_id)
-            .or_insert(MapxOrd::new())
+            .or_insert(self.in_db(MapxOrd::new))
             .insert_ref_encoded_value(&version_id, &encode_optioned_bytes(&value)[..]);
 
+        // roots are calculated on request,
+        // the ones that can see the change must be calculated again
+        if let Some(roots) = self.roots.as_ref() {
+            roots.version_to_root.remove(&version_id);
+
+            // branches forked from this version can see the change
+            if roots
+                .version_to_root
+                .range((1 + version_id)..)
+                .next()
+                .is_some()
+                && self.branch_to_parent.iter().any(|(_, bp)| {
+                    matches!(bp, Some(bp) if bp.branch_id == branch_id
+                        && bp.version_id >= version_id)
+                })
+            {
+                roots.invalidate(1 + version_id);
+            }
+        }
+
         Ok(ret)
     }
 
@@ -199,17 +320,21 @@ impl MapxRawVs {
         key: &[u8],
         branch_id: BranchID,
     ) -> Option<RawValue> {
-        if let Some(vers) = self.branch_to_created_versions.get(&branch_id) {
-            if let Some(version_id) = vers.last().map(|(id, _)| id).or_else(|| {
-                self.branch_to_parent
-                    .get(&branch_id)
-                    .unwrap()
-                    .map(|bi| bi.version_id)
-            }) {
-                return self.get_by_branch_version(key, branch_id, version_id);
-            }
-        }
-        None
+        self.branch_get_read_head(branch_id).and_then(|version_id| {
+            self.get_by_branch_version(key, branch_id, version_id)
+        })
+    }
+
+    // Like `get_by_branch`, but in the view of the writer,
+    // changes of the version still being built are included.
+    pub(super) fn get_by_branch_head(
+        &self,
+        key: &[u8],
+        branch_id: BranchID,
+    ) -> Option<RawValue> {
+        self.branch_get_head(branch_id).and_then(|version_id| {
+            self.get_by_branch_version(key, branch_id, version_id)
+        })
     }
 
     pub(super) fn get_by_branch_version(
@@ -224,6 +349,16 @@ impl MapxRawVs {
             return None;
         }
 
+        self.get_by_path(key, &fp, version_id)
+    }
+
+    // NOTE: the version should have been checked by `version_id_is_in_bounds`
+    fn get_by_path(
+        &self,
+        key: &[u8],
+        fp: &BranchPath,
+        version_id: VersionID,
+    ) -> Option<RawValue> {
         if let Some(brs) = self.layered_kv.get(key) {
             // they are all monotonically increasing
             for (br, ver) in fp.iter().rev() {
@@ -238,6 +373,241 @@ impl MapxRawVs {
         None
     }
 
+    // Find the newest version on the path of the branch that changed the key,
+    // `None` if the key has never been changed or the version has been pruned.
+    pub(super) fn key_blame_by_branch(
+        &self,
+        key: &[u8],
+        branch_id: BranchID,
+    ) -> Option<KeyBlame> {
+        let head = self.branch_get_read_head(branch_id)?;
+        let brs = self.layered_kv.get(key)?;
+        let fp = self.branch_get_full_path(branch_id);
+
+        let (br, ver, v) = fp.iter().rev().find_map(|(br, ver)| {
+            brs.get(br)
+                .and_then(|vers| vers.get_le(&min!(*ver, head)))
+                .map(|(v_id, v)| (*br, v_id, v))
+        })?;
+
+        let name = self.version_get_name(ver)?;
+        let branch = self.branch_get_name(br)?;
+
+        Some(KeyBlame {
+            branch,
+            version: VersionInfo {
+                name,
+                created_at: self.version_to_timestamp.get(&ver).unwrap_or_default(),
+                change_count: self
+                    .version_to_change_set
+                    .get(&ver)
+                    .map(|chgset| chgset.len())
+                    .unwrap_or_default(),
+            },
+            meta: self.version_to_meta.get(&ver),
+            removed: v.is_none(),
+        })
+    }
+
+    // All changes of the key on the path of the branch, from the oldest one,
+    // changes made by pruned versions are skipped.
+    pub(super) fn key_history_by_branch(
+        &self,
+        key: &[u8],
+        branch_id: BranchID,
+    ) -> Vec<(Vec<u8>, Option<RawValue>)> {
+        let brs = if let Some(brs) = self.layered_kv.get(key) {
+            brs
+        } else {
+            return vec![];
+        };
+
+        // they are all monotonically increasing
+        let mut ret = vec![];
+        for (br, ver) in self.branch_get_full_path(branch_id).iter() {
+            if let Some(vers) = brs.get(br) {
+                let names = self.version_names_of_branch(*br);
+                ret.extend(vers.range(..=*ver).filter_map(|(v_id, v)| {
+                    names.get(&v_id).map(|name| (name.clone(), v))
+                }));
+            }
+        }
+        ret
+    }
+
+    #[inline(always)]
+    pub(super) fn contains_all_by_branch<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        branch_id: BranchID,
+    ) -> bool {
+        self.branch_get_read_head(branch_id)
+            .map(|ver| self.contains_all_by_branch_version(keys, branch_id, ver))
+            .unwrap_or(false)
+    }
+
+    // Check the existence of many keys against the same state,
+    // the path of the branch will be resolved only once.
+    pub(super) fn contains_all_by_branch_version<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> bool {
+        let fp = self.branch_get_full_path(branch_id);
+
+        if !Self::version_id_is_in_bounds(&fp, version_id) {
+            return false;
+        }
+
+        keys.into_iter()
+            .all(|k| self.get_by_path(k, &fp, version_id).is_some())
+    }
+
+    // Read many keys from the same state, in the order of the input,
+    // the path of the branch will be resolved only once.
+    pub(super) fn get_many_by_branch_version<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Vec<Option<RawValue>> {
+        let fp = self.branch_get_full_path(branch_id);
+        let in_bounds = Self::version_id_is_in_bounds(&fp, version_id);
+
+        keys.into_iter()
+            .map(|k| alt!(in_bounds, self.get_by_path(k, &fp, version_id), None))
+            .collect()
+    }
+
+    // The version that represents the head state of a branch,
+    // for a branch without any created version,
+    // it is the version where the branch is forked from.
+    fn branch_get_head(&self, branch_id: BranchID) -> Option<VersionID> {
+        self.branch_to_created_versions
+            .get(&branch_id)?
+            .last()
+            .map(|(id, _)| id)
+            .or_else(|| {
+                self.branch_to_parent
+                    .get(&branch_id)
+                    .flatten()
+                    .map(|bp| bp.version_id)
+            })
+    }
+
+    // The head version for reads, it is the same as `branch_get_head`,
+    // except that the version still being built is skipped
+    // when the read isolation is enabled.
+    fn branch_get_read_head(&self, branch_id: BranchID) -> Option<VersionID> {
+        self.branch_get_committed_version(branch_id).or_else(|| {
+            self.branch_to_parent
+                .get(&branch_id)
+                .flatten()
+                .map(|bp| bp.version_id)
+        })
+    }
+
+    // The last version created on a branch that is visible to reads,
+    // `None` if there is no one.
+    fn branch_get_committed_version(&self, branch_id: BranchID) -> Option<VersionID> {
+        let vers = self.branch_to_created_versions.get(&branch_id)?;
+        let (last, _) = vers.last()?;
+
+        // marks of removed or replaced versions are just ignored,
+        // version IDs are never reused
+        if self.read_isolation.get_value()
+            && Some(last) == self.branch_to_open_version.get(&branch_id)
+        {
+            return last
+                .checked_sub(1)
+                .and_then(|id| vers.get_le(&id))
+                .map(|(id, _)| id);
+        }
+
+        Some(last)
+    }
+
+    // The version to iterate for reads on the head of a branch,
+    // `None` if no version has been created on the branch,
+    // it falls back to the base point like `branch_get_read_head`
+    // if the only version is still being built.
+    fn branch_get_iter_version(&self, branch_id: BranchID) -> Option<VersionID> {
+        self.branch_to_created_versions.get(&branch_id)?.last()?;
+        self.branch_get_read_head(branch_id)
+    }
+
+    // Compare the head states of two branches,
+    // all changes are described from the view of `branch_a` to `branch_b`.
+    pub(super) fn branch_diff(
+        &self,
+        branch_a: BranchID,
+        branch_b: BranchID,
+    ) -> Result<Vec<(RawKey, Diff<RawValue>)>> {
+        let ver_a = self.branch_get_head(branch_a).c(d!("branch not found"))?;
+        let ver_b = self.branch_get_head(branch_b).c(d!("branch not found"))?;
+
+        // keys out of the changes since the merge base are the same on both
+        let since = self
+            .branch_merge_base_version(branch_a, branch_b)
+            .map(|(_, ver)| Bound::Excluded(ver))
+            .unwrap_or(Bound::Unbounded);
+        let mut keys = self.keys_changed_on_path_since(branch_a, since);
+        keys.append(&mut self.keys_changed_on_path_since(branch_b, since));
+
+        let mut ret = vec![];
+
+        for k in keys {
+            let va = self.get_by_branch_version(&k, branch_a, ver_a);
+            let vb = self.get_by_branch_version(&k, branch_b, ver_b);
+            match (va, vb) {
+                (None, Some(v)) => ret.push((k, Diff::Added(v))),
+                (Some(v), None) => ret.push((k, Diff::Removed(v))),
+                (Some(a), Some(b)) if a != b => ret.push((k, Diff::Modified(a, b))),
+                _ => {}
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // The smallest and the biggest keys changed by the versions
+    // newer than the specified one, in the view of the branch.
+    pub(super) fn changed_key_bounds_since(
+        &self,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Option<(RawKey, RawKey)> {
+        let mut ret: Option<(RawKey, RawKey)> = None;
+
+        for (br, last_ver) in self.branch_get_full_path(branch_id).into_iter() {
+            alt!(last_ver <= version_id, continue);
+            let vers = if let Some(vers) = self.branch_to_created_versions.get(&br) {
+                vers
+            } else {
+                continue;
+            };
+            let bounds = (Bound::Excluded(version_id), Bound::Included(last_ver));
+            for (ver, _) in vers.range(bounds) {
+                let chgset = if let Some(chgset) = self.version_to_change_set.get(&ver) {
+                    chgset
+                } else {
+                    continue;
+                };
+                let min = chgset.iter().next();
+                let max = chgset.iter().next_back();
+                if let (Some((min, _)), Some((max, _))) = (min, max) {
+                    ret = Some(match ret {
+                        Some((a, b)) => (a.min(min), b.max(max)),
+                        None => (min, max),
+                    });
+                }
+            }
+        }
+
+        ret
+    }
+
     #[inline(always)]
     pub(super) fn get_ge(&self, key: &[u8]) -> Option<(RawKey, RawValue)> {
         self.range_ref(key..).next()
@@ -295,10 +665,8 @@ impl MapxRawVs {
 
     #[inline(always)]
     pub(super) fn iter_by_branch(&self, branch_id: BranchID) -> MapxRawVsIter {
-        if let Some(vers) = self.branch_to_created_versions.get(&branch_id) {
-            if let Some((version_id, _)) = vers.last() {
-                return self.iter_by_branch_version(branch_id, version_id);
-            }
+        if let Some(version_id) = self.branch_get_iter_version(branch_id) {
+            return self.iter_by_branch_version(branch_id, version_id);
         }
 
         MapxRawVsIter {
@@ -337,10 +705,8 @@ impl MapxRawVs {
         branch_id: BranchID,
         bounds: R,
     ) -> MapxRawVsIter<'a> {
-        if let Some(vers) = self.branch_to_created_versions.get(&branch_id) {
-            if let Some((version_id, _)) = vers.last() {
-                return self.range_by_branch_version(branch_id, version_id, bounds);
-            }
+        if let Some(version_id) = self.branch_get_iter_version(branch_id) {
+            return self.range_by_branch_version(branch_id, version_id, bounds);
         }
 
         MapxRawVsIter {
@@ -380,10 +746,8 @@ impl MapxRawVs {
         branch_id: BranchID,
         bounds: R,
     ) -> MapxRawVsIter<'a> {
-        if let Some(vers) = self.branch_to_created_versions.get(&branch_id) {
-            if let Some((version_id, _)) = vers.last() {
-                return self.range_ref_by_branch_version(branch_id, version_id, bounds);
-            }
+        if let Some(version_id) = self.branch_get_iter_version(branch_id) {
+            return self.range_ref_by_branch_version(branch_id, version_id, bounds);
         }
 
         MapxRawVsIter {
@@ -411,12 +775,38 @@ impl MapxRawVs {
 
     #[inline(always)]
     pub(super) fn len(&self) -> usize {
-        self.iter().count()
+        self.len_by_branch(self.branch_get_default())
     }
 
+    // In the view of the writer, items of the version still being built
+    // are counted even if the read isolation is enabled, so new indexes
+    // of vectors will not be allocated repeatedly.
     #[inline(always)]
     pub(super) fn len_by_branch(&self, branch_id: BranchID) -> usize {
-        self.iter_by_branch(branch_id).count()
+        self.iter_by_branch_head(branch_id).count()
+    }
+
+    #[inline(always)]
+    pub(super) fn is_empty_by_branch(&self, branch_id: BranchID) -> bool {
+        self.iter_by_branch_head(branch_id).next().is_none()
+    }
+
+    // Like `iter_by_branch`, but in the view of the writer.
+    fn iter_by_branch_head(&self, branch_id: BranchID) -> MapxRawVsIter {
+        if let Some((version_id, _)) = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .and_then(|vers| vers.last())
+        {
+            return self.iter_by_branch_version(branch_id, version_id);
+        }
+
+        MapxRawVsIter {
+            hdr: self,
+            iter: self.layered_kv.iter(),
+            branch_id: NULL,
+            version_id: NULL,
+        }
     }
 
     #[inline(always)]
@@ -433,14 +823,73 @@ impl MapxRawVs {
     pub(super) fn clear(&mut self) {
         self.branch_name_to_branch_id.clear();
         self.version_name_to_version_id.clear();
+        self.branch_id_to_branch_name.clear();
+        self.version_id_to_version_name.clear();
+        self.version_tags.clear();
         self.branch_to_parent.clear();
         self.branch_to_created_versions.clear();
         self.version_to_change_set.clear();
+        self.version_to_timestamp.clear();
+        self.version_to_meta.clear();
+        self.frozen_branches.clear();
         self.layered_kv.clear();
+        if let Some(roots) = self.roots.as_ref() {
+            roots.clear();
+        }
 
         self.init();
     }
 
+    // Flush all data of this instance(include the nested instances) to disk,
+    // nested instances share a few areas, so each area is flushed only once.
+    pub(super) fn flush(&self) {
+        let mut areas = BTreeSet::new();
+        let mut add = |raw: &MapxRaw| {
+            areas.insert(raw.area_idx());
+        };
+
+        add(self.branch_name_to_branch_id.as_raw());
+        add(self.version_name_to_version_id.as_raw());
+        add(self.branch_id_to_branch_name.as_raw());
+        add(self.version_id_to_version_name.as_raw());
+        add(self.version_tags.as_raw());
+        add(self.branch_to_parent.as_raw());
+
+        add(self.branch_to_created_versions.as_raw());
+        self.branch_to_created_versions
+            .iter()
+            .for_each(|(_, vers)| add(vers.as_raw()));
+
+        add(self.version_to_change_set.as_raw());
+        self.version_to_change_set
+            .iter()
+            .for_each(|(_, chgset)| add(&chgset));
+        add(self.version_to_timestamp.as_raw());
+        add(self.version_to_meta.as_raw());
+        add(self.frozen_branches.as_raw());
+
+        add(self.layered_kv.as_raw());
+        self.layered_kv.iter().for_each(|(_, brs)| {
+            add(brs.as_raw());
+            brs.iter().for_each(|(_, vers)| add(vers.as_raw()));
+        });
+
+        if let Some(roots) = self.roots.as_ref() {
+            add(roots.nodes.as_raw());
+            add(roots.version_to_root.as_raw());
+        }
+
+        add(self.read_isolation.as_raw());
+        add(self.branch_to_open_version.as_raw());
+        add(self.branch_to_savepoints.as_raw());
+        self.branch_to_savepoints.iter().for_each(|(_, sp)| {
+            add(sp.names.as_raw());
+            add(sp.undo.as_raw());
+        });
+
+        self.layered_kv.as_raw().flush_areas(&areas);
+    }
+
     #[inline(always)]
     pub(super) fn version_create(&self, version_name: &[u8]) -> Result<()> {
         self.version_create_by_branch(version_name, self.branch_get_default())
@@ -452,6 +901,10 @@ impl MapxRawVs {
         version_name: &[u8],
         branch_id: BranchID,
     ) -> Result<()> {
+        let _guard = VERSION_CREATE_LOCK.lock();
+
+        self.branch_check_writable(branch_id).c(d!())?;
+
         let mut vername = branch_id.to_be_bytes().to_vec();
         vername.extend_from_slice(version_name);
 
@@ -464,84 +917,360 @@ impl MapxRawVs {
             .get_mut(&branch_id)
             .c(d!("branch not found"))?;
 
-        let version_id = VSDB.alloc_version_id();
+        let version_id = self.db().alloc_version_id();
+
+        // recorded before any write, so a crash in the middle can be recovered
+        let in_commit = commit_journal_record(self, version_id, branch_id).c(d!())?;
+
         vers.insert(version_id, ());
+        self.savepoint_drop_all(branch_id);
+        if self.read_isolation.get_value() {
+            self.branch_to_open_version.insert(branch_id, version_id);
+        }
 
-        self.version_name_to_version_id
-            .insert(vername.into_boxed_slice(), version_id);
+        self.version_name_bind(branch_id, version_name, version_id);
         self.version_to_change_set
-            .insert(version_id, MapxRaw::new());
+            .insert(version_id, self.in_db(MapxRaw::new));
+        self.version_to_timestamp.insert(version_id, clock::now());
+
+        if !in_commit {
+            durability::version_created(self.db());
+        }
 
         Ok(())
     }
 
-    // Check if a verison exists on the initial branch
-    #[inline(always)]
-    pub(super) fn version_exists(&self, version_id: BranchID) -> bool {
-        self.version_exists_on_branch(version_id, self.branch_get_default())
-            .0
+    // Enable or disable the read isolation, versions still being built
+    // are all finalized when it is disabled.
+    pub(super) fn read_isolation_set(&self, enabled: bool) {
+        *self.read_isolation.get_mut() = enabled;
+        if !enabled {
+            self.branch_to_open_version.clear();
+        }
     }
 
-    // Check if a version exists on a specified branch(include its parents)
-    #[inline(always)]
-    pub(super) fn version_exists_on_branch(
+    // Make the version being built on a branch visible to reads,
+    // do nothing if there is no such one.
+    pub(super) fn version_finalize_by_branch(&self, branch_id: BranchID) -> Result<()> {
+        if !self.branch_exists(branch_id) {
+            return Err(eg!("branch not found"));
+        }
+        self.branch_to_open_version.remove(&branch_id);
+        Ok(())
+    }
+
+    // Take a savepoint in the head version of a branch,
+    // a former savepoint of the same name is replaced.
+    pub(super) fn savepoint_create(
         &self,
-        version_id: VersionID,
+        name: &[u8],
         branch_id: BranchID,
-    ) -> (bool, BranchPath) {
-        let fp = self.branch_get_full_path(branch_id);
+    ) -> Result<()> {
+        self.branch_check_head_writable(branch_id).c(d!())?;
+        let version_id = self
+            .branch_get_head(branch_id)
+            .c(d!("BUG: head not found"))?;
+
+        let log = match self.branch_to_savepoints.get(&branch_id) {
+            Some(log) if log.version_id == version_id => log,
+            _ => {
+                // savepoints of former versions are useless
+                self.savepoint_drop_all(branch_id);
+                let log = self.in_db(|| SavepointLog::new(version_id));
+                self.branch_to_savepoints.insert(branch_id, log.clone());
+                log
+            }
+        };
+        log.names.insert_ref(name, &(log.undo.len() as u64));
 
-        if !Self::version_id_is_in_bounds(&fp, version_id) {
-            return (false, fp);
-        }
+        Ok(())
+    }
 
-        for (br, ver) in fp.iter().rev() {
-            if self
-                .branch_to_created_versions
-                .get(br)
-                .unwrap()
-                .get_le(&min!(*ver, version_id))
-                .is_some()
-            {
-                return (true, fp);
+    // Undo all changes made in the head version of a branch since the savepoint,
+    // the savepoint itself is kept, those taken after it are dropped.
+    pub(super) fn savepoint_rollback(
+        &self,
+        name: &[u8],
+        branch_id: BranchID,
+    ) -> Result<()> {
+        self.branch_check_head_writable(branch_id).c(d!())?;
+        let version_id = self
+            .branch_get_head(branch_id)
+            .c(d!("BUG: head not found"))?;
+
+        let log = self
+            .branch_to_savepoints
+            .get(&branch_id)
+            .filter(|log| log.version_id == version_id)
+            .c(d!("savepoint not found"))?;
+        let pos = log.names.get(name).c(d!("savepoint not found"))?;
+
+        let chgset = self
+            .version_to_change_set
+            .get(&version_id)
+            .c(d!("BUG: version not found"))?;
+
+        // the newest change is undone first
+        while log.undo.len() as u64 > pos {
+            let (key, former) = log.undo.pop().c(d!("BUG: undo log missing"))?;
+            match former {
+                FormerValue::Absent => {
+                    if let Some(brs) = self.layered_kv.get(&key) {
+                        if let Some(vers) = brs.get(&branch_id) {
+                            vers.remove(&version_id);
+                            if vers.is_empty() {
+                                brs.remove(&branch_id);
+                            }
+                        }
+                    }
+                    chgset.remove(&key);
+                }
+                FormerValue::Value(v) => {
+                    self.layered_kv
+                        .entry_ref(&key)
+                        .or_insert_ref(&self.in_db(MapxOrd::new))
+                        .entry(branch_id)
+                        .or_insert(self.in_db(MapxOrd::new))
+                        .insert_ref_encoded_value(
+                            &version_id,
+                            &encode_optioned_bytes(&v.as_deref())[..],
+                        );
+                }
             }
         }
 
-        (false, fp)
+        let newer = log
+            .names
+            .iter()
+            .filter(|(_, p)| *p > pos)
+            .map(|(n, _)| n)
+            .collect::<Vec<_>>();
+        for n in newer {
+            log.names.remove(&n);
+        }
+
+        self.roots_invalidate(version_id);
+
+        Ok(())
     }
 
-    // Check if a version is directly created on a specified branch(exclude its parents)
+    // Record the former value of a key in the version before it is changed,
+    // if there are savepoints taken in the version.
     #[inline(always)]
-    pub(super) fn version_created_on_branch(
+    fn savepoint_log_record(
         &self,
-        version_id: VersionID,
+        key: &[u8],
         branch_id: BranchID,
-    ) -> bool {
-        self.branch_to_created_versions
-            .get(&branch_id)
-            .map(|vers| vers.get(&version_id))
-            .flatten()
-            .is_some()
+        version_id: VersionID,
+    ) {
+        let log = if let Some(log) = self.branch_to_savepoints.get(&branch_id) {
+            log
+        } else {
+            return;
+        };
+        alt!(log.version_id != version_id, return);
+
+        let former = self
+            .layered_kv
+            .get(key)
+            .and_then(|brs| brs.get(&branch_id))
+            .and_then(|vers| vers.get(&version_id))
+            .map(FormerValue::Value)
+            .unwrap_or(FormerValue::Absent);
+        log.undo.push((key.to_vec().into_boxed_slice(), former));
     }
 
-    // 'Write'-like operations on branches and versions are different from operations on data.
-    //
-    // 'Write'-like operations on data require recursive tracing of all parent nodes,
-    // while operations on branches and versions are limited to their own perspective,
-    // and should not do any tracing.
+    // Check if any savepoint can be rolled back to on the branch.
     #[inline(always)]
-    pub(super) fn version_pop(&self) -> Result<()> {
-        self.version_pop_by_branch(self.branch_get_default())
-            .c(d!())
+    fn savepoint_exists(&self, branch_id: BranchID) -> bool {
+        match (
+            self.branch_to_savepoints.get(&branch_id),
+            self.branch_get_head(branch_id),
+        ) {
+            (Some(log), Some(head)) => log.version_id == head && !log.names.is_empty(),
+            _ => false,
+        }
     }
 
-    // 'Write'-like operations on branches and versions are different from operations on data.
-    //
-    // 'Write'-like operations on data require recursive tracing of all parent nodes,
-    // while operations on branches and versions are limited to their own perspective,
-    // and should not do any tracing.
     #[inline(always)]
-    pub(super) fn version_pop_by_branch(&self, branch_id: BranchID) -> Result<()> {
+    fn savepoint_drop_all(&self, branch_id: BranchID) {
+        if let Some(log) = self.branch_to_savepoints.remove(&branch_id) {
+            log.clear();
+        }
+    }
+
+    // Create a version only if the head of the branch is still `expected_head`,
+    // that is the last version created on it, or `None` if there is no one.
+    pub(super) fn version_create_by_branch_cas(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+        expected_head: Option<&[u8]>,
+    ) -> Result<()> {
+        let _guard = VERSION_CREATE_LOCK.lock();
+
+        let head = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))?
+            .last()
+            .map(|(version_id, _)| version_id);
+        let matched = match (head, expected_head) {
+            (Some(id), Some(name)) => {
+                Some(id) == self.version_get_id_by_name(name, branch_id)
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if !matched {
+            return Err(eg!("the head of the branch has been changed"));
+        }
+
+        self.version_create_by_branch(version_name, branch_id)
+            .c(d!())
+    }
+
+    pub(super) fn version_create_by_branch_with_meta(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+        meta: &VersionMeta,
+    ) -> Result<()> {
+        self.version_create_by_branch(version_name, branch_id)
+            .c(d!())?;
+
+        let version_id = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))?
+            .last()
+            .c(d!("BUG: version not found"))?
+            .0;
+        self.version_to_meta.insert(version_id, meta.clone());
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub(super) fn version_get_meta(&self, version_id: VersionID) -> Option<VersionMeta> {
+        self.version_to_meta.get(&version_id)
+    }
+
+    // Check if a verison exists on the initial branch
+    #[inline(always)]
+    pub(super) fn version_exists(&self, version_id: BranchID) -> bool {
+        self.version_exists_on_branch(version_id, self.branch_get_default())
+            .0
+    }
+
+    // Check if a version exists on a specified branch(include its parents)
+    #[inline(always)]
+    pub(super) fn version_exists_on_branch(
+        &self,
+        version_id: VersionID,
+        branch_id: BranchID,
+    ) -> (bool, BranchPath) {
+        let fp = self.branch_get_full_path(branch_id);
+
+        if !Self::version_id_is_in_bounds(&fp, version_id) {
+            return (false, fp);
+        }
+
+        for (br, ver) in fp.iter().rev() {
+            if self
+                .branch_to_created_versions
+                .get(br)
+                .unwrap()
+                .get_le(&min!(*ver, version_id))
+                .is_some()
+            {
+                return (true, fp);
+            }
+        }
+
+        (false, fp)
+    }
+
+    // Check if a version is directly created on a specified branch(exclude its parents)
+    #[inline(always)]
+    pub(super) fn version_created_on_branch(
+        &self,
+        version_id: VersionID,
+        branch_id: BranchID,
+    ) -> bool {
+        self.branch_to_created_versions
+            .get(&branch_id)
+            .map(|vers| vers.get(&version_id))
+            .flatten()
+            .is_some()
+    }
+
+    // The ID of the version created on a branch by this name, tags are not checked.
+    #[inline(always)]
+    fn version_get_id_by_name(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+    ) -> Option<VersionID> {
+        let mut vername = branch_id.to_be_bytes().to_vec();
+        vername.extend_from_slice(version_name);
+        self.version_name_to_version_id.get(&vername)
+    }
+
+    // Check whether the head version of a branch is the one created by this name.
+    #[inline(always)]
+    pub(super) fn version_is_head_by_name(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+    ) -> bool {
+        let head = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .and_then(|vers| vers.last())
+            .map(|(version_id, _)| version_id);
+        head.is_some() && head == self.version_get_id_by_name(version_name, branch_id)
+    }
+
+    // Check whether a version can be created on a branch by this name,
+    // errors here are the same as those of `version_create_by_branch`.
+    #[inline(always)]
+    pub(super) fn version_check_creatable(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+    ) -> Result<()> {
+        self.branch_check_writable(branch_id).c(d!())?;
+        if self
+            .version_get_id_by_name(version_name, branch_id)
+            .is_some()
+        {
+            return Err(eg!("version already exists"));
+        }
+        self.branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))
+            .map(|_| ())
+    }
+
+    // 'Write'-like operations on branches and versions are different from operations on data.
+    //
+    // 'Write'-like operations on data require recursive tracing of all parent nodes,
+    // while operations on branches and versions are limited to their own perspective,
+    // and should not do any tracing.
+    #[inline(always)]
+    pub(super) fn version_pop(&self) -> Result<()> {
+        self.version_pop_by_branch(self.branch_get_default())
+            .c(d!())
+    }
+
+    // 'Write'-like operations on branches and versions are different from operations on data.
+    //
+    // 'Write'-like operations on data require recursive tracing of all parent nodes,
+    // while operations on branches and versions are limited to their own perspective,
+    // and should not do any tracing.
+    #[inline(always)]
+    pub(super) fn version_pop_by_branch(&self, branch_id: BranchID) -> Result<()> {
         if let Some((version_id, _)) = self
             .branch_to_created_versions
             .get(&branch_id)
@@ -570,6 +1299,8 @@ impl MapxRawVs {
         version_id: VersionID,
         branch_id: BranchID,
     ) -> Result<()> {
+        self.branch_check_writable(branch_id).c(d!())?;
+
         if self
             .branch_to_created_versions
             .get(&branch_id)
@@ -580,12 +1311,11 @@ impl MapxRawVs {
             return Err(eg!("version is not created by this branch"));
         }
 
-        for (key, _) in self
+        let chgset = self
             .version_to_change_set
-            .get(&version_id)
-            .c(d!("BUG: change set not found"))?
-            .iter()
-        {
+            .remove(&version_id)
+            .c(d!("BUG: change set not found"))?;
+        for (key, _) in chgset.iter() {
             let local_brs = self.layered_kv.get(&key).unwrap();
             let local_vers = local_brs.get(&branch_id).unwrap();
             local_vers.remove(&version_id);
@@ -593,21 +1323,209 @@ impl MapxRawVs {
                 local_brs.remove(&branch_id);
             }
         }
-        self.version_to_change_set.remove(&version_id);
+        chgset.clear();
+        self.version_to_timestamp.remove(&version_id);
+        self.version_to_meta.remove(&version_id);
+        self.roots_invalidate(version_id);
+
+        self.version_name_unbind(branch_id, version_id)
+            .c(d!("BUG: version name not found"))?;
+
+        Ok(())
+    }
+
+    // Remove whatever has been written for a version
+    // whose creation may have been interrupted,
+    // every step tolerates the missing parts.
+    fn version_discard(&self, version_id: VersionID, branch_id: BranchID) {
+        if let Some(vers) = self.branch_to_created_versions.get(&branch_id) {
+            vers.remove(&version_id);
+        }
+
+        if let Some(chgset) = self.version_to_change_set.remove(&version_id) {
+            for (key, _) in chgset.iter() {
+                if let Some(local_brs) = self.layered_kv.get(&key) {
+                    if let Some(local_vers) = local_brs.get(&branch_id) {
+                        local_vers.remove(&version_id);
+                        if local_vers.is_empty() {
+                            local_brs.remove(&branch_id);
+                        }
+                    }
+                }
+            }
+            chgset.clear();
+        }
+        self.version_to_timestamp.remove(&version_id);
+        self.version_to_meta.remove(&version_id);
+        self.roots_invalidate(version_id);
 
-        let version_name = self
+        // the version may have been merged into another branch,
+        // so the prefix of its name is not known here
+        if let Some((name, _)) = self
             .version_name_to_version_id
             .iter()
             .find(|(_, id)| *id == version_id)
-            .map(|(name, _)| name)
-            .unwrap();
-        self.version_name_to_version_id
-            .remove(&version_name)
-            .unwrap();
+        {
+            self.version_name_to_version_id.remove(&name);
+        }
+        self.version_id_to_version_name.remove(&version_id);
+    }
+
+    // Collapse consecutive versions created by the branch into the last one,
+    // which will hold the net changes of all of them.
+    //
+    // NOTE: versions used as base points of other branches can not be squashed.
+    pub(super) fn version_squash_by_branch(
+        &self,
+        branch_id: BranchID,
+        from: VersionID,
+        to: VersionID,
+    ) -> Result<()> {
+        self.branch_check_writable(branch_id).c(d!())?;
+
+        let vers = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))?;
+
+        if !vers.contains_key(&from) || !vers.contains_key(&to) {
+            return Err(eg!("version is not created by this branch"));
+        }
+        if from > to {
+            return Err(eg!("invalid version range"));
+        }
+
+        // all versions to be removed
+        let squashed = vers.range(from..to).map(|(ver, _)| ver).collect::<Vec<_>>();
+        alt!(squashed.is_empty(), return Ok(()));
+
+        if self.branch_to_parent.iter().any(|(_, bp)| {
+            matches!(bp, Some(bp) if bp.branch_id == branch_id
+                && from <= bp.version_id && bp.version_id < to)
+        }) {
+            return Err(eg!("some versions are used by other branches"));
+        }
+
+        self.roots_invalidate(from);
+        let to_chgset = self
+            .version_to_change_set
+            .get(&to)
+            .c(d!("BUG: change set not found"))?;
+
+        // from the newest to the oldest, so the newest value of every key wins
+        for ver in squashed.into_iter().rev() {
+            let chgset = self
+                .version_to_change_set
+                .remove(&ver)
+                .c(d!("BUG: change set not found"))?;
+            for (key, _) in chgset.iter() {
+                let local_vers =
+                    self.layered_kv.get(&key).unwrap().get(&branch_id).unwrap();
+                let value = local_vers.remove(&ver).unwrap();
+                if !to_chgset.contains_key(&key) {
+                    to_chgset.insert(&key, &[]);
+                    local_vers.insert(to, value);
+                }
+            }
+            chgset.clear();
+
+            vers.remove(&ver);
+            self.version_to_timestamp.remove(&ver);
+            self.version_to_meta.remove(&ver);
+            self.version_name_unbind(branch_id, ver)
+                .c(d!("BUG: version name not found"))?;
+        }
+
+        Ok(())
+    }
+
+    // Create a new version on the head of the branch,
+    // which undoes all changes made by a historical version,
+    // somewhat like the `git revert`.
+    pub(super) fn version_revert_by_branch(
+        &self,
+        reverted_version_id: VersionID,
+        new_version_name: &[u8],
+        branch_id: BranchID,
+    ) -> Result<()> {
+        if !self.version_visible_on_branch(reverted_version_id, branch_id) {
+            return Err(eg!("version not found on this branch"));
+        }
+
+        // the states just before the reverted version
+        let inverse = self
+            .version_to_change_set
+            .get(&reverted_version_id)
+            .c(d!("BUG: change set not found"))?
+            .iter()
+            .map(|(k, _)| {
+                let v = reverted_version_id
+                    .checked_sub(1)
+                    .and_then(|prev| self.get_by_branch_version(&k, branch_id, prev));
+                (k, v)
+            })
+            .collect::<Vec<_>>();
+
+        self.version_create_by_branch(new_version_name, branch_id)
+            .c(d!())?;
+
+        for (k, v) in inverse {
+            if let Some(v) = v {
+                self.insert_by_branch(&k, &v, branch_id).c(d!())?;
+            } else {
+                self.remove_by_branch(&k, branch_id).c(d!())?;
+            }
+        }
 
         Ok(())
     }
 
+    // Check if a version is visible on a branch,
+    // that is, created by the branch itself or by its ancestors before the fork.
+    fn version_visible_on_branch(
+        &self,
+        version_id: VersionID,
+        branch_id: BranchID,
+    ) -> bool {
+        self.branch_get_full_path(branch_id)
+            .iter()
+            .any(|(br, ver)| {
+                version_id <= *ver && self.version_created_on_branch(version_id, *br)
+            })
+    }
+
+    // Attach a tag to a version,
+    // the tag can be used in place of the version name on any branch
+    // where the version is visible.
+    pub(super) fn version_tag(&self, version_id: VersionID, tag: &[u8]) -> Result<()> {
+        if !self.version_to_change_set.contains_key(&version_id) {
+            return Err(eg!("version not found"));
+        }
+
+        // tags of removed versions can be reused
+        if self
+            .version_tags
+            .get(tag)
+            .map(|ver| self.version_to_change_set.contains_key(&ver))
+            .unwrap_or(false)
+        {
+            return Err(eg!("tag already exists"));
+        }
+
+        self.version_tags
+            .insert(tag.to_owned().into_boxed_slice(), version_id);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub(super) fn version_untag(&self, tag: &[u8]) -> Result<()> {
+        self.version_tags
+            .remove(tag)
+            .c(d!("tag not found"))
+            .map(|_| ())
+    }
+
     // Check if the given version is bigger than the biggest existing version
     fn version_id_is_in_bounds(fp: &BranchPath, version_id: VersionID) -> bool {
         if let Some(max_version_id) = fp.values().last() {
@@ -650,6 +1568,9 @@ impl MapxRawVs {
         .c(d!())
     }
 
+    // Copy-on-write, nothing is copied here, only the base point is recorded,
+    // reads fall through to the ancestors until the key is written on the new
+    // branch, so the cost does not depend on the size of the base data.
     pub(super) fn branch_create_by_base_branch_version(
         &self,
         branch_name: &[u8],
@@ -664,14 +1585,13 @@ impl MapxRawVs {
         if !exist {
             return Err(eg!("version is not on the base branch"));
         }
-        if BRANCH_ANCESTORS_LIMIT < fp.len() {
+        if vsdb_get_branch_ancestors_limit() < fp.len() {
             return Err(eg!("the base branch has too many ancestors"));
         }
 
-        let branch_id = VSDB.alloc_branch_id();
+        let branch_id = self.db().alloc_branch_id();
 
-        self.branch_name_to_branch_id
-            .insert(branch_name.to_owned().into_boxed_slice(), branch_id);
+        self.branch_name_bind(branch_name, branch_id);
 
         // All new branches will have a base point,
         // the only exception is the initial branch created by system
@@ -684,15 +1604,113 @@ impl MapxRawVs {
         );
 
         self.branch_to_created_versions
-            .insert(branch_id, MapxOrd::new());
+            .insert(branch_id, self.in_db(MapxOrd::new));
 
         Ok(())
     }
 
+    // Re-point the name of a branch, its ID will not change,
+    // so all versions and data of this branch are kept intact.
+    pub(super) fn branch_rename(&self, old_name: &[u8], new_name: &[u8]) -> Result<()> {
+        if INITIAL_BRANCH_NAME == old_name {
+            return Err(eg!("the initial branch can NOT be renamed"));
+        }
+
+        if self.branch_name_to_branch_id.contains_key(new_name) {
+            return Err(eg!("branch already exists"));
+        }
+
+        let branch_id = self
+            .branch_name_to_branch_id
+            .get(old_name)
+            .c(d!("branch not found"))?;
+
+        self.branch_name_unbind(branch_id);
+        self.branch_name_bind(new_name, branch_id);
+
+        Ok(())
+    }
+
+    // Exchange the IDs bound to two names in one write batch.
+    //
+    // The policy of the initial branch name is the same as renaming,
+    // it must always be bound to a branch, and it still is after swapping,
+    // it will point to a branch that has a parent then.
+    pub(super) fn branch_swap(&self, name_a: &[u8], name_b: &[u8]) -> Result<()> {
+        if name_a == name_b {
+            return Err(eg!("can not swap a branch with itself"));
+        }
+
+        let id_a = self
+            .branch_name_to_branch_id
+            .get(name_a)
+            .c(d!("branch not found"))?;
+        let id_b = self
+            .branch_name_to_branch_id
+            .get(name_b)
+            .c(d!("branch not found"))?;
+        let raw_a = self.branch_name_to_branch_id.get_raw(name_a).c(d!())?;
+        let raw_b = self.branch_name_to_branch_id.get_raw(name_b).c(d!())?;
+
+        let names = self.branch_name_to_branch_id.as_raw();
+        let ids = self.branch_id_to_branch_name.as_raw();
+        let mut batch = WriteBatch::new();
+        batch.insert_raw(names, name_a, &raw_b);
+        batch.insert_raw(names, name_b, &raw_a);
+        batch.insert_raw(ids, &id_a.to_be_bytes(), name_b);
+        batch.insert_raw(ids, &id_b.to_be_bytes(), name_a);
+        batch.commit().c(d!())
+    }
+
     // Check if a branch exists or not
     #[inline(always)]
-    pub(super) fn branch_exists(&self, branch_id: BranchID) -> bool {
-        self.branch_to_parent.contains_key(&branch_id)
+    pub(super) fn branch_exists(&self, branch_id: BranchID) -> bool {
+        self.branch_to_parent.contains_key(&branch_id)
+    }
+
+    // All write operations on a frozen branch will fail,
+    // pruning is not limited, it does not change the view of the head.
+    #[inline(always)]
+    pub(super) fn branch_freeze(&self, branch_id: BranchID) -> Result<()> {
+        if !self.branch_exists(branch_id) {
+            return Err(eg!("branch not found"));
+        }
+        self.frozen_branches.insert(branch_id, ());
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub(super) fn branch_unfreeze(&self, branch_id: BranchID) -> Result<()> {
+        if !self.branch_exists(branch_id) {
+            return Err(eg!("branch not found"));
+        }
+        self.frozen_branches.remove(&branch_id);
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub(super) fn branch_is_frozen(&self, branch_id: BranchID) -> bool {
+        self.frozen_branches.contains_key(&branch_id)
+    }
+
+    #[inline(always)]
+    fn branch_check_writable(&self, branch_id: BranchID) -> Result<()> {
+        if self.branch_is_frozen(branch_id) {
+            return Err(eg!("branch is frozen"));
+        }
+        Ok(())
+    }
+
+    // Check whether the head version of a branch can be written,
+    // errors here are the same as those of `insert_by_branch`.
+    #[inline(always)]
+    pub(super) fn branch_check_head_writable(&self, branch_id: BranchID) -> Result<()> {
+        self.branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))?
+            .last()
+            .c(d!("no version on this branch, create a version first"))?;
+        self.branch_check_writable(branch_id).c(d!())
     }
 
     // Remove all changes directly made by this branch, and delete the branch itself.
@@ -704,6 +1722,8 @@ impl MapxRawVs {
     // and should not do any tracing.
     #[inline(always)]
     pub(super) fn branch_remove(&self, branch_id: BranchID) -> Result<()> {
+        self.branch_check_writable(branch_id).c(d!())?;
+
         if self.branch_has_children(branch_id) {
             return Err(eg!("can not remove branches with children"));
         }
@@ -715,14 +1735,11 @@ impl MapxRawVs {
         self.branch_truncate(branch_id).c(d!())?;
 
         self.branch_to_parent.remove(&branch_id);
+        self.branch_to_open_version.remove(&branch_id);
+        self.savepoint_drop_all(branch_id);
 
-        let branch_name = self
-            .branch_name_to_branch_id
-            .iter()
-            .find(|(_, id)| *id == branch_id)
-            .map(|(name, _)| name)
+        self.branch_name_unbind(branch_id)
             .c(d!("BUG: branch name not found"))?;
-        self.branch_name_to_branch_id.remove(&branch_name);
 
         let created_vers = self
             .branch_to_created_versions
@@ -771,6 +1788,29 @@ impl MapxRawVs {
         }
     }
 
+    // Same as `branch_truncate_to`, but all checks are done before removing,
+    // so the branch will not be left in a half-truncated state.
+    pub(super) fn version_rollback_to(
+        &self,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Result<()> {
+        self.branch_check_writable(branch_id).c(d!())?;
+
+        if !self.version_created_on_branch(version_id, branch_id) {
+            return Err(eg!("version is not created by this branch"));
+        }
+
+        if self.branch_to_parent.iter().any(|(_, bp)| {
+            matches!(bp, Some(bp) if bp.branch_id == branch_id
+                && version_id < bp.version_id)
+        }) {
+            return Err(eg!("some versions are used by other branches"));
+        }
+
+        self.branch_truncate_to(branch_id, version_id).c(d!())
+    }
+
     // 'Write'-like operations on branches and versions are different from operations on data.
     //
     // 'Write'-like operations on data require recursive tracing of all parent nodes,
@@ -798,6 +1838,10 @@ impl MapxRawVs {
 
         let parent_branch_id = fp.keys().rev().find(|&id| *id != branch_id).unwrap();
 
+        // both of them will be changed
+        self.branch_check_writable(branch_id).c(d!())?;
+        self.branch_check_writable(*parent_branch_id).c(d!())?;
+
         let vers_created = self.branch_to_created_versions.remove(&branch_id).unwrap();
 
         let vers_created_parent = self
@@ -811,6 +1855,11 @@ impl MapxRawVs {
         //     return Ok(());
         // }
 
+        // the states of the parent branch are changed since the oldest of them
+        if let Some((ver, _)) = vers_created.first() {
+            self.roots_invalidate(ver);
+        }
+
         for (ver, _) in vers_created.iter() {
             vers_created_parent.insert(ver, ());
 
@@ -836,40 +1885,665 @@ impl MapxRawVs {
 
                 key_hdr
                     .entry(*parent_branch_id)
-                    .or_insert(MapxOrd::new())
+                    .or_insert(self.in_db(MapxOrd::new))
                     .insert(ver, value);
             }
         }
 
-        // remove data on the original branch
-        vers_created.clear();
+        // remove data on the original branch
+        vers_created.clear();
+
+        self.branch_to_parent.remove(&branch_id);
+
+        // change the prefix of version names to the id of parent branch
+        let brbytes = branch_id.to_be_bytes();
+        let parent_brbytes = parent_branch_id.to_be_bytes();
+        self.version_name_to_version_id
+            .iter()
+            .for_each(|(vername, verid)| {
+                if vername.starts_with(&brbytes) {
+                    let mut new_vername = vername.to_vec();
+                    new_vername[..brbytes.len()].copy_from_slice(&parent_brbytes);
+                    self.version_name_to_version_id.remove(&vername).unwrap();
+                    self.version_name_to_version_id
+                        .insert(new_vername.into_boxed_slice(), verid);
+                }
+            });
+
+        // remove user-registered infomation
+        self.branch_name_unbind(branch_id).unwrap();
+
+        Ok(())
+    }
+
+    // Find keys that have been changed by both the branch and its parent
+    // since the fork point, and have different values on their heads.
+    pub(super) fn branch_merge_conflicts(
+        &self,
+        branch_id: BranchID,
+    ) -> Result<Vec<MergeConflict<RawKey, RawValue>>> {
+        let base = if let Some(bp) = self
+            .branch_to_parent
+            .get(&branch_id)
+            .c(d!("branch not found"))?
+        {
+            bp
+        } else {
+            // the initial branch has no parent
+            return Ok(vec![]);
+        };
+
+        let theirs = self.keys_changed_since(branch_id, Bound::Unbounded);
+        let ours =
+            self.keys_changed_since(base.branch_id, Bound::Excluded(base.version_id));
+
+        Ok(theirs
+            .intersection(&ours)
+            .filter_map(|k| {
+                let ours = self.get_by_branch_head(k, base.branch_id);
+                let theirs = self.get_by_branch_head(k, branch_id);
+                alt!(ours == theirs, return None);
+                Some(MergeConflict {
+                    key: k.clone(),
+                    ours,
+                    theirs,
+                })
+            })
+            .collect())
+    }
+
+    // Apply the resolutions of all conflicts in a new version of the branch,
+    // and then merge the branch to its parent, the two steps succeed or fail
+    // as a whole. The new version is named after the head of the branch with
+    // a "+resolved" suffix, so existing versions are kept untouched.
+    pub(super) fn branch_merge_to_parent_with_resolver(
+        &self,
+        branch_id: BranchID,
+        mut resolver: impl FnMut(
+            &[u8],
+            Option<&[u8]>,
+            Option<&[u8]>,
+        ) -> MergeResolution<RawValue>,
+    ) -> Result<()> {
+        let resolved = self
+            .branch_merge_conflicts(branch_id)
+            .c(d!())?
+            .into_iter()
+            .filter_map(|c| {
+                let r = resolver(&c.key[..], c.ours.as_deref(), c.theirs.as_deref());
+                let v = match r {
+                    MergeResolution::Ours => c.ours,
+                    MergeResolution::Theirs => return None,
+                    MergeResolution::Value(v) => v,
+                };
+                // the merged value is already the resolved one
+                alt!(v == c.theirs, return None);
+                Some((c.key, v))
+            })
+            .collect::<Vec<_>>();
+
+        if resolved.is_empty() {
+            return self.branch_merge_to_parent(branch_id).c(d!());
+        }
+
+        // conflicts exist only if the branch has created versions
+        let head = self.branch_get_head(branch_id).c(d!())?;
+        let mut vername = self
+            .version_names_of_branch(branch_id)
+            .remove(&head)
+            .c(d!())?;
+        vername.extend_from_slice(b"+resolved");
+
+        commit_atomically(|| {
+            self.version_create_by_branch(&vername, branch_id).c(d!())?;
+            for (k, v) in resolved.iter() {
+                if let Some(v) = v {
+                    self.insert_by_branch(k, v, branch_id).c(d!())?;
+                } else {
+                    self.remove_by_branch(k, branch_id).c(d!())?;
+                }
+            }
+            // the resolutions are complete, nothing is being built
+            self.version_finalize_by_branch(branch_id).c(d!())?;
+            self.branch_merge_to_parent(branch_id).c(d!())
+        })
+    }
+
+    // Merge the changes of `theirs` since the base version into `ours`,
+    // both sides are compared with the base version instead of each other,
+    // so the two branches can have any relationship.
+    //
+    // Keys changed by both sides to different values keep the values of
+    // `ours`, and they are returned as conflicts.
+    pub(super) fn branch_merge_3way(
+        &self,
+        ours: BranchID,
+        theirs: BranchID,
+        base_version: VersionID,
+    ) -> Result<Vec<MergeConflict<RawKey, RawValue>>> {
+        if !self.branch_exists(ours) || !self.branch_exists(theirs) {
+            return Err(eg!("branch not found"));
+        }
+        if ours == theirs {
+            return Err(eg!("can not merge a branch into itself"));
+        }
+        self.branch_check_writable(ours).c(d!())?;
+        if !self.version_visible_on_branch(base_version, ours)
+            || !self.version_visible_on_branch(base_version, theirs)
+        {
+            return Err(eg!("the base version is not shared by the two branches"));
+        }
+
+        let mut changes = vec![];
+        let mut conflicts = vec![];
+
+        // keys unchanged by `theirs` since the base version need nothing,
+        // whatever `ours` has done to them
+        let keys =
+            self.keys_changed_on_path_since(theirs, Bound::Excluded(base_version));
+
+        for k in keys {
+            let base = self.get_by_branch_version(&k, ours, base_version);
+            let o = self.get_by_branch_head(&k, ours);
+            let t = self.get_by_branch_head(&k, theirs);
+            if t == base || t == o {
+                continue;
+            }
+            if o == base {
+                changes.push((k, t));
+            } else {
+                conflicts.push(MergeConflict {
+                    key: k,
+                    ours: o,
+                    theirs: t,
+                });
+            }
+        }
+
+        for (k, v) in changes {
+            if let Some(v) = v {
+                self.insert_by_branch(&k, &v, ours).c(d!())?;
+            } else {
+                self.remove_by_branch(&k, ours).c(d!())?;
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    // All keys changed by the versions newer than the lower bound,
+    // in the view of the branch, that is, on its full path.
+    fn keys_changed_on_path_since(
+        &self,
+        branch_id: BranchID,
+        lower_bound: Bound<VersionID>,
+    ) -> BTreeSet<RawKey> {
+        let mut ret = BTreeSet::new();
+        for (br, last_ver) in self.branch_get_full_path(branch_id).into_iter() {
+            if let Bound::Excluded(ver) = lower_bound {
+                alt!(last_ver <= ver, continue);
+            }
+            let vers = if let Some(vers) = self.branch_to_created_versions.get(&br) {
+                vers
+            } else {
+                continue;
+            };
+            for (ver, _) in vers.range((lower_bound, Bound::Included(last_ver))) {
+                if let Some(chgset) = self.version_to_change_set.get(&ver) {
+                    chgset.iter().for_each(|(k, _)| {
+                        ret.insert(k);
+                    });
+                }
+            }
+        }
+        ret
+    }
+
+    // All keys changed by the versions that are created by the branch
+    // and newer than the lower bound.
+    fn keys_changed_since(
+        &self,
+        branch_id: BranchID,
+        lower_bound: Bound<VersionID>,
+    ) -> BTreeSet<RawKey> {
+        let mut ret = BTreeSet::new();
+        if let Some(vers) = self.branch_to_created_versions.get(&branch_id) {
+            for (ver, _) in vers.range((lower_bound, Bound::Unbounded)) {
+                if let Some(chgset) = self.version_to_change_set.get(&ver) {
+                    chgset.iter().for_each(|(k, _)| {
+                        ret.insert(k);
+                    });
+                }
+            }
+        }
+        ret
+    }
+
+    // Replay all versions created by a branch on top of a new base point,
+    // every replayed version will get a new ID, but keep its name.
+    //
+    // NOTE:
+    // - the branch must have no children, and no savepoints
+    // - the new parent must be created earlier than the branch
+    pub(super) fn branch_rebase(
+        &self,
+        branch_id: BranchID,
+        new_parent_branch_id: BranchID,
+        new_parent_version_id: VersionID,
+    ) -> Result<()> {
+        if !self.branch_exists(branch_id) {
+            return Err(eg!("branch not found"));
+        }
+
+        self.branch_check_writable(branch_id).c(d!())?;
+
+        if self.branch_has_children(branch_id) {
+            return Err(eg!("can not rebase branches with children"));
+        }
+
+        // savepoints are bound to the ID of the head version,
+        // which will be changed by the replay
+        if self.savepoint_exists(branch_id) {
+            return Err(eg!("can not rebase branches with savepoints"));
+        }
+
+        // branches are searched in the order of their IDs,
+        // so a parent branch must be older than its children
+        if new_parent_branch_id >= branch_id {
+            return Err(eg!("the new parent must be older than the branch"));
+        }
+
+        let (exist, fp) =
+            self.version_exists_on_branch(new_parent_version_id, new_parent_branch_id);
+        if !exist {
+            return Err(eg!("version is not on the new parent branch"));
+        }
+        if vsdb_get_branch_ancestors_limit() < fp.len() {
+            return Err(eg!("the new parent branch has too many ancestors"));
+        }
+
+        let vers = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("BUG: created versions missing"))?;
+
+        // collect them first, new versions will be inserted during the replay
+        let old_vers = vers.iter().map(|(ver, _)| ver).collect::<Vec<_>>();
+        if let Some(ver) = old_vers.first() {
+            self.roots_invalidate(*ver);
+        }
+
+        // built once, instead of being searched for each version
+        let mut tags = BTreeMap::<VersionID, Vec<RawKey>>::new();
+        self.version_tags.iter().for_each(|(tag, ver)| {
+            tags.entry(ver).or_default().push(tag);
+        });
+
+        // Copy every version to a new ID first, the copies are removed
+        // by `commit_atomically` if anything goes wrong, the original
+        // versions are left untouched until all of them have been copied.
+        let mut replayed = Vec::with_capacity(old_vers.len());
+        commit_atomically(|| {
+            for &ver in old_vers.iter() {
+                // new version IDs are always bigger than the new base point
+                let new_ver = self.db().alloc_version_id();
+                commit_journal_record(self, new_ver, branch_id).c(d!())?;
+                vers.insert(new_ver, ());
+
+                let chgset = self
+                    .version_to_change_set
+                    .get(&ver)
+                    .c(d!("BUG: change set not found"))?;
+                let new_chgset = self.in_db(MapxRaw::new);
+                self.version_to_change_set
+                    .insert(new_ver, new_chgset.clone());
+                for (k, _) in chgset.iter() {
+                    let br_hdr = self
+                        .layered_kv
+                        .get(&k)
+                        .and_then(|brs| brs.get(&branch_id))
+                        .c(d!("BUG: branch data not found"))?;
+                    let v = br_hdr.get(&ver).c(d!("BUG: value not found"))?;
+                    new_chgset.insert(&k, &[]);
+                    br_hdr.insert(new_ver, v);
+                }
+
+                replayed.push((ver, new_ver));
+            }
+            Ok(())
+        })
+        .c(d!())?;
+
+        // switch to the copies, nothing can fail from here on
+        for (ver, new_ver) in replayed {
+            if let Some(chgset) = self.version_to_change_set.remove(&ver) {
+                for (k, _) in chgset.iter() {
+                    if let Some(br_hdr) =
+                        self.layered_kv.get(&k).and_then(|brs| brs.get(&branch_id))
+                    {
+                        br_hdr.remove(&ver);
+                    }
+                }
+                chgset.clear();
+            }
+            if let Some(ts) = self.version_to_timestamp.remove(&ver) {
+                self.version_to_timestamp.insert(new_ver, ts);
+            }
+            if let Some(meta) = self.version_to_meta.remove(&ver) {
+                self.version_to_meta.insert(new_ver, meta);
+            }
+            if Some(ver) == self.branch_to_open_version.get(&branch_id) {
+                self.branch_to_open_version.insert(branch_id, new_ver);
+            }
+
+            vers.remove(&ver);
+
+            if let Some(name) = self.version_id_to_version_name.remove(&ver) {
+                self.version_name_bind(branch_id, &name, new_ver);
+            }
+
+            // tags follow the replayed versions
+            tags.get(&ver).into_iter().flatten().for_each(|tag| {
+                self.version_tags.insert_ref(tag, &new_ver);
+            });
+        }
+
+        self.branch_to_parent.insert(
+            branch_id,
+            Some(BasePoint {
+                branch_id: new_parent_branch_id,
+                version_id: new_parent_version_id,
+            }),
+        );
+
+        Ok(())
+    }
+
+    // Export the versions directly created by a branch(in their creation order),
+    // along with all changes made within each of them.
+    pub(super) fn export_versions<R: RangeBounds<VersionID>>(
+        &self,
+        branch_id: BranchID,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        let vers = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))?;
+
+        let names = self.version_names_of_branch(branch_id);
+        let mut ret = VersionBundle::default();
+
+        for (ver, _) in vers.range(bounds) {
+            let changes = self
+                .version_to_change_set
+                .get(&ver)
+                .c(d!("BUG: change set not found"))?
+                .iter()
+                .filter_map(|(k, _)| {
+                    let v = self.layered_kv.get(&k)?.get(&branch_id)?.get(&ver)?;
+                    Some((k, v))
+                })
+                .collect();
+            let name = names.get(&ver).c(d!("BUG: version name not found"))?;
+            ret.versions.push(BundledVersion {
+                name: name.clone(),
+                changes,
+            });
+        }
+
+        Ok(ret)
+    }
+
+    // List all versions directly created on the branch,
+    // in the order of their creation.
+    pub(super) fn version_list_by_branch(
+        &self,
+        branch_id: BranchID,
+    ) -> Result<Vec<VersionInfo>> {
+        let vers = self
+            .branch_to_created_versions
+            .get(&branch_id)
+            .c(d!("branch not found"))?;
+        let names = self.version_names_of_branch(branch_id);
+
+        vers.iter()
+            .map(|(ver, _)| {
+                let name = names.get(&ver).c(d!("BUG: version name not found"))?;
+                let change_count = self
+                    .version_to_change_set
+                    .get(&ver)
+                    .c(d!("BUG: change set not found"))?
+                    .len();
+                let created_at = self.version_to_timestamp.get(&ver).unwrap_or_default();
+                Ok(VersionInfo {
+                    name: name.clone(),
+                    created_at,
+                    change_count,
+                })
+            })
+            .collect()
+    }
+
+    // The inner version names are prefixed by their branch IDs.
+    fn version_names_of_branch(
+        &self,
+        branch_id: BranchID,
+    ) -> BTreeMap<VersionID, Vec<u8>> {
+        let prefix = branch_id.to_be_bytes();
+        self.version_name_to_version_id
+            .range_ref(&prefix[..]..)
+            .take_while(|(name, _)| name.starts_with(&prefix))
+            .map(|(name, id)| (id, name[prefix.len()..].to_vec()))
+            .collect()
+    }
+
+    // Bind a name to a branch, along with its reverse index.
+    #[inline(always)]
+    fn branch_name_bind(&self, branch_name: &[u8], branch_id: BranchID) {
+        self.branch_name_to_branch_id
+            .insert_ref(branch_name, &branch_id);
+        self.branch_id_to_branch_name
+            .insert_ref(&branch_id, branch_name);
+    }
+
+    // Remove the name of a branch, along with its reverse index.
+    #[inline(always)]
+    fn branch_name_unbind(&self, branch_id: BranchID) -> Option<RawValue> {
+        let name = self.branch_id_to_branch_name.remove(&branch_id)?;
+        self.branch_name_to_branch_id.remove(&name);
+        Some(name)
+    }
+
+    #[inline(always)]
+    fn branch_get_name(&self, branch_id: BranchID) -> Option<Vec<u8>> {
+        self.branch_id_to_branch_name
+            .get(&branch_id)
+            .map(|name| name.to_vec())
+    }
+
+    // Bind a name to a version created by the branch,
+    // the inner name is prefixed by the branch ID, the reverse one is not.
+    #[inline(always)]
+    fn version_name_bind(
+        &self,
+        branch_id: BranchID,
+        version_name: &[u8],
+        version_id: VersionID,
+    ) {
+        let mut vername = branch_id.to_be_bytes().to_vec();
+        vername.extend_from_slice(version_name);
+        self.version_name_to_version_id
+            .insert_ref(&vername, &version_id);
+        self.version_id_to_version_name
+            .insert_ref(&version_id, version_name);
+    }
+
+    // Remove the name of a version created by the branch,
+    // along with its reverse index.
+    #[inline(always)]
+    fn version_name_unbind(
+        &self,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Option<RawValue> {
+        let name = self.version_id_to_version_name.remove(&version_id)?;
+        let mut vername = branch_id.to_be_bytes().to_vec();
+        vername.extend_from_slice(&name);
+        self.version_name_to_version_id.remove(&vername);
+        Some(name)
+    }
+
+    #[inline(always)]
+    fn version_get_name(&self, version_id: VersionID) -> Option<Vec<u8>> {
+        self.version_id_to_version_name
+            .get(&version_id)
+            .map(|name| name.to_vec())
+    }
+
+    // Fill the reverse indexes of the names,
+    // for the instances created before they are introduced.
+    fn name_indexes_rebuild(&self) {
+        self.branch_name_to_branch_id.iter().for_each(|(name, id)| {
+            self.branch_id_to_branch_name.insert_ref(&id, &name);
+        });
+        self.version_name_to_version_id
+            .iter()
+            .for_each(|(name, id)| {
+                self.version_id_to_version_name
+                    .insert_ref(&id, &name[mem::size_of::<BranchID>()..]);
+            });
+    }
+
+    // Create a new branch on the head of the base branch,
+    // and then rebuild all versions of the bundle on it.
+    pub(super) fn branch_create_from_bundle(
+        &self,
+        branch_name: &[u8],
+        base_branch_id: BranchID,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        let mut names = BTreeSet::new();
+        if !bundle.versions.iter().all(|v| names.insert(&v.name[..])) {
+            return Err(eg!("duplicate version names in the bundle"));
+        }
+
+        self.branch_create_by_base_branch(branch_name, base_branch_id)
+            .c(d!())?;
+        let branch_id = self
+            .get_branch_id(BranchName(branch_name))
+            .c(d!("BUG: branch not found"))?;
+
+        self.bundle_rebuild(branch_id, bundle).c(d!())
+    }
+
+    // Rebuild all versions of the bundle on the head of an existing branch,
+    // like `git am`, nothing will be left if any of them fails.
+    pub(super) fn branch_apply_bundle(
+        &self,
+        branch_id: BranchID,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        if !self.branch_exists(branch_id) {
+            return Err(eg!("branch not found"));
+        }
+        self.branch_check_writable(branch_id).c(d!())?;
+
+        let mut names = BTreeSet::new();
+        if !bundle.versions.iter().all(|v| names.insert(&v.name[..])) {
+            return Err(eg!("duplicate version names in the bundle"));
+        }
+
+        commit_atomically(|| self.bundle_rebuild(branch_id, bundle).c(d!()))
+    }
+
+    fn bundle_rebuild(&self, branch_id: BranchID, bundle: &VersionBundle) -> Result<()> {
+        for v in bundle.versions.iter() {
+            self.version_create_by_branch(&v.name, branch_id).c(d!())?;
+            let ver = self
+                .branch_get_head(branch_id)
+                .c(d!("BUG: version not found"))?;
+            for (k, value) in v.changes.iter() {
+                self.write_by_branch_version(k, value.as_deref(), branch_id, ver)
+                    .c(d!())?;
+            }
+        }
+        Ok(())
+    }
+
+    // All existing branches, sorted by their names.
+    pub(super) fn branch_list(&self) -> Vec<BranchInfo> {
+        let br_names = self
+            .branch_name_to_branch_id
+            .iter()
+            .map(|(name, id)| (id, name))
+            .collect::<BTreeMap<_, _>>();
 
-        self.branch_to_parent.remove(&branch_id);
+        // the inner version names are prefixed by their branch IDs
+        let ver_names = self
+            .version_name_to_version_id
+            .iter()
+            .map(|(name, id)| (id, name[mem::size_of::<BranchID>()..].to_vec()))
+            .collect::<BTreeMap<_, _>>();
 
-        // change the prefix of version names to the id of parent branch
-        let brbytes = branch_id.to_be_bytes();
-        let parent_brbytes = parent_branch_id.to_be_bytes();
-        self.version_name_to_version_id
+        self.branch_name_to_branch_id
             .iter()
-            .for_each(|(vername, verid)| {
-                if vername.starts_with(&brbytes) {
-                    let mut new_vername = vername.to_vec();
-                    new_vername[..brbytes.len()].copy_from_slice(&parent_brbytes);
-                    self.version_name_to_version_id.remove(&vername).unwrap();
-                    self.version_name_to_version_id
-                        .insert(new_vername.into_boxed_slice(), verid);
+            .map(|(name, id)| {
+                let bp = self.branch_to_parent.get(&id).flatten();
+                BranchInfo {
+                    name: name.to_vec(),
+                    parent: bp
+                        .and_then(|bp| br_names.get(&bp.branch_id))
+                        .map(|n| n.to_vec()),
+                    base_version: bp
+                        .and_then(|bp| ver_names.get(&bp.version_id).cloned()),
                 }
-            });
+            })
+            .collect()
+    }
 
-        // remove user-registered infomation
-        let (br_name, _) = self
-            .branch_name_to_branch_id
-            .iter()
-            .find(|(_, br)| *br == branch_id)
-            .unwrap();
-        self.branch_name_to_branch_id.remove(&br_name);
+    // Check if `branch_a` is an ancestor of `branch_b`,
+    // a branch is not an ancestor of itself.
+    pub(super) fn branch_is_ancestor(
+        &self,
+        branch_a: BranchID,
+        branch_b: BranchID,
+    ) -> bool {
+        branch_a != branch_b
+            && self.branch_get_full_path(branch_b).contains_key(&branch_a)
+    }
 
-        Ok(())
+    // Find the newest version that is visible on both branches,
+    // return its `(branch name, version name)`.
+    pub(super) fn branch_merge_base(
+        &self,
+        branch_a: BranchID,
+        branch_b: BranchID,
+    ) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (br, ver) = self.branch_merge_base_version(branch_a, branch_b)?;
+        Some((self.branch_get_name(br)?, self.version_get_name(ver)?))
+    }
+
+    // Find the newest version that is visible on both branches,
+    // return its `(branch ID, version ID)`.
+    fn branch_merge_base_version(
+        &self,
+        branch_a: BranchID,
+        branch_b: BranchID,
+    ) -> Option<(BranchID, VersionID)> {
+        let fp_a = self.branch_get_full_path(branch_a);
+        let fp_b = self.branch_get_full_path(branch_b);
+
+        fp_a.iter()
+            .filter_map(|(br, ver_a)| {
+                let limit = fp_b.get(br).map(|ver_b| *ver_a.min(ver_b))?;
+                self.branch_to_created_versions
+                    .get(br)?
+                    .range(..=limit)
+                    .next_back()
+                    .map(|(ver, _)| (*br, ver))
+            })
+            .max_by_key(|(_, ver)| *ver)
     }
 
     pub(super) fn branch_has_children(&self, branch_id: BranchID) -> bool {
@@ -879,10 +2553,22 @@ impl MapxRawVs {
             .any(|p| p.branch_id == branch_id)
     }
 
+    // How many ancestral branches it has, `None` if the branch does not exist.
+    pub(super) fn branch_depth(&self, branch_id: BranchID) -> Option<usize> {
+        alt!(!self.branch_exists(branch_id), return None);
+        let mut depth = 0;
+        let mut br = branch_id;
+        while let Some(Some(bp)) = self.branch_to_parent.get(&br) {
+            depth += 1;
+            br = bp.branch_id;
+        }
+        Some(depth)
+    }
+
     // Get itself and all its ancestral branches with the base point it born on.
     #[inline(always)]
     fn branch_get_full_path(&self, branch_id: BranchID) -> BranchPath {
-        self.branch_get_recurive_path(branch_id, BRANCH_ANCESTORS_LIMIT)
+        self.branch_get_recurive_path(branch_id, vsdb_get_branch_ancestors_limit())
     }
 
     fn branch_get_recurive_path(
@@ -934,12 +2620,25 @@ impl MapxRawVs {
         self.prune_by_branch(self.branch_get_default(), reserved_ver_num)
     }
 
+    #[inline(always)]
     pub(super) fn prune_by_branch(
         &self,
         branch_id: BranchID,
         reserved_ver_num: Option<usize>,
     ) -> Result<()> {
-        let reserved_ver_num = reserved_ver_num.unwrap_or(RESERVED_VERSION_NUM_DEFAULT);
+        let policy = PrunePolicy {
+            keep_last: reserved_ver_num,
+            ..Default::default()
+        };
+        self.prune_by_branch_with(branch_id, &policy).c(d!())
+    }
+
+    pub(super) fn prune_by_branch_with(
+        &self,
+        branch_id: BranchID,
+        policy: &PrunePolicy,
+    ) -> Result<()> {
+        let reserved_ver_num = policy.keep_last.unwrap_or(RESERVED_VERSION_NUM_DEFAULT);
         if 0 == reserved_ver_num {
             return Err(eg!("reserved version number should NOT be zero"));
         }
@@ -954,13 +2653,47 @@ impl MapxRawVs {
             return Ok(());
         }
 
-        let guard_ver_id = created_vers
+        let mut guard_ver_id = created_vers
             .iter()
             .rev()
             .nth(reserved_ver_num - 1)
             .map(|(ver, _)| ver)
             .unwrap();
 
+        if let Some(d) = policy.keep_newer_than {
+            let threshold = clock::now().saturating_sub(d.as_secs());
+            if let Some((ver_id, _)) = created_vers
+                .iter()
+                .take_while(|(ver, _)| *ver < guard_ver_id)
+                .find(|(ver, _)| {
+                    self.version_to_timestamp
+                        .get(ver)
+                        .map(|ts| ts >= threshold)
+                        .unwrap_or(false)
+                })
+            {
+                guard_ver_id = ver_id;
+            }
+        }
+
+        if policy.keep_tagged {
+            for (_, ver_id) in self.version_tags.iter() {
+                if ver_id < guard_ver_id && created_vers.contains_key(&ver_id) {
+                    guard_ver_id = ver_id;
+                }
+            }
+        }
+
+        // versions pinned by snapshots must be kept
+        for (br, ver) in snapshot::pinned_versions(self.instance_id()) {
+            if let Some(ver_id) = self.get_version_id(BranchName(&br), VersionName(&ver))
+            {
+                if ver_id < guard_ver_id && created_vers.contains_key(&ver_id) {
+                    guard_ver_id = ver_id;
+                }
+            }
+        }
+
         for (key, _) in self
             .layered_kv
             .iter()
@@ -969,34 +2702,87 @@ impl MapxRawVs {
             let key_hdr = self.layered_kv.get_mut(&key).unwrap();
             let br_hdr = key_hdr.get_mut(&branch_id).unwrap();
 
-            // keep one version at least
+            // keep one version at least, and the view of
+            // the guard version depends on the newest one before it
             for (ver, _) in br_hdr
                 .iter()
                 .rev()
-                .skip(1)
                 .filter(|(ver, _)| *ver < guard_ver_id)
+                .skip(1)
             {
                 br_hdr.remove(&ver);
             }
         }
 
-        for (ver, _) in created_vers.iter().rev().skip(reserved_ver_num) {
+        for (ver, _) in created_vers
+            .iter()
+            .take_while(|(ver, _)| *ver < guard_ver_id)
+        {
             created_vers.remove(&ver);
-            self.version_to_change_set.remove(&ver);
+            if let Some(chgset) = self.version_to_change_set.remove(&ver) {
+                chgset.clear();
+            }
+            self.version_to_timestamp.remove(&ver);
+            self.version_to_meta.remove(&ver);
+
+            // the states of other versions are not changed
+            if let Some(roots) = self.roots.as_ref() {
+                roots.version_to_root.remove(&ver);
+            }
 
             // one version belong(directly) to one branch only,
             // so we can remove these created versions safely.
-            let (vername, _) = self
-                .version_name_to_version_id
-                .iter()
-                .find(|(_, v)| *v == ver)
-                .unwrap();
-            self.version_name_to_version_id.remove(&vername);
+            self.version_name_unbind(branch_id, ver).unwrap();
         }
 
         Ok(())
     }
 
+    // Physically delete the data that can not be reached from any branch:
+    // - values left by removed branches, e.g. the ones kept by pruning
+    // - empty containers of keys and branches
+    // - tags of removed versions
+    // - nodes of Merkle trees that only belong to removed versions,
+    //   or to outdated roots, e.g. those of the heads before later writes
+    //
+    // Returns the number of deleted values, tags and nodes.
+    pub(super) fn gc(&self) -> usize {
+        let mut cnt = 0;
+
+        for (key, brs) in self.layered_kv.iter() {
+            for (br, vers) in brs.iter() {
+                if !self.branch_exists(br) || vers.is_empty() {
+                    cnt += vers.len();
+                    vers.clear();
+                    brs.remove(&br);
+                }
+            }
+            if brs.is_empty() {
+                self.layered_kv.remove(&key);
+            }
+        }
+
+        for (tag, ver) in self.version_tags.iter() {
+            if !self.version_to_change_set.contains_key(&ver) {
+                self.version_tags.remove(&tag);
+                cnt += 1;
+            }
+        }
+
+        if let Some(roots) = self.roots.as_ref() {
+            for (ver, _) in roots.version_to_root.iter() {
+                if !self.version_to_change_set.contains_key(&ver) {
+                    roots.version_to_root.remove(&ver);
+                }
+            }
+            cnt += roots
+                .nodes
+                .retain(roots.version_to_root.iter().map(|(_, root)| root));
+        }
+
+        cnt
+    }
+
     #[inline(always)]
     pub(super) fn get_branch_id(&self, branch_name: BranchName) -> Option<BranchID> {
         self.branch_name_to_branch_id.get(branch_name.0)
@@ -1008,9 +2794,167 @@ impl MapxRawVs {
         branch_name: BranchName,
         version_name: VersionName,
     ) -> Option<VersionID> {
-        let mut vername = self.get_branch_id(branch_name)?.to_be_bytes().to_vec();
+        self.get_branch_id(branch_name)
+            .and_then(|br_id| self.get_version_id_by_branch_id(br_id, version_name))
+    }
+
+    #[inline(always)]
+    pub(super) fn get_version_id_by_branch_id(
+        &self,
+        branch_id: BranchID,
+        version_name: VersionName,
+    ) -> Option<VersionID> {
+        let mut vername = branch_id.to_be_bytes().to_vec();
         vername.extend_from_slice(version_name.0);
-        self.version_name_to_version_id.get(&vername)
+        self.version_name_to_version_id.get(&vername).or_else(|| {
+            // fallback to tags, real version names take precedence
+            self.version_tags
+                .get(version_name.0)
+                .filter(|ver| self.version_visible_on_branch(*ver, branch_id))
+        })
+    }
+
+    // Find a visible version by its name,
+    // it may be created by the branch itself or any of its ancestors.
+    pub(super) fn get_visible_version_id(
+        &self,
+        branch_id: BranchID,
+        version_name: VersionName,
+    ) -> Option<VersionID> {
+        self.branch_get_full_path(branch_id)
+            .keys()
+            .rev()
+            .filter_map(|br| self.get_version_id_by_branch_id(*br, version_name))
+            .find(|ver| self.version_visible_on_branch(*ver, branch_id))
+    }
+
+    #[inline(always)]
+    pub(super) fn roots_enabled(&self) -> bool {
+        self.roots.is_some()
+    }
+
+    // The Merkle root of the head state of a branch.
+    pub(super) fn root_by_branch(&self, branch_id: BranchID) -> Result<Hash32> {
+        let roots = self.roots.as_ref().c(d!("Merkle roots are not enabled"))?;
+        let version_id = self.branch_get_head(branch_id).c(d!("branch not found"))?;
+        Ok(self.root_by_path(roots, branch_id, version_id))
+    }
+
+    // The Merkle root of the state of a branch at a historical version,
+    // it is kept once calculated, until the history before it is rewritten.
+    pub(super) fn root_by_branch_version(
+        &self,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Result<Hash32> {
+        let roots = self.roots.as_ref().c(d!("Merkle roots are not enabled"))?;
+        if !self.version_visible_on_branch(version_id, branch_id) {
+            return Err(eg!("version not found on this branch"));
+        }
+        Ok(self.root_by_path(roots, branch_id, version_id))
+    }
+
+    // A proof of the value(or the absence) of a key in the head state of a branch.
+    pub(super) fn prove_by_branch(
+        &self,
+        key: &[u8],
+        branch_id: BranchID,
+    ) -> Result<SparseMerkleProof> {
+        let roots = self.roots.as_ref().c(d!("Merkle roots are not enabled"))?;
+        let root = self.root_by_branch(branch_id).c(d!())?;
+        Ok(roots.nodes.prove(&root, key))
+    }
+
+    pub(super) fn prove_by_branch_version(
+        &self,
+        key: &[u8],
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Result<SparseMerkleProof> {
+        let roots = self.roots.as_ref().c(d!("Merkle roots are not enabled"))?;
+        let root = self.root_by_branch_version(branch_id, version_id).c(d!())?;
+        Ok(roots.nodes.prove(&root, key))
+    }
+
+    // Versions newer than the nearest one with a known root are replayed
+    // on that root, only the keys changed within each of them are touched;
+    // the root is rebuilt from all entries if no known roots can be found,
+    // e.g. the older versions have been pruned.
+    //
+    // All the calculated roots are kept for later queries.
+    fn root_by_path(
+        &self,
+        roots: &VersionRoots,
+        branch_id: BranchID,
+        version_id: VersionID,
+    ) -> Hash32 {
+        let fp = self.branch_get_full_path(branch_id);
+
+        let mut pending = vec![];
+        let mut base = None;
+        let mut cur = self.version_visible_by_path(&fp, version_id);
+        while let Some(ver) = cur {
+            if let Some(root) = roots.version_to_root.get(&ver) {
+                base = Some(root);
+                break;
+            }
+            pending.push(ver);
+            cur = ver
+                .checked_sub(1)
+                .and_then(|prev| self.version_visible_by_path(&fp, prev));
+        }
+
+        let mut root = if let Some(root) = base {
+            root
+        } else if let Some(ver) = pending.first().copied() {
+            let root = self
+                .iter_by_branch_version(branch_id, ver)
+                .fold(EMPTY_ROOT, |root, (k, v)| roots.nodes.insert(&root, &k, &v));
+            roots.version_to_root.insert(ver, root);
+            return root;
+        } else {
+            // nothing has been written
+            return EMPTY_ROOT;
+        };
+
+        for ver in pending.into_iter().rev() {
+            if let Some(chgset) = self.version_to_change_set.get(&ver) {
+                for (k, _) in chgset.iter() {
+                    root = if let Some(v) = self.get_by_path(&k, &fp, ver) {
+                        roots.nodes.insert(&root, &k, &v)
+                    } else {
+                        roots.nodes.remove(&root, &k)
+                    };
+                }
+            }
+            roots.version_to_root.insert(ver, root);
+        }
+
+        root
+    }
+
+    // The newest version on the path that is not newer than `version_id`.
+    fn version_visible_by_path(
+        &self,
+        fp: &BranchPath,
+        version_id: VersionID,
+    ) -> Option<VersionID> {
+        fp.iter()
+            .filter_map(|(br, ver)| {
+                self.branch_to_created_versions
+                    .get(br)?
+                    .get_le(&min!(*ver, version_id))
+                    .map(|(ver, _)| ver)
+            })
+            .max()
+    }
+
+    // The states since `from` may be changed.
+    #[inline(always)]
+    fn roots_invalidate(&self, from: VersionID) {
+        if let Some(roots) = self.roots.as_ref() {
+            roots.invalidate(from);
+        }
     }
 }
 
@@ -1020,6 +2964,146 @@ impl Default for MapxRawVs {
     }
 }
 
+// Serialized fields of `MapxRawVs`, the same as the ones of the first release,
+// so the handles encoded by older releases can still be decoded,
+// also by the codecs that find fields by their order.
+#[derive(Serialize, Deserialize)]
+struct Layout {
+    default_branch: BranchID,
+    branch_name_to_branch_id: MapxOrdRawKey<BranchID>,
+    version_name_to_version_id: MapxOrdRawKey<VersionID>,
+    branch_to_parent: MapxOrd<BranchID, Option<BasePoint>>,
+    branch_to_created_versions: MapxOrd<BranchID, MapxOrd<VersionID, ()>>,
+    version_to_change_set: MapxOrd<VersionID, MapxRaw>,
+    layered_kv: MapxOrdRawKey<MapxOrd<BranchID, MapxOrd<VersionID, Option<RawValue>>>>,
+}
+
+// Fields of `MapxRawVs` added after the first release.
+//
+// They are kept in a registry of the database keyed by the ID of `layered_kv`,
+// and created the first time a handle without them is decoded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Extension {
+    version_tags: MapxOrdRawKey<VersionID>,
+    version_to_timestamp: MapxOrd<VersionID, u64>,
+    version_to_meta: MapxOrd<VersionID, VersionMeta>,
+    frozen_branches: MapxOrd<BranchID, ()>,
+    roots: Option<VersionRoots>,
+    read_isolation: Orphan<bool>,
+    branch_to_open_version: MapxOrd<BranchID, VersionID>,
+    branch_to_savepoints: MapxOrd<BranchID, SavepointLog>,
+    branch_id_to_branch_name: MapxOrdRawValue<BranchID>,
+    version_id_to_version_name: MapxOrdRawValue<VersionID>,
+}
+
+impl Extension {
+    #[inline(always)]
+    fn new() -> Self {
+        Self {
+            version_tags: MapxOrdRawKey::new(),
+            version_to_timestamp: MapxOrd::new(),
+            version_to_meta: MapxOrd::new(),
+            frozen_branches: MapxOrd::new(),
+            roots: None,
+            read_isolation: Orphan::new(false),
+            branch_to_open_version: MapxOrd::new(),
+            branch_to_savepoints: MapxOrd::new(),
+            branch_id_to_branch_name: MapxOrdRawValue::new(),
+            version_id_to_version_name: MapxOrdRawValue::new(),
+        }
+    }
+
+    #[inline(always)]
+    fn registry(db: database::Db) -> MapxRaw {
+        MapxRaw::open_reserved_in(db, VS_EXTENSION_REGISTRY)
+    }
+
+    fn get_or_create(db: database::Db, id: u64) -> Self {
+        // an extension is created only once
+        static LK: Mutex<()> = parking_lot::const_mutex(());
+        let _guard = LK.lock();
+
+        let registry = Self::registry(db);
+        if let Some(ext) = registry.get(&id.to_be_bytes()) {
+            return pnk!(database::scoped(db, || Self::decode_value(&ext)));
+        }
+
+        let ext = database::scoped(db, Self::new);
+        registry.insert(&id.to_be_bytes(), &ext.encode_value());
+        ext
+    }
+}
+
+impl MapxRawVs {
+    // Record the fields that are not in the `Layout`.
+    fn extension_save(&self) {
+        let ext = Extension {
+            version_tags: self.version_tags.clone(),
+            version_to_timestamp: self.version_to_timestamp.clone(),
+            version_to_meta: self.version_to_meta.clone(),
+            frozen_branches: self.frozen_branches.clone(),
+            roots: self.roots.clone(),
+            read_isolation: self.read_isolation.clone(),
+            branch_to_open_version: self.branch_to_open_version.clone(),
+            branch_to_savepoints: self.branch_to_savepoints.clone(),
+            branch_id_to_branch_name: self.branch_id_to_branch_name.clone(),
+            version_id_to_version_name: self.version_id_to_version_name.clone(),
+        };
+        Extension::registry(self.db()).insert(
+            &self.layered_kv.as_raw().id().to_be_bytes(),
+            &ext.encode_value(),
+        );
+    }
+}
+
+impl From<Layout> for MapxRawVs {
+    fn from(l: Layout) -> Self {
+        let raw = l.layered_kv.as_raw();
+        let ext = Extension::get_or_create(raw.db(), raw.id());
+        let ret = Self {
+            default_branch: l.default_branch,
+            branch_name_to_branch_id: l.branch_name_to_branch_id,
+            version_name_to_version_id: l.version_name_to_version_id,
+            branch_id_to_branch_name: ext.branch_id_to_branch_name,
+            version_id_to_version_name: ext.version_id_to_version_name,
+            version_tags: ext.version_tags,
+            branch_to_parent: l.branch_to_parent,
+            branch_to_created_versions: l.branch_to_created_versions,
+            version_to_change_set: l.version_to_change_set,
+            version_to_timestamp: ext.version_to_timestamp,
+            version_to_meta: ext.version_to_meta,
+            frozen_branches: ext.frozen_branches,
+            layered_kv: l.layered_kv,
+            roots: ext.roots,
+            read_isolation: ext.read_isolation,
+            branch_to_open_version: ext.branch_to_open_version,
+            branch_to_savepoints: ext.branch_to_savepoints,
+        };
+
+        // the initial branch is always named,
+        // so the indexes are empty only if they have just been created
+        if ret.branch_id_to_branch_name.is_empty() {
+            ret.name_indexes_rebuild();
+        }
+
+        ret
+    }
+}
+
+impl From<MapxRawVs> for Layout {
+    fn from(x: MapxRawVs) -> Self {
+        Self {
+            default_branch: x.default_branch,
+            branch_name_to_branch_id: x.branch_name_to_branch_id,
+            version_name_to_version_id: x.version_name_to_version_id,
+            branch_to_parent: x.branch_to_parent,
+            branch_to_created_versions: x.branch_to_created_versions,
+            version_to_change_set: x.version_to_change_set,
+            layered_kv: x.layered_kv,
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////
 
@@ -1032,6 +3116,75 @@ struct BasePoint {
     version_id: VersionID,
 }
 
+// Undo log of the savepoints taken in a version.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SavepointLog {
+    // the version where the savepoints are taken
+    version_id: VersionID,
+    // savepoint name => the length of `undo` when it is taken
+    names: MapxOrdRawKey<u64>,
+    // written keys and their former values in the version, in the order of writing
+    undo: Vecx<(RawKey, FormerValue)>,
+}
+
+impl SavepointLog {
+    #[inline(always)]
+    fn new(version_id: VersionID) -> Self {
+        Self {
+            version_id,
+            names: MapxOrdRawKey::new(),
+            undo: Vecx::new(),
+        }
+    }
+
+    #[inline(always)]
+    fn clear(&self) {
+        self.names.clear();
+        self.undo.clear();
+    }
+}
+
+// The value of a key in a version before it is changed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum FormerValue {
+    // the key has not been changed by the version
+    Absent,
+    Value(Option<RawValue>),
+}
+
+// Merkle roots of the states of versions, they are calculated on demand,
+// and dropped once the history before them is rewritten.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct VersionRoots {
+    // nodes of all the roots
+    nodes: SparseMerkleStore,
+    version_to_root: MapxOrd<VersionID, Hash32>,
+}
+
+impl VersionRoots {
+    #[inline(always)]
+    fn new() -> Self {
+        Self {
+            nodes: SparseMerkleStore::new(),
+            version_to_root: MapxOrd::new(),
+        }
+    }
+
+    // Drop the roots of `from` and all newer versions.
+    #[inline(always)]
+    fn invalidate(&self, from: VersionID) {
+        for (ver, _) in self.version_to_root.range(from..) {
+            self.version_to_root.remove(&ver);
+        }
+    }
+
+    #[inline(always)]
+    fn clear(&self) {
+        self.nodes.clear();
+        self.version_to_root.clear();
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////
 
@@ -1088,3 +3241,132 @@ impl ExactSizeIterator for MapxRawVsIter<'_> {}
 
 ////////////////////////////////////////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////
+
+thread_local! {
+    // Versions created within the current `vs_commit` of this thread,
+    // `None` if there is no `vs_commit` in progress.
+    static COMMIT_JOURNAL: RefCell<Option<Vec<(MapxRawVs, VersionID, BranchID)>>> =
+        RefCell::new(None);
+}
+
+// Versions in the journal are also persisted in the database of `hdr`,
+// until the whole commit finishes, return whether there is a `vs_commit` in progress.
+#[inline(always)]
+fn commit_journal_record(
+    hdr: &MapxRawVs,
+    version_id: VersionID,
+    branch_id: BranchID,
+) -> Result<bool> {
+    let in_commit = COMMIT_JOURNAL.with(|j| j.borrow().is_some());
+    alt!(!in_commit, return Ok(false));
+
+    // versions left by former processes must be removed before new ones are recorded
+    recover_once(hdr.db()).c(d!())?;
+
+    persisted_journal(hdr.db()).insert(
+        &version_id.to_be_bytes(),
+        &(branch_id, hdr.clone()).encode_value(),
+    );
+    COMMIT_JOURNAL.with(|j| {
+        if let Some(j) = j.borrow_mut().as_mut() {
+            j.push((hdr.clone(), version_id, branch_id));
+        }
+    });
+    Ok(true)
+}
+
+// The journal of the database `db`.
+#[inline(always)]
+fn persisted_journal(db: database::Db) -> MapxRaw {
+    MapxRaw::open_reserved_in(db, COMMIT_JOURNAL_ID)
+}
+
+/// Roll back versions left by interrupted `vs_commit`s(e.g. a crash),
+/// return how many versions have been removed.
+///
+/// It works on the current database, and should be called at startup,
+/// before any `vs_commit`, it is also called automatically before the
+/// first version is created by a `vs_commit` in each database.
+pub fn vsdb_recover() -> Result<usize> {
+    recover(database::current()).c(d!())
+}
+
+fn recover(db: database::Db) -> Result<usize> {
+    let journal = persisted_journal(db);
+    let mut cnt = 0;
+    // the instances recorded in the journal belong to `db`
+    database::scoped(db, || {
+        for (k, v) in journal.iter() {
+            let version_id = crate::parse_int!(k, VersionID);
+            let (branch_id, hdr) = <(BranchID, MapxRawVs)>::decode_value(&v).c(d!())?;
+            hdr.version_discard(version_id, branch_id);
+            journal.remove(&k);
+            cnt += 1;
+        }
+        Ok(())
+    })
+    .c(d!())?;
+    if 0 < cnt {
+        db.flush();
+    }
+    Ok(cnt)
+}
+
+// Run `recover` on the database `db` if it has never been run.
+fn recover_once(db: database::Db) -> Result<()> {
+    static RECOVERED: Lazy<Mutex<HashSet<usize>>> =
+        Lazy::new(|| Mutex::new(HashSet::new()));
+
+    let mut recovered = RECOVERED.lock();
+    let id = db as *const _ as usize;
+    if !recovered.contains(&id) {
+        recover(db).c(d!())?;
+        recovered.insert(id);
+    }
+    Ok(())
+}
+
+/// Run `f` as a whole, all versions created within it will be removed
+/// if it returns an error or panics, the panic will be resumed after that.
+///
+/// Nested calls are merged into the outermost one.
+pub(crate) fn commit_atomically(f: impl FnOnce() -> Result<()>) -> Result<()> {
+    let is_outermost = COMMIT_JOURNAL.with(|j| {
+        let mut j = j.borrow_mut();
+        alt!(j.is_some(), return false);
+        *j = Some(vec![]);
+        true
+    });
+
+    if !is_outermost {
+        return f();
+    }
+
+    let ret = panic::catch_unwind(AssertUnwindSafe(f));
+    let journal = COMMIT_JOURNAL
+        .with(|j| j.borrow_mut().take())
+        .unwrap_or_default();
+
+    let succeeded = matches!(ret, Ok(Ok(())));
+    // databases holding the versions of this commit
+    let mut dbs: Vec<database::Db> = vec![];
+    for (hdr, version_id, branch_id) in journal.into_iter().rev() {
+        if !succeeded {
+            // the version may have been removed by `f` itself
+            hdr.version_discard(version_id, branch_id);
+        }
+        persisted_journal(hdr.db()).remove(&version_id.to_be_bytes());
+        if !dbs.iter().any(|db| ptr::eq(*db, hdr.db())) {
+            dbs.push(hdr.db());
+        }
+    }
+
+    if succeeded {
+        dbs.into_iter().for_each(durability::version_created);
+    }
+
+    match ret {
+        Ok(r) => r,
+        Err(e) => panic::resume_unwind(e),
+    }
+}
@@ -30,22 +30,32 @@
 //!
 
 mod backend;
+mod patch;
+mod shallow;
 
 #[cfg(test)]
 mod test;
 
 use crate::{
     common::{
-        BranchName, ParentBranchName, RawKey, RawValue, VersionName,
-        INITIAL_BRANCH_NAME, NULL,
+        database, named, stats::SizeHistogram, BranchID, BranchName, ParentBranchName,
+        RawKey, RawValue, SavepointName, VersionID, VersionName, INITIAL_BRANCH_NAME,
+        NULL,
     },
-    VsMgmt,
+    merkle::sparse::{Hash32, SparseMerkleProof},
+    versioned::{
+        BranchInfo, Diff, InstanceId, KeyBlame, MergeConflict, MergeResolution,
+        PrunePolicy, SnapshotHandle, VersionBundle, VersionInfo, VersionMeta,
+    },
+    VsComplete, VsMgmt,
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::ops::{Deref, DerefMut, RangeBounds};
+use sha3::{Digest, Sha3_256};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
 
-pub(crate) use backend::MapxRawVsIter;
+pub use backend::vsdb_recover;
+pub(crate) use backend::{commit_atomically, MapxRawVsIter};
 
 /// Advanced `MapxRaw`, with versioned feature.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -68,6 +78,110 @@ impl MapxRawVs {
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
+    /// Create an instance that keeps the Merkle root of every version,
+    /// roots are calculated on request, from the nearest known one by
+    /// replaying the keys changed since it, so writes cost nothing more.
+    ///
+    /// Nodes of the outdated roots are removed by [gc](VsMgmt::gc).
+    ///
+    /// See [sparse](crate::merkle::sparse) for the structure of the trees.
+    #[inline(always)]
+    pub fn new_with_roots() -> Self {
+        Self {
+            inner: backend::MapxRawVs::new_with_roots(),
+        }
+    }
+
+    /// Check if this instance is created by `new_with_roots`.
+    #[inline(always)]
+    pub fn roots_enabled(&self) -> bool {
+        self.inner.roots_enabled()
+    }
+
+    /// The Merkle root of the head state of the default branch.
+    #[inline(always)]
+    pub fn root(&self) -> Result<Hash32> {
+        self.inner
+            .root_by_branch(self.inner.branch_get_default())
+            .c(d!())
+    }
+
+    /// The Merkle root of the head state of a specified branch.
+    #[inline(always)]
+    pub fn root_by_branch(&self, branch_name: BranchName) -> Result<Hash32> {
+        let branch_id = self.inner.get_branch_id(branch_name).c(d!())?;
+        self.inner.root_by_branch(branch_id).c(d!())
+    }
+
+    /// The Merkle root of the state of a branch at a historical version,
+    /// e.g. for serving proof-of-history queries.
+    ///
+    /// Roots of old versions are calculated on demand(from the nearest
+    /// known one, by replaying the keys changed since it), and persisted
+    /// for later queries.
+    #[inline(always)]
+    pub fn root_by_branch_version(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<Hash32> {
+        let branch_id = self.inner.get_branch_id(branch_name).c(d!())?;
+        let version_id = self
+            .inner
+            .get_version_id(branch_name, version_name)
+            .c(d!("version not found"))?;
+        self.inner
+            .root_by_branch_version(branch_id, version_id)
+            .c(d!())
+    }
+
+    /// A proof of the value, or the absence, of a key
+    /// in the head state of the default branch.
+    ///
+    /// It is verified against the root of the same state by
+    /// [verify_inclusion](SparseMerkleProof::verify_inclusion) or
+    /// [verify_exclusion](SparseMerkleProof::verify_exclusion).
+    #[inline(always)]
+    pub fn prove(&self, key: &[u8]) -> Result<SparseMerkleProof> {
+        self.inner
+            .prove_by_branch(key, self.inner.branch_get_default())
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove_by_branch(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+    ) -> Result<SparseMerkleProof> {
+        let branch_id = self.inner.get_branch_id(branch_name).c(d!())?;
+        self.inner.prove_by_branch(key, branch_id).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn prove_by_branch_version(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<SparseMerkleProof> {
+        let branch_id = self.inner.get_branch_id(branch_name).c(d!())?;
+        let version_id = self
+            .inner
+            .get_version_id(branch_name, version_name)
+            .c(d!("version not found"))?;
+        self.inner
+            .prove_by_branch_version(key, branch_id, version_id)
+            .c(d!())
+    }
+
     /// Insert a KV to the head version of the default branch.
     #[inline(always)]
     pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<RawValue>> {
@@ -103,6 +217,86 @@ impl MapxRawVs {
         self.inner.remove_by_branch(key, branch_id).c(d!())
     }
 
+    // The database holding this instance.
+    #[inline(always)]
+    pub(crate) fn db(&self) -> database::Db {
+        self.inner.db()
+    }
+
+    // The ID of a branch, that of the default branch if `branch_name` is `None`.
+    #[inline(always)]
+    pub(crate) fn branch_id(&self, branch_name: Option<BranchName>) -> Result<BranchID> {
+        match branch_name {
+            Some(name) => self.inner.get_branch_id(name).c(d!("branch not found")),
+            None => Ok(self.inner.branch_get_default()),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn check_writable_by_branch_id(&self, branch_id: BranchID) -> Result<()> {
+        self.inner.branch_check_head_writable(branch_id).c(d!())
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_by_branch_id(
+        &self,
+        key: &[u8],
+        branch_id: BranchID,
+    ) -> Option<RawValue> {
+        self.inner.get_by_branch_head(key, branch_id)
+    }
+
+    #[inline(always)]
+    pub(crate) fn version_create_by_branch_id(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+    ) -> Result<()> {
+        self.inner
+            .version_create_by_branch(version_name, branch_id)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub(crate) fn version_pop_by_branch_id(&self, branch_id: BranchID) -> Result<()> {
+        self.inner.version_pop_by_branch(branch_id).c(d!())
+    }
+
+    #[inline(always)]
+    pub(crate) fn version_check_creatable_by_branch_id(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+    ) -> Result<()> {
+        self.inner
+            .version_check_creatable(version_name, branch_id)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub(crate) fn version_is_head_by_branch_id(
+        &self,
+        version_name: &[u8],
+        branch_id: BranchID,
+    ) -> bool {
+        self.inner.version_is_head_by_name(version_name, branch_id)
+    }
+
+    // Write a KV to the head version of a branch,
+    // the key is removed if `value` is `None`.
+    #[inline(always)]
+    pub(crate) fn write_by_branch_id(
+        &self,
+        key: &[u8],
+        value: Option<&[u8]>,
+        branch_id: BranchID,
+    ) -> Result<Option<RawValue>> {
+        match value {
+            Some(v) => self.inner.insert_by_branch(key, v, branch_id).c(d!()),
+            None => self.inner.remove_by_branch(key, branch_id).c(d!()),
+        }
+    }
+
     /// Get the value of a key from the default branch.
     #[inline(always)]
     pub fn get(&self, key: &[u8]) -> Option<RawValue> {
@@ -111,7 +305,26 @@ impl MapxRawVs {
 
     #[inline(always)]
     pub fn get_mut<'a>(&'a self, key: &'a [u8]) -> Option<ValueMut<'a>> {
-        self.get(key).map(move |v| ValueMut::new(self, key, v))
+        self.get_head(key).map(move |v| ValueMut::new(self, key, v))
+    }
+
+    // The value of a key from the default branch in the view of the writer,
+    // for read-modify-write paths, check `VsMgmt::set_read_isolation`.
+    #[inline(always)]
+    pub(crate) fn get_head(&self, key: &[u8]) -> Option<RawValue> {
+        self.inner
+            .get_by_branch_head(key, self.inner.branch_get_default())
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_head_by_branch(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+    ) -> Option<RawValue> {
+        self.inner
+            .get_branch_id(branch_name)
+            .and_then(|br_id| self.inner.get_by_branch_head(key, br_id))
     }
 
     #[inline(always)]
@@ -130,6 +343,33 @@ impl MapxRawVs {
         self.inner.get_by_branch(key, branch_id)
     }
 
+    /// Find the version that most recently wrote or removed a key,
+    /// as seen from the head of a specified branch.
+    ///
+    /// `None` if the key has never been changed on the branch(and its ancestors),
+    /// or the version has been pruned.
+    #[inline(always)]
+    pub fn key_blame(&self, key: &[u8], branch_name: BranchName) -> Option<KeyBlame> {
+        let branch_id = self.inner.get_branch_id(branch_name)?;
+        self.inner.key_blame_by_branch(key, branch_id)
+    }
+
+    /// Every change of a key seen from the head of a specified branch,
+    /// including those made by its ancestors, from the oldest one to the newest one.
+    ///
+    /// Items are `(version name, value)`, a `None` value means the key is removed.
+    #[inline(always)]
+    pub fn key_history(
+        &self,
+        key: &[u8],
+        branch_name: BranchName,
+    ) -> Vec<(Vec<u8>, Option<RawValue>)> {
+        self.inner
+            .get_branch_id(branch_name)
+            .map(|br_id| self.inner.key_history_by_branch(key, br_id))
+            .unwrap_or_default()
+    }
+
     /// Get the value of a key from a specified version of a specified branch.
     #[inline(always)]
     pub fn get_by_branch_version(
@@ -143,6 +383,63 @@ impl MapxRawVs {
         self.inner.get_by_branch_version(key, branch_id, version_id)
     }
 
+    /// The smallest key changed by the versions newer than `version_name`
+    /// on the default branch, `None` if nothing has been changed since then.
+    ///
+    /// Together with `max_changed_key_since`, incremental consumers can
+    /// narrow their re-processing window to the affected key range.
+    #[inline(always)]
+    pub fn min_changed_key_since(&self, version_name: VersionName) -> Option<RawKey> {
+        self.changed_key_bounds_since(self.inner.branch_get_default(), version_name)
+            .map(|(min, _)| min)
+    }
+
+    /// The biggest key changed by the versions newer than `version_name`
+    /// on the default branch, `None` if nothing has been changed since then.
+    #[inline(always)]
+    pub fn max_changed_key_since(&self, version_name: VersionName) -> Option<RawKey> {
+        self.changed_key_bounds_since(self.inner.branch_get_default(), version_name)
+            .map(|(_, max)| max)
+    }
+
+    /// The smallest key changed by the versions newer than `version_name`
+    /// on a specified branch(include its parents).
+    #[inline(always)]
+    pub fn min_changed_key_since_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<RawKey> {
+        let branch_id = self.inner.get_branch_id(branch_name)?;
+        self.changed_key_bounds_since(branch_id, version_name)
+            .map(|(min, _)| min)
+    }
+
+    /// The biggest key changed by the versions newer than `version_name`
+    /// on a specified branch(include its parents).
+    #[inline(always)]
+    pub fn max_changed_key_since_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<RawKey> {
+        let branch_id = self.inner.get_branch_id(branch_name)?;
+        self.changed_key_bounds_since(branch_id, version_name)
+            .map(|(_, max)| max)
+    }
+
+    #[inline(always)]
+    fn changed_key_bounds_since(
+        &self,
+        branch_id: BranchID,
+        version_name: VersionName,
+    ) -> Option<(RawKey, RawKey)> {
+        let version_id = self
+            .inner
+            .get_version_id_by_branch_id(branch_id, version_name)?;
+        self.inner.changed_key_bounds_since(branch_id, version_id)
+    }
+
     /// Get the value of a key from the default branch,
     /// if the target key does not exist, will try to
     /// search a closest value bigger than the target key.
@@ -343,6 +640,82 @@ impl MapxRawVs {
             .is_some()
     }
 
+    /// Check if all the keys exist on the default branch.
+    #[inline(always)]
+    pub fn contains_all<T: AsRef<[u8]>>(&self, keys: &[T]) -> bool {
+        self.inner.contains_all_by_branch(
+            keys.iter().map(|k| k.as_ref()),
+            self.inner.branch_get_default(),
+        )
+    }
+
+    /// Check if all the keys exist on a specified branch.
+    #[inline(always)]
+    pub fn contains_all_by_branch<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        branch_name: BranchName,
+    ) -> bool {
+        self.inner
+            .get_branch_id(branch_name)
+            .map(|br_id| {
+                self.inner
+                    .contains_all_by_branch(keys.iter().map(|k| k.as_ref()), br_id)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Check if all the keys exist on a specified version of a specified branch,
+    /// much faster than checking them one by one.
+    #[inline(always)]
+    pub fn contains_all_by_branch_version<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> bool {
+        self.inner
+            .get_branch_id(branch_name)
+            .and_then(|br_id| {
+                self.inner
+                    .get_version_id(branch_name, version_name)
+                    .map(|ver_id| {
+                        self.inner.contains_all_by_branch_version(
+                            keys.iter().map(|k| k.as_ref()),
+                            br_id,
+                            ver_id,
+                        )
+                    })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Get the values of many keys on a specified version of a specified branch,
+    /// results are in the order of the input keys,
+    /// much faster than getting them one by one.
+    #[inline(always)]
+    pub fn get_many_by_branch_version<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Vec<Option<RawValue>> {
+        self.inner
+            .get_branch_id(branch_name)
+            .and_then(|br_id| {
+                self.inner
+                    .get_version_id(branch_name, version_name)
+                    .map(|ver_id| {
+                        self.inner.get_many_by_branch_version(
+                            keys.iter().map(|k| k.as_ref()),
+                            br_id,
+                            ver_id,
+                        )
+                    })
+            })
+            .unwrap_or_else(|| vec![None; keys.len()])
+    }
+
     /// Get the total number of items of the default branch.
     #[inline(always)]
     pub fn len(&self) -> usize {
@@ -378,13 +751,17 @@ impl MapxRawVs {
     #[inline(always)]
     #[allow(missing_docs)]
     pub fn is_empty(&self) -> bool {
-        self.iter().next().is_none()
+        self.inner
+            .is_empty_by_branch(self.inner.branch_get_default())
     }
 
     #[inline(always)]
     #[allow(missing_docs)]
     pub fn is_empty_by_branch(&self, branch_name: BranchName) -> bool {
-        self.iter_by_branch(branch_name).next().is_none()
+        self.inner
+            .get_branch_id(branch_name)
+            .map(|id| self.inner.is_empty_by_branch(id))
+            .unwrap_or(true)
     }
 
     #[inline(always)]
@@ -399,57 +776,524 @@ impl MapxRawVs {
             .is_none()
     }
 
-    /// Clear all data, mainly for testing purpose.
+    /// Check if `branch_a` is an ancestor of `branch_b`,
+    /// a branch is not an ancestor of itself.
+    ///
+    /// If it is, and `branch_a` has no new versions since `branch_b` was forked,
+    /// merging `branch_b` to `branch_a` is a fast-forward one.
     #[inline(always)]
-    pub fn clear(&mut self) {
-        self.inner.clear();
+    pub fn branch_is_ancestor(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<bool> {
+        let br_a = self
+            .inner
+            .get_branch_id(branch_a)
+            .c(d!("branch not found"))?;
+        let br_b = self
+            .inner
+            .get_branch_id(branch_b)
+            .c(d!("branch not found"))?;
+        Ok(self.inner.branch_is_ancestor(br_a, br_b))
     }
-}
 
-impl VsMgmt for MapxRawVs {
-    /// Create a new version on the default branch.
+    /// Find where two branches diverged, that is,
+    /// the newest version visible on both of them,
+    /// the result is `(branch name, version name)`,
+    /// `None` if they have no common versions.
     #[inline(always)]
-    fn version_create(&self, version_name: VersionName) -> Result<()> {
-        self.inner.version_create(version_name.0).c(d!())
+    pub fn branch_merge_base(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let br_a = self
+            .inner
+            .get_branch_id(branch_a)
+            .c(d!("branch not found"))?;
+        let br_b = self
+            .inner
+            .get_branch_id(branch_b)
+            .c(d!("branch not found"))?;
+        Ok(self.inner.branch_merge_base(br_a, br_b))
     }
 
-    /// Create a new version on a specified branch,
-    /// NOTE: the branch must has been created.
-    #[inline(always)]
-    fn version_create_by_branch(
+    /// A SHA3-256 hash over all KVs visible in a version of a branch,
+    /// two instances have the same checksum if and only if
+    /// they hold the same data in the version, no matter how they got there.
+    ///
+    /// NOTE: all KVs of the version will be read, it may take a long time.
+    pub fn version_checksum(
         &self,
-        version_name: VersionName,
         branch_name: BranchName,
-    ) -> Result<()> {
-        self.inner
+        version_name: VersionName,
+    ) -> Result<[u8; 32]> {
+        let br_id = self
+            .inner
             .get_branch_id(branch_name)
-            .c(d!("branch not found"))
-            .and_then(|br_id| {
-                self.inner
-                    .version_create_by_branch(version_name.0, br_id)
-                    .c(d!())
-            })
+            .c(d!("branch not found"))?;
+        let ver_id = self
+            .inner
+            .get_version_id(branch_name, version_name)
+            .c(d!("version not found"))?;
+
+        // lengths are hashed too, so the boundaries of KVs are unambiguous
+        let mut hasher = Sha3_256::new();
+        for (k, v) in self.inner.iter_by_branch_version(br_id, ver_id) {
+            hasher.update((k.len() as u64).to_be_bytes());
+            hasher.update(&k);
+            hasher.update((v.len() as u64).to_be_bytes());
+            hasher.update(&v);
+        }
+
+        let mut ret = [0; 32];
+        ret.copy_from_slice(&hasher.finalize());
+        Ok(ret)
     }
 
-    /// Check if a verison exists on default branch.
+    /// Compare the head states of two branches,
+    /// all changes are described from the view of `branch_a` to `branch_b`,
+    /// items are sorted by the key.
     #[inline(always)]
-    fn version_exists(&self, version_name: VersionName) -> bool {
-        self.inner
-            .get_version_id(BranchName(INITIAL_BRANCH_NAME), version_name)
-            .map(|id| self.inner.version_exists(id))
-            .unwrap_or(false)
+    pub fn branch_diff(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Vec<(RawKey, Diff<RawValue>)>> {
+        let br_a = self
+            .inner
+            .get_branch_id(branch_a)
+            .c(d!("branch not found"))?;
+        let br_b = self
+            .inner
+            .get_branch_id(branch_b)
+            .c(d!("branch not found"))?;
+        self.inner.branch_diff(br_a, br_b).c(d!())
     }
 
-    /// Check if a version exists on a specified branch(include its parents).
+    /// Find keys that have been changed by both a branch and its parent
+    /// since the fork point, and have different values on their heads,
+    /// that is, the keys that will be silently overwritten
+    /// by `branch_merge_to_parent`.
     #[inline(always)]
-    fn version_exists_on_branch(
+    pub fn branch_merge_conflicts(
         &self,
-        version_name: VersionName,
         branch_name: BranchName,
-    ) -> bool {
+    ) -> Result<Vec<MergeConflict<RawKey, RawValue>>> {
         self.inner
             .get_branch_id(branch_name)
-            .and_then(|br_id| {
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.inner.branch_merge_conflicts(br_id).c(d!()))
+    }
+
+    /// Merge a branch to its parent like `branch_merge_to_parent`,
+    /// but every conflict will be resolved by the `resolver`,
+    /// whose arguments are `(key, ours, theirs)`.
+    ///
+    /// The resolved values are written to a new version of the branch before
+    /// merging, which is named after its head version with a "+resolved" suffix,
+    /// so the existing versions are untouched; the resolutions and the merge
+    /// succeed or fail as a whole.
+    #[inline(always)]
+    pub fn branch_merge_to_parent_with_resolver(
+        &self,
+        branch_name: BranchName,
+        resolver: impl FnMut(
+            &[u8],
+            Option<&[u8]>,
+            Option<&[u8]>,
+        ) -> MergeResolution<RawValue>,
+    ) -> Result<()> {
+        let br_id = self
+            .inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))?;
+        self.inner
+            .branch_merge_to_parent_with_resolver(br_id, resolver)
+            .c(d!())
+    }
+
+    /// Merge the changes made by `theirs` since `base_version` into `ours`,
+    /// both sides are compared with the base version instead of each other,
+    /// so the two branches do not need to be a child and its parent.
+    ///
+    /// The base version must be visible on both branches,
+    /// `branch_merge_base` can be used to find the newest one.
+    /// The merged values are written to the head version of `ours`,
+    /// keys changed by both sides to different values keep the values of
+    /// `ours`, and they are returned as conflicts.
+    #[inline(always)]
+    pub fn branch_merge_3way(
+        &self,
+        ours: BranchName,
+        theirs: BranchName,
+        base_version: VersionName,
+    ) -> Result<Vec<MergeConflict<RawKey, RawValue>>> {
+        let ours = self.inner.get_branch_id(ours).c(d!("branch not found"))?;
+        let theirs = self.inner.get_branch_id(theirs).c(d!("branch not found"))?;
+        let base = self
+            .inner
+            .get_visible_version_id(ours, base_version)
+            .c(d!("version not found"))?;
+        self.inner.branch_merge_3way(ours, theirs, base).c(d!())
+    }
+
+    /// Replay all versions created by a branch on top of
+    /// a specified version of a new parent branch,
+    /// the replayed versions will keep their names.
+    ///
+    /// NOTE:
+    /// - the branch must have no children, and no savepoints
+    /// - the new parent must be created earlier than the branch
+    #[inline(always)]
+    pub fn branch_rebase(
+        &self,
+        branch_name: BranchName,
+        new_parent_branch_name: ParentBranchName,
+        new_parent_version_name: VersionName,
+    ) -> Result<()> {
+        let br_id = self
+            .inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))?;
+        let parent_br_id = self
+            .inner
+            .get_branch_id(BranchName(new_parent_branch_name.0))
+            .c(d!("new parent branch not found"))?;
+        let parent_ver_id = self
+            .inner
+            .get_version_id(
+                BranchName(new_parent_branch_name.0),
+                new_parent_version_name,
+            )
+            .c(d!("version not found"))?;
+        self.inner
+            .branch_rebase(br_id, parent_br_id, parent_ver_id)
+            .c(d!())
+    }
+
+    /// Export versions directly created by the default branch,
+    /// check `export_versions_by_branch` for details.
+    #[inline(always)]
+    pub fn export_versions<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.export_versions_by_branch_id(self.inner.branch_get_default(), bounds)
+            .c(d!())
+    }
+
+    /// Export versions directly created by a specified branch,
+    /// the bounds are versions created on this branch,
+    /// use `..` to export all of them.
+    ///
+    /// The result can be imported by `branch_create_from_bundle`.
+    #[inline(always)]
+    pub fn export_versions_by_branch<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_name: BranchName,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.export_versions_by_branch_id(br_id, bounds).c(d!()))
+    }
+
+    /// Export the versions created by a branch after `from_version`
+    /// up to and including `to_version` as a patch, like `git format-patch`.
+    ///
+    /// The patch is in a stable binary format,
+    /// it can be applied to any instance by `apply_patch`.
+    #[inline(always)]
+    pub fn export_patch(
+        &self,
+        branch_name: BranchName,
+        from_version: VersionName,
+        to_version: VersionName,
+    ) -> Result<Vec<u8>> {
+        let br_id = self
+            .inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))?;
+        self.export_versions_by_branch_id(
+            br_id,
+            (Bound::Excluded(from_version), Bound::Included(to_version)),
+        )
+        .c(d!())
+        .map(|bundle| patch::encode(&bundle))
+    }
+
+    /// Rebuild all versions of a patch on the head of a branch,
+    /// like `git am`, the versions will keep their names.
+    ///
+    /// Either all versions are applied, or none of them.
+    #[inline(always)]
+    pub fn apply_patch(&self, branch_name: BranchName, patch: &[u8]) -> Result<()> {
+        let br_id = self
+            .inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))?;
+        let bundle = patch::decode(patch).c(d!())?;
+        self.inner.branch_apply_bundle(br_id, &bundle).c(d!())
+    }
+
+    fn export_versions_by_branch_id<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_id: BranchID,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        let to_id = |b: Bound<&VersionName<'a>>| -> Result<Bound<VersionID>> {
+            let get_id = |v: &VersionName<'a>| {
+                self.inner
+                    .get_version_id_by_branch_id(branch_id, *v)
+                    .c(d!("version not found"))
+            };
+            Ok(match b {
+                Bound::Included(v) => Bound::Included(get_id(v)?),
+                Bound::Excluded(v) => Bound::Excluded(get_id(v)?),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        };
+        let start = to_id(bounds.start_bound()).c(d!())?;
+        let end = to_id(bounds.end_bound()).c(d!())?;
+        self.inner.export_versions(branch_id, (start, end)).c(d!())
+    }
+
+    fn version_squash_by_branch_id(
+        &self,
+        branch_id: BranchID,
+        from: VersionName,
+        to: VersionName,
+    ) -> Result<()> {
+        let from = self
+            .inner
+            .get_version_id_by_branch_id(branch_id, from)
+            .c(d!("version not found"))?;
+        let to = self
+            .inner
+            .get_version_id_by_branch_id(branch_id, to)
+            .c(d!("version not found"))?;
+        self.inner
+            .version_squash_by_branch(branch_id, from, to)
+            .c(d!())
+    }
+
+    fn version_revert_by_branch_id(
+        &self,
+        reverted_version_name: VersionName,
+        new_version_name: VersionName,
+        branch_id: BranchID,
+    ) -> Result<()> {
+        let reverted = self
+            .inner
+            .get_version_id_by_branch_id(branch_id, reverted_version_name)
+            .c(d!("version not found"))?;
+        self.inner
+            .version_revert_by_branch(reverted, new_version_name.0, branch_id)
+            .c(d!())
+    }
+
+    /// Create a new branch on the head of the default branch,
+    /// and rebuild all versions of the bundle on it.
+    #[inline(always)]
+    pub fn branch_create_from_bundle(
+        &self,
+        branch_name: BranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_from_bundle(
+                branch_name.0,
+                self.inner.branch_get_default(),
+                bundle,
+            )
+            .c(d!())
+    }
+
+    /// Create a new branch on the head of a specified base branch,
+    /// and rebuild all versions of the bundle on it.
+    ///
+    /// Like the `git bundle`, the head state of the base branch
+    /// should be the same as the one that the bundle is exported upon.
+    #[inline(always)]
+    pub fn branch_create_by_base_branch_from_bundle(
+        &self,
+        branch_name: BranchName,
+        base_branch_name: ParentBranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        let base_br_id = self
+            .inner
+            .get_branch_id(BranchName(base_branch_name.0))
+            .c(d!("base branch not found"))?;
+        self.inner
+            .branch_create_from_bundle(branch_name.0, base_br_id, bundle)
+            .c(d!())
+    }
+
+    /// List versions directly created by the default branch,
+    /// check `version_list_by_branch` for details.
+    #[inline(always)]
+    pub fn version_list(&self) -> Result<Vec<VersionInfo>> {
+        self.inner
+            .version_list_by_branch(self.inner.branch_get_default())
+            .c(d!())
+    }
+
+    /// List versions directly created by a specified branch,
+    /// in the order of their creation, along with
+    /// their creation timestamps and the number of changed keys.
+    #[inline(always)]
+    pub fn version_list_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<VersionInfo>> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.inner.version_list_by_branch(br_id).c(d!()))
+    }
+
+    /// Get the metadata of a version on the default branch,
+    /// `None` if the version does not exist or has no metadata.
+    #[inline(always)]
+    pub fn version_get_meta(&self, version_name: VersionName) -> Option<VersionMeta> {
+        self.inner
+            .get_version_id_by_branch_id(self.inner.branch_get_default(), version_name)
+            .and_then(|ver_id| self.inner.version_get_meta(ver_id))
+    }
+
+    /// Get the metadata of a version on a specified branch,
+    /// `None` if the version does not exist or has no metadata.
+    #[inline(always)]
+    pub fn version_get_meta_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<VersionMeta> {
+        self.inner
+            .get_branch_id(branch_name)
+            .and_then(|br_id| {
+                self.inner.get_version_id_by_branch_id(br_id, version_name)
+            })
+            .and_then(|ver_id| self.inner.version_get_meta(ver_id))
+    }
+
+    /// Check if a branch has been frozen.
+    #[inline(always)]
+    pub fn branch_is_frozen(&self, branch_name: BranchName) -> bool {
+        self.inner
+            .get_branch_id(branch_name)
+            .map(|br_id| self.inner.branch_is_frozen(br_id))
+            .unwrap_or(false)
+    }
+
+    /// How many ancestral branches a branch has, `None` if the branch does not exist.
+    ///
+    /// New branches can not be created on a branch whose depth has reached
+    /// `vsdb_get_branch_ancestors_limit`, merge it to its parent to flatten.
+    #[inline(always)]
+    pub fn branch_depth(&self, branch_name: BranchName) -> Option<usize> {
+        self.inner
+            .get_branch_id(branch_name)
+            .and_then(|br_id| self.inner.branch_depth(br_id))
+    }
+
+    /// Materialize the visible KVs of a branch head into a fresh directory,
+    /// all history is dropped, somewhat like the `git clone --depth 1`.
+    ///
+    /// The target directory must not exist or be empty,
+    /// use `import_shallow` to load it into a new instance.
+    #[inline(always)]
+    pub fn branch_export_shallow(
+        &self,
+        branch_name: BranchName,
+        target_dir: &str,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| {
+                shallow::write(target_dir, self.inner.iter_by_branch(br_id)).c(d!())
+            })
+    }
+
+    /// Create a new instance from a shallow copy,
+    /// all KVs are written to the initial version of the default branch.
+    pub fn import_shallow(src_dir: &str) -> Result<Self> {
+        let ret = Self::new();
+        shallow::read(src_dir, |k, v| ret.insert(k, v).c(d!()).map(|_| ())).c(d!())?;
+        Ok(ret)
+    }
+
+    /// Clear all data, mainly for testing purpose.
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Flush the data of this instance to disk,
+    /// unlike the global `vsdb_flush()`,
+    /// the areas that hold no data of this instance will not be touched.
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    /// Distribution of the key and value sizes of the head of the default branch,
+    /// only one of every `sample_step` entries will be recorded.
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        SizeHistogram::scan(self.iter(), sample_step)
+    }
+}
+
+impl VsMgmt for MapxRawVs {
+    /// Create a new version on the default branch.
+    #[inline(always)]
+    fn version_create(&self, version_name: VersionName) -> Result<()> {
+        self.inner.version_create(version_name.0).c(d!())
+    }
+
+    /// Create a new version on a specified branch,
+    /// NOTE: the branch must has been created.
+    #[inline(always)]
+    fn version_create_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| {
+                self.inner
+                    .version_create_by_branch(version_name.0, br_id)
+                    .c(d!())
+            })
+    }
+
+    /// Check if a verison exists on default branch.
+    #[inline(always)]
+    fn version_exists(&self, version_name: VersionName) -> bool {
+        self.inner
+            .get_version_id(BranchName(INITIAL_BRANCH_NAME), version_name)
+            .map(|id| self.inner.version_exists(id))
+            .unwrap_or(false)
+    }
+
+    /// Check if a version exists on a specified branch(include its parents).
+    #[inline(always)]
+    fn version_exists_on_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> bool {
+        self.inner
+            .get_branch_id(branch_name)
+            .and_then(|br_id| {
                 self.inner
                     .get_version_id(branch_name, version_name)
                     .map(|ver_id| self.inner.version_exists_on_branch(ver_id, br_id).0)
@@ -507,6 +1351,245 @@ impl VsMgmt for MapxRawVs {
             .and_then(|br_id| self.inner.version_pop_by_branch(br_id).c(d!()))
     }
 
+    /// Attach a human-friendly tag to a version of a specified branch,
+    /// the tag can be used in place of the version name
+    /// on any branch where the version is visible.
+    ///
+    /// NOTE: real version names take precedence over tags.
+    #[inline(always)]
+    fn version_tag(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+        tag: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .get_version_id(branch_name, version_name)
+            .c(d!("version not found"))
+            .and_then(|ver_id| self.inner.version_tag(ver_id, tag.0).c(d!()))
+    }
+
+    /// Remove a tag, the tagged version will not be affected.
+    #[inline(always)]
+    fn version_untag(&self, tag: VersionName) -> Result<()> {
+        self.inner.version_untag(tag.0).c(d!())
+    }
+
+    /// Collapse consecutive versions(`from` and `to` included) of the default branch
+    /// into one, which keeps the name of `to` and holds the net changes of them all.
+    #[inline(always)]
+    fn version_squash(&self, from: VersionName, to: VersionName) -> Result<()> {
+        self.version_squash_by_branch_id(self.inner.branch_get_default(), from, to)
+            .c(d!())
+    }
+
+    /// Collapse consecutive versions(`from` and `to` included) of a specified branch
+    /// into one, which keeps the name of `to` and holds the net changes of them all.
+    ///
+    /// The per-version bookkeeping of the collapsed versions is reclaimed,
+    /// and the state of every remaining version is unchanged.
+    ///
+    /// NOTE: versions used as base points of other branches can not be squashed.
+    #[inline(always)]
+    fn version_squash_by_branch(
+        &self,
+        branch_name: BranchName,
+        from: VersionName,
+        to: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.version_squash_by_branch_id(br_id, from, to).c(d!()))
+    }
+
+    /// Create a new version on the default branch,
+    /// which undoes all changes made by a historical version,
+    /// somewhat like the `git revert`.
+    #[inline(always)]
+    fn version_revert(
+        &self,
+        reverted_version_name: VersionName,
+        new_version_name: VersionName,
+    ) -> Result<()> {
+        self.version_revert_by_branch_id(
+            reverted_version_name,
+            new_version_name,
+            self.inner.branch_get_default(),
+        )
+        .c(d!())
+    }
+
+    /// Create a new version on a specified branch,
+    /// which undoes all changes made by a historical version,
+    /// somewhat like the `git revert`.
+    ///
+    /// Every key changed by the reverted version will be set back to
+    /// its value just before that version, in the view of the branch,
+    /// even if it has been changed again by later versions.
+    #[inline(always)]
+    fn version_revert_by_branch(
+        &self,
+        reverted_version_name: VersionName,
+        new_version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| {
+                self.version_revert_by_branch_id(
+                    reverted_version_name,
+                    new_version_name,
+                    br_id,
+                )
+                .c(d!())
+            })
+    }
+
+    /// Create a new version with metadata on the default branch.
+    #[inline(always)]
+    fn version_create_with_meta(
+        &self,
+        version_name: VersionName,
+        meta: &VersionMeta,
+    ) -> Result<()> {
+        self.inner
+            .version_create_by_branch_with_meta(
+                version_name.0,
+                self.inner.branch_get_default(),
+                meta,
+            )
+            .c(d!())
+    }
+
+    /// Create a new version with metadata on a specified branch,
+    /// NOTE: the branch must has been created.
+    #[inline(always)]
+    fn version_create_by_branch_with_meta(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+        meta: &VersionMeta,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| {
+                self.inner
+                    .version_create_by_branch_with_meta(version_name.0, br_id, meta)
+                    .c(d!())
+            })
+    }
+
+    /// Create a new version on the default branch
+    /// if its head is still `expected_head`.
+    #[inline(always)]
+    fn version_create_cas(
+        &self,
+        version_name: VersionName,
+        expected_head: Option<VersionName>,
+    ) -> Result<()> {
+        self.inner
+            .version_create_by_branch_cas(
+                version_name.0,
+                self.inner.branch_get_default(),
+                expected_head.map(|v| v.0),
+            )
+            .c(d!())
+    }
+
+    /// Create a new version on a specified branch
+    /// if its head is still `expected_head`.
+    #[inline(always)]
+    fn version_create_by_branch_cas(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+        expected_head: Option<VersionName>,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| {
+                self.inner
+                    .version_create_by_branch_cas(
+                        version_name.0,
+                        br_id,
+                        expected_head.map(|v| v.0),
+                    )
+                    .c(d!())
+            })
+    }
+
+    /// Enable or disable the read isolation.
+    #[inline(always)]
+    fn set_read_isolation(&self, enabled: bool) -> Result<()> {
+        self.inner.read_isolation_set(enabled);
+        Ok(())
+    }
+
+    /// Make the version being built on the default branch visible to reads.
+    #[inline(always)]
+    fn version_finalize(&self) -> Result<()> {
+        self.inner
+            .version_finalize_by_branch(self.inner.branch_get_default())
+            .c(d!())
+    }
+
+    /// Make the version being built on a specified branch visible to reads.
+    #[inline(always)]
+    fn version_finalize_by_branch(&self, branch_name: BranchName) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.inner.version_finalize_by_branch(br_id).c(d!()))
+    }
+
+    /// Take a savepoint in the head version of the default branch.
+    #[inline(always)]
+    fn savepoint(&self, name: SavepointName) -> Result<()> {
+        self.inner
+            .savepoint_create(name.0, self.inner.branch_get_default())
+            .c(d!())
+    }
+
+    /// Take a savepoint in the head version of a specified branch.
+    #[inline(always)]
+    fn savepoint_by_branch(
+        &self,
+        name: SavepointName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.inner.savepoint_create(name.0, br_id).c(d!()))
+    }
+
+    /// Undo all changes made in the head version of the default branch
+    /// since the savepoint.
+    #[inline(always)]
+    fn rollback_to_savepoint(&self, name: SavepointName) -> Result<()> {
+        self.inner
+            .savepoint_rollback(name.0, self.inner.branch_get_default())
+            .c(d!())
+    }
+
+    /// Undo all changes made in the head version of a specified branch
+    /// since the savepoint.
+    #[inline(always)]
+    fn rollback_to_savepoint_by_branch(
+        &self,
+        name: SavepointName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.inner.savepoint_rollback(name.0, br_id).c(d!()))
+    }
+
     /// Create a new branch based on the head of the default branch.
     #[inline(always)]
     fn branch_create(&self, branch_name: BranchName) -> Result<()> {
@@ -560,6 +1643,27 @@ impl VsMgmt for MapxRawVs {
             .unwrap_or(false)
     }
 
+    /// List all existing branches, sorted by their names.
+    #[inline(always)]
+    fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+        Ok(self.inner.branch_list())
+    }
+
+    /// Rename a branch, all versions and data of it will be kept.
+    ///
+    /// NOTE: the initial branch can NOT be renamed, but it can be swapped.
+    #[inline(always)]
+    fn branch_rename(&self, old_name: BranchName, new_name: BranchName) -> Result<()> {
+        self.inner.branch_rename(old_name.0, new_name.0).c(d!())
+    }
+
+    /// Exchange the names of two branches as a whole,
+    /// versions and data stay with their branches.
+    #[inline(always)]
+    fn branch_swap(&self, branch_a: BranchName, branch_b: BranchName) -> Result<()> {
+        self.inner.branch_swap(branch_a.0, branch_b.0).c(d!())
+    }
+
     /// Remove a branch, remove all changes directly made by this branch.
     ///
     /// 'Write'-like operations on branches and versions are different from operations on data.
@@ -617,6 +1721,25 @@ impl VsMgmt for MapxRawVs {
             })
     }
 
+    /// Roll a branch back to a version created by it, all newer versions are removed
+    /// in one call, versions used as base points of other branches can not be removed.
+    #[inline(always)]
+    fn version_rollback_to(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<()> {
+        let br_id = self
+            .inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))?;
+        let ver_id = self
+            .inner
+            .get_version_id_by_branch_id(br_id, version_name)
+            .c(d!("version not found"))?;
+        self.inner.version_rollback_to(br_id, ver_id).c(d!())
+    }
+
     /// Remove the newest version on a specified branch.
     ///
     /// 'Write'-like operations on branches and versions are different from operations on data.
@@ -660,6 +1783,27 @@ impl VsMgmt for MapxRawVs {
             .and_then(|brid| self.inner.branch_set_default(brid).c(d!()))
     }
 
+    /// Freeze a branch, all write operations on it will fail until it is unfrozen,
+    /// including data writes and creating/removing/merging/rebasing of versions.
+    ///
+    /// NOTE: pruning is still allowed, it does not change the view of the head.
+    #[inline(always)]
+    fn branch_freeze(&self, branch_name: BranchName) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|brid| self.inner.branch_freeze(brid).c(d!()))
+    }
+
+    /// Make a frozen branch writable again.
+    #[inline(always)]
+    fn branch_unfreeze(&self, branch_name: BranchName) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|brid| self.inner.branch_unfreeze(brid).c(d!()))
+    }
+
     /// Clean outdated versions out of the default reserved number.
     #[inline(always)]
     fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()> {
@@ -680,6 +1824,125 @@ impl VsMgmt for MapxRawVs {
                 self.inner.prune_by_branch(br_id, reserved_ver_num).c(d!())
             })
     }
+
+    /// Clean outdated versions of the default branch by a retention policy.
+    #[inline(always)]
+    fn prune_with(&self, policy: &PrunePolicy) -> Result<()> {
+        self.inner
+            .prune_by_branch_with(self.inner.branch_get_default(), policy)
+            .c(d!())
+    }
+
+    /// Clean outdated versions of a specified branch by a retention policy.
+    #[inline(always)]
+    fn prune_by_branch_with(
+        &self,
+        branch_name: BranchName,
+        policy: &PrunePolicy,
+    ) -> Result<()> {
+        self.inner
+            .get_branch_id(branch_name)
+            .c(d!("branch not found"))
+            .and_then(|br_id| self.inner.prune_by_branch_with(br_id, policy).c(d!()))
+    }
+
+    /// Physically delete the data that can not be reached from any branch.
+    #[inline(always)]
+    fn gc(&self) -> Result<()> {
+        self.inner.gc();
+        Ok(())
+    }
+
+    /// The Merkle root of the head state of the default branch,
+    /// it fails if Merkle roots are not enabled.
+    #[inline(always)]
+    fn state_root(&self) -> Result<Hash32> {
+        self.root().c(d!())
+    }
+
+    /// The identity of this instance.
+    #[inline(always)]
+    fn instance_ids(&self) -> Result<Vec<InstanceId>> {
+        Ok(vec![self.inner.instance_id()])
+    }
+}
+
+impl VsComplete for MapxRawVs {}
+
+impl<'a> SnapshotHandle<'a, MapxRawVs> {
+    /// Get the value of a key at the snapshot.
+    #[inline(always)]
+    pub fn get(&self, key: &[u8]) -> Option<RawValue> {
+        self.inner()
+            .get_by_branch_version(key, self.branch_name(), self.version_name())
+    }
+
+    /// Get the closest KV whose key is not bigger than the target key.
+    #[inline(always)]
+    pub fn get_le(&self, key: &[u8]) -> Option<(RawKey, RawValue)> {
+        self.inner().get_le_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    /// Get the closest KV whose key is not smaller than the target key.
+    #[inline(always)]
+    pub fn get_ge(&self, key: &[u8]) -> Option<(RawKey, RawValue)> {
+        self.inner().get_ge_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
+
+    /// The number of entries at the snapshot.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner()
+            .len_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    /// Check if there is no entry at the snapshot.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner()
+            .is_empty_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    /// Create an iterator over the snapshot.
+    #[inline(always)]
+    pub fn iter(&self) -> MapxRawVsIter<'a> {
+        self.inner()
+            .iter_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    /// Collect all entries at the snapshot into plain types.
+    #[inline(always)]
+    pub fn to_plain(&self) -> Vec<(RawKey, RawValue)> {
+        self.iter().collect()
+    }
+
+    /// Create a range iterator over the snapshot.
+    #[inline(always)]
+    pub fn range<R: 'a + RangeBounds<RawKey>>(&self, bounds: R) -> MapxRawVsIter<'a> {
+        self.inner().range_by_branch_version(
+            self.branch_name(),
+            self.version_name(),
+            bounds,
+        )
+    }
+
+    /// Check if a key exists at the snapshot.
+    #[inline(always)]
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.inner().contains_key_by_branch_version(
+            key,
+            self.branch_name(),
+            self.version_name(),
+        )
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -722,7 +1985,7 @@ pub struct Entry<'a> {
 
 impl<'a> Entry<'a> {
     pub fn or_insert_ref(self, default: &'a [u8]) -> ValueMut<'a> {
-        if !self.hdr.contains_key(self.key) {
+        if self.hdr.get_head(self.key).is_none() {
             pnk!(self.hdr.insert(self.key, default));
         }
         pnk!(self.hdr.get_mut(self.key))
@@ -0,0 +1,99 @@
+//!
+//! Binary format of patches.
+//!
+//! A patch holds a range of versions exported from a branch,
+//! the format is stable, it does not depend on the codec of VSDB:
+//!
+//! - `MAGIC`
+//! - `[version count: u64 BE]`
+//! - repeated versions:
+//!     - `[name len: u64 BE][name][change count: u64 BE]`
+//!     - repeated changes: `[key len: u64 BE][key][0u8]` for a removed key,
+//!       or `[key len: u64 BE][key][1u8][value len: u64 BE][value]`
+//!
+
+use crate::{
+    common::RawBytes,
+    versioned::{BundledVersion, VersionBundle},
+};
+use ruc::*;
+
+const MAGIC: &[u8] = b"VSDB-PATCH-1";
+
+pub(super) fn encode(bundle: &VersionBundle) -> Vec<u8> {
+    let mut ret = MAGIC.to_vec();
+    put_len(&mut ret, bundle.versions.len());
+    for v in bundle.versions.iter() {
+        put_chunk(&mut ret, &v.name);
+        put_len(&mut ret, v.changes.len());
+        for (k, value) in v.changes.iter() {
+            put_chunk(&mut ret, k);
+            if let Some(value) = value {
+                ret.push(1);
+                put_chunk(&mut ret, value);
+            } else {
+                ret.push(0);
+            }
+        }
+    }
+    ret
+}
+
+pub(super) fn decode(mut bytes: &[u8]) -> Result<VersionBundle> {
+    let r = &mut bytes;
+
+    if MAGIC != take(r, MAGIC.len()).c(d!("not a patch"))? {
+        return Err(eg!("not a patch"));
+    }
+
+    let mut ret = VersionBundle::default();
+    for _ in 0..take_len(r).c(d!())? {
+        let name = take_chunk(r).c(d!())?.to_vec();
+        let mut changes = vec![];
+        for _ in 0..take_len(r).c(d!())? {
+            let k = RawBytes::from(take_chunk(r).c(d!())?);
+            let v = match take(r, 1).c(d!())?[0] {
+                0 => None,
+                1 => Some(RawBytes::from(take_chunk(r).c(d!())?)),
+                _ => return Err(eg!("invalid patch")),
+            };
+            changes.push((k, v));
+        }
+        ret.versions.push(BundledVersion { name, changes });
+    }
+
+    if !r.is_empty() {
+        return Err(eg!("trailing bytes in the patch"));
+    }
+
+    Ok(ret)
+}
+
+fn put_len(buf: &mut Vec<u8>, len: usize) {
+    buf.extend_from_slice(&(len as u64).to_be_bytes());
+}
+
+fn put_chunk(buf: &mut Vec<u8>, chunk: &[u8]) {
+    put_len(buf, chunk.len());
+    buf.extend_from_slice(chunk);
+}
+
+fn take<'a>(r: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if r.len() < n {
+        return Err(eg!("truncated patch"));
+    }
+    let (ret, rest) = r.split_at(n);
+    *r = rest;
+    Ok(ret)
+}
+
+// Lengths are never trusted, they are checked against the remaining bytes.
+fn take_len(r: &mut &[u8]) -> Result<u64> {
+    take(r, 8).c(d!()).map(|b| crate::parse_int!(b, u64))
+}
+
+fn take_chunk<'a>(r: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = take_len(r).c(d!())?;
+    let len = usize::try_from(len).c(d!("truncated patch"))?;
+    take(r, len).c(d!())
+}
@@ -0,0 +1,80 @@
+//!
+//! On-disk format of shallow copies.
+//!
+//! A shallow copy is a directory containing one data file,
+//! which holds the visible KVs of a branch head in their key order:
+//!
+//! - `MAGIC`
+//! - repeated: `[key len: u64 BE][key][value len: u64 BE][value]`
+//!
+
+use crate::common::{RawKey, RawValue};
+use ruc::*;
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8] = b"VSDB-SHALLOW-1";
+const DATA_FILE: &str = "shallow.dat";
+
+// The target directory must not exist or be empty,
+// existing data will never be overwritten.
+pub(super) fn write(
+    target_dir: &str,
+    kvs: impl Iterator<Item = (RawKey, RawValue)>,
+) -> Result<()> {
+    let dir = Path::new(target_dir);
+    if dir.exists() && fs::read_dir(dir).c(d!())?.next().is_some() {
+        return Err(eg!("the target directory is not empty"));
+    }
+    fs::create_dir_all(dir).c(d!())?;
+
+    let mut w = BufWriter::new(File::create(dir.join(DATA_FILE)).c(d!())?);
+    w.write_all(MAGIC).c(d!())?;
+    for (k, v) in kvs {
+        w.write_all(&(k.len() as u64).to_be_bytes()).c(d!())?;
+        w.write_all(&k).c(d!())?;
+        w.write_all(&(v.len() as u64).to_be_bytes()).c(d!())?;
+        w.write_all(&v).c(d!())?;
+    }
+    w.flush().c(d!())?;
+    w.get_ref().sync_all().c(d!())
+}
+
+// KVs are passed to `f` one by one, the whole set is never loaded into memory.
+pub(super) fn read(
+    src_dir: &str,
+    mut f: impl FnMut(&[u8], &[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut r = BufReader::new(File::open(Path::new(src_dir).join(DATA_FILE)).c(d!())?);
+
+    let mut magic = [0u8; MAGIC.len()];
+    r.read_exact(&mut magic).c(d!())?;
+    if MAGIC != magic {
+        return Err(eg!("not a shallow copy"));
+    }
+
+    while let Some(k) = read_chunk(&mut r, true).c(d!())? {
+        let v = read_chunk(&mut r, false)
+            .c(d!())?
+            .c(d!("truncated shallow copy"))?;
+        f(&k, &v).c(d!())?;
+    }
+
+    Ok(())
+}
+
+// A clean EOF is only allowed before a key.
+fn read_chunk(r: &mut impl Read, eof_ok: bool) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 8];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if eof_ok && ErrorKind::UnexpectedEof == e.kind() => return Ok(None),
+        Err(e) => return Err(e).c(d!()),
+    }
+    let mut buf = vec![0u8; u64::from_be_bytes(len) as usize];
+    r.read_exact(&mut buf).c(d!())?;
+    Ok(Some(buf))
+}
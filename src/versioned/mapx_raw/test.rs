@@ -1,9 +1,32 @@
 use super::*;
 use crate::{
-    common::{BranchName, ParentBranchName, VersionName, BRANCH_ANCESTORS_LIMIT},
+    basic::mapx_raw::MapxRaw,
+    common::{
+        database::Vsdb,
+        durability::{vsdb_set_durability, Durability},
+        vsdb_get_branch_ancestors_limit, vsdb_get_custom_dir,
+        vsdb_set_branch_ancestors_limit, BranchName, ParentBranchName, SavepointName,
+        VersionName, BRANCH_ANCESTORS_LIMIT, COMMIT_JOURNAL_ID,
+    },
+    merkle::sparse::EMPTY_ROOT,
+    versioned::{
+        clock::{vsdb_set_version_clock, LogicalClock, SystemClock, VersionClock},
+        state_root_of, BranchInfo, MergeConflict, MergeResolution, Unversioned,
+        VersionBundle, VersionMeta,
+    },
     ValueEnDe, VsMgmt,
 };
-use std::{sync::mpsc::channel, thread};
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 
 #[test]
 fn basic_cases() {
@@ -31,6 +54,9 @@ fn basic_cases() {
 
         assert_eq!(cnt, hdr_i.len());
 
+        hdr_i.flush();
+        assert_eq!(cnt, hdr_i.len());
+
         <MapxRawVs as ValueEnDe>::encode(&hdr_i)
     };
 
@@ -84,6 +110,1843 @@ fn VCS_mgmt() {
     default_branch(&mut hdr);
 }
 
+#[test]
+fn branch_diff() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-001")).unwrap();
+    hdr.insert(b"k-0", b"v-0").unwrap();
+    hdr.insert(b"k-1", b"v-1").unwrap();
+    hdr.insert(b"k-2", b"v-2").unwrap();
+
+    hdr.branch_create(BranchName(b"b-1")).unwrap();
+
+    // no changes on the new branch
+    assert!(
+        pnk!(hdr.branch_diff(BranchName(b"main"), BranchName(b"b-1"))).is_empty()
+    );
+
+    hdr.version_create_by_branch(VersionName(b"v-002"), BranchName(b"b-1"))
+        .unwrap();
+    hdr.remove_by_branch(b"k-0", BranchName(b"b-1")).unwrap();
+    hdr.insert_by_branch(b"k-1", b"v-1", BranchName(b"b-1"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-2", b"v-2-new", BranchName(b"b-1"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-3", b"v-3", BranchName(b"b-1"))
+        .unwrap();
+
+    let diff = pnk!(hdr.branch_diff(BranchName(b"main"), BranchName(b"b-1")));
+    assert_eq!(
+        diff,
+        vec![
+            (
+                b"k-0".to_vec().into_boxed_slice(),
+                Diff::Removed(b"v-0".to_vec().into_boxed_slice())
+            ),
+            (
+                b"k-2".to_vec().into_boxed_slice(),
+                Diff::Modified(
+                    b"v-2".to_vec().into_boxed_slice(),
+                    b"v-2-new".to_vec().into_boxed_slice()
+                )
+            ),
+            (
+                b"k-3".to_vec().into_boxed_slice(),
+                Diff::Added(b"v-3".to_vec().into_boxed_slice())
+            ),
+        ]
+    );
+
+    // the reverse view
+    let diff = pnk!(hdr.branch_diff(BranchName(b"b-1"), BranchName(b"main")));
+    assert!(matches!(diff[0].1, Diff::Added(_)));
+    assert!(matches!(diff[1].1, Diff::Modified(_, _)));
+    assert!(matches!(diff[2].1, Diff::Removed(_)));
+
+    // changes of the parent after the fork point are seen too,
+    // keys changed back to the same values are not
+    hdr.version_create(VersionName(b"v-003")).unwrap();
+    hdr.insert(b"k-1", b"v-1-new").unwrap();
+    hdr.insert(b"k-3", b"v-3").unwrap();
+    let diff = pnk!(hdr.branch_diff(BranchName(b"main"), BranchName(b"b-1")));
+    let keys = diff.iter().map(|(k, _)| &k[..]).collect::<Vec<_>>();
+    assert_eq!(keys, vec![&b"k-0"[..], b"k-1", b"k-2"]);
+
+    assert!(
+        hdr.branch_diff(BranchName(b"main"), BranchName(b"fake branch"))
+            .is_err()
+    );
+}
+
+#[test]
+fn branch_merge_conflicts() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-0", b"base").unwrap();
+    hdr.insert(b"k-1", b"base").unwrap();
+    hdr.insert(b"k-2", b"base").unwrap();
+
+    hdr.branch_create(BranchName(b"b-1")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"b-1"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-0", b"theirs", BranchName(b"b-1"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-1", b"same", BranchName(b"b-1"))
+        .unwrap();
+    hdr.remove_by_branch(b"k-2", BranchName(b"b-1")).unwrap();
+    hdr.insert_by_branch(b"k-3", b"theirs", BranchName(b"b-1"))
+        .unwrap();
+
+    // no changes on the parent side
+    assert!(pnk!(hdr.branch_merge_conflicts(BranchName(b"b-1"))).is_empty());
+
+    hdr.version_create(VersionName(b"v-2")).unwrap();
+    hdr.insert(b"k-0", b"ours").unwrap();
+    hdr.insert(b"k-1", b"same").unwrap();
+    hdr.insert(b"k-2", b"ours").unwrap();
+
+    // changes with the same values are not conflicts
+    let conflicts = pnk!(hdr.branch_merge_conflicts(BranchName(b"b-1")));
+    assert_eq!(
+        conflicts,
+        vec![
+            MergeConflict {
+                key: b"k-0".to_vec().into_boxed_slice(),
+                ours: Some(b"ours".to_vec().into_boxed_slice()),
+                theirs: Some(b"theirs".to_vec().into_boxed_slice()),
+            },
+            MergeConflict {
+                key: b"k-2".to_vec().into_boxed_slice(),
+                ours: Some(b"ours".to_vec().into_boxed_slice()),
+                theirs: None,
+            },
+        ]
+    );
+
+    // the initial branch has no parent
+    assert!(pnk!(hdr.branch_merge_conflicts(BranchName(b"main"))).is_empty());
+    assert!(hdr.branch_merge_conflicts(BranchName(b"fake branch")).is_err());
+
+    let mut resolved = vec![];
+    pnk!(
+        hdr.branch_merge_to_parent_with_resolver(BranchName(b"b-1"), |k, _, _| {
+            resolved.push(k.to_vec());
+            if k == b"k-0" {
+                MergeResolution::Theirs
+            } else {
+                MergeResolution::Value(Some(b"resolved".to_vec().into_boxed_slice()))
+            }
+        })
+    );
+    assert_eq!(resolved, vec![b"k-0".to_vec(), b"k-2".to_vec()]);
+
+    assert!(!hdr.branch_exists(BranchName(b"b-1")));
+    assert_eq!(&hdr.get(b"k-0").unwrap()[..], b"theirs");
+    assert_eq!(&hdr.get(b"k-1").unwrap()[..], b"same");
+    assert_eq!(&hdr.get(b"k-2").unwrap()[..], b"resolved");
+    assert_eq!(&hdr.get(b"k-3").unwrap()[..], b"theirs");
+
+    // the merged versions are untouched,
+    // the resolutions are in a new version after them
+    let get = |k: &[u8], v: &[u8]| {
+        hdr.get_by_branch_version(k, BranchName(b"main"), VersionName(v))
+    };
+    assert!(get(b"k-2", b"v-1").is_none());
+    assert_eq!(&get(b"k-2", b"v-1+resolved").unwrap()[..], b"resolved");
+    assert_eq!(&get(b"k-0", b"v-1+resolved").unwrap()[..], b"theirs");
+}
+
+#[test]
+fn branch_ancestry() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.branch_create_by_base_branch(BranchName(b"b-1"), ParentBranchName(b"b-0"))
+        .unwrap();
+    hdr.version_create(VersionName(b"v-2")).unwrap();
+    hdr.branch_create(BranchName(b"b-2")).unwrap();
+
+    let is_ancestor = |a: &[u8], b: &[u8]| {
+        pnk!(hdr.branch_is_ancestor(BranchName(a), BranchName(b)))
+    };
+    assert!(is_ancestor(b"main", b"b-0"));
+    assert!(is_ancestor(b"main", b"b-1"));
+    assert!(is_ancestor(b"b-0", b"b-1"));
+    assert!(!is_ancestor(b"b-1", b"b-0"));
+    assert!(!is_ancestor(b"b-0", b"b-0"));
+    assert!(!is_ancestor(b"b-0", b"b-2"));
+    assert!(hdr
+        .branch_is_ancestor(BranchName(b"main"), BranchName(b"fake branch"))
+        .is_err());
+
+    let merge_base = |a: &[u8], b: &[u8]| {
+        pnk!(hdr.branch_merge_base(BranchName(a), BranchName(b))).unwrap()
+    };
+    assert_eq!(merge_base(b"b-1", b"b-2"), (b"main".to_vec(), b"v-0".to_vec()));
+    assert_eq!(merge_base(b"b-0", b"b-1"), (b"b-0".to_vec(), b"v-1".to_vec()));
+    assert_eq!(merge_base(b"main", b"b-2"), (b"main".to_vec(), b"v-2".to_vec()));
+    assert_eq!(merge_base(b"main", b"main"), (b"main".to_vec(), b"v-2".to_vec()));
+}
+
+#[test]
+fn branch_rebase() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-001")).unwrap();
+    hdr.insert(b"k-0", b"v-0").unwrap();
+    hdr.insert(b"k-1", b"v-1").unwrap();
+
+    hdr.branch_create(BranchName(b"feature")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"f-001"), BranchName(b"feature"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-1", b"f-1", BranchName(b"feature"))
+        .unwrap();
+    hdr.remove_by_branch(b"k-0", BranchName(b"feature"))
+        .unwrap();
+    pnk!(hdr.version_tag(
+        BranchName(b"feature"),
+        VersionName(b"f-001"),
+        VersionName(b"f-rc")
+    ));
+    let created = pnk!(hdr.version_list_by_branch(BranchName(b"feature")));
+
+    // new changes on the main branch
+    hdr.version_create(VersionName(b"v-002")).unwrap();
+    hdr.insert(b"k-0", b"v-0-new").unwrap();
+    hdr.insert(b"k-2", b"v-2").unwrap();
+    assert!(hdr.get_by_branch(b"k-2", BranchName(b"feature")).is_none());
+
+    // the new parent must be older than the branch
+    assert!(
+        hdr.branch_rebase(
+            BranchName(b"main"),
+            ParentBranchName(b"feature"),
+            VersionName(b"f-001")
+        )
+        .is_err()
+    );
+
+    pnk!(hdr.branch_rebase(
+        BranchName(b"feature"),
+        ParentBranchName(b"main"),
+        VersionName(b"v-002")
+    ));
+
+    // changes of the new base are visible
+    assert_eq!(
+        &hdr.get_by_branch(b"k-2", BranchName(b"feature")).unwrap()[..],
+        b"v-2"
+    );
+
+    // changes of the branch itself are replayed on the top
+    assert_eq!(
+        &hdr.get_by_branch(b"k-1", BranchName(b"feature")).unwrap()[..],
+        b"f-1"
+    );
+    assert!(hdr.get_by_branch(b"k-0", BranchName(b"feature")).is_none());
+    assert_eq!(
+        &hdr.get_by_branch_version(
+            b"k-1",
+            BranchName(b"feature"),
+            VersionName(b"f-001")
+        )
+        .unwrap()[..],
+        b"f-1"
+    );
+
+    // names, tags and creation times are kept, nothing is left behind
+    assert_eq!(
+        created,
+        pnk!(hdr.version_list_by_branch(BranchName(b"feature")))
+    );
+    assert_eq!(
+        &hdr.get_by_branch_version(b"k-1", BranchName(b"feature"), VersionName(b"f-rc"))
+            .unwrap()[..],
+        b"f-1"
+    );
+
+    // the main branch is not affected
+    assert_eq!(&hdr.get(b"k-0").unwrap()[..], b"v-0-new");
+    assert_eq!(&hdr.get(b"k-1").unwrap()[..], b"v-1");
+
+    pnk!(hdr.branch_merge_to_parent(BranchName(b"feature")));
+    assert_eq!(&hdr.get(b"k-1").unwrap()[..], b"f-1");
+    assert!(hdr.get(b"k-0").is_none());
+}
+
+#[test]
+fn branch_rename() {
+    let hdr = MapxRawVs::new();
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-0"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.insert_by_branch(b"k", b"v", BranchName(b"b-0")).unwrap();
+    hdr.branch_create(BranchName(b"b-1")).unwrap();
+
+    // the initial branch can not be renamed
+    assert!(hdr.branch_rename(BranchName(b"main"), BranchName(b"x")).is_err());
+    // the new name is occupied
+    assert!(hdr.branch_rename(BranchName(b"b-0"), BranchName(b"b-1")).is_err());
+    // the old name does not exist
+    assert!(hdr.branch_rename(BranchName(b"x"), BranchName(b"y")).is_err());
+
+    pnk!(hdr.branch_rename(BranchName(b"b-0"), BranchName(b"b-0-new")));
+    assert!(!hdr.branch_exists(BranchName(b"b-0")));
+    assert!(hdr.branch_exists(BranchName(b"b-0-new")));
+    assert_eq!(
+        &hdr.get_by_branch(b"k", BranchName(b"b-0-new")).unwrap()[..],
+        b"v"
+    );
+    assert!(hdr.version_exists_on_branch(VersionName(b"v-0"), BranchName(b"b-0-new")));
+
+    // the old name can be reused
+    pnk!(hdr.branch_create(BranchName(b"b-0")));
+    assert!(hdr.get_by_branch(b"k", BranchName(b"b-0")).is_none());
+}
+
+#[test]
+fn version_bundle() {
+    let src = MapxRawVs::new();
+
+    src.branch_create(BranchName(b"b")).unwrap();
+    (0u8..4).for_each(|i| {
+        src.version_create_by_branch(VersionName(&[i]), BranchName(b"b"))
+            .unwrap();
+        src.insert_by_branch(&[i], &[i], BranchName(b"b")).unwrap();
+    });
+    src.remove_by_branch(&[0], BranchName(b"b")).unwrap();
+
+    let bundle = pnk!(src.export_versions_by_branch(BranchName(b"b"), ..));
+    assert_eq!(4, bundle.versions.len());
+    assert_eq!(2, bundle.versions[3].changes.len());
+
+    let bundle = pnk!(src.export_versions_by_branch(
+        BranchName(b"b"),
+        VersionName(&[1])..VersionName(&[3])
+    ));
+    assert_eq!(2, bundle.versions.len());
+    assert_eq!(&[1], &bundle.versions[0].name[..]);
+    assert!(src
+        .export_versions_by_branch(BranchName(b"b"), VersionName(b"x")..)
+        .is_err());
+
+    // the bundle survives a round trip of encoding
+    let bundle = pnk!(src.export_versions_by_branch(BranchName(b"b"), ..));
+    let bundle = <VersionBundle as ValueEnDe>::encode(&bundle);
+    let bundle = pnk!(<VersionBundle as ValueEnDe>::decode(&bundle));
+
+    let dst = MapxRawVs::new();
+    pnk!(dst.branch_create_from_bundle(BranchName(b"b"), &bundle));
+    assert!(dst
+        .branch_create_from_bundle(BranchName(b"b"), &bundle)
+        .is_err());
+
+    (0u8..4).for_each(|i| {
+        assert!(dst.version_created_on_branch(VersionName(&[i]), BranchName(b"b")));
+    });
+    assert!(dst.get_by_branch(&[0], BranchName(b"b")).is_none());
+    (1u8..4).for_each(|i| {
+        assert_eq!(&[i], &dst.get_by_branch(&[i], BranchName(b"b")).unwrap()[..]);
+    });
+    assert_eq!(
+        &[0],
+        &dst.get_by_branch_version(&[0], BranchName(b"b"), VersionName(&[2]))
+            .unwrap()[..]
+    );
+    assert!(dst.get(&[1]).is_none());
+
+    let mut dup = bundle.clone();
+    dup.versions.push(bundle.versions[0].clone());
+    assert!(dst.branch_create_from_bundle(BranchName(b"c"), &dup).is_err());
+    assert!(!dst.branch_exists(BranchName(b"c")));
+}
+
+#[test]
+fn version_tag() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k", b"v-0").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(b"k", b"v-1").unwrap();
+
+    pnk!(hdr.version_tag(BranchName(b"main"), VersionName(b"v-0"), VersionName(b"rc")));
+    assert!(hdr
+        .version_tag(BranchName(b"main"), VersionName(b"v-1"), VersionName(b"rc"))
+        .is_err());
+    assert!(hdr
+        .version_tag(BranchName(b"main"), VersionName(b"v-x"), VersionName(b"x"))
+        .is_err());
+
+    assert_eq!(
+        &hdr.get_by_branch_version(b"k", BranchName(b"main"), VersionName(b"rc"))
+            .unwrap()[..],
+        b"v-0"
+    );
+    assert!(hdr.version_created(VersionName(b"rc")));
+
+    // tags are visible on child branches
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+        .unwrap();
+    assert_eq!(
+        &hdr.get_by_branch_version(b"k", BranchName(b"b-0"), VersionName(b"rc"))
+            .unwrap()[..],
+        b"v-0"
+    );
+
+    // but not on parent branches
+    pnk!(hdr.version_tag(BranchName(b"b-0"), VersionName(b"v-2"), VersionName(b"x")));
+    assert!(!hdr.version_exists_on_branch(VersionName(b"x"), BranchName(b"main")));
+    assert!(hdr.version_exists_on_branch(VersionName(b"x"), BranchName(b"b-0")));
+
+    // tags of removed versions are invalid and can be reused
+    pnk!(hdr.version_pop_by_branch(BranchName(b"b-0")));
+    assert!(!hdr.version_exists_on_branch(VersionName(b"x"), BranchName(b"b-0")));
+    pnk!(hdr.version_tag(BranchName(b"main"), VersionName(b"v-1"), VersionName(b"x")));
+    assert_eq!(
+        &hdr.get_by_branch_version(b"k", BranchName(b"main"), VersionName(b"x"))
+            .unwrap()[..],
+        b"v-1"
+    );
+
+    pnk!(hdr.version_untag(VersionName(b"rc")));
+    assert!(hdr.version_untag(VersionName(b"rc")).is_err());
+    assert!(!hdr.version_created(VersionName(b"rc")));
+    assert!(hdr.version_created(VersionName(b"v-0")));
+}
+
+#[test]
+fn branch_list() {
+    let hdr = MapxRawVs::new();
+
+    let brs = pnk!(hdr.branch_list());
+    assert_eq!(1, brs.len());
+    assert_eq!(b"main", &brs[0].name[..]);
+    assert!(brs[0].parent.is_none());
+    assert!(brs[0].base_version.is_none());
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.branch_create(BranchName(b"b-1")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"b-1"))
+        .unwrap();
+    hdr.branch_create_by_base_branch(BranchName(b"b-0"), ParentBranchName(b"b-1"))
+        .unwrap();
+
+    let brs = pnk!(hdr.branch_list());
+    assert_eq!(3, brs.len());
+    assert_eq!(b"b-0", &brs[0].name[..]);
+    assert_eq!(b"b-1", &brs[0].parent.as_ref().unwrap()[..]);
+    assert_eq!(b"v-1", &brs[0].base_version.as_ref().unwrap()[..]);
+    assert_eq!(b"b-1", &brs[1].name[..]);
+    assert_eq!(b"main", &brs[1].parent.as_ref().unwrap()[..]);
+    assert_eq!(b"v-0", &brs[1].base_version.as_ref().unwrap()[..]);
+    assert_eq!(b"main", &brs[2].name[..]);
+
+    pnk!(hdr.branch_remove(BranchName(b"b-0")));
+    assert_eq!(2, pnk!(hdr.branch_list()).len());
+
+    // aggregated through `VsMgmt`
+    let hdrs = Some(hdr);
+    assert_eq!(2, pnk!(hdrs.branch_list()).len());
+    assert!(pnk!(None::<MapxRawVs>.branch_list()).is_empty());
+}
+
+#[test]
+fn contains_all() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-0", b"").unwrap();
+    hdr.insert(b"k-1", b"").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(b"k-2", b"").unwrap();
+    hdr.remove(b"k-0").unwrap();
+
+    assert!(hdr.contains_all::<&[u8]>(&[]));
+    assert!(hdr.contains_all(&[b"k-1", b"k-2"]));
+    assert!(!hdr.contains_all(&[b"k-0", b"k-1", b"k-2"]));
+    assert!(hdr.contains_all_by_branch(&[b"k-1", b"k-2"], BranchName(b"main")));
+    assert!(!hdr.contains_all_by_branch(&[b"k-1"], BranchName(b"fake branch")));
+
+    assert!(hdr.contains_all_by_branch_version(
+        &[b"k-0", b"k-1"],
+        BranchName(b"main"),
+        VersionName(b"v-0")
+    ));
+    assert!(!hdr.contains_all_by_branch_version(
+        &[b"k-1", b"k-2"],
+        BranchName(b"main"),
+        VersionName(b"v-0")
+    ));
+    assert!(!hdr.contains_all_by_branch_version(
+        &[b"k-1"],
+        BranchName(b"main"),
+        VersionName(b"fake version")
+    ));
+}
+
+#[test]
+fn get_many_by_branch_version() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-0", b"0").unwrap();
+    hdr.insert(b"k-1", b"0").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(b"k-1", b"1").unwrap();
+    hdr.remove(b"k-0").unwrap();
+
+    let get_many = |keys: &[&[u8]], ver: &[u8]| {
+        hdr.get_many_by_branch_version(keys, BranchName(b"main"), VersionName(ver))
+            .into_iter()
+            .map(|v| v.map(|v| v.to_vec()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        get_many(&[b"k-1", b"k-2", b"k-0"], b"v-0"),
+        vec![Some(b"0".to_vec()), None, Some(b"0".to_vec())]
+    );
+    assert_eq!(
+        get_many(&[b"k-0", b"k-1", b"k-1"], b"v-1"),
+        vec![None, Some(b"1".to_vec()), Some(b"1".to_vec())]
+    );
+    assert_eq!(get_many(&[b"k-1"], b"fake version"), vec![None]);
+    assert!(get_many(&[], b"v-0").is_empty());
+}
+
+#[test]
+fn version_squash() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-0", b"0").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(b"k-0", b"1").unwrap();
+    hdr.insert(b"k-1", b"1").unwrap();
+    hdr.version_create(VersionName(b"v-2")).unwrap();
+    hdr.insert(b"k-1", b"2").unwrap();
+    hdr.remove(b"k-0").unwrap();
+    hdr.version_create(VersionName(b"v-3")).unwrap();
+    hdr.insert(b"k-2", b"3").unwrap();
+
+    hdr.branch_create_by_base_branch_version(
+        BranchName(b"b-0"),
+        ParentBranchName(b"main"),
+        VersionName(b"v-1"),
+    )
+    .unwrap();
+
+    // `v-1` is the base point of `b-0`
+    assert!(hdr
+        .version_squash(VersionName(b"v-1"), VersionName(b"v-2"))
+        .is_err());
+    assert!(hdr
+        .version_squash(VersionName(b"v-3"), VersionName(b"v-2"))
+        .is_err());
+    assert!(hdr
+        .version_squash(VersionName(b"v-2"), VersionName(b"fake version"))
+        .is_err());
+
+    pnk!(hdr.branch_remove(BranchName(b"b-0")));
+    pnk!(hdr.version_squash(VersionName(b"v-1"), VersionName(b"v-3")));
+
+    assert!(!hdr.version_exists(VersionName(b"v-1")));
+    assert!(!hdr.version_exists(VersionName(b"v-2")));
+    assert!(hdr.version_exists(VersionName(b"v-3")));
+
+    // the endpoint states are preserved
+    assert!(hdr.get(b"k-0").is_none());
+    assert_eq!(&hdr.get(b"k-1").unwrap()[..], b"2");
+    assert_eq!(&hdr.get(b"k-2").unwrap()[..], b"3");
+    assert_eq!(
+        &hdr.get_by_branch_version(b"k-0", BranchName(b"main"), VersionName(b"v-0"))
+            .unwrap()[..],
+        b"0"
+    );
+
+    let vers = pnk!(hdr.version_list());
+    assert_eq!(3, vers.len());
+    assert_eq!(b"v-3", &vers[2].name[..]);
+    assert_eq!(3, vers[2].change_count);
+
+    // squash a single version is a no-op
+    pnk!(hdr.version_squash(VersionName(b"v-3"), VersionName(b"v-3")));
+    assert_eq!(3, pnk!(hdr.version_list()).len());
+}
+
+#[test]
+fn changed_key_since() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-5", b"").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(b"k-3", b"").unwrap();
+    hdr.version_create(VersionName(b"v-2")).unwrap();
+    hdr.remove(b"k-5").unwrap();
+    hdr.insert(b"k-4", b"").unwrap();
+
+    assert_eq!(&pnk!(hdr.min_changed_key_since(VersionName(b"v-0")))[..], b"k-3");
+    assert_eq!(&pnk!(hdr.max_changed_key_since(VersionName(b"v-0")))[..], b"k-5");
+    assert_eq!(&pnk!(hdr.min_changed_key_since(VersionName(b"v-1")))[..], b"k-4");
+    assert_eq!(&pnk!(hdr.max_changed_key_since(VersionName(b"v-1")))[..], b"k-5");
+    assert!(hdr.min_changed_key_since(VersionName(b"v-2")).is_none());
+    assert!(hdr.max_changed_key_since(VersionName(b"fake version")).is_none());
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-3"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-4"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-9", b"", BranchName(b"b-0"))
+        .unwrap();
+
+    let b0 = BranchName(b"b-0");
+    assert_eq!(
+        &pnk!(hdr.min_changed_key_since_by_branch(VersionName(b"v-3"), b0))[..],
+        b"k-9"
+    );
+    assert!(hdr
+        .max_changed_key_since_by_branch(VersionName(b"v-4"), b0)
+        .is_none());
+
+    // changes on other branches are invisible
+    assert!(hdr.min_changed_key_since(VersionName(b"v-2")).is_none());
+}
+
+#[test]
+fn version_revert() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-0", b"0").unwrap();
+    hdr.insert(b"k-1", b"0").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(b"k-0", b"1").unwrap();
+    hdr.remove(b"k-1").unwrap();
+    hdr.insert(b"k-2", b"1").unwrap();
+    hdr.version_create(VersionName(b"v-2")).unwrap();
+    hdr.insert(b"k-3", b"2").unwrap();
+
+    pnk!(hdr.version_revert(VersionName(b"v-1"), VersionName(b"v-3")));
+    assert!(hdr.version_created(VersionName(b"v-3")));
+
+    assert_eq!(&hdr.get(b"k-0").unwrap()[..], b"0");
+    assert_eq!(&hdr.get(b"k-1").unwrap()[..], b"0");
+    assert!(hdr.get(b"k-2").is_none());
+    assert_eq!(&hdr.get(b"k-3").unwrap()[..], b"2");
+
+    // history is untouched
+    assert_eq!(
+        &hdr.get_by_branch_version(b"k-0", BranchName(b"main"), VersionName(b"v-2"))
+            .unwrap()[..],
+        b"1"
+    );
+
+    // revert on a branch
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-4"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-3", b"4", BranchName(b"b-0"))
+        .unwrap();
+    pnk!(hdr.version_revert_by_branch(
+        VersionName(b"v-4"),
+        VersionName(b"v-5"),
+        BranchName(b"b-0")
+    ));
+    assert_eq!(
+        &hdr.get_by_branch(b"k-3", BranchName(b"b-0")).unwrap()[..],
+        b"2"
+    );
+
+    assert!(hdr
+        .version_revert(VersionName(b"fake version"), VersionName(b"v-6"))
+        .is_err());
+    assert!(hdr
+        .version_revert(VersionName(b"v-2"), VersionName(b"v-3"))
+        .is_err());
+}
+
+#[test]
+fn version_list() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k-0", b"").unwrap();
+    hdr.insert(b"k-1", b"").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.remove(b"k-0").unwrap();
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+        .unwrap();
+
+    // the initial version is included
+    let vers = pnk!(hdr.version_list());
+    assert_eq!(3, vers.len());
+    assert!(vers[0].name.is_empty());
+    assert_eq!(b"v-0", &vers[1].name[..]);
+    assert_eq!(2, vers[1].change_count);
+    assert_eq!(b"v-1", &vers[2].name[..]);
+    assert_eq!(1, vers[2].change_count);
+    assert!(vers[1].created_at <= vers[2].created_at);
+
+    let vers = pnk!(hdr.version_list_by_branch(BranchName(b"b-0")));
+    assert_eq!(1, vers.len());
+    assert_eq!(b"v-2", &vers[0].name[..]);
+    assert_eq!(0, vers[0].change_count);
+
+    assert!(hdr.version_list_by_branch(BranchName(b"fake branch")).is_err());
+}
+
+#[test]
+fn version_meta() {
+    let hdr = MapxRawVs::new();
+
+    let meta = VersionMeta {
+        author: "alice".to_owned(),
+        message: "init".to_owned(),
+        timestamp: 1,
+        extra: vec![9; 3],
+    };
+
+    hdr.version_create_with_meta(VersionName(b"v-0"), &meta)
+        .unwrap();
+    hdr.insert(b"k-0", b"").unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    assert!(hdr
+        .version_create_with_meta(VersionName(b"v-1"), &meta)
+        .is_err());
+
+    assert_eq!(
+        Some(meta.clone()),
+        hdr.version_get_meta(VersionName(b"v-0"))
+    );
+    assert!(hdr.version_get_meta(VersionName(b"v-1")).is_none());
+    assert!(hdr.version_get_meta(VersionName(b"fake version")).is_none());
+
+    let meta2 = VersionMeta {
+        message: "fix".to_owned(),
+        ..meta
+    };
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch_with_meta(
+        VersionName(b"v-2"),
+        BranchName(b"b-0"),
+        &meta2,
+    )
+    .unwrap();
+    assert_eq!(
+        Some(meta2),
+        hdr.version_get_meta_by_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+    );
+    assert!(hdr.version_get_meta(VersionName(b"v-2")).is_none());
+
+    // metadata follows the version
+    hdr.branch_remove(BranchName(b"b-0")).unwrap();
+    hdr.version_pop().unwrap();
+    hdr.version_pop().unwrap();
+    assert!(hdr.version_get_meta(VersionName(b"v-0")).is_none());
+}
+
+struct Fields(Vec<MapxRawVs>);
+
+impl Fields {
+    fn values(&self) -> std::slice::Iter<'_, MapxRawVs> {
+        self.0.iter()
+    }
+
+    fn values_mut(&mut self) -> std::slice::IterMut<'_, MapxRawVs> {
+        self.0.iter_mut()
+    }
+}
+
+impl VsMgmt for Fields {
+    crate::impl_for_collections!(values, values_mut);
+}
+
+#[test]
+fn vs_commit() {
+    let hdr = Fields(vec![MapxRawVs::new(), MapxRawVs::new()]);
+
+    pnk!(hdr.vs_commit(VersionName(b"v-0")));
+    assert!(hdr.0.iter().all(|i| i.version_created(VersionName(b"v-0"))));
+
+    // the second field fails, the first one should be rolled back
+    hdr.0[1].version_create(VersionName(b"v-1")).unwrap();
+    assert!(hdr.vs_commit(VersionName(b"v-1")).is_err());
+    assert!(!hdr.0[0].version_created(VersionName(b"v-1")));
+    assert!(hdr.0[1].version_created(VersionName(b"v-1")));
+
+    // fields are still writable on their original versions
+    hdr.0[0].insert(b"k", b"v").unwrap();
+    assert_eq!(
+        pnk!(hdr.0[0].version_list()).last().unwrap().name,
+        b"v-0".to_vec()
+    );
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    pnk!(hdr.vs_commit_by_branch(VersionName(b"v-2"), BranchName(b"b-0")));
+    assert!(hdr.0.iter().all(|i| {
+        i.version_created_on_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+    }));
+}
+
+#[test]
+fn version_create_cas() {
+    let hdr = MapxRawVs::new();
+
+    // no version has been created on the branch
+    let v = VersionName(b"v-x");
+    assert!(hdr
+        .version_create_cas(VersionName(b"v-0"), Some(v))
+        .is_err());
+    pnk!(hdr.version_create_cas(VersionName(b"v-0"), None));
+    assert!(hdr.version_create_cas(VersionName(b"v-1"), None).is_err());
+
+    // another writer has advanced the branch
+    pnk!(hdr.version_create(VersionName(b"v-1")));
+    let v = VersionName(b"v-0");
+    assert!(hdr
+        .version_create_cas(VersionName(b"v-2"), Some(v))
+        .is_err());
+    let head = pnk!(hdr.version_list()).pop().unwrap().name;
+    pnk!(hdr.version_create_cas(VersionName(b"v-2"), Some(VersionName(&head))));
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    let br = BranchName(b"b-0");
+    pnk!(hdr.version_create_by_branch_cas(VersionName(b"v-3"), br, None));
+    let v = VersionName(b"v-2");
+    assert!(hdr
+        .version_create_by_branch_cas(VersionName(b"v-4"), br, Some(v))
+        .is_err());
+
+    // only one of the writers based on the same head succeeds
+    let writers = (0..8u8)
+        .map(|i| {
+            let hdr = hdr.clone();
+            thread::spawn(move || {
+                let v = VersionName(b"v-2");
+                hdr.version_create_cas(VersionName(&[i]), Some(v)).is_ok()
+            })
+        })
+        .collect::<Vec<_>>();
+    let succeeded = writers
+        .into_iter()
+        .map(|w| w.join().unwrap())
+        .filter(|ok| *ok)
+        .count();
+    assert_eq!(1, succeeded);
+
+    // the new version is removed from all fields if any check fails
+    let hdr = Fields(vec![MapxRawVs::new(), MapxRawVs::new()]);
+    pnk!(hdr.vs_commit_cas(VersionName(b"v-0"), None));
+    hdr.0[1].version_create(VersionName(b"v-1")).unwrap();
+    let v = VersionName(b"v-0");
+    assert!(hdr.vs_commit_cas(VersionName(b"v-2"), Some(v)).is_err());
+    assert!(!hdr.0[0].version_created(VersionName(b"v-2")));
+}
+
+#[test]
+fn read_isolation() {
+    let hdr = MapxRawVs::new();
+    pnk!(hdr.version_create(VersionName(b"v-0")));
+    pnk!(hdr.insert(&[0], &[0]));
+
+    pnk!(hdr.set_read_isolation(true));
+
+    // changes of the version being built are invisible to readers
+    pnk!(hdr.version_create(VersionName(b"v-1")));
+    pnk!(hdr.insert(&[0], &[1]));
+    pnk!(hdr.insert(&[1], &[1]));
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[0]);
+    assert!(hdr.get(&[1]).is_none());
+    assert_eq!(1, hdr.iter().count());
+
+    // lengths are in the view of the writer
+    assert_eq!(2, hdr.len());
+
+    // but the writer can read them by the version
+    let br = BranchName(INITIAL_BRANCH_NAME);
+    let v = VersionName(b"v-1");
+    assert!(hdr.get_by_branch_version(&[1], br, v).is_some());
+
+    pnk!(hdr.version_finalize());
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[1]);
+    assert_eq!(2, hdr.iter().count());
+
+    // creating a newer version finalizes the former one
+    pnk!(hdr.version_create(VersionName(b"v-2")));
+    pnk!(hdr.remove(&[0]));
+    pnk!(hdr.version_create(VersionName(b"v-3")));
+    assert!(hdr.get(&[0]).is_none());
+    assert!(hdr.get(&[1]).is_some());
+
+    // a new branch reads the state of its base until its version is finalized
+    pnk!(hdr.branch_create(BranchName(b"b-0")));
+    let br = BranchName(b"b-0");
+    pnk!(hdr.version_create_by_branch(VersionName(b"v-4"), br));
+    pnk!(hdr.insert_by_branch(&[2], &[2], br));
+    assert!(hdr.get_by_branch(&[1], br).is_some());
+    assert!(hdr.get_by_branch(&[2], br).is_none());
+    assert_eq!(1, hdr.iter_by_branch(br).count());
+    pnk!(hdr.version_finalize_by_branch(br));
+    assert!(hdr.get_by_branch(&[2], br).is_some());
+    assert!(hdr.version_finalize_by_branch(BranchName(b"b-x")).is_err());
+
+    // disabling the isolation finalizes all versions
+    pnk!(hdr.version_create(VersionName(b"v-5")));
+    pnk!(hdr.insert(&[3], &[3]));
+    assert!(hdr.get(&[3]).is_none());
+    pnk!(hdr.set_read_isolation(false));
+    assert!(hdr.get(&[3]).is_some());
+}
+
+#[test]
+fn read_isolation_writer_view() {
+    let hdr = crate::VecxVs::new();
+    pnk!(hdr.version_create(VersionName(b"v-0")));
+    hdr.push(0u8);
+    pnk!(hdr.set_read_isolation(true));
+
+    // indexes are allocated in the view of the writer
+    pnk!(hdr.version_create(VersionName(b"v-1")));
+    hdr.push(1);
+    hdr.push(2);
+    assert_eq!(3, hdr.len());
+    assert_eq!(Some(2), hdr.last());
+    assert!(hdr.get(2).is_none());
+    assert_eq!(Some(2), pnk!(hdr.pop()));
+    assert_eq!(2, hdr.len());
+    assert_eq!(Some(1), hdr.last());
+
+    // read-modify-write on the values being built
+    *hdr.get_mut(1).unwrap() += 10;
+    *hdr.get_mut(1).unwrap() += 10;
+    assert!(hdr.get(1).is_none());
+    assert_eq!(1, hdr.iter().count());
+
+    pnk!(hdr.version_finalize());
+    assert_eq!(vec![0, 21], hdr.iter().collect::<Vec<_>>());
+
+    let map = crate::MapxVs::new();
+    pnk!(map.version_create(VersionName(b"v-0")));
+    pnk!(map.set_read_isolation(true));
+    pnk!(map.version_create(VersionName(b"v-1")));
+    *map.entry_ref(&0u8).or_insert_ref(&0u8) += 1;
+    *map.entry_ref(&0u8).or_insert_ref(&0u8) += 1;
+    assert!(map.get(&0).is_none());
+    pnk!(map.version_finalize());
+    assert_eq!(Some(2), map.get(&0));
+}
+
+#[test]
+fn savepoint() {
+    let hdr = MapxRawVs::new();
+    pnk!(hdr.version_create(VersionName(b"v-0")));
+    pnk!(hdr.insert(&[0], &[0]));
+    pnk!(hdr.version_create(VersionName(b"v-1")));
+    pnk!(hdr.insert(&[1], &[1]));
+
+    pnk!(hdr.savepoint(SavepointName(b"s-0")));
+    pnk!(hdr.insert(&[0], &[10]));
+    pnk!(hdr.insert(&[1], &[11]));
+    pnk!(hdr.insert(&[2], &[12]));
+
+    pnk!(hdr.savepoint(SavepointName(b"s-1")));
+    pnk!(hdr.remove(&[0]));
+    pnk!(hdr.insert(&[3], &[13]));
+
+    pnk!(hdr.rollback_to_savepoint(SavepointName(b"s-1")));
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[10]);
+    assert!(hdr.get(&[3]).is_none());
+
+    // the savepoint is kept, those taken after it are dropped
+    pnk!(hdr.rollback_to_savepoint(SavepointName(b"s-0")));
+    assert!(hdr.rollback_to_savepoint(SavepointName(b"s-1")).is_err());
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[0]);
+    assert_eq!(&hdr.get(&[1]).unwrap()[..], &[1]);
+    assert!(hdr.get(&[2]).is_none());
+    assert_eq!(2, hdr.len());
+    pnk!(hdr.rollback_to_savepoint(SavepointName(b"s-0")));
+
+    // keys untouched since the savepoint are not in the version anymore
+    let v = VersionName(b"v-1");
+    let bundle = pnk!(hdr.export_versions(v..=v));
+    assert_eq!(1, bundle.versions[0].changes.len());
+
+    // savepoints are dropped once a new version is created
+    pnk!(hdr.version_create(VersionName(b"v-2")));
+    assert!(hdr.rollback_to_savepoint(SavepointName(b"s-0")).is_err());
+
+    // on a specified branch
+    pnk!(hdr.branch_create(BranchName(b"b-0")));
+    let br = BranchName(b"b-0");
+    pnk!(hdr.version_create_by_branch(VersionName(b"v-3"), br));
+    pnk!(hdr.savepoint_by_branch(SavepointName(b"s-0"), br));
+    pnk!(hdr.insert_by_branch(&[4], &[4], br));
+    pnk!(hdr.rollback_to_savepoint_by_branch(SavepointName(b"s-0"), br));
+    assert!(hdr.get_by_branch(&[4], br).is_none());
+    assert!(hdr.get_by_branch(&[1], br).is_some());
+    let br = BranchName(b"b-x");
+    assert!(hdr.savepoint_by_branch(SavepointName(b"s-0"), br).is_err());
+
+    // branches with savepoints can not be rebased, the IDs of versions are changed
+    let br = BranchName(b"b-0");
+    let (parent, v2) = (ParentBranchName(b"main"), VersionName(b"v-2"));
+    assert!(hdr.branch_rebase(br, parent, v2).is_err());
+    pnk!(hdr.version_create_by_branch(VersionName(b"v-4"), br));
+    pnk!(hdr.branch_rebase(br, parent, v2));
+
+    // all fields are rolled back together
+    let hdr = Fields(vec![MapxRawVs::new(), MapxRawVs::new()]);
+    pnk!(hdr.vs_commit(VersionName(b"v-0")));
+    pnk!(hdr.savepoint(SavepointName(b"s-0")));
+    hdr.0.iter().for_each(|i| {
+        pnk!(i.insert(&[0], &[0]));
+    });
+    pnk!(hdr.rollback_to_savepoint(SavepointName(b"s-0")));
+    assert!(hdr.0.iter().all(|i| i.is_empty()));
+}
+
+struct Wrapper {
+    state: State,
+}
+
+struct State {
+    balances: MapxRawVs,
+}
+
+impl VsMgmt for Wrapper {
+    crate::impl_vs_methods!(state.balances);
+}
+
+#[test]
+fn delegate_to_nested_field() {
+    let hdr = Wrapper {
+        state: State {
+            balances: MapxRawVs::new(),
+        },
+    };
+
+    pnk!(hdr.version_create(VersionName(b"v-0")));
+    assert!(hdr.state.balances.version_exists(VersionName(b"v-0")));
+    pnk!(hdr.branch_create(BranchName(b"b-0")));
+    assert!(hdr.state.balances.branch_exists(BranchName(b"b-0")));
+}
+
+#[test]
+fn unversioned_field() {
+    let cache = Unversioned(std::collections::HashMap::<u8, u8>::new());
+    pnk!(cache.version_create(VersionName(b"v-0")));
+    pnk!(cache.branch_create(BranchName(b"b-0")));
+    assert!(cache.version_exists(VersionName(b"v-0")));
+    assert!(cache.is_empty());
+
+    let bytes = <Unversioned<u8> as ValueEnDe>::encode(&Unversioned(9));
+    assert_eq!(bytes, <u8 as ValueEnDe>::encode(&9));
+}
+
+#[test]
+fn snapshot() {
+    let fields = Fields(vec![MapxRawVs::new()]);
+    let hdr = &fields.0[0];
+
+    hdr.version_create(VersionName(b"snap-0")).unwrap();
+    hdr.insert(b"k-0", b"v-0").unwrap();
+    hdr.insert(b"k-1", b"v-0").unwrap();
+    hdr.version_create(VersionName(b"snap-1")).unwrap();
+    hdr.insert(b"k-0", b"v-1").unwrap();
+
+    assert!(hdr
+        .snapshot(BranchName(b"main"), VersionName(b"fake version"))
+        .is_err());
+
+    let snap = pnk!(hdr.snapshot(BranchName(b"main"), VersionName(b"snap-0")));
+    assert_eq!(&snap.get(b"k-0").unwrap()[..], b"v-0");
+    assert_eq!(2, snap.len());
+    assert!(snap.contains_key(b"k-1"));
+
+    (2..5u8).for_each(|i| {
+        hdr.version_create(VersionName(&[i])).unwrap();
+        hdr.insert(b"k-1", &[i]).unwrap();
+    });
+
+    // the pinned version survives pruning
+    hdr.prune(Some(1)).unwrap();
+    assert_eq!(&snap.get(b"k-0").unwrap()[..], b"v-0");
+    assert_eq!(&snap.get(b"k-1").unwrap()[..], b"v-0");
+    assert_eq!(2, snap.iter().count());
+    assert!(hdr.version_exists(VersionName(b"snap-1")));
+    assert_eq!(&hdr.get(b"k-1").unwrap()[..], &[4]);
+
+    // a projected view keeps the pin
+    let view = {
+        let snap = pnk!(fields.snapshot(BranchName(b"main"), VersionName(b"snap-1")));
+        snap.project(|f| &f.0[0])
+    };
+    drop(snap);
+    fields.prune(Some(1)).unwrap();
+    assert!(!hdr.version_exists(VersionName(b"snap-0")));
+    assert_eq!(&view.get(b"k-0").unwrap()[..], b"v-1");
+    assert_eq!(&view.get(b"k-1").unwrap()[..], b"v-0");
+
+    drop(view);
+    fields.prune(Some(1)).unwrap();
+    assert!(!hdr.version_exists(VersionName(b"snap-1")));
+
+    // pins are kept by instances,
+    // same-named versions of other instances are not protected
+    let other = MapxRawVs::new();
+    [hdr, &other].iter().for_each(|i| {
+        (5..8u8).for_each(|n| {
+            i.version_create(VersionName(&[n])).unwrap();
+            i.insert(b"k-0", &[n]).unwrap();
+        });
+    });
+    let snap = pnk!(hdr.snapshot(BranchName(b"main"), VersionName(&[5])));
+    hdr.prune(Some(1)).unwrap();
+    other.prune(Some(1)).unwrap();
+    assert!(hdr.version_exists(VersionName(&[5])));
+    assert!(!other.version_exists(VersionName(&[5])));
+    drop(snap);
+}
+
+#[test]
+fn snapshot_to_plain() {
+    let map = crate::MapxVs::new();
+    let vec = crate::VecxVs::new();
+    let main = BranchName(b"main");
+
+    map.version_create(VersionName(b"v-0")).unwrap();
+    vec.version_create(VersionName(b"v-0")).unwrap();
+    map.insert(2u32, 20u64).unwrap();
+    map.insert(1u32, 10u64).unwrap();
+    vec.push(7u64);
+    map.version_create(VersionName(b"v-1")).unwrap();
+    vec.version_create(VersionName(b"v-1")).unwrap();
+    map.insert(3u32, 30u64).unwrap();
+    vec.push(8u64);
+
+    let m = pnk!(map.snapshot(main, VersionName(b"v-0")));
+    let v = pnk!(vec.snapshot(main, VersionName(b"v-0")));
+    let mut kvs = m.to_plain();
+    kvs.sort_unstable();
+    assert_eq!(vec![(1, 10), (2, 20)], kvs);
+    assert_eq!(vec![7], v.to_plain());
+
+    let v = pnk!(vec.snapshot(main, VersionName(b"v-1")));
+    assert_eq!(vec![7, 8], v.to_plain());
+}
+
+#[test]
+fn std_wrappers() {
+    let mut hdr = (
+        Box::new(MapxRawVs::new()),
+        Arc::new(MapxRawVs::new()),
+        [MapxRawVs::new(), MapxRawVs::new()],
+    );
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    assert!(hdr.0.version_exists(VersionName(b"v-0")));
+    assert!(hdr.1.version_exists(VersionName(b"v-0")));
+    assert!(hdr.2.iter().all(|i| i.version_exists(VersionName(b"v-0"))));
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    assert!(hdr.branch_exists(BranchName(b"b-0")));
+    hdr.branch_set_default(BranchName(b"b-0")).unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    assert!(hdr.version_created_on_branch(VersionName(b"v-1"), BranchName(b"b-0")));
+    assert!(!hdr.version_exists_on_branch(VersionName(b"v-1"), BranchName(b"main")));
+
+    // a shared `Arc` can not be changed
+    let shared = Arc::clone(&hdr.1);
+    assert!(hdr.branch_set_default(BranchName(b"main")).is_err());
+    drop(shared);
+    hdr.branch_set_default(BranchName(b"main")).unwrap();
+}
+
+#[test]
+fn vecx_by_branch_version() {
+    let hdr = crate::VecxVs::new();
+
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    (0..3u8).for_each(|i| hdr.push(i));
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.push(3);
+    hdr.update(0, 9).unwrap();
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.push_by_branch(4, BranchName(b"b-0"));
+
+    let main = BranchName(b"main");
+    assert_eq!(3, hdr.len_by_branch_version(main, VersionName(b"v-0")));
+    assert_eq!(
+        vec![0, 1, 2],
+        hdr.iter_by_branch_version(main, VersionName(b"v-0"))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(2),
+        hdr.last_by_branch_version(main, VersionName(b"v-0"))
+    );
+    assert_eq!(
+        vec![9, 1, 2, 3],
+        hdr.iter_by_branch_version(main, VersionName(b"v-1"))
+            .collect::<Vec<_>>()
+    );
+
+    // lengths differ between branches
+    assert_eq!(Some(4), hdr.last_by_branch(BranchName(b"b-0")));
+    assert_eq!(Some(3), hdr.last());
+    assert!(hdr
+        .last_by_branch_version(main, VersionName(b"fake version"))
+        .is_none());
+}
+
+#[test]
+fn prune_with() {
+    let hdr = MapxRawVs::new();
+    (0..10u8).for_each(|i| {
+        hdr.version_create(VersionName(&[i])).unwrap();
+        hdr.insert(&[i], &[i]).unwrap();
+    });
+    hdr.version_tag(BranchName(b"main"), VersionName(&[3]), VersionName(b"t-3"))
+        .unwrap();
+
+    assert!(hdr
+        .prune_with(&PrunePolicy {
+            keep_last: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+
+    // all versions are created just now
+    hdr.prune_with(&PrunePolicy {
+        keep_last: Some(1),
+        keep_newer_than: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(hdr.version_exists(VersionName(&[0])));
+
+    // versions before the tagged one are pruned
+    hdr.prune_with(&PrunePolicy {
+        keep_last: Some(1),
+        keep_tagged: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!hdr.version_exists(VersionName(&[2])));
+    assert!(hdr.version_exists(VersionName(&[3])));
+    assert!(hdr.version_exists(VersionName(b"t-3")));
+    assert_eq!(
+        &hdr.get_by_branch_version(&[0], BranchName(b"main"), VersionName(&[3]))
+            .unwrap()[..],
+        &[0]
+    );
+
+    hdr.prune_with(&PrunePolicy {
+        keep_last: Some(1),
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!hdr.version_exists(VersionName(&[8])));
+    assert!(hdr.version_exists(VersionName(&[9])));
+    assert!(hdr
+        .prune_by_branch_with(BranchName(b"fake branch"), &PrunePolicy::default())
+        .is_err());
+}
+
+#[test]
+fn version_clock() {
+    let clock = LogicalClock::new(7);
+    assert_eq!(7, clock.now());
+    assert_eq!(8, clock.now());
+
+    // keep the wall-clock time, other tests depend on it
+    static READ_CNT: AtomicUsize = AtomicUsize::new(0);
+    vsdb_set_version_clock(Arc::new(|| {
+        READ_CNT.fetch_add(1, Ordering::Relaxed);
+        SystemClock.now()
+    }));
+
+    let hdr = MapxRawVs::new();
+    let cnt = READ_CNT.load(Ordering::Relaxed);
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    assert!(READ_CNT.load(Ordering::Relaxed) > cnt);
+
+    vsdb_set_version_clock(Arc::new(SystemClock));
+}
+
+#[test]
+fn branch_freeze() {
+    let hdr = MapxRawVs::new();
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(&[0], &[0]).unwrap();
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.insert_by_branch(&[1], &[1], BranchName(b"b-0"))
+        .unwrap();
+
+    assert!(hdr.branch_freeze(BranchName(b"fake branch")).is_err());
+    hdr.branch_freeze(BranchName(b"main")).unwrap();
+    assert!(hdr.branch_is_frozen(BranchName(b"main")));
+    assert!(!hdr.branch_is_frozen(BranchName(b"b-0")));
+
+    // all write paths of the frozen branch are rejected
+    assert!(hdr.insert(&[0], &[9]).is_err());
+    assert!(hdr.remove(&[0]).is_err());
+    assert!(hdr.version_create(VersionName(b"v-2")).is_err());
+    assert!(hdr.version_pop().is_err());
+    assert!(hdr.branch_truncate(BranchName(b"main")).is_err());
+    assert!(hdr.branch_merge_to_parent(BranchName(b"b-0")).is_err());
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[0]);
+    assert!(hdr.version_exists(VersionName(b"v-0")));
+
+    // other branches are not affected, even the children
+    hdr.insert_by_branch(&[0], &[1], BranchName(b"b-0"))
+        .unwrap();
+    hdr.branch_create(BranchName(b"b-1")).unwrap();
+
+    hdr.branch_freeze(BranchName(b"b-0")).unwrap();
+    assert!(hdr.branch_remove(BranchName(b"b-0")).is_err());
+    assert!(hdr.branch_exists(BranchName(b"b-0")));
+
+    hdr.branch_unfreeze(BranchName(b"main")).unwrap();
+    assert!(!hdr.branch_is_frozen(BranchName(b"main")));
+    hdr.insert(&[0], &[2]).unwrap();
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[2]);
+
+    hdr.branch_unfreeze(BranchName(b"b-0")).unwrap();
+    hdr.branch_merge_to_parent(BranchName(b"b-0")).unwrap();
+    assert_eq!(&hdr.get(&[1]).unwrap()[..], &[1]);
+}
+
+#[test]
+fn branch_export_shallow() {
+    let hdr = MapxRawVs::new();
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    (0..10u8).for_each(|i| {
+        hdr.insert(&[i], &[i]).unwrap();
+    });
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(&[0], &[9]).unwrap();
+    hdr.remove(&[1]).unwrap();
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.insert_by_branch(&[2], &[0], BranchName(b"b-0"))
+        .unwrap();
+
+    let dir = format!("{}/shallow_export", vsdb_get_custom_dir());
+    let _ = fs::remove_dir_all(&dir);
+    assert!(hdr
+        .branch_export_shallow(BranchName(b"fake branch"), &dir)
+        .is_err());
+    hdr.branch_export_shallow(BranchName(b"b-0"), &dir).unwrap();
+
+    // existing data will never be overwritten
+    assert!(hdr
+        .branch_export_shallow(BranchName(b"main"), &dir)
+        .is_err());
+
+    let copy = MapxRawVs::import_shallow(&dir).unwrap();
+    assert_eq!(9, copy.len());
+    assert!(copy.iter().eq(hdr.iter_by_branch(BranchName(b"b-0"))));
+
+    // no history is kept
+    assert_eq!(1, copy.version_list().unwrap().len());
+    assert!(!copy.version_exists(VersionName(b"v-0")));
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(MapxRawVs::import_shallow(&dir).is_err());
+}
+
+#[test]
+fn key_blame() {
+    let hdr = MapxRawVs::new();
+    let meta = VersionMeta {
+        author: "alice".to_owned(),
+        ..Default::default()
+    };
+    hdr.version_create_with_meta(VersionName(b"v-0"), &meta)
+        .unwrap();
+    hdr.insert(&[0], &[0]).unwrap();
+    hdr.insert(&[1], &[1]).unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(&[1], &[2]).unwrap();
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-2"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.remove_by_branch(&[0], BranchName(b"b-0")).unwrap();
+
+    let blame = hdr.key_blame(&[0], BranchName(b"main")).unwrap();
+    assert_eq!(blame.branch, b"main".to_vec());
+    assert_eq!(blame.version.name, b"v-0".to_vec());
+    assert_eq!(blame.version.change_count, 2);
+    assert_eq!(blame.meta, Some(meta));
+    assert!(!blame.removed);
+
+    let blame = hdr.key_blame(&[0], BranchName(b"b-0")).unwrap();
+    assert_eq!(blame.branch, b"b-0".to_vec());
+    assert_eq!(blame.version.name, b"v-2".to_vec());
+    assert!(blame.removed);
+
+    // versions of the parent branch are found through the path
+    let blame = hdr.key_blame(&[1], BranchName(b"b-0")).unwrap();
+    assert_eq!(blame.branch, b"main".to_vec());
+    assert_eq!(blame.version.name, b"v-1".to_vec());
+    assert!(blame.meta.is_none());
+
+    // names are followed after renaming and merging
+    hdr.branch_rename(BranchName(b"b-0"), BranchName(b"b-1"))
+        .unwrap();
+    let blame = hdr.key_blame(&[0], BranchName(b"b-1")).unwrap();
+    assert_eq!(blame.branch, b"b-1".to_vec());
+    assert_eq!(blame.version.name, b"v-2".to_vec());
+    hdr.branch_merge_to_parent(BranchName(b"b-1")).unwrap();
+    let blame = hdr.key_blame(&[0], BranchName(b"main")).unwrap();
+    assert_eq!(blame.branch, b"main".to_vec());
+    assert_eq!(blame.version.name, b"v-2".to_vec());
+    assert!(blame.removed);
+
+    assert!(hdr.key_blame(&[9], BranchName(b"main")).is_none());
+    assert!(hdr.key_blame(&[0], BranchName(b"fake branch")).is_none());
+}
+
+#[test]
+fn key_history() {
+    let hdr = MapxRawVs::new();
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(&[0], &[0]).unwrap();
+    hdr.version_create(VersionName(b"v-1")).unwrap();
+    hdr.insert(&[1], &[1]).unwrap();
+    hdr.version_create(VersionName(b"v-2")).unwrap();
+    hdr.insert(&[0], &[2]).unwrap();
+    hdr.branch_create_by_base_branch_version(
+        BranchName(b"b-0"),
+        ParentBranchName(b"main"),
+        VersionName(b"v-1"),
+    )
+    .unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-3"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.remove_by_branch(&[0], BranchName(b"b-0")).unwrap();
+
+    let history = |br: &[u8]| {
+        hdr.key_history(&[0], BranchName(br))
+            .into_iter()
+            .map(|(ver, v)| (ver, v.map(|v| v.to_vec())))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        history(b"main"),
+        vec![
+            (b"v-0".to_vec(), Some(vec![0])),
+            (b"v-2".to_vec(), Some(vec![2]))
+        ]
+    );
+
+    // changes of the parent after the fork point are invisible
+    assert_eq!(
+        history(b"b-0"),
+        vec![(b"v-0".to_vec(), Some(vec![0])), (b"v-3".to_vec(), None)]
+    );
+
+    assert!(history(b"fake branch").is_empty());
+    assert!(hdr.key_history(&[9], BranchName(b"main")).is_empty());
+}
+
+#[test]
+fn version_rollback_to() {
+    let hdr = MapxRawVs::new();
+    (0..5u8).for_each(|i| {
+        hdr.version_create(VersionName(&[i])).unwrap();
+        hdr.insert(&[0], &[i]).unwrap();
+    });
+    hdr.branch_create_by_base_branch_version(
+        BranchName(b"b-0"),
+        ParentBranchName(b"main"),
+        VersionName(&[3]),
+    )
+    .unwrap();
+
+    // versions used by other branches can not be removed
+    assert!(hdr
+        .version_rollback_to(BranchName(b"main"), VersionName(&[1]))
+        .is_err());
+    assert!(hdr.version_exists(VersionName(&[4])));
+
+    hdr.version_rollback_to(BranchName(b"main"), VersionName(&[3]))
+        .unwrap();
+    assert!(!hdr.version_exists(VersionName(&[4])));
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[3]);
+
+    // versions of the parent branch can not be used
+    assert!(hdr
+        .version_rollback_to(BranchName(b"b-0"), VersionName(&[2]))
+        .is_err());
+    assert!(hdr
+        .version_rollback_to(BranchName(b"main"), VersionName(b"fake version"))
+        .is_err());
+
+    hdr.branch_remove(BranchName(b"b-0")).unwrap();
+    hdr.version_rollback_to(BranchName(b"main"), VersionName(&[0]))
+        .unwrap();
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[0]);
+    assert!(!hdr.version_exists(VersionName(&[1])));
+}
+
+#[test]
+fn gc() {
+    let hdr = MapxRawVs::new();
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(&[0], &[0]).unwrap();
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    (1..5u8).for_each(|i| {
+        hdr.version_create_by_branch(VersionName(&[i]), BranchName(b"b-0"))
+            .unwrap();
+        hdr.insert_by_branch(&[1], &[i], BranchName(b"b-0"))
+            .unwrap();
+    });
+    hdr.version_tag(BranchName(b"b-0"), VersionName(&[4]), VersionName(b"t-4"))
+        .unwrap();
+
+    // nothing to collect
+    assert_eq!(0, hdr.inner.gc());
+
+    // the newest value before the guard version is kept by pruning,
+    // and it is left behind after the branch has been removed
+    hdr.prune_by_branch(BranchName(b"b-0"), Some(1)).unwrap();
+    hdr.branch_remove(BranchName(b"b-0")).unwrap();
+
+    // the left value and the tag of the removed version
+    assert_eq!(2, hdr.inner.gc());
+    assert_eq!(0, hdr.inner.gc());
+
+    hdr.gc().unwrap();
+    assert_eq!(&hdr.get(&[0]).unwrap()[..], &[0]);
+    assert!(hdr.get(&[1]).is_none());
+}
+
+#[test]
+fn branch_depth() {
+    let hdr = MapxRawVs::new();
+    assert_eq!(Some(0), hdr.branch_depth(BranchName(b"main")));
+    assert!(hdr.branch_depth(BranchName(b"fake branch")).is_none());
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-0"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.branch_create_by_base_branch(BranchName(b"b-1"), ParentBranchName(b"b-0"))
+        .unwrap();
+    assert_eq!(Some(1), hdr.branch_depth(BranchName(b"b-0")));
+    assert_eq!(Some(2), hdr.branch_depth(BranchName(b"b-1")));
+
+    // can only be changed before initialization
+    assert_eq!(BRANCH_ANCESTORS_LIMIT, vsdb_get_branch_ancestors_limit());
+    assert!(vsdb_set_branch_ancestors_limit(0).is_err());
+    assert!(vsdb_set_branch_ancestors_limit(1024).is_err());
+    assert_eq!(BRANCH_ANCESTORS_LIMIT, vsdb_get_branch_ancestors_limit());
+}
+
+#[test]
+fn branch_merge_3way() {
+    let hdr = MapxRawVs::new();
+
+    hdr.version_create(VersionName(b"base")).unwrap();
+    hdr.insert(b"k-0", b"base").unwrap();
+    hdr.insert(b"k-1", b"base").unwrap();
+    hdr.insert(b"k-2", b"base").unwrap();
+    hdr.insert(b"k-3", b"base").unwrap();
+
+    // two sibling branches, neither is the parent of the other
+    hdr.branch_create(BranchName(b"ours")).unwrap();
+    hdr.branch_create(BranchName(b"theirs")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-o"), BranchName(b"ours"))
+        .unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-t"), BranchName(b"theirs"))
+        .unwrap();
+
+    hdr.insert_by_branch(b"k-0", b"ours", BranchName(b"ours"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-1", b"ours", BranchName(b"ours"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-1", b"theirs", BranchName(b"theirs"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-2", b"same", BranchName(b"ours"))
+        .unwrap();
+    hdr.insert_by_branch(b"k-2", b"same", BranchName(b"theirs"))
+        .unwrap();
+    hdr.remove_by_branch(b"k-3", BranchName(b"theirs")).unwrap();
+    hdr.insert_by_branch(b"k-4", b"theirs", BranchName(b"theirs"))
+        .unwrap();
+
+    let conflicts = pnk!(hdr.branch_merge_3way(
+        BranchName(b"ours"),
+        BranchName(b"theirs"),
+        VersionName(b"base")
+    ));
+    assert_eq!(
+        conflicts,
+        vec![MergeConflict {
+            key: b"k-1".to_vec().into_boxed_slice(),
+            ours: Some(b"ours".to_vec().into_boxed_slice()),
+            theirs: Some(b"theirs".to_vec().into_boxed_slice()),
+        }]
+    );
+
+    let ours = BranchName(b"ours");
+    assert_eq!(&hdr.get_by_branch(b"k-0", ours).unwrap()[..], b"ours");
+    assert_eq!(&hdr.get_by_branch(b"k-1", ours).unwrap()[..], b"ours");
+    assert_eq!(&hdr.get_by_branch(b"k-2", ours).unwrap()[..], b"same");
+    assert!(hdr.get_by_branch(b"k-3", ours).is_none());
+    assert_eq!(&hdr.get_by_branch(b"k-4", ours).unwrap()[..], b"theirs");
+
+    // the other side is untouched
+    assert_eq!(
+        &hdr.get_by_branch(b"k-0", BranchName(b"theirs")).unwrap()[..],
+        b"base"
+    );
+
+    // the base version must be visible on both sides
+    let theirs = BranchName(b"theirs");
+    let r = hdr.branch_merge_3way(ours, theirs, VersionName(b"v-o"));
+    assert!(r.is_err());
+    let r = hdr.branch_merge_3way(ours, ours, VersionName(b"base"));
+    assert!(r.is_err());
+}
+
+#[test]
+fn patch_export_apply() {
+    let src = MapxRawVs::new();
+    src.version_create(VersionName(b"v-0")).unwrap();
+    src.insert(b"k-0", b"v-0").unwrap();
+    src.version_create(VersionName(b"v-1")).unwrap();
+    src.insert(b"k-1", b"v-1").unwrap();
+    src.remove(b"k-0").unwrap();
+    src.version_create(VersionName(b"v-2")).unwrap();
+    src.insert(b"k-2", b"v-2").unwrap();
+
+    let main = BranchName(b"main");
+    let patch = pnk!(src.export_patch(main, VersionName(b"v-0"), VersionName(b"v-2")));
+
+    let dst = MapxRawVs::new();
+    dst.version_create(VersionName(b"v-0")).unwrap();
+    dst.insert(b"k-0", b"v-0").unwrap();
+    pnk!(dst.apply_patch(main, &patch));
+
+    assert!(dst.version_exists(VersionName(b"v-1")));
+    assert!(dst.version_exists(VersionName(b"v-2")));
+    assert!(dst.get(b"k-0").is_none());
+    assert_eq!(&dst.get(b"k-1").unwrap()[..], b"v-1");
+    assert_eq!(&dst.get(b"k-2").unwrap()[..], b"v-2");
+
+    // the versions already exist, nothing will be applied
+    let ver_cnt = pnk!(dst.version_list_by_branch(main)).len();
+    assert!(dst.apply_patch(main, &patch).is_err());
+    assert_eq!(ver_cnt, pnk!(dst.version_list_by_branch(main)).len());
+
+    // broken patches are rejected
+    assert!(dst.apply_patch(main, &patch[..patch.len() - 1]).is_err());
+    assert!(dst.apply_patch(main, b"fake patch").is_err());
+}
+
+#[test]
+fn version_checksum() {
+    let main = BranchName(b"main");
+
+    let a = MapxRawVs::new();
+    a.version_create(VersionName(b"v-0")).unwrap();
+    a.insert(b"k-0", b"v").unwrap();
+    a.insert(b"k-1", b"v").unwrap();
+    a.version_create(VersionName(b"v-1")).unwrap();
+    a.remove(b"k-0").unwrap();
+
+    // the same state through different paths
+    let b = MapxRawVs::new();
+    b.version_create(VersionName(b"v-0")).unwrap();
+    b.insert(b"k-1", b"x").unwrap();
+    b.insert(b"k-0", b"v").unwrap();
+    b.insert(b"k-1", b"v").unwrap();
+    b.version_create(VersionName(b"v-1")).unwrap();
+    b.remove(b"k-0").unwrap();
+
+    let sum =
+        |hdr: &MapxRawVs, v: &[u8]| pnk!(hdr.version_checksum(main, VersionName(v)));
+    assert_eq!(sum(&a, b"v-0"), sum(&b, b"v-0"));
+    assert_eq!(sum(&a, b"v-1"), sum(&b, b"v-1"));
+    assert_ne!(sum(&a, b"v-0"), sum(&a, b"v-1"));
+
+    // boundaries of KVs are not ambiguous
+    b.remove(b"k-1").unwrap();
+    b.insert(b"k-1v", b"").unwrap();
+    assert_ne!(sum(&a, b"v-1"), sum(&b, b"v-1"));
+
+    assert!(a.version_checksum(main, VersionName(b"fake")).is_err());
+}
+
+#[test]
+fn branch_create_is_copy_on_write() {
+    let hdr = MapxRawVs::new();
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    (0..1000_u32).for_each(|i| {
+        hdr.insert(&i.to_be_bytes(), &i.to_be_bytes()).unwrap();
+    });
+
+    hdr.branch_create(BranchName(b"b-0")).unwrap();
+    let br = pnk!(hdr.inner.get_branch_id(BranchName(b"b-0")));
+
+    let layered_cnt = || {
+        hdr.inner
+            .layered_kv
+            .iter()
+            .filter(|(_, brs)| brs.contains_key(&br))
+            .count()
+    };
+
+    // nothing is copied to the new branch
+    assert_eq!(0, layered_cnt());
+    assert_eq!(1000, hdr.len_by_branch(BranchName(b"b-0")));
+
+    // only the written key gets its own layer
+    hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"b-0"))
+        .unwrap();
+    hdr.insert_by_branch(&0_u32.to_be_bytes(), b"new", BranchName(b"b-0"))
+        .unwrap();
+    assert_eq!(1, layered_cnt());
+    assert_eq!(
+        &hdr.get(&0_u32.to_be_bytes()).unwrap()[..],
+        &0_u32.to_be_bytes()
+    );
+}
+
+#[test]
+fn branch_swap() {
+    let hdr = MapxRawVs::new();
+    hdr.version_create(VersionName(b"v-0")).unwrap();
+    hdr.insert(b"k", b"old").unwrap();
+
+    hdr.branch_create(BranchName(b"feature")).unwrap();
+    hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"feature"))
+        .unwrap();
+    hdr.insert_by_branch(b"k", b"new", BranchName(b"feature"))
+        .unwrap();
+
+    pnk!(hdr.branch_swap(BranchName(b"main"), BranchName(b"feature")));
+
+    let main = BranchName(b"main");
+    let feature = BranchName(b"feature");
+    assert_eq!(&hdr.get_by_branch(b"k", main).unwrap()[..], b"new");
+    assert_eq!(&hdr.get_by_branch(b"k", feature).unwrap()[..], b"old");
+
+    // versions stay with their branches
+    assert!(hdr.version_created_on_branch(VersionName(b"v-1"), main));
+    assert!(hdr.version_created_on_branch(VersionName(b"v-0"), feature));
+
+    assert!(hdr.branch_swap(main, main).is_err());
+    assert!(hdr.branch_swap(main, BranchName(b"fake branch")).is_err());
+
+    // the initial branch name is always bound
+    assert!(hdr.branch_rename(main, BranchName(b"other")).is_err());
+    pnk!(hdr.branch_swap(feature, main));
+    assert_eq!(&hdr.get_by_branch(b"k", main).unwrap()[..], b"old");
+    assert!(hdr.version_exists(VersionName(b"v-0")));
+}
+
+#[test]
+fn durability_and_recovery() {
+    // the global config is left untouched, other tests may be using it
+    assert!(vsdb_set_durability(Durability::Interval(Duration::ZERO)).is_err());
+
+    let db = pnk!(Vsdb::open(format!(
+        "{}/databases/recovery",
+        vsdb_get_custom_dir()
+    )));
+    db.scope(|| {
+        let hdr = MapxRawVs::new();
+        pnk!(hdr.version_create(VersionName(b"v-0")));
+        hdr.insert(b"k", b"v").unwrap();
+
+        let journal = MapxRaw::open_reserved(COMMIT_JOURNAL_ID);
+        let mut records = vec![];
+        pnk!(commit_atomically(|| {
+            hdr.version_create(VersionName(b"v-1")).c(d!())?;
+            hdr.insert(b"k", b"v1").c(d!())?;
+            records = journal.iter().collect::<Vec<_>>();
+            Ok(())
+        }));
+        assert_eq!(1, records.len());
+        assert!(journal.is_empty());
+        assert!(hdr.version_exists(VersionName(b"v-1")));
+
+        // as if the process crashed before the commit finished
+        records.iter().for_each(|(k, v)| {
+            journal.insert(k, v);
+        });
+        assert_eq!(1, pnk!(vsdb_recover()));
+        assert!(journal.is_empty());
+        assert!(!hdr.version_exists(VersionName(b"v-1")));
+        assert!(hdr.version_exists(VersionName(b"v-0")));
+        assert_eq!(&hdr.get(b"k").unwrap()[..], b"v");
+        assert_eq!(0, pnk!(vsdb_recover()));
+    });
+}
+
+#[test]
+fn versioned_outside_scope() {
+    let db = pnk!(Vsdb::open(format!(
+        "{}/databases/outside_scope",
+        vsdb_get_custom_dir()
+    )));
+    let hdr = db.scope(MapxRawVs::new);
+    let journal = db.scope(|| MapxRaw::open_reserved(COMMIT_JOURNAL_ID));
+
+    // IDs are allocated, and nested instances are created, in `db`
+    pnk!(hdr.version_create(VersionName(b"v-0")));
+    hdr.insert(b"k", b"v").unwrap();
+    pnk!(hdr.branch_create(BranchName(b"b-0")));
+    pnk!(hdr.version_create_by_branch(VersionName(b"v-1"), BranchName(b"b-0")));
+    hdr.insert_by_branch(b"k", b"v1", BranchName(b"b-0"))
+        .unwrap();
+
+    let mut journaled = 0;
+    pnk!(commit_atomically(|| {
+        hdr.version_create(VersionName(b"v-2")).c(d!())?;
+        journaled = journal.len();
+        Ok(())
+    }));
+    assert_eq!(1, journaled);
+    assert!(journal.is_empty());
+
+    // the same state is seen from both sides of the scope
+    let check = |hdr: &MapxRawVs| {
+        assert_eq!(&hdr.get(b"k").unwrap()[..], b"v");
+        assert_eq!(
+            &hdr.get_by_branch(b"k", BranchName(b"b-0")).unwrap()[..],
+            b"v1"
+        );
+        assert!(hdr.version_exists(VersionName(b"v-2")));
+    };
+    check(&hdr);
+    db.scope(|| check(&hdr));
+
+    let decoded = db.scope(|| pnk!(<MapxRawVs as ValueEnDe>::decode(&hdr.encode())));
+    check(&decoded);
+}
+
 // version:
 //
 // - can not write data before creating a version for the branch
@@ -661,3 +2524,356 @@ fn default_branch(hdr: &mut MapxRawVs) {
         );
     }
 }
+
+#[test]
+fn open_at() {
+    let hdr = pnk!(MapxRawVs::open_at("open_at/raw_vs"));
+    pnk!(hdr.version_create(VersionName(b"v-0")));
+    hdr.insert(b"k", b"v").unwrap();
+
+    let reopened = pnk!(MapxRawVs::open_at("open_at/raw_vs"));
+    assert_eq!(hdr, reopened);
+    assert_eq!(&reopened.get(b"k").unwrap()[..], b"v");
+    assert!(reopened.version_exists(VersionName(b"v-0")));
+}
+
+#[test]
+fn raw_values() {
+    let src = crate::Mapx::new();
+    let dst: crate::MapxVs<u32, String> = crate::MapxVs::new();
+    dst.version_create(VersionName(b"v-0")).unwrap();
+
+    (0..10u32).for_each(|i| {
+        src.insert(i, format!("v{}", i));
+    });
+
+    // pass-through replication, nothing is decoded
+    (0..10u32).for_each(|i| {
+        let v = src.get_raw(&i).unwrap();
+        assert_eq!(v, format!("v{}", i).encode());
+        assert!(dst.insert_raw(&i, &v).unwrap().is_none());
+    });
+    assert_eq!(Some("v5".to_owned()), dst.get(&5));
+    assert_eq!(src.get_raw(&5), dst.get_raw(&5));
+
+    let old = dst.insert_raw(&5, &"x".to_owned().encode()).unwrap();
+    assert_eq!(Some("v5".to_owned().encode()), old);
+    assert_eq!(Some("x".to_owned()), dst.get(&5));
+    assert!(src.insert_raw(&5, &dst.get_raw(&5).unwrap()).is_some());
+    assert_eq!(Some("x".to_owned()), src.get(&5));
+
+    assert!(src.get_raw(&100).is_none());
+    assert!(dst.get_raw(&100).is_none());
+}
+
+#[test]
+fn merkle_roots() {
+    // the root of the same entries, built from scratch
+    let rebuilt = |hdr: &MapxRawVs, branch_name: BranchName| {
+        let ret = MapxRawVs::new_with_roots();
+        pnk!(ret.version_create(VersionName(b"v")));
+        hdr.iter_by_branch(branch_name).for_each(|(k, v)| {
+            pnk!(ret.insert(&k, &v));
+        });
+        pnk!(ret.root())
+    };
+
+    assert!(MapxRawVs::new().root().is_err());
+
+    let hdr = MapxRawVs::new_with_roots();
+    assert!(hdr.roots_enabled());
+    pnk!(hdr.version_create(VersionName(b"v0")));
+    let empty = pnk!(hdr.root());
+
+    (0..50u32).for_each(|i| {
+        pnk!(hdr.insert(&i.to_be_bytes(), &i.to_le_bytes()));
+    });
+    let r0 = pnk!(hdr.root());
+    assert_ne!(empty, r0);
+
+    // independent of the writing order
+    let other = MapxRawVs::new_with_roots();
+    pnk!(other.version_create(VersionName(b"v0")));
+    (0..50u32).rev().for_each(|i| {
+        pnk!(other.insert(&i.to_be_bytes(), &i.to_le_bytes()));
+    });
+    assert_eq!(r0, pnk!(other.root()));
+
+    pnk!(hdr.remove(&0u32.to_be_bytes()));
+    assert_ne!(r0, pnk!(hdr.root()));
+    pnk!(hdr.insert(&0u32.to_be_bytes(), &0u32.to_le_bytes()));
+    assert_eq!(r0, pnk!(hdr.root()));
+
+    // a new version starts from the root of the previous one
+    pnk!(hdr.version_create(VersionName(b"v1")));
+    assert_eq!(r0, pnk!(hdr.root()));
+
+    // a branch without its own versions shares the root of its base,
+    // and sees the later writes on it
+    pnk!(hdr.branch_create(BranchName(b"b")));
+    assert_eq!(r0, pnk!(hdr.root_by_branch(BranchName(b"b"))));
+    pnk!(hdr.insert(b"x", b"x"));
+    let r1 = pnk!(hdr.root());
+    assert_eq!(r1, pnk!(hdr.root_by_branch(BranchName(b"b"))));
+
+    pnk!(hdr.version_create_by_branch(VersionName(b"b1"), BranchName(b"b")));
+    pnk!(hdr.insert_by_branch(b"y", b"y", BranchName(b"b")));
+    pnk!(hdr.remove_by_branch(&1u32.to_be_bytes(), BranchName(b"b")));
+    assert_eq!(r1, pnk!(hdr.root()));
+    assert_eq!(
+        rebuilt(&hdr, BranchName(b"b")),
+        pnk!(hdr.root_by_branch(BranchName(b"b")))
+    );
+
+    // the history of the parent is rewritten by merging
+    pnk!(hdr.version_create(VersionName(b"v2")));
+    pnk!(hdr.insert(b"y", b"yy"));
+    pnk!(hdr.branch_merge_to_parent(BranchName(b"b")));
+    assert_eq!(
+        rebuilt(&hdr, BranchName(INITIAL_BRANCH_NAME)),
+        pnk!(hdr.root())
+    );
+
+    // older versions are gone after pruning
+    (0..20u32).for_each(|i| {
+        pnk!(hdr.version_create(VersionName(&i.to_be_bytes())));
+        pnk!(hdr.insert(&i.to_be_bytes(), b"p"));
+    });
+    let r2 = pnk!(hdr.root());
+    pnk!(hdr.prune(Some(2)));
+    assert_eq!(r2, pnk!(hdr.root()));
+    pnk!(hdr.version_pop());
+    assert_eq!(
+        rebuilt(&hdr, BranchName(INITIAL_BRANCH_NAME)),
+        pnk!(hdr.root())
+    );
+
+    // nodes of the pruned versions and the outdated roots are reclaimed
+    let r3 = pnk!(hdr.root());
+    assert!(0 < hdr.inner.gc());
+    assert_eq!(0, hdr.inner.gc());
+    assert_eq!(r3, pnk!(hdr.root()));
+    let k = 0u32.to_be_bytes();
+    assert!(pnk!(hdr.prove(&k)).verify_inclusion(&r3, &k, b"p"));
+}
+
+#[test]
+fn historical_roots() {
+    let hdr = MapxRawVs::new_with_roots();
+    assert!(hdr
+        .root_by_branch_version(BranchName(INITIAL_BRANCH_NAME), VersionName(b"v0"))
+        .is_err());
+
+    let mut roots = vec![];
+    (0..10u32).for_each(|i| {
+        pnk!(hdr.version_create(VersionName(&i.to_be_bytes())));
+        (0..=i).for_each(|j| {
+            pnk!(hdr.insert(&j.to_be_bytes(), &i.to_be_bytes()));
+        });
+        roots.push(pnk!(hdr.root()));
+    });
+
+    let check = |hdr: &MapxRawVs, branch_name: BranchName| {
+        roots.iter().enumerate().for_each(|(i, r)| {
+            let ver = (i as u32).to_be_bytes();
+            assert_eq!(
+                *r,
+                pnk!(hdr.root_by_branch_version(branch_name, VersionName(&ver)))
+            );
+        });
+    };
+    check(&hdr, BranchName(INITIAL_BRANCH_NAME));
+
+    // the same roots are seen by the children
+    pnk!(hdr.branch_create(BranchName(b"b")));
+    pnk!(hdr.version_create_by_branch(VersionName(b"b0"), BranchName(b"b")));
+    pnk!(hdr.insert_by_branch(b"x", b"x", BranchName(b"b")));
+    check(&hdr, BranchName(b"b"));
+    assert!(hdr
+        .root_by_branch_version(BranchName(INITIAL_BRANCH_NAME), VersionName(b"b0"))
+        .is_err());
+
+    // versions before the merged ones are not affected
+    let head = pnk!(hdr.root_by_branch(BranchName(b"b")));
+    pnk!(hdr.branch_merge_to_parent(BranchName(b"b")));
+    check(&hdr, BranchName(INITIAL_BRANCH_NAME));
+    assert_eq!(head, pnk!(hdr.root()));
+    assert_eq!(
+        head,
+        pnk!(hdr.root_by_branch_version(
+            BranchName(INITIAL_BRANCH_NAME),
+            VersionName(b"b0")
+        ))
+    );
+}
+
+#[test]
+fn merkle_proofs() {
+    let hdr = MapxRawVs::new();
+    assert!(hdr.prove(b"a").is_err());
+
+    let hdr = MapxRawVs::new_with_roots();
+    pnk!(hdr.version_create(VersionName(b"v0")));
+    pnk!(hdr.insert(b"a", b"a"));
+    pnk!(hdr.insert(b"b", b"b"));
+    let root = pnk!(hdr.root());
+
+    pnk!(hdr.version_create(VersionName(b"v1")));
+    pnk!(hdr.insert(b"a", b"aa"));
+    pnk!(hdr.remove(b"b"));
+    let new_root = pnk!(hdr.root());
+
+    let p = pnk!(hdr.prove(b"a"));
+    assert!(p.verify_inclusion(&new_root, b"a", b"aa"));
+    assert!(!p.verify_inclusion(&root, b"a", b"a"));
+    assert!(pnk!(hdr.prove(b"b")).verify_exclusion(&new_root, b"b"));
+
+    // proofs of a historical version
+    let p = pnk!(hdr.prove_by_branch_version(
+        b"b",
+        BranchName(INITIAL_BRANCH_NAME),
+        VersionName(b"v0")
+    ));
+    assert!(p.verify_inclusion(&root, b"b", b"b"));
+    assert!(hdr
+        .prove_by_branch_version(
+            b"b",
+            BranchName(INITIAL_BRANCH_NAME),
+            VersionName(b"v2")
+        )
+        .is_err());
+}
+
+#[test]
+fn state_roots() {
+    assert!(MapxRawVs::new().state_root().is_err());
+    assert_eq!(EMPTY_ROOT, pnk!(Unversioned(1u8).state_root()));
+    assert_eq!(EMPTY_ROOT, pnk!(None::<MapxRawVs>.state_root()));
+
+    let state = (
+        MapxRawVs::new_with_roots(),
+        MapxRawVs::new_with_roots(),
+        0u8,
+    );
+    pnk!(state.version_create(VersionName(b"v0")));
+    let empty = pnk!(state.state_root());
+
+    pnk!(state.0.insert(b"a", b"a"));
+    let r0 = pnk!(state.state_root());
+    assert_ne!(empty, r0);
+    assert_eq!(
+        r0,
+        state_root_of([
+            ("0", pnk!(state.0.root())),
+            ("1", pnk!(state.1.root())),
+            ("2", EMPTY_ROOT),
+        ])
+    );
+
+    // the same change in another field gives another root
+    pnk!(state.0.remove(b"a"));
+    pnk!(state.1.insert(b"a", b"a"));
+    let r1 = pnk!(state.state_root());
+    assert_ne!(empty, r1);
+    assert_ne!(r0, r1);
+
+    // the order of fields does not matter
+    assert_eq!(
+        state_root_of([("a", [1; 32]), ("b", [2; 32])]),
+        state_root_of([("b", [2; 32]), ("a", [1; 32])])
+    );
+    assert_ne!(
+        state_root_of([("a", [1; 32]), ("b", [2; 32])]),
+        state_root_of([("a", [2; 32]), ("b", [1; 32])])
+    );
+}
+
+// handles encoded by the first release, without the fields added later
+#[test]
+fn old_layout() {
+    use crate::{
+        basic::{mapx_ord::MapxOrd, mapx_ord_rawkey::MapxOrdRawKey},
+        common::{
+            database, BranchID, RawValue, VersionID, INITIAL_BRANCH_ID, INITIAL_VERSION,
+        },
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct BasePoint {
+        branch_id: BranchID,
+        version_id: VersionID,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Backend {
+        default_branch: BranchID,
+        branch_name_to_branch_id: MapxOrdRawKey<BranchID>,
+        version_name_to_version_id: MapxOrdRawKey<VersionID>,
+        branch_to_parent: MapxOrd<BranchID, Option<BasePoint>>,
+        branch_to_created_versions: MapxOrd<BranchID, MapxOrd<VersionID, ()>>,
+        version_to_change_set: MapxOrd<VersionID, MapxRaw>,
+        layered_kv:
+            MapxOrdRawKey<MapxOrd<BranchID, MapxOrd<VersionID, Option<RawValue>>>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Old {
+        inner: Backend,
+    }
+
+    // what the first release wrote for a new instance with one key
+    let old = Old {
+        inner: Backend {
+            default_branch: INITIAL_BRANCH_ID,
+            branch_name_to_branch_id: MapxOrdRawKey::new(),
+            version_name_to_version_id: MapxOrdRawKey::new(),
+            branch_to_parent: MapxOrd::new(),
+            branch_to_created_versions: MapxOrd::new(),
+            version_to_change_set: MapxOrd::new(),
+            layered_kv: MapxOrdRawKey::new(),
+        },
+    };
+    let ver = database::current().alloc_version_id();
+    let b = &old.inner;
+    b.branch_name_to_branch_id
+        .insert_ref(b"main", &INITIAL_BRANCH_ID);
+    b.branch_to_parent.insert(INITIAL_BRANCH_ID, None);
+    let vers = MapxOrd::new();
+    vers.insert(ver, ());
+    b.branch_to_created_versions.insert(INITIAL_BRANCH_ID, vers);
+    b.version_name_to_version_id
+        .insert_ref(&INITIAL_BRANCH_ID.to_be_bytes(), &ver);
+    let chgset = MapxRaw::new();
+    chgset.insert(b"k", &[]);
+    b.version_to_change_set.insert(ver, chgset);
+    let vals = MapxOrd::new();
+    vals.insert(ver, Some(b"v".to_vec().into_boxed_slice()));
+    let brs = MapxOrd::new();
+    brs.insert(INITIAL_BRANCH_ID, vals);
+    b.layered_kv.insert_ref(b"k", &brs);
+
+    let bytes = <Old as ValueEnDe>::encode(&old);
+    let hdr = pnk!(<MapxRawVs as ValueEnDe>::decode(&bytes));
+    assert_eq!(&hdr.get(b"k").unwrap()[..], b"v");
+    assert!(hdr.version_exists(INITIAL_VERSION));
+
+    // the fields added later work as usual
+    pnk!(hdr.version_create(VersionName(b"v-1")));
+    pnk!(hdr.insert(b"k", b"v-1"));
+    pnk!(hdr.version_tag(BranchName(b"main"), VersionName(b"v-1"), VersionName(b"rc")));
+    pnk!(hdr.branch_freeze(BranchName(b"main")));
+    assert!(hdr.insert(b"k", b"v-2").is_err());
+
+    // and they are the same ones when decoded again
+    let hdr = pnk!(<MapxRawVs as ValueEnDe>::decode(&bytes));
+    assert!(hdr.version_exists(VersionName(b"rc")));
+    assert!(hdr.insert(b"k", b"v-2").is_err());
+
+    // the layout is unchanged
+    let old = pnk!(<Old as ValueEnDe>::decode(&hdr.encode()));
+    assert_eq!(INITIAL_BRANCH_ID, old.inner.default_branch);
+    assert!(old.inner.layered_kv.get(b"k").is_some());
+    let hdr = MapxRawVs::new();
+    assert!(<Old as ValueEnDe>::decode(&hdr.encode()).is_ok());
+}
@@ -199,35 +199,212 @@
 //! end_block();
 //! ```
 
+pub mod clock;
 pub mod mapx;
 pub mod mapx_ord;
 pub mod mapx_ord_rawkey;
 pub mod mapx_raw;
 pub mod orphan;
+pub mod snapshot;
 pub mod vecx;
 
+pub use snapshot::{InstanceId, SnapshotHandle};
+
 use crate::{
     basic::{
         mapx::Mapx, mapx_ord::MapxOrd, mapx_ord_rawkey::MapxOrdRawKey,
         mapx_ord_rawvalue::MapxOrdRawValue, mapx_raw::MapxRaw, orphan::Orphan,
         vecx::Vecx, vecx_raw::VecxRaw,
     },
-    merkle::{MerkleTree, MerkleTreeStore, Proof, ProofEntry},
-    BranchName, ParentBranchName, VersionName,
+    common::{RawKey, RawValue},
+    merkle::{
+        sparse::{Hash32, EMPTY_ROOT},
+        MerkleTree, MerkleTreeStore, Proof, ProofEntry, ProofPath,
+    },
+    BranchName, ParentBranchName, SavepointName, VersionName,
 };
 use primitive_types::{H128, H160, H256, H512, U128, U256, U512};
 use ruc::*;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
 use std::{
     collections::{
         BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque,
     },
     marker::PhantomData,
-    sync::atomic::{
-        AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicU16, AtomicU32,
-        AtomicU64, AtomicU8,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{
+            AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicU16, AtomicU32,
+            AtomicU64, AtomicU8,
+        },
+        Arc,
     },
+    time::Duration,
 };
 
+/// A difference of one key between two states.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diff<V> {
+    /// Not exist in the old state, but exist in the new one.
+    Added(V),
+    /// Exist in the old state, but not exist in the new one.
+    Removed(V),
+    /// Exist in both states with different values, `(old, new)`.
+    Modified(V, V),
+}
+
+impl<V> Diff<V> {
+    /// Convert the inner value(s) to another type.
+    #[inline(always)]
+    pub fn map<U>(self, f: impl Fn(V) -> U) -> Diff<U> {
+        match self {
+            Diff::Added(v) => Diff::Added(f(v)),
+            Diff::Removed(v) => Diff::Removed(f(v)),
+            Diff::Modified(a, b) => Diff::Modified(f(a), f(b)),
+        }
+    }
+}
+
+impl<V> Diff<Option<V>> {
+    /// `None` if any of the inner values is `None`.
+    #[inline(always)]
+    pub fn transpose(self) -> Option<Diff<V>> {
+        match self {
+            Diff::Added(v) => v.map(Diff::Added),
+            Diff::Removed(v) => v.map(Diff::Removed),
+            Diff::Modified(a, b) => Some(Diff::Modified(a?, b?)),
+        }
+    }
+}
+
+/// A set of versions exported from a branch,
+/// can be used to rebuild these versions in another instance,
+/// even in another database, somewhat like the `git bundle`.
+///
+/// Use `ValueEnDe::encode`/`ValueEnDe::decode` to (de)serialize it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionBundle {
+    /// Versions in their creation order.
+    pub versions: Vec<BundledVersion>,
+}
+
+/// A version within a `VersionBundle`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledVersion {
+    /// The name of this version.
+    pub name: Vec<u8>,
+    /// All changes made within this version,
+    /// a `None` value means that the key has been removed.
+    pub changes: Vec<(RawKey, Option<RawValue>)>,
+}
+
+/// Information of a branch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchInfo {
+    /// The name of this branch.
+    pub name: Vec<u8>,
+    /// The branch where this branch is forked from,
+    /// `None` for the initial branch.
+    pub parent: Option<Vec<u8>>,
+    /// The version where this branch is forked from,
+    /// `None` for the initial branch,
+    /// or if the base version has been pruned.
+    pub base_version: Option<Vec<u8>>,
+}
+
+/// Which versions should be kept by `prune_with`.
+///
+/// Versions are always pruned from the oldest one,
+/// so a kept version also keeps all the versions newer than it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrunePolicy {
+    /// Keep the newest N versions at least,
+    /// `None` means the default number(10), `0` is invalid.
+    pub keep_last: Option<usize>,
+    /// Keep versions created within this duration,
+    /// it is compared with timestamps of the version clock as seconds.
+    pub keep_newer_than: Option<Duration>,
+    /// Keep tagged versions.
+    pub keep_tagged: bool,
+}
+
+/// Metadata attached to a version, somewhat like the information of a git commit.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionMeta {
+    /// Who created this version.
+    pub author: String,
+    /// A description of the changes within this version.
+    pub message: String,
+    /// A user-defined timestamp,
+    /// it is unrelated to the creation time recorded by VSDB itself.
+    pub timestamp: u64,
+    /// Arbitrary bytes defined by the user.
+    pub extra: Vec<u8>,
+}
+
+/// Information of a version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// The name of this version.
+    pub name: Vec<u8>,
+    /// When this version was created, read from the version clock,
+    /// it is seconds since the UNIX epoch by default.
+    pub created_at: u64,
+    /// How many keys have been changed within this version.
+    pub change_count: usize,
+}
+
+/// The version that most recently wrote or removed a key,
+/// as seen from the head of a branch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyBlame {
+    /// The branch that owns the version now,
+    /// it may be an ancestor of the queried branch.
+    pub branch: Vec<u8>,
+    /// The version itself.
+    pub version: VersionInfo,
+    /// User-defined metadata of the version, if any.
+    pub meta: Option<VersionMeta>,
+    /// The key was removed by the version.
+    pub removed: bool,
+}
+
+/// A key that has been changed by both sides of a merge since their fork point,
+/// and ends up with different values on them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflict<K, V> {
+    /// The conflicting key.
+    pub key: K,
+    /// The value on the parent branch, `None` means not existing.
+    pub ours: Option<V>,
+    /// The value on the branch to be merged, `None` means not existing.
+    pub theirs: Option<V>,
+}
+
+/// How to resolve a merge conflict.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeResolution<V> {
+    /// Keep the value of the parent branch.
+    Ours,
+    /// Keep the value of the branch to be merged.
+    Theirs,
+    /// Use a new value, `None` means removing the key.
+    Value(Option<V>),
+}
+
+impl<V> MergeResolution<V> {
+    /// Convert the inner value to another type.
+    #[inline(always)]
+    pub fn map<U>(self, f: impl FnOnce(V) -> U) -> MergeResolution<U> {
+        match self {
+            MergeResolution::Ours => MergeResolution::Ours,
+            MergeResolution::Theirs => MergeResolution::Theirs,
+            MergeResolution::Value(v) => MergeResolution::Value(v.map(f)),
+        }
+    }
+}
+
 /// Methods collection of version management.
 pub trait VsMgmt {
     /// Create a new version on the default branch.
@@ -279,6 +456,281 @@ pub trait VsMgmt {
     /// and should not do any tracing.
     fn version_pop_by_branch(&self, branch_name: BranchName) -> Result<()>;
 
+    /// Attach a human-friendly tag to a version of a specified branch,
+    /// the tag can be used in place of the version name
+    /// on any branch where the version is visible.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_tag(&self, _: BranchName, _: VersionName, _: VersionName) -> Result<()> {
+        Err(eg!("`version_tag` is not implemented"))
+    }
+
+    /// Remove a tag, the tagged version will not be affected.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_untag(&self, _: VersionName) -> Result<()> {
+        Err(eg!("`version_untag` is not implemented"))
+    }
+
+    /// Collapse consecutive versions(`from` and `to` included) of the default branch
+    /// into one, which keeps the name of `to` and holds the net changes of them all.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_squash(&self, _: VersionName, _: VersionName) -> Result<()> {
+        Err(eg!("`version_squash` is not implemented"))
+    }
+
+    /// Collapse consecutive versions(`from` and `to` included) of a specified branch
+    /// into one, which keeps the name of `to` and holds the net changes of them all.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_squash_by_branch(
+        &self,
+        _: BranchName,
+        _: VersionName,
+        _: VersionName,
+    ) -> Result<()> {
+        Err(eg!("`version_squash_by_branch` is not implemented"))
+    }
+
+    /// Create a new version on the default branch,
+    /// which undoes all changes made by a historical version,
+    /// somewhat like the `git revert`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_revert(&self, _: VersionName, _: VersionName) -> Result<()> {
+        Err(eg!("`version_revert` is not implemented"))
+    }
+
+    /// Create a new version on a specified branch,
+    /// which undoes all changes made by a historical version,
+    /// somewhat like the `git revert`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_revert_by_branch(
+        &self,
+        _: VersionName,
+        _: VersionName,
+        _: BranchName,
+    ) -> Result<()> {
+        Err(eg!("`version_revert_by_branch` is not implemented"))
+    }
+
+    /// Create a new version on the default branch,
+    /// and attach some metadata to it, somewhat like a git commit.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_create_with_meta(&self, _: VersionName, _: &VersionMeta) -> Result<()> {
+        Err(eg!("`version_create_with_meta` is not implemented"))
+    }
+
+    /// Create a new version on a specified branch,
+    /// and attach some metadata to it, somewhat like a git commit.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_create_by_branch_with_meta(
+        &self,
+        _: VersionName,
+        _: BranchName,
+        _: &VersionMeta,
+    ) -> Result<()> {
+        Err(eg!(
+            "`version_create_by_branch_with_meta` is not implemented"
+        ))
+    }
+
+    /// Create a new version on the default branch only if the head of the
+    /// branch is still `expected_head`, that is the last version created on
+    /// it(e.g. got by `version_list`), `None` if no version has been created.
+    ///
+    /// It fails if another writer has created a version on the branch since
+    /// `expected_head` was read, so multiple writers can work on the same
+    /// branch safely, as long as all of them create versions in this way.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_create_cas(&self, _: VersionName, _: Option<VersionName>) -> Result<()> {
+        Err(eg!("`version_create_cas` is not implemented"))
+    }
+
+    /// Create a new version on a specified branch only if the head of the
+    /// branch is still `expected_head`, check `version_create_cas`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_create_by_branch_cas(
+        &self,
+        _: VersionName,
+        _: BranchName,
+        _: Option<VersionName>,
+    ) -> Result<()> {
+        Err(eg!("`version_create_by_branch_cas` is not implemented"))
+    }
+
+    /// Enable or disable the read isolation, it is disabled by default.
+    ///
+    /// If enabled, every newly created version is in building until it is
+    /// finalized(or a newer version is created on the same branch),
+    /// reads on the head of a branch(`get`, `iter`, `range`, etc.) resolve
+    /// against the last finalized version only, so readers never observe
+    /// changes that are still in progress. The writer itself can read its
+    /// own changes by the `*_by_branch_version` methods.
+    ///
+    /// Lengths and read-modify-write paths(`len`, `get_mut`, `entry_ref`,
+    /// `push`/`pop` of vectors, etc.) are always in the view of the writer,
+    /// so they keep working on the version being built.
+    ///
+    /// Disabling it finalizes all versions being built.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn set_read_isolation(&self, _: bool) -> Result<()> {
+        Err(eg!("`set_read_isolation` is not implemented"))
+    }
+
+    /// Make the version being built on the default branch visible to reads,
+    /// check `set_read_isolation`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_finalize(&self) -> Result<()> {
+        Err(eg!("`version_finalize` is not implemented"))
+    }
+
+    /// Make the version being built on a specified branch visible to reads,
+    /// check `set_read_isolation`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_finalize_by_branch(&self, _: BranchName) -> Result<()> {
+        Err(eg!("`version_finalize_by_branch` is not implemented"))
+    }
+
+    /// Take a savepoint in the head version(the one being built)
+    /// of the default branch, changes made after it can be undone by
+    /// `rollback_to_savepoint` without removing the whole version.
+    ///
+    /// A former savepoint of the same name is replaced,
+    /// and all savepoints are dropped once a new version is created.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn savepoint(&self, _: SavepointName) -> Result<()> {
+        Err(eg!("`savepoint` is not implemented"))
+    }
+
+    /// Take a savepoint in the head version of a specified branch,
+    /// check `savepoint`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn savepoint_by_branch(&self, _: SavepointName, _: BranchName) -> Result<()> {
+        Err(eg!("`savepoint_by_branch` is not implemented"))
+    }
+
+    /// Undo all changes made in the head version of the default branch
+    /// since the savepoint, the savepoint itself is kept,
+    /// but those taken after it are dropped.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn rollback_to_savepoint(&self, _: SavepointName) -> Result<()> {
+        Err(eg!("`rollback_to_savepoint` is not implemented"))
+    }
+
+    /// Undo all changes made in the head version of a specified branch
+    /// since the savepoint, check `rollback_to_savepoint`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn rollback_to_savepoint_by_branch(
+        &self,
+        _: SavepointName,
+        _: BranchName,
+    ) -> Result<()> {
+        Err(eg!("`rollback_to_savepoint_by_branch` is not implemented"))
+    }
+
+    /// Create a new version on the default branch atomically,
+    /// if any inner instance fails(or panics) during the creation,
+    /// the new version will be removed from all the others,
+    /// so fields of a `#[derive(Vs)]` struct will never be inconsistent.
+    ///
+    /// NOTE: a panic will be resumed after the rolling back.
+    fn vs_commit(&self, version_name: VersionName) -> Result<()> {
+        mapx_raw::commit_atomically(|| self.version_create(version_name).c(d!()))
+    }
+
+    /// Create a new version on a specified branch atomically,
+    /// check `vs_commit` for details.
+    fn vs_commit_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        mapx_raw::commit_atomically(|| {
+            self.version_create_by_branch(version_name, branch_name)
+                .c(d!())
+        })
+    }
+
+    /// Create a new version on the default branch atomically
+    /// if its head is still `expected_head`, check `version_create_cas`,
+    /// if the check fails on any inner instance, the new version will be
+    /// removed from all the others, like `vs_commit`.
+    fn vs_commit_cas(
+        &self,
+        version_name: VersionName,
+        expected_head: Option<VersionName>,
+    ) -> Result<()> {
+        mapx_raw::commit_atomically(|| {
+            self.version_create_cas(version_name, expected_head).c(d!())
+        })
+    }
+
+    /// Create a new version on a specified branch atomically
+    /// if its head is still `expected_head`, check `vs_commit_cas`.
+    fn vs_commit_by_branch_cas(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+        expected_head: Option<VersionName>,
+    ) -> Result<()> {
+        mapx_raw::commit_atomically(|| {
+            self.version_create_by_branch_cas(version_name, branch_name, expected_head)
+                .c(d!())
+        })
+    }
+
+    /// Get a read-only view at a specified version of a specified branch,
+    /// the version will not be pruned while the view is alive.
+    fn snapshot(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<SnapshotHandle<'_, Self>> {
+        SnapshotHandle::new(self, branch_name, version_name).c(d!())
+    }
+
+    /// The identities of all versioned instances inside,
+    /// snapshots pin their versions on these instances only.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// snapshots of such types pin their versions on all instances,
+    /// types derived by `#[derive(Vs)]` should implement it manually,
+    /// e.g. by `impl_instance_ids!`.
+    fn instance_ids(&self) -> Result<Vec<InstanceId>> {
+        Err(eg!("`instance_ids` is not implemented"))
+    }
+
     /// Create a new branch based on the head of the default branch.
     fn branch_create(&self, branch_name: BranchName) -> Result<()>;
 
@@ -290,6 +742,9 @@ pub trait VsMgmt {
     ) -> Result<()>;
 
     /// Create a new branch based on a specified version of a specified branch.
+    ///
+    /// Branches are copy-on-write, creating them copies no data,
+    /// the cost does not depend on how much data the base branch holds.
     fn branch_create_by_base_branch_version(
         &self,
         branch_name: BranchName,
@@ -300,6 +755,40 @@ pub trait VsMgmt {
     /// Check if a branch exists or not.
     fn branch_exists(&self, branch_name: BranchName) -> bool;
 
+    /// List all existing branches, sorted by their names.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+        Err(eg!("`branch_list` is not implemented"))
+    }
+
+    /// Rename a branch, all versions and data of it will be kept.
+    ///
+    /// The initial branch name("main") must always be bound to a branch,
+    /// so it can not be renamed, check `branch_swap` instead.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn branch_rename(&self, _: BranchName, _: BranchName) -> Result<()> {
+        Err(eg!("`branch_rename` is not implemented"))
+    }
+
+    /// Exchange the names of two branches as a whole,
+    /// so a prepared branch can take the place of another one(e.g. "main"),
+    /// a crash in the middle never leaves the names half exchanged.
+    ///
+    /// Versions and data stay with their branches, only names are exchanged,
+    /// the default branch is bound to the branch itself, not its name.
+    /// Both names are still bound after swapping, so the initial branch name
+    /// can be swapped, unlike renaming.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn branch_swap(&self, _: BranchName, _: BranchName) -> Result<()> {
+        Err(eg!("`branch_swap` is not implemented"))
+    }
+
     /// Remove a branch, remove all changes directly made by this branch.
     ///
     /// 'Write'-like operations on branches and versions are different from operations on data.
@@ -331,6 +820,15 @@ pub trait VsMgmt {
         last_version_name: VersionName,
     ) -> Result<()>;
 
+    /// Roll a branch back to a version created by it, all newer versions are removed
+    /// in one call, versions used as base points of other branches can not be removed.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn version_rollback_to(&self, _: BranchName, _: VersionName) -> Result<()> {
+        Err(eg!("`version_rollback_to` is not implemented"))
+    }
+
     /// Remove the newest version on a specified branch.
     ///
     /// 'Write'-like operations on branches and versions are different from operations on data.
@@ -352,6 +850,23 @@ pub trait VsMgmt {
     /// all default operations will be applied to it.
     fn branch_set_default(&mut self, branch_name: BranchName) -> Result<()>;
 
+    /// Freeze a branch, all write operations on it will fail until it is unfrozen,
+    /// including data writes and creating/removing/merging/rebasing of versions.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn branch_freeze(&self, _: BranchName) -> Result<()> {
+        Err(eg!("`branch_freeze` is not implemented"))
+    }
+
+    /// Make a frozen branch writable again.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn branch_unfreeze(&self, _: BranchName) -> Result<()> {
+        Err(eg!("`branch_unfreeze` is not implemented"))
+    }
+
     /// Clean outdated versions out of the default reserved number.
     fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()>;
 
@@ -361,15 +876,218 @@ pub trait VsMgmt {
         branch_name: BranchName,
         reserved_ver_num: Option<usize>,
     ) -> Result<()>;
+
+    /// Clean outdated versions of the default branch by a retention policy.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn prune_with(&self, _: &PrunePolicy) -> Result<()> {
+        Err(eg!("`prune_with` is not implemented"))
+    }
+
+    /// Clean outdated versions of a specified branch by a retention policy.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn prune_by_branch_with(&self, _: BranchName, _: &PrunePolicy) -> Result<()> {
+        Err(eg!("`prune_by_branch_with` is not implemented"))
+    }
+
+    /// Physically delete the data that can not be reached from any branch,
+    /// such as the values left by removed branches, and the Merkle nodes
+    /// left by pruned versions, this is never done automatically,
+    /// call it manually or periodically after removing branches or pruning.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually.
+    fn gc(&self) -> Result<()> {
+        Err(eg!("`gc` is not implemented"))
+    }
+
+    /// The Merkle root of the head state of the default branch,
+    /// so a whole application state can be committed by one hash.
+    ///
+    /// Roots of the parts of a type are combined by [state_root_of],
+    /// stateless parts contribute an `EMPTY_ROOT`.
+    ///
+    /// NOTE: the default implementation always returns an error,
+    /// types derived by `#[derive(Vs)]` should implement it manually,
+    /// e.g. by `impl_state_root!`.
+    fn state_root(&self) -> Result<Hash32> {
+        Err(eg!("`state_root` is not implemented"))
+    }
+}
+
+/// Combine the Merkle roots of named parts(e.g. fields of a struct) into one.
+///
+/// Parts are sorted by their names before hashing, so the result does not
+/// depend on the order they are listed in, reordering the fields of a struct
+/// keeps its root, while renaming a field changes it.
+///
+/// Parts with the same name are ordered by their roots, that is,
+/// they are committed as a set.
+pub fn state_root_of<'a>(parts: impl IntoIterator<Item = (&'a str, Hash32)>) -> Hash32 {
+    let mut parts = parts.into_iter().collect::<Vec<_>>();
+    if parts.is_empty() {
+        return EMPTY_ROOT;
+    }
+    parts.sort_unstable();
+
+    let mut hasher = Sha3_256::new();
+    for (name, root) in parts {
+        hasher.update((name.len() as u32).to_be_bytes());
+        hasher.update(name.as_bytes());
+        hasher.update(root);
+    }
+    let mut ret = EMPTY_ROOT;
+    ret.copy_from_slice(&hasher.finalize());
+    ret
+}
+
+/// Implement `VsMgmt::state_root` by combining the roots of the listed fields,
+/// fields out of the list are not committed.
+///
+/// Fields are named as they are written, e.g. `impl_state_root!(balances, height)`
+/// or `impl_state_root!(0, 1)` for tuple structs.
+#[macro_export]
+macro_rules! impl_state_root {
+    ($($field: tt),+ $(,)?) => {
+        fn state_root(&self) -> Result<$crate::merkle::sparse::Hash32> {
+            let parts = [
+                $((stringify!($field), self.$field.state_root().c(d!())?)),+
+            ];
+            Ok($crate::versioned::state_root_of(parts))
+        }
+    };
+}
+
+/// Implement `VsMgmt::instance_ids` by collecting the instances of the listed
+/// fields, e.g. `impl_instance_ids!(balances, height)`.
+#[macro_export]
+macro_rules! impl_instance_ids {
+    ($($field: tt),+ $(,)?) => {
+        fn instance_ids(&self) -> Result<Vec<$crate::versioned::InstanceId>> {
+            let mut ids = vec![];
+            $(ids.extend(self.$field.instance_ids().c(d!())?);)+
+            Ok(ids)
+        }
+    };
 }
 
+/// A marker of types whose states are all versioned,
+/// that is, they implement the full `VsMgmt` instead of a nope one.
+///
+/// Check [assert_vs_complete](crate::assert_vs_complete) for its usage.
+pub trait VsComplete: VsMgmt {}
+
+impl<T: VsComplete> VsComplete for Option<T> {}
+
+/// A marker of versioned collections whose elements are versioned too,
+/// e.g. `VecxVs<MapxVs<u8, u8>>`, the versioned calls can NOT reach the
+/// elements, so such fields are rejected by
+/// [assert_vs_complete](crate::assert_vs_complete).
+pub trait VsNested {}
+
+impl<T: VsNested> VsNested for Option<T> {}
+
+// `A` can be inferred only if `T` is not `VsNested`,
+// or the two impls are ambiguous and the compilation fails.
+#[doc(hidden)]
+pub trait NotVsNested<A> {}
+
+impl<T: ?Sized> NotVsNested<()> for T {}
+
+impl<T: ?Sized + VsNested> NotVsNested<u8> for T {}
+
+/// Assert at compile time that every field of a struct is fully versioned,
+/// fields that are stateless on purpose must be listed after `stateless`,
+/// any field missing from the list will fail the compilation.
+///
+/// Versioned collections of versioned elements(`VsNested`) are rejected too,
+/// `#[derive(Vs)]` can not forward the versioned calls to their elements.
+///
+/// The struct itself will be marked as `VsComplete`,
+/// so it can be used as a field of other checked structs.
+///
+/// NOTE: only structs with named fields and without generics are supported.
+///
+/// ```no_run
+/// use ruc::*;
+/// use vsdb::{
+///     assert_vs_complete, BranchName, MapxVs, OrphanVs, ParentBranchName,
+///     VersionName, Vs, VsMgmt,
+/// };
+///
+/// #[derive(Vs)]
+/// struct State {
+///     balances: MapxVs<u64, u64>,
+///     height: OrphanVs<u64>,
+///     cache_size: usize,
+/// }
+///
+/// assert_vs_complete!(State { balances, height } stateless { cache_size });
+///
+/// #[derive(Vs)]
+/// struct World {
+///     state: State,
+///     backup: Option<State>,
+/// }
+///
+/// assert_vs_complete!(World { state, backup });
+/// ```
+///
+/// ```compile_fail
+/// use ruc::*;
+/// use vsdb::{
+///     assert_vs_complete, BranchName, MapxVs, ParentBranchName, VecxVs,
+///     VersionName, Vs, VsMgmt,
+/// };
+///
+/// #[derive(Vs)]
+/// struct BadCase {
+///     a: VecxVs<MapxVs<u8, u8>>,
+/// }
+///
+/// assert_vs_complete!(BadCase { a });
+/// ```
+#[macro_export]
+macro_rules! assert_vs_complete {
+    ($name: ident { $($field: ident),* $(,)? }) => {
+        $crate::assert_vs_complete!($name { $($field),* } stateless {});
+    };
+    ($name: ident { $($field: ident),* $(,)? }
+        stateless { $($stateless: ident),* $(,)? }) => {
+        const _: fn(&$name) = |s| {
+            fn vs_complete<T: $crate::versioned::VsComplete>(_: &T) {}
+            // `NestedVsCollection` can not be inferred for `VsNested` fields
+            fn vs_not_nested<T, NestedVsCollection>(_: &T)
+            where
+                T: $crate::versioned::NotVsNested<NestedVsCollection>,
+            {
+            }
+            let $name { $($field,)* $($stateless,)* } = s;
+            $(vs_complete($field); vs_not_nested($field);)*
+            $(let _ = $stateless;)*
+        };
+
+        impl $crate::versioned::VsComplete for $name {}
+    };
+}
+
+/// Implement `VsMgmt` by forwarding all methods to a field,
+/// the `inner` field is used if no field path is specified.
+///
+/// Nested fields are supported, e.g. `impl_vs_methods!(state.balances)`.
 #[macro_export(super)]
 macro_rules! impl_vs_methods {
     () => {
+        $crate::impl_vs_methods!(inner);
+    };
+    ($($field: ident).+) => {
         /// Create a new version on the default branch.
         #[inline(always)]
         fn version_create(&self, version_name: VersionName) -> Result<()> {
-            self.inner.version_create(version_name).c(d!())
+            self.$($field).+.version_create(version_name).c(d!())
         }
 
         /// Create a new version on a specified branch,
@@ -380,7 +1098,7 @@ macro_rules! impl_vs_methods {
             version_name: VersionName,
             branch_name: BranchName,
         ) -> Result<()> {
-            self.inner
+            self.$($field).+
                 .version_create_by_branch(version_name, branch_name)
                 .c(d!())
         }
@@ -388,7 +1106,7 @@ macro_rules! impl_vs_methods {
         /// Check if a verison exists on default branch.
         #[inline(always)]
         fn version_exists(&self, version_name: VersionName) -> bool {
-            self.inner.version_exists(version_name)
+            self.$($field).+.version_exists(version_name)
         }
 
         /// Check if a version exists on a specified branch(include its parents).
@@ -398,14 +1116,14 @@ macro_rules! impl_vs_methods {
             version_name: VersionName,
             branch_name: BranchName,
         ) -> bool {
-            self.inner
+            self.$($field).+
                 .version_exists_on_branch(version_name, branch_name)
         }
 
         /// Check if a version is directly created on the default branch.
         #[inline(always)]
         fn version_created(&self, version_name: VersionName) -> bool {
-            self.inner.version_created(version_name)
+            self.$($field).+.version_created(version_name)
         }
 
         /// Check if a version is directly created on a specified branch(exclude its parents).
@@ -415,7 +1133,7 @@ macro_rules! impl_vs_methods {
             version_name: VersionName,
             branch_name: BranchName,
         ) -> bool {
-            self.inner
+            self.$($field).+
                 .version_created_on_branch(version_name, branch_name)
         }
 
@@ -428,7 +1146,7 @@ macro_rules! impl_vs_methods {
         /// and should not do any tracing.
         #[inline(always)]
         fn version_pop(&self) -> Result<()> {
-            self.inner.version_pop().c(d!())
+            self.$($field).+.version_pop().c(d!())
         }
 
         /// Remove the newest version on a specified branch.
@@ -440,77 +1158,272 @@ macro_rules! impl_vs_methods {
         /// and should not do any tracing.
         #[inline(always)]
         fn version_pop_by_branch(&self, branch_name: BranchName) -> Result<()> {
-            self.inner.version_pop_by_branch(branch_name).c(d!())
+            self.$($field).+.version_pop_by_branch(branch_name).c(d!())
         }
 
-        /// Create a new branch based on the head of the default branch.
+        /// Attach a human-friendly tag to a version of a specified branch.
         #[inline(always)]
-        fn branch_create(&self, branch_name: BranchName) -> Result<()> {
-            self.inner.branch_create(branch_name).c(d!())
+        fn version_tag(
+            &self,
+            branch_name: BranchName,
+            version_name: VersionName,
+            tag: VersionName,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_tag(branch_name, version_name, tag)
+                .c(d!())
         }
 
-        /// Create a new branch based on the head of a specified branch.
+        /// Remove a tag, the tagged version will not be affected.
         #[inline(always)]
-        fn branch_create_by_base_branch(
+        fn version_untag(&self, tag: VersionName) -> Result<()> {
+            self.$($field).+.version_untag(tag).c(d!())
+        }
+
+        /// Collapse consecutive versions of the default branch into one.
+        #[inline(always)]
+        fn version_squash(&self, from: VersionName, to: VersionName) -> Result<()> {
+            self.$($field).+.version_squash(from, to).c(d!())
+        }
+
+        /// Collapse consecutive versions of a specified branch into one.
+        #[inline(always)]
+        fn version_squash_by_branch(
             &self,
             branch_name: BranchName,
-            base_branch_name: ParentBranchName,
+            from: VersionName,
+            to: VersionName,
         ) -> Result<()> {
-            self.inner
-                .branch_create_by_base_branch(branch_name, base_branch_name)
+            self.$($field).+
+                .version_squash_by_branch(branch_name, from, to)
                 .c(d!())
         }
 
-        /// Create a new branch based on a specified version of a specified branch.
+        /// Undo a historical version of the default branch by a new version.
         #[inline(always)]
-        fn branch_create_by_base_branch_version(
+        fn version_revert(
+            &self,
+            reverted_version_name: VersionName,
+            new_version_name: VersionName,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_revert(reverted_version_name, new_version_name)
+                .c(d!())
+        }
+
+        /// Undo a historical version of a specified branch by a new version.
+        #[inline(always)]
+        fn version_revert_by_branch(
             &self,
+            reverted_version_name: VersionName,
+            new_version_name: VersionName,
             branch_name: BranchName,
-            base_branch_name: ParentBranchName,
-            base_version_name: VersionName,
         ) -> Result<()> {
-            self.inner
-                .branch_create_by_base_branch_version(
+            self.$($field).+
+                .version_revert_by_branch(
+                    reverted_version_name,
+                    new_version_name,
                     branch_name,
-                    base_branch_name,
-                    base_version_name,
                 )
                 .c(d!())
         }
 
-        /// Check if a branch exists or not.
+        /// Create a new version with metadata on the default branch.
         #[inline(always)]
-        fn branch_exists(&self, branch_name: BranchName) -> bool {
-            self.inner.branch_exists(branch_name)
+        fn version_create_with_meta(
+            &self,
+            version_name: VersionName,
+            meta: &VersionMeta,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_create_with_meta(version_name, meta)
+                .c(d!())
         }
 
-        /// Remove a branch, remove all changes directly made by this branch.
-        ///
-        /// 'Write'-like operations on branches and versions are different from operations on data.
-        ///
-        /// 'Write'-like operations on data require recursive tracing of all parent nodes,
-        /// while operations on branches and versions are limited to their own perspective,
-        /// and should not do any tracing.
+        /// Create a new version with metadata on a specified branch.
         #[inline(always)]
-        fn branch_remove(&self, branch_name: BranchName) -> Result<()> {
-            self.inner.branch_remove(branch_name).c(d!())
+        fn version_create_by_branch_with_meta(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+            meta: &VersionMeta,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_create_by_branch_with_meta(version_name, branch_name, meta)
+                .c(d!())
         }
 
-        /// Remove all changes directly made by versions(bigger than `last_version_id`) of this branch.
-        ///
-        /// 'Write'-like operations on branches and versions are different from operations on data.
-        ///
-        /// 'Write'-like operations on data require recursive tracing of all parent nodes,
-        /// while operations on branches and versions are limited to their own perspective,
-        /// and should not do any tracing.
+        /// Create a new version on the default branch
+        /// if its head is still `expected_head`.
         #[inline(always)]
-        fn branch_truncate(&self, branch_name: BranchName) -> Result<()> {
-            self.inner.branch_truncate(branch_name).c(d!())
+        fn version_create_cas(
+            &self,
+            version_name: VersionName,
+            expected_head: Option<VersionName>,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_create_cas(version_name, expected_head)
+                .c(d!())
         }
 
-        /// Remove all changes directly made by versions(bigger than `last_version_id`) of this branch.
-        ///
-        /// 'Write'-like operations on branches and versions are different from operations on data.
+        /// Create a new version on a specified branch
+        /// if its head is still `expected_head`.
+        #[inline(always)]
+        fn version_create_by_branch_cas(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+            expected_head: Option<VersionName>,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_create_by_branch_cas(version_name, branch_name, expected_head)
+                .c(d!())
+        }
+
+        /// Enable or disable the read isolation.
+        #[inline(always)]
+        fn set_read_isolation(&self, enabled: bool) -> Result<()> {
+            self.$($field).+.set_read_isolation(enabled).c(d!())
+        }
+
+        /// Make the version being built on the default branch visible to reads.
+        #[inline(always)]
+        fn version_finalize(&self) -> Result<()> {
+            self.$($field).+.version_finalize().c(d!())
+        }
+
+        /// Make the version being built on a specified branch visible to reads.
+        #[inline(always)]
+        fn version_finalize_by_branch(&self, branch_name: BranchName) -> Result<()> {
+            self.$($field).+.version_finalize_by_branch(branch_name).c(d!())
+        }
+
+        /// Take a savepoint in the head version of the default branch.
+        #[inline(always)]
+        fn savepoint(&self, name: SavepointName) -> Result<()> {
+            self.$($field).+.savepoint(name).c(d!())
+        }
+
+        /// Take a savepoint in the head version of a specified branch.
+        #[inline(always)]
+        fn savepoint_by_branch(
+            &self,
+            name: SavepointName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            self.$($field).+.savepoint_by_branch(name, branch_name).c(d!())
+        }
+
+        /// Undo all changes made in the head version of the default branch
+        /// since the savepoint.
+        #[inline(always)]
+        fn rollback_to_savepoint(&self, name: SavepointName) -> Result<()> {
+            self.$($field).+.rollback_to_savepoint(name).c(d!())
+        }
+
+        /// Undo all changes made in the head version of a specified branch
+        /// since the savepoint.
+        #[inline(always)]
+        fn rollback_to_savepoint_by_branch(
+            &self,
+            name: SavepointName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            self.$($field).+
+                .rollback_to_savepoint_by_branch(name, branch_name)
+                .c(d!())
+        }
+
+        /// Create a new branch based on the head of the default branch.
+        #[inline(always)]
+        fn branch_create(&self, branch_name: BranchName) -> Result<()> {
+            self.$($field).+.branch_create(branch_name).c(d!())
+        }
+
+        /// Create a new branch based on the head of a specified branch.
+        #[inline(always)]
+        fn branch_create_by_base_branch(
+            &self,
+            branch_name: BranchName,
+            base_branch_name: ParentBranchName,
+        ) -> Result<()> {
+            self.$($field).+
+                .branch_create_by_base_branch(branch_name, base_branch_name)
+                .c(d!())
+        }
+
+        /// Create a new branch based on a specified version of a specified branch.
+        #[inline(always)]
+        fn branch_create_by_base_branch_version(
+            &self,
+            branch_name: BranchName,
+            base_branch_name: ParentBranchName,
+            base_version_name: VersionName,
+        ) -> Result<()> {
+            self.$($field).+
+                .branch_create_by_base_branch_version(
+                    branch_name,
+                    base_branch_name,
+                    base_version_name,
+                )
+                .c(d!())
+        }
+
+        /// Check if a branch exists or not.
+        #[inline(always)]
+        fn branch_exists(&self, branch_name: BranchName) -> bool {
+            self.$($field).+.branch_exists(branch_name)
+        }
+
+        /// List all existing branches, sorted by their names.
+        #[inline(always)]
+        fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+            self.$($field).+.branch_list().c(d!())
+        }
+
+        /// Rename a branch, all versions and data of it will be kept.
+        #[inline(always)]
+        fn branch_rename(
+            &self,
+            old_name: BranchName,
+            new_name: BranchName,
+        ) -> Result<()> {
+            self.$($field).+.branch_rename(old_name, new_name).c(d!())
+        }
+
+        /// Exchange the names of two branches as a whole.
+        #[inline(always)]
+        fn branch_swap(&self, branch_a: BranchName, branch_b: BranchName) -> Result<()> {
+            self.$($field).+.branch_swap(branch_a, branch_b).c(d!())
+        }
+
+        /// Remove a branch, remove all changes directly made by this branch.
+        ///
+        /// 'Write'-like operations on branches and versions are different from operations on data.
+        ///
+        /// 'Write'-like operations on data require recursive tracing of all parent nodes,
+        /// while operations on branches and versions are limited to their own perspective,
+        /// and should not do any tracing.
+        #[inline(always)]
+        fn branch_remove(&self, branch_name: BranchName) -> Result<()> {
+            self.$($field).+.branch_remove(branch_name).c(d!())
+        }
+
+        /// Remove all changes directly made by versions(bigger than `last_version_id`) of this branch.
+        ///
+        /// 'Write'-like operations on branches and versions are different from operations on data.
+        ///
+        /// 'Write'-like operations on data require recursive tracing of all parent nodes,
+        /// while operations on branches and versions are limited to their own perspective,
+        /// and should not do any tracing.
+        #[inline(always)]
+        fn branch_truncate(&self, branch_name: BranchName) -> Result<()> {
+            self.$($field).+.branch_truncate(branch_name).c(d!())
+        }
+
+        /// Remove all changes directly made by versions(bigger than `last_version_id`) of this branch.
+        ///
+        /// 'Write'-like operations on branches and versions are different from operations on data.
         ///
         /// 'Write'-like operations on data require recursive tracing of all parent nodes,
         /// while operations on branches and versions are limited to their own perspective,
@@ -521,11 +1434,23 @@ macro_rules! impl_vs_methods {
             branch_name: BranchName,
             last_version_name: VersionName,
         ) -> Result<()> {
-            self.inner
+            self.$($field).+
                 .branch_truncate_to(branch_name, last_version_name)
                 .c(d!())
         }
 
+        /// Roll a branch back to a version created by it.
+        #[inline(always)]
+        fn version_rollback_to(
+            &self,
+            branch_name: BranchName,
+            version_name: VersionName,
+        ) -> Result<()> {
+            self.$($field).+
+                .version_rollback_to(branch_name, version_name)
+                .c(d!())
+        }
+
         /// Remove the newest version on a specified branch.
         ///
         /// 'Write'-like operations on branches and versions are different from operations on data.
@@ -535,32 +1460,44 @@ macro_rules! impl_vs_methods {
         /// and should not do any tracing.
         #[inline(always)]
         fn branch_pop_version(&self, branch_name: BranchName) -> Result<()> {
-            self.inner.branch_pop_version(branch_name).c(d!())
+            self.$($field).+.branch_pop_version(branch_name).c(d!())
         }
 
         /// Merge a branch to its parent branch.
         #[inline(always)]
         fn branch_merge_to_parent(&self, branch_name: BranchName) -> Result<()> {
-            self.inner.branch_merge_to_parent(branch_name).c(d!())
+            self.$($field).+.branch_merge_to_parent(branch_name).c(d!())
         }
 
         /// Check if a branch has children branches.
         #[inline(always)]
         fn branch_has_children(&self, branch_name: BranchName) -> bool {
-            self.inner.branch_has_children(branch_name)
+            self.$($field).+.branch_has_children(branch_name)
         }
 
         /// Make a branch to be default,
         /// all default operations will be applied to it.
         #[inline(always)]
         fn branch_set_default(&mut self, branch_name: BranchName) -> Result<()> {
-            self.inner.branch_set_default(branch_name).c(d!())
+            self.$($field).+.branch_set_default(branch_name).c(d!())
+        }
+
+        /// Freeze a branch, all write operations on it will fail.
+        #[inline(always)]
+        fn branch_freeze(&self, branch_name: BranchName) -> Result<()> {
+            self.$($field).+.branch_freeze(branch_name).c(d!())
+        }
+
+        /// Make a frozen branch writable again.
+        #[inline(always)]
+        fn branch_unfreeze(&self, branch_name: BranchName) -> Result<()> {
+            self.$($field).+.branch_unfreeze(branch_name).c(d!())
         }
 
         /// Clean outdated versions out of the default reserved number.
         #[inline(always)]
         fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()> {
-            self.inner.prune(reserved_ver_num).c(d!())
+            self.$($field).+.prune(reserved_ver_num).c(d!())
         }
 
         /// Clean outdated versions out of a specified reserved number.
@@ -570,10 +1507,44 @@ macro_rules! impl_vs_methods {
             branch_name: BranchName,
             reserved_ver_num: Option<usize>,
         ) -> Result<()> {
-            self.inner
+            self.$($field).+
                 .prune_by_branch(branch_name, reserved_ver_num)
                 .c(d!())
         }
+
+        /// Clean outdated versions of the default branch by a retention policy.
+        #[inline(always)]
+        fn prune_with(&self, policy: &PrunePolicy) -> Result<()> {
+            self.$($field).+.prune_with(policy).c(d!())
+        }
+
+        /// Clean outdated versions of a specified branch by a retention policy.
+        #[inline(always)]
+        fn prune_by_branch_with(
+            &self,
+            branch_name: BranchName,
+            policy: &PrunePolicy,
+        ) -> Result<()> {
+            self.$($field).+.prune_by_branch_with(branch_name, policy).c(d!())
+        }
+
+        /// Physically delete the data that can not be reached from any branch.
+        #[inline(always)]
+        fn gc(&self) -> Result<()> {
+            self.$($field).+.gc().c(d!())
+        }
+
+        /// The Merkle root of the head state of the default branch.
+        #[inline(always)]
+        fn state_root(&self) -> Result<$crate::merkle::sparse::Hash32> {
+            self.$($field).+.state_root().c(d!())
+        }
+
+        /// The identities of all versioned instances inside.
+        #[inline(always)]
+        fn instance_ids(&self) -> Result<Vec<$crate::versioned::InstanceId>> {
+            self.$($field).+.instance_ids().c(d!())
+        }
     };
 }
 
@@ -626,6 +1597,128 @@ macro_rules! impl_vs_methods_nope {
             Ok(())
         }
 
+        #[inline(always)]
+        fn version_tag(
+            &self,
+            _: BranchName,
+            _: VersionName,
+            _: VersionName,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_untag(&self, _: VersionName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_squash(&self, _: VersionName, _: VersionName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_squash_by_branch(
+            &self,
+            _: BranchName,
+            _: VersionName,
+            _: VersionName,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_revert(&self, _: VersionName, _: VersionName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_revert_by_branch(
+            &self,
+            _: VersionName,
+            _: VersionName,
+            _: BranchName,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_with_meta(
+            &self,
+            _: VersionName,
+            _: &VersionMeta,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch_with_meta(
+            &self,
+            _: VersionName,
+            _: BranchName,
+            _: &VersionMeta,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_cas(
+            &self,
+            _: VersionName,
+            _: Option<VersionName>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch_cas(
+            &self,
+            _: VersionName,
+            _: BranchName,
+            _: Option<VersionName>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn set_read_isolation(&self, _: bool) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_finalize(&self) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_finalize_by_branch(&self, _: BranchName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn savepoint(&self, _: SavepointName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn savepoint_by_branch(&self, _: SavepointName, _: BranchName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn rollback_to_savepoint(&self, _: SavepointName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn rollback_to_savepoint_by_branch(
+            &self,
+            _: SavepointName,
+            _: BranchName,
+        ) -> Result<()> {
+            Ok(())
+        }
+
         #[inline(always)]
         fn branch_create(&self, _: BranchName) -> Result<()> {
             Ok(())
@@ -655,6 +1748,21 @@ macro_rules! impl_vs_methods_nope {
             true
         }
 
+        #[inline(always)]
+        fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+            Ok(vec![])
+        }
+
+        #[inline(always)]
+        fn branch_rename(&self, _: BranchName, __: BranchName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_swap(&self, _: BranchName, __: BranchName) -> Result<()> {
+            Ok(())
+        }
+
         #[inline(always)]
         fn branch_remove(&self, _: BranchName) -> Result<()> {
             Ok(())
@@ -669,6 +1777,11 @@ macro_rules! impl_vs_methods_nope {
             Ok(())
         }
 
+        #[inline(always)]
+        fn version_rollback_to(&self, _: BranchName, _: VersionName) -> Result<()> {
+            Ok(())
+        }
+
         #[inline(always)]
         fn branch_pop_version(&self, _: BranchName) -> Result<()> {
             Ok(())
@@ -689,6 +1802,16 @@ macro_rules! impl_vs_methods_nope {
             Ok(())
         }
 
+        #[inline(always)]
+        fn branch_freeze(&self, _: BranchName) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_unfreeze(&self, _: BranchName) -> Result<()> {
+            Ok(())
+        }
+
         #[inline(always)]
         fn prune(&self, _: Option<usize>) -> Result<()> {
             Ok(())
@@ -698,6 +1821,31 @@ macro_rules! impl_vs_methods_nope {
         fn prune_by_branch(&self, _: BranchName, __: Option<usize>) -> Result<()> {
             Ok(())
         }
+
+        #[inline(always)]
+        fn prune_with(&self, _: &PrunePolicy) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn prune_by_branch_with(&self, _: BranchName, _: &PrunePolicy) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn gc(&self) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn state_root(&self) -> Result<$crate::merkle::sparse::Hash32> {
+            Ok($crate::merkle::sparse::EMPTY_ROOT)
+        }
+
+        #[inline(always)]
+        fn instance_ids(&self) -> Result<Vec<$crate::versioned::InstanceId>> {
+            Ok(vec![])
+        }
     };
 }
 
@@ -705,7 +1853,37 @@ impl<T: ?Sized> VsMgmt for PhantomData<T> {
     impl_vs_methods_nope!();
 }
 
-impl<K, V> VsMgmt for Mapx<K, V> {
+/// A field that is not versioned, such as a runtime cache,
+/// all versioned calls on it do nothing.
+///
+/// Wrap a field in it to let `#[derive(Vs)]` skip the field,
+/// instead of implementing the whole `VsMgmt` manually,
+/// it works for types defined outside of the current crate too.
+/// It takes the place of a `#[vs(skip)]` attribute, which the derive
+/// does not support, and it is serialized as the inner value itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Unversioned<T>(pub T);
+
+impl<T> Deref for Unversioned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Unversioned<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> VsMgmt for Unversioned<T> {
+    impl_vs_methods_nope!();
+}
+
+impl<K, V, C> VsMgmt for Mapx<K, V, C> {
     impl_vs_methods_nope!();
 }
 
@@ -713,7 +1891,7 @@ impl<K, V> VsMgmt for MapxOrd<K, V> {
     impl_vs_methods_nope!();
 }
 
-impl<V> VsMgmt for MapxOrdRawKey<V> {
+impl<V, C> VsMgmt for MapxOrdRawKey<V, C> {
     impl_vs_methods_nope!();
 }
 
@@ -745,7 +1923,11 @@ impl VsMgmt for MerkleTreeStore {
     impl_vs_methods_nope!();
 }
 
-impl<'a> VsMgmt for Proof<'a> {
+impl VsMgmt for Proof {
+    impl_vs_methods_nope!();
+}
+
+impl<'a> VsMgmt for ProofPath<'a> {
     impl_vs_methods_nope!();
 }
 
@@ -761,15 +1943,9 @@ macro_rules! impl_for_primitives {
         impl VsMgmt for dyn AsRef<$ty> {
             impl_vs_methods_nope!();
         }
-        impl VsMgmt for Box<dyn AsRef<$ty>> {
-            impl_vs_methods_nope!();
-        }
         impl VsMgmt for dyn AsRef<[$ty]> {
             impl_vs_methods_nope!();
         }
-        impl VsMgmt for Box<dyn AsRef<[$ty]>> {
-            impl_vs_methods_nope!();
-        }
         impl<K> VsMgmt for HashMap<K, $ty> {
             impl_vs_methods_nope!();
         }
@@ -906,6 +2082,196 @@ impl<T: VsMgmt> VsMgmt for Option<T> {
         Ok(())
     }
 
+    #[inline(always)]
+    fn version_tag(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+        tag: VersionName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_tag(branch_name, version_name, tag).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_untag(&self, tag: VersionName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_untag(tag).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_squash(&self, from: VersionName, to: VersionName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_squash(from, to).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_squash_by_branch(
+        &self,
+        branch_name: BranchName,
+        from: VersionName,
+        to: VersionName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_squash_by_branch(branch_name, from, to).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_revert(
+        &self,
+        reverted_version_name: VersionName,
+        new_version_name: VersionName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_revert(reverted_version_name, new_version_name)
+                .c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_revert_by_branch(
+        &self,
+        reverted_version_name: VersionName,
+        new_version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_revert_by_branch(
+                reverted_version_name,
+                new_version_name,
+                branch_name,
+            )
+            .c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_create_with_meta(
+        &self,
+        version_name: VersionName,
+        meta: &VersionMeta,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_create_with_meta(version_name, meta).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_create_by_branch_with_meta(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+        meta: &VersionMeta,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_create_by_branch_with_meta(version_name, branch_name, meta)
+                .c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_create_cas(
+        &self,
+        version_name: VersionName,
+        expected_head: Option<VersionName>,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_create_cas(version_name, expected_head).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_create_by_branch_cas(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+        expected_head: Option<VersionName>,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_create_by_branch_cas(version_name, branch_name, expected_head)
+                .c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn set_read_isolation(&self, enabled: bool) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.set_read_isolation(enabled).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_finalize(&self) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_finalize().c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn version_finalize_by_branch(&self, branch_name: BranchName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_finalize_by_branch(branch_name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn savepoint(&self, name: SavepointName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.savepoint(name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn savepoint_by_branch(
+        &self,
+        name: SavepointName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.savepoint_by_branch(name, branch_name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn rollback_to_savepoint(&self, name: SavepointName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.rollback_to_savepoint(name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn rollback_to_savepoint_by_branch(
+        &self,
+        name: SavepointName,
+        branch_name: BranchName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.rollback_to_savepoint_by_branch(name, branch_name)
+                .c(d!())?;
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     fn branch_create(&self, branch_name: BranchName) -> Result<()> {
         if let Some(i) = self.as_ref() {
@@ -953,6 +2319,30 @@ impl<T: VsMgmt> VsMgmt for Option<T> {
         true
     }
 
+    #[inline(always)]
+    fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+        if let Some(i) = self.as_ref() {
+            return i.branch_list().c(d!());
+        }
+        Ok(vec![])
+    }
+
+    #[inline(always)]
+    fn branch_rename(&self, old_name: BranchName, new_name: BranchName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.branch_rename(old_name, new_name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn branch_swap(&self, branch_a: BranchName, branch_b: BranchName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.branch_swap(branch_a, branch_b).c(d!())?;
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     fn branch_remove(&self, branch_name: BranchName) -> Result<()> {
         if let Some(i) = self.as_ref() {
@@ -982,6 +2372,18 @@ impl<T: VsMgmt> VsMgmt for Option<T> {
         Ok(())
     }
 
+    #[inline(always)]
+    fn version_rollback_to(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.version_rollback_to(branch_name, version_name).c(d!())?;
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     fn branch_pop_version(&self, branch_name: BranchName) -> Result<()> {
         if let Some(i) = self.as_ref() {
@@ -1011,39 +2413,640 @@ impl<T: VsMgmt> VsMgmt for Option<T> {
         if let Some(i) = self.as_mut() {
             i.branch_set_default(branch_name).c(d!())?;
         }
-        Ok(())
-    }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn branch_freeze(&self, branch_name: BranchName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.branch_freeze(branch_name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn branch_unfreeze(&self, branch_name: BranchName) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.branch_unfreeze(branch_name).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.prune(reserved_ver_num).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn prune_by_branch(
+        &self,
+        branch_name: BranchName,
+        reserved_ver_num: Option<usize>,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.prune_by_branch(branch_name, reserved_ver_num).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn prune_with(&self, policy: &PrunePolicy) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.prune_with(policy).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn prune_by_branch_with(
+        &self,
+        branch_name: BranchName,
+        policy: &PrunePolicy,
+    ) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.prune_by_branch_with(branch_name, policy).c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn gc(&self) -> Result<()> {
+        if let Some(i) = self.as_ref() {
+            i.gc().c(d!())?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn state_root(&self) -> Result<Hash32> {
+        if let Some(i) = self.as_ref() {
+            return i.state_root().c(d!());
+        }
+        Ok(EMPTY_ROOT)
+    }
+
+    #[inline(always)]
+    fn instance_ids(&self) -> Result<Vec<InstanceId>> {
+        if let Some(i) = self.as_ref() {
+            return i.instance_ids().c(d!());
+        }
+        Ok(vec![])
+    }
+}
+
+/// A helper for implementing `VsMgmt` for collection types,
+/// `struct NewType(HashMap)`, `struct NewType(BTreeMap)`, etc.
+#[macro_export]
+macro_rules! impl_for_collections {
+    ($values: tt, $values_mut: tt) => {
+        fn version_create(&self, version_name: VersionName) -> Result<()> {
+            for i in self.$values() {
+                i.version_create(version_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_create_by_branch(version_name, branch_name)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_exists(&self, version_name: VersionName) -> bool {
+            for i in self.$values() {
+                alt!(!i.version_exists(version_name), return false);
+            }
+            true
+        }
+
+        #[inline(always)]
+        fn version_exists_on_branch(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+        ) -> bool {
+            for i in self.$values() {
+                alt!(
+                    !i.version_exists_on_branch(version_name, branch_name),
+                    return false
+                );
+            }
+            true
+        }
+
+        #[inline(always)]
+        fn version_created(&self, version_name: VersionName) -> bool {
+            for i in self.$values() {
+                alt!(!i.version_created(version_name), return false);
+            }
+            true
+        }
+
+        #[inline(always)]
+        fn version_created_on_branch(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+        ) -> bool {
+            for i in self.$values() {
+                alt!(
+                    !i.version_created_on_branch(version_name, branch_name),
+                    return false
+                );
+            }
+            true
+        }
+
+        #[inline(always)]
+        fn version_pop(&self) -> Result<()> {
+            for i in self.$values() {
+                i.version_pop().c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_pop_by_branch(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.version_pop_by_branch(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_tag(
+            &self,
+            branch_name: BranchName,
+            version_name: VersionName,
+            tag: VersionName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_tag(branch_name, version_name, tag).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_untag(&self, tag: VersionName) -> Result<()> {
+            for i in self.$values() {
+                i.version_untag(tag).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_squash(&self, from: VersionName, to: VersionName) -> Result<()> {
+            for i in self.$values() {
+                i.version_squash(from, to).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_squash_by_branch(
+            &self,
+            branch_name: BranchName,
+            from: VersionName,
+            to: VersionName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_squash_by_branch(branch_name, from, to).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_revert(
+            &self,
+            reverted_version_name: VersionName,
+            new_version_name: VersionName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_revert(reverted_version_name, new_version_name)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_revert_by_branch(
+            &self,
+            reverted_version_name: VersionName,
+            new_version_name: VersionName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_revert_by_branch(
+                    reverted_version_name,
+                    new_version_name,
+                    branch_name,
+                )
+                .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_with_meta(
+            &self,
+            version_name: VersionName,
+            meta: &VersionMeta,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_create_with_meta(version_name, meta).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch_with_meta(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+            meta: &VersionMeta,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_create_by_branch_with_meta(version_name, branch_name, meta)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_cas(
+            &self,
+            version_name: VersionName,
+            expected_head: Option<VersionName>,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_create_cas(version_name, expected_head).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch_cas(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+            expected_head: Option<VersionName>,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_create_by_branch_cas(version_name, branch_name, expected_head)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn set_read_isolation(&self, enabled: bool) -> Result<()> {
+            for i in self.$values() {
+                i.set_read_isolation(enabled).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_finalize(&self) -> Result<()> {
+            for i in self.$values() {
+                i.version_finalize().c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_finalize_by_branch(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.version_finalize_by_branch(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn savepoint(&self, name: SavepointName) -> Result<()> {
+            for i in self.$values() {
+                i.savepoint(name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn savepoint_by_branch(
+            &self,
+            name: SavepointName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.savepoint_by_branch(name, branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn rollback_to_savepoint(&self, name: SavepointName) -> Result<()> {
+            for i in self.$values() {
+                i.rollback_to_savepoint(name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn rollback_to_savepoint_by_branch(
+            &self,
+            name: SavepointName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.rollback_to_savepoint_by_branch(name, branch_name)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_create(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_create(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_create_by_base_branch(
+            &self,
+            branch_name: BranchName,
+            base_branch_name: ParentBranchName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.branch_create_by_base_branch(branch_name, base_branch_name)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_create_by_base_branch_version(
+            &self,
+            branch_name: BranchName,
+            base_branch_name: ParentBranchName,
+            base_version_name: VersionName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.branch_create_by_base_branch_version(
+                    branch_name,
+                    base_branch_name,
+                    base_version_name,
+                )
+                .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_exists(&self, branch_name: BranchName) -> bool {
+            for i in self.$values() {
+                alt!(!i.branch_exists(branch_name), return false);
+            }
+            true
+        }
+
+        // the union of the branches of all items
+        #[inline(always)]
+        fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+            let mut ret = BTreeMap::new();
+            for i in self.$values() {
+                for br in i.branch_list().c(d!())? {
+                    ret.insert(br.name.clone(), br);
+                }
+            }
+            Ok(ret.into_values().collect())
+        }
+
+        #[inline(always)]
+        fn branch_rename(
+            &self,
+            old_name: BranchName,
+            new_name: BranchName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.branch_rename(old_name, new_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_swap(&self, branch_a: BranchName, branch_b: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_swap(branch_a, branch_b).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_remove(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_remove(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_truncate(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_truncate(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_truncate_to(
+            &self,
+            branch_name: BranchName,
+            last_version_name: VersionName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.branch_truncate_to(branch_name, last_version_name)
+                    .c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_rollback_to(
+            &self,
+            branch_name: BranchName,
+            version_name: VersionName,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.version_rollback_to(branch_name, version_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_pop_version(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_pop_version(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_merge_to_parent(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_merge_to_parent(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_has_children(&self, branch_name: BranchName) -> bool {
+            for i in self.$values() {
+                alt!(!i.branch_has_children(branch_name), return false);
+            }
+            true
+        }
+
+        #[inline(always)]
+        fn branch_set_default(&mut self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values_mut() {
+                i.branch_set_default(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_freeze(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_freeze(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_unfreeze(&self, branch_name: BranchName) -> Result<()> {
+            for i in self.$values() {
+                i.branch_unfreeze(branch_name).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()> {
+            for i in self.$values() {
+                i.prune(reserved_ver_num).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn prune_by_branch(
+            &self,
+            branch_name: BranchName,
+            reserved_ver_num: Option<usize>,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.prune_by_branch(branch_name, reserved_ver_num).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn prune_with(&self, policy: &PrunePolicy) -> Result<()> {
+            for i in self.$values() {
+                i.prune_with(policy).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn prune_by_branch_with(
+            &self,
+            branch_name: BranchName,
+            policy: &PrunePolicy,
+        ) -> Result<()> {
+            for i in self.$values() {
+                i.prune_by_branch_with(branch_name, policy).c(d!())?;
+            }
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn gc(&self) -> Result<()> {
+            for i in self.$values() {
+                i.gc().c(d!())?;
+            }
+            Ok(())
+        }
 
-    #[inline(always)]
-    fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()> {
-        if let Some(i) = self.as_ref() {
-            i.prune(reserved_ver_num).c(d!())?;
+        // elements are committed as a set, their order is not kept
+        #[inline(always)]
+        fn state_root(&self) -> Result<$crate::merkle::sparse::Hash32> {
+            let mut parts = vec![];
+            for i in self.$values() {
+                parts.push(("", i.state_root().c(d!())?));
+            }
+            Ok($crate::versioned::state_root_of(parts))
         }
-        Ok(())
-    }
 
-    #[inline(always)]
-    fn prune_by_branch(
-        &self,
-        branch_name: BranchName,
-        reserved_ver_num: Option<usize>,
-    ) -> Result<()> {
-        if let Some(i) = self.as_ref() {
-            i.prune_by_branch(branch_name, reserved_ver_num).c(d!())?;
+        #[inline(always)]
+        fn instance_ids(&self) -> Result<Vec<$crate::versioned::InstanceId>> {
+            let mut ids = vec![];
+            for i in self.$values() {
+                ids.extend(i.instance_ids().c(d!())?);
+            }
+            Ok(ids)
         }
-        Ok(())
-    }
+    };
 }
 
-/// A helper for implementing `VsMgmt` for collection types,
-/// `struct NewType(HashMap)`, `struct NewType(BTreeMap)`, etc.
-#[macro_export]
-macro_rules! impl_for_collections {
-    ($values: tt, $values_mut: tt) => {
+// The versioned parts of std wrappers,
+// `*` is the pointee of a `Box`, `Arc` is the pointee of an `Arc`,
+// others are fields of a tuple.
+macro_rules! vs_part {
+    ($s: ident, *) => {
+        &**$s
+    };
+    ($s: ident, Arc) => {
+        &**$s
+    };
+    ($s: ident, $idx: tt) => {
+        &$s.$idx
+    };
+}
+
+// An `Arc` can only be changed when it is not shared.
+macro_rules! vs_part_mut {
+    ($s: ident, *) => {
+        &mut **$s
+    };
+    ($s: ident, Arc) => {
+        Arc::get_mut($s).c(d!("the `Arc` is shared"))?
+    };
+    ($s: ident, $idx: tt) => {
+        &mut $s.$idx
+    };
+}
+
+// Forward all methods to every part of a wrapper.
+macro_rules! impl_for_parts {
+    ($($part: tt),+) => {
         fn version_create(&self, version_name: VersionName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.version_create(version_name).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
@@ -1053,18 +3056,20 @@ macro_rules! impl_for_collections {
             version_name: VersionName,
             branch_name: BranchName,
         ) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.version_create_by_branch(version_name, branch_name)
                     .c(d!())?;
-            }
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn version_exists(&self, version_name: VersionName) -> bool {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 alt!(!i.version_exists(version_name), return false);
-            }
+            )+
             true
         }
 
@@ -1074,20 +3079,22 @@ macro_rules! impl_for_collections {
             version_name: VersionName,
             branch_name: BranchName,
         ) -> bool {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 alt!(
                     !i.version_exists_on_branch(version_name, branch_name),
                     return false
                 );
-            }
+            )+
             true
         }
 
         #[inline(always)]
         fn version_created(&self, version_name: VersionName) -> bool {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 alt!(!i.version_created(version_name), return false);
-            }
+            )+
             true
         }
 
@@ -1097,36 +3104,246 @@ macro_rules! impl_for_collections {
             version_name: VersionName,
             branch_name: BranchName,
         ) -> bool {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 alt!(
                     !i.version_created_on_branch(version_name, branch_name),
                     return false
                 );
-            }
+            )+
             true
         }
 
         #[inline(always)]
         fn version_pop(&self) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.version_pop().c(d!())?;
-            }
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn version_pop_by_branch(&self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.version_pop_by_branch(branch_name).c(d!())?;
-            }
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_tag(
+            &self,
+            branch_name: BranchName,
+            version_name: VersionName,
+            tag: VersionName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_tag(branch_name, version_name, tag).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_untag(&self, tag: VersionName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_untag(tag).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_squash(&self, from: VersionName, to: VersionName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_squash(from, to).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_squash_by_branch(
+            &self,
+            branch_name: BranchName,
+            from: VersionName,
+            to: VersionName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_squash_by_branch(branch_name, from, to).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_revert(
+            &self,
+            reverted_version_name: VersionName,
+            new_version_name: VersionName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_revert(reverted_version_name, new_version_name)
+                    .c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_revert_by_branch(
+            &self,
+            reverted_version_name: VersionName,
+            new_version_name: VersionName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_revert_by_branch(
+                    reverted_version_name,
+                    new_version_name,
+                    branch_name,
+                )
+                .c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_with_meta(
+            &self,
+            version_name: VersionName,
+            meta: &VersionMeta,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_create_with_meta(version_name, meta).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch_with_meta(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+            meta: &VersionMeta,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_create_by_branch_with_meta(version_name, branch_name, meta)
+                    .c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_cas(
+            &self,
+            version_name: VersionName,
+            expected_head: Option<VersionName>,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_create_cas(version_name, expected_head).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_create_by_branch_cas(
+            &self,
+            version_name: VersionName,
+            branch_name: BranchName,
+            expected_head: Option<VersionName>,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_create_by_branch_cas(version_name, branch_name, expected_head)
+                    .c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn set_read_isolation(&self, enabled: bool) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.set_read_isolation(enabled).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_finalize(&self) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_finalize().c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_finalize_by_branch(&self, branch_name: BranchName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_finalize_by_branch(branch_name).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn savepoint(&self, name: SavepointName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.savepoint(name).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn savepoint_by_branch(
+            &self,
+            name: SavepointName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.savepoint_by_branch(name, branch_name).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn rollback_to_savepoint(&self, name: SavepointName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.rollback_to_savepoint(name).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn rollback_to_savepoint_by_branch(
+            &self,
+            name: SavepointName,
+            branch_name: BranchName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.rollback_to_savepoint_by_branch(name, branch_name).c(d!())?;
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn branch_create(&self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_create(branch_name).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
@@ -1136,10 +3353,11 @@ macro_rules! impl_for_collections {
             branch_name: BranchName,
             base_branch_name: ParentBranchName,
         ) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_create_by_base_branch(branch_name, base_branch_name)
                     .c(d!())?;
-            }
+            )+
             Ok(())
         }
 
@@ -1150,38 +3368,77 @@ macro_rules! impl_for_collections {
             base_branch_name: ParentBranchName,
             base_version_name: VersionName,
         ) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_create_by_base_branch_version(
                     branch_name,
                     base_branch_name,
                     base_version_name,
                 )
                 .c(d!())?;
-            }
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn branch_exists(&self, branch_name: BranchName) -> bool {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 alt!(!i.branch_exists(branch_name), return false);
-            }
+            )+
             true
         }
 
+        // the union of the branches of all parts
+        #[inline(always)]
+        fn branch_list(&self) -> Result<Vec<BranchInfo>> {
+            let mut ret = BTreeMap::new();
+            $(
+                let i = vs_part!(self, $part);
+                for br in i.branch_list().c(d!())? {
+                    ret.insert(br.name.clone(), br);
+                }
+            )+
+            Ok(ret.into_values().collect())
+        }
+
+        #[inline(always)]
+        fn branch_rename(
+            &self,
+            old_name: BranchName,
+            new_name: BranchName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.branch_rename(old_name, new_name).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_swap(&self, branch_a: BranchName, branch_b: BranchName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.branch_swap(branch_a, branch_b).c(d!())?;
+            )+
+            Ok(())
+        }
+
         #[inline(always)]
         fn branch_remove(&self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_remove(branch_name).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn branch_truncate(&self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_truncate(branch_name).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
@@ -1191,50 +3448,87 @@ macro_rules! impl_for_collections {
             branch_name: BranchName,
             last_version_name: VersionName,
         ) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_truncate_to(branch_name, last_version_name)
                     .c(d!())?;
-            }
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn version_rollback_to(
+            &self,
+            branch_name: BranchName,
+            version_name: VersionName,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.version_rollback_to(branch_name, version_name).c(d!())?;
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn branch_pop_version(&self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_pop_version(branch_name).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn branch_merge_to_parent(&self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.branch_merge_to_parent(branch_name).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn branch_has_children(&self, branch_name: BranchName) -> bool {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 alt!(!i.branch_has_children(branch_name), return false);
-            }
+            )+
             true
         }
 
         #[inline(always)]
         fn branch_set_default(&mut self, branch_name: BranchName) -> Result<()> {
-            for i in self.$values_mut() {
+            $(
+                let i = vs_part_mut!(self, $part);
                 i.branch_set_default(branch_name).c(d!())?;
-            }
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_freeze(&self, branch_name: BranchName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.branch_freeze(branch_name).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn branch_unfreeze(&self, branch_name: BranchName) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.branch_unfreeze(branch_name).c(d!())?;
+            )+
             Ok(())
         }
 
         #[inline(always)]
         fn prune(&self, reserved_ver_num: Option<usize>) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.prune(reserved_ver_num).c(d!())?;
-            }
+            )+
             Ok(())
         }
 
@@ -1244,10 +3538,109 @@ macro_rules! impl_for_collections {
             branch_name: BranchName,
             reserved_ver_num: Option<usize>,
         ) -> Result<()> {
-            for i in self.$values() {
+            $(
+                let i = vs_part!(self, $part);
                 i.prune_by_branch(branch_name, reserved_ver_num).c(d!())?;
-            }
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn prune_with(&self, policy: &PrunePolicy) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.prune_with(policy).c(d!())?;
+            )+
             Ok(())
         }
+
+        #[inline(always)]
+        fn prune_by_branch_with(
+            &self,
+            branch_name: BranchName,
+            policy: &PrunePolicy,
+        ) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.prune_by_branch_with(branch_name, policy).c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn gc(&self) -> Result<()> {
+            $(
+                let i = vs_part!(self, $part);
+                i.gc().c(d!())?;
+            )+
+            Ok(())
+        }
+
+        #[inline(always)]
+        fn instance_ids(&self) -> Result<Vec<InstanceId>> {
+            let mut ids = vec![];
+            $(
+                let i = vs_part!(self, $part);
+                ids.extend(i.instance_ids().c(d!())?);
+            )+
+            Ok(ids)
+        }
+    };
+}
+
+impl<T: ?Sized + VsMgmt> VsMgmt for Box<T> {
+    impl_for_parts!(*);
+
+    #[inline(always)]
+    fn state_root(&self) -> Result<Hash32> {
+        (**self).state_root().c(d!())
+    }
+}
+
+impl<T: ?Sized + VsMgmt> VsMgmt for Arc<T> {
+    impl_for_parts!(Arc);
+
+    #[inline(always)]
+    fn state_root(&self) -> Result<Hash32> {
+        (**self).state_root().c(d!())
+    }
+}
+
+impl<T: VsMgmt, const N: usize> VsMgmt for [T; N] {
+    impl_for_collections!(iter, iter_mut);
+}
+
+macro_rules! impl_for_tuples {
+    ($($t: ident: $idx: tt),+) => {
+        impl<$($t: VsMgmt),+> VsMgmt for ($($t,)+) {
+            impl_for_parts!($($idx),+);
+            impl_state_root!($($idx),+);
+        }
     };
 }
+
+impl_for_tuples!(A: 0);
+impl_for_tuples!(A: 0, B: 1);
+impl_for_tuples!(A: 0, B: 1, C: 2);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+impl_for_tuples!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+impl_for_tuples!(
+    A: 0,
+    B: 1,
+    C: 2,
+    D: 3,
+    E: 4,
+    F: 5,
+    G: 6,
+    H: 7,
+    I: 8,
+    J: 9,
+    K: 10,
+    L: 11
+);
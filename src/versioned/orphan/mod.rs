@@ -3,12 +3,16 @@
 //!
 
 use crate::{
-    versioned::mapx_ord_rawkey::MapxOrdRawKeyVs, BranchName, ParentBranchName,
-    ValueEnDe, VersionName, VsMgmt,
+    common::stats::SizeHistogram,
+    versioned::{
+        mapx_ord_rawkey::MapxOrdRawKeyVs, BranchInfo, PrunePolicy, SnapshotHandle,
+        VersionBundle, VersionInfo, VersionMeta,
+    },
+    BranchName, ParentBranchName, ValueEnDe, VersionName, VsComplete, VsMgmt, VsNested,
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, RangeBounds};
 
 /// Used to express some 'non-collection' types,
 /// such as any type of integer, an enum value, etc..
@@ -46,7 +50,8 @@ where
     ///     - `*(&mut <Orphan>) = Orphan::new(...)`
     ///     - OR you will loss the 'versioned' ability of this object
     pub fn get_mut(&self) -> ValueMut<'_, T> {
-        let value = self.get_value();
+        // in the view of the writer, check `VsMgmt::set_read_isolation`
+        let value = self.inner.get_head(&[]).unwrap();
         ValueMut { hdr: self, value }
     }
 
@@ -92,6 +97,159 @@ where
         self.inner
             .get_by_branch_version(&[], branch_name, version_name)
     }
+
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+
+    #[inline(always)]
+    pub fn branch_rebase(
+        &self,
+        branch_name: BranchName,
+        new_parent_branch_name: ParentBranchName,
+        new_parent_version_name: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .branch_rebase(branch_name, new_parent_branch_name, new_parent_version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner.export_versions(bounds).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions_by_branch<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_name: BranchName,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner
+            .export_versions_by_branch(branch_name, bounds)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_from_bundle(
+        &self,
+        branch_name: BranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_from_bundle(branch_name, bundle)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_by_base_branch_from_bundle(
+        &self,
+        branch_name: BranchName,
+        base_branch_name: ParentBranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_by_base_branch_from_bundle(
+                branch_name,
+                base_branch_name,
+                bundle,
+            )
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_is_ancestor(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<bool> {
+        self.inner.branch_is_ancestor(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_base(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.inner.branch_merge_base(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_checksum(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<[u8; 32]> {
+        self.inner
+            .version_checksum(branch_name, version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list(&self) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list_by_branch(branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta(&self, version_name: VersionName) -> Option<VersionMeta> {
+        self.inner.version_get_meta(version_name)
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<VersionMeta> {
+        self.inner
+            .version_get_meta_by_branch(version_name, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_is_frozen(&self, branch_name: BranchName) -> bool {
+        self.inner.branch_is_frozen(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_depth(&self, branch_name: BranchName) -> Option<usize> {
+        self.inner.branch_depth(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_export_shallow(
+        &self,
+        branch_name: BranchName,
+        target_dir: &str,
+    ) -> Result<()> {
+        self.inner
+            .branch_export_shallow(branch_name, target_dir)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn import_shallow(src_dir: &str) -> Result<Self> {
+        Ok(Self {
+            inner: MapxOrdRawKeyVs::import_shallow(src_dir).c(d!())?,
+        })
+    }
 }
 
 impl<T> Default for OrphanVs<T>
@@ -110,6 +268,21 @@ where
     crate::impl_vs_methods!();
 }
 
+impl<T> VsComplete for OrphanVs<T> where T: ValueEnDe {}
+
+impl<T> VsNested for OrphanVs<T> where T: ValueEnDe + VsComplete {}
+
+impl<T> SnapshotHandle<'_, OrphanVs<T>>
+where
+    T: ValueEnDe,
+{
+    #[inline(always)]
+    pub fn get_value(&self) -> Option<T> {
+        self.inner()
+            .get_value_by_branch_version(self.branch_name(), self.version_name())
+    }
+}
+
 /// A type returned by `get_mut()`.
 pub struct ValueMut<'a, T>
 where
@@ -0,0 +1,211 @@
+//!
+//! Read-only views pinned at a fixed version.
+//!
+//! Reads through a [SnapshotHandle](self::SnapshotHandle) need not to
+//! pass the branch name and the version name every time, and the version
+//! is guarded against pruning while any handle of it is alive.
+//!
+//! `#[derive(Vs)]` does not generate plain snapshot structs, it is provided
+//! by the external `vsdb_derive` crate. To export a whole versioned struct
+//! to serde-friendly types, declare the plain struct and collect each field
+//! by `to_plain`:
+//!
+//! ```
+//! use ruc::*;
+//! use serde::Serialize;
+//! use vsdb::{BranchName, MapxVs, ParentBranchName, VecxVs, VersionName, Vs, VsMgmt};
+//!
+//! #[derive(Vs)]
+//! struct Foo {
+//!     a: MapxVs<u32, String>,
+//!     b: VecxVs<u64>,
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct FooSnapshot {
+//!     a: Vec<(u32, String)>,
+//!     b: Vec<u64>,
+//! }
+//!
+//! let foo = Foo {
+//!     a: MapxVs::new(),
+//!     b: VecxVs::new(),
+//! };
+//! pnk!(foo.version_create(VersionName(b"v-0")));
+//! pnk!(foo.a.insert(1, "x".to_owned()));
+//! foo.b.push(7);
+//!
+//! let s = pnk!(foo.snapshot(BranchName(b"main"), VersionName(b"v-0")));
+//! let plain = FooSnapshot {
+//!     a: s.project(|f| &f.a).to_plain(),
+//!     b: s.project(|f| &f.b).to_plain(),
+//! };
+//! assert_eq!(vec![(1, "x".to_owned())], plain.a);
+//! assert_eq!(vec![7], plain.b);
+//! ```
+//!
+
+use crate::{
+    basic::mapx_raw::MapxRaw,
+    common::{BranchName, VersionName},
+    VsMgmt,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ruc::*;
+use std::{collections::HashMap, sync::Arc};
+
+/// The identity of a versioned instance, unique among all databases.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceId {
+    db: usize,
+    id: u64,
+}
+
+impl InstanceId {
+    #[inline(always)]
+    pub(crate) fn of(hdr: &MapxRaw) -> Self {
+        Self {
+            db: hdr.db() as *const _ as usize,
+            id: hdr.id(),
+        }
+    }
+}
+
+// `None` means all instances,
+// it is used by types that can not tell their instances.
+type PinKey = (Option<InstanceId>, Vec<u8>, Vec<u8>);
+
+// Pinned versions, indexed by (instance, branch name, version name),
+// the value is the number of alive handles.
+static PINNED: Lazy<Mutex<HashMap<PinKey, usize>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Versions of the instance that should not be pruned currently,
+// as (branch name, version name).
+pub(crate) fn pinned_versions(instance: InstanceId) -> Vec<(Vec<u8>, Vec<u8>)> {
+    PINNED
+        .lock()
+        .keys()
+        .filter(|(id, _, _)| id.map(|id| id == instance).unwrap_or(true))
+        .map(|(_, br, ver)| (br.clone(), ver.clone()))
+        .collect()
+}
+
+struct Pin {
+    branch_name: Vec<u8>,
+    version_name: Vec<u8>,
+    keys: Vec<PinKey>,
+}
+
+impl Pin {
+    fn new(
+        instances: Option<Vec<InstanceId>>,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Self {
+        let (br, ver) = (branch_name.0.to_vec(), version_name.0.to_vec());
+        let keys = match instances {
+            Some(ids) => ids
+                .into_iter()
+                .map(|id| (Some(id), br.clone(), ver.clone()))
+                .collect(),
+            None => vec![(None, br.clone(), ver.clone())],
+        };
+
+        let mut hdr = PINNED.lock();
+        for k in keys.iter() {
+            *hdr.entry(k.clone()).or_insert(0) += 1;
+        }
+
+        Pin {
+            branch_name: br,
+            version_name: ver,
+            keys,
+        }
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        let mut hdr = PINNED.lock();
+        for k in self.keys.iter() {
+            if let Some(cnt) = hdr.get_mut(k) {
+                *cnt -= 1;
+                if 0 == *cnt {
+                    hdr.remove(k);
+                }
+            }
+        }
+    }
+}
+
+/// An immutable view of a collection(or a whole `#[derive(Vs)]` struct)
+/// at a specified version of a specified branch.
+pub struct SnapshotHandle<'a, T: ?Sized> {
+    hdr: &'a T,
+    pin: Arc<Pin>,
+}
+
+impl<'a, T: ?Sized + VsMgmt> SnapshotHandle<'a, T> {
+    /// Pin the version, it must exist on the branch.
+    ///
+    /// Only the instances reported by `VsMgmt::instance_ids` are pinned,
+    /// the version is pinned on all instances if they are unknown.
+    pub fn new(
+        hdr: &'a T,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<Self> {
+        if !hdr.version_exists_on_branch(version_name, branch_name) {
+            return Err(eg!("version not found"));
+        }
+        Ok(Self {
+            hdr,
+            pin: Arc::new(Pin::new(hdr.instance_ids().ok(), branch_name, version_name)),
+        })
+    }
+}
+
+impl<'a, T: ?Sized> SnapshotHandle<'a, T> {
+    /// The underlying instance,
+    /// reads through it are NOT limited to the snapshot.
+    #[inline(always)]
+    pub fn inner(&self) -> &'a T {
+        self.hdr
+    }
+
+    /// The branch of this snapshot.
+    #[inline(always)]
+    pub fn branch_name(&self) -> BranchName {
+        BranchName(&self.pin.branch_name)
+    }
+
+    /// The version of this snapshot.
+    #[inline(always)]
+    pub fn version_name(&self) -> VersionName {
+        VersionName(&self.pin.version_name)
+    }
+
+    /// Get a view of a field at the same version,
+    /// the version is kept pinned until all related handles are dropped.
+    #[inline(always)]
+    pub fn project<U: ?Sized>(
+        &self,
+        f: impl FnOnce(&'a T) -> &'a U,
+    ) -> SnapshotHandle<'a, U> {
+        SnapshotHandle {
+            hdr: f(self.hdr),
+            pin: Arc::clone(&self.pin),
+        }
+    }
+}
+
+impl<T: ?Sized> Clone for SnapshotHandle<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            hdr: self.hdr,
+            pin: Arc::clone(&self.pin),
+        }
+    }
+}
@@ -3,12 +3,17 @@
 //!
 
 use crate::{
-    versioned::mapx_ord_rawkey::{MapxOrdRawKeyVs, MapxOrdRawKeyVsIter},
-    BranchName, ParentBranchName, ValueEnDe, VersionName, VsMgmt,
+    common::{named, stats::SizeHistogram},
+    versioned::{
+        mapx_ord_rawkey::{MapxOrdRawKeyVs, MapxOrdRawKeyVsIter},
+        BranchInfo, PrunePolicy, SnapshotHandle, VersionBundle, VersionInfo,
+        VersionMeta,
+    },
+    BranchName, ParentBranchName, ValueEnDe, VersionName, VsComplete, VsMgmt, VsNested,
 };
 use ruc::*;
 use serde::{Deserialize, Serialize};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, RangeBounds};
 
 /// Documents => [MapxRawVs](crate::versioned::mapx_raw::MapxRawVs)
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -31,6 +36,13 @@ impl<T: ValueEnDe> VecxVs<T> {
         }
     }
 
+    /// Open the instance registered at `path` in the current database,
+    /// a new one is created and registered if not found.
+    #[inline(always)]
+    pub fn open_at(path: &str) -> Result<Self> {
+        named::open_at(path, Self::new).c(d!())
+    }
+
     #[inline(always)]
     pub fn get(&self, idx: usize) -> Option<T> {
         self.inner.get(&(idx as u64).to_be_bytes())
@@ -38,7 +50,9 @@ impl<T: ValueEnDe> VecxVs<T> {
 
     #[inline(always)]
     pub fn get_mut(&self, idx: usize) -> Option<ValueMut<'_, T>> {
-        self.get(idx).map(|v| ValueMut::new(&self.inner, idx, v))
+        self.inner
+            .get_head(&(idx as u64).to_be_bytes())
+            .map(|v| ValueMut::new(&self.inner, idx, v))
     }
 
     #[inline(always)]
@@ -46,11 +60,14 @@ impl<T: ValueEnDe> VecxVs<T> {
         alt!(self.is_empty(), return None);
         Some(
             self.inner
-                .get(&(self.len() as u64 - 1).to_be_bytes())
+                .get_head(&(self.len() as u64 - 1).to_be_bytes())
                 .unwrap(),
         )
     }
 
+    /// Items of the version still being built are counted even if
+    /// the read isolation is enabled, so `push` and `pop` always work
+    /// on the real end, check `VsMgmt::set_read_isolation`.
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.inner.len()
@@ -106,6 +123,16 @@ impl<T: ValueEnDe> VecxVs<T> {
         self.inner.clear();
     }
 
+    #[inline(always)]
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    #[inline(always)]
+    pub fn size_histogram(&self, sample_step: usize) -> SizeHistogram {
+        self.inner.size_histogram(sample_step)
+    }
+
     #[inline(always)]
     pub fn get_by_branch(&self, idx: usize, branch_name: BranchName) -> Option<T> {
         self.inner
@@ -114,10 +141,11 @@ impl<T: ValueEnDe> VecxVs<T> {
 
     #[inline(always)]
     pub fn last_by_branch(&self, branch_name: BranchName) -> Option<T> {
-        alt!(self.is_empty(), return None);
+        let len = self.len_by_branch(branch_name);
+        alt!(0 == len, return None);
         Some(
             self.inner
-                .get_by_branch(&(self.len() as u64 - 1).to_be_bytes(), branch_name)
+                .get_head_by_branch(&(len as u64 - 1).to_be_bytes(), branch_name)
                 .unwrap(),
         )
     }
@@ -204,11 +232,12 @@ impl<T: ValueEnDe> VecxVs<T> {
         branch_name: BranchName,
         version_name: VersionName,
     ) -> Option<T> {
-        alt!(self.is_empty(), return None);
+        let len = self.len_by_branch_version(branch_name, version_name);
+        alt!(0 == len, return None);
         Some(
             self.inner
                 .get_by_branch_version(
-                    &(self.len() as u64 - 1).to_be_bytes(),
+                    &(len as u64 - 1).to_be_bytes(),
                     branch_name,
                     version_name,
                 )
@@ -245,12 +274,197 @@ impl<T: ValueEnDe> VecxVs<T> {
             iter: self.inner.iter_by_branch_version(branch_name, version_name),
         }
     }
+
+    #[inline(always)]
+    pub fn branch_rebase(
+        &self,
+        branch_name: BranchName,
+        new_parent_branch_name: ParentBranchName,
+        new_parent_version_name: VersionName,
+    ) -> Result<()> {
+        self.inner
+            .branch_rebase(branch_name, new_parent_branch_name, new_parent_version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner.export_versions(bounds).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn export_versions_by_branch<'a, R: RangeBounds<VersionName<'a>>>(
+        &self,
+        branch_name: BranchName,
+        bounds: R,
+    ) -> Result<VersionBundle> {
+        self.inner
+            .export_versions_by_branch(branch_name, bounds)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_from_bundle(
+        &self,
+        branch_name: BranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_from_bundle(branch_name, bundle)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_create_by_base_branch_from_bundle(
+        &self,
+        branch_name: BranchName,
+        base_branch_name: ParentBranchName,
+        bundle: &VersionBundle,
+    ) -> Result<()> {
+        self.inner
+            .branch_create_by_base_branch_from_bundle(
+                branch_name,
+                base_branch_name,
+                bundle,
+            )
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_is_ancestor(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<bool> {
+        self.inner.branch_is_ancestor(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn branch_merge_base(
+        &self,
+        branch_a: BranchName,
+        branch_b: BranchName,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.inner.branch_merge_base(branch_a, branch_b).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_checksum(
+        &self,
+        branch_name: BranchName,
+        version_name: VersionName,
+    ) -> Result<[u8; 32]> {
+        self.inner
+            .version_checksum(branch_name, version_name)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list(&self) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list().c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_list_by_branch(
+        &self,
+        branch_name: BranchName,
+    ) -> Result<Vec<VersionInfo>> {
+        self.inner.version_list_by_branch(branch_name).c(d!())
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta(&self, version_name: VersionName) -> Option<VersionMeta> {
+        self.inner.version_get_meta(version_name)
+    }
+
+    #[inline(always)]
+    pub fn version_get_meta_by_branch(
+        &self,
+        version_name: VersionName,
+        branch_name: BranchName,
+    ) -> Option<VersionMeta> {
+        self.inner
+            .version_get_meta_by_branch(version_name, branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_is_frozen(&self, branch_name: BranchName) -> bool {
+        self.inner.branch_is_frozen(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_depth(&self, branch_name: BranchName) -> Option<usize> {
+        self.inner.branch_depth(branch_name)
+    }
+
+    #[inline(always)]
+    pub fn branch_export_shallow(
+        &self,
+        branch_name: BranchName,
+        target_dir: &str,
+    ) -> Result<()> {
+        self.inner
+            .branch_export_shallow(branch_name, target_dir)
+            .c(d!())
+    }
+
+    #[inline(always)]
+    pub fn import_shallow(src_dir: &str) -> Result<Self> {
+        Ok(Self {
+            inner: MapxOrdRawKeyVs::import_shallow(src_dir).c(d!())?,
+        })
+    }
 }
 
 impl<T: ValueEnDe> VsMgmt for VecxVs<T> {
     crate::impl_vs_methods!();
 }
 
+impl<T: ValueEnDe> VsComplete for VecxVs<T> {}
+
+impl<T: ValueEnDe + VsComplete> VsNested for VecxVs<T> {}
+
+impl<'a, T: ValueEnDe> SnapshotHandle<'a, VecxVs<T>> {
+    #[inline(always)]
+    pub fn get(&self, idx: usize) -> Option<T> {
+        self.inner()
+            .get_by_branch_version(idx, self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn last(&self) -> Option<T> {
+        let len = self.len();
+        alt!(0 == len, return None);
+        self.get(len - 1)
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner()
+            .len_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner()
+            .is_empty_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> VecxVsIter<'a, T> {
+        self.inner()
+            .iter_by_branch_version(self.branch_name(), self.version_name())
+    }
+
+    #[inline(always)]
+    pub fn to_plain(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
 pub struct VecxVsIter<'a, T: ValueEnDe> {
     iter: MapxOrdRawKeyVsIter<'a, T>,
 }